@@ -9,7 +9,7 @@
 use criterion::{BenchmarkId, Criterion, black_box, criterion_group, criterion_main};
 use m3u8_rs::Playlist;
 use ritcher::ad::interleaver;
-use ritcher::ad::provider::AdSegment;
+use ritcher::ad::provider::{AdSegment, break_segment_url};
 use ritcher::hls::cue;
 use ritcher::hls::parser;
 
@@ -134,6 +134,16 @@ fn generate_ad_segments(duration: f32, segment_duration: f32) -> Vec<AdSegment>
         .collect()
 }
 
+/// Stitcher URL of a mock ad segment, as providers build it by default
+fn bench_segment_url(_name: &str, break_idx: usize, segment_idx: usize) -> String {
+    break_segment_url(
+        "http://stitcher.example.com",
+        "bench-session",
+        break_idx,
+        segment_idx,
+    )
+}
+
 // ── Benchmarks ──────────────────────────────────────────────────────
 
 /// Benchmark: Parse HLS media playlist
@@ -206,8 +216,7 @@ fn bench_interleave_ads(c: &mut Criterion) {
                         black_box(media.clone()),
                         black_box(breaks),
                         black_box(ads),
-                        "bench-session",
-                        "http://stitcher.example.com",
+                        bench_segment_url,
                     );
                 });
             },
@@ -372,13 +381,7 @@ fn full_pipeline(playlist_str: &str) -> String {
             .map(|ab| generate_ad_segments(ab.duration, 6.0))
             .collect();
 
-        media = interleaver::interleave_ads(
-            media,
-            &ad_breaks,
-            &ad_segments,
-            "bench-session",
-            "http://stitcher.example.com",
-        );
+        media = interleaver::interleave_ads(media, &ad_breaks, &ad_segments, bench_segment_url);
     }
 
     // Step 4: Rewrite URLs
@@ -0,0 +1,133 @@
+//! Per-session memo of break decisions
+//!
+//! A live media playlist is requested again on every refresh, and once per
+//! rendition, while the breaks in it stay the same. [`BreakDecisions`]
+//! decides each break of a session once and hands every later request the
+//! same segments, so refreshes and renditions stitch the same pod and the
+//! break's ads count once towards the session's frequency cap.
+//!
+//! Breaks are keyed like shared decisions (see
+//! [`crate::ad::shared_decision`]): by the media sequence number of their
+//! first segment in HLS, by their start on the presentation timeline in
+//! DASH. The key also goes with the ad request
+//! ([`AdRequestContext::break_key`](crate::ad::AdRequestContext::break_key)),
+//! so a break decided again once its decision expired, or by another
+//! instance, is not recorded twice in the session's ad history.
+
+use crate::ad::provider::AdSegment;
+use dashmap::DashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::OnceCell;
+use tracing::info;
+
+/// How long a decision is kept; matches the VAST provider's creative cache
+const DECISION_TTL: Duration = Duration::from_secs(300);
+
+/// A decision made for a session's break
+struct Decision {
+    segments: Arc<OnceCell<Vec<AdSegment>>>,
+    created_at: Instant,
+}
+
+/// Store of the break decisions made for each session
+#[derive(Clone, Default)]
+pub struct BreakDecisions {
+    /// Decisions by "session_id:break_key"
+    decisions: Arc<DashMap<String, Decision>>,
+}
+
+impl BreakDecisions {
+    /// Create an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ad segments of a session's break, deciding them with `decide` when
+    /// the break has no decision yet
+    ///
+    /// Concurrent requests for the same break, e.g. of two renditions, wait
+    /// for a single decision.
+    pub async fn segments<F>(&self, session_id: &str, break_key: u64, decide: F) -> Vec<AdSegment>
+    where
+        F: Future<Output = Vec<AdSegment>>,
+    {
+        let cell = self
+            .decisions
+            .entry(format!("{session_id}:{break_key}"))
+            .or_insert_with(|| Decision {
+                segments: Arc::new(OnceCell::new()),
+                created_at: Instant::now(),
+            })
+            .segments
+            .clone();
+        cell.get_or_init(|| decide).await.clone()
+    }
+
+    /// Evict expired decisions
+    pub fn cleanup(&self) {
+        let before = self.decisions.len();
+        self.decisions
+            .retain(|_, decision| decision.created_at.elapsed() < DECISION_TTL);
+
+        let evicted = before - self.decisions.len();
+        if evicted > 0 {
+            info!(
+                "Break decisions: evicted {} expired decision(s) ({} remaining)",
+                evicted,
+                self.decisions.len()
+            );
+        }
+    }
+
+    /// Number of decisions held
+    pub fn len(&self) -> usize {
+        self.decisions.len()
+    }
+
+    /// Whether no decision is held
+    pub fn is_empty(&self) -> bool {
+        self.decisions.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn segment(uri: &str) -> AdSegment {
+        AdSegment {
+            uri: uri.to_string(),
+            duration: 10.0,
+            tracking: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn each_break_of_a_session_is_decided_once() {
+        let decisions = BreakDecisions::new();
+        let calls = AtomicUsize::new(0);
+        let decide = |uri: &'static str| {
+            let calls = &calls;
+            async move {
+                calls.fetch_add(1, Ordering::SeqCst);
+                vec![segment(uri)]
+            }
+        };
+
+        let first = decisions.segments("viewer", 12, decide("a.ts")).await;
+        let again = decisions.segments("viewer", 12, decide("b.ts")).await;
+        assert_eq!(first[0].uri, "a.ts");
+        assert_eq!(again[0].uri, "a.ts");
+
+        // Other breaks and other sessions decide their own
+        let next = decisions.segments("viewer", 20, decide("c.ts")).await;
+        let other = decisions.segments("other", 12, decide("d.ts")).await;
+        assert_eq!(next[0].uri, "c.ts");
+        assert_eq!(other[0].uri, "d.ts");
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert_eq!(decisions.len(), 3);
+    }
+}
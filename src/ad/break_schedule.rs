@@ -0,0 +1,460 @@
+//! Server-side ad break schedule for channels without SCTE-35
+//!
+//! Some linear channels carry no ad signalling at all. For those, breaks
+//! are defined ahead of time per channel, either pushed through the
+//! schedule API (`/schedule/{channel}/breaks`) or loaded from
+//! `SCHEDULE_FILE` at startup. Each break is a wall-clock start and a
+//! duration; the HLS and DASH placement steps match them against
+//! `EXT-X-PROGRAM-DATE-TIME` or the MPD `availabilityStartTime` and merge
+//! them with the detected breaks, so they are stitched as if SCTE-35 had
+//! been present.
+
+use crate::error::{Result, RitcherError};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use tracing::{debug, info};
+
+/// Channel used when a stitch request does not name one
+pub const DEFAULT_CHANNEL: &str = "default";
+
+/// How long a break is kept after its scheduled end, so players behind the
+/// live edge still see it
+const RETENTION_SECS: i64 = 120;
+
+/// Upper bound on the breaks held for one channel
+const MAX_BREAKS_PER_CHANNEL: usize = 1000;
+
+/// Longest accepted break, in seconds
+const MAX_BREAK_DURATION: f64 = 3600.0;
+
+/// Maximum channel name length, matching the session ID limit
+const MAX_CHANNEL_LEN: usize = 128;
+
+/// A break scheduled on a channel's wall clock
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelBreak {
+    /// Caller-supplied or generated identifier, unique per channel
+    pub id: String,
+    /// Wall-clock start of the break
+    pub start: DateTime<Utc>,
+    /// Duration in seconds
+    pub duration: f64,
+}
+
+impl ChannelBreak {
+    /// Wall-clock end of the break
+    pub fn end(&self) -> DateTime<Utc> {
+        self.start + seconds(self.duration)
+    }
+
+    fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.end() + chrono::Duration::seconds(RETENTION_SECS) < now
+    }
+}
+
+/// A break as submitted to the schedule API or listed in a schedule file
+///
+/// The start is either an absolute RFC 3339 time (`start`) or a number of
+/// seconds from now (`start_in`).
+#[derive(Debug, Clone, Deserialize)]
+pub struct BreakDefinition {
+    /// Break identifier; generated from the start time when omitted
+    #[serde(default)]
+    pub id: Option<String>,
+    /// RFC 3339 wall-clock start
+    #[serde(default)]
+    pub start: Option<String>,
+    /// Seconds from now until the break starts
+    #[serde(default)]
+    pub start_in: Option<f64>,
+    /// Break duration in seconds
+    pub duration: f64,
+}
+
+impl BreakDefinition {
+    /// Validate the definition and pin it to the wall clock
+    pub fn resolve(self, now: DateTime<Utc>) -> Result<ChannelBreak> {
+        if !self.duration.is_finite() || self.duration <= 0.0 {
+            return Err(invalid(format!(
+                "duration must be positive, got {}",
+                self.duration
+            )));
+        }
+        if self.duration > MAX_BREAK_DURATION {
+            return Err(invalid(format!(
+                "duration {}s exceeds the {}s limit",
+                self.duration, MAX_BREAK_DURATION
+            )));
+        }
+
+        let start = match (self.start.as_deref(), self.start_in) {
+            (Some(start), None) => DateTime::parse_from_rfc3339(start.trim())
+                .map_err(|e| invalid(format!("start {:?} is not RFC 3339: {}", start, e)))?
+                .with_timezone(&Utc),
+            (None, Some(secs)) if secs.is_finite() && secs >= 0.0 => now + seconds(secs),
+            (None, Some(secs)) => {
+                return Err(invalid(format!("start_in must be >= 0, got {}", secs)));
+            }
+            _ => return Err(invalid("exactly one of start or start_in is required")),
+        };
+
+        let id = match self.id {
+            Some(id) => {
+                validate_name("break id", &id)?;
+                id
+            }
+            None => format!("sched-{}", start.timestamp_millis()),
+        };
+
+        Ok(ChannelBreak {
+            id,
+            start,
+            duration: self.duration,
+        })
+    }
+}
+
+/// Thread-safe store of scheduled breaks keyed by channel
+#[derive(Clone, Debug, Default)]
+pub struct BreakSchedule {
+    channels: Arc<DashMap<String, Vec<ChannelBreak>>>,
+}
+
+impl BreakSchedule {
+    /// Create an empty schedule
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add breaks to a channel, replacing breaks with the same id
+    ///
+    /// Breaks are kept ordered by start time. Fails without changing the
+    /// channel when it would exceed its break limit.
+    pub fn add(&self, channel: &str, breaks: Vec<ChannelBreak>) -> Result<usize> {
+        let mut entry = self.channels.entry(channel.to_string()).or_default();
+        let mut merged = entry.clone();
+        let added = breaks.len();
+        for ad_break in breaks {
+            merged.retain(|b| b.id != ad_break.id);
+            merged.push(ad_break);
+        }
+        if merged.len() > MAX_BREAKS_PER_CHANNEL {
+            return Err(invalid(format!(
+                "channel {} would hold {} breaks, limit is {}",
+                channel,
+                merged.len(),
+                MAX_BREAKS_PER_CHANNEL
+            )));
+        }
+        merged.sort_by_key(|b| b.start);
+        *entry = merged;
+        drop(entry);
+        info!("Scheduled {} break(s) on channel {}", added, channel);
+        Ok(added)
+    }
+
+    /// Unexpired breaks of a channel, ordered by start time
+    pub fn breaks(&self, channel: &str) -> Vec<ChannelBreak> {
+        let now = Utc::now();
+        self.channels
+            .get(channel)
+            .map(|entry| {
+                entry
+                    .iter()
+                    .filter(|b| !b.is_expired(now))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Remove one break; returns whether it existed
+    pub fn remove(&self, channel: &str, id: &str) -> bool {
+        let Some(mut entry) = self.channels.get_mut(channel) else {
+            return false;
+        };
+        let before = entry.len();
+        entry.retain(|b| b.id != id);
+        let removed = entry.len() != before;
+        drop(entry);
+        if removed {
+            info!("Removed scheduled break {} from channel {}", id, channel);
+        }
+        removed
+    }
+
+    /// Drop expired breaks and channels left without any
+    pub fn cleanup(&self) {
+        let now = Utc::now();
+        self.channels.retain(|channel, breaks| {
+            let before = breaks.len();
+            breaks.retain(|b| !b.is_expired(now));
+            if breaks.len() != before {
+                debug!(
+                    "Dropped {} expired break(s) from channel {}",
+                    before - breaks.len(),
+                    channel
+                );
+            }
+            !breaks.is_empty()
+        });
+    }
+
+    /// Number of channels with scheduled breaks
+    pub fn len(&self) -> usize {
+        self.channels.len()
+    }
+
+    /// Return `true` if no channel has scheduled breaks
+    pub fn is_empty(&self) -> bool {
+        self.channels.is_empty()
+    }
+
+    /// Load a schedule file, adding its breaks to the store
+    ///
+    /// Files ending in `.csv` hold `channel,start,duration[,id]` rows (an
+    /// optional header row is skipped); anything else is read as JSON
+    /// mapping channel names to arrays of [`BreakDefinition`]s. Returns the
+    /// number of breaks loaded.
+    pub fn load_file(&self, path: &str) -> Result<usize> {
+        let contents = std::fs::read_to_string(path).map_err(|e| {
+            RitcherError::ConfigError(format!("cannot read schedule file {}: {}", path, e))
+        })?;
+        let is_csv = Path::new(path)
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"));
+        let channels = if is_csv {
+            parse_csv(&contents)?
+        } else {
+            parse_json(&contents)?
+        };
+
+        let now = Utc::now();
+        let mut loaded = 0;
+        for (channel, definitions) in channels {
+            validate_channel(&channel)?;
+            let breaks = definitions
+                .into_iter()
+                .map(|d| d.resolve(now))
+                .collect::<Result<Vec<_>>>()?;
+            loaded += self.add(&channel, breaks)?;
+        }
+        Ok(loaded)
+    }
+}
+
+/// Validate a channel name: 1-128 characters of `a-z A-Z 0-9 - _`
+pub fn validate_channel(channel: &str) -> Result<()> {
+    validate_name("channel", channel)
+}
+
+fn validate_name(what: &str, name: &str) -> Result<()> {
+    if name.is_empty() || name.len() > MAX_CHANNEL_LEN {
+        return Err(invalid(format!(
+            "{} must be 1-{} characters",
+            what, MAX_CHANNEL_LEN
+        )));
+    }
+    if !name
+        .bytes()
+        .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+    {
+        return Err(invalid(format!(
+            "{} contains invalid characters (only a-z, A-Z, 0-9, -, _ allowed)",
+            what
+        )));
+    }
+    Ok(())
+}
+
+fn parse_json(contents: &str) -> Result<HashMap<String, Vec<BreakDefinition>>> {
+    serde_json::from_str(contents)
+        .map_err(|e| RitcherError::ConfigError(format!("invalid JSON schedule file: {}", e)))
+}
+
+fn parse_csv(contents: &str) -> Result<HashMap<String, Vec<BreakDefinition>>> {
+    let mut channels: HashMap<String, Vec<BreakDefinition>> = HashMap::new();
+    for (line_no, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        if line_no == 0 && fields.first() == Some(&"channel") {
+            continue;
+        }
+        let (channel, start, duration, id) = match fields.as_slice() {
+            [channel, start, duration] => (channel, start, duration, None),
+            [channel, start, duration, id] => (channel, start, duration, Some(id.to_string())),
+            _ => {
+                return Err(RitcherError::ConfigError(format!(
+                    "schedule CSV line {}: expected channel,start,duration[,id]",
+                    line_no + 1
+                )));
+            }
+        };
+        let duration = duration.parse().map_err(|_| {
+            RitcherError::ConfigError(format!(
+                "schedule CSV line {}: invalid duration {:?}",
+                line_no + 1,
+                duration
+            ))
+        })?;
+        channels
+            .entry(channel.to_string())
+            .or_default()
+            .push(BreakDefinition {
+                id,
+                start: Some(start.to_string()),
+                start_in: None,
+                duration,
+            });
+    }
+    Ok(channels)
+}
+
+fn invalid(message: impl Into<String>) -> RitcherError {
+    RitcherError::InvalidRequest(message.into())
+}
+
+/// Fractional seconds as a chrono duration, at millisecond precision
+fn seconds(secs: f64) -> chrono::Duration {
+    // Break offsets are bounded, validated seconds; ms values fit in i64
+    #[allow(clippy::cast_possible_truncation)]
+    chrono::Duration::milliseconds((secs * 1000.0).round() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn definition(start: Option<&str>, start_in: Option<f64>, duration: f64) -> BreakDefinition {
+        BreakDefinition {
+            id: None,
+            start: start.map(str::to_string),
+            start_in,
+            duration,
+        }
+    }
+
+    #[test]
+    fn resolve_accepts_wall_clock_or_relative_start() {
+        let now = Utc::now();
+        let absolute = definition(Some("2026-10-18T12:00:00+02:00"), None, 30.0)
+            .resolve(now)
+            .unwrap();
+        assert_eq!(absolute.start.to_rfc3339(), "2026-10-18T10:00:00+00:00");
+        assert_eq!(
+            absolute.id,
+            format!("sched-{}", absolute.start.timestamp_millis())
+        );
+
+        let relative = definition(None, Some(90.0), 15.0).resolve(now).unwrap();
+        assert_eq!(relative.start, now + chrono::Duration::seconds(90));
+        assert_eq!(relative.end(), now + chrono::Duration::seconds(105));
+    }
+
+    #[test]
+    fn resolve_rejects_invalid_definitions() {
+        let now = Utc::now();
+        assert!(definition(None, None, 30.0).resolve(now).is_err());
+        assert!(definition(Some("noon"), None, 30.0).resolve(now).is_err());
+        assert!(definition(None, Some(10.0), 0.0).resolve(now).is_err());
+        assert!(definition(None, Some(-1.0), 30.0).resolve(now).is_err());
+        assert!(definition(None, Some(10.0), 7200.0).resolve(now).is_err());
+        assert!(
+            definition(Some("2026-10-18T12:00:00Z"), Some(1.0), 30.0)
+                .resolve(now)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn add_replaces_by_id_and_orders_by_start() {
+        let schedule = BreakSchedule::new();
+        let now = Utc::now();
+        let at = |id: &str, secs: i64| ChannelBreak {
+            id: id.to_string(),
+            start: now + chrono::Duration::seconds(secs),
+            duration: 30.0,
+        };
+
+        schedule
+            .add("news", vec![at("b", 600), at("a", 300)])
+            .unwrap();
+        schedule.add("news", vec![at("b", 60)]).unwrap();
+
+        let ids: Vec<String> = schedule.breaks("news").into_iter().map(|b| b.id).collect();
+        assert_eq!(ids, vec!["b", "a"]);
+        assert!(schedule.breaks("sports").is_empty());
+
+        assert!(schedule.remove("news", "a"));
+        assert!(!schedule.remove("news", "a"));
+        assert_eq!(schedule.breaks("news").len(), 1);
+    }
+
+    #[test]
+    fn expired_breaks_are_hidden_and_cleaned_up() {
+        let schedule = BreakSchedule::new();
+        let past = ChannelBreak {
+            id: "old".to_string(),
+            start: Utc::now() - chrono::Duration::seconds(RETENTION_SECS + 60),
+            duration: 30.0,
+        };
+        schedule.add("news", vec![past]).unwrap();
+
+        assert!(schedule.breaks("news").is_empty());
+        assert_eq!(schedule.len(), 1);
+        schedule.cleanup();
+        assert!(schedule.is_empty());
+    }
+
+    #[test]
+    fn parse_csv_rows() {
+        let csv = "channel,start,duration,id\n\
+                   news,2026-10-18T10:00:00Z,30,first\n\
+                   # comment\n\
+                   news,2026-10-18T10:30:00Z,60\n\
+                   sports,2026-10-18T11:00:00Z,90\n";
+        let channels = parse_csv(csv).unwrap();
+        assert_eq!(channels["news"].len(), 2);
+        assert_eq!(channels["news"][0].id.as_deref(), Some("first"));
+        assert_eq!(channels["sports"][0].duration, 90.0);
+
+        assert!(parse_csv("news,2026-10-18T10:00:00Z").is_err());
+        assert!(parse_csv("news,2026-10-18T10:00:00Z,long").is_err());
+    }
+
+    #[test]
+    fn load_json_file() {
+        let path =
+            std::env::temp_dir().join(format!("ritcher-schedule-{}.json", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"{"news": [{"id": "top", "start_in": 60, "duration": 30},
+                         {"start": "2099-01-01T00:00:00Z", "duration": 45}]}"#,
+        )
+        .unwrap();
+
+        let schedule = BreakSchedule::new();
+        let loaded = schedule.load_file(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded, 2);
+        let breaks = schedule.breaks("news");
+        assert_eq!(breaks[0].id, "top");
+        assert_eq!(breaks[1].duration, 45.0);
+    }
+
+    #[test]
+    fn validate_channel_names() {
+        assert!(validate_channel("news-1_hd").is_ok());
+        assert!(validate_channel("").is_err());
+        assert!(validate_channel("news/1").is_err());
+        assert!(validate_channel(&"a".repeat(MAX_CHANNEL_LEN + 1)).is_err());
+    }
+}
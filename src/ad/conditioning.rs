@@ -1,5 +1,12 @@
+//! Ad creative conditioning: which creatives the stitcher accepts
+//!
+//! [`CreativePolicy`] picks a creative's media file, or rejects the
+//! creative, while VAST is resolved; [`check_creative`] then logs
+//! compatibility issues of the file that was picked.
+
 use crate::ad::vast::MediaFile;
-use tracing::warn;
+use crate::metrics;
+use tracing::{debug, warn};
 
 /// Known HLS-compatible MIME types for ad creatives
 const HLS_MIME_TYPES: &[&str] = &["application/x-mpegURL", "application/vnd.apple.mpegurl"];
@@ -7,15 +14,263 @@ const HLS_MIME_TYPES: &[&str] = &["application/x-mpegURL", "application/vnd.appl
 /// Known progressive video MIME types
 const PROGRESSIVE_MIME_TYPES: &[&str] = &["video/mp4", "video/webm", "video/3gpp"];
 
+/// Which delivery of a creative the stitcher picks
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DeliveryPreference {
+    /// HLS renditions first, progressive files as a fallback
+    #[default]
+    PreferHls,
+    /// HLS renditions only
+    HlsOnly,
+    /// Progressive files first (e.g. for a transcoder), HLS as a fallback
+    PreferProgressive,
+    /// Progressive files only
+    ProgressiveOnly,
+}
+
+/// Why a creative or media file was rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejection {
+    MimeType,
+    Delivery,
+    Bitrate,
+    Resolution,
+    Duration,
+    DeniedDomain,
+    DeniedAdId,
+    Vpaid,
+}
+
+impl Rejection {
+    /// Metric label of the reason
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::MimeType => "mime_type",
+            Self::Delivery => "delivery",
+            Self::Bitrate => "bitrate",
+            Self::Resolution => "resolution",
+            Self::Duration => "duration",
+            Self::DeniedDomain => "denied_domain",
+            Self::DeniedAdId => "denied_ad_id",
+            Self::Vpaid => "vpaid",
+        }
+    }
+}
+
+/// Rules a creative must meet to be stitched
+///
+/// Media file rules (MIME type, bitrate, resolution, domain, VPAID) are
+/// checked per file, falling back to the creative's next file in
+/// [`DeliveryPreference`] order; ad rules (duration, denied ad IDs and
+/// advertiser domains) reject the whole creative. Bitrate and resolution
+/// bounds only apply to files that declare them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreativePolicy {
+    /// Accepted media file MIME types, compared case-insensitively
+    pub allowed_mime_types: Vec<String>,
+    /// Bitrate bounds in kbps
+    pub min_bitrate: Option<u32>,
+    pub max_bitrate: Option<u32>,
+    /// Resolution bounds as (width, height)
+    pub min_resolution: Option<(u32, u32)>,
+    pub max_resolution: Option<(u32, u32)>,
+    /// Creative duration bounds in seconds
+    pub min_duration: Option<f32>,
+    pub max_duration: Option<f32>,
+    /// Media hosts and advertiser domains never stitched, subdomains
+    /// included
+    pub denied_domains: Vec<String>,
+    /// Ad, creative or Universal Ad IDs never stitched
+    pub denied_ad_ids: Vec<String>,
+    /// Reject VPAID and other script-driven interactive media files
+    pub reject_vpaid: bool,
+    pub delivery: DeliveryPreference,
+}
+
+impl Default for CreativePolicy {
+    fn default() -> Self {
+        Self {
+            allowed_mime_types: HLS_MIME_TYPES
+                .iter()
+                .chain(&["video/mp4"])
+                .map(|t| t.to_string())
+                .collect(),
+            min_bitrate: None,
+            max_bitrate: None,
+            min_resolution: None,
+            max_resolution: None,
+            min_duration: None,
+            max_duration: None,
+            denied_domains: Vec::new(),
+            denied_ad_ids: Vec::new(),
+            reject_vpaid: true,
+            delivery: DeliveryPreference::default(),
+        }
+    }
+}
+
+impl CreativePolicy {
+    /// Check the ad-level rules of a creative
+    ///
+    /// `ad_ids` are the ad, creative and Universal Ad IDs it goes by.
+    pub fn check_ad(
+        &self,
+        duration: f32,
+        ad_ids: &[&str],
+        advertiser: Option<&str>,
+    ) -> Result<(), Rejection> {
+        if self.min_duration.is_some_and(|min| duration < min)
+            || self.max_duration.is_some_and(|max| duration > max)
+        {
+            return Err(Rejection::Duration);
+        }
+        if ad_ids
+            .iter()
+            .any(|id| !id.is_empty() && self.denied_ad_ids.iter().any(|denied| denied == id))
+        {
+            return Err(Rejection::DeniedAdId);
+        }
+        if advertiser.is_some_and(|advertiser| self.is_denied_domain(advertiser)) {
+            return Err(Rejection::DeniedDomain);
+        }
+        Ok(())
+    }
+
+    /// Check the rules of one media file
+    pub fn check_media_file(&self, media_file: &MediaFile) -> Result<(), Rejection> {
+        if self.reject_vpaid && is_interactive(media_file) {
+            return Err(Rejection::Vpaid);
+        }
+        if !self
+            .allowed_mime_types
+            .iter()
+            .any(|t| t.eq_ignore_ascii_case(&media_file.mime_type))
+        {
+            return Err(Rejection::MimeType);
+        }
+        let hls = is_hls_mime(&media_file.mime_type);
+        if (hls && self.delivery == DeliveryPreference::ProgressiveOnly)
+            || (!hls && self.delivery == DeliveryPreference::HlsOnly)
+        {
+            return Err(Rejection::Delivery);
+        }
+        if let Some(bitrate) = media_file.bitrate
+            && (self.min_bitrate.is_some_and(|min| bitrate < min)
+                || self.max_bitrate.is_some_and(|max| bitrate > max))
+        {
+            return Err(Rejection::Bitrate);
+        }
+        let (width, height) = (media_file.width, media_file.height);
+        if width > 0
+            && height > 0
+            && (self
+                .min_resolution
+                .is_some_and(|(w, h)| width < w || height < h)
+                || self
+                    .max_resolution
+                    .is_some_and(|(w, h)| width > w || height > h))
+        {
+            return Err(Rejection::Resolution);
+        }
+        let host = url::Url::parse(&media_file.url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string));
+        if host.is_some_and(|host| self.is_denied_domain(&host)) {
+            return Err(Rejection::DeniedDomain);
+        }
+        Ok(())
+    }
+
+    /// The first media file meeting the policy, in delivery preference order
+    ///
+    /// Every rejected file is counted in `ritcher_creative_rejections_total`.
+    pub fn select_media_file<'a>(
+        &self,
+        media_files: &'a [MediaFile],
+        session_id: &str,
+    ) -> Option<&'a MediaFile> {
+        self.rank(media_files).into_iter().find(|media_file| {
+            match self.check_media_file(media_file) {
+                Ok(()) => true,
+                Err(rejection) => {
+                    debug!(
+                        session_id = session_id,
+                        url = media_file.url,
+                        "Creative policy: media file rejected ({})",
+                        rejection.as_str()
+                    );
+                    metrics::record_creative_rejection(rejection.as_str());
+                    false
+                }
+            }
+        })
+    }
+
+    /// Every media file meeting the policy, in delivery preference order
+    pub fn accepted_media_files<'a>(&self, media_files: &'a [MediaFile]) -> Vec<&'a MediaFile> {
+        self.rank(media_files)
+            .into_iter()
+            .filter(|media_file| self.check_media_file(media_file).is_ok())
+            .collect()
+    }
+
+    /// Media files in preference order: by delivery, then by bitrate,
+    /// highest first, keeping document order among equals
+    fn rank<'a>(&self, media_files: &'a [MediaFile]) -> Vec<&'a MediaFile> {
+        let hls_first = matches!(
+            self.delivery,
+            DeliveryPreference::PreferHls | DeliveryPreference::HlsOnly
+        );
+        let mut ranked: Vec<&MediaFile> = media_files.iter().collect();
+        ranked.sort_by_key(|f| {
+            (
+                is_hls_mime(&f.mime_type) != hls_first,
+                std::cmp::Reverse(f.bitrate),
+            )
+        });
+        ranked
+    }
+
+    fn is_denied_domain(&self, host: &str) -> bool {
+        let host = host.trim().trim_end_matches('.').to_ascii_lowercase();
+        self.denied_domains.iter().any(|domain| {
+            let domain = domain.trim().trim_start_matches("*.").to_ascii_lowercase();
+            host == domain || host.ends_with(&format!(".{}", domain))
+        })
+    }
+}
+
+/// Parse a `WIDTHxHEIGHT` resolution such as `1280x720`
+pub fn parse_resolution(value: &str) -> Option<(u32, u32)> {
+    let (width, height) = value.trim().split_once(['x', 'X'])?;
+    Some((width.trim().parse().ok()?, height.trim().parse().ok()?))
+}
+
+/// Whether a media file needs a script runtime (VPAID, Flash, JavaScript)
+fn is_interactive(media_file: &MediaFile) -> bool {
+    let mime = media_file.mime_type.to_ascii_lowercase();
+    media_file
+        .api_framework
+        .as_deref()
+        .is_some_and(|api| api.to_ascii_lowercase().contains("vpaid"))
+        || media_file
+            .codec
+            .as_deref()
+            .is_some_and(|codec| codec.to_ascii_lowercase().contains("vpaid"))
+        || mime.contains("javascript")
+        || mime.contains("shockwave")
+}
+
 /// Validate ad creative compatibility and log warnings
 ///
-/// Phase 1: warning-only. Does not block ad insertion.
-/// Checks for common issues that may cause playback problems:
+/// Warning-only: creatives are accepted or rejected by [`CreativePolicy`]
+/// before this runs. Checks for common issues that may cause playback problems:
 /// - Non-HLS ad creative in HLS stream (codec mismatch)
 /// - Resolution mismatches (if detectable)
 /// - Missing or unknown MIME types
 ///
-/// Future: integrate with Eyevinn Ad Normalizer for transcoding
+/// Such creatives can be transcoded by a normalizer (see
+/// [`crate::ad::normalizer`]).
 pub fn check_creative(media_file: &MediaFile, session_id: &str) {
     let mime = &media_file.mime_type;
 
@@ -28,7 +283,7 @@ pub fn check_creative(media_file: &MediaFile, session_id: &str) {
                 url = media_file.url,
                 "Ad conditioning: Progressive MP4 creative detected — \
                  may cause playback issues in HLS stream. \
-                 Consider using Eyevinn Ad Normalizer for transcoding."
+                 Set AD_NORMALIZER_URL to have it transcoded."
             );
         } else {
             warn!(
@@ -78,8 +333,8 @@ pub fn check_creative(media_file: &MediaFile, session_id: &str) {
                 session_id = session_id,
                 codec = codec,
                 url = media_file.url,
-                "Ad conditioning: VPAID creative detected — \
-                 not supported in SSAI mode. Creative will be skipped."
+                "Ad conditioning: VPAID creative allowed by the creative policy — \
+                 its interactive layer is not supported in SSAI mode."
             );
         }
     }
@@ -98,7 +353,7 @@ pub fn check_creatives(media_files: &[&MediaFile], session_id: &str) -> usize {
     warning_count
 }
 
-fn is_hls_mime(mime: &str) -> bool {
+pub(crate) fn is_hls_mime(mime: &str) -> bool {
     HLS_MIME_TYPES.iter().any(|&t| t.eq_ignore_ascii_case(mime))
 }
 
@@ -121,9 +376,142 @@ mod tests {
             height,
             bitrate: Some(2000),
             codec: None,
+            api_framework: None,
         }
     }
 
+    fn media_file(url: &str, mime_type: &str, bitrate: Option<u32>) -> MediaFile {
+        MediaFile {
+            url: url.to_string(),
+            bitrate,
+            ..create_media_file(mime_type, 1280, 720)
+        }
+    }
+
+    #[test]
+    fn policy_falls_back_to_the_next_acceptable_media_file() {
+        let policy = CreativePolicy {
+            max_bitrate: Some(4000),
+            denied_domains: vec!["blocked.example".to_string()],
+            ..Default::default()
+        };
+        let files = vec![
+            MediaFile {
+                api_framework: Some("VPAID".to_string()),
+                ..media_file(
+                    "https://ads.example/vpaid.js",
+                    "application/javascript",
+                    None,
+                )
+            },
+            media_file(
+                "https://cdn.blocked.example/ad.m3u8",
+                "application/x-mpegURL",
+                None,
+            ),
+            media_file("https://ads.example/high.mp4", "video/mp4", Some(8000)),
+            media_file("https://ads.example/low.mp4", "video/mp4", Some(1000)),
+            media_file("https://ads.example/mid.mp4", "video/mp4", Some(2000)),
+        ];
+
+        let selected = policy.select_media_file(&files, "s").unwrap();
+        assert_eq!(selected.url, "https://ads.example/mid.mp4");
+        assert_eq!(
+            policy.check_media_file(&files[0]),
+            Err(Rejection::Vpaid),
+            "VPAID is rejected before its MIME type"
+        );
+    }
+
+    #[test]
+    fn policy_delivery_preference_orders_and_filters() {
+        let files = vec![
+            media_file("https://ads.example/ad.mp4", "video/mp4", Some(2000)),
+            media_file("https://ads.example/ad.m3u8", "application/x-mpegURL", None),
+        ];
+        let pick = |delivery| {
+            CreativePolicy {
+                delivery,
+                ..Default::default()
+            }
+            .select_media_file(&files, "s")
+            .map(|f| f.url.clone())
+        };
+
+        assert_eq!(
+            pick(DeliveryPreference::PreferHls).as_deref(),
+            Some("https://ads.example/ad.m3u8")
+        );
+        assert_eq!(
+            pick(DeliveryPreference::PreferProgressive).as_deref(),
+            Some("https://ads.example/ad.mp4")
+        );
+        assert_eq!(
+            pick(DeliveryPreference::HlsOnly).as_deref(),
+            Some("https://ads.example/ad.m3u8")
+        );
+        assert!(
+            CreativePolicy {
+                delivery: DeliveryPreference::HlsOnly,
+                ..Default::default()
+            }
+            .select_media_file(&files[..1], "s")
+            .is_none()
+        );
+    }
+
+    #[test]
+    fn policy_resolution_bounds_skip_undeclared_sizes() {
+        let policy = CreativePolicy {
+            min_resolution: Some((1280, 720)),
+            ..Default::default()
+        };
+        assert_eq!(
+            policy.check_media_file(&create_media_file("video/mp4", 640, 360)),
+            Err(Rejection::Resolution)
+        );
+        assert!(
+            policy
+                .check_media_file(&create_media_file("video/mp4", 0, 0))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn policy_checks_duration_ids_and_advertiser() {
+        let policy = CreativePolicy {
+            min_duration: Some(5.0),
+            max_duration: Some(30.0),
+            denied_ad_ids: vec!["CNPA0484000H".to_string()],
+            denied_domains: vec!["*.rival.example".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(policy.check_ad(15.0, &["ad-1", ""], None), Ok(()));
+        assert_eq!(
+            policy.check_ad(60.0, &["ad-1"], None),
+            Err(Rejection::Duration)
+        );
+        assert_eq!(
+            policy.check_ad(15.0, &["ad-1", "CNPA0484000H"], None),
+            Err(Rejection::DeniedAdId)
+        );
+        assert_eq!(
+            policy.check_ad(15.0, &["ad-1"], Some("shop.rival.example")),
+            Err(Rejection::DeniedDomain)
+        );
+        assert_eq!(
+            policy.check_ad(15.0, &["ad-1"], Some("notrival.example")),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_parse_resolution() {
+        assert_eq!(parse_resolution("1920x1080"), Some((1920, 1080)));
+        assert_eq!(parse_resolution(" 640 X 360 "), Some((640, 360)));
+        assert_eq!(parse_resolution("1080p"), None);
+    }
+
     #[test]
     fn test_hls_mime_detection() {
         assert!(is_hls_mime("application/x-mpegURL"));
@@ -0,0 +1,505 @@
+//! Per-viewer ad request context
+//!
+//! An [`AdRequestContext`] is captured from the request that starts a
+//! session (the player's IP and user agent, consent signals, content
+//! metadata and allowlisted targeting parameters), persisted with the
+//! session and handed to every ad provider call, so each viewer's ad
+//! request carries their own targeting.
+//!
+//! The viewer's IP, user agent and referer are read from the `X-Device-*`
+//! headers a player-side proxy may set, falling back to `X-Forwarded-For`
+//! (or `X-Real-IP`), `User-Agent` and `Referer`. Server-side requests made
+//! on the viewer's behalf forward them as [`viewer_headers`].
+//!
+//! [`viewer_headers`]: AdRequestContext::viewer_headers
+//!
+//! Recognised query parameters:
+//!
+//! - allowlisted targeting parameters (`AD_PARAM_ALLOWLIST`, default `ad.*`)
+//! - `gdpr` (`0`/`1`), `gdpr_consent` (TCF string), `us_privacy` (CCPA string)
+//! - `lat` (`1` limits ad tracking, as do the `DNT: 1` and `Sec-GPC: 1` headers)
+//! - `content_id`, `content_title`, `content_genre`, `content_series`
+
+use crate::ad::macros::MacroContext;
+use axum::http::{HeaderMap, HeaderName, HeaderValue, header};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// Maximum number of targeting parameters kept per session
+const MAX_PARAMS: usize = 32;
+
+/// Maximum length of a stored parameter or header value
+const MAX_VALUE_LEN: usize = 512;
+
+/// Viewer IP header of SSAI ad requests
+const X_DEVICE_IP: HeaderName = HeaderName::from_static("x-device-ip");
+/// Viewer user agent header of SSAI ad requests
+const X_DEVICE_USER_AGENT: HeaderName = HeaderName::from_static("x-device-user-agent");
+/// Viewer page or app referer header of SSAI ad requests
+const X_DEVICE_REFERER: HeaderName = HeaderName::from_static("x-device-referer");
+/// Proxy chain header, starting with the viewer's IP
+const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+
+/// Coarse device class derived from the user agent
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceType {
+    Desktop,
+    Mobile,
+    Tablet,
+    /// Connected TV, set-top box or streaming stick
+    Ctv,
+    #[default]
+    Unknown,
+}
+
+impl DeviceType {
+    /// Classify a user agent
+    pub fn from_user_agent(user_agent: &str) -> Self {
+        let ua = user_agent.to_ascii_lowercase();
+        let has = |needles: &[&str]| needles.iter().any(|n| ua.contains(n));
+
+        if has(&[
+            "smart-tv",
+            "smarttv",
+            "appletv",
+            "apple tv",
+            "tvos",
+            "roku",
+            "; aft", // Amazon Fire TV models (AFTS, AFTMM, ...)
+            "crkey",
+            "googletv",
+            "android tv",
+            "hbbtv",
+            "tizen",
+            "webos",
+            "playstation",
+            "xbox",
+        ]) {
+            Self::Ctv
+        } else if has(&["ipad", "tablet"]) || (ua.contains("android") && !ua.contains("mobile")) {
+            Self::Tablet
+        } else if has(&["iphone", "ipod", "mobile", "android"]) {
+            Self::Mobile
+        } else if has(&["windows", "macintosh", "mac os x", "x11", "linux", "cros"]) {
+            Self::Desktop
+        } else {
+            Self::Unknown
+        }
+    }
+
+    /// Lowercase name, as serialized
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Desktop => "desktop",
+            Self::Mobile => "mobile",
+            Self::Tablet => "tablet",
+            Self::Ctv => "ctv",
+            Self::Unknown => "unknown",
+        }
+    }
+}
+
+/// Metadata of the content the ads play in
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ContentMetadata {
+    pub id: Option<String>,
+    pub title: Option<String>,
+    pub genre: Option<String>,
+    pub series: Option<String>,
+}
+
+/// Viewer context sent along with every ad request of a session
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AdRequestContext {
+    /// Viewer's IP address
+    pub client_ip: Option<String>,
+    /// Viewer's user agent
+    pub user_agent: Option<String>,
+    /// Page or app the viewer watches in
+    pub referer: Option<String>,
+    /// Device class derived from the user agent
+    pub device_type: DeviceType,
+    /// Allowlisted targeting parameters, keyed by query parameter name
+    pub params: BTreeMap<String, String>,
+    /// Whether GDPR applies (`gdpr`)
+    pub gdpr: Option<bool>,
+    /// IAB TCF consent string (`gdpr_consent`)
+    pub gdpr_consent: Option<String>,
+    /// IAB CCPA string (`us_privacy`)
+    pub us_privacy: Option<String>,
+    /// Viewer opted out of tracking
+    pub limit_ad_tracking: bool,
+    /// Content metadata
+    pub content: ContentMetadata,
+    /// SCTE-35 segmentation UPID of the break being filled
+    ///
+    /// Set per ad request from the break's cue, never stored with the
+    /// session.
+    #[serde(skip)]
+    pub upid: Option<String>,
+    /// Key of the break being filled within the session (see
+    /// [`crate::ad::break_decision`]), so its ads are recorded in the
+    /// session's ad history once
+    ///
+    /// Set per ad request, never stored with the session.
+    #[serde(skip)]
+    pub break_key: Option<u64>,
+}
+
+impl AdRequestContext {
+    /// Capture the context of a player request
+    ///
+    /// `allowlist` holds the query parameter names forwarded for targeting;
+    /// a trailing `*` matches any suffix (`ad.*`).
+    pub fn from_request(
+        headers: &HeaderMap,
+        params: &HashMap<String, String>,
+        allowlist: &[String],
+    ) -> Self {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+        };
+        let param = |name: &str| params.get(name).and_then(|v| bounded(v));
+
+        let client_ip = header(X_DEVICE_IP.as_str())
+            .or_else(|| header(X_FORWARDED_FOR.as_str()).and_then(|v| v.split(',').next()))
+            .or_else(|| header("x-real-ip"))
+            .and_then(bounded);
+        let user_agent = header(X_DEVICE_USER_AGENT.as_str())
+            .or_else(|| header(header::USER_AGENT.as_str()))
+            .and_then(bounded);
+        let referer = header(X_DEVICE_REFERER.as_str())
+            .or_else(|| header(header::REFERER.as_str()))
+            .and_then(bounded);
+        let device_type = user_agent
+            .as_deref()
+            .map(DeviceType::from_user_agent)
+            .unwrap_or_default();
+
+        let mut targeting: Vec<(&String, &String)> = params
+            .iter()
+            .filter(|(name, _)| is_allowed(name, allowlist))
+            .collect();
+        // Deterministic pick when a request exceeds the cap
+        targeting.sort();
+        let params_kept: BTreeMap<String, String> = targeting
+            .into_iter()
+            .filter(|(name, value)| name.len() <= MAX_VALUE_LEN && value.len() <= MAX_VALUE_LEN)
+            .take(MAX_PARAMS)
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+
+        let opted_out = |v: Option<&str>| v.is_some_and(|v| v == "1");
+
+        Self {
+            client_ip,
+            user_agent,
+            referer,
+            device_type,
+            params: params_kept,
+            gdpr: param("gdpr").and_then(|v| match v.as_str() {
+                "1" | "true" => Some(true),
+                "0" | "false" => Some(false),
+                _ => None,
+            }),
+            gdpr_consent: param("gdpr_consent"),
+            us_privacy: param("us_privacy"),
+            limit_ad_tracking: opted_out(header("dnt"))
+                || opted_out(header("sec-gpc"))
+                || opted_out(params.get("lat").map(String::as_str)),
+            content: ContentMetadata {
+                id: param("content_id"),
+                title: param("content_title"),
+                genre: param("content_genre"),
+                series: param("content_series"),
+            },
+            upid: None,
+            break_key: None,
+        }
+    }
+
+    /// Take the viewer's IP, user agent and referer from `other` where this
+    /// context has none
+    ///
+    /// Sessions started by a request without them (e.g. from a CDN that
+    /// strips headers) pick them up from later requests.
+    pub fn fill_viewer(&mut self, other: &AdRequestContext) {
+        if self.client_ip.is_none() {
+            self.client_ip.clone_from(&other.client_ip);
+        }
+        if self.user_agent.is_none() {
+            self.user_agent.clone_from(&other.user_agent);
+            self.device_type = other.device_type;
+        }
+        if self.referer.is_none() {
+            self.referer.clone_from(&other.referer);
+        }
+    }
+
+    /// Headers identifying the viewer on server-side ad requests and beacons
+    ///
+    /// Sets `X-Device-IP` and `X-Forwarded-For` to the viewer's IP,
+    /// `X-Device-User-Agent` to their user agent and `X-Device-Referer` to
+    /// the referer; unknown values are left out.
+    pub fn viewer_headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        let mut set = |name: HeaderName, value: &Option<String>| {
+            if let Some(value) = value.as_deref().and_then(|v| HeaderValue::from_str(v).ok()) {
+                headers.insert(name, value);
+            }
+        };
+        set(X_DEVICE_IP, &self.client_ip);
+        set(X_FORWARDED_FOR, &self.client_ip);
+        set(X_DEVICE_USER_AGENT, &self.user_agent);
+        set(X_DEVICE_REFERER, &self.referer);
+        headers
+    }
+
+    /// The context of a request for the break identified by `upid`
+    pub fn with_upid(mut self, upid: Option<String>) -> Self {
+        self.upid = upid;
+        self
+    }
+
+    /// The context of a request for the break keyed `break_key` within the
+    /// session
+    pub fn with_break_key(mut self, break_key: u64) -> Self {
+        self.break_key = Some(break_key);
+        self
+    }
+
+    /// Macro values known from the viewer context
+    pub fn macro_context(&self) -> MacroContext {
+        MacroContext {
+            device_ip: self.client_ip.clone(),
+            device_ua: self.user_agent.clone(),
+            gdpr_consent: self.gdpr_consent.clone(),
+            limit_ad_tracking: Some(self.limit_ad_tracking),
+            upid: self.upid.clone(),
+            ..MacroContext::default()
+        }
+    }
+}
+
+/// Whether query parameter `name` matches an allowlist entry
+pub fn is_allowed(name: &str, allowlist: &[String]) -> bool {
+    allowlist
+        .iter()
+        .any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => name.len() > prefix.len() && name.starts_with(prefix),
+            None => name == pattern,
+        })
+}
+
+/// `value` as an owned string, unless it is empty or too long to store
+fn bounded(value: &str) -> Option<String> {
+    let value = value.trim();
+    (!value.is_empty() && value.len() <= MAX_VALUE_LEN).then(|| value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn params(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn captures_headers_params_and_consent() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "x-forwarded-for",
+            HeaderValue::from_static("198.51.100.4, 10.0.0.1"),
+        );
+        headers.insert(
+            header::USER_AGENT,
+            HeaderValue::from_static("Mozilla/5.0 (iPhone; CPU iPhone OS 17_0 like Mac OS X)"),
+        );
+
+        let ctx = AdRequestContext::from_request(
+            &headers,
+            &params(&[
+                ("ad.genre", "sports"),
+                ("ad.tier", "premium"),
+                ("origin", "https://cdn.example.com/live.m3u8"),
+                ("gdpr", "1"),
+                (
+                    "gdpr_consent",
+                    "CPXxRfAPXxRfAAfKABENB-CgAAAAAAAAAAYgAAAAAAAA",
+                ),
+                ("content_id", "ep-42"),
+                ("lat", "1"),
+            ]),
+            &["ad.*".to_string()],
+        );
+
+        assert_eq!(ctx.client_ip.as_deref(), Some("198.51.100.4"));
+        assert_eq!(ctx.device_type, DeviceType::Mobile);
+        assert_eq!(
+            ctx.params.keys().collect::<Vec<_>>(),
+            vec!["ad.genre", "ad.tier"]
+        );
+        assert_eq!(ctx.gdpr, Some(true));
+        assert!(ctx.gdpr_consent.is_some());
+        assert_eq!(ctx.content.id.as_deref(), Some("ep-42"));
+        assert!(ctx.limit_ad_tracking);
+    }
+
+    #[test]
+    fn device_headers_take_precedence() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-device-ip", HeaderValue::from_static("203.0.113.9"));
+        headers.insert("x-forwarded-for", HeaderValue::from_static("10.0.0.1"));
+        headers.insert(
+            "x-device-user-agent",
+            HeaderValue::from_static("Roku/DVP-12.0"),
+        );
+        headers.insert(header::USER_AGENT, HeaderValue::from_static("proxy/1.0"));
+        headers.insert(
+            header::REFERER,
+            HeaderValue::from_static("https://tv.example.com/"),
+        );
+
+        let ctx = AdRequestContext::from_request(&headers, &HashMap::new(), &[]);
+        assert_eq!(ctx.client_ip.as_deref(), Some("203.0.113.9"));
+        assert_eq!(ctx.user_agent.as_deref(), Some("Roku/DVP-12.0"));
+        assert_eq!(ctx.device_type, DeviceType::Ctv);
+        assert_eq!(ctx.referer.as_deref(), Some("https://tv.example.com/"));
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-real-ip", HeaderValue::from_static("192.0.2.7"));
+        let ctx = AdRequestContext::from_request(&headers, &HashMap::new(), &[]);
+        assert_eq!(ctx.client_ip.as_deref(), Some("192.0.2.7"));
+    }
+
+    #[test]
+    fn viewer_headers_carry_known_values() {
+        let ctx = AdRequestContext {
+            client_ip: Some("198.51.100.4".to_string()),
+            user_agent: Some("Roku/DVP-12.0".to_string()),
+            referer: Some("bad\nvalue".to_string()),
+            ..Default::default()
+        };
+        let headers = ctx.viewer_headers();
+        assert_eq!(headers["x-device-ip"], "198.51.100.4");
+        assert_eq!(headers["x-forwarded-for"], "198.51.100.4");
+        assert_eq!(headers["x-device-user-agent"], "Roku/DVP-12.0");
+        assert!(!headers.contains_key("x-device-referer"));
+
+        assert!(AdRequestContext::default().viewer_headers().is_empty());
+    }
+
+    #[test]
+    fn fill_viewer_keeps_captured_values() {
+        let mut ctx = AdRequestContext {
+            client_ip: Some("198.51.100.4".to_string()),
+            ..Default::default()
+        };
+        ctx.fill_viewer(&AdRequestContext {
+            client_ip: Some("10.0.0.1".to_string()),
+            user_agent: Some("Roku/DVP-12.0".to_string()),
+            device_type: DeviceType::Ctv,
+            ..Default::default()
+        });
+        assert_eq!(ctx.client_ip.as_deref(), Some("198.51.100.4"));
+        assert_eq!(ctx.user_agent.as_deref(), Some("Roku/DVP-12.0"));
+        assert_eq!(ctx.device_type, DeviceType::Ctv);
+    }
+
+    #[test]
+    fn empty_request_gives_default_context() {
+        let ctx = AdRequestContext::from_request(&HeaderMap::new(), &HashMap::new(), &[]);
+        assert_eq!(ctx, AdRequestContext::default());
+    }
+
+    #[test]
+    fn targeting_params_are_capped() {
+        let many: Vec<(String, String)> = (0..50)
+            .map(|i| (format!("ad.k{i:02}"), "v".to_string()))
+            .collect();
+        let map: HashMap<String, String> = many.into_iter().collect();
+        let ctx = AdRequestContext::from_request(&HeaderMap::new(), &map, &["ad.*".to_string()]);
+        assert_eq!(ctx.params.len(), MAX_PARAMS);
+        assert!(ctx.params.contains_key("ad.k00"));
+    }
+
+    #[test]
+    fn allowlist_matches_exact_names_and_prefixes() {
+        let allowlist = vec!["ad.*".to_string(), "ppid".to_string()];
+        assert!(is_allowed("ad.genre", &allowlist));
+        assert!(is_allowed("ppid", &allowlist));
+        assert!(!is_allowed("ad.", &allowlist));
+        assert!(!is_allowed("ppid2", &allowlist));
+        assert!(!is_allowed("origin", &allowlist));
+    }
+
+    #[test]
+    fn device_type_from_user_agent() {
+        let cases = [
+            ("Roku/DVP-12.0 (12.0.0.4182)", DeviceType::Ctv),
+            (
+                "AppleCoreMedia/1.0.0.21J354 (Apple TV; U; CPU OS 17_0 like Mac OS X)",
+                DeviceType::Ctv,
+            ),
+            (
+                "Mozilla/5.0 (iPad; CPU OS 17_0 like Mac OS X)",
+                DeviceType::Tablet,
+            ),
+            (
+                "Mozilla/5.0 (Linux; Android 14; SM-X710)",
+                DeviceType::Tablet,
+            ),
+            (
+                "Mozilla/5.0 (Linux; Android 14; Pixel 8) Mobile Safari/537.36",
+                DeviceType::Mobile,
+            ),
+            (
+                "Mozilla/5.0 (Windows NT 10.0; Win64; x64)",
+                DeviceType::Desktop,
+            ),
+            ("curl/8.5.0", DeviceType::Unknown),
+        ];
+        for (ua, expected) in cases {
+            assert_eq!(DeviceType::from_user_agent(ua), expected, "{ua}");
+        }
+    }
+
+    #[test]
+    fn macro_context_carries_viewer_values() {
+        let ctx = AdRequestContext {
+            client_ip: Some("198.51.100.4".to_string()),
+            gdpr_consent: Some("consent".to_string()),
+            ..Default::default()
+        }
+        .with_upid(Some("BREAK-7".to_string()))
+        .macro_context();
+        assert_eq!(ctx.device_ip.as_deref(), Some("198.51.100.4"));
+        assert_eq!(ctx.gdpr_consent.as_deref(), Some("consent"));
+        assert_eq!(ctx.limit_ad_tracking, Some(false));
+        assert_eq!(ctx.upid.as_deref(), Some("BREAK-7"));
+    }
+
+    #[test]
+    fn session_json_without_context_fields_deserializes() {
+        let ctx: AdRequestContext = serde_json::from_str("{}").unwrap();
+        assert_eq!(ctx, AdRequestContext::default());
+    }
+
+    #[test]
+    fn upid_is_not_stored_with_the_session() {
+        let ctx = AdRequestContext::default().with_upid(Some("BREAK-7".to_string()));
+        let json = serde_json::to_string(&ctx).unwrap();
+        assert!(!json.contains("BREAK-7"), "{json}");
+    }
+}
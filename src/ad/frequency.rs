@@ -0,0 +1,257 @@
+//! Per-session frequency capping and competitive separation
+//!
+//! A viewer should not see the same creative break after break, nor two
+//! competing advertisers back to back. [`AdHistory`] records the ads placed
+//! in a session's breaks and is kept with the session in its store (see
+//! [`crate::session::SessionManager::record_ads`]). Before a pod is fitted
+//! to a break, [`FrequencyPolicy::select`] leaves out:
+//!
+//! - creatives that already played `cap` times in the session
+//! - creatives of an advertiser, or sharing a category, with another ad of
+//!   the same pod or of the `separation_breaks` breaks before it
+//!
+//! Ads are identified by their VAST `<UniversalAdId>`, falling back to the
+//! creative ID and then the media URL; competitors by `<Advertiser>` and
+//! `<Category>`. Ads without an advertiser or categories never compete.
+
+use crate::metrics;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+/// Ads kept per session; older ones are forgotten
+pub(crate) const MAX_HISTORY: usize = 100;
+
+/// What capping and separation know about an ad
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AdIdentity {
+    /// Universal Ad ID (`registry:value`), creative ID or media URL
+    pub ad_id: String,
+    /// Advertiser name or domain
+    pub advertiser: Option<String>,
+    /// Content categories, e.g. IAB codes
+    pub categories: Vec<String>,
+}
+
+impl AdIdentity {
+    /// Whether an ad of `advertiser` and `categories` competes with this one
+    fn competes_with(&self, advertiser: Option<&str>, categories: &[String]) -> bool {
+        let same_advertiser = match (&self.advertiser, advertiser) {
+            (Some(a), Some(b)) => a.trim().eq_ignore_ascii_case(b.trim()),
+            _ => false,
+        };
+        same_advertiser
+            || self
+                .categories
+                .iter()
+                .any(|c| categories.iter().any(|o| c.eq_ignore_ascii_case(o)))
+    }
+}
+
+/// An ad placed in one of a session's breaks
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeenAd {
+    pub ad_id: String,
+    pub advertiser: Option<String>,
+    pub categories: Vec<String>,
+    /// Break the ad played in, counted from the session's first
+    pub break_index: u32,
+}
+
+/// Ads placed in a session's breaks, most recent last
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AdHistory {
+    /// Breaks recorded so far
+    pub breaks: u32,
+    pub ads: Vec<SeenAd>,
+    /// Keys of the recent breaks recorded with one
+    break_keys: Vec<u64>,
+}
+
+impl AdHistory {
+    /// Times an ad was placed in the session
+    pub fn impressions(&self, ad_id: &str) -> usize {
+        self.ads.iter().filter(|seen| seen.ad_id == ad_id).count()
+    }
+
+    /// Record the ads placed in the session's next break
+    ///
+    /// A break keyed `break_key` is recorded once: its ads are ignored when
+    /// the break is already in the history, e.g. decided again by another
+    /// instance. Returns whether the break was recorded.
+    pub fn record(&mut self, break_key: Option<u64>, ads: &[AdIdentity]) -> bool {
+        if let Some(break_key) = break_key {
+            if self.break_keys.contains(&break_key) {
+                return false;
+            }
+            self.break_keys.push(break_key);
+            if self.break_keys.len() > MAX_HISTORY {
+                self.break_keys.remove(0);
+            }
+        }
+        let break_index = self.breaks;
+        self.ads.extend(ads.iter().map(|ad| SeenAd {
+            ad_id: ad.ad_id.clone(),
+            advertiser: ad.advertiser.clone(),
+            categories: ad.categories.clone(),
+            break_index,
+        }));
+        self.breaks += 1;
+        if self.ads.len() > MAX_HISTORY {
+            self.ads.drain(..self.ads.len() - MAX_HISTORY);
+        }
+        true
+    }
+
+    /// Ads of the last `breaks` breaks
+    fn recent(&self, breaks: u32) -> impl Iterator<Item = &SeenAd> {
+        self.ads
+            .iter()
+            .filter(move |seen| seen.break_index.saturating_add(breaks) >= self.breaks)
+    }
+}
+
+/// Frequency cap and competitive separation of a session's ads
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrequencyPolicy {
+    /// Times a creative may play per session, 0 = uncapped
+    pub cap: u32,
+    /// Keep competing ads out of the same pod and this many breaks before
+    /// it; `None` = no separation
+    pub separation_breaks: Option<u32>,
+}
+
+impl FrequencyPolicy {
+    /// Whether the policy leaves any ad out
+    pub fn is_enabled(&self) -> bool {
+        self.cap > 0 || self.separation_breaks.is_some()
+    }
+
+    /// Indices of the ads that may play in the session's next break
+    ///
+    /// Ads are considered in ad server order; an ad competing with an
+    /// earlier one of the pod loses to it.
+    pub fn select(&self, ads: &[&AdIdentity], history: &AdHistory) -> Vec<usize> {
+        let mut selected: Vec<usize> = Vec::new();
+        for (index, ad) in ads.iter().enumerate() {
+            if self.cap > 0 {
+                let in_pod = selected
+                    .iter()
+                    .filter(|&&i| ads[i].ad_id == ad.ad_id)
+                    .count();
+                if history.impressions(&ad.ad_id) + in_pod >= self.cap as usize {
+                    debug!("Ad {} reached its frequency cap of {}", ad.ad_id, self.cap);
+                    metrics::record_frequency_rejection("cap");
+                    continue;
+                }
+            }
+            if let Some(breaks) = self.separation_breaks {
+                let in_pod = selected
+                    .iter()
+                    .any(|&i| ad.competes_with(ads[i].advertiser.as_deref(), &ads[i].categories));
+                let recent = history
+                    .recent(breaks)
+                    .any(|seen| ad.competes_with(seen.advertiser.as_deref(), &seen.categories));
+                if in_pod || recent {
+                    debug!("Ad {} competes with an ad played nearby", ad.ad_id);
+                    metrics::record_frequency_rejection("separation");
+                    continue;
+                }
+            }
+            selected.push(index);
+        }
+        selected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ad(ad_id: &str, advertiser: Option<&str>, categories: &[&str]) -> AdIdentity {
+        AdIdentity {
+            ad_id: ad_id.to_string(),
+            advertiser: advertiser.map(str::to_string),
+            categories: categories.iter().map(|c| c.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn cap_counts_history_and_pod() {
+        let policy = FrequencyPolicy {
+            cap: 2,
+            separation_breaks: None,
+        };
+        let a = ad("ad-id.org:A", None, &[]);
+        let b = ad("ad-id.org:B", None, &[]);
+        let mut history = AdHistory::default();
+        history.record(None, std::slice::from_ref(&a));
+
+        // A played once: one more is allowed, the third is capped
+        assert_eq!(policy.select(&[&a, &b, &a], &history), vec![0, 1]);
+
+        history.record(None, std::slice::from_ref(&a));
+        assert_eq!(policy.select(&[&a, &b], &history), vec![1]);
+    }
+
+    #[test]
+    fn competitors_are_kept_out_of_the_pod() {
+        let policy = FrequencyPolicy {
+            cap: 0,
+            separation_breaks: Some(0),
+        };
+        let cola = ad("1", Some("cola.example"), &["IAB8-5"]);
+        let other_cola = ad("2", Some("Cola.Example "), &[]);
+        let soda = ad("3", Some("soda.example"), &["iab8-5"]);
+        let car = ad("4", Some("cars.example"), &["IAB2"]);
+        let unknown = ad("5", None, &[]);
+
+        let history = AdHistory::default();
+        assert_eq!(
+            policy.select(&[&cola, &other_cola, &soda, &car, &unknown], &history),
+            vec![0, 3, 4]
+        );
+    }
+
+    #[test]
+    fn separation_reaches_back_the_configured_breaks() {
+        let cola = ad("1", Some("cola.example"), &[]);
+        let soda = ad("2", Some("cola.example"), &[]);
+        let mut history = AdHistory::default();
+        history.record(None, std::slice::from_ref(&cola));
+        history.record(None, &[]);
+
+        let one = FrequencyPolicy {
+            cap: 0,
+            separation_breaks: Some(1),
+        };
+        let two = FrequencyPolicy {
+            separation_breaks: Some(2),
+            ..one
+        };
+        assert_eq!(one.select(&[&soda], &history), vec![0]);
+        assert!(two.select(&[&soda], &history).is_empty());
+    }
+
+    #[test]
+    fn history_is_bounded() {
+        let mut history = AdHistory::default();
+        for i in 0..MAX_HISTORY + 10 {
+            history.record(None, &[ad(&i.to_string(), None, &[])]);
+        }
+        assert_eq!(history.ads.len(), MAX_HISTORY);
+        assert_eq!(history.impressions("0"), 0);
+        assert_eq!(history.breaks as usize, MAX_HISTORY + 10);
+    }
+
+    #[test]
+    fn keyed_breaks_are_recorded_once() {
+        let a = ad("ad-id.org:A", None, &[]);
+        let mut history = AdHistory::default();
+        assert!(history.record(Some(7), std::slice::from_ref(&a)));
+        assert!(!history.record(Some(7), std::slice::from_ref(&a)));
+        assert!(history.record(Some(8), std::slice::from_ref(&a)));
+
+        assert_eq!(history.breaks, 2);
+        assert_eq!(history.impressions("ad-id.org:A"), 2);
+    }
+}
@@ -12,8 +12,9 @@ use tracing::{info, warn};
 /// * `playlist` - The parsed MediaPlaylist to modify
 /// * `ad_breaks` - Detected ad break positions from CUE tags
 /// * `ad_segments` - Ad segments to insert (one vec per ad break)
-/// * `session_id` - Session ID for URL generation
-/// * `base_url` - Base URL for the stitcher
+/// * `segment_url` - Stitcher URL of an ad segment, given its name, break
+///   index and index in the break (see
+///   [`AdProvider::segment_url`](crate::ad::provider::AdProvider::segment_url))
 ///
 /// # Returns
 /// Modified MediaPlaylist with ad segments interleaved
@@ -21,8 +22,7 @@ pub fn interleave_ads(
     mut playlist: MediaPlaylist,
     ad_breaks: &[AdBreak],
     ad_segments_per_break: &[Vec<AdSegment>],
-    session_id: &str,
-    base_url: &str,
+    segment_url: impl Fn(&str, usize, usize) -> String,
 ) -> MediaPlaylist {
     if ad_breaks.is_empty() {
         info!("No ad breaks detected, returning playlist unchanged");
@@ -62,19 +62,14 @@ pub fn interleave_ads(
 
             // Add discontinuity before first ad segment
             let mut first_ad_segment =
-                create_media_segment_from_ad(&ad_segments[0], session_id, base_url, break_idx, 0);
+                create_media_segment_from_ad(&ad_segments[0], &segment_url, break_idx, 0);
             first_ad_segment.discontinuity = true;
             new_segments.push(first_ad_segment);
 
             // Add remaining ad segments
             for (idx, ad_segment) in ad_segments.iter().skip(1).enumerate() {
-                let media_segment = create_media_segment_from_ad(
-                    ad_segment,
-                    session_id,
-                    base_url,
-                    break_idx,
-                    idx + 1,
-                );
+                let media_segment =
+                    create_media_segment_from_ad(ad_segment, &segment_url, break_idx, idx + 1);
                 new_segments.push(media_segment);
             }
 
@@ -113,20 +108,13 @@ pub fn interleave_ads(
 /// Create a MediaSegment from an AdSegment
 fn create_media_segment_from_ad(
     ad_segment: &AdSegment,
-    session_id: &str,
-    base_url: &str,
+    segment_url: impl Fn(&str, usize, usize) -> String,
     break_idx: usize,
     segment_idx: usize,
 ) -> MediaSegment {
     // Route ad segment through the stitcher's ad handler
-    // Format: /stitch/{session_id}/ad/break-{break_idx}-seg-{segment_idx}.ts
-    let stitcher_uri = format!(
-        "{}/stitch/{}/ad/break-{}-seg-{}.ts",
-        base_url, session_id, break_idx, segment_idx
-    );
-
     MediaSegment {
-        uri: stitcher_uri,
+        uri: segment_url(&ad_segment.uri, break_idx, segment_idx),
         duration: ad_segment.duration,
         title: Some(format!("Ad Break {}", break_idx + 1)),
         byte_range: None,
@@ -142,6 +130,12 @@ fn create_media_segment_from_ad(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ad::provider::{ad_segment_url, break_segment_url};
+
+    /// Segment URLs as providers build them by default
+    fn break_urls(_name: &str, break_idx: usize, segment_idx: usize) -> String {
+        break_segment_url("http://localhost", "test-session", break_idx, segment_idx)
+    }
 
     fn create_test_segment(uri: &str, duration: f32) -> MediaSegment {
         MediaSegment {
@@ -175,6 +169,7 @@ mod tests {
             start_index: 1,
             end_index: 3,
             duration: 30.0,
+            upid: None,
         }];
 
         let ad_segments = vec![vec![
@@ -190,13 +185,7 @@ mod tests {
             },
         ]];
 
-        let result = interleave_ads(
-            playlist,
-            &ad_breaks,
-            &ad_segments,
-            "test-session",
-            "http://localhost",
-        );
+        let result = interleave_ads(playlist, &ad_breaks, &ad_segments, break_urls);
 
         // Should have: seg0, ad1, ad2, seg3(with discontinuity), seg4
         assert_eq!(result.segments.len(), 5);
@@ -229,11 +218,13 @@ mod tests {
                 start_index: 1,
                 end_index: 2,
                 duration: 15.0,
+                upid: None,
             },
             AdBreak {
                 start_index: 4,
                 end_index: 5,
                 duration: 15.0,
+                upid: None,
             },
         ];
 
@@ -250,13 +241,7 @@ mod tests {
             }],
         ];
 
-        let result = interleave_ads(
-            playlist,
-            &ad_breaks,
-            &ad_segments,
-            "test-session",
-            "http://localhost",
-        );
+        let result = interleave_ads(playlist, &ad_breaks, &ad_segments, break_urls);
 
         // seg0, ad1, seg2, seg3, ad2, seg5
         assert_eq!(result.segments.len(), 6);
@@ -280,16 +265,59 @@ mod tests {
             ..Default::default()
         };
 
-        let result = interleave_ads(
-            playlist.clone(),
-            &[],
-            &[],
-            "test-session",
-            "http://localhost",
-        );
+        let result = interleave_ads(playlist.clone(), &[], &[], break_urls);
 
         assert_eq!(result.segments.len(), 2);
         assert_eq!(result.segments[0].uri, "seg0.ts");
         assert_eq!(result.segments[1].uri, "seg1.ts");
     }
+
+    #[test]
+    fn test_interleave_asks_for_each_segment_url() {
+        let playlist = MediaPlaylist {
+            segments: vec![
+                create_test_segment("seg0.ts", 10.0),
+                create_test_segment("seg1.ts", 10.0),
+                create_test_segment("seg2.ts", 10.0),
+            ],
+            ..Default::default()
+        };
+        let ad_breaks = vec![AdBreak {
+            start_index: 1,
+            end_index: 2,
+            duration: 10.0,
+            upid: None,
+        }];
+        let ad_segments = vec![vec![
+            AdSegment {
+                uri: "https://ads.example.com/ad.ts".to_string(),
+                duration: 6.0,
+                tracking: None,
+            },
+            AdSegment {
+                uri: "slate-seg-0.ts".to_string(),
+                duration: 4.0,
+                tracking: None,
+            },
+        ]];
+
+        // Names that resolve as they are keep them; others are renamed
+        let segment_url = |name: &str, break_idx, segment_idx| {
+            if name.starts_with("slate-") {
+                ad_segment_url("http://localhost", "s1", name)
+            } else {
+                break_segment_url("http://localhost", "s1", break_idx, segment_idx)
+            }
+        };
+        let result = interleave_ads(playlist, &ad_breaks, &ad_segments, segment_url);
+
+        assert_eq!(
+            result.segments[1].uri,
+            "http://localhost/stitch/s1/ad/break-0-seg-0.ts"
+        );
+        assert_eq!(
+            result.segments[2].uri,
+            "http://localhost/stitch/s1/ad/slate-seg-0.ts"
+        );
+    }
 }
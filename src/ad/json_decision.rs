@@ -0,0 +1,666 @@
+//! Generic JSON ad-decision provider
+//!
+//! [`JsonDecisionAdProvider`] lets a decisioning service that does not speak
+//! VAST pick the ads. For every break it POSTs a [`DecisionRequest`] to the
+//! configured URL (`AD_DECISION_URL`):
+//!
+//! ```json
+//! {
+//!   "session_id": "3f2a…",
+//!   "break_id": "break-0",
+//!   "duration": 30.0,
+//!   "position": "mid",
+//!   "targeting": {"ad.genre": "news"},
+//!   "viewer": {"ip": "203.0.113.7", "user_agent": "…", "referer": null, "device_type": "ctv"},
+//!   "privacy": {"gdpr": true, "gdpr_consent": "…", "us_privacy": null, "limit_ad_tracking": false},
+//!   "content": {"id": "ep-12", "title": null, "genre": "news", "series": null}
+//! }
+//! ```
+//!
+//! and plays the creatives of the [`DecisionResponse`], in order:
+//!
+//! ```json
+//! {
+//!   "creatives": [{
+//!     "url": "https://cdn.example.com/ad-1/master.m3u8",
+//!     "duration": 15.0,
+//!     "impressions": ["https://track.example.com/imp?id=1"],
+//!     "tracking": [{"event": "start", "url": "https://track.example.com/start?id=1"}],
+//!     "errors": ["https://track.example.com/error?code=[ERRORCODE]"],
+//!     "verifications": [{"vendor": "vendor.com-omid", "javascript_resource_url": "https://…/omid.js"}]
+//!   }]
+//! }
+//! ```
+//!
+//! A `204 No Content` or an empty list leaves the break to slate. Creatives
+//! go through the same creative policy, probing, normalization and
+//! frequency policy as VAST creatives, and are fitted to the break, cached
+//! and tracked like them, so tracking URLs may use the VAST macros.
+
+use crate::ad::conditioning::{self, CreativePolicy};
+use crate::ad::context::{AdRequestContext, ContentMetadata, DeviceType};
+use crate::ad::frequency::{AdIdentity, FrequencyPolicy};
+use crate::ad::normalizer::AdNormalizer;
+use crate::ad::probe::CreativeProber;
+use crate::ad::provider::{AdCreative, AdProvider, AdSegment, ResolvedSegment};
+use crate::ad::schedule::BreakPosition;
+use crate::ad::slate::SlateProvider;
+use crate::ad::vast::{MediaFile, TrackingEvent, Verification, VerificationTrackingEvent};
+use crate::ad::vast_provider::{ResolvedVastCreative, VastAdProvider};
+use crate::metrics;
+use crate::session::SessionManager;
+use async_trait::async_trait;
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Body of the decision request
+#[derive(Debug, Clone, Serialize)]
+pub struct DecisionRequest<'a> {
+    pub session_id: &'a str,
+    /// Index of the break within the session (`break-N`)
+    pub break_id: String,
+    /// Break duration in seconds
+    pub duration: f32,
+    /// Where the break plays: always `mid` for signalled breaks
+    pub position: &'static str,
+    /// Allowlisted targeting parameters of the session
+    pub targeting: &'a BTreeMap<String, String>,
+    pub viewer: DecisionViewer<'a>,
+    pub privacy: DecisionPrivacy<'a>,
+    pub content: &'a ContentMetadata,
+}
+
+/// Viewer fields of a decision request
+#[derive(Debug, Clone, Serialize)]
+pub struct DecisionViewer<'a> {
+    pub ip: Option<&'a str>,
+    pub user_agent: Option<&'a str>,
+    pub referer: Option<&'a str>,
+    pub device_type: DeviceType,
+}
+
+/// Consent fields of a decision request
+#[derive(Debug, Clone, Serialize)]
+pub struct DecisionPrivacy<'a> {
+    pub gdpr: Option<bool>,
+    pub gdpr_consent: Option<&'a str>,
+    pub us_privacy: Option<&'a str>,
+    pub limit_ad_tracking: bool,
+}
+
+/// Body of the decision response
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct DecisionResponse {
+    pub creatives: Vec<DecisionCreative>,
+}
+
+/// A creative picked by the decision service
+#[derive(Debug, Clone, Deserialize)]
+pub struct DecisionCreative {
+    /// HLS playlist or progressive MP4 URL
+    pub url: String,
+    /// Duration in seconds
+    pub duration: f32,
+    /// MIME type; defaults to HLS for `.m3u8` URLs
+    #[serde(default)]
+    pub mime_type: Option<String>,
+    #[serde(default)]
+    pub impressions: Vec<String>,
+    #[serde(default)]
+    pub tracking: Vec<DecisionTrackingEvent>,
+    #[serde(default)]
+    pub errors: Vec<String>,
+    #[serde(default)]
+    pub verifications: Vec<DecisionVerification>,
+}
+
+/// A tracking URL fired at a VAST event (`start`, `firstQuartile`, ...)
+#[derive(Debug, Clone, Deserialize)]
+pub struct DecisionTrackingEvent {
+    pub event: String,
+    pub url: String,
+}
+
+/// An OM SDK verification resource
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct DecisionVerification {
+    pub vendor: Option<String>,
+    pub javascript_resource_url: Option<String>,
+    pub api_framework: Option<String>,
+    pub parameters: Option<String>,
+    pub tracking: Vec<DecisionTrackingEvent>,
+}
+
+impl DecisionCreative {
+    /// The creative as a media file, for the creative policy
+    fn media_file(&self) -> MediaFile {
+        let hls = self.is_hls();
+        MediaFile {
+            url: self.url.clone(),
+            delivery: if hls { "streaming" } else { "progressive" }.to_string(),
+            mime_type: self.mime_type.clone().unwrap_or_else(|| {
+                if hls {
+                    "application/x-mpegURL"
+                } else {
+                    "video/mp4"
+                }
+                .to_string()
+            }),
+            width: 0,
+            height: 0,
+            bitrate: None,
+            codec: None,
+            api_framework: None,
+        }
+    }
+
+    fn is_hls(&self) -> bool {
+        match &self.mime_type {
+            Some(mime) => mime.eq_ignore_ascii_case("application/x-mpegURL"),
+            None => self
+                .url
+                .split(['?', '#'])
+                .next()
+                .is_some_and(|path| path.ends_with(".m3u8")),
+        }
+    }
+
+    fn into_resolved(self) -> ResolvedVastCreative {
+        let is_hls = self.is_hls();
+        let identity = AdIdentity {
+            ad_id: self.url.clone(),
+            ..Default::default()
+        };
+        ResolvedVastCreative {
+            url: self.url,
+            duration: self.duration,
+            is_hls,
+            impression_urls: self.impressions,
+            tracking_events: self
+                .tracking
+                .into_iter()
+                .map(|t| TrackingEvent {
+                    event: t.event,
+                    url: t.url,
+                })
+                .collect(),
+            error_urls: self.errors,
+            alternatives: Vec::new(),
+            sequenced: false,
+            verifications: self
+                .verifications
+                .into_iter()
+                .map(|v| Verification {
+                    vendor: v.vendor,
+                    javascript_resource_url: v.javascript_resource_url,
+                    api_framework: v.api_framework.or_else(|| Some("omid".to_string())),
+                    parameters: v.parameters,
+                    tracking_events: v
+                        .tracking
+                        .into_iter()
+                        .map(|t| VerificationTrackingEvent {
+                            event: t.event,
+                            uri: t.url,
+                        })
+                        .collect(),
+                })
+                .collect(),
+            identity,
+        }
+    }
+}
+
+/// Ad provider asking an HTTP decision service for creatives as JSON
+pub struct JsonDecisionAdProvider {
+    /// Decision service URL receiving the POST requests
+    decision_url: String,
+    /// HTTP client for decision requests
+    http_client: Client,
+    /// Fits, caches and tracks the decided creatives
+    vast: VastAdProvider,
+    /// Time budget of a decision request
+    timeout: Duration,
+    /// Send the viewer's `X-Device-*` headers with decision requests
+    forward_viewer_headers: bool,
+}
+
+impl JsonDecisionAdProvider {
+    /// Create a new JsonDecisionAdProvider
+    ///
+    /// # Arguments
+    /// * `decision_url` - URL the decision requests are POSTed to
+    /// * `http_client` - Shared HTTP client
+    pub fn new(decision_url: String, http_client: Client) -> Self {
+        Self {
+            decision_url,
+            vast: VastAdProvider::new(String::new(), http_client.clone()),
+            http_client,
+            timeout: Duration::from_millis(3000),
+            forward_viewer_headers: true,
+        }
+    }
+
+    /// Configure a slate provider for breaks the service leaves unfilled
+    pub fn with_slate(mut self, slate: SlateProvider) -> Self {
+        self.vast = self.vast.with_slate(slate);
+        self
+    }
+
+    /// Set the time budget of a decision request
+    pub fn with_decision_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Enable or disable forwarding the viewer headers on decision requests
+    pub fn with_viewer_headers(mut self, forward: bool) -> Self {
+        self.forward_viewer_headers = forward;
+        self
+    }
+
+    /// Enable or disable forwarding the viewer headers on tracking and
+    /// error beacons
+    pub fn with_beacon_headers(mut self, forward: bool) -> Self {
+        self.vast = self.vast.with_beacon_headers(forward);
+        self
+    }
+
+    /// Cap and separate each session's ads by its history in `sessions`
+    pub fn with_frequency_policy(
+        mut self,
+        policy: FrequencyPolicy,
+        sessions: SessionManager,
+    ) -> Self {
+        self.vast = self.vast.with_frequency_policy(policy, sessions);
+        self
+    }
+
+    /// Set the rules decided creatives must meet
+    pub fn with_creative_policy(mut self, policy: CreativePolicy) -> Self {
+        self.vast = self.vast.with_creative_policy(policy);
+        self
+    }
+
+    /// Probe the media of decided creatives before stitching
+    pub fn with_prober(mut self, prober: CreativeProber) -> Self {
+        self.vast = self.vast.with_prober(prober);
+        self
+    }
+
+    /// Play non-HLS decided creatives through their normalized packages
+    pub fn with_normalizer(mut self, normalizer: AdNormalizer) -> Self {
+        self.vast = self.vast.with_normalizer(normalizer);
+        self
+    }
+
+    /// Whether a decided creative meets the creative policy
+    fn accepts(&self, creative: &DecisionCreative, session_id: &str) -> bool {
+        let policy = &self.vast.creative_policy;
+        let media_file = creative.media_file();
+        let checked = policy
+            .check_ad(creative.duration, &[creative.url.as_str()], None)
+            .and_then(|()| policy.check_media_file(&media_file));
+        match checked {
+            Ok(()) => {
+                conditioning::check_creative(&media_file, session_id);
+                true
+            }
+            Err(rejection) => {
+                warn!(
+                    "JsonDecisionAdProvider: Creative {} rejected by the creative policy ({}) for session {}",
+                    creative.url,
+                    rejection.as_str(),
+                    session_id
+                );
+                metrics::record_creative_rejection(rejection.as_str());
+                false
+            }
+        }
+    }
+
+    /// Build the decision request of a break
+    fn decision_request<'a>(
+        &self,
+        session_id: &'a str,
+        duration: f32,
+        ctx: &'a AdRequestContext,
+    ) -> DecisionRequest<'a> {
+        // The break stitch_session_pod numbers next
+        let break_index = self
+            .vast
+            .break_counter
+            .get(session_id)
+            .map_or(0, |counter| *counter);
+        DecisionRequest {
+            session_id,
+            break_id: format!("break-{break_index}"),
+            duration,
+            position: "mid",
+            targeting: &ctx.params,
+            viewer: DecisionViewer {
+                ip: ctx.client_ip.as_deref(),
+                user_agent: ctx.user_agent.as_deref(),
+                referer: ctx.referer.as_deref(),
+                device_type: ctx.device_type,
+            },
+            privacy: DecisionPrivacy {
+                gdpr: ctx.gdpr,
+                gdpr_consent: ctx.gdpr_consent.as_deref(),
+                us_privacy: ctx.us_privacy.as_deref(),
+                limit_ad_tracking: ctx.limit_ad_tracking,
+            },
+            content: &ctx.content,
+        }
+    }
+
+    /// Ask the decision service for a break's creatives
+    ///
+    /// Returns `None` when the request fails.
+    async fn decide(
+        &self,
+        duration: f32,
+        session_id: &str,
+        ctx: &AdRequestContext,
+    ) -> Option<Vec<ResolvedVastCreative>> {
+        let request = self.decision_request(session_id, duration, ctx);
+        info!(
+            "JsonDecisionAdProvider: Requesting {} for session {} ({}s)",
+            request.break_id, session_id, duration
+        );
+
+        let headers = if self.forward_viewer_headers {
+            ctx.viewer_headers()
+        } else {
+            Default::default()
+        };
+        let response = match self
+            .http_client
+            .post(&self.decision_url)
+            .headers(headers)
+            .json(&request)
+            .timeout(self.timeout)
+            .send()
+            .await
+        {
+            Ok(response) => response,
+            Err(e) => {
+                warn!("JsonDecisionAdProvider: Decision request failed: {}", e);
+                metrics::record_decision_request(if e.is_timeout() { "timeout" } else { "error" });
+                return None;
+            }
+        };
+
+        let status = response.status();
+        if status == StatusCode::NO_CONTENT {
+            metrics::record_decision_request("empty");
+            return Some(Vec::new());
+        }
+        if !status.is_success() {
+            warn!(
+                "JsonDecisionAdProvider: Decision service answered {}",
+                status
+            );
+            metrics::record_decision_request("error");
+            return None;
+        }
+        match response.json::<DecisionResponse>().await {
+            Ok(body) => {
+                let creatives: Vec<ResolvedVastCreative> = body
+                    .creatives
+                    .into_iter()
+                    .filter(|c| !c.url.is_empty() && c.duration > 0.0)
+                    .filter(|c| self.accepts(c, session_id))
+                    .map(DecisionCreative::into_resolved)
+                    .collect();
+                metrics::record_decision_request(if creatives.is_empty() {
+                    "empty"
+                } else {
+                    "success"
+                });
+                Some(creatives)
+            }
+            Err(e) => {
+                warn!("JsonDecisionAdProvider: Invalid decision response: {}", e);
+                metrics::record_decision_request("error");
+                None
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl AdProvider for JsonDecisionAdProvider {
+    async fn get_ad_segments(
+        &self,
+        duration: f32,
+        session_id: &str,
+        ctx: &AdRequestContext,
+    ) -> Vec<AdSegment> {
+        let creatives = self
+            .decide(duration, session_id, ctx)
+            .await
+            .unwrap_or_default();
+        let creatives = self.vast.prepare_creatives(creatives, session_id).await;
+        self.vast
+            .stitch_session_pod(
+                &creatives,
+                duration,
+                Some(BreakPosition::Mid),
+                session_id,
+                ctx.break_key,
+            )
+            .await
+    }
+
+    async fn get_ad_creatives(
+        &self,
+        duration: f32,
+        session_id: &str,
+        ctx: &AdRequestContext,
+    ) -> Vec<AdCreative> {
+        let creatives = self
+            .decide(duration, session_id, ctx)
+            .await
+            .unwrap_or_default();
+        self.vast
+            .session_creatives(creatives, session_id, ctx.break_key)
+            .await
+    }
+
+    fn resolve_segment_url(&self, ad_name: &str, session_id: &str) -> Option<String> {
+        self.vast.resolve_segment_url(ad_name, session_id)
+    }
+
+    fn segment_url(
+        &self,
+        segment_name: &str,
+        session_id: &str,
+        base_url: &str,
+        break_idx: usize,
+        segment_idx: usize,
+    ) -> String {
+        self.vast
+            .segment_url(segment_name, session_id, base_url, break_idx, segment_idx)
+    }
+
+    fn resolve_segment_with_tracking(
+        &self,
+        ad_name: &str,
+        session_id: &str,
+    ) -> Option<ResolvedSegment> {
+        self.vast.resolve_segment_with_tracking(ad_name, session_id)
+    }
+
+    fn cleanup_cache(&self) {
+        self.vast.cleanup_cache();
+    }
+
+    fn slate(&self) -> Option<&SlateProvider> {
+        self.vast.slate()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_partial_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn creatives_map_onto_tracking_and_verifications() {
+        let creative: DecisionCreative = serde_json::from_value(serde_json::json!({
+            "url": "https://cdn.example.com/ad.m3u8?v=1",
+            "duration": 15.0,
+            "impressions": ["https://t/imp"],
+            "tracking": [{"event": "start", "url": "https://t/start"}],
+            "errors": ["https://t/err?c=[ERRORCODE]"],
+            "verifications": [{"vendor": "v-omid", "javascript_resource_url": "https://v/omid.js",
+                               "tracking": [{"event": "verificationNotExecuted", "url": "https://v/ne"}]}]
+        }))
+        .unwrap();
+        let resolved = creative.into_resolved();
+        assert!(resolved.is_hls);
+        assert_eq!(resolved.impression_urls, vec!["https://t/imp"]);
+        assert_eq!(resolved.tracking_events[0].event, "start");
+        assert_eq!(resolved.error_urls, vec!["https://t/err?c=[ERRORCODE]"]);
+        let verification = &resolved.verifications[0];
+        assert_eq!(verification.api_framework.as_deref(), Some("omid"));
+        assert_eq!(verification.tracking_events[0].uri, "https://v/ne");
+
+        let mp4: DecisionCreative = serde_json::from_value(
+            serde_json::json!({"url": "https://cdn.example.com/ad.mp4", "duration": 10.0}),
+        )
+        .unwrap();
+        assert!(!mp4.is_hls());
+    }
+
+    #[tokio::test]
+    async fn decided_creatives_are_stitched_and_resolvable() {
+        let server = MockServer::start().await;
+        let uri = server.uri();
+        Mock::given(method("POST"))
+            .and(path("/decide"))
+            .and(body_partial_json(serde_json::json!({
+                "session_id": "session-json",
+                "break_id": "break-0",
+                "duration": 20.0,
+                "position": "mid",
+                "targeting": {"ad.genre": "news"},
+                "viewer": {"user_agent": "TestPlayer/1.0"}
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "creatives": [
+                    {"url": format!("{uri}/a.m3u8"), "duration": 10.0,
+                     "impressions": [format!("{uri}/imp/a")]},
+                    {"url": format!("{uri}/b.m3u8"), "duration": 10.0}
+                ]
+            })))
+            .mount(&server)
+            .await;
+
+        let provider = JsonDecisionAdProvider::new(format!("{uri}/decide"), Client::new());
+        let ctx = AdRequestContext {
+            user_agent: Some("TestPlayer/1.0".to_string()),
+            params: BTreeMap::from([("ad.genre".to_string(), "news".to_string())]),
+            ..AdRequestContext::default()
+        };
+        let segments = provider.get_ad_segments(20.0, "session-json", &ctx).await;
+
+        assert_eq!(segments.len(), 2);
+        let resolved = provider
+            .resolve_segment_with_tracking(&segments[0].uri, "session-json")
+            .unwrap();
+        assert_eq!(resolved.url, format!("{uri}/a.m3u8"));
+        assert_eq!(
+            resolved.tracking.unwrap().impression_urls,
+            vec![format!("{uri}/imp/a")]
+        );
+        assert_eq!(
+            provider
+                .resolve_segment_url(&segments[1].uri, "session-json")
+                .as_deref(),
+            Some(format!("{uri}/b.m3u8").as_str())
+        );
+    }
+
+    #[tokio::test]
+    async fn decided_creatives_meet_creative_and_frequency_policies() {
+        use std::time::Duration;
+        use wiremock::matchers::header;
+
+        let server = MockServer::start().await;
+        let uri = server.uri();
+        Mock::given(method("POST"))
+            .and(path("/decide"))
+            .and(header("x-device-ip", "203.0.113.7"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "creatives": [
+                    {"url": format!("{uri}/a.m3u8"), "duration": 10.0},
+                    {"url": format!("{uri}/long.m3u8"), "duration": 30.0}
+                ]
+            })))
+            .mount(&server)
+            .await;
+
+        let sessions = SessionManager::new_memory(Duration::from_secs(60));
+        sessions
+            .get_or_create(
+                "viewer".to_string(),
+                "http://origin".to_string(),
+                AdRequestContext::default(),
+            )
+            .await;
+        let provider = JsonDecisionAdProvider::new(format!("{uri}/decide"), Client::new())
+            .with_creative_policy(CreativePolicy {
+                max_duration: Some(15.0),
+                ..CreativePolicy::default()
+            })
+            .with_frequency_policy(
+                FrequencyPolicy {
+                    cap: 1,
+                    separation_breaks: None,
+                },
+                sessions.clone(),
+            );
+        let ctx = AdRequestContext {
+            client_ip: Some("203.0.113.7".to_string()),
+            ..AdRequestContext::default()
+        };
+
+        // The 30s creative breaks the creative policy
+        let first = provider.get_ad_segments(30.0, "viewer", &ctx).await;
+        assert_eq!(first.len(), 1);
+        assert_eq!(
+            provider.resolve_segment_url(&first[0].uri, "viewer"),
+            Some(format!("{uri}/a.m3u8"))
+        );
+
+        // The 10s creative is capped at one play per session
+        let second = provider.get_ad_segments(30.0, "viewer", &ctx).await;
+        assert!(second.is_empty(), "{second:?}");
+        assert_eq!(sessions.ad_history("viewer").await.breaks, 1);
+    }
+
+    #[tokio::test]
+    async fn no_content_leaves_break_to_slate() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(204))
+            .mount(&server)
+            .await;
+
+        let provider = JsonDecisionAdProvider::new(server.uri(), Client::new()).with_slate(
+            SlateProvider::new("http://slate.example.com".to_string(), 1.0),
+        );
+        let segments = provider
+            .get_ad_segments(3.0, "session-empty", &AdRequestContext::default())
+            .await;
+        assert_eq!(segments.len(), 3);
+        assert!(segments.iter().all(|s| s.uri.starts_with("slate-")));
+    }
+}
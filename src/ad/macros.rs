@@ -0,0 +1,303 @@
+//! IAB VAST 4.x macro expansion
+//!
+//! Ad request URLs (the VAST endpoint, wrapper `VASTAdTagURI`s) and
+//! tracking beacons may carry `[MACRO]` placeholders that the stitcher
+//! fills in before the request goes out. [`expand`] replaces every
+//! supported macro with its percent-encoded value from a [`MacroContext`].
+//!
+//! Following the VAST 4.x spec, a supported macro whose value is unknown
+//! becomes `-2` ("not available"), and unsupported macros are left as-is so
+//! the ad server can tell they were not filled.
+
+use crate::ad::schedule::BreakPosition;
+use chrono::{SecondsFormat, Utc};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Value sent for a supported macro whose value is unknown
+const NOT_AVAILABLE: &str = "-2";
+
+/// Values available for macro substitution
+///
+/// Every field is optional; the caller fills in what it knows about the
+/// session and the request. `[TIMESTAMP]`, `[CACHEBUSTING]` and
+/// `[CLIENTUA]` are generated.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MacroContext {
+    /// `[DURATION]`: requested break duration in seconds
+    pub duration: Option<f64>,
+    /// `[ERRORCODE]`: VAST error code of an error beacon
+    pub error_code: Option<u16>,
+    /// `[CONTENTPLAYHEAD]` / `[MEDIAPLAYHEAD]`: content position in seconds
+    pub content_playhead: Option<f64>,
+    /// `[ADPLAYHEAD]`: position within the creative in seconds
+    pub ad_playhead: Option<f64>,
+    /// `[BREAKPOSITION]`: where the break plays
+    pub break_position: Option<BreakPosition>,
+    /// `[PODSEQUENCE]`: 1-based position of the ad in its pod
+    pub pod_sequence: Option<usize>,
+    /// `[DEVICEIP]`: viewer's IP address
+    pub device_ip: Option<String>,
+    /// `[DEVICEUA]`: viewer's user agent
+    pub device_ua: Option<String>,
+    /// `[GDPRCONSENT]`: IAB TCF consent string
+    pub gdpr_consent: Option<String>,
+    /// `[LIMITADTRACKING]`: viewer opted out of tracking
+    pub limit_ad_tracking: Option<bool>,
+    /// `[ASSETURI]`: URI of the creative being played
+    pub asset_uri: Option<String>,
+    /// `[UPID]`: SCTE-35 segmentation UPID of the break
+    pub upid: Option<String>,
+}
+
+impl MacroContext {
+    /// Set the requested break duration
+    pub fn with_duration(mut self, duration: f64) -> Self {
+        self.duration = Some(duration);
+        self
+    }
+
+    /// Set the VAST error code
+    pub fn with_error_code(mut self, code: u16) -> Self {
+        self.error_code = Some(code);
+        self
+    }
+
+    /// Set the content playhead
+    pub fn with_content_playhead(mut self, seconds: f64) -> Self {
+        self.content_playhead = Some(seconds);
+        self
+    }
+
+    /// Set the ad playhead
+    pub fn with_ad_playhead(mut self, seconds: f64) -> Self {
+        self.ad_playhead = Some(seconds);
+        self
+    }
+
+    /// Set the break position
+    pub fn with_break_position(mut self, position: BreakPosition) -> Self {
+        self.break_position = Some(position);
+        self
+    }
+
+    /// Set the ad's 1-based position in its pod
+    pub fn with_pod_sequence(mut self, sequence: usize) -> Self {
+        self.pod_sequence = Some(sequence);
+        self
+    }
+
+    /// Set the viewer's IP address
+    pub fn with_device_ip(mut self, ip: impl Into<String>) -> Self {
+        self.device_ip = Some(ip.into());
+        self
+    }
+
+    /// Set the viewer's user agent
+    pub fn with_device_ua(mut self, ua: impl Into<String>) -> Self {
+        self.device_ua = Some(ua.into());
+        self
+    }
+
+    /// Set the TCF consent string
+    pub fn with_gdpr_consent(mut self, consent: impl Into<String>) -> Self {
+        self.gdpr_consent = Some(consent.into());
+        self
+    }
+
+    /// Set the limit-ad-tracking flag
+    pub fn with_limit_ad_tracking(mut self, limit: bool) -> Self {
+        self.limit_ad_tracking = Some(limit);
+        self
+    }
+
+    /// Set the creative URI
+    pub fn with_asset_uri(mut self, uri: impl Into<String>) -> Self {
+        self.asset_uri = Some(uri.into());
+        self
+    }
+
+    /// Set the break's segmentation UPID
+    pub fn with_upid(mut self, upid: impl Into<String>) -> Self {
+        self.upid = Some(upid.into());
+        self
+    }
+
+    /// Unencoded value of a supported macro, `None` for unsupported names
+    fn value(&self, name: &str) -> Option<Option<String>> {
+        let value = match name {
+            "TIMESTAMP" => Some(Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)),
+            "CACHEBUSTING" => Some(cache_buster()),
+            "CLIENTUA" => Some(concat!("ritcher/", env!("CARGO_PKG_VERSION")).to_string()),
+            // Ad servers expect whole seconds
+            "DURATION" => self.duration.map(|d| format!("{}", d.max(0.0).trunc())),
+            "ERRORCODE" => self.error_code.map(|c| c.to_string()),
+            "CONTENTPLAYHEAD" | "MEDIAPLAYHEAD" => self.content_playhead.map(playhead),
+            "ADPLAYHEAD" => self.ad_playhead.map(playhead),
+            "BREAKPOSITION" => self.break_position.map(|p| p.vast_code().to_string()),
+            "PODSEQUENCE" => self.pod_sequence.map(|s| s.to_string()),
+            "DEVICEIP" => self.device_ip.clone(),
+            "DEVICEUA" => self.device_ua.clone(),
+            "GDPRCONSENT" => self.gdpr_consent.clone(),
+            "LIMITADTRACKING" => self.limit_ad_tracking.map(|l| u8::from(l).to_string()),
+            "ASSETURI" => self.asset_uri.clone(),
+            "UPID" => self.upid.clone(),
+            _ => return None,
+        };
+        Some(value)
+    }
+}
+
+/// Replace the supported `[MACRO]`s in `template` with values from `ctx`
+///
+/// Values are percent-encoded so they are safe in any URL component.
+pub fn expand(template: &str, ctx: &MacroContext) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(open) = rest.find('[') {
+        out.push_str(&rest[..open]);
+        let candidate = &rest[open + 1..];
+        let name_len = candidate
+            .find(|c: char| !(c.is_ascii_uppercase() || c == '_'))
+            .unwrap_or(candidate.len());
+
+        if name_len > 0
+            && candidate[name_len..].starts_with(']')
+            && let Some(value) = ctx.value(&candidate[..name_len])
+        {
+            match value {
+                Some(value) => out.push_str(&encode(&value)),
+                None => out.push_str(NOT_AVAILABLE),
+            }
+            rest = &candidate[name_len + 1..];
+        } else {
+            out.push('[');
+            rest = candidate;
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+/// Percent-encode everything but RFC 3986 unreserved characters
+fn encode(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.' | b'~') {
+            out.push(char::from(byte));
+        } else {
+            out.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    out
+}
+
+/// Playhead in the VAST `HH:MM:SS.mmm` format
+fn playhead(seconds: f64) -> String {
+    // Playheads are small non-negative values; whole milliseconds suffice
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let total_ms = (seconds.max(0.0) * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        total_ms / 3_600_000,
+        total_ms / 60_000 % 60,
+        total_ms / 1000 % 60,
+        total_ms % 1000
+    )
+}
+
+/// Random 8-digit number for `[CACHEBUSTING]`
+fn cache_buster() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    // Mix the clock so consecutive calls differ in the leading digits too
+    let mixed = (nanos ^ (nanos >> 17)).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    format!("{:08}", mixed % 100_000_000)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_context_values() {
+        let ctx = MacroContext::default()
+            .with_duration(30.7)
+            .with_error_code(402)
+            .with_break_position(BreakPosition::Mid)
+            .with_pod_sequence(2)
+            .with_limit_ad_tracking(true)
+            .with_upid("0x000000002CA0A18A");
+        let url = expand(
+            "http://ads/t?d=[DURATION]&e=[ERRORCODE]&bp=[BREAKPOSITION]&ps=[PODSEQUENCE]&lat=[LIMITADTRACKING]&upid=[UPID]",
+            &ctx,
+        );
+        assert_eq!(
+            url,
+            "http://ads/t?d=30&e=402&bp=2&ps=2&lat=1&upid=0x000000002CA0A18A"
+        );
+    }
+
+    #[test]
+    fn percent_encodes_values() {
+        let ctx = MacroContext::default()
+            .with_device_ua("Mozilla/5.0 (X11; Linux)")
+            .with_device_ip("2001:db8::1")
+            .with_asset_uri("https://cdn.example.com/ad.m3u8?x=1&y=2");
+        let url = expand("ua=[DEVICEUA]&ip=[DEVICEIP]&a=[ASSETURI]", &ctx);
+        assert_eq!(
+            url,
+            "ua=Mozilla%2F5.0%20%28X11%3B%20Linux%29&ip=2001%3Adb8%3A%3A1\
+             &a=https%3A%2F%2Fcdn.example.com%2Fad.m3u8%3Fx%3D1%26y%3D2"
+        );
+    }
+
+    #[test]
+    fn unknown_values_become_not_available() {
+        let url = expand(
+            "c=[GDPRCONSENT]&ip=[DEVICEIP]&ph=[CONTENTPLAYHEAD]",
+            &MacroContext::default(),
+        );
+        assert_eq!(url, "c=-2&ip=-2&ph=-2");
+    }
+
+    #[test]
+    fn unsupported_macros_and_brackets_are_kept() {
+        let url = expand(
+            "a=[UNKNOWNMACRO]&b=[lower]&c=[&d=[]&e=[DURATION",
+            &MacroContext::default(),
+        );
+        assert_eq!(url, "a=[UNKNOWNMACRO]&b=[lower]&c=[&d=[]&e=[DURATION");
+    }
+
+    #[test]
+    fn generated_macros_are_filled() {
+        let url = expand(
+            "cb=[CACHEBUSTING]&ts=[TIMESTAMP]&cua=[CLIENTUA]",
+            &MacroContext::default(),
+        );
+        let params: Vec<&str> = url.split('&').collect();
+        let cb = params[0].strip_prefix("cb=").unwrap();
+        assert_eq!(cb.len(), 8);
+        assert!(cb.bytes().all(|b| b.is_ascii_digit()));
+        // 2026-10-18T12:00:00.000Z with ':' encoded
+        let ts = params[1].strip_prefix("ts=").unwrap();
+        assert!(ts.ends_with('Z') && ts.contains("%3A"), "{ts}");
+        assert!(params[2].starts_with("cua=ritcher%2F"));
+    }
+
+    #[test]
+    fn playheads_use_vast_time_format() {
+        let ctx = MacroContext::default()
+            .with_content_playhead(3723.5)
+            .with_ad_playhead(7.25);
+        let url = expand("c=[CONTENTPLAYHEAD]&m=[MEDIAPLAYHEAD]&a=[ADPLAYHEAD]", &ctx);
+        assert_eq!(
+            url,
+            "c=01%3A02%3A03.500&m=01%3A02%3A03.500&a=00%3A00%3A07.250"
+        );
+    }
+}
@@ -1,11 +1,30 @@
+pub mod break_decision;
+pub mod break_schedule;
 pub mod conditioning;
+pub mod context;
+pub mod frequency;
 pub mod interleaver;
+pub mod json_decision;
+pub mod macros;
+pub mod normalizer;
+pub mod openrtb;
+pub mod pod;
+pub mod prefetch;
+pub mod probe;
 pub mod provider;
+pub mod schedule;
+pub mod shared_decision;
 pub mod slate;
+pub mod source;
 pub mod tracking;
 pub mod vast;
 pub mod vast_provider;
+pub mod waterfall;
 
+pub use context::AdRequestContext;
+pub use json_decision::JsonDecisionAdProvider;
+pub use openrtb::OpenRtbAdProvider;
 pub use provider::{AdProvider, DemoAdProvider, StaticAdProvider};
 pub use slate::SlateProvider;
 pub use vast_provider::VastAdProvider;
+pub use waterfall::WaterfallAdProvider;
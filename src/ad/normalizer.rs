@@ -0,0 +1,417 @@
+//! Creative normalization through an external transcoder
+//!
+//! Progressive MP4 and other non-HLS creatives cannot be stitched into an
+//! HLS stream as they are. [`AdNormalizer`] submits them to a transcode
+//! service (`AD_NORMALIZER_URL`, e.g. an Eyevinn Ad Normalizer deployment)
+//! and plays the HLS/CMAF package it produces in their place. A job is
+//! created with
+//!
+//! ```text
+//! POST {AD_NORMALIZER_URL}
+//! {"creative_id": "ad-id.org:ABCD0001", "url": "https://cdn.example.com/ad.mp4"}
+//! ```
+//!
+//! answered by a [`Job`], which is polled at `GET {AD_NORMALIZER_URL}/{id}`
+//! until it is done:
+//!
+//! ```json
+//! {"id": "job-1", "status": "ready", "url": "https://cdn.example.com/ad/index.m3u8"}
+//! ```
+//!
+//! Package URLs are cached per creative ID. Until its package is ready, a
+//! creative is left out of the breaks it is decided for, which the next
+//! ads or slate fill instead. Failed jobs are retried after
+//! [`RETRY_AFTER`].
+
+use crate::metrics;
+use dashmap::DashMap;
+use dashmap::mapref::entry::Entry;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+/// Time budget of one request to the transcode service
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a failed creative is left out before it is submitted again
+pub const RETRY_AFTER: Duration = Duration::from_secs(300);
+
+/// How long a package URL is reused
+const PACKAGE_TTL: Duration = Duration::from_secs(24 * 3600);
+
+/// Maximum number of creatives tracked
+const MAX_PACKAGES: usize = 10_000;
+
+/// Body of a job creation request
+#[derive(Debug, Clone, Serialize)]
+pub struct JobRequest<'a> {
+    /// Universal Ad ID, creative ID or media URL of the creative
+    pub creative_id: &'a str,
+    /// Media file to transcode
+    pub url: &'a str,
+}
+
+/// A transcode job as reported by the service
+#[derive(Debug, Clone, Deserialize)]
+pub struct Job {
+    /// Job ID polled at `{AD_NORMALIZER_URL}/{id}`; may be left out of
+    /// poll responses
+    #[serde(default)]
+    pub id: String,
+    pub status: JobStatus,
+    /// HLS/CMAF package URL, once `ready`
+    #[serde(default)]
+    pub url: Option<String>,
+}
+
+/// State of a transcode job
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Ready,
+    Failed,
+    /// Queued, processing, or any other state: still running
+    #[serde(other)]
+    Pending,
+}
+
+/// Whether a creative can be played normalized
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Package {
+    /// Package URL to play instead of the creative's media file
+    Ready(String),
+    /// Submitted and not done yet
+    Pending,
+    /// The transcoder failed it; retried after [`RETRY_AFTER`]
+    Failed,
+}
+
+struct PackageEntry {
+    package: Package,
+    updated_at: Instant,
+}
+
+/// Client of a creative transcode service, caching package URLs per
+/// creative ID
+#[derive(Clone)]
+pub struct AdNormalizer {
+    /// Job endpoint, without a trailing slash
+    endpoint: String,
+    http_client: Client,
+    /// Time between job status polls
+    poll_interval: Duration,
+    /// Time after which a job that is not done counts as failed
+    job_timeout: Duration,
+    packages: Arc<DashMap<String, PackageEntry>>,
+}
+
+impl AdNormalizer {
+    /// Create a new AdNormalizer
+    ///
+    /// # Arguments
+    /// * `endpoint` - URL jobs are POSTed to and polled under
+    /// * `http_client` - Shared HTTP client
+    pub fn new(endpoint: String, http_client: Client) -> Self {
+        Self {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            http_client,
+            poll_interval: Duration::from_secs(2),
+            job_timeout: Duration::from_secs(600),
+            packages: Arc::new(DashMap::new()),
+        }
+    }
+
+    /// Set the time between job status polls
+    pub fn with_poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Set the time after which an unfinished job counts as failed
+    pub fn with_job_timeout(mut self, timeout: Duration) -> Self {
+        self.job_timeout = timeout;
+        self
+    }
+
+    /// The normalized package of a creative
+    ///
+    /// A creative seen for the first time, or failed more than
+    /// [`RETRY_AFTER`] ago, is submitted in the background and reported
+    /// [`Package::Pending`].
+    pub fn package(&self, creative_id: &str, media_url: &str) -> Package {
+        if !self.packages.contains_key(creative_id) && self.packages.len() >= MAX_PACKAGES {
+            warn!(
+                "AdNormalizer: {} creatives tracked, not submitting {}",
+                MAX_PACKAGES, creative_id
+            );
+            return Package::Pending;
+        }
+
+        let package = match self.packages.entry(creative_id.to_string()) {
+            Entry::Occupied(entry)
+                if entry.get().package != Package::Failed
+                    || entry.get().updated_at.elapsed() < RETRY_AFTER =>
+            {
+                entry.get().package.clone()
+            }
+            entry => {
+                entry.insert(PackageEntry {
+                    package: Package::Pending,
+                    updated_at: Instant::now(),
+                });
+                self.submit(creative_id.to_string(), media_url.to_string());
+                Package::Pending
+            }
+        };
+        if package == Package::Pending {
+            metrics::record_normalization("pending");
+        }
+        package
+    }
+
+    /// Evict expired packages and failures due for a retry
+    pub fn cleanup(&self) {
+        self.packages.retain(|_, entry| match entry.package {
+            Package::Ready(_) => entry.updated_at.elapsed() < PACKAGE_TTL,
+            Package::Pending => true,
+            Package::Failed => entry.updated_at.elapsed() < RETRY_AFTER,
+        });
+    }
+
+    /// Run a creative's job in the background and record its outcome
+    fn submit(&self, creative_id: String, media_url: String) {
+        let normalizer = self.clone();
+        tokio::spawn(async move {
+            let package = normalizer.run_job(&creative_id, &media_url).await;
+            metrics::record_normalization(match package {
+                Package::Ready(_) => "ready",
+                _ => "failed",
+            });
+            normalizer.packages.insert(
+                creative_id,
+                PackageEntry {
+                    package,
+                    updated_at: Instant::now(),
+                },
+            );
+        });
+    }
+
+    /// Create a job and poll it until it is done
+    async fn run_job(&self, creative_id: &str, media_url: &str) -> Package {
+        let deadline = Instant::now() + self.job_timeout;
+        let mut job = match self.create_job(creative_id, media_url).await {
+            Ok(job) => job,
+            Err(e) => {
+                warn!(
+                    "AdNormalizer: Submitting creative {} failed: {}",
+                    creative_id, e
+                );
+                return Package::Failed;
+            }
+        };
+        metrics::record_normalization("submitted");
+        let job_id = job.id.clone();
+        info!(
+            "AdNormalizer: Creative {} submitted as job {}",
+            creative_id, job_id
+        );
+
+        loop {
+            match job.status {
+                JobStatus::Ready => {
+                    return match job.url {
+                        Some(url) => {
+                            info!(
+                                "AdNormalizer: Creative {} normalized to {}",
+                                creative_id, url
+                            );
+                            Package::Ready(url)
+                        }
+                        None => {
+                            warn!("AdNormalizer: Job {} is ready without a URL", job_id);
+                            Package::Failed
+                        }
+                    };
+                }
+                JobStatus::Failed => {
+                    warn!(
+                        "AdNormalizer: Job {} for creative {} failed",
+                        job_id, creative_id
+                    );
+                    return Package::Failed;
+                }
+                JobStatus::Pending => {}
+            }
+            if job_id.is_empty() || Instant::now() >= deadline {
+                warn!(
+                    "AdNormalizer: Job {:?} for creative {} did not finish",
+                    job_id, creative_id
+                );
+                return Package::Failed;
+            }
+
+            tokio::time::sleep(self.poll_interval).await;
+            // A failed poll is retried on the next interval
+            match self.poll_job(&job_id).await {
+                Ok(next) => job = next,
+                Err(e) => debug!("AdNormalizer: Polling job {} failed: {}", job_id, e),
+            }
+        }
+    }
+
+    async fn create_job(&self, creative_id: &str, media_url: &str) -> reqwest::Result<Job> {
+        self.http_client
+            .post(&self.endpoint)
+            .json(&JobRequest {
+                creative_id,
+                url: media_url,
+            })
+            .timeout(REQUEST_TIMEOUT)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+
+    async fn poll_job(&self, job_id: &str) -> reqwest::Result<Job> {
+        self.http_client
+            .get(format!("{}/{}", self.endpoint, job_id))
+            .timeout(REQUEST_TIMEOUT)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+}
+
+impl std::fmt::Debug for AdNormalizer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AdNormalizer")
+            .field("endpoint", &self.endpoint)
+            .field("poll_interval", &self.poll_interval)
+            .field("packages", &self.packages.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    async fn wait_for(normalizer: &AdNormalizer, creative_id: &str, url: &str) -> Package {
+        for _ in 0..100 {
+            match normalizer.package(creative_id, url) {
+                Package::Pending => tokio::time::sleep(Duration::from_millis(10)).await,
+                package => return package,
+            }
+        }
+        Package::Pending
+    }
+
+    #[tokio::test]
+    async fn submits_once_and_polls_until_ready() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/jobs"))
+            .and(body_json(serde_json::json!({
+                "creative_id": "ad-id.org:A",
+                "url": "https://cdn.example.com/a.mp4"
+            })))
+            .respond_with(
+                ResponseTemplate::new(201)
+                    .set_body_json(serde_json::json!({"id": "job-1", "status": "queued"})),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/jobs/job-1"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({"status": "transcoding"})),
+            )
+            .up_to_n_times(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/jobs/job-1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "id": "job-1",
+                "status": "ready",
+                "url": "https://cdn.example.com/a/index.m3u8"
+            })))
+            .mount(&server)
+            .await;
+
+        let normalizer = AdNormalizer::new(format!("{}/jobs/", server.uri()), Client::new())
+            .with_poll_interval(Duration::from_millis(5));
+        let url = "https://cdn.example.com/a.mp4";
+        assert_eq!(normalizer.package("ad-id.org:A", url), Package::Pending);
+        assert_eq!(normalizer.package("ad-id.org:A", url), Package::Pending);
+        assert_eq!(
+            wait_for(&normalizer, "ad-id.org:A", url).await,
+            Package::Ready("https://cdn.example.com/a/index.m3u8".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_jobs_are_retried_later() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/jobs"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({"id": "job-2", "status": "failed"})),
+            )
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let normalizer = AdNormalizer::new(format!("{}/jobs", server.uri()), Client::new());
+        let url = "https://cdn.example.com/b.mp4";
+        normalizer.package("creative:B", url);
+        assert_eq!(
+            wait_for(&normalizer, "creative:B", url).await,
+            Package::Failed
+        );
+
+        // Not resubmitted until the failure is old enough
+        assert_eq!(normalizer.package("creative:B", url), Package::Failed);
+        normalizer
+            .packages
+            .get_mut("creative:B")
+            .unwrap()
+            .updated_at -= RETRY_AFTER;
+        assert_eq!(normalizer.package("creative:B", url), Package::Pending);
+        assert_eq!(
+            wait_for(&normalizer, "creative:B", url).await,
+            Package::Failed
+        );
+    }
+
+    #[tokio::test]
+    async fn unreachable_service_fails_the_creative() {
+        let normalizer = AdNormalizer::new("http://127.0.0.1:1/jobs".to_string(), Client::new());
+        let url = "https://cdn.example.com/c.mp4";
+        normalizer.package("creative:C", url);
+        assert_eq!(
+            wait_for(&normalizer, "creative:C", url).await,
+            Package::Failed
+        );
+
+        normalizer
+            .packages
+            .get_mut("creative:C")
+            .unwrap()
+            .updated_at -= RETRY_AFTER;
+        normalizer.cleanup();
+        assert!(normalizer.packages.is_empty());
+    }
+}
@@ -0,0 +1,699 @@
+//! OpenRTB 2.6 bidder ad provider
+//!
+//! [`OpenRtbAdProvider`] runs a first-price auction for every break. It
+//! posts an OpenRTB 2.6 bid request offering one video pod (`poddur`,
+//! `maxseq`) to each configured SSP or bidder endpoint, with the device,
+//! user and regulation fields filled from the viewer's
+//! [`AdRequestContext`]. The highest bids that fit the pod win.
+//!
+//! A winning bid's VAST markup (`adm`), or the VAST its win notice URL
+//! (`nurl`) returns when it carries no markup, goes through the VAST
+//! provider's wrapper resolution and pod fitting, so segments, slate and
+//! tracking work as for [`VastAdProvider`]. Win notices fire when a bid
+//! with markup wins; billing notices (`burl`) fire with the ad's
+//! impression. The `${AUCTION_*}` macros of notices and markup are filled
+//! in first.
+
+mod types;
+
+pub use types::{
+    Bid, BidRequest, BidResponse, Content, Device, Imp, Regs, SeatBid, Site, User, Video,
+};
+
+use crate::ad::conditioning::CreativePolicy;
+use crate::ad::context::{AdRequestContext, DeviceType};
+use crate::ad::frequency::FrequencyPolicy;
+use crate::ad::normalizer::AdNormalizer;
+use crate::ad::probe::CreativeProber;
+use crate::ad::provider::{AdCreative, AdProvider, AdSegment, ResolvedSegment};
+use crate::ad::schedule::BreakPosition;
+use crate::ad::slate::SlateProvider;
+use crate::ad::tracking;
+use crate::ad::vast::VmapAdSource;
+use crate::ad::vast_provider::{ResolvedVastCreative, VastAdProvider};
+use crate::metrics;
+use crate::session::SessionManager;
+use async_trait::async_trait;
+use axum::http::HeaderMap;
+use futures_util::future::join_all;
+use reqwest::{Client, StatusCode};
+use std::net::Ipv6Addr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+/// Header announcing the OpenRTB version of a bid request
+const OPENRTB_VERSION_HEADER: &str = "x-openrtb-version";
+/// OpenRTB version of the bid requests
+const OPENRTB_VERSION: &str = "2.6";
+/// ID of the pod impression in every bid request
+const IMP_ID: &str = "1";
+/// Creative types the stitcher can play
+const MIMES: &[&str] = &["application/x-mpegURL", "video/mp4"];
+/// VAST 2.0, 3.0 and 4.0, inline and wrapper
+const PROTOCOLS: &[u8] = &[2, 3, 5, 6, 7, 8];
+
+/// A bid with the response details its notice macros need
+#[derive(Debug, Clone)]
+struct PlacedBid {
+    bid: Bid,
+    seat: Option<String>,
+    response_id: Option<String>,
+    currency: String,
+}
+
+impl PlacedBid {
+    /// Fill the `${AUCTION_*}` macros of a notice URL or markup
+    fn expand(&self, template: &str, auction_id: &str) -> String {
+        template
+            .replace("${AUCTION_ID}", auction_id)
+            .replace(
+                "${AUCTION_BID_ID}",
+                self.response_id.as_deref().unwrap_or(&self.bid.id),
+            )
+            .replace("${AUCTION_IMP_ID}", &self.bid.impid)
+            .replace("${AUCTION_SEAT_ID}", self.seat.as_deref().unwrap_or(""))
+            .replace("${AUCTION_AD_ID}", self.bid.adid.as_deref().unwrap_or(""))
+            .replace("${AUCTION_PRICE}", &self.bid.price.to_string())
+            .replace("${AUCTION_CURRENCY}", &self.currency)
+    }
+}
+
+/// Pick the winning bids: highest price first, as long as their durations
+/// fit the pod and it has room for another ad
+fn select_winners(mut bids: Vec<PlacedBid>, poddur: u32, max_ads: u32) -> Vec<PlacedBid> {
+    bids.sort_by(|a, b| b.bid.price.total_cmp(&a.bid.price));
+    let mut filled = 0;
+    let mut winners = Vec::new();
+    for bid in bids {
+        if winners.len() >= max_ads as usize {
+            break;
+        }
+        // Bids without a duration are fitted once their VAST is resolved
+        let duration = bid.bid.dur.unwrap_or(0);
+        if filled + duration > poddur {
+            continue;
+        }
+        filled += duration;
+        winners.push(bid);
+    }
+    winners
+}
+
+/// Unique ID of a bid request
+fn next_auction_id() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    format!("{:x}-{}", nanos, COUNTER.fetch_add(1, Ordering::Relaxed))
+}
+
+/// OpenRTB 2.6 ad provider auctioning each break among bidders
+pub struct OpenRtbAdProvider {
+    /// Bidder endpoints receiving every bid request
+    endpoints: Vec<String>,
+    /// HTTP client for bid requests and win notices
+    http_client: Client,
+    /// Resolves the winning VAST and serves the stitched segments
+    vast: VastAdProvider,
+    /// Overall time budget of a break: auction and VAST resolution
+    decision_timeout: Duration,
+    /// Maximum number of ads in a pod (`maxseq`)
+    max_ads: u32,
+}
+
+impl OpenRtbAdProvider {
+    /// Create a new OpenRtbAdProvider
+    ///
+    /// # Arguments
+    /// * `endpoints` - Bidder endpoint URLs receiving the bid requests
+    /// * `http_client` - Shared HTTP client
+    pub fn new(endpoints: Vec<String>, http_client: Client) -> Self {
+        Self {
+            endpoints,
+            vast: VastAdProvider::new(String::new(), http_client.clone()),
+            http_client,
+            decision_timeout: Duration::from_millis(3000),
+            max_ads: 10,
+        }
+    }
+
+    /// Configure a slate provider for breaks the auction leaves unfilled
+    pub fn with_slate(mut self, slate: SlateProvider) -> Self {
+        self.vast = self.vast.with_slate(slate);
+        self
+    }
+
+    /// Enable or disable forwarding the viewer headers on VAST requests
+    /// made for winning bids
+    pub fn with_viewer_headers(mut self, forward: bool) -> Self {
+        self.vast = self.vast.with_viewer_headers(forward);
+        self
+    }
+
+    /// Enable or disable forwarding the viewer headers on win notices and
+    /// error beacons
+    pub fn with_beacon_headers(mut self, forward: bool) -> Self {
+        self.vast = self.vast.with_beacon_headers(forward);
+        self
+    }
+
+    /// Set the overall deadline of a break: the auction (`tmax`) and the
+    /// resolution of the winning VAST share it
+    pub fn with_decision_timeout(mut self, timeout: Duration) -> Self {
+        self.decision_timeout = timeout;
+        self.vast = self.vast.with_decision_timeout(timeout);
+        self
+    }
+
+    /// Cap and separate each session's ads by its history in `sessions`
+    pub fn with_frequency_policy(
+        mut self,
+        policy: FrequencyPolicy,
+        sessions: SessionManager,
+    ) -> Self {
+        self.vast = self.vast.with_frequency_policy(policy, sessions);
+        self
+    }
+
+    /// Set the rules the creatives of winning bids must meet
+    pub fn with_creative_policy(mut self, policy: CreativePolicy) -> Self {
+        self.vast = self.vast.with_creative_policy(policy);
+        self
+    }
+
+    /// Probe the media of winning creatives before stitching
+    pub fn with_prober(mut self, prober: CreativeProber) -> Self {
+        self.vast = self.vast.with_prober(prober);
+        self
+    }
+
+    /// Play non-HLS winning creatives through their normalized packages
+    pub fn with_normalizer(mut self, normalizer: AdNormalizer) -> Self {
+        self.vast = self.vast.with_normalizer(normalizer);
+        self
+    }
+
+    /// Set the maximum number of ads in a pod (default: 10)
+    pub fn with_max_ads(mut self, max_ads: u32) -> Self {
+        self.max_ads = max_ads.max(1);
+        self
+    }
+
+    /// Build the bid request offering a break of `duration` seconds
+    fn bid_request(
+        &self,
+        id: String,
+        duration: f32,
+        ctx: &AdRequestContext,
+        tmax: Duration,
+    ) -> BidRequest {
+        // Whole seconds, so winning bids never overfill the break
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let poddur = duration.max(0.0).floor() as u32;
+
+        let is_ipv6 = ctx
+            .client_ip
+            .as_deref()
+            .is_some_and(|ip| ip.parse::<Ipv6Addr>().is_ok());
+        let (ip, ipv6) = if is_ipv6 {
+            (None, ctx.client_ip.clone())
+        } else {
+            (ctx.client_ip.clone(), None)
+        };
+        let devicetype = match ctx.device_type {
+            DeviceType::Desktop => Some(2),
+            DeviceType::Ctv => Some(3),
+            DeviceType::Mobile => Some(4),
+            DeviceType::Tablet => Some(5),
+            DeviceType::Unknown => None,
+        };
+
+        let keywords: Vec<String> = ctx
+            .params
+            .iter()
+            .map(|(name, value)| format!("{}={}", name.strip_prefix("ad.").unwrap_or(name), value))
+            .collect();
+        let user = (ctx.gdpr_consent.is_some() || !keywords.is_empty()).then(|| User {
+            consent: ctx.gdpr_consent.clone(),
+            keywords: (!keywords.is_empty()).then(|| keywords.join(",")),
+        });
+        let regs = (ctx.gdpr.is_some() || ctx.us_privacy.is_some()).then(|| Regs {
+            gdpr: ctx.gdpr.map(u8::from),
+            us_privacy: ctx.us_privacy.clone(),
+        });
+
+        let content = &ctx.content;
+        let has_content = content.id.is_some()
+            || content.title.is_some()
+            || content.genre.is_some()
+            || content.series.is_some();
+
+        BidRequest {
+            imp: vec![Imp {
+                id: IMP_ID.to_string(),
+                video: Video {
+                    mimes: MIMES.iter().map(|m| m.to_string()).collect(),
+                    maxduration: poddur,
+                    protocols: PROTOCOLS.to_vec(),
+                    startdelay: -1,
+                    plcmt: 1,
+                    podid: format!("{id}-pod"),
+                    poddur,
+                    maxseq: self.max_ads,
+                },
+            }],
+            id,
+            site: Some(Site {
+                page: ctx.referer.clone(),
+                content: has_content.then(|| Content {
+                    id: content.id.clone(),
+                    title: content.title.clone(),
+                    genre: content.genre.clone(),
+                    series: content.series.clone(),
+                }),
+            }),
+            device: Device {
+                ua: ctx.user_agent.clone(),
+                ip,
+                ipv6,
+                devicetype,
+                lmt: u8::from(ctx.limit_ad_tracking),
+            },
+            user,
+            regs,
+            tmax: u64::try_from(tmax.as_millis()).unwrap_or(u64::MAX),
+            at: 1,
+            cur: vec!["USD".to_string()],
+        }
+    }
+
+    /// Send the bid request to one bidder and collect its bids for the pod
+    async fn request_bids(
+        &self,
+        endpoint: &str,
+        request: &BidRequest,
+        remaining: Duration,
+    ) -> Vec<PlacedBid> {
+        let response = match self
+            .http_client
+            .post(endpoint)
+            .header(OPENRTB_VERSION_HEADER, OPENRTB_VERSION)
+            .json(request)
+            .timeout(remaining)
+            .send()
+            .await
+        {
+            Ok(response) => response,
+            Err(e) => {
+                warn!(
+                    "OpenRtbAdProvider: Bid request to {} failed: {}",
+                    endpoint, e
+                );
+                metrics::record_openrtb_request(if e.is_timeout() { "timeout" } else { "error" });
+                return Vec::new();
+            }
+        };
+
+        let status = response.status();
+        if status == StatusCode::NO_CONTENT {
+            metrics::record_openrtb_request("nobid");
+            return Vec::new();
+        }
+        if !status.is_success() {
+            warn!("OpenRtbAdProvider: Bidder {} answered {}", endpoint, status);
+            metrics::record_openrtb_request("error");
+            return Vec::new();
+        }
+        let body: BidResponse = match response.json().await {
+            Ok(body) => body,
+            Err(e) => {
+                warn!(
+                    "OpenRtbAdProvider: Invalid bid response from {}: {}",
+                    endpoint, e
+                );
+                metrics::record_openrtb_request("error");
+                return Vec::new();
+            }
+        };
+
+        let currency = body.cur.unwrap_or_else(|| "USD".to_string());
+        let response_id = body.bidid;
+        let bids: Vec<PlacedBid> = body
+            .seatbid
+            .into_iter()
+            .flat_map(|seat| {
+                let name = seat.seat;
+                seat.bid.into_iter().map(move |bid| (bid, name.clone()))
+            })
+            .filter(|(bid, _)| bid.impid == IMP_ID && (bid.adm.is_some() || bid.nurl.is_some()))
+            .map(|(bid, seat)| PlacedBid {
+                bid,
+                seat,
+                response_id: response_id.clone(),
+                currency: currency.clone(),
+            })
+            .collect();
+
+        metrics::record_openrtb_request(if bids.is_empty() { "nobid" } else { "bid" });
+        bids
+    }
+
+    /// Run the auction for a break and resolve the winning bids' VAST
+    async fn decide(
+        &self,
+        duration: f32,
+        session_id: &str,
+        ctx: &AdRequestContext,
+    ) -> Vec<ResolvedVastCreative> {
+        let deadline = Instant::now() + self.decision_timeout;
+        let auction_id = next_auction_id();
+        let request = self.bid_request(auction_id.clone(), duration, ctx, self.decision_timeout);
+        info!(
+            "OpenRtbAdProvider: Auction {} for session {} ({}s pod, {} bidders)",
+            auction_id,
+            session_id,
+            request.imp[0].video.poddur,
+            self.endpoints.len()
+        );
+
+        let bids: Vec<PlacedBid> = join_all(
+            self.endpoints
+                .iter()
+                .map(|endpoint| self.request_bids(endpoint, &request, self.decision_timeout)),
+        )
+        .await
+        .into_iter()
+        .flatten()
+        .collect();
+        let winners = select_winners(bids, request.imp[0].video.poddur, self.max_ads);
+        if winners.is_empty() {
+            return Vec::new();
+        }
+
+        let headers = if self.vast.forward_beacon_headers {
+            ctx.viewer_headers()
+        } else {
+            HeaderMap::new()
+        };
+        let sources: Vec<VmapAdSource> = winners
+            .iter()
+            .filter_map(|winner| match (&winner.bid.adm, &winner.bid.nurl) {
+                (Some(adm), nurl) => {
+                    if let Some(nurl) = nurl {
+                        tracking::fire_beacon(
+                            self.http_client.clone(),
+                            &winner.expand(nurl, &auction_id),
+                            "win".to_string(),
+                            &ctx.macro_context(),
+                            &headers,
+                        );
+                    }
+                    Some(VmapAdSource::VastAdData(winner.expand(adm, &auction_id)))
+                }
+                // Without markup, the win notice returns the VAST
+                (None, Some(nurl)) => {
+                    Some(VmapAdSource::AdTagUri(winner.expand(nurl, &auction_id)))
+                }
+                (None, None) => None,
+            })
+            .collect();
+
+        let resolved = join_all(
+            sources
+                .iter()
+                .map(|source| self.vast.resolve_source(source, session_id, ctx, deadline)),
+        )
+        .await;
+
+        let mut creatives = Vec::new();
+        for (winner, result) in winners.iter().zip(resolved) {
+            let mut bid_creatives = result.unwrap_or_default();
+            // The bid is billed once, with its first ad's impression
+            if let (Some(burl), Some(first)) = (&winner.bid.burl, bid_creatives.first_mut()) {
+                first.impression_urls.push(winner.expand(burl, &auction_id));
+            }
+            creatives.extend(bid_creatives);
+        }
+        creatives
+    }
+}
+
+#[async_trait]
+impl AdProvider for OpenRtbAdProvider {
+    async fn get_ad_segments(
+        &self,
+        duration: f32,
+        session_id: &str,
+        ctx: &AdRequestContext,
+    ) -> Vec<AdSegment> {
+        let creatives = self.decide(duration, session_id, ctx).await;
+        let creatives = self.vast.prepare_creatives(creatives, session_id).await;
+        if creatives.is_empty() {
+            warn!(
+                "OpenRtbAdProvider: No winning ads for session {} ({}s break)",
+                session_id, duration
+            );
+        }
+        self.vast
+            .stitch_session_pod(
+                &creatives,
+                duration,
+                Some(BreakPosition::Mid),
+                session_id,
+                ctx.break_key,
+            )
+            .await
+    }
+
+    async fn get_ad_creatives(
+        &self,
+        duration: f32,
+        session_id: &str,
+        ctx: &AdRequestContext,
+    ) -> Vec<AdCreative> {
+        let creatives = self.decide(duration, session_id, ctx).await;
+        self.vast
+            .session_creatives(creatives, session_id, ctx.break_key)
+            .await
+    }
+
+    fn resolve_segment_url(&self, ad_name: &str, session_id: &str) -> Option<String> {
+        self.vast.resolve_segment_url(ad_name, session_id)
+    }
+
+    fn segment_url(
+        &self,
+        segment_name: &str,
+        session_id: &str,
+        base_url: &str,
+        break_idx: usize,
+        segment_idx: usize,
+    ) -> String {
+        self.vast
+            .segment_url(segment_name, session_id, base_url, break_idx, segment_idx)
+    }
+
+    fn resolve_segment_with_tracking(
+        &self,
+        ad_name: &str,
+        session_id: &str,
+    ) -> Option<ResolvedSegment> {
+        self.vast.resolve_segment_with_tracking(ad_name, session_id)
+    }
+
+    fn cleanup_cache(&self) {
+        self.vast.cleanup_cache();
+    }
+
+    fn slate(&self) -> Option<&SlateProvider> {
+        self.vast.slate()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn placed(id: &str, price: f64, dur: Option<u32>) -> PlacedBid {
+        PlacedBid {
+            bid: Bid {
+                id: id.to_string(),
+                impid: IMP_ID.to_string(),
+                price,
+                adm: Some("<VAST/>".to_string()),
+                dur,
+                ..Bid::default()
+            },
+            seat: Some("seat-1".to_string()),
+            response_id: None,
+            currency: "USD".to_string(),
+        }
+    }
+
+    #[test]
+    fn winners_are_highest_bids_that_fit_the_pod() {
+        let bids = vec![
+            placed("cheap", 1.0, Some(10)),
+            placed("long", 5.0, Some(25)),
+            placed("top", 4.0, Some(15)),
+            placed("mid", 2.0, Some(15)),
+            placed("short", 1.5, Some(5)),
+        ];
+        let winners = select_winners(bids.clone(), 30, 10);
+        let ids: Vec<&str> = winners.iter().map(|w| w.bid.id.as_str()).collect();
+        assert_eq!(ids, vec!["long", "short"]);
+
+        let winners = select_winners(bids, 30, 1);
+        assert_eq!(winners.len(), 1);
+        assert_eq!(winners[0].bid.id, "long");
+    }
+
+    #[test]
+    fn notice_macros_are_substituted() {
+        let bid = placed("b1", 2.5, None);
+        assert_eq!(
+            bid.expand(
+                "http://win/?p=${AUCTION_PRICE}&a=${AUCTION_ID}&b=${AUCTION_BID_ID}&s=${AUCTION_SEAT_ID}&c=${AUCTION_CURRENCY}",
+                "auction-1"
+            ),
+            "http://win/?p=2.5&a=auction-1&b=b1&s=seat-1&c=USD"
+        );
+    }
+
+    #[test]
+    fn bid_request_carries_pod_device_and_user() {
+        let provider = OpenRtbAdProvider::new(vec![], Client::new()).with_max_ads(4);
+        let ctx = AdRequestContext {
+            client_ip: Some("2001:db8::1".to_string()),
+            user_agent: Some("Roku/DVP-9.10".to_string()),
+            referer: Some("https://example.com/watch".to_string()),
+            device_type: DeviceType::Ctv,
+            params: BTreeMap::from([("ad.genre".to_string(), "news".to_string())]),
+            gdpr: Some(true),
+            gdpr_consent: Some("CONSENT".to_string()),
+            limit_ad_tracking: true,
+            ..AdRequestContext::default()
+        };
+        let request =
+            provider.bid_request("a1".to_string(), 30.7, &ctx, Duration::from_millis(800));
+        let json = serde_json::to_value(&request).unwrap();
+
+        assert_eq!(json["tmax"], 800);
+        let video = &json["imp"][0]["video"];
+        assert_eq!(video["poddur"], 30);
+        assert_eq!(video["maxseq"], 4);
+        assert_eq!(video["startdelay"], -1);
+        assert_eq!(json["device"]["ipv6"], "2001:db8::1");
+        assert!(json["device"].get("ip").is_none());
+        assert_eq!(json["device"]["devicetype"], 3);
+        assert_eq!(json["device"]["lmt"], 1);
+        assert_eq!(json["user"]["consent"], "CONSENT");
+        assert_eq!(json["user"]["keywords"], "genre=news");
+        assert_eq!(json["regs"]["gdpr"], 1);
+        assert_eq!(json["site"]["page"], "https://example.com/watch");
+    }
+
+    #[tokio::test]
+    async fn auction_winners_play_with_win_and_billing_notices() {
+        use wiremock::matchers::{body_partial_json, header, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        let uri = server.uri();
+        let inline = |id: &str| {
+            format!(
+                r#"<VAST version="3.0"><Ad id="{id}"><InLine><AdSystem>T</AdSystem>
+<Impression>{uri}/imp/{id}?p=${{AUCTION_PRICE}}</Impression>
+<Creatives><Creative><Linear>
+<Duration>00:00:10</Duration>
+<MediaFiles><MediaFile delivery="streaming" type="application/x-mpegURL">{uri}/{id}.m3u8</MediaFile></MediaFiles>
+</Linear></Creative></Creatives></InLine></Ad></VAST>"#
+            )
+        };
+        let response = serde_json::json!({
+            "id": "r1",
+            "cur": "USD",
+            "seatbid": [{"seat": "s1", "bid": [
+                {"id": "low", "impid": "1", "price": 1.0, "dur": 10,
+                 "nurl": format!("{uri}/vast/low")},
+                {"id": "high", "impid": "1", "price": 3.0, "dur": 10,
+                 "adm": inline("high"),
+                 "nurl": format!("{uri}/win/high?p=${{AUCTION_PRICE}}"),
+                 "burl": format!("{uri}/bill/high?p=${{AUCTION_PRICE}}")},
+                {"id": "other-imp", "impid": "2", "price": 9.0, "adm": inline("x")}
+            ]}]
+        });
+        Mock::given(method("POST"))
+            .and(path("/bid"))
+            .and(header("x-openrtb-version", "2.6"))
+            .and(body_partial_json(serde_json::json!({
+                "imp": [{"video": {"poddur": 20}}],
+                "device": {"ua": "TestPlayer/1.0"}
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(response))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/nobid"))
+            .respond_with(ResponseTemplate::new(204))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/vast/low"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(inline("low")))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/win/high"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+
+        let provider = OpenRtbAdProvider::new(
+            vec![format!("{uri}/bid"), format!("{uri}/nobid")],
+            Client::new(),
+        );
+        let ctx = AdRequestContext {
+            user_agent: Some("TestPlayer/1.0".to_string()),
+            ..AdRequestContext::default()
+        };
+        let segments = provider.get_ad_segments(20.0, "session-rtb", &ctx).await;
+
+        let urls: Vec<String> = segments
+            .iter()
+            .map(|s| provider.resolve_segment_url(&s.uri, "session-rtb").unwrap())
+            .collect();
+        assert_eq!(
+            urls,
+            vec![format!("{uri}/high.m3u8"), format!("{uri}/low.m3u8")]
+        );
+        let impressions = &segments[0].tracking.as_ref().unwrap().impression_urls;
+        assert_eq!(
+            impressions,
+            &vec![
+                format!("{uri}/imp/high?p=3"),
+                format!("{uri}/bill/high?p=3")
+            ]
+        );
+
+        // The win notice of the markup bid fires in the background
+        let mut won = false;
+        for _ in 0..50 {
+            let requests = server.received_requests().await.unwrap_or_default();
+            if requests
+                .iter()
+                .any(|r| r.url.path() == "/win/high" && r.url.query() == Some("p=3"))
+            {
+                won = true;
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(won, "win notice should fire with the clearing price");
+    }
+}
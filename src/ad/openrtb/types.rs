@@ -0,0 +1,156 @@
+//! OpenRTB 2.6 objects
+//!
+//! Only the fields the stitcher sends or reads are modelled. Unknown
+//! response fields are ignored.
+
+use serde::{Deserialize, Serialize};
+
+/// OpenRTB `BidRequest`
+#[derive(Debug, Clone, Serialize)]
+pub struct BidRequest {
+    /// Unique ID of the request, `${AUCTION_ID}`
+    pub id: String,
+    /// Impressions on offer; the stitcher offers one video pod
+    pub imp: Vec<Imp>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub site: Option<Site>,
+    pub device: Device,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<User>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub regs: Option<Regs>,
+    /// Time allowed for the bid response in milliseconds
+    pub tmax: u64,
+    /// Auction type: 1 = first price
+    pub at: u8,
+    /// Allowed bid currencies
+    pub cur: Vec<String>,
+}
+
+/// OpenRTB `Imp`
+#[derive(Debug, Clone, Serialize)]
+pub struct Imp {
+    pub id: String,
+    pub video: Video,
+}
+
+/// OpenRTB `Video`, describing the ad pod of a break
+#[derive(Debug, Clone, Serialize)]
+pub struct Video {
+    /// Supported creative MIME types
+    pub mimes: Vec<String>,
+    /// Maximum duration of a single ad in seconds
+    pub maxduration: u32,
+    /// Supported VAST versions (AdCOM creative subtypes)
+    pub protocols: Vec<u8>,
+    /// Start delay: 0 = pre-roll, -1 = mid-roll, -2 = post-roll
+    pub startdelay: i32,
+    /// Placement: 1 = instream
+    pub plcmt: u8,
+    /// Pod ID, shared by the impressions of one pod
+    pub podid: String,
+    /// Total pod duration in seconds (dynamic pod)
+    pub poddur: u32,
+    /// Maximum number of ads in the pod
+    pub maxseq: u32,
+}
+
+/// OpenRTB `Site`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Site {
+    /// Page the viewer watches on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<Content>,
+}
+
+/// OpenRTB `Content`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Content {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub genre: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub series: Option<String>,
+}
+
+/// OpenRTB `Device`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Device {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ua: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ip: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ipv6: Option<String>,
+    /// AdCOM device type: 2 = PC, 3 = connected TV, 4 = phone, 5 = tablet
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub devicetype: Option<u8>,
+    /// Limit ad tracking: 1 = opted out
+    pub lmt: u8,
+}
+
+/// OpenRTB `User`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct User {
+    /// IAB TCF consent string
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub consent: Option<String>,
+    /// Comma-separated targeting keywords (`name=value`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keywords: Option<String>,
+}
+
+/// OpenRTB `Regs`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Regs {
+    /// Whether GDPR applies: 0 = no, 1 = yes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gdpr: Option<u8>,
+    /// IAB CCPA string
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub us_privacy: Option<String>,
+}
+
+/// OpenRTB `BidResponse`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct BidResponse {
+    pub id: String,
+    pub seatbid: Vec<SeatBid>,
+    /// Bidder-generated response ID, `${AUCTION_BID_ID}`
+    pub bidid: Option<String>,
+    pub cur: Option<String>,
+}
+
+/// OpenRTB `SeatBid`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SeatBid {
+    pub bid: Vec<Bid>,
+    pub seat: Option<String>,
+}
+
+/// OpenRTB `Bid`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Bid {
+    pub id: String,
+    pub impid: String,
+    pub price: f64,
+    /// VAST markup
+    pub adm: Option<String>,
+    /// Win notice URL; returns the VAST markup when `adm` is absent
+    pub nurl: Option<String>,
+    /// Billing notice URL, fired when the ad's impression is counted
+    pub burl: Option<String>,
+    pub adid: Option<String>,
+    pub adomain: Vec<String>,
+    pub crid: Option<String>,
+    /// Creative duration in seconds
+    pub dur: Option<u32>,
+}
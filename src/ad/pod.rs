@@ -0,0 +1,279 @@
+//! Fit-to-break ad pod assembly
+//!
+//! Ad servers rarely return a pod that matches the break exactly. Inserting
+//! a short pod drops more content than it replaces and shifts the live
+//! timeline; a long pod overruns the break. [`fit_pod`] selects the
+//! creatives that best fill the break without overrunning it, drops the
+//! rest, and reports the remainder for the caller to pad with slate.
+//! Creatives are never shortened: a segment advertised shorter than its
+//! media would desynchronise the player.
+
+use tracing::debug;
+
+/// Pods up to this size are searched exhaustively; larger pods are filled
+/// greedily in ad server order
+const MAX_EXHAUSTIVE_CREATIVES: usize = 10;
+
+/// Gaps and overruns below this are rounding noise, in seconds
+const FILL_EPSILON: f32 = 0.01;
+
+/// One creative placed in the pod
+#[derive(Debug, Clone, PartialEq)]
+pub struct PodSlot {
+    /// Index of the creative in the ad server response
+    pub index: usize,
+    /// Duration of the creative
+    pub duration: f32,
+}
+
+/// Creatives chosen for a break and the time left to pad
+#[derive(Debug, Clone, PartialEq)]
+pub struct PodPlan {
+    /// Creatives to play, in order
+    pub slots: Vec<PodSlot>,
+    /// Seconds of the break not covered by creatives
+    pub remainder: f32,
+}
+
+impl PodPlan {
+    /// Total duration of the selected creatives
+    pub fn filled(&self) -> f32 {
+        self.slots.iter().map(|s| s.duration).sum()
+    }
+}
+
+/// Fit creatives of the given durations into a break
+///
+/// Picks the subset of creatives that fills the most of the break without
+/// overrunning it, keeping ad server order; ties prefer earlier creatives.
+/// A `sequenced` pod (VAST 4 `sequence`) is played as the ad server
+/// ordered it, so only its tail is dropped. Creatives without a positive
+/// duration are never selected.
+pub fn fit_pod(durations: &[f32], break_duration: f32, sequenced: bool) -> PodPlan {
+    if break_duration <= 0.0 {
+        return PodPlan {
+            slots: Vec::new(),
+            remainder: 0.0,
+        };
+    }
+
+    let selected = if sequenced {
+        leading_subset(durations, break_duration)
+    } else if durations.len() <= MAX_EXHAUSTIVE_CREATIVES {
+        best_subset(durations, break_duration)
+    } else {
+        greedy_subset(durations, break_duration)
+    };
+    if selected.len() < durations.len() {
+        debug!(
+            "Selected creatives {:?} of {} for a {}s break",
+            selected,
+            durations.len(),
+            break_duration
+        );
+    }
+
+    let slots: Vec<PodSlot> = selected
+        .iter()
+        .map(|&index| PodSlot {
+            index,
+            duration: durations[index],
+        })
+        .collect();
+
+    let remainder = break_duration - slots.iter().map(|s| s.duration).sum::<f32>();
+    PodPlan {
+        slots,
+        remainder: if remainder > FILL_EPSILON {
+            remainder
+        } else {
+            0.0
+        },
+    }
+}
+
+/// Return `true` if creatives with `total` duration fit the break
+fn fits(total: f32, break_duration: f32) -> bool {
+    total <= break_duration + FILL_EPSILON
+}
+
+/// Exhaustive search for the best-filling subset, in index order
+///
+/// Only called with at most [`MAX_EXHAUSTIVE_CREATIVES`] creatives.
+fn best_subset(durations: &[f32], break_duration: f32) -> Vec<usize> {
+    let mut best = 0_u32;
+    let mut best_fill = 0.0_f32;
+
+    'masks: for mask in 1_u32..(1 << durations.len()) {
+        let mut total = 0.0_f32;
+        for (i, &duration) in durations.iter().enumerate() {
+            if mask & (1 << i) != 0 {
+                if duration <= 0.0 {
+                    continue 'masks;
+                }
+                total += duration;
+            }
+        }
+        if !fits(total, break_duration) {
+            continue;
+        }
+
+        let fill = total.min(break_duration);
+        // On a tie, the subset holding the earliest creative the two
+        // differ in wins
+        let diff = mask ^ best;
+        let better = if (fill - best_fill).abs() > FILL_EPSILON {
+            fill > best_fill
+        } else {
+            mask & diff & diff.wrapping_neg() != 0
+        };
+        if better {
+            best = mask;
+            best_fill = fill;
+        }
+    }
+
+    (0..durations.len())
+        .filter(|i| best & (1 << i) != 0)
+        .collect()
+}
+
+/// Take creatives in order while they fit
+fn greedy_subset(durations: &[f32], break_duration: f32) -> Vec<usize> {
+    let mut total = 0.0_f32;
+    let mut selected = Vec::new();
+    for (index, &duration) in durations.iter().enumerate() {
+        if total >= break_duration - FILL_EPSILON {
+            break;
+        }
+        if duration > 0.0 && fits(total + duration, break_duration) {
+            total += duration;
+            selected.push(index);
+        }
+    }
+    selected
+}
+
+/// Take creatives in order up to the first that does not fit
+fn leading_subset(durations: &[f32], break_duration: f32) -> Vec<usize> {
+    let mut total = 0.0_f32;
+    let mut selected = Vec::new();
+    for (index, &duration) in durations.iter().enumerate() {
+        if duration <= 0.0 {
+            continue;
+        }
+        if !fits(total + duration, break_duration) {
+            break;
+        }
+        total += duration;
+        selected.push(index);
+    }
+    selected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn indices(plan: &PodPlan) -> Vec<usize> {
+        plan.slots.iter().map(|s| s.index).collect()
+    }
+
+    #[test]
+    fn exact_fit_keeps_all_creatives() {
+        let plan = fit_pod(&[15.0, 15.0], 30.0, false);
+        assert_eq!(indices(&plan), vec![0, 1]);
+        assert_eq!(plan.remainder, 0.0);
+    }
+
+    #[test]
+    fn short_pod_reports_remainder() {
+        let plan = fit_pod(&[15.0, 7.0], 30.0, false);
+        assert_eq!(indices(&plan), vec![0, 1]);
+        assert!((plan.remainder - 8.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn long_pod_drops_overflowing_creative() {
+        let plan = fit_pod(&[20.0, 15.0, 10.0], 30.0, false);
+        // 20 + 10 fills the break exactly; 15 is dropped
+        assert_eq!(indices(&plan), vec![0, 2]);
+        assert_eq!(plan.remainder, 0.0);
+    }
+
+    #[test]
+    fn best_fill_beats_ad_server_order() {
+        let plan = fit_pod(&[25.0, 15.0, 15.0], 30.0, false);
+        assert_eq!(indices(&plan), vec![1, 2]);
+        assert_eq!(plan.filled(), 30.0);
+    }
+
+    #[test]
+    fn ties_prefer_earlier_creatives() {
+        let plan = fit_pod(&[15.0, 15.0, 15.0], 30.0, false);
+        assert_eq!(indices(&plan), vec![0, 1]);
+    }
+
+    #[test]
+    fn small_overrun_is_dropped_not_trimmed() {
+        let plan = fit_pod(&[15.0, 15.5], 30.0, false);
+        assert_eq!(indices(&plan), vec![1]);
+        assert_eq!(plan.slots[0].duration, 15.5);
+        assert!((plan.remainder - 14.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn large_overrun_is_dropped() {
+        let plan = fit_pod(&[15.0, 20.0], 30.0, false);
+        // 35s overruns by 5s: keep the single creative that fills the most
+        assert_eq!(indices(&plan), vec![1]);
+        assert!((plan.remainder - 10.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn overrunning_creative_is_skipped() {
+        let plan = fit_pod(&[15.0, 15.5, 15.0], 30.0, false);
+        assert_eq!(indices(&plan), vec![0, 2]);
+        assert_eq!(plan.remainder, 0.0);
+    }
+
+    #[test]
+    fn sequenced_pod_drops_only_its_tail() {
+        // 20 + 10 would fill the break, but the pod must play in sequence
+        let plan = fit_pod(&[20.0, 15.0, 10.0], 30.0, true);
+        assert_eq!(indices(&plan), vec![0]);
+        assert!((plan.remainder - 10.0).abs() < 1e-4);
+
+        let plan = fit_pod(&[10.0, 10.0, 10.0, 10.0], 30.0, true);
+        assert_eq!(indices(&plan), vec![0, 1, 2]);
+        assert_eq!(plan.remainder, 0.0);
+    }
+
+    #[test]
+    fn zero_duration_creatives_are_skipped() {
+        let plan = fit_pod(&[0.0, 10.0], 10.0, false);
+        assert_eq!(indices(&plan), vec![1]);
+    }
+
+    #[test]
+    fn empty_pod_is_all_remainder() {
+        let plan = fit_pod(&[], 30.0, false);
+        assert!(plan.slots.is_empty());
+        assert_eq!(plan.remainder, 30.0);
+    }
+
+    #[test]
+    fn pods_past_the_exhaustive_limit_fill_greedily() {
+        let durations = [20.0, 15.0, 10.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0];
+        let plan = fit_pod(&durations, 30.0, false);
+        assert_eq!(indices(&plan), vec![0, 2]);
+    }
+
+    #[test]
+    fn large_pod_fills_greedily() {
+        let durations = vec![5.0; 20];
+        let plan = fit_pod(&durations, 30.0, false);
+        assert_eq!(indices(&plan), (0..6).collect::<Vec<_>>());
+        assert_eq!(plan.remainder, 0.0);
+    }
+}
@@ -0,0 +1,452 @@
+//! Ad decisions made ahead of the break
+//!
+//! Ad breaks are often signalled before they start: an `EXT-X-DATERANGE`
+//! with a future `START-DATE`, or a break pushed to the channel schedule.
+//! Deciding such a break only when the playlist containing its first ad
+//! segment is stitched puts the ad server's latency on that request.
+//! [`AdPrefetcher`] decides upcoming breaks in the background instead,
+//! once they start within `AD_PREFETCH_SECS`; the stitcher then picks up
+//! the decision made (or still in flight) when the break arrives.
+//!
+//! Decisions are keyed by session (or shared decision ID) and wall-clock
+//! start, so prefetching needs `EXT-X-PROGRAM-DATE-TIME`. With
+//! `AD_PREFETCH_WARM=true` the ad segments of a prefetched decision are
+//! also fetched into the [`SegmentCache`].
+
+use crate::ad::context::AdRequestContext;
+use crate::ad::provider::{AdProvider, AdSegment};
+use crate::cache::{MAX_SEGMENT_BYTES, SegmentCache};
+use crate::http_retry::{RetryConfig, fetch_with_retry};
+use crate::metrics;
+use axum::body::Bytes;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use futures_util::StreamExt;
+use reqwest::header;
+use reqwest::{Client, Response};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::OnceCell;
+use tracing::{debug, info, warn};
+
+/// How long a prefetched decision is kept; matches the VAST provider's
+/// creative cache
+const DECISION_TTL: Duration = Duration::from_secs(300);
+
+/// Largest difference in seconds between a prefetched break's duration
+/// and the signalled break it is used for
+const DURATION_TOLERANCE: f32 = 0.5;
+
+/// A break signalled ahead of its start
+#[derive(Debug, Clone, PartialEq)]
+pub struct UpcomingBreak {
+    /// Wall-clock start of the break
+    pub start: DateTime<Utc>,
+    /// Duration in seconds
+    pub duration: f32,
+    /// SCTE-35 segmentation UPID, if the cue carried one
+    pub upid: Option<String>,
+}
+
+/// A decision made ahead of its break
+struct Prefetched {
+    start: DateTime<Utc>,
+    duration: f32,
+    segments: Arc<OnceCell<Vec<AdSegment>>>,
+    created_at: Instant,
+}
+
+/// Background ad decisioning for upcoming breaks
+#[derive(Clone)]
+pub struct AdPrefetcher {
+    provider: Arc<dyn AdProvider>,
+    http_client: Client,
+    /// How far ahead of its start a break is decided; zero disables
+    /// prefetching
+    lookahead: Duration,
+    /// Cache the ad segments of prefetched decisions are warmed into
+    segment_cache: Option<SegmentCache>,
+    /// Prefetched decisions by session or shared decision ID
+    decisions: Arc<DashMap<String, Vec<Prefetched>>>,
+}
+
+impl AdPrefetcher {
+    /// Create a prefetcher deciding breaks up to `lookahead` ahead
+    pub fn new(provider: Arc<dyn AdProvider>, http_client: Client, lookahead: Duration) -> Self {
+        Self {
+            provider,
+            http_client,
+            lookahead,
+            segment_cache: None,
+            decisions: Arc::new(DashMap::new()),
+        }
+    }
+
+    /// Warm the ad segments of prefetched decisions into `cache`
+    pub fn with_segment_cache(mut self, cache: SegmentCache) -> Self {
+        self.segment_cache = Some(cache);
+        self
+    }
+
+    /// Whether upcoming breaks are decided ahead of time
+    pub fn is_enabled(&self) -> bool {
+        !self.lookahead.is_zero()
+    }
+
+    /// Start deciding the upcoming breaks of `id` in the background
+    ///
+    /// Breaks starting later than the lookahead from now, and breaks
+    /// already prefetched, are skipped. `id` is the session ID or shared
+    /// decision ID the decision is made for, with `ctx` as its context.
+    pub fn prefetch(&self, id: &str, breaks: &[UpcomingBreak], ctx: &AdRequestContext) {
+        if !self.is_enabled() {
+            return;
+        }
+        let horizon = Utc::now()
+            + chrono::Duration::from_std(self.lookahead).unwrap_or(chrono::Duration::MAX);
+
+        for upcoming in breaks {
+            if upcoming.start > horizon {
+                continue;
+            }
+            let cell = {
+                let mut entries = self.decisions.entry(id.to_string()).or_default();
+                if entries
+                    .iter()
+                    .any(|p| matches(p, upcoming.start, upcoming.duration, 0.0))
+                {
+                    continue;
+                }
+                let cell = Arc::new(OnceCell::new());
+                entries.push(Prefetched {
+                    start: upcoming.start,
+                    duration: upcoming.duration,
+                    segments: cell.clone(),
+                    created_at: Instant::now(),
+                });
+                cell
+            };
+
+            info!(
+                "Prefetching ad decision for {} ({}s break at {})",
+                id, upcoming.duration, upcoming.start
+            );
+            metrics::record_ad_prefetch("started");
+
+            let prefetcher = self.clone();
+            let id = id.to_string();
+            let ctx = ctx.clone().with_upid(upcoming.upid.clone());
+            let duration = upcoming.duration;
+            tokio::spawn(async move {
+                let segments = cell
+                    .get_or_init(|| prefetcher.provider.get_ad_segments(duration, &id, &ctx))
+                    .await;
+                if let Some(cache) = &prefetcher.segment_cache {
+                    prefetcher.warm(cache, &id, segments).await;
+                }
+            });
+        }
+    }
+
+    /// Ad segments of a signalled break
+    ///
+    /// Uses the break's prefetched decision when one starts within
+    /// `tolerance` seconds of `start` with the same duration, waiting for
+    /// it when still in flight; otherwise decides with `decide`.
+    pub async fn segments<F>(
+        &self,
+        id: &str,
+        start: Option<DateTime<Utc>>,
+        duration: f32,
+        tolerance: f64,
+        decide: F,
+    ) -> Vec<AdSegment>
+    where
+        F: Future<Output = Vec<AdSegment>>,
+    {
+        let cell = start.and_then(|start| {
+            self.decisions
+                .get(id)?
+                .iter()
+                .find_map(|p| matches(p, start, duration, tolerance).then(|| p.segments.clone()))
+        });
+
+        match cell {
+            Some(cell) => {
+                let hit = cell.initialized();
+                metrics::record_ad_prefetch(if hit { "hit" } else { "waited" });
+                debug!("Using prefetched ad decision for {} (ready: {})", id, hit);
+                cell.get_or_init(|| decide).await.clone()
+            }
+            None => decide.await,
+        }
+    }
+
+    /// Fetch the ad segments of a decision into the segment cache
+    async fn warm(&self, cache: &SegmentCache, id: &str, segments: &[AdSegment]) {
+        for segment in segments {
+            let Some(url) = self.provider.resolve_segment_url(&segment.uri, id) else {
+                continue;
+            };
+            if cache.contains(&url) {
+                continue;
+            }
+            let response =
+                match fetch_with_retry(&self.http_client, &url, &RetryConfig::default()).await {
+                    Ok(response) => response,
+                    Err(e) => {
+                        warn!("Ad segment pre-warm failed for {}: {}", url, e);
+                        continue;
+                    }
+                };
+            if response
+                .content_length()
+                .is_some_and(|len| len > MAX_SEGMENT_BYTES as u64)
+            {
+                debug!("Ad segment {} too large to pre-warm", url);
+                continue;
+            }
+            let content_type = response
+                .headers()
+                .get(header::CONTENT_TYPE)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or("video/MP2T")
+                .to_string();
+            match read_capped(response).await {
+                Ok(Some(body)) => {
+                    if cache.insert(&url, body, content_type) {
+                        debug!("Pre-warmed ad segment {}", url);
+                    }
+                }
+                Ok(None) => debug!("Ad segment {} too large to pre-warm", url),
+                Err(e) => warn!("Ad segment pre-warm failed for {}: {}", url, e),
+            }
+        }
+    }
+
+    /// Evict expired decisions
+    pub fn cleanup(&self) {
+        self.decisions.retain(|_, entries| {
+            entries.retain(|p| p.created_at.elapsed() < DECISION_TTL);
+            !entries.is_empty()
+        });
+        if let Some(cache) = &self.segment_cache {
+            cache.cleanup();
+        }
+    }
+
+    /// Number of sessions with prefetched decisions
+    pub fn len(&self) -> usize {
+        self.decisions.len()
+    }
+
+    /// Whether no decision is prefetched
+    pub fn is_empty(&self) -> bool {
+        self.decisions.is_empty()
+    }
+}
+
+/// Read a response body of at most [`MAX_SEGMENT_BYTES`]
+///
+/// The body is streamed so that a response without `Content-Length` is
+/// abandoned as soon as it exceeds the limit; `None` when it does.
+async fn read_capped(response: Response) -> reqwest::Result<Option<Bytes>> {
+    let mut body = Vec::new();
+    let mut stream = response.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        body.extend_from_slice(&chunk?);
+        if body.len() > MAX_SEGMENT_BYTES {
+            return Ok(None);
+        }
+    }
+    Ok(Some(Bytes::from(body)))
+}
+
+/// Whether `prefetched` was made for a break at `start` lasting `duration`
+fn matches(prefetched: &Prefetched, start: DateTime<Utc>, duration: f32, tolerance: f64) -> bool {
+    let offset = (prefetched.start - start).num_milliseconds().abs() as f64 / 1000.0;
+    offset <= tolerance && (prefetched.duration - duration).abs() <= DURATION_TOLERANCE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ad::provider::StaticAdProvider;
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    /// Provider counting its decisions, answering after a short delay
+    struct CountingProvider {
+        calls: AtomicUsize,
+        base_url: String,
+    }
+
+    #[async_trait]
+    impl AdProvider for CountingProvider {
+        async fn get_ad_segments(
+            &self,
+            duration: f32,
+            _session_id: &str,
+            _ctx: &AdRequestContext,
+        ) -> Vec<AdSegment> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            vec![AdSegment {
+                uri: "break-0-seg-0.ts".to_string(),
+                duration,
+                tracking: None,
+            }]
+        }
+
+        fn resolve_segment_url(&self, ad_name: &str, _session_id: &str) -> Option<String> {
+            Some(format!("{}/{}", self.base_url, ad_name))
+        }
+    }
+
+    fn counting(base_url: &str) -> Arc<CountingProvider> {
+        Arc::new(CountingProvider {
+            calls: AtomicUsize::new(0),
+            base_url: base_url.to_string(),
+        })
+    }
+
+    fn upcoming(in_secs: i64, duration: f32) -> UpcomingBreak {
+        UpcomingBreak {
+            start: Utc::now() + chrono::Duration::seconds(in_secs),
+            duration,
+            upid: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn prefetched_decision_is_used_at_the_break() {
+        let provider = counting("http://ads.example.com");
+        let prefetcher =
+            AdPrefetcher::new(provider.clone(), Client::new(), Duration::from_secs(60));
+        let ctx = AdRequestContext::default();
+        let next = upcoming(10, 30.0);
+
+        prefetcher.prefetch("s1", std::slice::from_ref(&next), &ctx);
+        prefetcher.prefetch("s1", std::slice::from_ref(&next), &ctx);
+        tokio::task::yield_now().await;
+
+        // The break arrives 2s off its signalled start while the decision
+        // is in flight: the stitcher waits for it instead of deciding again
+        let start = next.start + chrono::Duration::seconds(2);
+        let segments = prefetcher
+            .segments("s1", Some(start), 30.0, 6.0, async { Vec::new() })
+            .await;
+        assert_eq!(segments.len(), 1);
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 1);
+
+        // Another session, or a break of another length, decides itself
+        let other = prefetcher
+            .segments("s2", Some(start), 30.0, 6.0, async { Vec::new() })
+            .await;
+        assert!(other.is_empty());
+        let longer = prefetcher
+            .segments("s1", Some(start), 60.0, 6.0, async { Vec::new() })
+            .await;
+        assert!(longer.is_empty());
+    }
+
+    #[tokio::test]
+    async fn breaks_beyond_the_lookahead_are_not_prefetched() {
+        let provider = counting("http://ads.example.com");
+        let prefetcher =
+            AdPrefetcher::new(provider.clone(), Client::new(), Duration::from_secs(30));
+        prefetcher.prefetch("s1", &[upcoming(120, 30.0)], &AdRequestContext::default());
+        assert!(prefetcher.is_empty());
+
+        let disabled = AdPrefetcher::new(provider.clone(), Client::new(), Duration::ZERO);
+        disabled.prefetch("s1", &[upcoming(10, 30.0)], &AdRequestContext::default());
+        assert!(disabled.is_empty());
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn prefetch_warms_ad_segments() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/break-0-seg-0.ts"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("content-type", "video/MP2T")
+                    .set_body_bytes(b"ad".to_vec()),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let cache = SegmentCache::new();
+        let prefetcher = AdPrefetcher::new(
+            counting(&server.uri()),
+            Client::new(),
+            Duration::from_secs(60),
+        )
+        .with_segment_cache(cache.clone());
+        prefetcher.prefetch("s1", &[upcoming(10, 30.0)], &AdRequestContext::default());
+
+        let url = format!("{}/break-0-seg-0.ts", server.uri());
+        for _ in 0..50 {
+            if cache.contains(&url) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(
+            cache.get(&url).map(|(body, _)| body),
+            Some(Bytes::from_static(b"ad"))
+        );
+    }
+
+    #[tokio::test]
+    async fn read_capped_stops_at_the_segment_limit() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/small.ts"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(b"ad".to_vec()))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/huge.ts"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(vec![0; MAX_SEGMENT_BYTES + 1]))
+            .mount(&server)
+            .await;
+        let client = Client::new();
+
+        let small = client
+            .get(format!("{}/small.ts", server.uri()))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(
+            read_capped(small).await.unwrap(),
+            Some(Bytes::from_static(b"ad"))
+        );
+        let huge = client
+            .get(format!("{}/huge.ts", server.uri()))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(read_capped(huge).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn cleanup_keeps_fresh_decisions() {
+        let prefetcher = AdPrefetcher::new(
+            Arc::new(StaticAdProvider::new(
+                "http://ads.example.com".to_string(),
+                1.0,
+            )),
+            Client::new(),
+            Duration::from_secs(60),
+        );
+        prefetcher.prefetch("s1", &[upcoming(10, 5.0)], &AdRequestContext::default());
+        prefetcher.cleanup();
+        assert_eq!(prefetcher.len(), 1);
+    }
+}
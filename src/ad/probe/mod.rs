@@ -0,0 +1,607 @@
+//! Creative media probing
+//!
+//! A VAST `<MediaFile>` only claims a codec, size and duration. Before a
+//! creative is stitched, [`CreativeProber`] reads what the media actually
+//! holds and [`CodecProfile`] checks it against what the content plays:
+//!
+//! - HLS: `CODECS` and `RESOLUTION` of the master playlist, the summed
+//!   `EXTINF` of its first rendition, and the init section or first
+//!   segment when the playlist declares no codecs
+//! - progressive files: the first [`PROBE_BYTES`] of the file, read as
+//!   MPEG-TS (PMT stream types) or MP4 (`moov` sample entries)
+//!
+//! Probes are cached per URL. A probe that does not finish within the
+//! timeout is treated as unknown, so a slow CDN never holds up a break.
+
+mod mp4;
+mod ts;
+
+use crate::error::{Result, RitcherError};
+use crate::server::MAX_MANIFEST_SIZE;
+use dashmap::DashMap;
+use m3u8_rs::{MediaPlaylist, Playlist};
+use reqwest::Client;
+use reqwest::header::RANGE;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::debug;
+use url::Url;
+
+/// Bytes read from the start of a file or segment
+pub const PROBE_BYTES: usize = 256 * 1024;
+
+/// Default time budget of one probe
+const PROBE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long a probe result is reused
+const CACHE_TTL: Duration = Duration::from_secs(3600);
+
+/// Maximum number of cached probe results
+const MAX_CACHE_SIZE: usize = 10_000;
+
+/// Seconds the probed duration may differ from the VAST duration
+const DURATION_TOLERANCE: f32 = 1.0;
+
+/// What probing found in a creative's media; `None` fields are unknown
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProbeResult {
+    /// RFC 6381 codec strings, e.g. `avc1.64001f`, `mp4a.40.2`
+    pub codecs: Vec<String>,
+    /// Largest video size as (width, height)
+    pub resolution: Option<(u32, u32)>,
+    /// Duration in seconds
+    pub duration: Option<f32>,
+}
+
+impl ProbeResult {
+    fn is_empty(&self) -> bool {
+        self.codecs.is_empty() && self.resolution.is_none() && self.duration.is_none()
+    }
+}
+
+/// How a creative's media compares with the content
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// Probed and fit to stitch
+    Match,
+    /// Could not be probed, or nothing could be read from it
+    Unknown,
+    /// Probed and not fit to stitch, with the reason
+    Mismatch(&'static str),
+}
+
+impl Verdict {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Verdict::Match => "match",
+            Verdict::Unknown => "unknown",
+            Verdict::Mismatch(_) => "mismatch",
+        }
+    }
+}
+
+/// Codecs and maximum size the content's players are known to decode
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodecProfile {
+    /// Codec families (`avc1`, `hvc1`, `mp4a`, ...); empty = any
+    pub codecs: Vec<String>,
+    /// Largest creative size as (width, height)
+    pub max_resolution: Option<(u32, u32)>,
+}
+
+impl Default for CodecProfile {
+    fn default() -> Self {
+        Self {
+            codecs: vec!["avc1".to_string(), "mp4a".to_string()],
+            max_resolution: None,
+        }
+    }
+}
+
+impl CodecProfile {
+    /// Compare a probe of a creative declared `duration` seconds long
+    pub fn verdict(&self, probe: Option<&ProbeResult>, duration: f32) -> Verdict {
+        let Some(probe) = probe.filter(|p| !p.is_empty()) else {
+            return Verdict::Unknown;
+        };
+        if !self.codecs.is_empty()
+            && let Some(codec) = probe.codecs.iter().find(|codec| {
+                let family = codec_family(codec);
+                !self.codecs.iter().any(|c| codec_family(c) == family)
+            })
+        {
+            debug!("Probe: codec {} is not in the content profile", codec);
+            return Verdict::Mismatch("codec");
+        }
+        if let (Some((max_w, max_h)), Some((w, h))) = (self.max_resolution, probe.resolution)
+            && (w > max_w || h > max_h)
+        {
+            return Verdict::Mismatch("resolution");
+        }
+        if probe
+            .duration
+            .is_some_and(|d| (d - duration).abs() > DURATION_TOLERANCE)
+        {
+            return Verdict::Mismatch("duration");
+        }
+        Verdict::Match
+    }
+}
+
+/// Codec family of an RFC 6381 codec string, with equivalent sample
+/// entries folded together (`avc3` as `avc1`, `hev1` as `hvc1`)
+fn codec_family(codec: &str) -> String {
+    let family = codec
+        .trim()
+        .split('.')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    match family.as_str() {
+        "avc3" => "avc1".to_string(),
+        "hev1" => "hvc1".to_string(),
+        _ => family,
+    }
+}
+
+/// A cached probe; `None` when the media could not be read
+struct CachedProbe {
+    result: Option<ProbeResult>,
+    inserted_at: Instant,
+}
+
+/// Probes creative media, caching results per URL
+#[derive(Clone)]
+pub struct CreativeProber {
+    http_client: Client,
+    profile: CodecProfile,
+    timeout: Duration,
+    cache: Arc<DashMap<String, CachedProbe>>,
+}
+
+impl CreativeProber {
+    pub fn new(http_client: Client, profile: CodecProfile) -> Self {
+        Self {
+            http_client,
+            profile,
+            timeout: PROBE_TIMEOUT,
+            cache: Arc::new(DashMap::new()),
+        }
+    }
+
+    /// Set the time budget of one probe
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// The profile probes are checked against
+    pub fn profile(&self) -> &CodecProfile {
+        &self.profile
+    }
+
+    /// Probe a creative and compare it with the profile
+    pub async fn verdict(&self, url: &str, is_hls: bool, duration: f32) -> Verdict {
+        let probe = self.probe(url, is_hls).await;
+        self.profile.verdict(probe.as_ref(), duration)
+    }
+
+    /// Probe the media at `url`; `None` when it could not be read in time
+    pub async fn probe(&self, url: &str, is_hls: bool) -> Option<ProbeResult> {
+        if let Some(cached) = self.cache.get(url)
+            && cached.inserted_at.elapsed() < CACHE_TTL
+        {
+            return cached.result.clone();
+        }
+
+        let probe = if is_hls {
+            tokio::time::timeout(self.timeout, self.probe_hls(url)).await
+        } else {
+            tokio::time::timeout(self.timeout, self.probe_file(url)).await
+        };
+        let result = match probe {
+            Ok(Ok(result)) => Some(result),
+            Ok(Err(e)) => {
+                debug!("Probe of {} failed: {}", url, e);
+                None
+            }
+            Err(_) => {
+                // Not cached: the CDN may answer in time on the next break
+                debug!("Probe of {} timed out", url);
+                return None;
+            }
+        };
+        if self.cache.len() < MAX_CACHE_SIZE {
+            self.cache.insert(
+                url.to_string(),
+                CachedProbe {
+                    result: result.clone(),
+                    inserted_at: Instant::now(),
+                },
+            );
+        }
+        result
+    }
+
+    /// Evict expired probe results
+    pub fn cleanup(&self) {
+        self.cache
+            .retain(|_, cached| cached.inserted_at.elapsed() < CACHE_TTL);
+    }
+
+    async fn probe_hls(&self, url: &str) -> Result<ProbeResult> {
+        let base = parse_url(url)?;
+        let body = self.fetch_playlist(&base).await?;
+        let playlist = m3u8_rs::parse_playlist_res(body.as_bytes()).map_err(|e| {
+            RitcherError::PlaylistParseError(format!("Failed to parse {}: {:?}", url, e))
+        })?;
+
+        match playlist {
+            Playlist::MediaPlaylist(media) => self.probe_hls_media(&media, &base).await,
+            Playlist::MasterPlaylist(master) => {
+                let variants: Vec<_> = master.variants.iter().filter(|v| !v.is_i_frame).collect();
+                let mut codecs: Vec<String> = Vec::new();
+                for codec in variants
+                    .iter()
+                    .filter_map(|v| v.codecs.as_deref())
+                    .flat_map(|c| c.split(','))
+                    .map(str::trim)
+                    .filter(|c| !c.is_empty())
+                {
+                    if !codecs.iter().any(|c| c == codec) {
+                        codecs.push(codec.to_string());
+                    }
+                }
+                let resolution = variants
+                    .iter()
+                    .filter_map(|v| v.resolution)
+                    .filter_map(|r| {
+                        Some((u32::try_from(r.width).ok()?, u32::try_from(r.height).ok()?))
+                    })
+                    .max_by_key(|&(w, h)| u64::from(w) * u64::from(h));
+
+                let Some(first) = variants.first() else {
+                    return Ok(ProbeResult {
+                        codecs,
+                        resolution,
+                        duration: None,
+                    });
+                };
+                let variant_url = join(&base, &first.uri)?;
+                let body = self.fetch_playlist(&variant_url).await?;
+                let media = m3u8_rs::parse_media_playlist_res(body.as_bytes()).map_err(|e| {
+                    RitcherError::PlaylistParseError(format!(
+                        "Failed to parse {}: {:?}",
+                        variant_url, e
+                    ))
+                })?;
+
+                if codecs.is_empty() {
+                    // Nothing declared: read the rendition's media instead
+                    let mut probe = self.probe_hls_media(&media, &variant_url).await?;
+                    probe.resolution = resolution.or(probe.resolution);
+                    return Ok(probe);
+                }
+                Ok(ProbeResult {
+                    codecs,
+                    resolution,
+                    duration: playlist_duration(&media),
+                })
+            }
+        }
+    }
+
+    /// Duration of a media playlist, and codecs and size of its init
+    /// section or first segment
+    async fn probe_hls_media(&self, media: &MediaPlaylist, base: &Url) -> Result<ProbeResult> {
+        let duration = playlist_duration(media);
+        let first = media
+            .segments
+            .iter()
+            .find_map(|s| s.map.as_ref().map(|m| m.uri.as_str()))
+            .or_else(|| media.segments.first().map(|s| s.uri.as_str()));
+        let mut probe = match first {
+            Some(uri) => self.probe_file(join(base, uri)?.as_str()).await?,
+            None => ProbeResult::default(),
+        };
+        probe.duration = duration;
+        Ok(probe)
+    }
+
+    /// Read the head of a progressive file or segment
+    async fn probe_file(&self, url: &str) -> Result<ProbeResult> {
+        let data = self.fetch_head(url).await?;
+        if ts::is_ts(&data) {
+            return Ok(ProbeResult {
+                codecs: ts::stream_codecs(&data).unwrap_or_default(),
+                ..Default::default()
+            });
+        }
+        if mp4::is_mp4(&data) {
+            let info = mp4::movie_info(&data).unwrap_or_default();
+            return Ok(ProbeResult {
+                codecs: info.codecs,
+                resolution: info.resolution,
+                duration: info.duration,
+            });
+        }
+        Ok(ProbeResult::default())
+    }
+
+    /// GET the first [`PROBE_BYTES`] of `url`, whether or not the server
+    /// honours the range
+    async fn fetch_head(&self, url: &str) -> Result<Vec<u8>> {
+        let mut response = self
+            .http_client
+            .get(url)
+            .header(RANGE, format!("bytes=0-{}", PROBE_BYTES - 1))
+            .send()
+            .await?
+            .error_for_status()?;
+        let mut data = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            data.extend_from_slice(&chunk);
+            if data.len() >= PROBE_BYTES {
+                data.truncate(PROBE_BYTES);
+                break;
+            }
+        }
+        Ok(data)
+    }
+
+    /// GET a playlist as text, bounded by [`MAX_MANIFEST_SIZE`]
+    async fn fetch_playlist(&self, url: &Url) -> Result<String> {
+        let mut response = self
+            .http_client
+            .get(url.as_str())
+            .send()
+            .await?
+            .error_for_status()?;
+        let too_large = || {
+            RitcherError::ResponseTooLarge(format!(
+                "Creative playlist {} exceeds {} byte limit",
+                url, MAX_MANIFEST_SIZE
+            ))
+        };
+        if response
+            .content_length()
+            .is_some_and(|len| len > MAX_MANIFEST_SIZE)
+        {
+            return Err(too_large());
+        }
+        // Read chunk by chunk so a body without Content-Length is abandoned
+        // as soon as it passes the limit rather than buffered whole
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            body.extend_from_slice(&chunk);
+            if body.len() as u64 > MAX_MANIFEST_SIZE {
+                return Err(too_large());
+            }
+        }
+        String::from_utf8(body).map_err(|e| {
+            RitcherError::PlaylistParseError(format!("Creative playlist is not UTF-8: {}", e))
+        })
+    }
+}
+
+impl std::fmt::Debug for CreativeProber {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CreativeProber")
+            .field("profile", &self.profile)
+            .field("timeout", &self.timeout)
+            .field("cached_probes", &self.cache.len())
+            .finish()
+    }
+}
+
+/// Summed `EXTINF` of a media playlist; `None` when it has no segments
+fn playlist_duration(media: &MediaPlaylist) -> Option<f32> {
+    let duration: f32 = media.segments.iter().map(|s| s.duration).sum();
+    (duration > 0.0).then_some(duration)
+}
+
+fn parse_url(url: &str) -> Result<Url> {
+    Url::parse(url)
+        .map_err(|e| RitcherError::ConfigError(format!("Invalid creative URL {}: {}", url, e)))
+}
+
+fn join(base: &Url, reference: &str) -> Result<Url> {
+    base.join(reference).map_err(|e| {
+        RitcherError::PlaylistParseError(format!(
+            "Invalid URI {} relative to {}: {}",
+            reference, base, e
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn probe(codecs: &[&str], resolution: Option<(u32, u32)>, duration: f32) -> ProbeResult {
+        ProbeResult {
+            codecs: codecs.iter().map(|c| c.to_string()).collect(),
+            resolution,
+            duration: Some(duration),
+        }
+    }
+
+    #[test]
+    fn profile_compares_codec_families_size_and_duration() {
+        let profile = CodecProfile {
+            max_resolution: Some((1920, 1080)),
+            ..Default::default()
+        };
+        let h264 = probe(&["avc3.640028", "mp4a.40.2"], Some((1920, 1080)), 15.2);
+        assert_eq!(profile.verdict(Some(&h264), 15.0), Verdict::Match);
+
+        let hevc = probe(&["hvc1.1.6.L120.90", "mp4a.40.2"], None, 15.0);
+        assert_eq!(
+            profile.verdict(Some(&hevc), 15.0),
+            Verdict::Mismatch("codec")
+        );
+
+        let uhd = probe(&["avc1.640033"], Some((3840, 2160)), 15.0);
+        assert_eq!(
+            profile.verdict(Some(&uhd), 15.0),
+            Verdict::Mismatch("resolution")
+        );
+
+        assert_eq!(
+            profile.verdict(Some(&h264), 30.0),
+            Verdict::Mismatch("duration")
+        );
+        assert_eq!(profile.verdict(None, 15.0), Verdict::Unknown);
+        assert_eq!(
+            profile.verdict(Some(&ProbeResult::default()), 15.0),
+            Verdict::Unknown
+        );
+    }
+
+    #[test]
+    fn empty_profile_accepts_any_codec() {
+        let profile = CodecProfile {
+            codecs: Vec::new(),
+            max_resolution: None,
+        };
+        let av1 = probe(&["av01.0.08M.08"], None, 10.0);
+        assert_eq!(profile.verdict(Some(&av1), 10.0), Verdict::Match);
+    }
+
+    #[tokio::test]
+    async fn probes_hls_master_and_first_rendition() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/ad/master.m3u8"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                "#EXTM3U\n\
+                 #EXT-X-STREAM-INF:BANDWIDTH=800000,RESOLUTION=640x360,CODECS=\"avc1.4d401e,mp4a.40.2\"\n\
+                 low.m3u8\n\
+                 #EXT-X-STREAM-INF:BANDWIDTH=3000000,RESOLUTION=1280x720,CODECS=\"avc1.64001f,mp4a.40.2\"\n\
+                 high.m3u8\n",
+            ))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/ad/low.m3u8"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                "#EXTM3U\n#EXT-X-TARGETDURATION:6\n\
+                 #EXTINF:6.0,\nseg0.ts\n#EXTINF:6.0,\nseg1.ts\n#EXTINF:3.0,\nseg2.ts\n\
+                 #EXT-X-ENDLIST\n",
+            ))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let prober = CreativeProber::new(Client::new(), CodecProfile::default());
+        let url = format!("{}/ad/master.m3u8", server.uri());
+        let expected = ProbeResult {
+            codecs: vec![
+                "avc1.4d401e".to_string(),
+                "mp4a.40.2".to_string(),
+                "avc1.64001f".to_string(),
+            ],
+            resolution: Some((1280, 720)),
+            duration: Some(15.0),
+        };
+        assert_eq!(prober.probe(&url, true).await, Some(expected.clone()));
+        // Served from the cache the second time
+        assert_eq!(prober.probe(&url, true).await, Some(expected));
+        assert_eq!(prober.verdict(&url, true, 15.0).await, Verdict::Match);
+    }
+
+    #[tokio::test]
+    async fn probes_segments_when_playlist_declares_no_codecs() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/ad/index.m3u8"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                "#EXTM3U\n#EXT-X-TARGETDURATION:10\n\
+                 #EXTINF:10.0,\nseg0.ts\n#EXTINF:10.0,\nseg1.ts\n#EXT-X-ENDLIST\n",
+            ))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/ad/seg0.ts"))
+            .respond_with(
+                ResponseTemplate::new(206).set_body_bytes(ts::tests::program(&[0x24, 0x0f])),
+            )
+            .mount(&server)
+            .await;
+
+        let prober = CreativeProber::new(Client::new(), CodecProfile::default());
+        let url = format!("{}/ad/index.m3u8", server.uri());
+        assert_eq!(
+            prober.probe(&url, true).await,
+            Some(probe(&["hvc1", "mp4a"], None, 20.0))
+        );
+        assert_eq!(
+            prober.verdict(&url, true, 20.0).await,
+            Verdict::Mismatch("codec")
+        );
+    }
+
+    #[tokio::test]
+    async fn probes_progressive_mp4_head() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/ad.mp4"))
+            .respond_with(
+                ResponseTemplate::new(206).set_body_bytes(mp4::tests::movie(1920, 1080, 30)),
+            )
+            .mount(&server)
+            .await;
+
+        let prober = CreativeProber::new(Client::new(), CodecProfile::default());
+        let url = format!("{}/ad.mp4", server.uri());
+        assert_eq!(
+            prober.probe(&url, false).await,
+            Some(probe(&["avc1.64001f", "mp4a"], Some((1920, 1080)), 30.0))
+        );
+    }
+
+    #[tokio::test]
+    async fn slow_or_broken_media_is_unknown() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/slow.mp4"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_bytes(mp4::tests::movie(640, 360, 10))
+                    .set_delay(Duration::from_millis(500)),
+            )
+            .mount(&server)
+            .await;
+
+        let prober = CreativeProber::new(Client::new(), CodecProfile::default())
+            .with_timeout(Duration::from_millis(50));
+        let slow = format!("{}/slow.mp4", server.uri());
+        assert_eq!(prober.verdict(&slow, false, 10.0).await, Verdict::Unknown);
+        // Timeouts are retried on the next break
+        assert!(prober.cache.is_empty());
+
+        let missing = format!("{}/missing.m3u8", server.uri());
+        assert_eq!(prober.verdict(&missing, true, 10.0).await, Verdict::Unknown);
+        assert_eq!(prober.cache.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn oversized_creative_playlist_is_rejected() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/huge.m3u8"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("x".repeat(11 * 1024 * 1024)))
+            .mount(&server)
+            .await;
+
+        let prober = CreativeProber::new(Client::new(), CodecProfile::default());
+        let url = Url::parse(&format!("{}/huge.m3u8", server.uri())).unwrap();
+        assert!(matches!(
+            prober.fetch_playlist(&url).await,
+            Err(RitcherError::ResponseTooLarge(_))
+        ));
+    }
+}
@@ -0,0 +1,233 @@
+//! ISO BMFF (`moov`) header parsing
+//!
+//! Walks `moov` → `trak` → `mdia` → `minf` → `stbl` → `stsd` for the codec
+//! and coded size of every track, and reads the duration from `mvhd`, or
+//! from `mvex`/`mehd` for fragmented files whose `mvhd` carries none.
+
+/// Container boxes walked on the way to the headers
+const CONTAINERS: &[&[u8; 4]] = &[b"moov", b"trak", b"mdia", b"minf", b"stbl", b"mvex"];
+
+/// Sample entries of video tracks, whose width and height are read
+const VISUAL_ENTRIES: &[&[u8; 4]] = &[
+    b"avc1", b"avc3", b"hvc1", b"hev1", b"vp09", b"av01", b"encv",
+];
+
+/// What the `moov` box says about a file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MovieInfo {
+    /// Sample entry codecs; `avc1`/`avc3` carry their `avcC` profile and level
+    pub codecs: Vec<String>,
+    /// Largest video track size
+    pub resolution: Option<(u32, u32)>,
+    /// Duration in seconds
+    pub duration: Option<f32>,
+}
+
+/// Whether `data` starts with an ISO BMFF box
+pub fn is_mp4(data: &[u8]) -> bool {
+    matches!(
+        data.get(4..8),
+        Some(b"ftyp" | b"styp" | b"moov" | b"moof" | b"free" | b"skip" | b"mdat")
+    )
+}
+
+/// Read the `moov` box in `data`; `None` when it is not in these bytes
+/// (e.g. an MP4 with `moov` at the end)
+pub fn movie_info(data: &[u8]) -> Option<MovieInfo> {
+    let moov = boxes(data).find(|(kind, _)| kind == b"moov")?.1;
+    let mut info = MovieInfo::default();
+    let mut timescale = 0;
+    walk(moov, &mut info, &mut timescale);
+    Some(info)
+}
+
+/// Boxes of a byte range as (type, content)
+fn boxes(mut data: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+    std::iter::from_fn(move || {
+        let size = u32::from_be_bytes(data.get(..4)?.try_into().ok()?) as usize;
+        let kind: [u8; 4] = data.get(4..8)?.try_into().ok()?;
+        let (header, size) = match size {
+            0 => (8, data.len()),
+            1 => (
+                16,
+                usize::try_from(u64::from_be_bytes(data.get(8..16)?.try_into().ok()?)).ok()?,
+            ),
+            size => (8, size),
+        };
+        let content = data.get(header..size)?;
+        data = &data[size..];
+        Some((kind, content))
+    })
+}
+
+fn walk(data: &[u8], info: &mut MovieInfo, timescale: &mut u32) {
+    for (kind, content) in boxes(data) {
+        match &kind {
+            b"mvhd" => {
+                let (scale, duration) = mvhd(content).unwrap_or_default();
+                *timescale = scale;
+                if duration > 0 && scale > 0 {
+                    info.duration = Some(duration as f32 / scale as f32);
+                }
+            }
+            b"mehd" if info.duration.is_none() && *timescale > 0 => {
+                info.duration = full_box_u32_or_u64(content, 0)
+                    .filter(|&d| d > 0)
+                    .map(|d| d as f32 / *timescale as f32);
+            }
+            b"stsd" => stsd(content, info),
+            kind if CONTAINERS.contains(&kind) => walk(content, info, timescale),
+            _ => {}
+        }
+    }
+}
+
+/// Timescale and duration of an `mvhd` box
+fn mvhd(content: &[u8]) -> Option<(u32, u64)> {
+    // After version/flags: creation and modification times, 4 or 8 bytes
+    let times = if *content.first()? == 1 { 16 } else { 8 };
+    let timescale = u32::from_be_bytes(content.get(4 + times..8 + times)?.try_into().ok()?);
+    Some((timescale, full_box_u32_or_u64(content, 8 + times - 4)?))
+}
+
+/// A version-dependent 32/64-bit field `offset` bytes after version/flags
+fn full_box_u32_or_u64(content: &[u8], offset: usize) -> Option<u64> {
+    let start = 4 + offset;
+    if *content.first()? == 1 {
+        Some(u64::from_be_bytes(
+            content.get(start..start + 8)?.try_into().ok()?,
+        ))
+    } else {
+        Some(u64::from(u32::from_be_bytes(
+            content.get(start..start + 4)?.try_into().ok()?,
+        )))
+    }
+}
+
+fn stsd(content: &[u8], info: &mut MovieInfo) {
+    // version/flags and entry count precede the sample entries
+    let Some(entries) = content.get(8..) else {
+        return;
+    };
+    for (kind, entry) in boxes(entries) {
+        let Ok(name) = std::str::from_utf8(&kind) else {
+            continue;
+        };
+        if VISUAL_ENTRIES.contains(&&kind) {
+            if let (Some(width), Some(height)) = (be_u16(entry, 24), be_u16(entry, 26)) {
+                let (width, height) = (u32::from(width), u32::from(height));
+                if info.resolution.is_none_or(|(w, h)| width * height > w * h) {
+                    info.resolution = Some((width, height));
+                }
+            }
+            // Child boxes follow the 78-byte visual sample entry
+            let avc = entry
+                .get(78..)
+                .and_then(|children| boxes(children).find(|(k, _)| k == b"avcC"))
+                .and_then(|(_, avcc)| avcc.get(1..4));
+            match avc {
+                Some(avc) if name.starts_with("avc") => info.codecs.push(format!(
+                    "{}.{:02x}{:02x}{:02x}",
+                    name, avc[0], avc[1], avc[2]
+                )),
+                _ => info.codecs.push(name.to_string()),
+            }
+        } else {
+            info.codecs.push(name.to_string());
+        }
+    }
+}
+
+fn be_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    fn mp4_box(kind: &[u8; 4], content: &[u8]) -> Vec<u8> {
+        let mut data = u32::try_from(content.len() + 8)
+            .unwrap()
+            .to_be_bytes()
+            .to_vec();
+        data.extend_from_slice(kind);
+        data.extend_from_slice(content);
+        data
+    }
+
+    fn visual_entry(kind: &[u8; 4], width: u16, height: u16, children: &[u8]) -> Vec<u8> {
+        let mut entry = vec![0; 78];
+        entry[24..26].copy_from_slice(&width.to_be_bytes());
+        entry[26..28].copy_from_slice(&height.to_be_bytes());
+        entry.extend_from_slice(children);
+        mp4_box(kind, &entry)
+    }
+
+    fn track(entry: Vec<u8>) -> Vec<u8> {
+        let mut stsd = vec![0, 0, 0, 0, 0, 0, 0, 1];
+        stsd.extend(entry);
+        let stbl = mp4_box(b"stbl", &mp4_box(b"stsd", &stsd));
+        mp4_box(b"trak", &mp4_box(b"mdia", &mp4_box(b"minf", &stbl)))
+    }
+
+    /// `ftyp` and `moov` of a file with an H.264 `width`x`height` video
+    /// track, an AAC track and `duration` seconds at timescale 1000
+    pub(crate) fn movie(width: u16, height: u16, duration: u32) -> Vec<u8> {
+        let mut mvhd = vec![0; 4 + 8];
+        mvhd.extend_from_slice(&1000_u32.to_be_bytes());
+        mvhd.extend_from_slice(&(duration * 1000).to_be_bytes());
+        mvhd.resize(100, 0);
+
+        let avcc = mp4_box(b"avcC", &[1, 0x64, 0x00, 0x1f]);
+        let mut moov = mp4_box(b"mvhd", &mvhd);
+        moov.extend(track(visual_entry(b"avc1", width, height, &avcc)));
+        moov.extend(track(mp4_box(b"mp4a", &[0; 28])));
+
+        let mut data = mp4_box(b"ftyp", b"isom\0\0\0\0");
+        data.extend(mp4_box(b"moov", &moov));
+        data
+    }
+
+    #[test]
+    fn reads_codecs_size_and_duration_from_moov() {
+        let data = movie(1920, 1080, 15);
+        assert!(is_mp4(&data));
+        assert_eq!(
+            movie_info(&data),
+            Some(MovieInfo {
+                codecs: vec!["avc1.64001f".to_string(), "mp4a".to_string()],
+                resolution: Some((1920, 1080)),
+                duration: Some(15.0),
+            })
+        );
+    }
+
+    #[test]
+    fn fragmented_duration_comes_from_mehd() {
+        let mut mvhd = vec![0; 4 + 8];
+        mvhd.extend_from_slice(&90_000_u32.to_be_bytes());
+        mvhd.resize(100, 0);
+        let mut mehd = vec![0; 4];
+        mehd.extend_from_slice(&(90_000_u32 * 10).to_be_bytes());
+
+        let mut moov = mp4_box(b"mvhd", &mvhd);
+        moov.extend(track(visual_entry(b"hvc1", 1280, 720, &[])));
+        moov.extend(mp4_box(b"mvex", &mp4_box(b"mehd", &mehd)));
+        let data = mp4_box(b"moov", &moov);
+
+        let info = movie_info(&data).unwrap();
+        assert_eq!(info.codecs, vec!["hvc1"]);
+        assert_eq!(info.resolution, Some((1280, 720)));
+        assert_eq!(info.duration, Some(10.0));
+    }
+
+    #[test]
+    fn moov_past_the_probed_bytes_is_unknown() {
+        let mut data = mp4_box(b"ftyp", b"isom\0\0\0\0");
+        data.extend(mp4_box(b"mdat", &[0; 64]));
+        assert_eq!(movie_info(&data), None);
+    }
+}
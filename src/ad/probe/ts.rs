@@ -0,0 +1,163 @@
+//! MPEG-TS program map parsing
+//!
+//! Reads the PAT to find the PMT, and the PMT for the codecs of the
+//! elementary streams. Only single-packet PAT/PMT sections are read, which
+//! covers the one-program streams ad creatives are.
+
+/// TS packet size in bytes
+const PACKET_SIZE: usize = 188;
+
+/// Whether `data` starts with TS packets
+pub fn is_ts(data: &[u8]) -> bool {
+    data.first() == Some(&0x47) && data.get(PACKET_SIZE).is_none_or(|&b| b == 0x47)
+}
+
+/// Codecs of the elementary streams listed in the first PMT
+///
+/// Stream types are mapped to their RFC 6381 sample entry names (`avc1`,
+/// `hvc1`, `mp4a`, ...); unknown types are left out. `None` when no PMT
+/// is found in `data`.
+pub fn stream_codecs(data: &[u8]) -> Option<Vec<String>> {
+    let mut pmt_pid = None;
+    for packet in data.chunks_exact(PACKET_SIZE) {
+        let Some((pid, section)) = section(packet) else {
+            continue;
+        };
+        match pmt_pid {
+            None if pid == 0 => pmt_pid = pat_pmt_pid(section),
+            Some(pmt) if pid == pmt => return pmt_codecs(section),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// PID and PSI section of a packet starting one
+fn section(packet: &[u8]) -> Option<(u16, &[u8])> {
+    if packet[0] != 0x47 || packet[1] & 0x40 == 0 {
+        return None;
+    }
+    let pid = (u16::from(packet[1] & 0x1f) << 8) | u16::from(packet[2]);
+    let adaptation = (packet[3] >> 4) & 0x3;
+    let mut offset = 4;
+    if adaptation & 0x2 != 0 {
+        offset += 1 + usize::from(*packet.get(4)?);
+    }
+    if adaptation & 0x1 == 0 {
+        return None;
+    }
+    let pointer = usize::from(*packet.get(offset)?);
+    packet
+        .get(offset + 1 + pointer..)
+        .map(|section| (pid, section))
+}
+
+/// Section bytes between the header and the CRC
+fn section_body(section: &[u8], table_id: u8, header: usize) -> Option<&[u8]> {
+    if *section.first()? != table_id {
+        return None;
+    }
+    let length = (usize::from(section.get(1)? & 0x0f) << 8) | usize::from(*section.get(2)?);
+    // length counts from after itself, CRC32 included
+    section.get(header..(3 + length).checked_sub(4)?)
+}
+
+/// PID of the first program's PMT
+fn pat_pmt_pid(section: &[u8]) -> Option<u16> {
+    section_body(section, 0x00, 8)?
+        .chunks_exact(4)
+        .find(|program| program[0] != 0 || program[1] != 0)
+        .map(|program| (u16::from(program[2] & 0x1f) << 8) | u16::from(program[3]))
+}
+
+fn pmt_codecs(section: &[u8]) -> Option<Vec<String>> {
+    let body = section_body(section, 0x02, 12)?;
+    let info_length = (usize::from(section[10] & 0x0f) << 8) | usize::from(section[11]);
+    let mut streams = body.get(info_length..)?;
+    let mut codecs = Vec::new();
+    while streams.len() >= 5 {
+        let es_info_length = (usize::from(streams[3] & 0x0f) << 8) | usize::from(streams[4]);
+        if let Some(codec) = stream_type_codec(streams[0]) {
+            codecs.push(codec.to_string());
+        }
+        streams = streams.get(5 + es_info_length..).unwrap_or_default();
+    }
+    Some(codecs)
+}
+
+fn stream_type_codec(stream_type: u8) -> Option<&'static str> {
+    match stream_type {
+        0x1b => Some("avc1"),
+        0x24 => Some("hvc1"),
+        0x0f | 0x11 => Some("mp4a"),
+        0x03 | 0x04 => Some("mp3"),
+        0x81 => Some("ac-3"),
+        0x87 => Some("ec-3"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A TS packet carrying a PSI section on `pid`
+    fn psi_packet(pid: u16, section: &[u8]) -> Vec<u8> {
+        let [pid_high, pid_low] = pid.to_be_bytes();
+        let mut packet = vec![0x47, 0x40 | pid_high, pid_low, 0x10, 0x00];
+        packet.extend_from_slice(section);
+        packet.resize(PACKET_SIZE, 0xff);
+        packet
+    }
+
+    /// PAT and PMT packets of a one-program stream with `stream_types`
+    pub(crate) fn program(stream_types: &[u8]) -> Vec<u8> {
+        // PAT: program 1 on PID 0x1000
+        let pat = [
+            0x00, 0xb0, 0x0d, 0x00, 0x01, 0xc1, 0x00, 0x00, 0x00, 0x01, 0xf0, 0x00, 0, 0, 0, 0,
+        ];
+        let mut streams = Vec::new();
+        for (i, &stream_type) in stream_types.iter().enumerate() {
+            streams.extend_from_slice(&[stream_type, 0xe1, u8::try_from(i).unwrap(), 0xf0, 0x00]);
+        }
+        let length = 9 + streams.len() + 4;
+        let mut pmt = vec![
+            0x02,
+            0xb0,
+            u8::try_from(length).unwrap(),
+            0x00,
+            0x01,
+            0xc1,
+            0x00,
+            0x00,
+            0xe1,
+            0x00,
+            0xf0,
+            0x00,
+        ];
+        pmt.extend_from_slice(&streams);
+        pmt.extend_from_slice(&[0, 0, 0, 0]);
+
+        let mut data = psi_packet(0, &pat);
+        data.extend(psi_packet(0x1000, &pmt));
+        data
+    }
+
+    #[test]
+    fn reads_stream_codecs_from_pmt() {
+        let data = program(&[0x1b, 0x0f, 0x15]);
+        assert!(is_ts(&data));
+        assert_eq!(
+            stream_codecs(&data),
+            Some(vec!["avc1".to_string(), "mp4a".to_string()])
+        );
+    }
+
+    #[test]
+    fn no_pmt_no_codecs() {
+        let data = program(&[0x24]);
+        assert_eq!(stream_codecs(&data[..PACKET_SIZE]), None);
+        assert_eq!(stream_codecs(&data), Some(vec!["hvc1".to_string()]));
+        assert!(!is_ts(b"\x00\x00\x00\x18ftypisom"));
+    }
+}
@@ -1,6 +1,14 @@
-use crate::ad::vast::{TrackingEvent, Verification};
+use crate::ad::context::AdRequestContext;
+use crate::ad::schedule::BreakPosition;
+use crate::ad::slate::SlateProvider;
+use crate::ad::source::{self, SourceSegment};
+use crate::ad::vast::{TrackingEvent, Verification, VmapAdSource};
+use crate::error::{Result, RitcherError};
 use async_trait::async_trait;
-use tracing::info;
+use reqwest::Client;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, PoisonError, RwLock};
+use tracing::{info, warn};
 
 /// Represents a single ad segment
 #[derive(Debug, Clone, PartialEq)]
@@ -20,12 +28,18 @@ pub struct AdTrackingInfo {
     pub impression_urls: Vec<String>,
     /// Quartile/progress tracking events
     pub tracking_events: Vec<TrackingEvent>,
-    /// Error URL to fire on failures
-    pub error_url: Option<String>,
+    /// `<Error>` URLs of the ad and its wrapper chain, fired on failures
+    pub error_urls: Vec<String>,
     /// Total number of segments in this ad (for quartile calculation)
     pub total_segments: usize,
     /// Index of this segment within the ad
     pub segment_index: usize,
+    /// Creative URI, for the `[ASSETURI]` macro
+    pub asset_uri: Option<String>,
+    /// Creative duration in seconds, for the `[ADPLAYHEAD]` macro
+    pub duration: f32,
+    /// Where the break plays, for the `[BREAKPOSITION]` macro
+    pub break_position: Option<BreakPosition>,
 }
 
 /// Resolved segment with optional tracking context
@@ -52,6 +66,26 @@ pub struct AdCreative {
     pub verifications: Vec<Verification>,
 }
 
+/// URL of ad segment `name` on the stitcher's ad endpoint
+pub fn ad_segment_url(base_url: &str, session_id: &str, name: &str) -> String {
+    format!("{}/stitch/{}/ad/{}", base_url, session_id, name)
+}
+
+/// URL of segment `segment_idx` of break `break_idx` on the ad endpoint,
+/// resolved by the provider from the session's cache
+pub fn break_segment_url(
+    base_url: &str,
+    session_id: &str,
+    break_idx: usize,
+    segment_idx: usize,
+) -> String {
+    ad_segment_url(
+        base_url,
+        session_id,
+        &format!("break-{}-seg-{}.ts", break_idx, segment_idx),
+    )
+}
+
 /// Trait for ad content providers
 ///
 /// Implementations provide ad segments to fill ad breaks of a given duration.
@@ -63,11 +97,32 @@ pub trait AdProvider: Send + Sync {
     /// # Arguments
     /// * `duration` - Duration of the ad break in seconds
     /// * `session_id` - Session ID for tracking and personalization
+    /// * `ctx` - Viewer context captured when the session started, for targeting
     ///
     /// # Returns
     /// A vector of AdSegment structs. The total duration may be less than, equal to,
     /// or slightly greater than the requested duration.
-    async fn get_ad_segments(&self, duration: f32, session_id: &str) -> Vec<AdSegment>;
+    async fn get_ad_segments(
+        &self,
+        duration: f32,
+        session_id: &str,
+        ctx: &AdRequestContext,
+    ) -> Vec<AdSegment>;
+
+    /// Get ad segments for a scheduled (VMAP) ad break
+    ///
+    /// `source` is the break's VAST tag or inline VAST document. The default
+    /// ignores it and fills `duration` like any other break; the VAST
+    /// provider plays the source's pod instead, at its own length.
+    async fn get_ad_segments_for_source(
+        &self,
+        _source: &VmapAdSource,
+        duration: f32,
+        session_id: &str,
+        ctx: &AdRequestContext,
+    ) -> Vec<AdSegment> {
+        self.get_ad_segments(duration, session_id, ctx).await
+    }
 
     /// Resolve an ad segment identifier to its actual source URL
     ///
@@ -106,6 +161,24 @@ pub trait AdProvider: Send + Sync {
             })
     }
 
+    /// Stitcher URL of the ad segment named `segment_name`, placed as
+    /// segment `segment_idx` of break `break_idx` of a playlist
+    ///
+    /// Default: [`break_segment_url`], which the provider resolves from the
+    /// session's cache. Providers whose segment names resolve as they are
+    /// (slate, loaded creatives, waterfall sources) serve them under their
+    /// own name.
+    fn segment_url(
+        &self,
+        _segment_name: &str,
+        session_id: &str,
+        base_url: &str,
+        break_idx: usize,
+        segment_idx: usize,
+    ) -> String {
+        break_segment_url(base_url, session_id, break_idx, segment_idx)
+    }
+
     /// Evict stale entries from provider-side caches.
     ///
     /// Default: no-op — stateless providers have nothing to evict.
@@ -121,8 +194,13 @@ pub trait AdProvider: Send + Sync {
     ///
     /// Default implementation adapts the SSAI segment list — one creative per
     /// segment. VAST provider overrides this to return proper creative-level URLs.
-    async fn get_ad_creatives(&self, duration: f32, session_id: &str) -> Vec<AdCreative> {
-        self.get_ad_segments(duration, session_id)
+    async fn get_ad_creatives(
+        &self,
+        duration: f32,
+        session_id: &str,
+        ctx: &AdRequestContext,
+    ) -> Vec<AdCreative> {
+        self.get_ad_segments(duration, session_id, ctx)
             .await
             .into_iter()
             .map(|seg| AdCreative {
@@ -132,27 +210,78 @@ pub trait AdProvider: Send + Sync {
             })
             .collect()
     }
+
+    /// Get ad creatives for the SGAI asset-list of a scheduled (VMAP) break.
+    ///
+    /// Counterpart of [`AdProvider::get_ad_segments_for_source`]; the default
+    /// ignores `source` and calls [`AdProvider::get_ad_creatives`].
+    async fn get_ad_creatives_for_source(
+        &self,
+        _source: &VmapAdSource,
+        duration: f32,
+        session_id: &str,
+        ctx: &AdRequestContext,
+    ) -> Vec<AdCreative> {
+        self.get_ad_creatives(duration, session_id, ctx).await
+    }
+
+    /// Slate used by this provider, if any.
+    ///
+    /// Default: `None`. The server refreshes a playlist-based slate in the
+    /// background and the playlist handler uses it to pick the slate
+    /// rendition and signal its init section and keys.
+    fn slate(&self) -> Option<&SlateProvider> {
+        None
+    }
 }
 
 /// Static ad provider that returns a fixed set of ad segments
 ///
-/// This is the MVP implementation that uses a configured ad source URL
-/// and segment duration to generate ad segments.
+/// With a plain base URL the provider synthesises `out_NNN.ts` segments of a
+/// fixed duration. When `ad_source_url` lists one or more HLS playlists
+/// (comma-separated `.m3u8` URLs), [`load_creatives`](Self::load_creatives)
+/// loads them as house-ad creatives: each break opens with the next
+/// creative in rotation and is filled with the creatives' real segments and
+/// durations.
 #[derive(Clone, Debug)]
 pub struct StaticAdProvider {
-    /// Base URL for ad segments
+    /// Base URL for ad segments, or comma-separated creative playlist URLs
     ad_source_url: String,
     /// Duration of each ad segment
     segment_duration: f32,
     /// Number of available segments in the ad source (for cycling)
     segment_count: usize,
+    /// Loaded creatives, empty until `load_creatives` succeeds
+    creatives: Arc<RwLock<Arc<Vec<StaticCreative>>>>,
+    /// Rotation position: the creative that opens the next break
+    next_creative: Arc<AtomicUsize>,
+}
+
+/// A house-ad creative loaded from an HLS playlist
+#[derive(Debug)]
+struct StaticCreative {
+    /// Playlist URL, served as-is in SGAI asset lists
+    url: String,
+    /// Segments of the creative's first rendition
+    segments: Vec<SourceSegment>,
+    /// Total duration in seconds
+    duration: f32,
+}
+
+/// Gaps below this are rounding noise when filling a break, in seconds
+const FILL_EPSILON: f32 = 0.01;
+
+/// Return `true` if `name` is a segment of a loaded static creative
+pub fn is_creative_segment(name: &str) -> bool {
+    name.starts_with("creative-")
 }
 
 impl StaticAdProvider {
     /// Create a new StaticAdProvider
     ///
     /// # Arguments
-    /// * `ad_source_url` - Base URL where ad segments are hosted
+    /// * `ad_source_url` - Base URL where ad segments are hosted, or
+    ///   comma-separated creative playlist URLs
     /// * `segment_duration` - Duration of each ad segment in seconds
     pub fn new(ad_source_url: String, segment_duration: f32) -> Self {
         Self::with_segment_count(ad_source_url, segment_duration, 10)
@@ -173,7 +302,128 @@ impl StaticAdProvider {
             ad_source_url,
             segment_duration,
             segment_count,
+            creatives: Arc::new(RwLock::new(Arc::new(Vec::new()))),
+            next_creative: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Creative playlist URLs, or `None` when the source is a segment directory
+    fn creative_urls(&self) -> Option<Vec<&str>> {
+        let urls: Vec<&str> = self
+            .ad_source_url
+            .split(',')
+            .map(str::trim)
+            .filter(|u| !u.is_empty())
+            .collect();
+        (!urls.is_empty() && urls.iter().all(|u| source::is_playlist_url(u))).then_some(urls)
+    }
+
+    /// Return `true` if the ad source lists creative playlists to load
+    pub fn has_creatives(&self) -> bool {
+        self.creative_urls().is_some()
+    }
+
+    /// Load the creative playlists, replacing the previous set on success
+    ///
+    /// Creatives that fail to load are skipped with a warning; it is an error
+    /// only when none loads. Returns the number of creatives loaded.
+    pub async fn load_creatives(&self, client: &Client) -> Result<usize> {
+        let Some(urls) = self.creative_urls() else {
+            return Ok(0);
+        };
+
+        let mut creatives = Vec::with_capacity(urls.len());
+        for url in urls {
+            match source::load(client, url).await {
+                Ok(playlist) => {
+                    let segments = playlist.variants[0].segments.clone();
+                    let duration = segments.iter().map(|s| s.duration).sum();
+                    info!(
+                        "StaticAdProvider: Loaded creative {} ({} segments, {}s)",
+                        url,
+                        segments.len(),
+                        duration
+                    );
+                    creatives.push(StaticCreative {
+                        url: url.to_string(),
+                        segments,
+                        duration,
+                    });
+                }
+                Err(e) => warn!("StaticAdProvider: Skipping creative {}: {}", url, e),
+            }
+        }
+
+        if creatives.is_empty() {
+            return Err(RitcherError::ConfigError(
+                "No static ad creative could be loaded".to_string(),
+            ));
+        }
+        let count = creatives.len();
+        *self
+            .creatives
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = Arc::new(creatives);
+        Ok(count)
+    }
+
+    /// The loaded creatives (empty in segment-directory mode)
+    fn loaded(&self) -> Arc<Vec<StaticCreative>> {
+        self.creatives
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Creatives for the next break, in play order, starting at the next
+    /// rotation position and continuing round-robin until `duration` is
+    /// covered (each creative at most once)
+    fn rotate(&self, creatives: &[StaticCreative], duration: f32) -> Vec<usize> {
+        let start = self.next_creative.fetch_add(1, Ordering::Relaxed);
+        let mut filled = 0.0_f32;
+        let mut order = Vec::new();
+        for offset in 0..creatives.len() {
+            if !order.is_empty() && filled >= duration - FILL_EPSILON {
+                break;
+            }
+            let index = (start + offset) % creatives.len();
+            filled += creatives[index].duration;
+            order.push(index);
         }
+        order
+    }
+
+    /// Fill a break with the segments of the rotated creatives, trimming the
+    /// last segment so the total matches `duration`
+    fn creative_segments(&self, creatives: &[StaticCreative], duration: f32) -> Vec<AdSegment> {
+        let mut segments = Vec::new();
+        let mut filled = 0.0_f32;
+
+        'creatives: for index in self.rotate(creatives, duration) {
+            for (seg_idx, source) in creatives[index].segments.iter().enumerate() {
+                let remaining = duration - filled;
+                if !segments.is_empty() && remaining <= FILL_EPSILON {
+                    break 'creatives;
+                }
+                let segment_duration = if remaining > 0.0 {
+                    source.duration.min(remaining)
+                } else {
+                    source.duration
+                };
+                segments.push(AdSegment {
+                    uri: format!(
+                        "creative-{}-seg-{}.{}",
+                        index,
+                        seg_idx,
+                        source::extension(&source.uri).unwrap_or("ts")
+                    ),
+                    duration: segment_duration,
+                    tracking: None,
+                });
+                filled += segment_duration;
+            }
+        }
+        segments
     }
 
     /// Parse segment index from ad name like "break-0-seg-3.ts" → Some(3)
@@ -188,16 +438,43 @@ impl StaticAdProvider {
             None
         }
     }
+
+    /// Parse a creative segment name like "creative-1-seg-4.ts" → (1, 4)
+    fn parse_creative_name(ad_name: &str) -> Option<(usize, usize)> {
+        let (stem, _ext) = ad_name.strip_prefix("creative-")?.rsplit_once('.')?;
+        let (creative, seg) = stem.split_once("-seg-")?;
+        Some((creative.parse().ok()?, seg.parse().ok()?))
+    }
 }
 
 #[async_trait]
 impl AdProvider for StaticAdProvider {
-    async fn get_ad_segments(&self, duration: f32, session_id: &str) -> Vec<AdSegment> {
+    async fn get_ad_segments(
+        &self,
+        duration: f32,
+        session_id: &str,
+        _ctx: &AdRequestContext,
+    ) -> Vec<AdSegment> {
         info!(
             "StaticAdProvider: Generating ad segments for session {} with duration {}s",
             session_id, duration
         );
 
+        let creatives = self.loaded();
+        if creatives.is_empty() && self.has_creatives() {
+            warn!("StaticAdProvider: No creatives loaded, returning an empty break");
+            return Vec::new();
+        }
+        if !creatives.is_empty() {
+            let segments = self.creative_segments(&creatives, duration);
+            info!(
+                "StaticAdProvider: Filled {}s break with {} creative segments",
+                duration,
+                segments.len()
+            );
+            return segments;
+        }
+
         // Calculate how many segments we need to fill the duration
         // Duration and segment_duration are positive f32; ceil() yields a non-negative
         // finite value well within usize range for realistic ad durations.
@@ -225,7 +502,31 @@ impl AdProvider for StaticAdProvider {
         segments
     }
 
+    fn segment_url(
+        &self,
+        segment_name: &str,
+        session_id: &str,
+        base_url: &str,
+        break_idx: usize,
+        segment_idx: usize,
+    ) -> String {
+        if is_creative_segment(segment_name) {
+            ad_segment_url(base_url, session_id, segment_name)
+        } else {
+            break_segment_url(base_url, session_id, break_idx, segment_idx)
+        }
+    }
+
     fn resolve_segment_url(&self, ad_name: &str, _session_id: &str) -> Option<String> {
+        if let Some((creative, seg_idx)) = Self::parse_creative_name(ad_name) {
+            let creatives = self.loaded();
+            return creatives
+                .get(creative)?
+                .segments
+                .get(seg_idx)
+                .map(|s| s.uri.clone());
+        }
+
         let seg_index = self.parse_segment_index(ad_name)?;
 
         // Map to ad source segment name, cycling through available segments
@@ -235,6 +536,36 @@ impl AdProvider for StaticAdProvider {
 
         Some(format!("{}/{}", self.ad_source_url, source_segment))
     }
+
+    async fn get_ad_creatives(
+        &self,
+        duration: f32,
+        session_id: &str,
+        ctx: &AdRequestContext,
+    ) -> Vec<AdCreative> {
+        let creatives = self.loaded();
+        if creatives.is_empty() {
+            return self
+                .get_ad_segments(duration, session_id, ctx)
+                .await
+                .into_iter()
+                .map(|seg| AdCreative {
+                    uri: seg.uri,
+                    duration: seg.duration as f64,
+                    verifications: Vec::new(),
+                })
+                .collect();
+        }
+
+        self.rotate(&creatives, duration)
+            .into_iter()
+            .map(|index| AdCreative {
+                uri: creatives[index].url.clone(),
+                duration: creatives[index].duration as f64,
+                verifications: Vec::new(),
+            })
+            .collect()
+    }
 }
 
 /// Demo ad provider that serves visually different ad creatives per break
@@ -380,7 +711,12 @@ impl DemoAdProvider {
 
 #[async_trait]
 impl AdProvider for DemoAdProvider {
-    async fn get_ad_segments(&self, duration: f32, session_id: &str) -> Vec<AdSegment> {
+    async fn get_ad_segments(
+        &self,
+        duration: f32,
+        session_id: &str,
+        _ctx: &AdRequestContext,
+    ) -> Vec<AdSegment> {
         info!(
             "DemoAdProvider: Generating ad segments for session {} with duration {}s",
             session_id, duration
@@ -453,11 +789,15 @@ impl AdProvider for DemoAdProvider {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
 
     #[tokio::test]
     async fn test_static_ad_provider_exact_duration() {
         let provider = StaticAdProvider::new("https://ads.example.com".to_string(), 10.0);
-        let segments = provider.get_ad_segments(30.0, "test-session").await;
+        let segments = provider
+            .get_ad_segments(30.0, "test-session", &AdRequestContext::default())
+            .await;
 
         assert_eq!(segments.len(), 3);
         assert_eq!(segments[0].duration, 10.0);
@@ -470,7 +810,9 @@ mod tests {
     #[tokio::test]
     async fn test_static_ad_provider_partial_duration() {
         let provider = StaticAdProvider::new("https://ads.example.com".to_string(), 10.0);
-        let segments = provider.get_ad_segments(25.0, "test-session").await;
+        let segments = provider
+            .get_ad_segments(25.0, "test-session", &AdRequestContext::default())
+            .await;
 
         // 25 / 10 = 2.5, ceiling = 3 segments
         assert_eq!(segments.len(), 3);
@@ -479,7 +821,9 @@ mod tests {
     #[tokio::test]
     async fn test_static_ad_provider_min_one_segment() {
         let provider = StaticAdProvider::new("https://ads.example.com".to_string(), 10.0);
-        let segments = provider.get_ad_segments(2.0, "test-session").await;
+        let segments = provider
+            .get_ad_segments(2.0, "test-session", &AdRequestContext::default())
+            .await;
 
         // Even for very short duration, return at least 1 segment
         assert_eq!(segments.len(), 1);
@@ -488,7 +832,9 @@ mod tests {
     #[tokio::test]
     async fn test_static_ad_provider_zero_duration() {
         let provider = StaticAdProvider::new("https://ads.example.com".to_string(), 10.0);
-        let segments = provider.get_ad_segments(0.0, "test-session").await;
+        let segments = provider
+            .get_ad_segments(0.0, "test-session", &AdRequestContext::default())
+            .await;
 
         // Should return at least 1 segment
         assert_eq!(segments.len(), 1);
@@ -531,6 +877,26 @@ mod tests {
         assert_eq!(provider.resolve_segment_url("invalid.ts", "test"), None);
     }
 
+    #[test]
+    fn test_segment_url_keeps_creative_names() {
+        let provider = StaticAdProvider::new("https://ads.example.com".to_string(), 1.0);
+
+        assert_eq!(
+            provider.segment_url(
+                "https://ads.example.com/ad-segment-3.ts",
+                "s1",
+                "http://localhost",
+                1,
+                3
+            ),
+            "http://localhost/stitch/s1/ad/break-1-seg-3.ts"
+        );
+        assert_eq!(
+            provider.segment_url("creative-1-seg-0.m4s", "s1", "http://localhost", 1, 0),
+            "http://localhost/stitch/s1/ad/creative-1-seg-0.m4s"
+        );
+    }
+
     // === DemoAdProvider tests ===
 
     #[test]
@@ -746,10 +1112,118 @@ mod tests {
     #[tokio::test]
     async fn test_demo_ad_provider_get_segments() {
         let provider = DemoAdProvider::new("http://localhost:3333/ads");
-        let segments = provider.get_ad_segments(10.0, "test").await;
+        let segments = provider
+            .get_ad_segments(10.0, "test", &AdRequestContext::default())
+            .await;
 
         assert_eq!(segments.len(), 10);
         assert_eq!(segments[0].duration, 1.0);
         assert!(segments[0].tracking.is_none());
     }
+
+    const CREATIVE_MASTER: &str = "#EXTM3U\n\
+        #EXT-X-STREAM-INF:BANDWIDTH=1000000\nhd/index.m3u8\n";
+    const CREATIVE_A: &str = "#EXTM3U\n#EXT-X-TARGETDURATION:6\n\
+        #EXTINF:6.0,\na0.ts\n#EXTINF:4.0,\na1.ts\n#EXT-X-ENDLIST\n";
+    const CREATIVE_B: &str = "#EXTM3U\n#EXT-X-TARGETDURATION:5\n\
+        #EXTINF:5.0,\nb0.ts\n#EXTINF:5.0,\nb1.ts\n#EXTINF:5.0,\nb2.ts\n#EXT-X-ENDLIST\n";
+
+    /// Provider with creative A (10s, via a master playlist) and B (15s)
+    async fn creative_provider() -> (MockServer, StaticAdProvider) {
+        let server = MockServer::start().await;
+        for (route, body) in [
+            ("/a/master.m3u8", CREATIVE_MASTER),
+            ("/a/hd/index.m3u8", CREATIVE_A),
+            ("/b/index.m3u8", CREATIVE_B),
+        ] {
+            Mock::given(method("GET"))
+                .and(path(route))
+                .respond_with(ResponseTemplate::new(200).set_body_string(body))
+                .mount(&server)
+                .await;
+        }
+
+        let provider = StaticAdProvider::new(
+            format!(
+                "{uri}/a/master.m3u8, {uri}/b/index.m3u8",
+                uri = server.uri()
+            ),
+            1.0,
+        );
+        assert!(provider.has_creatives());
+        assert_eq!(provider.load_creatives(&Client::new()).await.unwrap(), 2);
+        (server, provider)
+    }
+
+    #[tokio::test]
+    async fn test_static_creatives_rotate_per_break() {
+        let (server, provider) = creative_provider().await;
+
+        let first = provider
+            .get_ad_segments(10.0, "s1", &AdRequestContext::default())
+            .await;
+        let uris: Vec<&str> = first.iter().map(|s| s.uri.as_str()).collect();
+        assert_eq!(uris, vec!["creative-0-seg-0.ts", "creative-0-seg-1.ts"]);
+        assert_eq!(first[0].duration, 6.0);
+        assert_eq!(first[1].duration, 4.0);
+
+        // The next break opens with creative B, trimmed to the break
+        let second = provider
+            .get_ad_segments(12.0, "s1", &AdRequestContext::default())
+            .await;
+        let durations: Vec<f32> = second.iter().map(|s| s.duration).collect();
+        assert_eq!(durations, vec![5.0, 5.0, 2.0]);
+        assert_eq!(second[0].uri, "creative-1-seg-0.ts");
+
+        // A break longer than one creative continues with the next
+        let third = provider
+            .get_ad_segments(20.0, "s1", &AdRequestContext::default())
+            .await;
+        assert_eq!(third.len(), 4);
+        assert_eq!(third[2].uri, "creative-1-seg-0.ts");
+        let total: f32 = third.iter().map(|s| s.duration).sum();
+        assert_eq!(total, 20.0);
+
+        assert_eq!(
+            provider.resolve_segment_url("creative-0-seg-1.ts", "s1"),
+            Some(format!("{}/a/hd/a1.ts", server.uri()))
+        );
+        assert_eq!(
+            provider.resolve_segment_url("creative-1-seg-2.ts", "s1"),
+            Some(format!("{}/b/b2.ts", server.uri()))
+        );
+        assert_eq!(
+            provider.resolve_segment_url("creative-2-seg-0.ts", "s1"),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_static_creatives_for_sgai() {
+        let (server, provider) = creative_provider().await;
+
+        let creatives = provider
+            .get_ad_creatives(10.0, "s1", &AdRequestContext::default())
+            .await;
+        assert_eq!(creatives.len(), 1);
+        assert_eq!(creatives[0].uri, format!("{}/a/master.m3u8", server.uri()));
+        assert_eq!(creatives[0].duration, 10.0);
+
+        let creatives = provider
+            .get_ad_creatives(20.0, "s1", &AdRequestContext::default())
+            .await;
+        let durations: Vec<f64> = creatives.iter().map(|c| c.duration).collect();
+        assert_eq!(durations, vec![15.0, 10.0]);
+    }
+
+    #[tokio::test]
+    async fn test_static_creatives_not_loaded_returns_empty_break() {
+        let provider = StaticAdProvider::new("https://ads.example.com/house.m3u8".to_string(), 1.0);
+        assert!(
+            provider
+                .get_ad_segments(10.0, "s1", &AdRequestContext::default())
+                .await
+                .is_empty()
+        );
+    }
 }
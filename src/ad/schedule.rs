@@ -0,0 +1,293 @@
+//! VMAP-backed ad break schedule for VOD content
+//!
+//! VOD assets rarely carry SCTE-35 markers, so ad breaks come from a VMAP
+//! document instead (`VMAP_URL`). The schedule is fetched per manifest
+//! request (deduplicated by the manifest cache) and each linear break is
+//! resolved to an offset into the content. The HLS and DASH placement
+//! steps then map those offsets onto segment or Period boundaries.
+
+use crate::ad::vast::{self, TimeOffset, VmapAdSource, VmapResponse};
+use crate::cache::ManifestCache;
+use crate::error::{Result, RitcherError};
+use crate::http_retry::{RetryConfig, fetch_with_retry};
+use crate::server::MAX_MANIFEST_SIZE;
+use futures_util::StreamExt;
+use reqwest::Client;
+use tracing::{info, warn};
+
+/// Duration requested for a scheduled break when the ad provider needs one
+///
+/// VMAP does not carry break durations: a VAST-backed break lasts as long
+/// as its pod. Providers without a pod of their own (static, demo) fill
+/// this much, and SGAI signals it as the planned break duration.
+pub const DEFAULT_BREAK_DURATION: f32 = 30.0;
+
+/// Where a scheduled break plays relative to the content
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakPosition {
+    /// Before the content starts
+    Pre,
+    /// Inside the content
+    Mid,
+    /// After the content ends
+    Post,
+}
+
+impl BreakPosition {
+    /// HLS Interstitials `CUE` attribute value, if any
+    pub fn cue(self) -> Option<&'static str> {
+        match self {
+            Self::Pre => Some("PRE"),
+            Self::Mid => None,
+            Self::Post => Some("POST"),
+        }
+    }
+
+    /// VAST `[BREAKPOSITION]` value: 1 pre-roll, 2 mid-roll, 3 post-roll
+    pub fn vast_code(self) -> u8 {
+        match self {
+            Self::Pre => 1,
+            Self::Mid => 2,
+            Self::Post => 3,
+        }
+    }
+
+    /// Sort key: pre-rolls, then mid-rolls, then post-rolls
+    fn rank(self) -> u8 {
+        match self {
+            Self::Pre => 0,
+            Self::Mid => 1,
+            Self::Post => 2,
+        }
+    }
+}
+
+/// A VMAP break resolved against a piece of content
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScheduledBreak {
+    /// Index of the break in the VMAP document, used to look it up again
+    /// from the asset-list and ad MPD endpoints
+    pub vmap_index: usize,
+    /// Seconds into the content
+    pub offset: f64,
+    /// Pre-, mid- or post-roll
+    pub position: BreakPosition,
+    /// VAST source filling the break
+    pub source: VmapAdSource,
+}
+
+/// VMAP document source
+#[derive(Debug, Clone)]
+pub struct VmapSchedule {
+    url: String,
+}
+
+impl VmapSchedule {
+    /// Create a schedule backed by the VMAP document at `url`
+    pub fn new(url: String) -> Self {
+        Self { url }
+    }
+
+    /// VMAP document URL
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Fetch and parse the VMAP document
+    ///
+    /// Responses are kept in `cache` so the variant playlists of one
+    /// master playlist share a single VMAP request.
+    pub async fn fetch(&self, client: &Client, cache: &ManifestCache) -> Result<VmapResponse> {
+        let xml = if let Some(cached) = cache.get(&self.url) {
+            cached
+        } else {
+            let xml = fetch_vmap(client, &self.url).await?;
+            cache.insert(&self.url, xml.clone());
+            xml
+        };
+        vast::parse_vmap(&xml)
+    }
+
+    /// Fetch the VMAP document and return the ad source of one break
+    pub async fn ad_source(
+        &self,
+        client: &Client,
+        cache: &ManifestCache,
+        vmap_index: usize,
+    ) -> Result<VmapAdSource> {
+        self.fetch(client, cache)
+            .await?
+            .ad_breaks
+            .into_iter()
+            .nth(vmap_index)
+            .and_then(|ad_break| ad_break.ad_source)
+            .ok_or_else(|| {
+                RitcherError::InvalidOrigin(format!("VMAP has no ad break {}", vmap_index))
+            })
+    }
+}
+
+/// GET the VMAP document, bounded by [`MAX_MANIFEST_SIZE`]
+async fn fetch_vmap(client: &Client, url: &str) -> Result<String> {
+    info!("Fetching VMAP from {}", url);
+    let response = fetch_with_retry(client, url, &RetryConfig::default()).await?;
+
+    // Check Content-Length header if present for early rejection
+    if let Some(content_length) = response.content_length()
+        && content_length > MAX_MANIFEST_SIZE
+    {
+        return Err(RitcherError::ResponseTooLarge(format!(
+            "VMAP Content-Length {} bytes exceeds {} byte limit",
+            content_length, MAX_MANIFEST_SIZE
+        )));
+    }
+
+    // Stream body incrementally to enforce size limit.
+    // Unlike `response.bytes()` which buffers the entire body before
+    // the size check, this aborts as soon as the limit is exceeded —
+    // protecting against chunked-encoding OOM attacks.
+    let mut body_buf = Vec::new();
+    let mut stream = response.bytes_stream();
+    while let Some(chunk_result) = stream.next().await {
+        let chunk = chunk_result?;
+        body_buf.extend_from_slice(&chunk);
+        if body_buf.len() as u64 > MAX_MANIFEST_SIZE {
+            return Err(RitcherError::ResponseTooLarge(format!(
+                "VMAP response exceeded {} byte limit while streaming",
+                MAX_MANIFEST_SIZE
+            )));
+        }
+    }
+
+    String::from_utf8(body_buf)
+        .map_err(|e| RitcherError::InternalError(format!("VMAP response is not UTF-8: {}", e)))
+}
+
+/// Resolve the linear VMAP breaks against content of `total_duration` seconds
+///
+/// `opportunities` lists the content's ad opportunities for `#n` offsets
+/// (see [`TimeOffset::resolve`]). Breaks without an ad source, non-linear
+/// breaks and positions past the last opportunity are dropped. The result
+/// is ordered by offset, pre-rolls first and post-rolls last.
+pub fn resolve_breaks(
+    vmap: &VmapResponse,
+    total_duration: f64,
+    opportunities: &[f64],
+) -> Vec<ScheduledBreak> {
+    let mut breaks: Vec<ScheduledBreak> = vmap
+        .ad_breaks
+        .iter()
+        .enumerate()
+        .filter_map(|(vmap_index, ad_break)| {
+            if !ad_break.is_linear() {
+                info!("VMAP: skipping non-linear break {}", vmap_index);
+                return None;
+            }
+            let source = ad_break.ad_source.clone()?;
+            let Some(offset) = ad_break.time_offset.resolve(total_duration, opportunities) else {
+                warn!(
+                    "VMAP: break {} offset {:?} has no matching ad opportunity",
+                    vmap_index, ad_break.time_offset
+                );
+                return None;
+            };
+            let position = match ad_break.time_offset {
+                TimeOffset::Start => BreakPosition::Pre,
+                TimeOffset::End => BreakPosition::Post,
+                _ if offset <= 0.0 => BreakPosition::Pre,
+                _ if offset >= total_duration => BreakPosition::Post,
+                _ => BreakPosition::Mid,
+            };
+            Some(ScheduledBreak {
+                vmap_index,
+                offset,
+                position,
+                source,
+            })
+        })
+        .collect();
+
+    breaks.sort_by(|a, b| {
+        a.position
+            .rank()
+            .cmp(&b.position.rank())
+            .then(a.offset.total_cmp(&b.offset))
+    });
+    breaks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ad::vast::VmapAdBreak;
+
+    fn vmap_break(offset: TimeOffset, break_type: &str, source: bool) -> VmapAdBreak {
+        VmapAdBreak {
+            break_id: None,
+            break_type: break_type.to_string(),
+            time_offset: offset,
+            ad_source: source.then(|| VmapAdSource::AdTagUri("https://ads.example.com".into())),
+        }
+    }
+
+    #[test]
+    fn resolve_breaks_orders_and_classifies() {
+        let vmap = VmapResponse {
+            version: "1.0".into(),
+            ad_breaks: vec![
+                vmap_break(TimeOffset::End, "linear", true),
+                vmap_break(TimeOffset::Percent(50.0), "linear", true),
+                vmap_break(TimeOffset::Start, "linear", true),
+                vmap_break(TimeOffset::Position(2), "linear", true),
+                vmap_break(TimeOffset::Seconds(10.0), "nonlinear", true),
+                vmap_break(TimeOffset::Seconds(20.0), "linear", false),
+                vmap_break(TimeOffset::Position(9), "linear", true),
+            ],
+        };
+
+        let breaks = resolve_breaks(&vmap, 120.0, &[0.0, 30.0, 120.0]);
+
+        let summary: Vec<(usize, f64, BreakPosition)> = breaks
+            .iter()
+            .map(|b| (b.vmap_index, b.offset, b.position))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (2, 0.0, BreakPosition::Pre),
+                (3, 30.0, BreakPosition::Mid),
+                (1, 60.0, BreakPosition::Mid),
+                (0, 120.0, BreakPosition::Post),
+            ]
+        );
+    }
+
+    #[test]
+    fn resolve_breaks_treats_offsets_at_the_edges_as_pre_and_post() {
+        let vmap = VmapResponse {
+            version: "1.0".into(),
+            ad_breaks: vec![
+                vmap_break(TimeOffset::Percent(100.0), "linear", true),
+                vmap_break(TimeOffset::Seconds(0.0), "linear", true),
+            ],
+        };
+        let breaks = resolve_breaks(&vmap, 60.0, &[0.0, 60.0]);
+        assert_eq!(breaks[0].position, BreakPosition::Pre);
+        assert_eq!(breaks[1].position, BreakPosition::Post);
+    }
+
+    #[tokio::test]
+    async fn oversized_vmap_is_rejected() {
+        use wiremock::matchers::method;
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("x".repeat(11 * 1024 * 1024)))
+            .mount(&server)
+            .await;
+
+        let result = fetch_vmap(&Client::new(), &server.uri()).await;
+        assert!(matches!(result, Err(RitcherError::ResponseTooLarge(_))));
+    }
+}
@@ -0,0 +1,625 @@
+//! Ad decisions shared between sessions
+//!
+//! By default every session asks the ad server for its own ads, so a break
+//! in a live event with many viewers becomes as many simultaneous ad
+//! requests. With `AD_DECISION_SCOPE=channel` a break's ads are decided
+//! once per channel and break and shared by every session watching it;
+//! `cohort` shares them among the sessions with the same targeting
+//! parameters. The first session to reach a break makes the decision,
+//! concurrent sessions wait for it.
+//!
+//! A shared decision is made under a decision ID (`_shared-<hash>`) that
+//! stands in for the session ID in the stitched media playlist, so every
+//! session of a channel or cohort gets the same playlist and the same
+//! CDN-cacheable ad segment URLs. DASH MPDs carry it in their ad Periods.
+//! The decision ID is derived from the origin, channel and targeting only,
+//! so every stitcher instance computes the same one.
+//!
+//! The decisions themselves are held in memory by the instance that made
+//! them. Deployments running several instances must route each decision
+//! ID (the second path segment of playlist and ad URLs) to a single
+//! instance, e.g. by hashing on it at the load balancer; otherwise each
+//! instance decides the break on its own and playlists cached from one
+//! point at creatives another does not hold.
+//!
+//! Ad segment requests of a shared decision carry no session, so tracking
+//! moves to playlist time: an ad segment's beacons fire with a session's
+//! viewer context once that session's playlist shows the break has reached
+//! the segment, at most once per session.
+//!
+//! Ad segments are named after the break and their position in it
+//! (`shared-120-seg-0.ts`) and resolved through the break's decision, so a
+//! URL never points at another break's creative. Segments of breaks this
+//! instance has no decision for are not found.
+
+use crate::ad::context::AdRequestContext;
+use crate::ad::provider::{AdProvider, AdSegment, ad_segment_url};
+use crate::ad::slate::is_slate_segment;
+use crate::ad::tracking;
+use crate::metrics;
+use dashmap::DashMap;
+use reqwest::Client;
+use reqwest::header::HeaderMap;
+use std::collections::HashSet;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::OnceCell;
+use tracing::info;
+
+/// Prefix of decision IDs, which stand in for session IDs in URLs
+const DECISION_ID_PREFIX: &str = "_shared-";
+
+/// Prefix of shared ad segment names
+const SEGMENT_PREFIX: &str = "shared-";
+
+/// How long a decision and a session's fired beacons are kept; matches the
+/// VAST provider's creative cache
+const DECISION_TTL: Duration = Duration::from_secs(300);
+
+/// Which sessions share an ad decision
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum DecisionScope {
+    /// Every session decides its own ads
+    #[default]
+    Session,
+    /// One decision per channel and break
+    Channel,
+    /// One decision per channel, break and set of targeting parameters
+    Cohort,
+}
+
+/// Return `true` if `id` is the decision ID of a shared decision
+pub fn is_shared_decision(id: &str) -> bool {
+    id.starts_with(DECISION_ID_PREFIX)
+}
+
+/// Return `true` if `name` is a segment of a shared decision
+pub fn is_shared_segment(name: &str) -> bool {
+    parse_segment_name(name).is_some()
+}
+
+/// Break key and position of a shared segment name
+fn parse_segment_name(ad_name: &str) -> Option<(u64, usize)> {
+    let (break_key, rest) = ad_name.strip_prefix(SEGMENT_PREFIX)?.split_once("-seg-")?;
+    let index = rest.split_once('.').map_or(rest, |(index, _)| index);
+    Some((break_key.parse().ok()?, index.parse().ok()?))
+}
+
+/// Shared name of the segment at `index` of a break, as returned by the ad
+/// provider
+///
+/// Slate segments keep their name (the slate is the same for every break)
+/// and so do absolute URLs, which the interleaver renames anyway. Others
+/// keep the provider's file extension.
+fn shared_segment_name(break_key: u64, index: usize, uri: &str) -> String {
+    if is_slate_segment(uri) || uri.contains("://") {
+        return uri.to_string();
+    }
+    let extension = uri.rsplit_once('.').map_or("", |(_, ext)| ext);
+    if extension.is_empty() {
+        format!("{SEGMENT_PREFIX}{break_key}-seg-{index}")
+    } else {
+        format!("{SEGMENT_PREFIX}{break_key}-seg-{index}.{extension}")
+    }
+}
+
+/// Key of a break starting `start_secs` into a presentation timeline
+///
+/// Millisecond precision; negative starts map to 0.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub fn timeline_break_key(start_secs: f64) -> u64 {
+    (start_secs * 1000.0).round().max(0.0) as u64
+}
+
+/// 64-bit FNV-1a, stable across processes and releases
+fn fnv1a(parts: &[&str]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for part in parts {
+        for byte in part.bytes().chain(std::iter::once(0)) {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    hash
+}
+
+/// A decision made for a break
+struct Decision {
+    segments: Arc<OnceCell<Decided>>,
+    created_at: Instant,
+}
+
+/// Segments of a decided break
+struct Decided {
+    /// Segments under their shared names
+    segments: Vec<AdSegment>,
+    /// Provider-side name of each segment
+    provider_names: Vec<String>,
+}
+
+/// Beacons already fired for a session
+struct FiredBeacons {
+    keys: HashSet<String>,
+    touched_at: Instant,
+}
+
+/// Store of the shared ad decisions and of the beacons fired for them
+#[derive(Clone)]
+pub struct SharedDecisions {
+    scope: DecisionScope,
+    http_client: Client,
+    /// Forward the viewer headers on tracking beacons
+    beacon_headers: bool,
+    /// Decisions by "decision_id:break_key"
+    decisions: Arc<DashMap<String, Decision>>,
+    /// Fired beacons by session ID
+    fired: Arc<DashMap<String, FiredBeacons>>,
+}
+
+impl SharedDecisions {
+    /// Create an empty store
+    pub fn new(scope: DecisionScope, http_client: Client) -> Self {
+        Self {
+            scope,
+            http_client,
+            beacon_headers: true,
+            decisions: Arc::new(DashMap::new()),
+            fired: Arc::new(DashMap::new()),
+        }
+    }
+
+    /// Forward the viewer's IP, user agent and referer on tracking beacons
+    pub fn with_beacon_headers(mut self, enabled: bool) -> Self {
+        self.beacon_headers = enabled;
+        self
+    }
+
+    /// Decision ID of a session watching `channel` from `origin_url`
+    ///
+    /// `None` when every session decides its own ads.
+    pub fn decision_id(
+        &self,
+        origin_url: &str,
+        channel: &str,
+        ctx: &AdRequestContext,
+    ) -> Option<String> {
+        let hash = match self.scope {
+            DecisionScope::Session => return None,
+            DecisionScope::Channel => fnv1a(&[origin_url, channel]),
+            DecisionScope::Cohort => {
+                let mut parts = vec![origin_url, channel];
+                for (name, value) in &ctx.params {
+                    parts.push(name);
+                    parts.push(value);
+                }
+                fnv1a(&parts)
+            }
+        };
+        Some(format!("{DECISION_ID_PREFIX}{hash:016x}"))
+    }
+
+    /// Context a shared decision is requested with
+    ///
+    /// Keeps the content metadata and the break's UPID, plus the targeting
+    /// parameters in cohort scope. Nothing identifying a viewer (IP, user
+    /// agent, referer, consent) is sent, as the ads are shown to every
+    /// session sharing the decision.
+    pub fn decision_context(&self, ctx: &AdRequestContext) -> AdRequestContext {
+        AdRequestContext {
+            params: if self.scope == DecisionScope::Cohort {
+                ctx.params.clone()
+            } else {
+                Default::default()
+            },
+            content: ctx.content.clone(),
+            upid: ctx.upid.clone(),
+            ..AdRequestContext::default()
+        }
+    }
+
+    /// Ad segments of a break, deciding them with `decide` when the break
+    /// has no decision yet
+    ///
+    /// `break_key` identifies the break within the decision ID, e.g. the
+    /// media sequence number of its first segment. Concurrent callers for
+    /// the same break wait for a single decision.
+    pub async fn segments<F>(&self, decision_id: &str, break_key: u64, decide: F) -> Vec<AdSegment>
+    where
+        F: Future<Output = Vec<AdSegment>>,
+    {
+        let cell = self
+            .decisions
+            .entry(format!("{decision_id}:{break_key}"))
+            .or_insert_with(|| Decision {
+                segments: Arc::new(OnceCell::new()),
+                created_at: Instant::now(),
+            })
+            .segments
+            .clone();
+
+        let mut decided = false;
+        let decision = cell
+            .get_or_init(|| async {
+                decided = true;
+                let segments = decide.await;
+                info!(
+                    "Shared ad decision {} for break {}: {} segment(s)",
+                    decision_id,
+                    break_key,
+                    segments.len()
+                );
+                let provider_names = segments.iter().map(|s| s.uri.clone()).collect();
+                let segments = segments
+                    .into_iter()
+                    .enumerate()
+                    .map(|(index, segment)| AdSegment {
+                        uri: shared_segment_name(break_key, index, &segment.uri),
+                        ..segment
+                    })
+                    .collect();
+                Decided {
+                    segments,
+                    provider_names,
+                }
+            })
+            .await;
+        metrics::record_shared_decision(if decided { "decided" } else { "reused" });
+        decision.segments.clone()
+    }
+
+    /// Stitcher URL of the segment named `segment_name`, placed as segment
+    /// `segment_idx` of break `break_idx` of a playlist stitched under
+    /// `decision_id`
+    ///
+    /// Shared segments are served under their shared name, which
+    /// [`route`](Self::route) maps back for the ad endpoint; other names
+    /// (slate, creative URLs) are left to `provider`.
+    pub fn segment_url(
+        &self,
+        provider: &dyn AdProvider,
+        segment_name: &str,
+        decision_id: &str,
+        base_url: &str,
+        break_idx: usize,
+        segment_idx: usize,
+    ) -> String {
+        if is_shared_segment(segment_name) {
+            ad_segment_url(base_url, decision_id, segment_name)
+        } else {
+            provider.segment_url(segment_name, decision_id, base_url, break_idx, segment_idx)
+        }
+    }
+
+    /// Provider-side name of a shared segment of `decision_id`
+    ///
+    /// Looks the segment up in the decision of its break. `None` when the
+    /// name is not a shared segment name or this instance holds no
+    /// decision for the break.
+    pub fn route(&self, decision_id: &str, ad_name: &str) -> Option<String> {
+        let (break_key, index) = parse_segment_name(ad_name)?;
+        let cell = self
+            .decisions
+            .get(&format!("{decision_id}:{break_key}"))?
+            .segments
+            .clone();
+        cell.get()?.provider_names.get(index).cloned()
+    }
+
+    /// Fire the beacons of a shared break that are due for a session
+    ///
+    /// `elapsed` is how many seconds of the break the session's playlist
+    /// has reached; the beacons of every ad segment starting before that
+    /// fire with the session's `viewer` context, once per session.
+    pub fn track(
+        &self,
+        session_id: &str,
+        decision_id: &str,
+        break_key: u64,
+        segments: &[AdSegment],
+        elapsed: f32,
+        viewer: &AdRequestContext,
+    ) {
+        let mut fired = self
+            .fired
+            .entry(session_id.to_string())
+            .or_insert_with(|| FiredBeacons {
+                keys: HashSet::new(),
+                touched_at: Instant::now(),
+            });
+        fired.touched_at = Instant::now();
+
+        let mut due = Vec::new();
+        let mut offset = 0.0;
+        for (idx, segment) in segments.iter().enumerate() {
+            let starts_at = offset;
+            offset += segment.duration;
+            if idx > 0 && starts_at >= elapsed {
+                break;
+            }
+            if let Some(tracking) = &segment.tracking
+                && fired
+                    .keys
+                    .insert(format!("{decision_id}:{break_key}:{idx}"))
+            {
+                due.push(tracking);
+            }
+        }
+        drop(fired);
+        if due.is_empty() {
+            return;
+        }
+
+        let headers = if self.beacon_headers {
+            viewer.viewer_headers()
+        } else {
+            HeaderMap::new()
+        };
+        for tracking in due {
+            let ctx = tracking::macro_context(tracking, viewer.macro_context());
+            tracking::fire_segment_beacons(self.http_client.clone(), tracking, &ctx, &headers);
+        }
+    }
+
+    /// Evict expired decisions and fired beacons
+    pub fn cleanup(&self) {
+        let before = self.decisions.len();
+        self.decisions
+            .retain(|_, decision| decision.created_at.elapsed() < DECISION_TTL);
+        self.fired
+            .retain(|_, fired| fired.touched_at.elapsed() < DECISION_TTL);
+
+        let evicted = before - self.decisions.len();
+        if evicted > 0 {
+            info!(
+                "Shared decisions: evicted {} expired decision(s) ({} remaining)",
+                evicted,
+                self.decisions.len()
+            );
+        }
+    }
+
+    /// Number of decisions held
+    pub fn len(&self) -> usize {
+        self.decisions.len()
+    }
+
+    /// Whether no decision is held
+    pub fn is_empty(&self) -> bool {
+        self.decisions.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ad::provider::AdTrackingInfo;
+    use crate::ad::vast::TrackingEvent;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn segment(uri: &str, tracking: Option<AdTrackingInfo>) -> AdSegment {
+        AdSegment {
+            uri: uri.to_string(),
+            duration: 10.0,
+            tracking,
+        }
+    }
+
+    fn tracked(base: &str, segment_index: usize) -> AdTrackingInfo {
+        AdTrackingInfo {
+            impression_urls: vec![format!("{base}/impression")],
+            tracking_events: vec![TrackingEvent {
+                event: "complete".to_string(),
+                url: format!("{base}/complete"),
+            }],
+            error_urls: Vec::new(),
+            total_segments: 2,
+            segment_index,
+            asset_uri: None,
+            duration: 10.0,
+            break_position: None,
+        }
+    }
+
+    fn targeted(params: &[(&str, &str)]) -> AdRequestContext {
+        AdRequestContext {
+            client_ip: Some("203.0.113.7".to_string()),
+            params: params
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            ..AdRequestContext::default()
+        }
+    }
+
+    #[test]
+    fn decision_id_depends_on_scope() {
+        let a = targeted(&[("ad.genre", "sport")]);
+        let b = targeted(&[("ad.genre", "news")]);
+        let origin = "https://origin.example.com/live.m3u8";
+
+        let session = SharedDecisions::new(DecisionScope::Session, Client::new());
+        assert_eq!(session.decision_id(origin, "news", &a), None);
+
+        let channel = SharedDecisions::new(DecisionScope::Channel, Client::new());
+        let id = channel.decision_id(origin, "news", &a).unwrap();
+        assert!(is_shared_decision(&id));
+        assert_eq!(channel.decision_id(origin, "news", &b).unwrap(), id);
+        assert_ne!(channel.decision_id(origin, "sport", &a).unwrap(), id);
+
+        let cohort = SharedDecisions::new(DecisionScope::Cohort, Client::new());
+        assert_ne!(
+            cohort.decision_id(origin, "news", &a),
+            cohort.decision_id(origin, "news", &b)
+        );
+        assert_eq!(
+            cohort.decision_id(origin, "news", &a),
+            cohort.decision_id(origin, "news", &a.clone())
+        );
+    }
+
+    #[test]
+    fn decision_context_drops_viewer() {
+        let ctx = targeted(&[("ad.genre", "sport")]);
+
+        let channel = SharedDecisions::new(DecisionScope::Channel, Client::new());
+        assert_eq!(channel.decision_context(&ctx), AdRequestContext::default());
+
+        let cohort = SharedDecisions::new(DecisionScope::Cohort, Client::new());
+        let decision_ctx = cohort.decision_context(&ctx);
+        assert_eq!(decision_ctx.client_ip, None);
+        assert_eq!(decision_ctx.params, ctx.params);
+    }
+
+    #[test]
+    fn shared_segment_names_carry_break_and_position() {
+        let name = shared_segment_name(120, 1, "break-3-seg-1.ts");
+        assert_eq!(name, "shared-120-seg-1.ts");
+        assert!(is_shared_segment(&name));
+        assert_eq!(parse_segment_name(&name), Some((120, 1)));
+        assert_eq!(
+            shared_segment_name(120, 0, "w1-break-0"),
+            "shared-120-seg-0"
+        );
+
+        assert_eq!(
+            shared_segment_name(120, 2, "slate-seg-0.ts"),
+            "slate-seg-0.ts"
+        );
+        assert_eq!(
+            shared_segment_name(120, 0, "http://ads.example.com/ad-segment-0.ts"),
+            "http://ads.example.com/ad-segment-0.ts"
+        );
+        assert!(!is_shared_segment("break-0-seg-0.ts"));
+        assert!(!is_shared_segment("shared-x-seg-0.ts"));
+    }
+
+    #[test]
+    fn shared_segments_keep_their_names_in_playlists() {
+        use crate::ad::provider::StaticAdProvider;
+
+        let decisions = SharedDecisions::new(DecisionScope::Channel, Client::new());
+        let provider = StaticAdProvider::new("http://ads.example.com".to_string(), 10.0);
+        let url = |name| {
+            decisions.segment_url(
+                &provider,
+                name,
+                "_shared-0123456789abcdef",
+                "http://localhost",
+                0,
+                1,
+            )
+        };
+
+        assert_eq!(
+            url("shared-121-seg-1.ts"),
+            "http://localhost/stitch/_shared-0123456789abcdef/ad/shared-121-seg-1.ts"
+        );
+        // Names the decision keeps are the provider's to map
+        assert_eq!(
+            url("http://ads.example.com/ad-segment-1.ts"),
+            "http://localhost/stitch/_shared-0123456789abcdef/ad/break-0-seg-1.ts"
+        );
+    }
+
+    #[test]
+    fn timeline_break_keys_are_milliseconds() {
+        assert_eq!(timeline_break_key(12.3456), 12_346);
+        assert_eq!(timeline_break_key(-1.0), 0);
+    }
+
+    #[tokio::test]
+    async fn route_resolves_through_the_break_decision() {
+        let store = SharedDecisions::new(DecisionScope::Channel, Client::new());
+        // The provider numbered this instance's breaks in its own order
+        store
+            .segments("_shared-1", 120, async {
+                vec![
+                    segment("break-1-seg-0.ts", None),
+                    segment("break-1-seg-1.ts", None),
+                ]
+            })
+            .await;
+        store
+            .segments("_shared-1", 130, async {
+                vec![segment("break-0-seg-0.ts", None)]
+            })
+            .await;
+
+        assert_eq!(
+            store.route("_shared-1", "shared-120-seg-1.ts").as_deref(),
+            Some("break-1-seg-1.ts")
+        );
+        assert_eq!(
+            store.route("_shared-1", "shared-130-seg-0.ts").as_deref(),
+            Some("break-0-seg-0.ts")
+        );
+        // Breaks and positions this instance has not decided
+        assert_eq!(store.route("_shared-1", "shared-130-seg-1.ts"), None);
+        assert_eq!(store.route("_shared-1", "shared-140-seg-0.ts"), None);
+        assert_eq!(store.route("_shared-2", "shared-120-seg-0.ts"), None);
+    }
+
+    #[tokio::test]
+    async fn concurrent_sessions_share_one_decision() {
+        let store = SharedDecisions::new(DecisionScope::Channel, Client::new());
+        let calls = AtomicUsize::new(0);
+        let decide = || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            vec![segment("break-0-seg-0.ts", None)]
+        };
+
+        let (a, b) = tokio::join!(
+            store.segments("_shared-1", 7, decide()),
+            store.segments("_shared-1", 7, decide())
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(a[0].uri, "shared-7-seg-0.ts");
+        assert_eq!(a[0].uri, b[0].uri);
+
+        store.segments("_shared-1", 8, decide()).await;
+        store.segments("_shared-2", 7, decide()).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert_eq!(store.len(), 3);
+
+        store.cleanup();
+        assert_eq!(store.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn track_fires_due_beacons_once_per_session() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/impression"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/complete"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let store = SharedDecisions::new(DecisionScope::Channel, Client::new());
+        let segments = vec![
+            segment("a.ts", Some(tracked(&server.uri(), 0))),
+            segment("b.ts", Some(tracked(&server.uri(), 1))),
+        ];
+        let viewer = AdRequestContext::default();
+
+        // Only the first segment is due 4s into the break
+        store.track("s1", "_shared-1", 7, &segments, 4.0, &viewer);
+        store.track("s1", "_shared-1", 7, &segments, 4.0, &viewer);
+        // The whole break is due for s1; s2 starts watching
+        store.track("s1", "_shared-1", 7, &segments, 20.0, &viewer);
+        store.track("s2", "_shared-1", 7, &segments, 4.0, &viewer);
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        server.verify().await;
+    }
+}
@@ -1,6 +1,20 @@
-use crate::ad::provider::{AdProvider, AdSegment};
+use crate::ad::context::AdRequestContext;
+use crate::ad::provider::{AdProvider, AdSegment, ad_segment_url};
+use crate::ad::source::{self, SourcePlaylist};
+use crate::error::Result;
 use async_trait::async_trait;
-use tracing::info;
+use m3u8_rs::{Key, KeyMethod, Map, MediaPlaylist};
+use reqwest::Client;
+use std::sync::{Arc, PoisonError, RwLock};
+use tracing::{info, warn};
+
+/// Gaps below this are rounding noise when filling a duration, in seconds
+const FILL_EPSILON: f32 = 0.01;
+
+/// Return `true` if `name` is a slate segment name served by [`SlateProvider`]
+pub fn is_slate_segment(name: &str) -> bool {
+    name.starts_with("slate-")
+}
 
 /// Slate provider for fallback content during ad breaks
 ///
@@ -10,68 +24,291 @@ use tracing::info;
 ///
 /// The slate is typically a short looping video ("We'll be right back",
 /// channel branding, etc.) that cycles to fill any duration.
+///
+/// When the slate URL points at an HLS playlist (`.m3u8`) or DASH MPD
+/// (`.mpd`), [`refresh`](Self::refresh) loads its real segments, durations,
+/// init sections and keys, and each content rendition gets the closest
+/// slate rendition. Otherwise the URL is treated as a directory of
+/// `out_NNN.ts` segments of the configured duration.
 #[derive(Clone, Debug)]
 pub struct SlateProvider {
-    /// Base URL for slate segments (HLS source with .ts segments)
+    /// Slate playlist URL, or base URL for `out_NNN.ts` slate segments
     slate_url: String,
-    /// Duration of each slate segment in seconds
+    /// Duration of each slate segment in seconds (directory slates only)
     segment_duration: f32,
     /// Number of available segments in the slate source (for cycling)
     segment_count: usize,
+    /// Last successfully loaded slate playlist, shared between clones
+    playlist: Arc<RwLock<Option<Arc<SourcePlaylist>>>>,
+}
+
+/// A parsed slate segment name
+#[derive(Debug, PartialEq)]
+struct SlateName {
+    /// Slate rendition, `None` for the default one
+    variant: Option<usize>,
+    /// Position in the filled break
+    index: usize,
+    /// `true` for the init section of the segment at `index`
+    init: bool,
+}
+
+impl SlateName {
+    /// Parse `slate-seg-{i}.ts`, `slate-v{v}-seg-{i}.{ext}` or
+    /// `slate-v{v}-init-{i}.{ext}`
+    fn parse(name: &str) -> Option<Self> {
+        let rest = name.strip_prefix("slate-")?;
+        let (stem, _ext) = rest.rsplit_once('.')?;
+
+        let (variant, stem) = match stem.strip_prefix('v') {
+            Some(v) => {
+                let (variant, stem) = v.split_once('-')?;
+                (Some(variant.parse().ok()?), stem)
+            }
+            None => (None, stem),
+        };
+        let (init, index) = if let Some(index) = stem.strip_prefix("seg-") {
+            (false, index)
+        } else if let Some(index) = stem.strip_prefix("init-") {
+            (true, index)
+        } else {
+            return None;
+        };
+        if init && variant.is_none() {
+            return None;
+        }
+
+        Some(Self {
+            variant,
+            index: index.parse().ok()?,
+            init,
+        })
+    }
 }
 
 impl SlateProvider {
     /// Create a new SlateProvider
     ///
     /// # Arguments
-    /// * `slate_url` - Base URL where slate segments are hosted
+    /// * `slate_url` - Slate playlist URL, or base URL where slate segments are hosted
     /// * `segment_duration` - Duration of each slate segment in seconds
     pub fn new(slate_url: String, segment_duration: f32) -> Self {
         Self {
             slate_url,
             segment_duration,
             segment_count: 10, // Default, same as static provider
+            playlist: Arc::new(RwLock::new(None)),
         }
     }
 
+    /// Return `true` if the slate URL is an HLS or DASH playlist to load
+    pub fn is_playlist(&self) -> bool {
+        source::is_playlist_url(&self.slate_url)
+    }
+
+    /// Load the slate playlist, replacing the previous one on success
+    ///
+    /// On failure the previously loaded playlist stays in use. Returns the
+    /// number of slate renditions loaded (0 for directory slates).
+    pub async fn refresh(&self, client: &Client) -> Result<usize> {
+        if !self.is_playlist() {
+            return Ok(0);
+        }
+        let loaded = source::load(client, &self.slate_url).await?;
+        let variants = loaded.variants.len();
+        info!(
+            "SlateProvider: Loaded {} rendition(s) from {} ({} segments in default rendition)",
+            variants,
+            self.slate_url,
+            loaded.variants[0].segments.len()
+        );
+        *self
+            .playlist
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = Some(Arc::new(loaded));
+        Ok(variants)
+    }
+
+    /// The currently loaded slate playlist, if any
+    pub fn loaded(&self) -> Option<Arc<SourcePlaylist>> {
+        self.playlist
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
     /// Generate slate segments to fill the given duration
     ///
-    /// Used directly by VastAdProvider when VAST returns empty or fails.
-    /// Cycles through available slate segments to fill the requested duration.
+    /// Used directly by VastAdProvider when VAST returns empty or fails, and
+    /// to pad pods shorter than the break. Cycles through available slate
+    /// segments, using the real durations of a loaded playlist. Segments are
+    /// never shortened, so filling stops at the last whole segment that fits
+    /// (at least one is always returned); whatever is left of the gap shows
+    /// up as drift against the live timeline.
     pub fn fill_duration(&self, duration: f32, session_id: &str) -> Vec<AdSegment> {
-        // Duration and segment_duration are positive f32; ceil() is non-negative
-        // and well within usize range for realistic durations.
-        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
-        let num_segments = (duration / self.segment_duration).ceil() as usize;
-        let num_segments = num_segments.max(1);
+        let loaded = self.loaded();
+        let source_duration = |i: usize| match &loaded {
+            Some(slate) => {
+                let segments = &slate.variants[0].segments;
+                segments[i % segments.len()].duration
+            }
+            None => self.segment_duration,
+        };
+
+        let mut segments = Vec::new();
+        let mut filled = 0.0_f32;
+        loop {
+            let i = segments.len();
+            let segment_duration = source_duration(i);
+            if !segments.is_empty() && filled + segment_duration > duration + FILL_EPSILON {
+                break;
+            }
+            segments.push(AdSegment {
+                uri: format!("slate-seg-{}.ts", i),
+                duration: segment_duration,
+                tracking: None,
+            });
+            filled += segment_duration;
+        }
 
         info!(
             "SlateProvider: Generating {} slate segments for session {} (duration: {}s)",
-            num_segments, session_id, duration
+            segments.len(),
+            session_id,
+            duration
         );
-
-        (0..num_segments)
-            .map(|i| AdSegment {
-                uri: format!("slate-seg-{}.ts", i),
-                duration: self.segment_duration,
-                tracking: None,
-            })
-            .collect()
+        segments
     }
 
     /// Resolve a slate segment identifier to its actual source URL
     ///
-    /// Slate segments use the naming format "slate-seg-{index}.ts"
+    /// Slate segments use the naming format "slate-seg-{index}.ts"; a loaded
+    /// playlist also serves "slate-v{variant}-seg-{index}.{ext}" and the
+    /// init sections "slate-v{variant}-init-{index}.{ext}".
     pub fn resolve_segment_url(&self, segment_name: &str) -> Option<String> {
-        let index = segment_name
-            .strip_prefix("slate-seg-")
-            .and_then(|s| s.strip_suffix(".ts"))
-            .and_then(|s| s.parse::<usize>().ok())?;
+        let name = SlateName::parse(segment_name)?;
+
+        if !self.is_playlist() {
+            if name.variant.is_some() {
+                return None;
+            }
+            let source_index = name.index % self.segment_count;
+            let source_segment = format!("out_{:03}.ts", source_index);
+            return Some(format!("{}/{}", self.slate_url, source_segment));
+        }
+
+        let Some(slate) = self.loaded() else {
+            warn!(
+                "SlateProvider: Slate playlist {} not loaded, cannot resolve {}",
+                self.slate_url, segment_name
+            );
+            return None;
+        };
+        let variant = slate.variants.get(name.variant.unwrap_or(0))?;
+        let source = &variant.segments[name.index % variant.segments.len()];
+        if name.init {
+            source.init.clone()
+        } else {
+            Some(source.uri.clone())
+        }
+    }
 
-        let source_index = index % self.segment_count;
-        let source_segment = format!("out_{:03}.ts", source_index);
+    /// Point slate segments of an interleaved playlist at the slate
+    /// rendition matching the content `bandwidth`
+    ///
+    /// Renames `slate-seg-{i}.ts` entries to rendition-specific names and
+    /// signals the slate's `EXT-X-MAP` and `EXT-X-KEY`: `METHOD=NONE` when a
+    /// clear slate follows encrypted content, and the content key and map
+    /// restated on the first content segment after the slate. Does nothing
+    /// for directory slates or before the playlist is loaded.
+    pub fn annotate_playlist(
+        &self,
+        playlist: &mut MediaPlaylist,
+        bandwidth: Option<u64>,
+        session_id: &str,
+        base_url: &str,
+    ) {
+        let Some(slate) = self.loaded() else {
+            return;
+        };
+        let variant_idx = slate.variant_index(bandwidth);
+        let variant = &slate.variants[variant_idx];
+        let prefix = format!("{}/stitch/{}/ad/", base_url, session_id);
+        let clear = || Key {
+            method: KeyMethod::None,
+            ..Key::default()
+        };
+
+        let mut content_key: Option<Key> = None;
+        let mut content_map: Option<Map> = None;
+        // Key and map the player has in effect at this point of the playlist
+        let mut current_key: Option<Key> = None;
+        let mut current_map: Option<Map> = None;
+
+        for segment in &mut playlist.segments {
+            let (key, map) = match segment.uri.strip_prefix(&prefix) {
+                Some(ad_name) => {
+                    let Some(name) =
+                        SlateName::parse(ad_name).filter(|n| n.variant.is_none() && !n.init)
+                    else {
+                        // Other ad segments keep whatever the interleaver set
+                        if segment.key.is_some() {
+                            current_key = segment.key.clone();
+                        }
+                        if segment.map.is_some() {
+                            current_map = segment.map.clone();
+                        }
+                        continue;
+                    };
 
-        Some(format!("{}/{}", self.slate_url, source_segment))
+                    let source = &variant.segments[name.index % variant.segments.len()];
+                    let ext = source::extension(&source.uri).unwrap_or("ts");
+                    segment.uri = format!(
+                        "{}slate-v{}-seg-{}.{}",
+                        prefix, variant_idx, name.index, ext
+                    );
+
+                    // Name the init section after the first segment using it so
+                    // consecutive segments sharing it signal it once
+                    let map = source.init.as_ref().map(|init| {
+                        let first = variant
+                            .segments
+                            .iter()
+                            .position(|s| s.init.as_ref() == Some(init))
+                            .unwrap_or_default();
+                        Map {
+                            uri: format!(
+                                "{}slate-v{}-init-{}.{}",
+                                prefix,
+                                variant_idx,
+                                first,
+                                source::extension(init).unwrap_or("mp4")
+                            ),
+                            ..Map::default()
+                        }
+                    });
+                    (source.key.clone(), map)
+                }
+                None => {
+                    if let Some(key) = &segment.key {
+                        content_key = (key.method != KeyMethod::None).then(|| key.clone());
+                    }
+                    if let Some(map) = &segment.map {
+                        content_map = Some(map.clone());
+                    }
+                    (content_key.clone(), content_map.clone())
+                }
+            };
+
+            if key != current_key {
+                segment.key = Some(key.clone().unwrap_or_else(clear));
+            }
+            current_key = key;
+            if map.is_some() && map != current_map {
+                segment.map = map.clone();
+                current_map = map;
+            }
+        }
     }
 }
 
@@ -81,18 +318,41 @@ impl SlateProvider {
 /// to serve slate content for all ad breaks. Also useful for testing.
 #[async_trait]
 impl AdProvider for SlateProvider {
-    async fn get_ad_segments(&self, duration: f32, session_id: &str) -> Vec<AdSegment> {
+    async fn get_ad_segments(
+        &self,
+        duration: f32,
+        session_id: &str,
+        _ctx: &AdRequestContext,
+    ) -> Vec<AdSegment> {
         self.fill_duration(duration, session_id)
     }
 
     fn resolve_segment_url(&self, ad_name: &str, _session_id: &str) -> Option<String> {
         self.resolve_segment_url(ad_name)
     }
+
+    fn segment_url(
+        &self,
+        segment_name: &str,
+        session_id: &str,
+        base_url: &str,
+        _break_idx: usize,
+        _segment_idx: usize,
+    ) -> String {
+        ad_segment_url(base_url, session_id, segment_name)
+    }
+
+    fn slate(&self) -> Option<&SlateProvider> {
+        Some(self)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use m3u8_rs::MediaSegment;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
 
     #[test]
     fn test_fill_duration_exact() {
@@ -112,8 +372,9 @@ mod tests {
         let provider = SlateProvider::new("https://slate.example.com".to_string(), 2.0);
         let segments = provider.fill_duration(7.0, "test-session");
 
-        // 7.0 / 2.0 = 3.5, ceil = 4
-        assert_eq!(segments.len(), 4);
+        // 7.0 / 2.0 = 3.5, whole segments stop at 3; the remaining 1s is drift
+        assert_eq!(segments.len(), 3);
+        assert!(segments.iter().all(|s| s.duration == 2.0));
     }
 
     #[test]
@@ -156,10 +417,156 @@ mod tests {
         let provider = SlateProvider::new("https://slate.example.com".to_string(), 2.0);
 
         // Test via AdProvider trait
-        let segments = provider.get_ad_segments(6.0, "session-1").await;
+        let segments = provider
+            .get_ad_segments(6.0, "session-1", &AdRequestContext::default())
+            .await;
         assert_eq!(segments.len(), 3);
 
         let url = AdProvider::resolve_segment_url(&provider, "slate-seg-0.ts", "session-1");
         assert!(url.is_some());
     }
+
+    const SLATE_MASTER: &str = "#EXTM3U\n\
+        #EXT-X-STREAM-INF:BANDWIDTH=500000\nlow/index.m3u8\n\
+        #EXT-X-STREAM-INF:BANDWIDTH=2000000\nhigh/index.m3u8\n";
+
+    fn slate_media(name: &str) -> String {
+        format!(
+            "#EXTM3U\n#EXT-X-TARGETDURATION:2\n#EXT-X-MAP:URI=\"init.mp4\"\n\
+             #EXTINF:2.0,\n{name}0.m4s\n#EXTINF:2.0,\n{name}1.m4s\n#EXTINF:1.5,\n{name}2.m4s\n\
+             #EXT-X-ENDLIST\n"
+        )
+    }
+
+    async fn loaded_provider() -> (MockServer, SlateProvider) {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/slate/master.m3u8"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(SLATE_MASTER))
+            .mount(&server)
+            .await;
+        for (variant, name) in [("low", "lo"), ("high", "hi")] {
+            Mock::given(method("GET"))
+                .and(path(format!("/slate/{}/index.m3u8", variant)))
+                .respond_with(ResponseTemplate::new(200).set_body_string(slate_media(name)))
+                .mount(&server)
+                .await;
+        }
+
+        let provider = SlateProvider::new(format!("{}/slate/master.m3u8", server.uri()), 1.0);
+        let variants = provider.refresh(&Client::new()).await.unwrap();
+        assert_eq!(variants, 2);
+        (server, provider)
+    }
+
+    #[tokio::test]
+    async fn test_refresh_directory_slate_is_noop() {
+        let provider = SlateProvider::new("https://slate.example.com/content".to_string(), 1.0);
+        assert!(!provider.is_playlist());
+        assert_eq!(provider.refresh(&Client::new()).await.unwrap(), 0);
+        assert!(provider.loaded().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_fill_duration_loops_real_segment_durations() {
+        let (_server, provider) = loaded_provider().await;
+        let segments = provider.fill_duration(7.0, "test-session");
+
+        // 2.0 + 2.0 + 1.5 + 2.0 (looped) = 7.5 overruns, so the fill stops at 5.5s
+        let durations: Vec<f32> = segments.iter().map(|s| s.duration).collect();
+        assert_eq!(durations, vec![2.0, 2.0, 1.5]);
+
+        // Past the end of the playlist the loop restarts with real durations
+        let segments = provider.fill_duration(9.5, "test-session");
+        let durations: Vec<f32> = segments.iter().map(|s| s.duration).collect();
+        assert_eq!(durations, vec![2.0, 2.0, 1.5, 2.0, 2.0]);
+        assert_eq!(segments[3].uri, "slate-seg-3.ts");
+    }
+
+    #[tokio::test]
+    async fn test_resolve_segment_url_uses_playlist_uris() {
+        let (server, provider) = loaded_provider().await;
+        let base = format!("{}/slate", server.uri());
+
+        assert_eq!(
+            provider.resolve_segment_url("slate-seg-4.ts"),
+            Some(format!("{}/low/lo1.m4s", base))
+        );
+        assert_eq!(
+            provider.resolve_segment_url("slate-v1-seg-2.m4s"),
+            Some(format!("{}/high/hi2.m4s", base))
+        );
+        assert_eq!(
+            provider.resolve_segment_url("slate-v1-init-0.mp4"),
+            Some(format!("{}/high/init.mp4", base))
+        );
+        assert_eq!(provider.resolve_segment_url("slate-v2-seg-0.m4s"), None);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_failure_keeps_previous_playlist() {
+        let (server, provider) = loaded_provider().await;
+        server.reset().await;
+
+        assert!(provider.refresh(&Client::new()).await.is_err());
+        assert!(provider.loaded().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_annotate_playlist_selects_variant_and_signals_map_and_key() {
+        let (_server, provider) = loaded_provider().await;
+        let base = "http://stitcher";
+        let ad = |name: &str| MediaSegment {
+            uri: format!("{}/stitch/s1/ad/{}", base, name),
+            duration: 2.0,
+            ..Default::default()
+        };
+        let content_key = Key {
+            method: KeyMethod::AES128,
+            uri: Some("https://keys.example.com/k".to_string()),
+            ..Key::default()
+        };
+        let mut playlist = MediaPlaylist {
+            segments: vec![
+                MediaSegment {
+                    uri: format!("{}/stitch/s1/segment/c0.ts", base),
+                    duration: 2.0,
+                    key: Some(content_key.clone()),
+                    ..Default::default()
+                },
+                ad("slate-seg-0.ts"),
+                ad("slate-seg-1.ts"),
+                MediaSegment {
+                    uri: format!("{}/stitch/s1/segment/c1.ts", base),
+                    duration: 2.0,
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+
+        provider.annotate_playlist(&mut playlist, Some(3_000_000), "s1", base);
+        let segments = &playlist.segments;
+
+        assert_eq!(
+            segments[1].uri,
+            "http://stitcher/stitch/s1/ad/slate-v1-seg-0.m4s"
+        );
+        assert_eq!(
+            segments[1].map.as_ref().map(|m| m.uri.as_str()),
+            Some("http://stitcher/stitch/s1/ad/slate-v1-init-0.mp4")
+        );
+        assert_eq!(
+            segments[1].key.as_ref().map(|k| &k.method),
+            Some(&KeyMethod::None),
+            "Clear slate after encrypted content"
+        );
+        assert_eq!(segments[2].map, None, "Init signalled once");
+        assert_eq!(segments[2].key, None);
+        assert_eq!(
+            segments[3].key,
+            Some(content_key),
+            "Content key restated after the slate"
+        );
+    }
 }
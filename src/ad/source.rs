@@ -0,0 +1,678 @@
+//! Media source playlist loading
+//!
+//! Reads an HLS (master or media) or DASH playlist into a flat list of
+//! segments per rendition, keeping the real segment URIs, durations, init
+//! sections and keys. Used for the slate
+//! ([`SlateProvider`](super::slate::SlateProvider)) and for house-ad
+//! creatives ([`StaticAdProvider`](super::StaticAdProvider)).
+
+use crate::dash::parser::parse_mpd;
+use crate::error::{Result, RitcherError};
+use crate::server::MAX_MANIFEST_SIZE;
+use dash_mpd::{AdaptationSet, BaseURL, Period, Representation, SegmentTimeline};
+use futures_util::StreamExt;
+use m3u8_rs::{Key, KeyMethod, MediaPlaylist, Playlist};
+use reqwest::Client;
+use tracing::{info, warn};
+use url::Url;
+
+/// One segment of a source playlist
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceSegment {
+    /// Absolute segment URL
+    pub uri: String,
+    /// Segment duration in seconds
+    pub duration: f32,
+    /// Absolute URL of the init section (`EXT-X-MAP` / DASH Initialization)
+    pub init: Option<String>,
+    /// Key in effect for this segment, with an absolute URI
+    pub key: Option<Key>,
+}
+
+/// One rendition of a source playlist
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceVariant {
+    /// Declared bandwidth in bits/s (`None` for a single media playlist)
+    pub bandwidth: Option<u64>,
+    /// Segments in playback order
+    pub segments: Vec<SourceSegment>,
+}
+
+/// A loaded source playlist: one or more renditions, the first being the default
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourcePlaylist {
+    /// Renditions in source order; never empty and never with empty segments
+    pub variants: Vec<SourceVariant>,
+}
+
+impl SourcePlaylist {
+    /// Build a playlist, dropping zero-length segments and renditions
+    /// without segments
+    fn from_variants(variants: Vec<SourceVariant>) -> Result<Self> {
+        let variants: Vec<SourceVariant> = variants
+            .into_iter()
+            .map(|mut v| {
+                v.segments.retain(|s| s.duration > 0.0);
+                v
+            })
+            .filter(|v| !v.segments.is_empty())
+            .collect();
+        if variants.is_empty() {
+            return Err(RitcherError::PlaylistParseError(
+                "Source playlist has no segments".to_string(),
+            ));
+        }
+        Ok(Self { variants })
+    }
+
+    /// Pick the rendition for a content rendition of `bandwidth`
+    ///
+    /// Returns the highest-bandwidth rendition not above `bandwidth`, or the
+    /// lowest one when all are above it. Without a bandwidth, or when the
+    /// source does not declare any, the first rendition is used.
+    pub fn variant_index(&self, bandwidth: Option<u64>) -> usize {
+        let Some(bandwidth) = bandwidth else {
+            return 0;
+        };
+        let declared = self
+            .variants
+            .iter()
+            .enumerate()
+            .filter_map(|(i, v)| v.bandwidth.map(|bw| (i, bw)));
+
+        let mut below: Option<(usize, u64)> = None;
+        let mut lowest: Option<(usize, u64)> = None;
+        for (i, bw) in declared {
+            if bw <= bandwidth && below.is_none_or(|(_, b)| bw > b) {
+                below = Some((i, bw));
+            }
+            if lowest.is_none_or(|(_, b)| bw < b) {
+                lowest = Some((i, bw));
+            }
+        }
+        below.or(lowest).map_or(0, |(i, _)| i)
+    }
+}
+
+/// Return `true` if `url` points at an HLS or DASH playlist rather than a
+/// directory of `out_NNN.ts` segments
+pub fn is_playlist_url(url: &str) -> bool {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    path.ends_with(".m3u8") || path.ends_with(".mpd")
+}
+
+/// Fetch and parse the playlist at `url`
+///
+/// An HLS master playlist yields one rendition per variant stream (I-frame
+/// streams are skipped); a DASH MPD yields one per video Representation of
+/// the first Period.
+pub async fn load(client: &Client, url: &str) -> Result<SourcePlaylist> {
+    let base = parse_url(url)?;
+    let body = fetch(client, &base).await?;
+
+    let path = base.path();
+    if path.ends_with(".mpd") {
+        return SourcePlaylist::from_variants(parse_dash(&body, &base)?);
+    }
+
+    let playlist = m3u8_rs::parse_playlist_res(body.as_bytes()).map_err(|e| {
+        RitcherError::PlaylistParseError(format!(
+            "Failed to parse source playlist {}: {:?}",
+            url, e
+        ))
+    })?;
+
+    let variants = match playlist {
+        Playlist::MediaPlaylist(media) => vec![parse_hls_media(&media, &base, None)?],
+        Playlist::MasterPlaylist(master) => {
+            let mut variants = Vec::with_capacity(master.variants.len());
+            for variant in master.variants.iter().filter(|v| !v.is_i_frame) {
+                let url = join(&base, &variant.uri)?;
+                let body = fetch(client, &url).await?;
+                match m3u8_rs::parse_media_playlist_res(body.as_bytes()) {
+                    Ok(media) => {
+                        variants.push(parse_hls_media(&media, &url, Some(variant.bandwidth))?)
+                    }
+                    Err(e) => warn!("Skipping unparseable variant {}: {:?}", url, e),
+                }
+            }
+            variants
+        }
+    };
+
+    SourcePlaylist::from_variants(variants)
+}
+
+/// File extension of a URL path, without query or fragment
+pub(crate) fn extension(url: &str) -> Option<&str> {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let file = path.rsplit('/').next().unwrap_or(path);
+    file.rsplit_once('.')
+        .map(|(_, ext)| ext)
+        .filter(|e| !e.is_empty())
+}
+
+/// GET `url` as text, bounded by [`MAX_MANIFEST_SIZE`]
+async fn fetch(client: &Client, url: &Url) -> Result<String> {
+    info!("Fetching source playlist: {}", url);
+    let response = client.get(url.as_str()).send().await?.error_for_status()?;
+    if response
+        .content_length()
+        .is_some_and(|len| len > MAX_MANIFEST_SIZE)
+    {
+        return Err(RitcherError::ResponseTooLarge(format!(
+            "Source playlist {} exceeds {} byte limit",
+            url, MAX_MANIFEST_SIZE
+        )));
+    }
+
+    // Stream body incrementally to enforce size limit.
+    // Unlike `response.bytes()` which buffers the entire body before
+    // the size check, this aborts as soon as the limit is exceeded —
+    // protecting against chunked-encoding OOM attacks.
+    let mut body_buf = Vec::new();
+    let mut stream = response.bytes_stream();
+    while let Some(chunk_result) = stream.next().await {
+        let chunk = chunk_result?;
+        body_buf.extend_from_slice(&chunk);
+        if body_buf.len() as u64 > MAX_MANIFEST_SIZE {
+            return Err(RitcherError::ResponseTooLarge(format!(
+                "Source playlist {} exceeded {} byte limit while streaming",
+                url, MAX_MANIFEST_SIZE
+            )));
+        }
+    }
+
+    String::from_utf8(body_buf).map_err(|e| {
+        RitcherError::PlaylistParseError(format!("Source playlist is not UTF-8: {}", e))
+    })
+}
+
+fn parse_url(url: &str) -> Result<Url> {
+    Url::parse(url).map_err(|e| {
+        RitcherError::ConfigError(format!("Invalid source playlist URL {}: {}", url, e))
+    })
+}
+
+fn join(base: &Url, reference: &str) -> Result<Url> {
+    base.join(reference).map_err(|e| {
+        RitcherError::PlaylistParseError(format!(
+            "Invalid URI {} relative to {}: {}",
+            reference, base, e
+        ))
+    })
+}
+
+/// Flatten an HLS media playlist, carrying `EXT-X-KEY` and `EXT-X-MAP` forward
+fn parse_hls_media(
+    media: &MediaPlaylist,
+    base: &Url,
+    bandwidth: Option<u64>,
+) -> Result<SourceVariant> {
+    let mut key: Option<Key> = None;
+    let mut init: Option<String> = None;
+    let mut segments = Vec::with_capacity(media.segments.len());
+
+    for segment in &media.segments {
+        // m3u8-rs rejects `METHOD=NONE` without an IV and keeps the tag as
+        // unknown, so clear the key from there as well
+        if segment.unknown_tags.iter().any(|t| {
+            t.tag == "X-KEY"
+                && t.rest
+                    .as_deref()
+                    .is_some_and(|r| r.starts_with("METHOD=NONE"))
+        }) {
+            key = None;
+        }
+        if let Some(k) = &segment.key {
+            key = if k.method == KeyMethod::None {
+                None
+            } else {
+                let uri = match &k.uri {
+                    Some(uri) => Some(join(base, uri)?.to_string()),
+                    None => None,
+                };
+                Some(Key { uri, ..k.clone() })
+            };
+        }
+        if let Some(map) = &segment.map {
+            init = Some(join(base, &map.uri)?.to_string());
+        }
+        segments.push(SourceSegment {
+            uri: join(base, &segment.uri)?.to_string(),
+            duration: segment.duration,
+            init: init.clone(),
+            key: key.clone(),
+        });
+    }
+
+    Ok(SourceVariant {
+        bandwidth,
+        segments,
+    })
+}
+
+/// Flatten the video Representations of the first Period of a DASH MPD
+fn parse_dash(body: &str, base: &Url) -> Result<Vec<SourceVariant>> {
+    let mpd = parse_mpd(body)?;
+    let period = mpd
+        .periods
+        .first()
+        .ok_or_else(|| RitcherError::MpdParseError("Source MPD has no Period".to_string()))?;
+    let period_duration = period
+        .duration
+        .or(mpd.mediaPresentationDuration)
+        .map(|d| d.as_secs_f64());
+
+    let base = with_base_url(base, &mpd.base_url)?;
+    let base = with_base_url(&base, &period.BaseURL)?;
+
+    let mut variants = Vec::new();
+    for adaptation in period.adaptations.iter().filter(|a| is_video(a)) {
+        let base = with_base_url(&base, &adaptation.BaseURL)?;
+        for rep in &adaptation.representations {
+            let base = with_base_url(&base, &rep.BaseURL)?;
+            variants.push(SourceVariant {
+                bandwidth: rep.bandwidth,
+                segments: dash_segments(period, adaptation, rep, &base, period_duration)?,
+            });
+        }
+    }
+    Ok(variants)
+}
+
+fn with_base_url(base: &Url, base_urls: &[BaseURL]) -> Result<Url> {
+    match base_urls.first() {
+        Some(b) => join(base, &b.base),
+        None => Ok(base.clone()),
+    }
+}
+
+fn is_video(adaptation: &AdaptationSet) -> bool {
+    if let Some(content_type) = &adaptation.contentType {
+        return content_type == "video";
+    }
+    adaptation
+        .mimeType
+        .as_deref()
+        .or_else(|| {
+            adaptation
+                .representations
+                .first()
+                .and_then(|r| r.mimeType.as_deref())
+        })
+        .is_some_and(|m| m.starts_with("video/"))
+}
+
+/// List the segments of one Representation from its SegmentList or
+/// SegmentTemplate (inherited from the AdaptationSet or Period)
+fn dash_segments(
+    period: &Period,
+    adaptation: &AdaptationSet,
+    rep: &Representation,
+    base: &Url,
+    period_duration: Option<f64>,
+) -> Result<Vec<SourceSegment>> {
+    let rep_id = rep.id.as_deref().unwrap_or_default();
+    let bandwidth = rep.bandwidth.unwrap_or_default();
+
+    if let Some(list) = rep.SegmentList.as_ref().or(adaptation.SegmentList.as_ref()) {
+        let timescale = list.timescale.unwrap_or(1).max(1);
+        let init = match list
+            .Initialization
+            .as_ref()
+            .and_then(|i| i.sourceURL.as_deref())
+        {
+            Some(url) => Some(join(base, url)?.to_string()),
+            None => None,
+        };
+        let durations: Vec<u64> = match &list.SegmentTimeline {
+            Some(timeline) => timeline_entries(timeline, timescale, period_duration)
+                .into_iter()
+                .map(|(_, d)| d)
+                .collect(),
+            None => vec![list.duration.unwrap_or_default(); list.segment_urls.len()],
+        };
+        return list
+            .segment_urls
+            .iter()
+            .zip(durations)
+            .filter_map(|(s, d)| s.media.as_deref().map(|m| (m, d)))
+            .map(|(media, d)| {
+                Ok(SourceSegment {
+                    uri: join(base, media)?.to_string(),
+                    duration: ticks_to_secs(d, timescale),
+                    init: init.clone(),
+                    key: None,
+                })
+            })
+            .collect();
+    }
+
+    let Some(template) = rep
+        .SegmentTemplate
+        .as_ref()
+        .or(adaptation.SegmentTemplate.as_ref())
+        .or(period.SegmentTemplate.as_ref())
+    else {
+        return Err(RitcherError::MpdParseError(format!(
+            "Representation {} has no SegmentList or SegmentTemplate",
+            rep_id
+        )));
+    };
+    let Some(media) = template.media.as_deref() else {
+        return Err(RitcherError::MpdParseError(format!(
+            "Representation {} SegmentTemplate has no @media",
+            rep_id
+        )));
+    };
+
+    let timescale = template.timescale.unwrap_or(1).max(1);
+    let start_number = template.startNumber.unwrap_or(1);
+    let init = match template.initialization.as_deref() {
+        Some(init) => {
+            Some(join(base, &expand_template(init, rep_id, bandwidth, 0, 0))?.to_string())
+        }
+        None => None,
+    };
+
+    // (time, duration) in timescale units
+    let entries: Vec<(u64, u64)> = if let Some(timeline) = &template.SegmentTimeline {
+        timeline_entries(timeline, timescale, period_duration)
+    } else {
+        // @duration may be fractional in the wild; whole ticks are enough here
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let duration = template.duration.unwrap_or_default() as u64;
+        let Some(period_duration) = period_duration.filter(|_| duration > 0) else {
+            return Err(RitcherError::MpdParseError(format!(
+                "Representation {} needs a SegmentTimeline or @duration and a Period duration",
+                rep_id
+            )));
+        };
+        let total = period_duration * timescale as f64;
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let count = (total / duration as f64).ceil() as u64;
+        (0..count)
+            .map(|i| {
+                let time = i * duration;
+                #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                let left = (total as u64).saturating_sub(time);
+                (time, duration.min(left.max(1)))
+            })
+            .collect()
+    };
+
+    entries
+        .into_iter()
+        .zip(start_number..)
+        .map(|((time, duration), number)| {
+            let uri = expand_template(media, rep_id, bandwidth, number, time);
+            Ok(SourceSegment {
+                uri: join(base, &uri)?.to_string(),
+                duration: ticks_to_secs(duration, timescale),
+                init: init.clone(),
+                key: None,
+            })
+        })
+        .collect()
+}
+
+/// Expand a SegmentTimeline into `(time, duration)` pairs
+///
+/// A negative `@r` repeats until the end of the Period when its duration is
+/// known, and is treated as no repeat otherwise.
+fn timeline_entries(
+    timeline: &SegmentTimeline,
+    timescale: u64,
+    period_duration: Option<f64>,
+) -> Vec<(u64, u64)> {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let period_end = period_duration.map(|d| (d * timescale as f64) as u64);
+    let mut entries = Vec::new();
+    let mut time = 0_u64;
+
+    for s in &timeline.segments {
+        if let Some(t) = s.t {
+            time = t;
+        }
+        if s.d == 0 {
+            continue;
+        }
+        let repeat = match s.r {
+            Some(r) if r >= 0 => r.unsigned_abs(),
+            Some(_) => period_end.map_or(0, |end| {
+                end.saturating_sub(time).div_ceil(s.d).saturating_sub(1)
+            }),
+            None => 0,
+        };
+        for _ in 0..=repeat {
+            entries.push((time, s.d));
+            time += s.d;
+        }
+    }
+    entries
+}
+
+/// Substitute DASH template identifiers, including `%0Nd` width formats
+fn expand_template(template: &str, rep_id: &str, bandwidth: u64, number: u64, time: u64) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('$') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let Some(end) = after.find('$') else {
+            out.push_str(&rest[start..]);
+            return out;
+        };
+        let ident = &after[..end];
+        let (name, format) = ident.split_once('%').unwrap_or((ident, ""));
+        let value = match name {
+            "" => Some("$".to_string()),
+            "RepresentationID" => Some(rep_id.to_string()),
+            "Bandwidth" => Some(pad(bandwidth, format)),
+            "Number" => Some(pad(number, format)),
+            "Time" => Some(pad(time, format)),
+            _ => None,
+        };
+        match value {
+            Some(v) => out.push_str(&v),
+            None => {
+                out.push('$');
+                out.push_str(ident);
+                out.push('$');
+            }
+        }
+        rest = &after[end + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Format `value` with a `0Nd` width specifier
+fn pad(value: u64, format: &str) -> String {
+    let width = format
+        .strip_suffix('d')
+        .and_then(|w| w.trim_start_matches('0').parse::<usize>().ok())
+        .unwrap_or(0);
+    format!("{:0width$}", value, width = width)
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn ticks_to_secs(ticks: u64, timescale: u64) -> f32 {
+    (ticks as f64 / timescale as f64) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn base() -> Url {
+        Url::parse("https://slate.example.com/slate/index.m3u8").unwrap()
+    }
+
+    #[test]
+    fn is_playlist_url_checks_extension() {
+        assert!(is_playlist_url("https://s.example.com/slate.m3u8"));
+        assert!(is_playlist_url("https://s.example.com/slate.mpd?token=1"));
+        assert!(!is_playlist_url("https://s.example.com/content"));
+    }
+
+    #[test]
+    fn hls_media_carries_key_and_map_forward() {
+        let m3u8 = "#EXTM3U\n#EXT-X-TARGETDURATION:2\n\
+            #EXT-X-MAP:URI=\"init.mp4\"\n\
+            #EXT-X-KEY:METHOD=SAMPLE-AES,URI=\"keys/k1\"\n\
+            #EXTINF:2.0,\nseg0.m4s\n#EXTINF:1.5,\nseg1.m4s\n\
+            #EXT-X-KEY:METHOD=NONE\n#EXTINF:2.0,\nhttps://cdn.example.com/seg2.m4s\n";
+        let media = m3u8_rs::parse_media_playlist_res(m3u8.as_bytes()).unwrap();
+        let variant = parse_hls_media(&media, &base(), Some(800_000)).unwrap();
+
+        assert_eq!(variant.bandwidth, Some(800_000));
+        assert_eq!(variant.segments.len(), 3);
+        assert_eq!(
+            variant.segments[0].uri,
+            "https://slate.example.com/slate/seg0.m4s"
+        );
+        assert_eq!(variant.segments[1].duration, 1.5);
+        assert_eq!(
+            variant.segments[1].init.as_deref(),
+            Some("https://slate.example.com/slate/init.mp4")
+        );
+        let key = variant.segments[1].key.as_ref().unwrap();
+        assert_eq!(key.method, KeyMethod::SampleAES);
+        assert_eq!(
+            key.uri.as_deref(),
+            Some("https://slate.example.com/slate/keys/k1")
+        );
+        assert_eq!(variant.segments[2].key, None);
+        assert_eq!(variant.segments[2].uri, "https://cdn.example.com/seg2.m4s");
+    }
+
+    #[test]
+    fn dash_template_with_timeline() {
+        let mpd = r#"<?xml version="1.0"?>
+<MPD xmlns="urn:mpeg:dash:schema:mpd:2011" type="static" mediaPresentationDuration="PT5S">
+  <Period id="p0">
+    <AdaptationSet contentType="video" mimeType="video/mp4">
+      <SegmentTemplate timescale="1000" initialization="$RepresentationID$/init.mp4" media="$RepresentationID$/seg-$Number%03d$.m4s" startNumber="1">
+        <SegmentTimeline><S t="0" d="2000" r="1"/><S d="1000"/></SegmentTimeline>
+      </SegmentTemplate>
+      <Representation id="v1" bandwidth="500000"/>
+      <Representation id="v2" bandwidth="2000000"/>
+    </AdaptationSet>
+    <AdaptationSet contentType="audio" mimeType="audio/mp4">
+      <Representation id="a1" bandwidth="128000"/>
+    </AdaptationSet>
+  </Period>
+</MPD>"#;
+        let base = Url::parse("https://slate.example.com/dash/slate.mpd").unwrap();
+        let variants = parse_dash(mpd, &base).unwrap();
+
+        assert_eq!(variants.len(), 2, "audio is not a video rendition");
+        assert_eq!(variants[1].bandwidth, Some(2_000_000));
+        let segments = &variants[0].segments;
+        assert_eq!(segments.len(), 3);
+        assert_eq!(
+            segments[0].uri,
+            "https://slate.example.com/dash/v1/seg-001.m4s"
+        );
+        assert_eq!(
+            segments[2].uri,
+            "https://slate.example.com/dash/v1/seg-003.m4s"
+        );
+        assert_eq!(segments[2].duration, 1.0);
+        assert_eq!(
+            segments[0].init.as_deref(),
+            Some("https://slate.example.com/dash/v1/init.mp4")
+        );
+    }
+
+    #[test]
+    fn dash_template_with_duration_fills_period() {
+        let mpd = r#"<?xml version="1.0"?>
+<MPD xmlns="urn:mpeg:dash:schema:mpd:2011" type="static" mediaPresentationDuration="PT5S">
+  <Period>
+    <AdaptationSet mimeType="video/mp4">
+      <Representation id="v" bandwidth="1000000">
+        <SegmentTemplate timescale="1" duration="2" media="seg-$Time$.m4s"/>
+      </Representation>
+    </AdaptationSet>
+  </Period>
+</MPD>"#;
+        let base = Url::parse("https://slate.example.com/slate.mpd").unwrap();
+        let variants = parse_dash(mpd, &base).unwrap();
+        let segments = &variants[0].segments;
+
+        assert_eq!(segments.len(), 3);
+        assert_eq!(segments[1].uri, "https://slate.example.com/seg-2.m4s");
+        assert_eq!(segments[2].duration, 1.0);
+    }
+
+    #[test]
+    fn expand_template_substitutes_identifiers() {
+        assert_eq!(
+            expand_template(
+                "$RepresentationID$_$Bandwidth$/$Time$-$Number%05d$$$.m4s",
+                "hd",
+                5000,
+                7,
+                9000
+            ),
+            "hd_5000/9000-00007$.m4s"
+        );
+        assert_eq!(
+            expand_template("seg-$Unknown$.m4s", "x", 0, 1, 0),
+            "seg-$Unknown$.m4s"
+        );
+    }
+
+    #[test]
+    fn variant_index_picks_highest_not_above_bandwidth() {
+        let variant = |bw| SourceVariant {
+            bandwidth: Some(bw),
+            segments: vec![SourceSegment {
+                uri: "https://s/seg.ts".to_string(),
+                duration: 1.0,
+                init: None,
+                key: None,
+            }],
+        };
+        let playlist = SourcePlaylist {
+            variants: vec![variant(2_000_000), variant(500_000), variant(1_000_000)],
+        };
+
+        assert_eq!(playlist.variant_index(None), 0);
+        assert_eq!(playlist.variant_index(Some(1_500_000)), 2);
+        assert_eq!(playlist.variant_index(Some(5_000_000)), 0);
+        assert_eq!(
+            playlist.variant_index(Some(100_000)),
+            1,
+            "falls back to lowest"
+        );
+    }
+
+    #[test]
+    fn empty_source_is_an_error() {
+        let result = SourcePlaylist::from_variants(vec![SourceVariant {
+            bandwidth: None,
+            segments: Vec::new(),
+        }]);
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn load_rejects_oversized_playlist() {
+        use wiremock::matchers::method;
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("x".repeat(11 * 1024 * 1024)))
+            .mount(&server)
+            .await;
+
+        let result = load(&Client::new(), &format!("{}/slate.m3u8", server.uri())).await;
+        assert!(matches!(result, Err(RitcherError::ResponseTooLarge(_))));
+    }
+}
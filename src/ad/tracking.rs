@@ -1,6 +1,9 @@
-use crate::ad::vast::TrackingEvent;
+use crate::ad::macros::{self, MacroContext};
+use crate::ad::provider::AdTrackingInfo;
+use crate::ad::vast::{TrackingEvent, error_code};
 use crate::metrics;
 use reqwest::Client;
+use reqwest::header::HeaderMap;
 use std::sync::LazyLock;
 use std::time::Duration;
 use tokio::sync::Semaphore;
@@ -68,9 +71,55 @@ pub fn events_for_segment(
     events
 }
 
+/// Macro context for the beacons of an ad segment
+///
+/// Adds `[ASSETURI]`, `[PODSEQUENCE]` and `[BREAKPOSITION]` from the
+/// tracking metadata to `viewer`, the viewer's macro values.
+pub fn macro_context(tracking: &AdTrackingInfo, viewer: MacroContext) -> MacroContext {
+    let mut ctx = viewer.with_pod_sequence(tracking.segment_index + 1);
+    if let Some(uri) = &tracking.asset_uri {
+        ctx = ctx.with_asset_uri(uri.clone());
+    }
+    if let Some(position) = tracking.break_position {
+        ctx = ctx.with_break_position(position);
+    }
+    ctx
+}
+
+/// `[ADPLAYHEAD]` of a progress event for a creative of `duration` seconds
+///
+/// `None` for events without a fixed position in the creative.
+pub fn ad_playhead(event: &str, duration: f32) -> Option<f64> {
+    let fraction = match event {
+        "start" => 0.0,
+        "firstQuartile" => 0.25,
+        "midpoint" => 0.5,
+        "thirdQuartile" => 0.75,
+        "complete" => 1.0,
+        _ => return None,
+    };
+    Some(f64::from(duration) * fraction)
+}
+
+/// VAST error code for a failed ad media fetch
+///
+/// 402 when the request timed out, 401 when the file was not found and
+/// 405 (problem displaying the media file) otherwise.
+pub fn media_error_code(error: &reqwest::Error) -> u16 {
+    if error.is_timeout() {
+        error_code::MEDIA_TIMEOUT
+    } else if error.status() == Some(reqwest::StatusCode::NOT_FOUND) {
+        error_code::FILE_NOT_FOUND
+    } else {
+        error_code::MEDIA_DISPLAY
+    }
+}
+
 /// Fire a tracking beacon (fire-and-forget)
 ///
-/// Spawns a background task. Does not block the caller.
+/// VAST macros in `url` are expanded from `ctx` first, and `headers` (the
+/// viewer's `X-Device-*` headers, or none) go with the request. Spawns a background
+/// task. Does not block the caller.
 /// No retries -- best effort as per VAST spec.
 ///
 /// **Concurrency note:** Beacons are spawned via `tokio::spawn` without an
@@ -81,9 +130,19 @@ pub fn events_for_segment(
 ///
 /// # Arguments
 /// * `client` - HTTP client for beacon request
-/// * `url` - Tracking beacon URL
+/// * `url` - Tracking beacon URL, possibly with VAST macros
 /// * `event_name` - Name of the event being tracked (for logging/metrics)
-pub fn fire_beacon(client: Client, url: String, event_name: String) {
+/// * `ctx` - Values for the URL's macros
+/// * `headers` - Extra request headers
+pub fn fire_beacon(
+    client: Client,
+    url: &str,
+    event_name: String,
+    ctx: &MacroContext,
+    headers: &HeaderMap,
+) {
+    let url = macros::expand(url, ctx);
+    let headers = headers.clone();
     tokio::spawn(async move {
         // Acquire a permit to bound concurrent beacon requests.
         // If all 50 slots are in use, this waits (beacons already have a 2s
@@ -94,6 +153,7 @@ pub fn fire_beacon(client: Client, url: String, event_name: String) {
 
         match client
             .get(&url)
+            .headers(headers)
             .timeout(Duration::from_secs(2))
             .send()
             .await
@@ -120,21 +180,67 @@ pub fn fire_beacon(client: Client, url: String, event_name: String) {
 /// # Arguments
 /// * `client` - HTTP client
 /// * `impression_urls` - List of impression tracking URLs from VAST
-pub fn fire_impressions(client: Client, impression_urls: &[String]) {
+/// * `ctx` - Values for the URLs' macros
+/// * `headers` - Extra request headers
+pub fn fire_impressions(
+    client: Client,
+    impression_urls: &[String],
+    ctx: &MacroContext,
+    headers: &HeaderMap,
+) {
     for url in impression_urls {
-        fire_beacon(client.clone(), url.clone(), "impression".to_string());
+        fire_beacon(client.clone(), url, "impression".to_string(), ctx, headers);
     }
 }
 
-/// Fire error beacon
+/// Fire the impression and progress beacons due when an ad segment plays
 ///
-/// Called when VAST fetch or ad segment fetch fails.
+/// Impressions fire with the ad's first segment and progress events as
+/// selected by [`events_for_segment`], with their `[ADPLAYHEAD]`. `ctx`
+/// holds the segment's macro values (see [`macro_context`]).
+pub fn fire_segment_beacons(
+    client: Client,
+    tracking: &AdTrackingInfo,
+    ctx: &MacroContext,
+    headers: &HeaderMap,
+) {
+    if tracking.segment_index == 0 {
+        fire_impressions(client.clone(), &tracking.impression_urls, ctx, headers);
+    }
+
+    let events = events_for_segment(
+        tracking.segment_index,
+        tracking.total_segments,
+        &tracking.tracking_events,
+    );
+    for event in events {
+        let event_ctx = match ad_playhead(&event.event, tracking.duration) {
+            Some(playhead) => ctx.clone().with_ad_playhead(playhead),
+            None => ctx.clone(),
+        };
+        fire_beacon(
+            client.clone(),
+            &event.url,
+            event.event.clone(),
+            &event_ctx,
+            headers,
+        );
+    }
+}
+
+/// Fire error beacons
+///
+/// Called when resolving a VAST response or fetching an ad segment fails.
 ///
 /// # Arguments
 /// * `client` - HTTP client
-/// * `error_url` - Error tracking URL from VAST
-pub fn fire_error(client: Client, error_url: &str) {
-    fire_beacon(client, error_url.to_string(), "error".to_string());
+/// * `error_urls` - `<Error>` URLs of the ad and its wrapper chain
+/// * `ctx` - Values for the URLs' macros, including `[ERRORCODE]`
+/// * `headers` - Extra request headers
+pub fn fire_error(client: Client, error_urls: &[String], ctx: &MacroContext, headers: &HeaderMap) {
+    for url in error_urls {
+        fire_beacon(client.clone(), url, "error".to_string(), ctx, headers);
+    }
 }
 
 #[cfg(test)]
@@ -253,6 +359,31 @@ mod tests {
         assert!(seg2.iter().any(|e| e.event == "complete"));
     }
 
+    #[test]
+    fn test_macro_context_from_tracking() {
+        let tracking = AdTrackingInfo {
+            segment_index: 1,
+            total_segments: 3,
+            asset_uri: Some("http://cdn/ad.m3u8".to_string()),
+            duration: 15.0,
+            break_position: Some(crate::ad::schedule::BreakPosition::Pre),
+            ..Default::default()
+        };
+        let url = macros::expand(
+            "http://t/?a=[ASSETURI]&s=[PODSEQUENCE]&b=[BREAKPOSITION]",
+            &macro_context(&tracking, MacroContext::default()),
+        );
+        assert_eq!(url, "http://t/?a=http%3A%2F%2Fcdn%2Fad.m3u8&s=2&b=1");
+    }
+
+    #[test]
+    fn test_ad_playhead_per_event() {
+        assert_eq!(ad_playhead("start", 20.0), Some(0.0));
+        assert_eq!(ad_playhead("midpoint", 20.0), Some(10.0));
+        assert_eq!(ad_playhead("complete", 20.0), Some(20.0));
+        assert_eq!(ad_playhead("pause", 20.0), None);
+    }
+
     #[test]
     fn test_single_segment_fires_all_events() {
         let events = make_events();
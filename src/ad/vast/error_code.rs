@@ -0,0 +1,22 @@
+//! VAST error codes reported through `<Error>` URLs as `[ERRORCODE]`
+//!
+//! See the IAB VAST 4.x specification, section 2.3.6.3.
+
+/// XML parsing error
+pub const XML_PARSE: u16 = 100;
+/// Creative duration not accepted by the stitcher
+pub const UNEXPECTED_DURATION: u16 = 202;
+/// Timeout of the VAST URI provided in a wrapper, or no response from it
+pub const WRAPPER_TIMEOUT: u16 = 301;
+/// Wrapper limit reached
+pub const WRAPPER_LIMIT: u16 = 302;
+/// No VAST response after one or more wrappers
+pub const NO_ADS_AFTER_WRAPPER: u16 = 303;
+/// Media file not found
+pub const FILE_NOT_FOUND: u16 = 401;
+/// Timeout of the media file URI
+pub const MEDIA_TIMEOUT: u16 = 402;
+/// No media file supported by the stitcher
+pub const UNSUPPORTED_MEDIA: u16 = 403;
+/// Problem displaying the media file
+pub const MEDIA_DISPLAY: u16 = 405;
@@ -113,6 +113,7 @@ mod tests {
                 height: 720,
                 bitrate: Some(2000),
                 codec: Some("H.264".to_string()),
+                api_framework: None,
             },
             MediaFile {
                 url: "https://example.com/ad.m3u8".to_string(),
@@ -122,6 +123,7 @@ mod tests {
                 height: 720,
                 bitrate: None,
                 codec: None,
+                api_framework: None,
             },
         ];
 
@@ -139,6 +141,7 @@ mod tests {
             height: 720,
             bitrate: Some(2000),
             codec: Some("H.264".to_string()),
+            api_framework: None,
         }];
 
         let best = select_best_media_file(&files).unwrap();
@@ -165,6 +168,7 @@ mod tests {
                 height: 360,
                 bitrate: Some(500),
                 codec: None,
+                api_framework: None,
             },
             MediaFile {
                 url: "https://example.com/high.mp4".to_string(),
@@ -174,6 +178,7 @@ mod tests {
                 height: 1080,
                 bitrate: Some(5000),
                 codec: None,
+                api_framework: None,
             },
         ];
         let best = select_best_media_file(&files).unwrap();
@@ -1,13 +1,17 @@
+pub mod error_code;
 mod helpers;
 mod parser;
 mod types;
+mod vmap;
 
 // Re-export all public types
 pub use types::{
-    Creative, InLineAd, LinearAd, MediaFile, TrackingEvent, VastAd, VastAdType, VastResponse,
-    Verification, VerificationTrackingEvent, WrapperAd,
+    Creative, InLineAd, LinearAd, MediaFile, TrackingEvent, UniversalAdId, VastAd, VastAdType,
+    VastResponse, Verification, VerificationTrackingEvent, WrapperAd,
 };
+pub use vmap::{TimeOffset, VmapAdBreak, VmapAdSource, VmapResponse};
 
-// Re-export the main parse function and helpers
+// Re-export the main parse functions and helpers
 pub use helpers::select_best_media_file;
 pub use parser::parse_vast;
+pub use vmap::parse_vmap;
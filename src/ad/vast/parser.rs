@@ -5,8 +5,8 @@ use tracing::info;
 
 use super::helpers::{get_attr, parse_duration, read_text};
 use super::types::{
-    Creative, InLineAd, LinearAd, MediaFile, TrackingEvent, VastAd, VastAdType, VastResponse,
-    Verification, VerificationTrackingEvent, WrapperAd,
+    Creative, InLineAd, LinearAd, MediaFile, TrackingEvent, UniversalAdId, VastAd, VastAdType,
+    VastResponse, Verification, VerificationTrackingEvent, WrapperAd,
 };
 
 /// Parse VAST XML into structured data
@@ -15,6 +15,7 @@ pub fn parse_vast(xml: &str) -> Result<VastResponse> {
 
     let mut version = String::new();
     let mut ads = Vec::new();
+    let mut error_urls = Vec::new();
 
     loop {
         match reader.read_event() {
@@ -24,10 +25,14 @@ pub fn parse_vast(xml: &str) -> Result<VastResponse> {
             }
             Ok(Event::Start(ref e)) if e.name().as_ref() == b"Ad" => {
                 let ad_id = get_attr(e, "id").unwrap_or_default();
-                if let Some(ad) = parse_ad(&mut reader, ad_id)? {
+                let sequence = get_attr(e, "sequence").and_then(|s| s.trim().parse().ok());
+                if let Some(ad) = parse_ad(&mut reader, ad_id, sequence)? {
                     ads.push(ad);
                 }
             }
+            Ok(Event::Start(ref e)) if e.name().as_ref() == b"Error" => {
+                push_url(&mut error_urls, read_text(&mut reader, "Error")?);
+            }
             Ok(Event::Eof) => break,
             Err(e) => {
                 return Err(RitcherError::InternalError(format!(
@@ -45,24 +50,45 @@ pub fn parse_vast(xml: &str) -> Result<VastResponse> {
         info!("Parsed {} ad(s) from VAST response", ads.len());
     }
 
-    Ok(VastResponse { version, ads })
+    Ok(VastResponse {
+        version,
+        ads,
+        error_urls,
+    })
 }
 
 /// Parse a single <Ad> element
-fn parse_ad(reader: &mut Reader<&[u8]>, id: String) -> Result<Option<VastAd>> {
+fn parse_ad(
+    reader: &mut Reader<&[u8]>,
+    id: String,
+    sequence: Option<u32>,
+) -> Result<Option<VastAd>> {
     loop {
         match reader.read_event() {
             Ok(Event::Start(ref e)) if e.name().as_ref() == b"InLine" => {
                 let inline = parse_inline(reader)?;
                 return Ok(Some(VastAd {
                     id,
+                    sequence,
                     ad_type: VastAdType::InLine(inline),
                 }));
             }
             Ok(Event::Start(ref e)) if e.name().as_ref() == b"Wrapper" => {
-                let wrapper = parse_wrapper(reader)?;
+                let bool_attr = |name: &str| {
+                    get_attr(e, name).and_then(|v| match v.trim() {
+                        "true" | "1" => Some(true),
+                        "false" | "0" => Some(false),
+                        _ => None,
+                    })
+                };
+                let mut wrapper = parse_wrapper(reader)?;
+                wrapper.follow_additional_wrappers =
+                    bool_attr("followAdditionalWrappers").unwrap_or(true);
+                wrapper.allow_multiple_ads = bool_attr("allowMultipleAds");
+                wrapper.fallback_on_no_ad = bool_attr("fallbackOnNoAd");
                 return Ok(Some(VastAd {
                     id,
+                    sequence,
                     ad_type: VastAdType::Wrapper(wrapper),
                 }));
             }
@@ -85,8 +111,10 @@ fn parse_inline(reader: &mut Reader<&[u8]>) -> Result<InLineAd> {
     let mut ad_title = String::new();
     let mut creatives = Vec::new();
     let mut impression_urls = Vec::new();
-    let mut error_url = None;
+    let mut error_urls = Vec::new();
     let mut verifications = Vec::new();
+    let mut advertiser = None;
+    let mut categories = Vec::new();
 
     loop {
         match reader.read_event() {
@@ -103,7 +131,7 @@ fn parse_inline(reader: &mut Reader<&[u8]>) -> Result<InLineAd> {
                 }
             }
             Ok(Event::Start(ref e)) if e.name().as_ref() == b"Error" => {
-                error_url = Some(read_text(reader, "Error")?);
+                push_url(&mut error_urls, read_text(reader, "Error")?);
             }
             Ok(Event::Start(ref e)) if e.name().as_ref() == b"Creatives" => {
                 creatives = parse_creatives(reader)?;
@@ -111,6 +139,16 @@ fn parse_inline(reader: &mut Reader<&[u8]>) -> Result<InLineAd> {
             Ok(Event::Start(ref e)) if e.name().as_ref() == b"AdVerifications" => {
                 verifications = parse_ad_verifications(reader)?;
             }
+            Ok(Event::Start(ref e)) if e.name().as_ref() == b"Advertiser" => {
+                let name = read_text(reader, "Advertiser")?;
+                advertiser = (!name.is_empty()).then_some(name);
+            }
+            Ok(Event::Start(ref e)) if e.name().as_ref() == b"Category" => {
+                let category = read_text(reader, "Category")?;
+                if !category.is_empty() {
+                    categories.push(category);
+                }
+            }
             Ok(Event::End(ref e)) if e.name().as_ref() == b"InLine" => break,
             Ok(Event::Eof) => break,
             Err(e) => {
@@ -128,8 +166,10 @@ fn parse_inline(reader: &mut Reader<&[u8]>) -> Result<InLineAd> {
         ad_title,
         creatives,
         impression_urls,
-        error_url,
+        error_urls,
         verifications,
+        advertiser,
+        categories,
     })
 }
 
@@ -138,6 +178,7 @@ fn parse_wrapper(reader: &mut Reader<&[u8]>) -> Result<WrapperAd> {
     let mut ad_tag_uri = String::new();
     let mut impression_urls = Vec::new();
     let mut tracking_events = Vec::new();
+    let mut error_urls = Vec::new();
     let mut verifications = Vec::new();
 
     loop {
@@ -154,6 +195,9 @@ fn parse_wrapper(reader: &mut Reader<&[u8]>) -> Result<WrapperAd> {
             Ok(Event::Start(ref e)) if e.name().as_ref() == b"TrackingEvents" => {
                 tracking_events = parse_tracking_events(reader)?;
             }
+            Ok(Event::Start(ref e)) if e.name().as_ref() == b"Error" => {
+                push_url(&mut error_urls, read_text(reader, "Error")?);
+            }
             Ok(Event::Start(ref e)) if e.name().as_ref() == b"AdVerifications" => {
                 verifications = parse_ad_verifications(reader)?;
             }
@@ -173,10 +217,21 @@ fn parse_wrapper(reader: &mut Reader<&[u8]>) -> Result<WrapperAd> {
         ad_tag_uri,
         impression_urls,
         tracking_events,
+        error_urls,
+        follow_additional_wrappers: true,
+        allow_multiple_ads: None,
+        fallback_on_no_ad: None,
         verifications,
     })
 }
 
+/// Append a non-empty URL read from the document
+fn push_url(urls: &mut Vec<String>, url: String) {
+    if !url.is_empty() {
+        urls.push(url);
+    }
+}
+
 /// Parse <Creatives> element
 fn parse_creatives(reader: &mut Reader<&[u8]>) -> Result<Vec<Creative>> {
     let mut creatives = Vec::new();
@@ -185,7 +240,14 @@ fn parse_creatives(reader: &mut Reader<&[u8]>) -> Result<Vec<Creative>> {
         match reader.read_event() {
             Ok(Event::Start(ref e)) if e.name().as_ref() == b"Creative" => {
                 let id = get_attr(e, "id").unwrap_or_default();
-                let creative = parse_creative(reader, id)?;
+                let ad_id = get_attr(e, "AdID").filter(|v| !v.is_empty());
+                let mut creative = parse_creative(reader, id)?;
+                if creative.universal_ad_id.is_none() {
+                    creative.universal_ad_id = ad_id.map(|value| UniversalAdId {
+                        registry: "unknown".to_string(),
+                        value,
+                    });
+                }
                 creatives.push(creative);
             }
             Ok(Event::End(ref e)) if e.name().as_ref() == b"Creatives" => break,
@@ -206,12 +268,23 @@ fn parse_creatives(reader: &mut Reader<&[u8]>) -> Result<Vec<Creative>> {
 /// Parse a single <Creative> element
 fn parse_creative(reader: &mut Reader<&[u8]>, id: String) -> Result<Creative> {
     let mut linear = None;
+    let mut universal_ad_id = None;
 
     loop {
         match reader.read_event() {
             Ok(Event::Start(ref e)) if e.name().as_ref() == b"Linear" => {
                 linear = Some(parse_linear(reader)?);
             }
+            Ok(Event::Start(ref e)) if e.name().as_ref() == b"UniversalAdId" => {
+                let registry = get_attr(e, "idRegistry")
+                    .filter(|r| !r.is_empty())
+                    .unwrap_or_else(|| "unknown".to_string());
+                let value = read_text(reader, "UniversalAdId")?;
+                // The first ID wins; "unknown" is the spec's placeholder
+                if universal_ad_id.is_none() && !value.is_empty() && value != "unknown" {
+                    universal_ad_id = Some(UniversalAdId { registry, value });
+                }
+            }
             Ok(Event::End(ref e)) if e.name().as_ref() == b"Creative" => break,
             Ok(Event::Eof) => break,
             Err(e) => {
@@ -224,7 +297,11 @@ fn parse_creative(reader: &mut Reader<&[u8]>, id: String) -> Result<Creative> {
         }
     }
 
-    Ok(Creative { id, linear })
+    Ok(Creative {
+        id,
+        universal_ad_id,
+        linear,
+    })
 }
 
 /// Parse <Linear> element
@@ -281,6 +358,7 @@ fn parse_media_files(reader: &mut Reader<&[u8]>) -> Result<Vec<MediaFile>> {
                     .unwrap_or(0);
                 let bitrate = get_attr(e, "bitrate").and_then(|s| s.parse().ok());
                 let codec = get_attr(e, "codec");
+                let api_framework = get_attr(e, "apiFramework").filter(|s| !s.is_empty());
 
                 let url = read_text(reader, "MediaFile")?.trim().to_string();
 
@@ -292,6 +370,7 @@ fn parse_media_files(reader: &mut Reader<&[u8]>) -> Result<Vec<MediaFile>> {
                     height,
                     bitrate,
                     codec,
+                    api_framework,
                 });
             }
             Ok(Event::End(ref e)) if e.name().as_ref() == b"MediaFiles" => break,
@@ -528,6 +607,52 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_ad_identity() {
+        let xml = r#"<VAST version="4.1">
+  <Ad id="ad-1">
+    <InLine>
+      <AdSystem>S</AdSystem>
+      <AdTitle>T</AdTitle>
+      <Advertiser id="adv-9">cola.example</Advertiser>
+      <Category authority="https://iabtechlab.com">IAB8-5</Category>
+      <Category authority="https://iabtechlab.com">IAB8</Category>
+      <Creatives>
+        <Creative id="c-1">
+          <UniversalAdId idRegistry="ad-id.org">CNPA0484000H</UniversalAdId>
+          <UniversalAdId idRegistry="clearcast.co.uk">ABC/DEFG123/030</UniversalAdId>
+        </Creative>
+        <Creative id="c-2" AdID="LEGACY-1"></Creative>
+        <Creative id="c-3">
+          <UniversalAdId idRegistry="unknown">unknown</UniversalAdId>
+        </Creative>
+      </Creatives>
+    </InLine>
+  </Ad>
+</VAST>"#;
+
+        let result = parse_vast(xml).unwrap();
+        let VastAdType::InLine(inline) = &result.ads[0].ad_type else {
+            panic!("Expected InLine ad");
+        };
+        assert_eq!(inline.advertiser.as_deref(), Some("cola.example"));
+        assert_eq!(inline.categories, vec!["IAB8-5", "IAB8"]);
+
+        let ids: Vec<Option<String>> = inline
+            .creatives
+            .iter()
+            .map(|c| c.universal_ad_id.as_ref().map(UniversalAdId::key))
+            .collect();
+        assert_eq!(
+            ids,
+            vec![
+                Some("ad-id.org:CNPA0484000H".to_string()),
+                Some("unknown:LEGACY-1".to_string()),
+                None,
+            ]
+        );
+    }
+
     #[test]
     fn test_parse_wrapper_ad() {
         let result = parse_vast(VAST_WRAPPER).unwrap();
@@ -544,6 +669,72 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_error_urls_at_every_level() {
+        let xml = r#"<VAST version="4.0">
+  <Error><![CDATA[http://example.com/no-ad?e=[ERRORCODE]]]></Error>
+  <Ad id="w"><Wrapper>
+    <VASTAdTagURI>http://example.com/next.xml</VASTAdTagURI>
+    <Error>http://example.com/wrapper-a</Error>
+    <Error>http://example.com/wrapper-b</Error>
+    <Error></Error>
+  </Wrapper></Ad>
+  <Ad id="i"><InLine>
+    <AdSystem>S</AdSystem>
+    <Error>http://example.com/inline</Error>
+  </InLine></Ad>
+</VAST>"#;
+        let result = parse_vast(xml).unwrap();
+        assert_eq!(
+            result.error_urls,
+            vec!["http://example.com/no-ad?e=[ERRORCODE]"]
+        );
+        match &result.ads[0].ad_type {
+            VastAdType::Wrapper(wrapper) => assert_eq!(
+                wrapper.error_urls,
+                vec![
+                    "http://example.com/wrapper-a",
+                    "http://example.com/wrapper-b"
+                ]
+            ),
+            _ => panic!("Expected Wrapper ad"),
+        }
+        match &result.ads[1].ad_type {
+            VastAdType::InLine(inline) => {
+                assert_eq!(inline.error_urls, vec!["http://example.com/inline"])
+            }
+            _ => panic!("Expected InLine ad"),
+        }
+    }
+
+    #[test]
+    fn test_parse_sequence_and_wrapper_attributes() {
+        let xml = r#"<VAST version="4.1">
+  <Ad id="p2" sequence="2"><Wrapper followAdditionalWrappers="false" allowMultipleAds="true" fallbackOnNoAd="false">
+    <VASTAdTagURI>http://example.com/next.xml</VASTAdTagURI>
+  </Wrapper></Ad>
+  <Ad id="s"><Wrapper><VASTAdTagURI>http://example.com/other.xml</VASTAdTagURI></Wrapper></Ad>
+</VAST>"#;
+        let result = parse_vast(xml).unwrap();
+        assert!(!result.allows_multiple_ads_by_default());
+        assert_eq!(result.ads[0].sequence, Some(2));
+        assert_eq!(result.ads[1].sequence, None);
+        match (&result.ads[0].ad_type, &result.ads[1].ad_type) {
+            (VastAdType::Wrapper(set), VastAdType::Wrapper(unset)) => {
+                assert!(!set.follow_additional_wrappers);
+                assert_eq!(set.allow_multiple_ads, Some(true));
+                assert_eq!(set.fallback_on_no_ad, Some(false));
+                assert!(unset.follow_additional_wrappers);
+                assert_eq!(unset.allow_multiple_ads, None);
+                assert_eq!(unset.fallback_on_no_ad, None);
+            }
+            _ => panic!("Expected Wrapper ads"),
+        }
+
+        let vast3 = parse_vast(r#"<VAST version="3.0"></VAST>"#).unwrap();
+        assert!(vast3.allows_multiple_ads_by_default());
+    }
+
     #[test]
     fn test_parse_empty_vast() {
         let result = parse_vast(VAST_EMPTY).unwrap();
@@ -3,12 +3,16 @@
 pub struct VastResponse {
     pub version: String,
     pub ads: Vec<VastAd>,
+    /// Root `<Error>` URLs of a response without ads
+    pub error_urls: Vec<String>,
 }
 
 /// A single ad from a VAST response
 #[derive(Debug, Clone)]
 pub struct VastAd {
     pub id: String,
+    /// Position in the ad pod (`sequence`); `None` for stand-alone ads
+    pub sequence: Option<u32>,
     pub ad_type: VastAdType,
 }
 
@@ -26,9 +30,14 @@ pub struct InLineAd {
     pub ad_title: String,
     pub creatives: Vec<Creative>,
     pub impression_urls: Vec<String>,
-    pub error_url: Option<String>,
+    /// `<Error>` URLs, fired with `[ERRORCODE]` when the ad fails
+    pub error_urls: Vec<String>,
     /// OMID verification resources from `<AdVerifications>`
     pub verifications: Vec<Verification>,
+    /// Advertiser name or domain from `<Advertiser>`
+    pub advertiser: Option<String>,
+    /// Content categories of the ad from `<Category>`, e.g. IAB codes
+    pub categories: Vec<String>,
 }
 
 /// Wrapper ad that references another VAST tag
@@ -37,17 +46,58 @@ pub struct WrapperAd {
     pub ad_tag_uri: String,
     pub impression_urls: Vec<String>,
     pub tracking_events: Vec<TrackingEvent>,
+    /// `<Error>` URLs, fired when the wrapped ad fails
+    pub error_urls: Vec<String>,
+    /// Whether the wrapped response may itself contain wrappers
+    /// (`followAdditionalWrappers`, default: true)
+    pub follow_additional_wrappers: bool,
+    /// Whether the wrapped response may contain pods and several ads
+    /// (`allowMultipleAds`); the default depends on the VAST version
+    pub allow_multiple_ads: Option<bool>,
+    /// Whether a stand-alone ad may replace the wrapper when its response
+    /// has no ads (`fallbackOnNoAd`)
+    pub fallback_on_no_ad: Option<bool>,
     /// OMID verification resources from `<AdVerifications>` in the wrapper
     pub verifications: Vec<Verification>,
 }
 
+impl VastResponse {
+    /// Default of a wrapper's `allowMultipleAds` in this response: `true`
+    /// up to VAST 3, `false` from VAST 4
+    pub fn allows_multiple_ads_by_default(&self) -> bool {
+        self.version
+            .split('.')
+            .next()
+            .and_then(|major| major.trim().parse::<u32>().ok())
+            .is_none_or(|major| major < 4)
+    }
+}
+
 /// A creative containing linear video content
 #[derive(Debug, Clone)]
 pub struct Creative {
     pub id: String,
+    /// Registry-issued identifier of the creative from `<UniversalAdId>`
+    /// (VAST 4) or the `AdID` attribute (VAST 3)
+    pub universal_ad_id: Option<UniversalAdId>,
     pub linear: Option<LinearAd>,
 }
 
+/// A creative identifier issued by a registry such as Ad-ID
+#[derive(Debug, Clone, PartialEq)]
+pub struct UniversalAdId {
+    /// `idRegistry`, e.g. "ad-id.org"; "unknown" when not given
+    pub registry: String,
+    pub value: String,
+}
+
+impl UniversalAdId {
+    /// `registry:value`, unique across registries
+    pub fn key(&self) -> String {
+        format!("{}:{}", self.registry, self.value)
+    }
+}
+
 /// Linear (video) ad content
 #[derive(Debug, Clone)]
 pub struct LinearAd {
@@ -66,6 +116,8 @@ pub struct MediaFile {
     pub height: u32,
     pub bitrate: Option<u32>,
     pub codec: Option<String>,
+    /// `apiFramework`, e.g. "VPAID" for interactive creatives
+    pub api_framework: Option<String>,
 }
 
 /// Tracking event for ad playback reporting
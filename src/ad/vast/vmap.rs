@@ -0,0 +1,304 @@
+//! VMAP 1.0 parsing
+//!
+//! A VMAP document schedules ad breaks for a piece of content: each
+//! `<vmap:AdBreak>` carries a `timeOffset` and an `<vmap:AdSource>` that
+//! either references a VAST tag (`<vmap:AdTagURI>`) or embeds the VAST
+//! response (`<vmap:VASTAdData>`). Elements are matched on their local name,
+//! so documents with or without the `vmap:` prefix both parse.
+
+use crate::error::{Result, RitcherError};
+use quick_xml::events::Event;
+use quick_xml::reader::Reader;
+use tracing::{info, warn};
+
+use super::helpers::{get_attr, parse_duration, read_text};
+
+/// Parsed VMAP response
+#[derive(Debug, Clone, PartialEq)]
+pub struct VmapResponse {
+    pub version: String,
+    pub ad_breaks: Vec<VmapAdBreak>,
+}
+
+/// A single scheduled ad break
+#[derive(Debug, Clone, PartialEq)]
+pub struct VmapAdBreak {
+    /// `breakId` attribute
+    pub break_id: Option<String>,
+    /// `breakType` attribute (`linear`, `nonlinear`, `display`)
+    pub break_type: String,
+    /// Where the break plays in the content
+    pub time_offset: TimeOffset,
+    /// VAST source filling the break
+    pub ad_source: Option<VmapAdSource>,
+}
+
+impl VmapAdBreak {
+    /// Whether the break holds linear ads, the only kind a stitcher can play
+    pub fn is_linear(&self) -> bool {
+        self.break_type
+            .split(',')
+            .any(|t| t.trim().eq_ignore_ascii_case("linear"))
+    }
+}
+
+/// VAST source of a VMAP ad break
+#[derive(Debug, Clone, PartialEq)]
+pub enum VmapAdSource {
+    /// URL of a VAST tag to request
+    AdTagUri(String),
+    /// Inline VAST document
+    VastAdData(String),
+}
+
+/// VMAP `timeOffset` value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeOffset {
+    /// `start` — pre-roll
+    Start,
+    /// `end` — post-roll
+    End,
+    /// `HH:MM:SS[.mmm]` — seconds into the content
+    Seconds(f64),
+    /// `n%` — percentage of the content duration
+    Percent(f64),
+    /// `#n` — the n-th ad opportunity (1-based)
+    Position(u32),
+}
+
+impl TimeOffset {
+    /// Parse a `timeOffset` attribute value
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        if value.eq_ignore_ascii_case("start") {
+            return Some(Self::Start);
+        }
+        if value.eq_ignore_ascii_case("end") {
+            return Some(Self::End);
+        }
+        if let Some(position) = value.strip_prefix('#') {
+            return position.parse().ok().filter(|&n| n > 0).map(Self::Position);
+        }
+        if let Some(percent) = value.strip_suffix('%') {
+            return percent
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|p| p.is_finite() && (0.0..=100.0).contains(p))
+                .map(Self::Percent);
+        }
+        if value.split(':').count() == 3 && value.split(':').all(|p| p.parse::<f64>().is_ok()) {
+            return Some(Self::Seconds(f64::from(parse_duration(value))));
+        }
+        None
+    }
+
+    /// Resolve the offset to seconds into the content
+    ///
+    /// `opportunities` lists the content's ad opportunities in order: its
+    /// start, each content boundary (discontinuity or Period start) and its
+    /// end. Returns None when a `#n` position has no matching opportunity.
+    pub fn resolve(&self, total_duration: f64, opportunities: &[f64]) -> Option<f64> {
+        match *self {
+            Self::Start => Some(0.0),
+            Self::End => Some(total_duration),
+            Self::Seconds(secs) => Some(secs.min(total_duration)),
+            Self::Percent(percent) => Some(total_duration * percent / 100.0),
+            Self::Position(n) => opportunities.get(n as usize - 1).copied(),
+        }
+    }
+}
+
+/// Parse VMAP XML into structured data
+pub fn parse_vmap(xml: &str) -> Result<VmapResponse> {
+    let mut reader = Reader::from_str(xml);
+
+    let mut version = String::new();
+    let mut ad_breaks = Vec::new();
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(ref e)) if e.local_name().as_ref() == b"VMAP" => {
+                version = get_attr(e, "version").unwrap_or_default();
+                info!("Parsing VMAP version {}", version);
+            }
+            Ok(Event::Start(ref e)) if e.local_name().as_ref() == b"AdBreak" => {
+                let offset = get_attr(e, "timeOffset").unwrap_or_default();
+                let break_id = get_attr(e, "breakId");
+                let break_type = get_attr(e, "breakType").unwrap_or_else(|| "linear".into());
+                let ad_source = parse_ad_break(&mut reader)?;
+                match TimeOffset::parse(&offset) {
+                    Some(time_offset) => ad_breaks.push(VmapAdBreak {
+                        break_id,
+                        break_type,
+                        time_offset,
+                        ad_source,
+                    }),
+                    None => warn!("Skipping VMAP AdBreak with invalid timeOffset {:?}", offset),
+                }
+            }
+            Ok(Event::Eof) => break,
+            Err(e) => {
+                return Err(RitcherError::InternalError(format!(
+                    "VMAP XML parse error: {}",
+                    e
+                )));
+            }
+            _ => {}
+        }
+    }
+
+    info!("Parsed {} ad break(s) from VMAP response", ad_breaks.len());
+
+    Ok(VmapResponse { version, ad_breaks })
+}
+
+/// Parse the children of an `<AdBreak>` element, returning its ad source
+fn parse_ad_break(reader: &mut Reader<&[u8]>) -> Result<Option<VmapAdSource>> {
+    let mut ad_source = None;
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(ref e)) if e.local_name().as_ref() == b"AdTagURI" => {
+                let name = String::from_utf8_lossy(e.name().as_ref()).into_owned();
+                let uri = read_text(reader, &name)?;
+                if !uri.is_empty() {
+                    ad_source = Some(VmapAdSource::AdTagUri(uri));
+                }
+            }
+            Ok(Event::Start(ref e)) if e.local_name().as_ref() == b"VASTAdData" => {
+                let vast = reader.read_text(e.name()).map_err(|e| {
+                    RitcherError::InternalError(format!("VMAP VASTAdData read error: {}", e))
+                })?;
+                ad_source = Some(VmapAdSource::VastAdData(vast.trim().to_string()));
+            }
+            Ok(Event::End(ref e)) if e.local_name().as_ref() == b"AdBreak" => break,
+            Ok(Event::Eof) => break,
+            Err(e) => {
+                return Err(RitcherError::InternalError(format!(
+                    "VMAP XML parse error in AdBreak: {}",
+                    e
+                )));
+            }
+            _ => {}
+        }
+    }
+
+    Ok(ad_source)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_time_offsets() {
+        assert_eq!(TimeOffset::parse("start"), Some(TimeOffset::Start));
+        assert_eq!(TimeOffset::parse("end"), Some(TimeOffset::End));
+        assert_eq!(
+            TimeOffset::parse("00:10:00.500"),
+            Some(TimeOffset::Seconds(600.5))
+        );
+        assert_eq!(TimeOffset::parse("25%"), Some(TimeOffset::Percent(25.0)));
+        assert_eq!(TimeOffset::parse("#2"), Some(TimeOffset::Position(2)));
+        assert_eq!(TimeOffset::parse("#0"), None);
+        assert_eq!(TimeOffset::parse("150%"), None);
+        assert_eq!(TimeOffset::parse("soon"), None);
+    }
+
+    #[test]
+    fn test_resolve_time_offsets() {
+        let opportunities = [0.0, 40.0, 100.0];
+        assert_eq!(TimeOffset::Start.resolve(100.0, &opportunities), Some(0.0));
+        assert_eq!(TimeOffset::End.resolve(100.0, &opportunities), Some(100.0));
+        assert_eq!(
+            TimeOffset::Seconds(250.0).resolve(100.0, &opportunities),
+            Some(100.0)
+        );
+        assert_eq!(
+            TimeOffset::Percent(50.0).resolve(100.0, &opportunities),
+            Some(50.0)
+        );
+        assert_eq!(
+            TimeOffset::Position(2).resolve(100.0, &opportunities),
+            Some(40.0)
+        );
+        assert_eq!(TimeOffset::Position(4).resolve(100.0, &opportunities), None);
+    }
+
+    #[test]
+    fn test_parse_vmap_breaks() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<vmap:VMAP xmlns:vmap="http://www.iab.net/videosuite/vmap" version="1.0">
+  <vmap:AdBreak timeOffset="start" breakType="linear" breakId="preroll">
+    <vmap:AdSource id="pre" allowMultipleAds="true" followRedirects="true">
+      <vmap:AdTagURI templateType="vast3"><![CDATA[https://ads.example.com/vast?pos=pre]]></vmap:AdTagURI>
+    </vmap:AdSource>
+  </vmap:AdBreak>
+  <vmap:AdBreak timeOffset="00:05:00.000" breakType="linear" breakId="midroll-1">
+    <vmap:AdSource id="mid">
+      <vmap:VASTAdData>
+        <VAST version="3.0"><Ad id="a1"><InLine><AdTitle>Mid</AdTitle></InLine></Ad></VAST>
+      </vmap:VASTAdData>
+    </vmap:AdSource>
+  </vmap:AdBreak>
+  <vmap:AdBreak timeOffset="later" breakType="linear">
+    <vmap:AdSource><vmap:AdTagURI>https://ads.example.com/vast?pos=bad</vmap:AdTagURI></vmap:AdSource>
+  </vmap:AdBreak>
+  <vmap:AdBreak timeOffset="end" breakType="nonlinear">
+    <vmap:AdSource id="post">
+      <vmap:AdTagURI templateType="vast3">https://ads.example.com/vast?pos=post</vmap:AdTagURI>
+    </vmap:AdSource>
+  </vmap:AdBreak>
+</vmap:VMAP>"#;
+
+        let vmap = parse_vmap(xml).unwrap();
+        assert_eq!(vmap.version, "1.0");
+        assert_eq!(vmap.ad_breaks.len(), 3);
+
+        let pre = &vmap.ad_breaks[0];
+        assert_eq!(pre.break_id.as_deref(), Some("preroll"));
+        assert_eq!(pre.time_offset, TimeOffset::Start);
+        assert!(pre.is_linear());
+        assert_eq!(
+            pre.ad_source,
+            Some(VmapAdSource::AdTagUri(
+                "https://ads.example.com/vast?pos=pre".to_string()
+            ))
+        );
+
+        let mid = &vmap.ad_breaks[1];
+        assert_eq!(mid.time_offset, TimeOffset::Seconds(300.0));
+        match &mid.ad_source {
+            Some(VmapAdSource::VastAdData(vast)) => {
+                assert!(vast.starts_with("<VAST version=\"3.0\">"));
+                assert!(vast.contains("<AdTitle>Mid</AdTitle>"));
+            }
+            other => panic!("expected inline VAST, got {:?}", other),
+        }
+
+        let post = &vmap.ad_breaks[2];
+        assert_eq!(post.time_offset, TimeOffset::End);
+        assert!(!post.is_linear());
+    }
+
+    #[test]
+    fn test_parse_vmap_without_prefix() {
+        let xml = r##"<VMAP version="1.0">
+  <AdBreak timeOffset="#1"><AdSource><AdTagURI>https://ads.example.com/a</AdTagURI></AdSource></AdBreak>
+</VMAP>"##;
+        let vmap = parse_vmap(xml).unwrap();
+        assert_eq!(vmap.ad_breaks.len(), 1);
+        assert_eq!(vmap.ad_breaks[0].time_offset, TimeOffset::Position(1));
+        assert_eq!(vmap.ad_breaks[0].break_type, "linear");
+    }
+
+    #[test]
+    fn test_parse_vmap_break_without_source() {
+        let vmap =
+            parse_vmap(r#"<vmap:VMAP version="1.0"><vmap:AdBreak timeOffset="end"/></vmap:VMAP>"#)
+                .unwrap();
+        // Self-closing AdBreak is an Empty event and carries no source
+        assert!(vmap.ad_breaks.is_empty());
+    }
+}
@@ -82,7 +82,8 @@ mod tests {
                 is_hls: true,
                 impression_urls: vec![],
                 tracking_events: vec![],
-                error_url: None,
+                error_urls: vec![],
+                break_position: None,
                 total_segments: 1,
                 segment_index: 0,
                 visited: false,
@@ -99,7 +100,8 @@ mod tests {
                 is_hls: true,
                 impression_urls: vec![],
                 tracking_events: vec![],
-                error_url: None,
+                error_urls: vec![],
+                break_position: None,
                 total_segments: 1,
                 segment_index: 0,
                 visited: false,
@@ -135,7 +137,8 @@ mod tests {
                 is_hls: true,
                 impression_urls: vec![],
                 tracking_events: vec![],
-                error_url: None,
+                error_urls: vec![],
+                break_position: None,
                 total_segments: 1,
                 segment_index: 0,
                 visited: false,
@@ -155,7 +158,8 @@ mod tests {
                 is_hls: true,
                 impression_urls: vec![],
                 tracking_events: vec![],
-                error_url: None,
+                error_urls: vec![],
+                break_position: None,
                 total_segments: 1,
                 segment_index: 0,
                 visited: false,
@@ -190,7 +194,8 @@ mod tests {
                     is_hls: true,
                     impression_urls: vec![],
                     tracking_events: vec![],
-                    error_url: None,
+                    error_urls: vec![],
+                    break_position: None,
                     total_segments: 1,
                     segment_index: 0,
                     visited: false,
@@ -214,7 +219,8 @@ mod tests {
                     is_hls: true,
                     impression_urls: vec![],
                     tracking_events: vec![],
-                    error_url: None,
+                    error_urls: vec![],
+                    break_position: None,
                     total_segments: 1,
                     segment_index: 0,
                     visited: false,
@@ -252,7 +258,8 @@ mod tests {
                     is_hls: true,
                     impression_urls: vec![],
                     tracking_events: vec![],
-                    error_url: None,
+                    error_urls: vec![],
+                    break_position: None,
                     total_segments: 1,
                     segment_index: 0,
                     visited: false,
@@ -1,137 +1,506 @@
-use crate::ad::conditioning;
-use crate::ad::vast::{self, VastAdType, Verification};
+use crate::ad::conditioning::{self, Rejection};
+use crate::ad::frequency::AdIdentity;
+use crate::ad::macros::{self, MacroContext};
+use crate::ad::tracking;
+use crate::ad::vast::{
+    self, Creative, InLineAd, VastAd, VastAdType, Verification, WrapperAd, error_code,
+};
 use crate::http_retry::{RetryConfig, fetch_with_retry};
-use tracing::{error, warn};
+use crate::metrics;
+use futures_util::future::join_all;
+use reqwest::header::HeaderMap;
+use tokio::time::{Instant, timeout};
+use tracing::{error, info, warn};
 
 use super::{ResolvedVastCreative, VastAdProvider};
 
+/// Per-viewer values of a VAST request and the wrappers it leads to
+#[derive(Debug, Clone)]
+pub(crate) struct VastRequest {
+    /// Fills the VAST macros of wrapper `VASTAdTagURI`s and error beacons
+    pub(crate) macros: MacroContext,
+    /// Viewer headers sent with every request of the wrapper chain
+    pub(crate) headers: HeaderMap,
+    /// Viewer headers sent with error beacons
+    pub(crate) beacon_headers: HeaderMap,
+    /// When the ad decision for the break must be complete
+    pub(crate) deadline: Instant,
+}
+
+/// State accumulated along a wrapper chain
+///
+/// Tracking, `<Error>` URLs and verification nodes of every wrapper level
+/// are merged into the creatives the chain resolves to; the wrapper
+/// attributes restrict what the next response may contain.
+#[derive(Debug, Clone)]
+pub(crate) struct WrapperChain {
+    pub(crate) impressions: Vec<String>,
+    pub(crate) tracking: Vec<vast::TrackingEvent>,
+    pub(crate) errors: Vec<String>,
+    pub(crate) verifications: Vec<Verification>,
+    /// Wrappers in the response may be followed (`followAdditionalWrappers`)
+    pub(crate) follow_wrappers: bool,
+    /// The response may hold a pod or several ads (`allowMultipleAds`)
+    pub(crate) allow_multiple_ads: bool,
+}
+
+impl Default for WrapperChain {
+    /// Chain of a top-level request: no wrappers yet, nothing restricted
+    fn default() -> Self {
+        Self {
+            impressions: Vec::new(),
+            tracking: Vec::new(),
+            errors: Vec::new(),
+            verifications: Vec::new(),
+            follow_wrappers: true,
+            allow_multiple_ads: true,
+        }
+    }
+}
+
+impl WrapperChain {
+    /// Chain of the response `wrapper` points to
+    fn wrap(&self, wrapper: &WrapperAd, allow_multiple_ads_default: bool) -> Self {
+        let mut next = self.clone();
+        next.impressions.extend(wrapper.impression_urls.clone());
+        next.tracking.extend(wrapper.tracking_events.clone());
+        next.errors.extend(wrapper.error_urls.clone());
+        next.verifications.extend(wrapper.verifications.clone());
+        next.follow_wrappers = self.follow_wrappers && wrapper.follow_additional_wrappers;
+        next.allow_multiple_ads = wrapper
+            .allow_multiple_ads
+            .unwrap_or(allow_multiple_ads_default);
+        next
+    }
+}
+
+/// Ads of a VAST response in play order, and the stand-alone ads left to
+/// replace ads that fail
+///
+/// Following VAST 4.x: ads with a `sequence` form a pod played in sequence
+/// order, and the stand-alone ads are a buffet for fallback. Without a pod
+/// every stand-alone ad plays in document order. When the wrapper disallows
+/// multiple ads only the first stand-alone ad plays.
+pub(crate) fn plan_ads(ads: &[VastAd], allow_multiple_ads: bool) -> (Vec<&VastAd>, Vec<&VastAd>) {
+    let mut pod: Vec<&VastAd> = ads.iter().filter(|ad| ad.sequence.is_some()).collect();
+    // Stable: ads sharing a sequence number keep their document order
+    pod.sort_by_key(|ad| ad.sequence);
+    let standalone: Vec<&VastAd> = ads.iter().filter(|ad| ad.sequence.is_none()).collect();
+
+    if !allow_multiple_ads {
+        (standalone.into_iter().take(1).collect(), Vec::new())
+    } else if pod.is_empty() {
+        (standalone, Vec::new())
+    } else {
+        (pod, standalone)
+    }
+}
+
 impl VastAdProvider {
     /// Fetch and parse VAST XML, following wrapper chains
     ///
-    /// Accumulates wrapper tracking data and verification nodes through the chain.
-    /// Uses [`fetch_with_retry`] for fault-tolerant HTTP fetching.
+    /// Uses [`fetch_with_retry`] for fault-tolerant HTTP fetching, bounded
+    /// by the request's deadline: a hop that cannot finish in time counts as
+    /// a wrapper timeout. `request` carries the viewer's macro values and headers down the
+    /// wrapper chain; `chain` holds what the wrappers so far accumulated.
+    /// Failures are reported to the chain's `<Error>` URLs with their VAST
+    /// error code (see [`Self::report_error`]).
     ///
-    /// Parameters use owned types (`String`, `Vec<T>`) instead of references
-    /// because recursive async functions cannot hold borrows across `.await`
-    /// points without self-referential lifetimes.
+    /// Parameters use owned types instead of references because recursive
+    /// async functions cannot hold borrows across `.await` points without
+    /// self-referential lifetimes.
     pub(crate) async fn fetch_vast(
         &self,
         url: String,
         depth: u32,
         session_id: String,
-        wrapper_impressions: Vec<String>,
-        wrapper_tracking: Vec<vast::TrackingEvent>,
-        wrapper_verifications: Vec<Verification>,
+        request: VastRequest,
+        chain: WrapperChain,
     ) -> Option<Vec<ResolvedVastCreative>> {
         if depth > self.max_wrapper_depth {
             warn!(
                 "VAST wrapper chain exceeded max depth ({})",
                 self.max_wrapper_depth
             );
+            self.report_error(&chain.errors, error_code::WRAPPER_LIMIT, &request);
+            return None;
+        }
+
+        let remaining = request.deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            warn!(
+                "Ad decision deadline passed before VAST request at depth {}",
+                depth
+            );
+            self.report_error(&chain.errors, error_code::WRAPPER_TIMEOUT, &request);
             return None;
         }
 
         let retry_cfg = RetryConfig {
-            timeout: Some(self.timeout),
+            timeout: Some(self.timeout.min(remaining)),
+            headers: request.headers.clone(),
             ..Default::default()
         };
-        let xml = match fetch_with_retry(&self.http_client, &url, &retry_cfg).await {
-            Ok(resp) => match resp.text().await {
-                Ok(text) => text,
-                Err(e) => {
-                    error!("Failed to read VAST response body: {}", e);
-                    return None;
-                }
-            },
-            Err(e) => {
+        let fetch = async {
+            fetch_with_retry(&self.http_client, &url, &retry_cfg)
+                .await?
+                .text()
+                .await
+        };
+        let xml = match timeout(remaining, fetch).await {
+            Ok(Ok(text)) => text,
+            Ok(Err(e)) => {
                 error!("VAST request failed after retries: {}", e);
+                // Deliberately 301 for HTTP errors too: VAST 4 defines it as
+                // the wrapper's VAST URI being "either unavailable or reached
+                // a timeout", and has no closer code for a 404 or 500
+                self.report_error(&chain.errors, error_code::WRAPPER_TIMEOUT, &request);
+                return None;
+            }
+            Err(_) => {
+                warn!(
+                    "VAST request at depth {} hit the ad decision deadline",
+                    depth
+                );
+                self.report_error(&chain.errors, error_code::WRAPPER_TIMEOUT, &request);
                 return None;
             }
         };
 
-        let vast_response = match vast::parse_vast(&xml) {
+        self.resolve_vast(&xml, depth, session_id, request, chain)
+            .await
+    }
+
+    /// Parse VAST XML and resolve its ads, following wrapper chains
+    ///
+    /// Used by [`Self::fetch_vast`] once the tag is fetched, and directly
+    /// for VAST documents embedded in VMAP (`VASTAdData`). `depth` is the
+    /// wrapper depth of `xml` itself. Ads play in the order of
+    /// [`plan_ads`] and resolve concurrently, so sibling wrappers share the
+    /// deadline instead of queueing behind each other. An ad that resolves
+    /// to no creative is replaced from the stand-alone buffet unless its
+    /// wrapper sets `fallbackOnNoAd="false"`.
+    pub(crate) async fn resolve_vast(
+        &self,
+        xml: &str,
+        depth: u32,
+        session_id: String,
+        request: VastRequest,
+        chain: WrapperChain,
+    ) -> Option<Vec<ResolvedVastCreative>> {
+        let vast_response = match vast::parse_vast(xml) {
             Ok(r) => r,
             Err(e) => {
                 error!("Failed to parse VAST XML: {}", e);
+                self.report_error(&chain.errors, error_code::XML_PARSE, &request);
                 return None;
             }
         };
 
+        let (planned, buffet) = plan_ads(&vast_response.ads, chain.allow_multiple_ads);
+        if planned.is_empty() {
+            if !vast_response.ads.is_empty() {
+                info!("VAST response has no stand-alone ad where only one is allowed");
+            }
+            // 303 is for a wrapper leading nowhere; an empty top-level
+            // response is a plain no-fill
+            if depth > 0 {
+                let mut error_urls = chain.errors;
+                error_urls.extend(vast_response.error_urls);
+                self.report_error(&error_urls, error_code::NO_ADS_AFTER_WRAPPER, &request);
+            } else {
+                info!("VAST response has no ads");
+            }
+            return Some(Vec::new());
+        }
+
+        let allow_multiple_ads_default = vast_response.allows_multiple_ads_by_default();
+        let mut buffet = buffet.into_iter();
         let mut creatives = Vec::new();
 
-        for ad in &vast_response.ads {
-            match &ad.ad_type {
-                VastAdType::InLine(inline) => {
-                    for creative in &inline.creatives {
-                        if let Some(linear) = &creative.linear
-                            && let Some(media_file) =
-                                vast::select_best_media_file(&linear.media_files)
-                        {
-                            // Ad conditioning: check creative compatibility (warnings only)
-                            conditioning::check_creative(media_file, &session_id);
-
-                            let is_hls = media_file.mime_type == "application/x-mpegURL";
-
-                            // Merge wrapper tracking with inline tracking
-                            let mut impression_urls = wrapper_impressions.clone();
-                            impression_urls.extend(inline.impression_urls.clone());
-
-                            let mut tracking_events = wrapper_tracking.clone();
-                            tracking_events.extend(linear.tracking_events.clone());
-
-                            // Merge wrapper verifications with inline verifications
-                            // IAB spec: all Verification nodes from all wrapper levels must survive
-                            let mut verifications = wrapper_verifications.clone();
-                            verifications.extend(inline.verifications.clone());
-
-                            creatives.push(ResolvedVastCreative {
-                                url: media_file.url.clone(),
-                                duration: linear.duration,
-                                is_hls,
-                                impression_urls,
-                                tracking_events,
-                                error_url: inline.error_url.clone(),
-                                verifications,
-                            });
-                        }
-                    }
+        let resolved_ads = join_all(planned.iter().map(|ad| {
+            self.resolve_ad(
+                ad,
+                depth,
+                &session_id,
+                &request,
+                &chain,
+                allow_multiple_ads_default,
+            )
+        }))
+        .await;
+
+        for (ad, mut resolved) in planned.into_iter().zip(resolved_ads) {
+            let may_fall_back = match &ad.ad_type {
+                VastAdType::Wrapper(wrapper) => wrapper.fallback_on_no_ad.unwrap_or(true),
+                VastAdType::InLine(_) => true,
+            };
+            while resolved.is_empty() && may_fall_back {
+                let Some(fallback) = buffet.next() else {
+                    break;
+                };
+                info!(
+                    "VAST ad {} resolved to no creative, falling back to stand-alone ad {}",
+                    ad.id, fallback.id
+                );
+                resolved = self
+                    .resolve_ad(
+                        fallback,
+                        depth,
+                        &session_id,
+                        &request,
+                        &chain,
+                        allow_multiple_ads_default,
+                    )
+                    .await;
+            }
+
+            creatives.extend(resolved);
+        }
+
+        Some(creatives)
+    }
+
+    /// Resolve one ad of a response: its creatives, or those of the chain
+    /// its wrapper leads to
+    async fn resolve_ad(
+        &self,
+        ad: &VastAd,
+        depth: u32,
+        session_id: &str,
+        request: &VastRequest,
+        chain: &WrapperChain,
+        allow_multiple_ads_default: bool,
+    ) -> Vec<ResolvedVastCreative> {
+        match &ad.ad_type {
+            VastAdType::InLine(inline) => {
+                self.resolve_inline(ad, inline, session_id, request, chain)
+            }
+            VastAdType::Wrapper(wrapper) => {
+                let next = chain.wrap(wrapper, allow_multiple_ads_default);
+
+                if !chain.follow_wrappers {
+                    warn!(
+                        "VAST wrapper {} not followed: an earlier wrapper disallows additional wrappers",
+                        ad.id
+                    );
+                    self.report_error(&next.errors, error_code::WRAPPER_LIMIT, request);
+                    return Vec::new();
                 }
-                VastAdType::Wrapper(wrapper) => {
-                    // Accumulate wrapper tracking, verifications and follow chain
-                    let mut merged_impressions = wrapper_impressions.clone();
-                    merged_impressions.extend(wrapper.impression_urls.clone());
-
-                    let mut merged_tracking = wrapper_tracking.clone();
-                    merged_tracking.extend(wrapper.tracking_events.clone());
-
-                    let mut merged_verifications = wrapper_verifications.clone();
-                    merged_verifications.extend(wrapper.verifications.clone());
-
-                    // Box::pin is required for recursive async functions to avoid
-                    // infinite future size at compile time
-                    if let Some(mut wrapped_creatives) = Box::pin(self.fetch_vast(
-                        wrapper.ad_tag_uri.clone(),
-                        depth + 1,
-                        session_id.clone(),
-                        merged_impressions,
-                        merged_tracking,
-                        merged_verifications,
-                    ))
-                    .await
-                    {
-                        creatives.append(&mut wrapped_creatives);
-                    }
+                if wrapper.ad_tag_uri.is_empty() {
+                    warn!("VAST wrapper {} has no VASTAdTagURI", ad.id);
+                    self.report_error(&next.errors, error_code::NO_ADS_AFTER_WRAPPER, request);
+                    return Vec::new();
                 }
+
+                // Box::pin is required for recursive async functions to avoid
+                // infinite future size at compile time
+                Box::pin(self.fetch_vast(
+                    macros::expand(&wrapper.ad_tag_uri, &request.macros),
+                    depth + 1,
+                    session_id.to_string(),
+                    request.clone(),
+                    next,
+                ))
+                .await
+                .unwrap_or_default()
             }
         }
+    }
 
-        Some(creatives)
+    /// Creatives of an InLine ad, merged with the chain's tracking data
+    fn resolve_inline(
+        &self,
+        ad: &VastAd,
+        inline: &InLineAd,
+        session_id: &str,
+        request: &VastRequest,
+        chain: &WrapperChain,
+    ) -> Vec<ResolvedVastCreative> {
+        // Report to the whole chain: wrappers first, then the InLine
+        let mut error_urls = chain.errors.clone();
+        error_urls.extend(inline.error_urls.clone());
+
+        let mut creatives = Vec::new();
+        for creative in &inline.creatives {
+            let Some(linear) = &creative.linear else {
+                continue;
+            };
+
+            // Creative policy: the ad itself, then its media files in turn
+            let universal_ad_id = creative.universal_ad_id.as_ref();
+            let ad_ids = [
+                ad.id.as_str(),
+                creative.id.as_str(),
+                universal_ad_id.map_or("", |id| id.value.as_str()),
+            ];
+            if let Err(rejection) = self.creative_policy.check_ad(
+                linear.duration,
+                &ad_ids,
+                inline.advertiser.as_deref(),
+            ) {
+                warn!(
+                    "VAST ad {} rejected by the creative policy ({}) for session {}",
+                    ad.id,
+                    rejection.as_str(),
+                    session_id
+                );
+                metrics::record_creative_rejection(rejection.as_str());
+                if rejection == Rejection::Duration {
+                    self.report_error(&error_urls, error_code::UNEXPECTED_DURATION, request);
+                }
+                continue;
+            }
+            let Some(media_file) = self
+                .creative_policy
+                .select_media_file(&linear.media_files, session_id)
+            else {
+                warn!(
+                    "VAST ad {} has no supported media file for session {}",
+                    ad.id, session_id
+                );
+                self.report_error(&error_urls, error_code::UNSUPPORTED_MEDIA, request);
+                continue;
+            };
+
+            // Ad conditioning: check creative compatibility (warnings only)
+            conditioning::check_creative(media_file, session_id);
+
+            let is_hls = conditioning::is_hls_mime(&media_file.mime_type);
+            // Other accepted files, should probing find the chosen one unfit
+            let alternatives = if self.prober.is_some() {
+                self.creative_policy
+                    .accepted_media_files(&linear.media_files)
+                    .into_iter()
+                    .filter(|f| !std::ptr::eq(*f, media_file))
+                    .cloned()
+                    .collect()
+            } else {
+                Vec::new()
+            };
+
+            // Merge wrapper tracking with inline tracking
+            let mut impression_urls = chain.impressions.clone();
+            impression_urls.extend(inline.impression_urls.clone());
+
+            let mut tracking_events = chain.tracking.clone();
+            tracking_events.extend(linear.tracking_events.clone());
+
+            // Merge wrapper verifications with inline verifications
+            // IAB spec: all Verification nodes from all wrapper levels must survive
+            let mut verifications = chain.verifications.clone();
+            verifications.extend(inline.verifications.clone());
+
+            creatives.push(ResolvedVastCreative {
+                url: media_file.url.clone(),
+                duration: linear.duration,
+                is_hls,
+                impression_urls,
+                tracking_events,
+                error_urls: error_urls.clone(),
+                verifications,
+                identity: AdIdentity {
+                    ad_id: creative_ad_id(ad, creative, &media_file.url),
+                    advertiser: inline.advertiser.clone(),
+                    categories: inline.categories.clone(),
+                },
+                alternatives,
+                sequenced: ad.sequence.is_some(),
+            });
+        }
+        creatives
+    }
+
+    /// Report a VAST failure to the `<Error>` URLs of the chain it occurred in
+    ///
+    /// `[ERRORCODE]` in the URLs becomes `code`; the other macros are filled
+    /// from the viewer's request.
+    pub(crate) fn report_error(&self, error_urls: &[String], code: u16, request: &VastRequest) {
+        metrics::record_vast_error(code);
+        if error_urls.is_empty() {
+            return;
+        }
+        tracking::fire_error(
+            self.http_client.clone(),
+            error_urls,
+            &request.macros.clone().with_error_code(code),
+            &request.beacon_headers,
+        );
+    }
+}
+
+/// ID a creative is capped by: its Universal Ad ID, else the creative or
+/// ad ID, else its media URL
+fn creative_ad_id(ad: &VastAd, creative: &Creative, media_url: &str) -> String {
+    if let Some(universal) = &creative.universal_ad_id {
+        universal.key()
+    } else if !creative.id.is_empty() {
+        format!("creative:{}", creative.id)
+    } else if !ad.id.is_empty() {
+        format!("ad:{}", ad.id)
+    } else {
+        media_url.to_string()
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::ad::vast::{TrackingEvent, Verification};
+    use super::plan_ads;
+    use crate::ad::vast::{InLineAd, TrackingEvent, VastAd, VastAdType, Verification};
+
+    fn ad(id: &str, sequence: Option<u32>) -> VastAd {
+        VastAd {
+            id: id.to_string(),
+            sequence,
+            ad_type: VastAdType::InLine(InLineAd {
+                ad_system: String::new(),
+                ad_title: String::new(),
+                creatives: vec![],
+                impression_urls: vec![],
+                error_urls: vec![],
+                verifications: vec![],
+                advertiser: None,
+                categories: vec![],
+            }),
+        }
+    }
+
+    fn ids(ads: &[&VastAd]) -> Vec<String> {
+        ads.iter().map(|ad| ad.id.clone()).collect()
+    }
+
+    #[test]
+    fn plan_orders_pod_by_sequence_and_keeps_buffet() {
+        let ads = vec![
+            ad("s1", None),
+            ad("p3", Some(3)),
+            ad("p1", Some(1)),
+            ad("s2", None),
+            ad("p2", Some(2)),
+        ];
+        let (planned, buffet) = plan_ads(&ads, true);
+        assert_eq!(ids(&planned), vec!["p1", "p2", "p3"]);
+        assert_eq!(ids(&buffet), vec!["s1", "s2"]);
+    }
+
+    #[test]
+    fn plan_without_pod_plays_standalone_ads_in_order() {
+        let ads = vec![ad("a", None), ad("b", None)];
+        let (planned, buffet) = plan_ads(&ads, true);
+        assert_eq!(ids(&planned), vec!["a", "b"]);
+        assert!(buffet.is_empty());
+    }
+
+    #[test]
+    fn plan_single_ad_takes_first_standalone() {
+        let ads = vec![ad("p1", Some(1)), ad("s1", None), ad("s2", None)];
+        let (planned, buffet) = plan_ads(&ads, false);
+        assert_eq!(ids(&planned), vec!["s1"]);
+        assert!(buffet.is_empty());
+
+        let pod_only = vec![ad("p1", Some(1))];
+        assert!(plan_ads(&pod_only, false).0.is_empty());
+    }
 
     #[test]
     fn test_wrapper_tracking_merge() {
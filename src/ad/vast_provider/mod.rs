@@ -1,10 +1,24 @@
 mod cache;
 mod fetch;
 
-use crate::ad::provider::{AdCreative, AdProvider, AdSegment, AdTrackingInfo, ResolvedSegment};
-use crate::ad::slate::SlateProvider;
-use crate::ad::vast::{TrackingEvent, Verification};
+use fetch::{VastRequest, WrapperChain};
+
+use crate::ad::conditioning::{self, CreativePolicy};
+use crate::ad::context::AdRequestContext;
+use crate::ad::frequency::{AdIdentity, FrequencyPolicy};
+use crate::ad::macros;
+use crate::ad::normalizer::{AdNormalizer, Package};
+use crate::ad::pod::{self, PodPlan};
+use crate::ad::probe::{CreativeProber, Verdict};
+use crate::ad::provider::{
+    AdCreative, AdProvider, AdSegment, AdTrackingInfo, ResolvedSegment, ad_segment_url,
+    break_segment_url,
+};
+use crate::ad::schedule::BreakPosition;
+use crate::ad::slate::{SlateProvider, is_slate_segment};
+use crate::ad::vast::{MediaFile, TrackingEvent, Verification, VmapAdSource};
 use crate::metrics;
+use crate::session::SessionManager;
 use async_trait::async_trait;
 use cache::MAX_CACHE_SIZE;
 use dashmap::DashMap;
@@ -26,10 +40,18 @@ pub(crate) struct ResolvedVastCreative {
     pub(crate) impression_urls: Vec<String>,
     /// Tracking events
     pub(crate) tracking_events: Vec<TrackingEvent>,
-    /// Error URL
-    pub(crate) error_url: Option<String>,
+    /// `<Error>` URLs of the InLine ad and its wrapper chain
+    pub(crate) error_urls: Vec<String>,
     /// OMID verification resources accumulated from wrapper chain + InLine
     pub(crate) verifications: Vec<Verification>,
+    /// Ad ID, advertiser and categories for frequency capping
+    pub(crate) identity: AdIdentity,
+    /// Whether the ad belongs to a VAST 4 pod (`sequence`), which plays in
+    /// order
+    pub(crate) sequenced: bool,
+    /// Other media files meeting the creative policy, in preference order;
+    /// only collected when a prober is configured
+    pub(crate) alternatives: Vec<MediaFile>,
 }
 
 /// Ad creative cached per session with tracking state
@@ -46,8 +68,10 @@ pub(crate) struct ResolvedCreative {
     pub(crate) impression_urls: Vec<String>,
     /// Tracking events
     pub(crate) tracking_events: Vec<TrackingEvent>,
-    /// Error URL
-    pub(crate) error_url: Option<String>,
+    /// `<Error>` URLs of the InLine ad and its wrapper chain
+    pub(crate) error_urls: Vec<String>,
+    /// Where the break plays, when known
+    pub(crate) break_position: Option<BreakPosition>,
     /// Total segments in this ad
     pub(crate) total_segments: usize,
     /// Index of this segment
@@ -63,10 +87,12 @@ pub(crate) struct ResolvedCreative {
 /// Implements the AdProvider trait by:
 /// 1. Fetching VAST XML from configured endpoint on each ad break
 /// 2. Parsing the response to extract media file URLs and durations
-/// 3. Caching resolved creatives per session for segment URL resolution
+/// 3. Fitting the pod to the break (see [`pod::fit_pod`]), padding any
+///    remainder with slate when configured
+/// 4. Caching resolved creatives per session for segment URL resolution
 #[derive(Clone)]
 pub struct VastAdProvider {
-    /// VAST endpoint URL (with optional macros like [DURATION])
+    /// VAST endpoint URL (with optional VAST macros like [DURATION])
     vast_endpoint: String,
     /// HTTP client for VAST requests
     pub(crate) http_client: Client,
@@ -76,17 +102,33 @@ pub struct VastAdProvider {
     pub(crate) break_counter: Arc<DashMap<String, u32>>,
     /// Maximum number of VAST wrapper redirects to follow
     pub(crate) max_wrapper_depth: u32,
-    /// VAST request timeout
+    /// Timeout of a single VAST request
     pub(crate) timeout: Duration,
+    /// Overall time budget to resolve the ads of a break, wrappers included
+    pub(crate) decision_timeout: Duration,
     /// Optional slate provider for fallback when VAST returns no ads
     pub(crate) slate: Option<SlateProvider>,
+    /// Send the viewer's `X-Device-*` headers with VAST requests
+    pub(crate) forward_viewer_headers: bool,
+    /// Send the viewer's `X-Device-*` headers with error beacons
+    pub(crate) forward_beacon_headers: bool,
+    /// Frequency cap and competitive separation of each session's ads
+    pub(crate) frequency_policy: FrequencyPolicy,
+    /// Session store holding the ad history the policy is applied to
+    pub(crate) sessions: Option<SessionManager>,
+    /// Rules creatives and their media files must meet to be stitched
+    pub(crate) creative_policy: CreativePolicy,
+    /// Checks creative media against the content's codecs before stitching
+    pub(crate) prober: Option<CreativeProber>,
+    /// Transcodes non-HLS creatives into packages that can be stitched
+    pub(crate) normalizer: Option<AdNormalizer>,
 }
 
 impl VastAdProvider {
     /// Create a new VastAdProvider
     ///
     /// # Arguments
-    /// * `vast_endpoint` - VAST endpoint URL (supports `[DURATION]` and `[CACHEBUSTING]` macros)
+    /// * `vast_endpoint` - VAST endpoint URL (supports the VAST macros of [`macros::expand`])
     /// * `http_client` - Shared HTTP client for VAST requests
     pub fn new(vast_endpoint: String, http_client: Client) -> Self {
         Self {
@@ -96,7 +138,15 @@ impl VastAdProvider {
             break_counter: Arc::new(DashMap::new()),
             max_wrapper_depth: 5,
             timeout: Duration::from_millis(2000),
+            decision_timeout: Duration::from_millis(3000),
             slate: None,
+            forward_viewer_headers: true,
+            forward_beacon_headers: true,
+            frequency_policy: FrequencyPolicy::default(),
+            sessions: None,
+            creative_policy: CreativePolicy::default(),
+            prober: None,
+            normalizer: None,
         }
     }
 
@@ -109,26 +159,96 @@ impl VastAdProvider {
         self
     }
 
-    /// Replace VAST macros in the endpoint URL
-    pub(crate) fn resolve_endpoint(&self, duration: f32) -> String {
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_millis();
+    /// Enable or disable forwarding the viewer's IP, user agent and referer
+    /// headers on VAST requests (see [`AdRequestContext::viewer_headers`])
+    pub fn with_viewer_headers(mut self, forward: bool) -> Self {
+        self.forward_viewer_headers = forward;
+        self
+    }
 
-        // VAST macro: duration is always a positive f32 representing seconds;
-        // truncation to u32 is intentional (ad servers expect integer seconds).
-        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
-        let dur_secs = duration as u32;
-        self.vast_endpoint
-            .replace("[DURATION]", &format!("{}", dur_secs))
-            .replace("[CACHEBUSTING]", &format!("{}", timestamp))
+    /// Enable or disable forwarding the viewer headers on the error beacons
+    /// fired while resolving VAST
+    pub fn with_beacon_headers(mut self, forward: bool) -> Self {
+        self.forward_beacon_headers = forward;
+        self
+    }
+
+    /// Set the overall deadline for resolving a break's ads
+    ///
+    /// Wrapper hops share this budget; ads that have not resolved when it
+    /// runs out are left out and the break is padded with slate.
+    pub fn with_decision_timeout(mut self, timeout: Duration) -> Self {
+        self.decision_timeout = timeout;
+        self
+    }
+
+    /// Cap and separate each session's ads by its history in `sessions`
+    /// (see [`crate::ad::frequency`])
+    pub fn with_frequency_policy(
+        mut self,
+        policy: FrequencyPolicy,
+        sessions: SessionManager,
+    ) -> Self {
+        self.frequency_policy = policy;
+        self.sessions = Some(sessions);
+        self
+    }
+
+    /// Set the rules creatives must meet (see [`CreativePolicy`])
+    pub fn with_creative_policy(mut self, policy: CreativePolicy) -> Self {
+        self.creative_policy = policy;
+        self
+    }
+
+    /// Probe creative media before stitching (see [`crate::ad::probe`])
+    pub fn with_prober(mut self, prober: CreativeProber) -> Self {
+        self.prober = Some(prober);
+        self
+    }
+
+    /// Play non-HLS creatives through their normalized packages (see
+    /// [`crate::ad::normalizer`])
+    pub fn with_normalizer(mut self, normalizer: AdNormalizer) -> Self {
+        self.normalizer = Some(normalizer);
+        self
+    }
+
+    /// Macro values, headers and deadline of a viewer's VAST requests for
+    /// one break
+    fn vast_request(&self, ctx: &AdRequestContext) -> VastRequest {
+        let headers_if = |forward: bool| {
+            if forward {
+                ctx.viewer_headers()
+            } else {
+                Default::default()
+            }
+        };
+        VastRequest {
+            macros: ctx.macro_context(),
+            headers: headers_if(self.forward_viewer_headers),
+            beacon_headers: headers_if(self.forward_beacon_headers),
+            deadline: tokio::time::Instant::now() + self.decision_timeout,
+        }
+    }
+
+    /// Build the VAST request URL for a viewer
+    ///
+    /// Fills the endpoint's VAST macros from the viewer context and appends
+    /// the viewer's targeting parameters, minus any `ad.` namespace
+    /// (`ad.genre=news` is sent as `genre=news`). Breaks requested from
+    /// the endpoint are signalled in the content, so they are mid-rolls.
+    pub(crate) fn resolve_endpoint(&self, duration: f32, ctx: &AdRequestContext) -> String {
+        let macro_ctx = ctx
+            .macro_context()
+            .with_duration(f64::from(duration))
+            .with_break_position(BreakPosition::Mid);
+        with_targeting(macros::expand(&self.vast_endpoint, &macro_ctx), ctx)
     }
 
     /// Generate slate fallback segments when VAST returns no ads
     ///
-    /// Slate segments use "slate-seg-N.ts" naming to distinguish them
-    /// from regular VAST ad segments ("break-N-seg-M.ts").
+    /// Slate segments use "slate-" naming to distinguish them from regular
+    /// VAST ad segments ("break-N-seg-M.ts").
     fn slate_fallback(
         &self,
         slate: &SlateProvider,
@@ -144,12 +264,448 @@ impl VastAdProvider {
         segments
     }
 
+    /// Resolve a VMAP ad source: request its VAST tag or parse its inline VAST
+    async fn fetch_source(
+        &self,
+        source: &VmapAdSource,
+        session_id: &str,
+        ctx: &AdRequestContext,
+    ) -> Option<Vec<ResolvedVastCreative>> {
+        let deadline = tokio::time::Instant::now() + self.decision_timeout;
+        self.resolve_source(source, session_id, ctx, deadline).await
+    }
+
+    /// Resolve a VAST tag or inline VAST document by `deadline`
+    ///
+    /// Lets other providers that decide ads themselves (e.g. an OpenRTB
+    /// auction returning VAST markup) share the wrapper resolution.
+    pub(crate) async fn resolve_source(
+        &self,
+        source: &VmapAdSource,
+        session_id: &str,
+        ctx: &AdRequestContext,
+        deadline: tokio::time::Instant,
+    ) -> Option<Vec<ResolvedVastCreative>> {
+        let request = VastRequest {
+            deadline,
+            ..self.vast_request(ctx)
+        };
+        match source {
+            VmapAdSource::AdTagUri(url) => {
+                self.fetch_vast(
+                    with_targeting(macros::expand(url, &request.macros), ctx),
+                    0,
+                    session_id.to_string(),
+                    request,
+                    WrapperChain::default(),
+                )
+                .await
+            }
+            VmapAdSource::VastAdData(xml) => {
+                self.resolve_vast(
+                    xml,
+                    0,
+                    session_id.to_string(),
+                    request,
+                    WrapperChain::default(),
+                )
+                .await
+            }
+        }
+    }
+
+    /// Check creative media against the content with the prober
+    ///
+    /// A creative whose chosen media file mismatches switches to the first
+    /// matching alternative, or to one that could not be probed; it is left
+    /// out when all of them mismatch.
+    pub(crate) async fn probe_creatives(
+        &self,
+        creatives: Vec<ResolvedVastCreative>,
+        session_id: &str,
+    ) -> Vec<ResolvedVastCreative> {
+        let Some(prober) = &self.prober else {
+            return creatives;
+        };
+        futures_util::future::join_all(
+            creatives
+                .into_iter()
+                .map(|creative| self.probe_creative(prober, creative, session_id)),
+        )
+        .await
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+
+    async fn probe_creative(
+        &self,
+        prober: &CreativeProber,
+        mut creative: ResolvedVastCreative,
+        session_id: &str,
+    ) -> Option<ResolvedVastCreative> {
+        let verdict = prober
+            .verdict(&creative.url, creative.is_hls, creative.duration)
+            .await;
+        metrics::record_creative_probe(verdict.as_str());
+        if verdict == Verdict::Match {
+            return Some(creative);
+        }
+
+        let alternatives = std::mem::take(&mut creative.alternatives);
+        let verdicts = futures_util::future::join_all(alternatives.iter().map(|media_file| {
+            prober.verdict(
+                &media_file.url,
+                conditioning::is_hls_mime(&media_file.mime_type),
+                creative.duration,
+            )
+        }))
+        .await;
+        for verdict in &verdicts {
+            metrics::record_creative_probe(verdict.as_str());
+        }
+
+        // A matching alternative beats an unprobed media file; an unprobed
+        // one beats a mismatching file
+        let switch_to = verdicts
+            .iter()
+            .position(|v| *v == Verdict::Match)
+            .or_else(|| {
+                (verdict != Verdict::Unknown)
+                    .then(|| verdicts.iter().position(|v| *v == Verdict::Unknown))
+                    .flatten()
+            });
+        match switch_to {
+            Some(index) => {
+                let media_file = &alternatives[index];
+                info!(
+                    "VastAdProvider: Probe switched creative {} to {} for session {}",
+                    creative.url, media_file.url, session_id
+                );
+                creative.url = media_file.url.clone();
+                creative.is_hls = conditioning::is_hls_mime(&media_file.mime_type);
+                Some(creative)
+            }
+            None if verdict == Verdict::Unknown => Some(creative),
+            None => {
+                let Verdict::Mismatch(reason) = verdict else {
+                    unreachable!("matched and unknown verdicts return above");
+                };
+                warn!(
+                    "VastAdProvider: Creative {} does not match the content ({}) for session {}",
+                    creative.url, reason, session_id
+                );
+                metrics::record_creative_rejection(&format!("probe_{}", reason));
+                None
+            }
+        }
+    }
+
+    /// Swap non-HLS creatives for their normalized packages
+    ///
+    /// Creatives whose package is not ready yet, or failed, are left out;
+    /// the rest of the pod, or slate, fills the break.
+    pub(crate) fn normalize_creatives(
+        &self,
+        creatives: Vec<ResolvedVastCreative>,
+        session_id: &str,
+    ) -> Vec<ResolvedVastCreative> {
+        let Some(normalizer) = &self.normalizer else {
+            return creatives;
+        };
+        creatives
+            .into_iter()
+            .filter_map(|mut creative| {
+                if creative.is_hls {
+                    return Some(creative);
+                }
+                match normalizer.package(&creative.identity.ad_id, &creative.url) {
+                    Package::Ready(url) => {
+                        creative.url = url;
+                        creative.is_hls = true;
+                        Some(creative)
+                    }
+                    package => {
+                        info!(
+                            "VastAdProvider: Creative {} left out for session {}, normalization {:?}",
+                            creative.identity.ad_id, session_id, package
+                        );
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// Probe, normalize and frequency-select the resolved creatives of a
+    /// break: everything between deciding its ads and fitting the pod
+    pub(crate) async fn prepare_creatives(
+        &self,
+        creatives: Vec<ResolvedVastCreative>,
+        session_id: &str,
+    ) -> Vec<ResolvedVastCreative> {
+        let creatives = self.probe_creatives(creatives, session_id).await;
+        let creatives = self.normalize_creatives(creatives, session_id);
+        self.select_for_session(creatives, session_id).await
+    }
+
+    /// Leave out the creatives the session may not see (see
+    /// [`crate::ad::frequency`])
+    async fn select_for_session(
+        &self,
+        creatives: Vec<ResolvedVastCreative>,
+        session_id: &str,
+    ) -> Vec<ResolvedVastCreative> {
+        let Some(sessions) = self
+            .sessions
+            .as_ref()
+            .filter(|_| self.frequency_policy.is_enabled())
+        else {
+            return creatives;
+        };
+        let history = sessions.ad_history(session_id).await;
+        let identities: Vec<&AdIdentity> = creatives.iter().map(|c| &c.identity).collect();
+        let selected = self.frequency_policy.select(&identities, &history);
+        if selected.len() < creatives.len() {
+            info!(
+                "VastAdProvider: Frequency policy left out {} creative(s) for session {}",
+                creatives.len() - selected.len(),
+                session_id
+            );
+        }
+        creatives
+            .into_iter()
+            .enumerate()
+            .filter(|(index, _)| selected.contains(index))
+            .map(|(_, creative)| creative)
+            .collect()
+    }
+
+    /// Fit resolved creatives to a break of `duration` seconds and cache them
+    ///
+    /// Overflowing creatives are dropped (see [`pod::fit_pod`]);
+    /// a short pod is padded with slate when configured. `break_position`
+    /// is recorded for the tracking beacons' `[BREAKPOSITION]` macro. The
+    /// placed ads are recorded in the session's history, once for the break
+    /// keyed `break_key`, when a frequency policy is configured.
+    pub(crate) async fn stitch_session_pod(
+        &self,
+        creatives: &[ResolvedVastCreative],
+        duration: f32,
+        break_position: Option<BreakPosition>,
+        session_id: &str,
+        break_key: Option<u64>,
+    ) -> Vec<AdSegment> {
+        let plan = self.fit_pod(creatives, duration, session_id);
+        let placed: Vec<AdIdentity> = plan
+            .slots
+            .iter()
+            .map(|slot| creatives[slot.index].identity.clone())
+            .collect();
+        self.record_session_ads(&placed, session_id, break_key)
+            .await;
+        self.stitch_plan(creatives, &plan, duration, break_position, session_id)
+    }
+
+    /// [Prepared](Self::prepare_creatives) creatives for the player to play
+    /// itself (SGAI), recorded in the session's history like
+    /// [`Self::stitch_session_pod`]'s
+    pub(crate) async fn session_creatives(
+        &self,
+        creatives: Vec<ResolvedVastCreative>,
+        session_id: &str,
+        break_key: Option<u64>,
+    ) -> Vec<AdCreative> {
+        let creatives = self.prepare_creatives(creatives, session_id).await;
+        let placed: Vec<AdIdentity> = creatives.iter().map(|c| c.identity.clone()).collect();
+        self.record_session_ads(&placed, session_id, break_key)
+            .await;
+        creatives
+            .into_iter()
+            .map(|c| AdCreative {
+                uri: c.url,
+                duration: c.duration as f64,
+                verifications: c.verifications,
+            })
+            .collect()
+    }
+
+    /// Add the placed ads to the session's history when a frequency policy
+    /// is configured
+    async fn record_session_ads(
+        &self,
+        placed: &[AdIdentity],
+        session_id: &str,
+        break_key: Option<u64>,
+    ) {
+        if let Some(sessions) = &self.sessions
+            && self.frequency_policy.is_enabled()
+            && !placed.is_empty()
+        {
+            sessions.record_ads(session_id, break_key, placed).await;
+        }
+    }
+
+    /// Fit the pod to the break: drop overflowing creatives
+    fn fit_pod(
+        &self,
+        creatives: &[ResolvedVastCreative],
+        duration: f32,
+        session_id: &str,
+    ) -> PodPlan {
+        let durations: Vec<f32> = creatives.iter().map(|c| c.duration).collect();
+        let sequenced = creatives.iter().any(|c| c.sequenced);
+        let plan = pod::fit_pod(&durations, duration, sequenced);
+        let dropped = creatives.len() - plan.slots.len();
+        if dropped > 0 {
+            info!(
+                "VastAdProvider: Dropped {} creative(s) that do not fit the {}s break for session {}",
+                dropped, duration, session_id
+            );
+            for _ in 0..dropped {
+                metrics::record_pod_adjustment("dropped");
+            }
+        }
+        plan
+    }
+
+    /// Cache the creatives of a fitted pod and build its segments
+    fn stitch_plan(
+        &self,
+        creatives: &[ResolvedVastCreative],
+        plan: &PodPlan,
+        duration: f32,
+        break_position: Option<BreakPosition>,
+        session_id: &str,
+    ) -> Vec<AdSegment> {
+        if plan.slots.is_empty() {
+            if let Some(slate) = &self.slate {
+                warn!(
+                    "VastAdProvider: No creative fits the break for session {} \u{2014} falling back to slate",
+                    session_id
+                );
+                metrics::record_slate_fallback();
+                return self.slate_fallback(slate, duration, session_id);
+            }
+            warn!(
+                "VastAdProvider: No creative fits the break for session {} and no slate configured",
+                session_id
+            );
+            return Vec::new();
+        }
+
+        // Build ad segments and cache them for resolve_segment_url
+        let mut segments = Vec::new();
+        let break_idx = {
+            let mut counter = self
+                .break_counter
+                .entry(session_id.to_string())
+                .or_insert(0);
+            let idx = *counter;
+            *counter += 1;
+            idx
+        };
+        let total_segments = plan.slots.len();
+
+        for (seg_idx, slot) in plan.slots.iter().enumerate() {
+            let creative = &creatives[slot.index];
+            let ad_name = format!("break-{}-seg-{}.ts", break_idx, seg_idx);
+
+            // Cache the resolved creative with tracking metadata.
+            // Guard against unbounded growth between cleanup cycles.
+            if self.ad_cache.len() >= MAX_CACHE_SIZE {
+                warn!(
+                    "Ad cache at capacity ({} entries) \u{2014} skipping insert for {}",
+                    MAX_CACHE_SIZE, ad_name
+                );
+            } else {
+                self.ad_cache.insert(
+                    Self::cache_key(session_id, &ad_name),
+                    ResolvedCreative {
+                        url: creative.url.clone(),
+                        duration: slot.duration,
+                        is_hls: creative.is_hls,
+                        impression_urls: creative.impression_urls.clone(),
+                        tracking_events: creative.tracking_events.clone(),
+                        error_urls: creative.error_urls.clone(),
+                        break_position,
+                        total_segments,
+                        segment_index: seg_idx,
+                        visited: false,
+                        inserted_at: Instant::now(),
+                    },
+                );
+            }
+
+            segments.push(AdSegment {
+                uri: ad_name,
+                duration: slot.duration,
+                tracking: Some(AdTrackingInfo {
+                    impression_urls: creative.impression_urls.clone(),
+                    tracking_events: creative.tracking_events.clone(),
+                    error_urls: creative.error_urls.clone(),
+                    total_segments,
+                    segment_index: seg_idx,
+                    asset_uri: Some(creative.url.clone()),
+                    duration: slot.duration,
+                    break_position,
+                }),
+            });
+        }
+
+        // Pad a short pod with whole slate segments up to the break duration
+        if plan.remainder > 0.0 {
+            if let Some(slate) = &self.slate {
+                info!(
+                    "VastAdProvider: Padding {}s of the break with slate for session {}",
+                    plan.remainder, session_id
+                );
+                metrics::record_pod_adjustment("padded");
+                segments.extend(slate.fill_duration(plan.remainder, session_id));
+            } else {
+                warn!(
+                    "VastAdProvider: Pod is {}s short of the break for session {} and no slate configured",
+                    plan.remainder, session_id
+                );
+            }
+        }
+
+        info!(
+            "VastAdProvider: Resolved {} ad segment(s) for session {}",
+            segments.len(),
+            session_id
+        );
+
+        segments
+    }
+
     /// Build cache key for ad segment lookup
     fn cache_key(session_id: &str, ad_name: &str) -> String {
         format!("{}:{}", session_id, ad_name)
     }
 }
 
+/// Append a viewer's targeting parameters to a VAST request URL
+///
+/// A leading `ad.` namespace is dropped from the parameter names. URLs
+/// that do not parse are returned unchanged.
+fn with_targeting(url: String, ctx: &AdRequestContext) -> String {
+    if ctx.params.is_empty() {
+        return url;
+    }
+    let Ok(mut parsed) = reqwest::Url::parse(&url) else {
+        warn!("VastAdProvider: Cannot add targeting to unparsable VAST URL");
+        return url;
+    };
+    {
+        let mut query = parsed.query_pairs_mut();
+        for (name, value) in &ctx.params {
+            query.append_pair(name.strip_prefix("ad.").unwrap_or(name), value);
+        }
+    }
+    parsed.into()
+}
+
 impl std::fmt::Debug for VastAdProvider {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("VastAdProvider")
@@ -165,15 +721,26 @@ impl std::fmt::Debug for VastAdProvider {
 
 #[async_trait]
 impl AdProvider for VastAdProvider {
-    async fn get_ad_segments(&self, duration: f32, session_id: &str) -> Vec<AdSegment> {
-        let url = self.resolve_endpoint(duration);
+    async fn get_ad_segments(
+        &self,
+        duration: f32,
+        session_id: &str,
+        ctx: &AdRequestContext,
+    ) -> Vec<AdSegment> {
+        let url = self.resolve_endpoint(duration, ctx);
         info!(
             "VastAdProvider: Fetching VAST for session {} (duration: {}s) from {}",
             session_id, duration, url
         );
 
         let creatives = match self
-            .fetch_vast(url, 0, session_id.to_string(), vec![], vec![], vec![])
+            .fetch_vast(
+                url,
+                0,
+                session_id.to_string(),
+                self.vast_request(ctx),
+                WrapperChain::default(),
+            )
             .await
         {
             Some(c) if !c.is_empty() => {
@@ -216,72 +783,92 @@ impl AdProvider for VastAdProvider {
             }
         };
 
-        // Build ad segments and cache them for resolve_segment_url
-        let mut segments = Vec::new();
-        let break_idx = {
-            let mut counter = self
-                .break_counter
-                .entry(session_id.to_string())
-                .or_insert(0);
-            let idx = *counter;
-            *counter += 1;
-            idx
-        };
-        let total_segments = creatives.len();
-
-        for (seg_idx, creative) in creatives.iter().enumerate() {
-            let ad_name = format!("break-{}-seg-{}.ts", break_idx, seg_idx);
+        let creatives = self.prepare_creatives(creatives, session_id).await;
+        self.stitch_session_pod(
+            &creatives,
+            duration,
+            Some(BreakPosition::Mid),
+            session_id,
+            ctx.break_key,
+        )
+        .await
+    }
 
-            // Cache the resolved creative with tracking metadata.
-            // Guard against unbounded growth between cleanup cycles.
-            if self.ad_cache.len() >= MAX_CACHE_SIZE {
+    async fn get_ad_segments_for_source(
+        &self,
+        source: &VmapAdSource,
+        duration: f32,
+        session_id: &str,
+        ctx: &AdRequestContext,
+    ) -> Vec<AdSegment> {
+        info!(
+            "VastAdProvider: Resolving VMAP ad source for session {}",
+            session_id
+        );
+        match self.fetch_source(source, session_id, ctx).await {
+            Some(creatives) if !creatives.is_empty() => {
+                metrics::record_vast_request("success");
+                // A scheduled break lasts as long as its pod
+                let creatives = self.prepare_creatives(creatives, session_id).await;
+                let pod_duration = creatives.iter().map(|c| c.duration).sum();
+                self.stitch_session_pod(&creatives, pod_duration, None, session_id, ctx.break_key)
+                    .await
+            }
+            result => {
+                // An unfilled scheduled break is dropped rather than slated:
+                // VOD content simply plays on
+                metrics::record_vast_request(if result.is_some() { "empty" } else { "error" });
                 warn!(
-                    "Ad cache at capacity ({} entries) \u{2014} skipping insert for {}",
-                    MAX_CACHE_SIZE, ad_name
-                );
-            } else {
-                self.ad_cache.insert(
-                    Self::cache_key(session_id, &ad_name),
-                    ResolvedCreative {
-                        url: creative.url.clone(),
-                        duration: creative.duration,
-                        is_hls: creative.is_hls,
-                        impression_urls: creative.impression_urls.clone(),
-                        tracking_events: creative.tracking_events.clone(),
-                        error_url: creative.error_url.clone(),
-                        total_segments,
-                        segment_index: seg_idx,
-                        visited: false,
-                        inserted_at: Instant::now(),
-                    },
+                    "VastAdProvider: No ads for VMAP break of session {} (requested {}s)",
+                    session_id, duration
                 );
+                Vec::new()
             }
+        }
+    }
 
-            segments.push(AdSegment {
-                uri: ad_name,
-                duration: creative.duration,
-                tracking: Some(AdTrackingInfo {
-                    impression_urls: creative.impression_urls.clone(),
-                    tracking_events: creative.tracking_events.clone(),
-                    error_url: creative.error_url.clone(),
-                    total_segments,
-                    segment_index: seg_idx,
-                }),
-            });
+    async fn get_ad_creatives_for_source(
+        &self,
+        source: &VmapAdSource,
+        _duration: f32,
+        session_id: &str,
+        ctx: &AdRequestContext,
+    ) -> Vec<AdCreative> {
+        match self.fetch_source(source, session_id, ctx).await {
+            Some(creatives) if !creatives.is_empty() => {
+                metrics::record_vast_request("success");
+                self.session_creatives(creatives, session_id, ctx.break_key)
+                    .await
+            }
+            result => {
+                metrics::record_vast_request(if result.is_some() { "empty" } else { "error" });
+                warn!(
+                    "VastAdProvider: No ads for VMAP break of session {} (get_ad_creatives_for_source)",
+                    session_id
+                );
+                Vec::new()
+            }
         }
+    }
 
-        info!(
-            "VastAdProvider: Resolved {} ad segment(s) for session {}",
-            segments.len(),
-            session_id
-        );
-
-        segments
+    fn segment_url(
+        &self,
+        segment_name: &str,
+        session_id: &str,
+        base_url: &str,
+        break_idx: usize,
+        segment_idx: usize,
+    ) -> String {
+        if is_slate_segment(segment_name) {
+            ad_segment_url(base_url, session_id, segment_name)
+        } else {
+            break_segment_url(base_url, session_id, break_idx, segment_idx)
+        }
     }
 
     fn resolve_segment_url(&self, ad_name: &str, session_id: &str) -> Option<String> {
         // Check if this is a slate segment
-        if ad_name.starts_with("slate-seg-") {
+        if is_slate_segment(ad_name) {
             if let Some(slate) = &self.slate {
                 return slate.resolve_segment_url(ad_name);
             }
@@ -299,27 +886,32 @@ impl AdProvider for VastAdProvider {
         None
     }
 
-    async fn get_ad_creatives(&self, duration: f32, session_id: &str) -> Vec<AdCreative> {
-        let url = self.resolve_endpoint(duration);
+    async fn get_ad_creatives(
+        &self,
+        duration: f32,
+        session_id: &str,
+        ctx: &AdRequestContext,
+    ) -> Vec<AdCreative> {
+        let url = self.resolve_endpoint(duration, ctx);
         info!(
             "VastAdProvider: Fetching VAST creatives for session {} (duration: {}s)",
             session_id, duration
         );
 
         match self
-            .fetch_vast(url, 0, session_id.to_string(), vec![], vec![], vec![])
+            .fetch_vast(
+                url,
+                0,
+                session_id.to_string(),
+                self.vast_request(ctx),
+                WrapperChain::default(),
+            )
             .await
         {
             Some(creatives) if !creatives.is_empty() => {
                 metrics::record_vast_request("success");
-                creatives
-                    .into_iter()
-                    .map(|c| AdCreative {
-                        uri: c.url,
-                        duration: c.duration as f64,
-                        verifications: c.verifications,
-                    })
-                    .collect()
+                self.session_creatives(creatives, session_id, ctx.break_key)
+                    .await
             }
             Some(_) => {
                 metrics::record_vast_request("empty");
@@ -342,6 +934,16 @@ impl AdProvider for VastAdProvider {
 
     fn cleanup_cache(&self) {
         self.run_cleanup_cache();
+        if let Some(prober) = &self.prober {
+            prober.cleanup();
+        }
+        if let Some(normalizer) = &self.normalizer {
+            normalizer.cleanup();
+        }
+    }
+
+    fn slate(&self) -> Option<&SlateProvider> {
+        self.slate.as_ref()
     }
 
     fn resolve_segment_with_tracking(
@@ -350,7 +952,7 @@ impl AdProvider for VastAdProvider {
         session_id: &str,
     ) -> Option<ResolvedSegment> {
         // Slate segments have no tracking
-        if ad_name.starts_with("slate-seg-") {
+        if is_slate_segment(ad_name) {
             if let Some(slate) = &self.slate {
                 return slate
                     .resolve_segment_url(ad_name)
@@ -371,9 +973,12 @@ impl AdProvider for VastAdProvider {
                 Some(AdTrackingInfo {
                     impression_urls: entry.impression_urls.clone(),
                     tracking_events: entry.tracking_events.clone(),
-                    error_url: entry.error_url.clone(),
+                    error_urls: entry.error_urls.clone(),
                     total_segments: entry.total_segments,
                     segment_index: entry.segment_index,
+                    asset_uri: Some(entry.url.clone()),
+                    duration: entry.duration,
+                    break_position: entry.break_position,
                 })
             } else {
                 // Already served, don't fire tracking again
@@ -399,12 +1004,14 @@ mod tests {
     fn test_resolve_endpoint_macros() {
         let client = Client::new();
         let provider = VastAdProvider::new(
-            "http://ads.example.com/vast?dur=[DURATION]&cb=[CACHEBUSTING]".to_string(),
+            "http://ads.example.com/vast?dur=[DURATION]&cb=[CACHEBUSTING]&bp=[BREAKPOSITION]"
+                .to_string(),
             client,
         );
 
-        let resolved = provider.resolve_endpoint(30.0);
+        let resolved = provider.resolve_endpoint(30.0, &AdRequestContext::default());
         assert!(resolved.contains("dur=30"));
+        assert!(resolved.contains("bp=2"));
         assert!(!resolved.contains("[CACHEBUSTING]"));
         assert!(!resolved.contains("[DURATION]"));
     }
@@ -430,7 +1037,8 @@ mod tests {
                 is_hls: true,
                 impression_urls: vec![],
                 tracking_events: vec![],
-                error_url: None,
+                error_urls: vec![],
+                break_position: None,
                 total_segments: 1,
                 segment_index: 0,
                 visited: false,
@@ -468,7 +1076,8 @@ mod tests {
                 is_hls: true,
                 impression_urls: vec!["http://impression.example.com".to_string()],
                 tracking_events: vec![],
-                error_url: None,
+                error_urls: vec![],
+                break_position: None,
                 total_segments: 1,
                 segment_index: 0,
                 visited: false,
@@ -547,7 +1156,9 @@ mod tests {
         let client = Client::new();
         let provider = VastAdProvider::new(server.uri(), client);
 
-        let segments = provider.get_ad_segments(30.0, "session-vast").await;
+        let segments = provider
+            .get_ad_segments(30.0, "session-vast", &AdRequestContext::default())
+            .await;
 
         assert!(!segments.is_empty(), "Should return ad segments from VAST");
         assert_eq!(
@@ -565,6 +1176,253 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn get_ad_segments_fits_pod_to_break() {
+        use wiremock::matchers::method;
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        // Two-ad pod: 20s + 15s against a 27s break
+        const VAST_POD: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<VAST version="3.0">
+  <Ad id="ad-1" sequence="1">
+    <InLine>
+      <AdSystem>TestAds</AdSystem>
+      <AdTitle>Long</AdTitle>
+      <Creatives>
+        <Creative id="c1">
+          <Linear>
+            <Duration>00:00:20</Duration>
+            <MediaFiles>
+              <MediaFile delivery="streaming" type="application/x-mpegURL" width="1280" height="720">
+                http://ad.example.com/long.m3u8
+              </MediaFile>
+            </MediaFiles>
+          </Linear>
+        </Creative>
+      </Creatives>
+    </InLine>
+  </Ad>
+  <Ad id="ad-2" sequence="2">
+    <InLine>
+      <AdSystem>TestAds</AdSystem>
+      <AdTitle>Short</AdTitle>
+      <Creatives>
+        <Creative id="c2">
+          <Linear>
+            <Duration>00:00:15</Duration>
+            <MediaFiles>
+              <MediaFile delivery="streaming" type="application/x-mpegURL" width="1280" height="720">
+                http://ad.example.com/short.m3u8
+              </MediaFile>
+            </MediaFiles>
+          </Linear>
+        </Creative>
+      </Creatives>
+    </InLine>
+  </Ad>
+</VAST>"#;
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(VAST_POD))
+            .mount(&server)
+            .await;
+
+        let provider = VastAdProvider::new(server.uri(), Client::new()).with_slate(
+            SlateProvider::new("http://slate.example.com".to_string(), 2.0),
+        );
+
+        let segments = provider
+            .get_ad_segments(27.0, "session-pod", &AdRequestContext::default())
+            .await;
+
+        // The 15s ad overflows and is dropped; the 7s remainder is padded
+        // with three whole 2s slate segments, leaving 1s of drift
+        assert_eq!(segments[0].uri, "break-0-seg-0.ts");
+        assert_eq!(segments[0].duration, 20.0);
+        assert_eq!(
+            segments[0].tracking.as_ref().unwrap().total_segments,
+            1,
+            "Dropped creatives must not count toward quartile tracking"
+        );
+        assert!(
+            segments[1..]
+                .iter()
+                .all(|s| s.uri.starts_with("slate-seg-"))
+        );
+        let total: f32 = segments.iter().map(|s| s.duration).sum();
+        assert_eq!(total, 26.0, "Slate pads with whole segments only");
+    }
+
+    #[tokio::test]
+    async fn frequency_policy_caps_and_separates_session_ads() {
+        use wiremock::matchers::method;
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        fn ad(id: &str, advertiser: &str) -> String {
+            format!(
+                r#"<Ad id="{id}"><InLine><AdSystem>T</AdSystem><AdTitle>{id}</AdTitle>
+      <Advertiser>{advertiser}</Advertiser>
+      <Creatives><Creative id="{id}-c">
+        <UniversalAdId idRegistry="ad-id.org">{id}</UniversalAdId>
+        <Linear><Duration>00:00:10</Duration><MediaFiles>
+          <MediaFile delivery="streaming" type="application/x-mpegURL" width="1280" height="720">http://ad.example.com/{id}.m3u8</MediaFile>
+        </MediaFiles></Linear>
+      </Creative></Creatives></InLine></Ad>"#
+            )
+        }
+        let vast = format!(
+            r#"<VAST version="4.1">{}{}{}</VAST>"#,
+            ad("A", "cola.example"),
+            ad("B", "cola.example"),
+            ad("C", "cars.example"),
+        );
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(vast))
+            .mount(&server)
+            .await;
+
+        let sessions = SessionManager::new_memory(Duration::from_secs(300));
+        sessions
+            .get_or_create(
+                "viewer".to_string(),
+                server.uri(),
+                AdRequestContext::default(),
+            )
+            .await;
+        let provider = VastAdProvider::new(server.uri(), Client::new()).with_frequency_policy(
+            FrequencyPolicy {
+                cap: 1,
+                separation_breaks: Some(0),
+            },
+            sessions.clone(),
+        );
+        let urls = |segments: &[AdSegment]| -> Vec<String> {
+            segments
+                .iter()
+                .filter_map(|s| s.tracking.as_ref()?.asset_uri.clone())
+                .collect()
+        };
+
+        // B competes with A in the first pod; A and C are then capped
+        let first = provider
+            .get_ad_segments(30.0, "viewer", &AdRequestContext::default())
+            .await;
+        assert_eq!(
+            urls(&first),
+            vec![
+                "http://ad.example.com/A.m3u8",
+                "http://ad.example.com/C.m3u8"
+            ]
+        );
+        let second = provider
+            .get_ad_segments(30.0, "viewer", &AdRequestContext::default())
+            .await;
+        assert_eq!(urls(&second), vec!["http://ad.example.com/B.m3u8"]);
+
+        let history = sessions.ad_history("viewer").await;
+        assert_eq!(history.breaks, 2);
+        assert_eq!(history.impressions("ad-id.org:A"), 1);
+
+        // Interstitial creatives follow the same history: every ad is capped
+        let creatives = provider
+            .get_ad_creatives(30.0, "viewer", &AdRequestContext::default())
+            .await;
+        assert!(creatives.is_empty());
+
+        // A new session sees A and C, which count towards its history
+        sessions
+            .get_or_create(
+                "other".to_string(),
+                server.uri(),
+                AdRequestContext::default(),
+            )
+            .await;
+        let creatives = provider
+            .get_ad_creatives(30.0, "other", &AdRequestContext::default())
+            .await;
+        let uris: Vec<&str> = creatives.iter().map(|c| c.uri.as_str()).collect();
+        assert_eq!(
+            uris,
+            vec![
+                "http://ad.example.com/A.m3u8",
+                "http://ad.example.com/C.m3u8"
+            ]
+        );
+        let history = sessions.ad_history("other").await;
+        assert_eq!(history.breaks, 1);
+        assert_eq!(history.impressions("ad-id.org:C"), 1);
+    }
+
+    #[tokio::test]
+    async fn get_ad_segments_for_source_plays_the_whole_pod() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        const VAST_INLINE: &str = r#"<VAST version="3.0">
+  <Ad id="ad-1"><InLine><AdSystem>T</AdSystem><AdTitle>A</AdTitle><Creatives><Creative><Linear>
+    <Duration>00:00:20</Duration>
+    <MediaFiles><MediaFile delivery="streaming" type="application/x-mpegURL">http://ad.example.com/a.m3u8</MediaFile></MediaFiles>
+  </Linear></Creative></Creatives></InLine></Ad>
+  <Ad id="ad-2"><InLine><AdSystem>T</AdSystem><AdTitle>B</AdTitle><Creatives><Creative><Linear>
+    <Duration>00:00:15</Duration>
+    <MediaFiles><MediaFile delivery="streaming" type="application/x-mpegURL">http://ad.example.com/b.m3u8</MediaFile></MediaFiles>
+  </Linear></Creative></Creatives></InLine></Ad>
+</VAST>"#;
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/pre"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(VAST_INLINE))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/empty"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("<VAST version=\"3.0\"/>"))
+            .mount(&server)
+            .await;
+
+        let provider =
+            VastAdProvider::new(format!("{}/unused", server.uri()), Client::new()).with_slate(
+                SlateProvider::new("http://slate.example.com".to_string(), 1.0),
+            );
+
+        // Inline VASTAdData: both ads play, beyond the requested duration
+        let inline = VmapAdSource::VastAdData(VAST_INLINE.to_string());
+        let segments = provider
+            .get_ad_segments_for_source(&inline, 30.0, "session-vmap", &AdRequestContext::default())
+            .await;
+        let durations: Vec<f32> = segments.iter().map(|s| s.duration).collect();
+        assert_eq!(durations, vec![20.0, 15.0]);
+
+        // AdTagURI is requested as is
+        let tag = VmapAdSource::AdTagUri(format!("{}/pre", server.uri()));
+        let segments = provider
+            .get_ad_segments_for_source(&tag, 30.0, "session-vmap", &AdRequestContext::default())
+            .await;
+        assert_eq!(segments.len(), 2);
+        assert_eq!(
+            provider.resolve_segment_url(&segments[1].uri, "session-vmap"),
+            Some("http://ad.example.com/b.m3u8".to_string())
+        );
+
+        // No fill: the break is dropped rather than slated
+        let empty = VmapAdSource::AdTagUri(format!("{}/empty", server.uri()));
+        assert!(
+            provider
+                .get_ad_segments_for_source(
+                    &empty,
+                    30.0,
+                    "session-vmap",
+                    &AdRequestContext::default()
+                )
+                .await
+                .is_empty()
+        );
+    }
+
     #[tokio::test]
     async fn get_ad_segments_multi_break_uses_unique_indices() {
         use wiremock::matchers::method;
@@ -603,11 +1461,15 @@ mod tests {
         let provider = VastAdProvider::new(server.uri(), client);
 
         // First ad break for this session
-        let segments1 = provider.get_ad_segments(30.0, "session-multi").await;
+        let segments1 = provider
+            .get_ad_segments(30.0, "session-multi", &AdRequestContext::default())
+            .await;
         assert_eq!(segments1[0].uri, "break-0-seg-0.ts");
 
         // Second ad break for same session -- should get break-1, not break-0
-        let segments2 = provider.get_ad_segments(30.0, "session-multi").await;
+        let segments2 = provider
+            .get_ad_segments(30.0, "session-multi", &AdRequestContext::default())
+            .await;
         assert_eq!(
             segments2[0].uri, "break-1-seg-0.ts",
             "Second break should use break_idx=1, not overwrite break-0"
@@ -626,7 +1488,9 @@ mod tests {
         );
 
         // Different session should start at break-0
-        let segments3 = provider.get_ad_segments(30.0, "session-other").await;
+        let segments3 = provider
+            .get_ad_segments(30.0, "session-other", &AdRequestContext::default())
+            .await;
         assert_eq!(
             segments3[0].uri, "break-0-seg-0.ts",
             "Different session should start at break-0"
@@ -676,7 +1540,9 @@ mod tests {
         let client = Client::new();
         let provider = VastAdProvider::new(server.uri(), client);
 
-        let creatives = provider.get_ad_creatives(30.0, "session-omid").await;
+        let creatives = provider
+            .get_ad_creatives(30.0, "session-omid", &AdRequestContext::default())
+            .await;
 
         assert!(!creatives.is_empty(), "Should return creatives");
         assert_eq!(
@@ -738,7 +1604,9 @@ mod tests {
         let client = Client::new();
         let provider = VastAdProvider::new(server.uri(), client);
 
-        let creatives = provider.get_ad_creatives(30.0, "session-no-omid").await;
+        let creatives = provider
+            .get_ad_creatives(30.0, "session-no-omid", &AdRequestContext::default())
+            .await;
 
         assert!(!creatives.is_empty());
         assert!(
@@ -746,4 +1614,550 @@ mod tests {
             "No verifications when VAST has no AdVerifications"
         );
     }
+
+    #[tokio::test]
+    async fn resolution_failures_report_vast_error_codes() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        let uri = server.uri();
+        let wrapper = |next: &str, error: &str| {
+            format!(
+                r#"<VAST version="4.0"><Ad id="w"><Wrapper>
+<VASTAdTagURI>{uri}{next}</VASTAdTagURI>
+<Error><![CDATA[{uri}/err/{error}?code=[ERRORCODE]]]></Error>
+</Wrapper></Ad></VAST>"#
+            )
+        };
+        let routes = [
+            ("/unsupported", wrapper("/webm-only", "unsupported")),
+            (
+                "/webm-only",
+                format!(
+                    r#"<VAST version="4.0"><Ad id="i"><InLine><AdSystem>T</AdSystem>
+<Error><![CDATA[{uri}/err/inline?code=[ERRORCODE]]]></Error>
+<Creatives><Creative><Linear><Duration>00:00:10</Duration>
+<MediaFiles><MediaFile delivery="progressive" type="video/webm">{uri}/ad.webm</MediaFile></MediaFiles>
+</Linear></Creative></Creatives></InLine></Ad></VAST>"#
+                ),
+            ),
+            ("/parse", wrapper("/broken", "parse")),
+            ("/broken", "<VAST><Ad><InLine><unclosed".to_string()),
+            ("/timeout", wrapper("/missing", "timeout")),
+            ("/server-error", wrapper("/failing", "server-error")),
+            ("/empty", wrapper("/no-ads", "empty")),
+            (
+                "/no-ads",
+                format!(
+                    r#"<VAST version="4.0"><Error>{uri}/err/no-ads?code=[ERRORCODE]</Error></VAST>"#
+                ),
+            ),
+            ("/loop", wrapper("/loop", "loop")),
+            (
+                "/top-empty",
+                format!(
+                    r#"<VAST version="4.0"><Error>{uri}/err/top-empty?code=[ERRORCODE]</Error></VAST>"#
+                ),
+            ),
+        ];
+        for (route, body) in routes {
+            Mock::given(method("GET"))
+                .and(path(route))
+                .respond_with(ResponseTemplate::new(200).set_body_string(body))
+                .mount(&server)
+                .await;
+        }
+        Mock::given(method("GET"))
+            .and(path("/missing"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/failing"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&server)
+            .await;
+
+        for route in [
+            "/unsupported",
+            "/parse",
+            "/timeout",
+            "/server-error",
+            "/empty",
+            "/loop",
+            "/top-empty",
+        ] {
+            let mut provider = VastAdProvider::new(format!("{uri}{route}"), Client::new());
+            provider.max_wrapper_depth = 1;
+            let segments = provider
+                .get_ad_segments(30.0, "session-err", &AdRequestContext::default())
+                .await;
+            assert!(segments.is_empty(), "{route}");
+        }
+
+        let expected = [
+            "/err/unsupported?code=403",
+            "/err/inline?code=403",
+            "/err/parse?code=100",
+            "/err/timeout?code=301",
+            "/err/server-error?code=301",
+            "/err/empty?code=303",
+            "/err/no-ads?code=303",
+            "/err/loop?code=302",
+        ];
+        // Error beacons are fire-and-forget; wait for all of them
+        let mut beacons = Vec::new();
+        for _ in 0..100 {
+            beacons = server
+                .received_requests()
+                .await
+                .unwrap()
+                .into_iter()
+                .filter(|r| r.url.path().starts_with("/err/"))
+                .map(|r| format!("{}?{}", r.url.path(), r.url.query().unwrap_or_default()))
+                .collect::<Vec<_>>();
+            if expected.iter().all(|e| beacons.iter().any(|b| b == e)) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        for beacon in expected {
+            assert!(
+                beacons.iter().any(|b| b == beacon),
+                "{beacon} in {beacons:?}"
+            );
+        }
+        // An empty top-level response is a no-fill, not a wrapper error
+        assert!(
+            !beacons.iter().any(|b| b.starts_with("/err/top-empty")),
+            "{beacons:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn creative_policy_rejects_ads_and_falls_back_to_next_media_file() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        let uri = server.uri();
+        let vast = format!(
+            r#"<VAST version="4.0">
+<Ad id="long"><InLine><AdSystem>T</AdSystem>
+<Error><![CDATA[{uri}/err/long?code=[ERRORCODE]]]></Error>
+<Creatives><Creative><Linear><Duration>00:01:00</Duration>
+<MediaFiles><MediaFile delivery="progressive" type="video/mp4" bitrate="2000">{uri}/long.mp4</MediaFile></MediaFiles>
+</Linear></Creative></Creatives></InLine></Ad>
+<Ad id="ok"><InLine><AdSystem>T</AdSystem>
+<Creatives><Creative><Linear><Duration>00:00:10</Duration>
+<MediaFiles>
+<MediaFile delivery="progressive" type="application/javascript" apiFramework="VPAID">{uri}/vpaid.js</MediaFile>
+<MediaFile delivery="progressive" type="video/mp4" bitrate="8000">{uri}/high.mp4</MediaFile>
+<MediaFile delivery="progressive" type="video/mp4" bitrate="2000">{uri}/low.mp4</MediaFile>
+</MediaFiles>
+</Linear></Creative></Creatives></InLine></Ad>
+</VAST>"#
+        );
+        Mock::given(method("GET"))
+            .and(path("/vast"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(vast))
+            .mount(&server)
+            .await;
+
+        let provider = VastAdProvider::new(format!("{uri}/vast"), Client::new())
+            .with_creative_policy(CreativePolicy {
+                max_bitrate: Some(4000),
+                max_duration: Some(30.0),
+                ..Default::default()
+            });
+        let segments = provider
+            .get_ad_segments(10.0, "session-policy", &AdRequestContext::default())
+            .await;
+
+        assert_eq!(segments.len(), 1);
+        assert_eq!(
+            provider.resolve_segment_url(&segments[0].uri, "session-policy"),
+            Some(format!("{uri}/low.mp4"))
+        );
+
+        // The over-long ad is reported with VAST error 202
+        let mut reported = false;
+        for _ in 0..100 {
+            reported = server
+                .received_requests()
+                .await
+                .unwrap()
+                .iter()
+                .any(|r| r.url.path() == "/err/long" && r.url.query() == Some("code=202"));
+            if reported {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(reported);
+    }
+
+    #[tokio::test]
+    async fn prober_switches_or_drops_creatives_that_do_not_match_the_content() {
+        use crate::ad::probe::CodecProfile;
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        let uri = server.uri();
+        let hls = |file: &str, bitrate: u32| {
+            format!(
+                r#"<MediaFile delivery="streaming" type="application/x-mpegURL" bitrate="{bitrate}">{uri}/{file}</MediaFile>"#
+            )
+        };
+        let ad = |id: &str, media_files: String| {
+            format!(
+                r#"<Ad id="{id}"><InLine><AdSystem>T</AdSystem><Creatives><Creative><Linear>
+<Duration>00:00:10</Duration><MediaFiles>{media_files}</MediaFiles>
+</Linear></Creative></Creatives></InLine></Ad>"#
+            )
+        };
+        let vast = format!(
+            r#"<VAST version="4.0">{}{}{}</VAST>"#,
+            ad(
+                "switched",
+                hls("a-hevc.m3u8", 8000) + &hls("a-avc.m3u8", 2000)
+            ),
+            ad("dropped", hls("b-hevc.m3u8", 8000)),
+            ad("unprobed", hls("c-missing.m3u8", 2000)),
+        );
+        let master = |codecs: &str| {
+            format!(
+                "#EXTM3U\n#EXT-X-STREAM-INF:BANDWIDTH=2000000,CODECS=\"{codecs}\"\nmedia.m3u8\n"
+            )
+        };
+        let media = "#EXTM3U\n#EXT-X-TARGETDURATION:10\n#EXTINF:10.0,\nseg.ts\n#EXT-X-ENDLIST\n";
+
+        let routes = [
+            ("/vast", vast),
+            ("/a-hevc.m3u8", master("hvc1.1.6.L120.90,mp4a.40.2")),
+            ("/a-avc.m3u8", master("avc1.64001f,mp4a.40.2")),
+            ("/b-hevc.m3u8", master("hev1.1.6.L120.90")),
+            ("/media.m3u8", media.to_string()),
+        ];
+        for (route, body) in routes {
+            Mock::given(method("GET"))
+                .and(path(route))
+                .respond_with(ResponseTemplate::new(200).set_body_string(body))
+                .mount(&server)
+                .await;
+        }
+
+        let provider = VastAdProvider::new(format!("{uri}/vast"), Client::new())
+            .with_prober(CreativeProber::new(Client::new(), CodecProfile::default()));
+        let segments = provider
+            .get_ad_segments(20.0, "session-probe", &AdRequestContext::default())
+            .await;
+
+        let urls: Vec<String> = segments
+            .iter()
+            .filter_map(|s| provider.resolve_segment_url(&s.uri, "session-probe"))
+            .collect();
+        assert_eq!(
+            urls,
+            vec![format!("{uri}/a-avc.m3u8"), format!("{uri}/c-missing.m3u8")]
+        );
+
+        // Interstitial creatives are probed the same way
+        let creatives = provider
+            .get_ad_creatives(20.0, "session-probe", &AdRequestContext::default())
+            .await;
+        let uris: Vec<&str> = creatives.iter().map(|c| c.uri.as_str()).collect();
+        assert_eq!(
+            uris,
+            vec![format!("{uri}/a-avc.m3u8"), format!("{uri}/c-missing.m3u8")]
+        );
+    }
+
+    #[tokio::test]
+    async fn progressive_creatives_play_once_normalized() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        let uri = server.uri();
+        let vast = format!(
+            r#"<VAST version="4.0">
+<Ad id="prog"><InLine><AdSystem>T</AdSystem><Creatives><Creative><Linear><Duration>00:00:10</Duration>
+<MediaFiles><MediaFile delivery="progressive" type="video/mp4">{uri}/prog.mp4</MediaFile></MediaFiles>
+</Linear></Creative></Creatives></InLine></Ad>
+<Ad id="hls"><InLine><AdSystem>T</AdSystem><Creatives><Creative><Linear><Duration>00:00:10</Duration>
+<MediaFiles><MediaFile delivery="streaming" type="application/x-mpegURL">{uri}/hls.m3u8</MediaFile></MediaFiles>
+</Linear></Creative></Creatives></InLine></Ad>
+</VAST>"#
+        );
+        Mock::given(method("GET"))
+            .and(path("/vast"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(vast))
+            .mount(&server)
+            .await;
+        // Mock transcoder: the job is still running on the first poll
+        Mock::given(method("POST"))
+            .and(path("/jobs"))
+            .respond_with(
+                ResponseTemplate::new(202)
+                    .set_body_json(serde_json::json!({"id": "job-1", "status": "queued"})),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/jobs/job-1"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({"id": "job-1", "status": "processing"})),
+            )
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/jobs/job-1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "id": "job-1",
+                "status": "ready",
+                "url": format!("{uri}/prog/index.m3u8")
+            })))
+            .mount(&server)
+            .await;
+
+        let provider = VastAdProvider::new(format!("{uri}/vast"), Client::new()).with_normalizer(
+            AdNormalizer::new(format!("{uri}/jobs"), Client::new())
+                .with_poll_interval(Duration::from_millis(5)),
+        );
+        let ctx = AdRequestContext::default();
+        let creative_urls = |segments: &[AdSegment]| -> Vec<String> {
+            let mut urls: Vec<String> = segments
+                .iter()
+                .filter_map(|s| provider.resolve_segment_url(&s.uri, "session-norm"))
+                .collect();
+            urls.dedup();
+            urls
+        };
+
+        // Until its package is ready the progressive ad is left out
+        let segments = provider.get_ad_segments(20.0, "session-norm", &ctx).await;
+        assert_eq!(creative_urls(&segments), vec![format!("{uri}/hls.m3u8")]);
+
+        let mut urls = Vec::new();
+        for _ in 0..100 {
+            let segments = provider.get_ad_segments(20.0, "session-norm", &ctx).await;
+            urls = creative_urls(&segments);
+            if urls.len() == 2 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(
+            urls,
+            vec![format!("{uri}/prog/index.m3u8"), format!("{uri}/hls.m3u8")]
+        );
+
+        // Interstitials play the normalized package too
+        let creatives = provider.get_ad_creatives(20.0, "session-norm", &ctx).await;
+        let uris: Vec<&str> = creatives.iter().map(|c| c.uri.as_str()).collect();
+        assert_eq!(
+            uris,
+            vec![format!("{uri}/prog/index.m3u8"), format!("{uri}/hls.m3u8")]
+        );
+    }
+
+    #[tokio::test]
+    async fn pods_play_in_sequence_with_buffet_fallback_and_wrapper_rules() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        let uri = server.uri();
+        let inline = |id: &str, sequence: &str| {
+            format!(
+                r#"<Ad id="{id}"{sequence}><InLine><AdSystem>T</AdSystem><Creatives><Creative><Linear>
+<Duration>00:00:05</Duration>
+<MediaFiles><MediaFile delivery="streaming" type="application/x-mpegURL">{uri}/{id}.m3u8</MediaFile></MediaFiles>
+</Linear></Creative></Creatives></InLine></Ad>"#
+            )
+        };
+        let wrapper = |id: &str, sequence: &str, attrs: &str, next: &str| {
+            format!(
+                r#"<Ad id="{id}"{sequence}><Wrapper {attrs}><VASTAdTagURI>{uri}{next}</VASTAdTagURI></Wrapper></Ad>"#
+            )
+        };
+        let vast = |version: &str, ads: Vec<String>| {
+            format!(r#"<VAST version="{version}">{}</VAST>"#, ads.concat())
+        };
+
+        let routes = [
+            // Pod out of order, with a failing pod wrapper and two buffet ads
+            (
+                "/pod",
+                vast(
+                    "4.0",
+                    vec![
+                        inline("buffet-a", ""),
+                        inline("pod-3", r#" sequence="3""#),
+                        wrapper("pod-2", r#" sequence="2""#, "", "/no-ads"),
+                        inline("pod-1", r#" sequence="1""#),
+                        inline("buffet-b", ""),
+                    ],
+                ),
+            ),
+            ("/no-ads", vast("4.0", vec![])),
+            // A failing pod wrapper that must not fall back
+            (
+                "/no-fallback",
+                vast(
+                    "4.0",
+                    vec![
+                        inline("pod-1", r#" sequence="1""#),
+                        wrapper(
+                            "pod-2",
+                            r#" sequence="2""#,
+                            r#"fallbackOnNoAd="false""#,
+                            "/no-ads",
+                        ),
+                        inline("buffet-a", ""),
+                    ],
+                ),
+            ),
+            // VAST 4 wrapper: one stand-alone ad unless allowMultipleAds
+            ("/single", vast("4.0", vec![wrapper("w", "", "", "/multi")])),
+            (
+                "/multiple",
+                vast(
+                    "4.0",
+                    vec![wrapper("w", "", r#"allowMultipleAds="true""#, "/multi")],
+                ),
+            ),
+            (
+                "/multi",
+                vast(
+                    "4.0",
+                    vec![
+                        inline("pod-1", r#" sequence="1""#),
+                        inline("buffet-a", ""),
+                        inline("buffet-b", ""),
+                    ],
+                ),
+            ),
+            // No wrappers past a wrapper with followAdditionalWrappers="false"
+            (
+                "/no-follow",
+                vast(
+                    "4.0",
+                    vec![wrapper(
+                        "w1",
+                        "",
+                        r#"followAdditionalWrappers="false" allowMultipleAds="true""#,
+                        "/nested",
+                    )],
+                ),
+            ),
+            (
+                "/nested",
+                vast(
+                    "4.0",
+                    vec![wrapper("w2", "", "", "/multi"), inline("buffet-a", "")],
+                ),
+            ),
+        ];
+        for (route, body) in routes {
+            Mock::given(method("GET"))
+                .and(path(route))
+                .respond_with(ResponseTemplate::new(200).set_body_string(body))
+                .mount(&server)
+                .await;
+        }
+
+        let cases = [
+            ("/pod", vec!["pod-1", "buffet-a", "pod-3"]),
+            ("/no-fallback", vec!["pod-1"]),
+            ("/single", vec!["buffet-a"]),
+            ("/multiple", vec!["pod-1"]),
+            ("/no-follow", vec!["buffet-a"]),
+        ];
+        for (route, expected) in cases {
+            let provider = VastAdProvider::new(format!("{uri}{route}"), Client::new());
+            let creatives = provider
+                .get_ad_creatives(60.0, "session-pod", &AdRequestContext::default())
+                .await;
+            let played: Vec<String> = creatives
+                .iter()
+                .map(|c| {
+                    c.uri
+                        .trim_start_matches(&format!("{uri}/"))
+                        .trim_end_matches(".m3u8")
+                        .to_string()
+                })
+                .collect();
+            assert_eq!(played, expected, "{route}");
+        }
+    }
+
+    #[tokio::test]
+    async fn wrappers_resolve_concurrently_within_decision_deadline() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        let uri = server.uri();
+        let inline = |id: &str| {
+            format!(
+                r#"<VAST version="3.0"><Ad id="{id}"><InLine><AdSystem>T</AdSystem><Creatives><Creative><Linear>
+<Duration>00:00:10</Duration>
+<MediaFiles><MediaFile delivery="streaming" type="application/x-mpegURL">{uri}/{id}.m3u8</MediaFile></MediaFiles>
+</Linear></Creative></Creatives></InLine></Ad></VAST>"#
+            )
+        };
+        let pod: String = (1..=3)
+            .map(|n| {
+                format!(
+                    r#"<Ad id="w{n}" sequence="{n}"><Wrapper><VASTAdTagURI>{uri}/ad-{n}</VASTAdTagURI></Wrapper></Ad>"#
+                )
+            })
+            .collect();
+        Mock::given(method("GET"))
+            .and(path("/pod"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(format!(r#"<VAST version="3.0">{pod}</VAST>"#)),
+            )
+            .mount(&server)
+            .await;
+        // Two wrappers answer in 300ms each, the third never in time
+        for (n, delay_ms) in [(1, 300), (2, 300), (3, 10_000)] {
+            Mock::given(method("GET"))
+                .and(path(format!("/ad-{n}")))
+                .respond_with(
+                    ResponseTemplate::new(200)
+                        .set_body_string(inline(&format!("ad-{n}")))
+                        .set_delay(Duration::from_millis(delay_ms)),
+                )
+                .mount(&server)
+                .await;
+        }
+
+        let provider = VastAdProvider::new(format!("{uri}/pod"), Client::new())
+            .with_decision_timeout(Duration::from_millis(500));
+        let started = Instant::now();
+        let creatives = provider
+            .get_ad_creatives(30.0, "session-deadline", &AdRequestContext::default())
+            .await;
+        let elapsed = started.elapsed();
+
+        let uris: Vec<&str> = creatives.iter().map(|c| c.uri.as_str()).collect();
+        assert_eq!(
+            uris,
+            vec![format!("{uri}/ad-1.m3u8"), format!("{uri}/ad-2.m3u8")],
+            "Sibling wrappers resolve concurrently; the late one is dropped"
+        );
+        assert!(
+            elapsed < Duration::from_millis(1500),
+            "Ad decision must respect the deadline, took {elapsed:?}"
+        );
+    }
 }
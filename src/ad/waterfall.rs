@@ -0,0 +1,701 @@
+//! Waterfall of ad servers
+//!
+//! [`WaterfallAdProvider`] fills a break from an ordered list of demand
+//! sources (`AD_SOURCES`), e.g. a direct-sold ad server first and
+//! programmatic demand as a fallback. Sources are asked in descending
+//! `weight`, equal weights in the order they are listed:
+//!
+//! - [`WaterfallMode::Sequential`] asks one source at a time for the part of
+//!   the break still unfilled, and stops once the break is full
+//! - [`WaterfallMode::Parallel`] asks every source at once and fills the
+//!   break from their answers in waterfall order
+//!
+//! Whatever stays unfilled is padded with slate. The fill result of every
+//! source is counted in `ritcher_ad_source_fills_total`.
+//!
+//! VMAP breaks, which last as long as their pod, are not split: they go to
+//! the first source that fills them. A VAST source resolves the break's
+//! own ad tag; other sources answer from their own demand.
+//!
+//! Segment names are prefixed with the index of the source that served
+//! them (`w1-break-0-seg-0.ts`), so segment requests reach that source.
+
+use crate::ad::context::AdRequestContext;
+use crate::ad::provider::{
+    AdCreative, AdProvider, AdSegment, ResolvedSegment, ad_segment_url, break_segment_url,
+};
+use crate::ad::slate::{SlateProvider, is_slate_segment};
+use crate::ad::vast::VmapAdSource;
+use crate::error::{Result, RitcherError};
+use crate::metrics;
+use async_trait::async_trait;
+use futures_util::future::join_all;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::future::Future;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// Unfilled time in seconds below which a break counts as full
+const FILL_TOLERANCE: f32 = 0.5;
+
+/// Return `true` if `name` is a segment served through [`WaterfallAdProvider`]
+pub fn is_waterfall_segment(name: &str) -> bool {
+    route(name).is_some()
+}
+
+/// Source index and source-side name of a prefixed segment name
+fn route(ad_name: &str) -> Option<(usize, &str)> {
+    let (index, name) = ad_name.strip_prefix('w')?.split_once('-')?;
+    Some((index.parse().ok()?, name))
+}
+
+/// A demand source as configured in `AD_SOURCES`
+///
+/// ```json
+/// [{"name": "direct", "endpoint": "https://direct.example.com/vast?dur=[DURATION]",
+///   "timeout_ms": 800, "weight": 10},
+///  {"name": "programmatic", "endpoint": "https://ssp.example.com/vast"}]
+/// ```
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct AdSourceConfig {
+    /// Name used in logs and metrics (letters, digits, `-` and `_`)
+    pub name: String,
+    /// VAST endpoint template; supports the VAST macros
+    pub endpoint: String,
+    /// Time budget of this source per break in milliseconds
+    /// (default: `AD_DECISION_TIMEOUT_MS`)
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+    /// Priority: sources with a higher weight are asked first (default: 1)
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+/// Parse and validate the `AD_SOURCES` JSON array
+pub fn parse_sources(json: &str) -> Result<Vec<AdSourceConfig>> {
+    let sources: Vec<AdSourceConfig> = serde_json::from_str(json)
+        .map_err(|e| RitcherError::ConfigError(format!("invalid AD_SOURCES: {e}")))?;
+
+    let mut names = HashSet::new();
+    for source in &sources {
+        let valid_name = !source.name.is_empty()
+            && source
+                .name
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+        if !valid_name {
+            return Err(RitcherError::ConfigError(format!(
+                "invalid AD_SOURCES name '{}': use letters, digits, '-' and '_'",
+                source.name
+            )));
+        }
+        if !names.insert(source.name.as_str()) {
+            return Err(RitcherError::ConfigError(format!(
+                "duplicate AD_SOURCES name '{}'",
+                source.name
+            )));
+        }
+        if source.endpoint.trim().is_empty() {
+            return Err(RitcherError::ConfigError(format!(
+                "AD_SOURCES entry '{}' has no endpoint",
+                source.name
+            )));
+        }
+    }
+    Ok(sources)
+}
+
+/// How the waterfall asks its sources
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum WaterfallMode {
+    /// One source at a time, each for the part of the break still unfilled
+    #[default]
+    Sequential,
+    /// All sources at once; the break is filled in waterfall order
+    Parallel,
+}
+
+/// A source of the waterfall
+struct WaterfallSource {
+    name: String,
+    weight: u32,
+    provider: Arc<dyn AdProvider>,
+}
+
+/// Something that takes up time in a break
+trait Timed {
+    fn seconds(&self) -> f32;
+}
+
+impl Timed for AdSegment {
+    fn seconds(&self) -> f32 {
+        self.duration
+    }
+}
+
+impl Timed for AdCreative {
+    #[allow(clippy::cast_possible_truncation)] // creative durations are small
+    fn seconds(&self) -> f32 {
+        self.duration as f32
+    }
+}
+
+/// Ad provider filling breaks from several sources in priority order
+pub struct WaterfallAdProvider {
+    sources: Vec<WaterfallSource>,
+    mode: WaterfallMode,
+    slate: Option<SlateProvider>,
+}
+
+impl WaterfallAdProvider {
+    /// Create an empty waterfall
+    pub fn new(mode: WaterfallMode) -> Self {
+        Self {
+            sources: Vec::new(),
+            mode,
+            slate: None,
+        }
+    }
+
+    /// Add a source; it is asked after the sources with a higher or equal
+    /// weight added before it
+    pub fn with_source(
+        mut self,
+        name: impl Into<String>,
+        weight: u32,
+        provider: Arc<dyn AdProvider>,
+    ) -> Self {
+        let position = self
+            .sources
+            .iter()
+            .position(|s| s.weight < weight)
+            .unwrap_or(self.sources.len());
+        self.sources.insert(
+            position,
+            WaterfallSource {
+                name: name.into(),
+                weight,
+                provider,
+            },
+        );
+        self
+    }
+
+    /// Configure a slate provider to pad breaks the sources leave unfilled
+    pub fn with_slate(mut self, slate: SlateProvider) -> Self {
+        self.slate = Some(slate);
+        self
+    }
+
+    /// Source names in the order they are asked
+    pub fn source_names(&self) -> Vec<&str> {
+        self.sources.iter().map(|s| s.name.as_str()).collect()
+    }
+
+    /// Take the leading items of a source's answer that fit in `remaining`
+    /// seconds, and record the source's fill result
+    fn fill_from<T: Timed>(&self, index: usize, answer: Vec<T>, remaining: &mut f32) -> Vec<T> {
+        let name = &self.sources[index].name;
+        if *remaining <= FILL_TOLERANCE {
+            metrics::record_ad_source_fill(name, "skipped", 0.0);
+            return Vec::new();
+        }
+
+        let mut taken = Vec::new();
+        let mut filled = 0.0;
+        for item in answer {
+            if filled + item.seconds() > *remaining + FILL_TOLERANCE {
+                break;
+            }
+            filled += item.seconds();
+            taken.push(item);
+        }
+        *remaining -= filled;
+
+        let result = if taken.is_empty() {
+            "empty"
+        } else if *remaining <= FILL_TOLERANCE {
+            "filled"
+        } else {
+            "partial"
+        };
+        info!(
+            "WaterfallAdProvider: Source {} {} with {}s",
+            name, result, filled
+        );
+        metrics::record_ad_source_fill(name, result, filled);
+        taken
+    }
+
+    /// The first non-empty answer of the sources in waterfall order, with
+    /// the index of the source that gave it
+    ///
+    /// Used for VMAP breaks, which last as long as their pod: the break is
+    /// not split between sources, it goes to the first source that fills
+    /// it. Sequential mode asks the next source only once the previous one
+    /// came back empty; parallel mode asks them all at once.
+    async fn first_answer<'a, T, F, Fut>(&'a self, ask: F) -> Option<(usize, Vec<T>)>
+    where
+        T: Timed,
+        F: Fn(&'a dyn AdProvider) -> Fut,
+        Fut: Future<Output = Vec<T>>,
+    {
+        let answers = match self.mode {
+            WaterfallMode::Sequential => {
+                let mut answers = Vec::with_capacity(self.sources.len());
+                for source in &self.sources {
+                    let answer = ask(source.provider.as_ref()).await;
+                    let filled = !answer.is_empty();
+                    answers.push(answer);
+                    if filled {
+                        break;
+                    }
+                }
+                answers
+            }
+            WaterfallMode::Parallel => {
+                join_all(self.sources.iter().map(|s| ask(s.provider.as_ref()))).await
+            }
+        };
+
+        let mut first = None;
+        for (index, answer) in answers.into_iter().enumerate() {
+            let name = &self.sources[index].name;
+            if first.is_some() {
+                metrics::record_ad_source_fill(name, "skipped", 0.0);
+            } else if answer.is_empty() {
+                info!("WaterfallAdProvider: Source {} empty for VMAP break", name);
+                metrics::record_ad_source_fill(name, "empty", 0.0);
+            } else {
+                let filled: f32 = answer.iter().map(Timed::seconds).sum();
+                info!(
+                    "WaterfallAdProvider: Source {} filled VMAP break with {}s",
+                    name, filled
+                );
+                metrics::record_ad_source_fill(name, "filled", filled);
+                first = Some((index, answer));
+            }
+        }
+        first
+    }
+
+    /// Pad the unfilled rest of a break with slate, when configured
+    fn pad(&self, segments: &mut Vec<AdSegment>, remaining: f32, session_id: &str) {
+        if remaining <= FILL_TOLERANCE {
+            return;
+        }
+        let Some(slate) = &self.slate else {
+            warn!(
+                "WaterfallAdProvider: {}s of the break unfilled for session {} and no slate configured",
+                remaining, session_id
+            );
+            return;
+        };
+        if segments.is_empty() {
+            metrics::record_slate_fallback();
+        } else {
+            metrics::record_pod_adjustment("padded");
+        }
+        segments.extend(slate.fill_duration(remaining, session_id));
+    }
+}
+
+/// Prefix a source's segment with the source index
+fn prefixed(index: usize, mut segment: AdSegment) -> AdSegment {
+    segment.uri = format!("w{}-{}", index, segment.uri);
+    segment
+}
+
+#[async_trait]
+impl AdProvider for WaterfallAdProvider {
+    async fn get_ad_segments(
+        &self,
+        duration: f32,
+        session_id: &str,
+        ctx: &AdRequestContext,
+    ) -> Vec<AdSegment> {
+        let mut remaining = duration;
+        let mut segments = Vec::new();
+
+        match self.mode {
+            WaterfallMode::Sequential => {
+                for (index, source) in self.sources.iter().enumerate() {
+                    let answer = if remaining > FILL_TOLERANCE {
+                        source
+                            .provider
+                            .get_ad_segments(remaining, session_id, ctx)
+                            .await
+                    } else {
+                        Vec::new()
+                    };
+                    let taken = self.fill_from(index, answer, &mut remaining);
+                    segments.extend(taken.into_iter().map(|s| prefixed(index, s)));
+                }
+            }
+            WaterfallMode::Parallel => {
+                let answers = join_all(
+                    self.sources
+                        .iter()
+                        .map(|s| s.provider.get_ad_segments(duration, session_id, ctx)),
+                )
+                .await;
+                for (index, answer) in answers.into_iter().enumerate() {
+                    let taken = self.fill_from(index, answer, &mut remaining);
+                    segments.extend(taken.into_iter().map(|s| prefixed(index, s)));
+                }
+            }
+        }
+
+        self.pad(&mut segments, remaining, session_id);
+        segments
+    }
+
+    async fn get_ad_segments_for_source(
+        &self,
+        source: &VmapAdSource,
+        duration: f32,
+        session_id: &str,
+        ctx: &AdRequestContext,
+    ) -> Vec<AdSegment> {
+        let answers = self
+            .first_answer(|provider| {
+                provider.get_ad_segments_for_source(source, duration, session_id, ctx)
+            })
+            .await;
+        match answers {
+            Some((index, segments)) => segments.into_iter().map(|s| prefixed(index, s)).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    async fn get_ad_creatives(
+        &self,
+        duration: f32,
+        session_id: &str,
+        ctx: &AdRequestContext,
+    ) -> Vec<AdCreative> {
+        let mut remaining = duration;
+        let mut creatives = Vec::new();
+
+        match self.mode {
+            WaterfallMode::Sequential => {
+                for (index, source) in self.sources.iter().enumerate() {
+                    let answer = if remaining > FILL_TOLERANCE {
+                        source
+                            .provider
+                            .get_ad_creatives(remaining, session_id, ctx)
+                            .await
+                    } else {
+                        Vec::new()
+                    };
+                    creatives.extend(self.fill_from(index, answer, &mut remaining));
+                }
+            }
+            WaterfallMode::Parallel => {
+                let answers = join_all(
+                    self.sources
+                        .iter()
+                        .map(|s| s.provider.get_ad_creatives(duration, session_id, ctx)),
+                )
+                .await;
+                for (index, answer) in answers.into_iter().enumerate() {
+                    creatives.extend(self.fill_from(index, answer, &mut remaining));
+                }
+            }
+        }
+
+        creatives
+    }
+
+    async fn get_ad_creatives_for_source(
+        &self,
+        source: &VmapAdSource,
+        duration: f32,
+        session_id: &str,
+        ctx: &AdRequestContext,
+    ) -> Vec<AdCreative> {
+        self.first_answer(|provider| {
+            provider.get_ad_creatives_for_source(source, duration, session_id, ctx)
+        })
+        .await
+        .map(|(_, creatives)| creatives)
+        .unwrap_or_default()
+    }
+
+    fn segment_url(
+        &self,
+        segment_name: &str,
+        session_id: &str,
+        base_url: &str,
+        break_idx: usize,
+        segment_idx: usize,
+    ) -> String {
+        if is_waterfall_segment(segment_name) || is_slate_segment(segment_name) {
+            ad_segment_url(base_url, session_id, segment_name)
+        } else {
+            break_segment_url(base_url, session_id, break_idx, segment_idx)
+        }
+    }
+
+    fn resolve_segment_url(&self, ad_name: &str, session_id: &str) -> Option<String> {
+        if is_slate_segment(ad_name) {
+            return self.slate.as_ref()?.resolve_segment_url(ad_name);
+        }
+        let (index, name) = route(ad_name)?;
+        self.sources
+            .get(index)?
+            .provider
+            .resolve_segment_url(name, session_id)
+    }
+
+    fn resolve_segment_with_tracking(
+        &self,
+        ad_name: &str,
+        session_id: &str,
+    ) -> Option<ResolvedSegment> {
+        if is_slate_segment(ad_name) {
+            return self
+                .slate
+                .as_ref()?
+                .resolve_segment_url(ad_name)
+                .map(|url| ResolvedSegment {
+                    url,
+                    tracking: None,
+                });
+        }
+        let (index, name) = route(ad_name)?;
+        self.sources
+            .get(index)?
+            .provider
+            .resolve_segment_with_tracking(name, session_id)
+    }
+
+    fn cleanup_cache(&self) {
+        for source in &self.sources {
+            source.provider.cleanup_cache();
+        }
+    }
+
+    fn slate(&self) -> Option<&SlateProvider> {
+        self.slate.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ad::provider::StaticAdProvider;
+
+    fn static_source(duration: f32) -> Arc<dyn AdProvider> {
+        Arc::new(StaticAdProvider::new(
+            "http://ads.example.com/ad".to_string(),
+            duration,
+        ))
+    }
+
+    #[test]
+    fn parse_sources_applies_defaults_and_validates() {
+        let sources = parse_sources(
+            r#"[{"name": "direct", "endpoint": "http://a/vast", "timeout_ms": 800, "weight": 10},
+                {"name": "ssp", "endpoint": "http://b/vast"}]"#,
+        )
+        .unwrap();
+        assert_eq!(sources[0].timeout_ms, Some(800));
+        assert_eq!(sources[1].timeout_ms, None);
+        assert_eq!(sources[1].weight, 1);
+
+        for invalid in [
+            "not json",
+            r#"[{"name": "a b", "endpoint": "http://a/vast"}]"#,
+            r#"[{"name": "a", "endpoint": "http://a"}, {"name": "a", "endpoint": "http://b"}]"#,
+            r#"[{"name": "a", "endpoint": " "}]"#,
+        ] {
+            assert!(parse_sources(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn sources_are_ordered_by_weight_then_insertion() {
+        let waterfall = WaterfallAdProvider::new(WaterfallMode::Sequential)
+            .with_source("b", 1, static_source(1.0))
+            .with_source("a", 10, static_source(1.0))
+            .with_source("c", 1, static_source(1.0))
+            .with_source("d", 10, static_source(1.0));
+        assert_eq!(waterfall.source_names(), vec!["a", "d", "b", "c"]);
+    }
+
+    #[test]
+    fn route_splits_source_prefix() {
+        assert!(is_waterfall_segment("w0-slate-seg-0.ts"));
+        assert!(!is_waterfall_segment("slate-seg-0.ts"));
+        assert_eq!(route("w1-break-0-seg-2.ts"), Some((1, "break-0-seg-2.ts")));
+        assert_eq!(route("break-0-seg-2.ts"), None);
+        assert_eq!(route("wx-break.ts"), None);
+    }
+
+    #[test]
+    fn source_and_slate_segments_keep_their_names() {
+        let waterfall = WaterfallAdProvider::new(WaterfallMode::Sequential);
+        let url = |name| waterfall.segment_url(name, "s1", "http://localhost", 2, 1);
+        assert_eq!(
+            url("w1-break-0-seg-2.ts"),
+            "http://localhost/stitch/s1/ad/w1-break-0-seg-2.ts"
+        );
+        assert_eq!(
+            url("slate-seg-1.ts"),
+            "http://localhost/stitch/s1/ad/slate-seg-1.ts"
+        );
+    }
+
+    #[tokio::test]
+    async fn fills_break_from_sources_in_waterfall_order() {
+        use crate::ad::VastAdProvider;
+        use reqwest::Client;
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        let uri = server.uri();
+        let vast = |ids: &[&str]| {
+            let ads: String = ids
+                .iter()
+                .map(|id| {
+                    format!(
+                        r#"<Ad id="{id}"><InLine><AdSystem>T</AdSystem><Creatives><Creative><Linear>
+<Duration>00:00:10</Duration>
+<MediaFiles><MediaFile delivery="streaming" type="application/x-mpegURL">{uri}/{id}.m3u8</MediaFile></MediaFiles>
+</Linear></Creative></Creatives></InLine></Ad>"#
+                    )
+                })
+                .collect();
+            format!(r#"<VAST version="3.0">{ads}</VAST>"#)
+        };
+        for (route, ids) in [
+            ("/direct", vec!["direct-1"]),
+            ("/ssp", vec!["ssp-1", "ssp-2"]),
+            ("/backfill", vec!["backfill-1"]),
+        ] {
+            Mock::given(method("GET"))
+                .and(path(route))
+                .respond_with(ResponseTemplate::new(200).set_body_string(vast(&ids)))
+                .mount(&server)
+                .await;
+        }
+
+        for mode in [WaterfallMode::Sequential, WaterfallMode::Parallel] {
+            let source = |route: &str| -> Arc<dyn AdProvider> {
+                Arc::new(VastAdProvider::new(format!("{uri}{route}"), Client::new()))
+            };
+            let waterfall = WaterfallAdProvider::new(mode)
+                .with_source("backfill", 1, source("/backfill"))
+                .with_source("direct", 10, source("/direct"))
+                .with_source("ssp", 5, source("/ssp"));
+
+            let segments = waterfall
+                .get_ad_segments(20.0, "session-waterfall", &AdRequestContext::default())
+                .await;
+            let names: Vec<&str> = segments.iter().map(|s| s.uri.as_str()).collect();
+            assert_eq!(
+                names,
+                vec!["w0-break-0-seg-0.ts", "w1-break-0-seg-0.ts"],
+                "{mode:?}"
+            );
+            let urls: Vec<String> = names
+                .iter()
+                .map(|name| {
+                    waterfall
+                        .resolve_segment_url(name, "session-waterfall")
+                        .unwrap()
+                })
+                .collect();
+            assert_eq!(
+                urls,
+                vec![format!("{uri}/direct-1.m3u8"), format!("{uri}/ssp-1.m3u8")],
+                "{mode:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn vmap_break_falls_through_to_the_first_source_that_fills_it() {
+        use crate::ad::VastAdProvider;
+        use reqwest::Client;
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        let uri = server.uri();
+        Mock::given(method("GET"))
+            .and(path("/no-fill"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(r#"<VAST version="3.0"/>"#))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/fill"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(format!(
+                r#"<VAST version="3.0"><Ad id="a"><InLine><AdSystem>T</AdSystem><Creatives><Creative><Linear>
+<Duration>00:00:10</Duration>
+<MediaFiles><MediaFile delivery="streaming" type="application/x-mpegURL">{uri}/a.m3u8</MediaFile></MediaFiles>
+</Linear></Creative></Creatives></InLine></Ad></VAST>"#
+            )))
+            .mount(&server)
+            .await;
+
+        for mode in [WaterfallMode::Sequential, WaterfallMode::Parallel] {
+            let waterfall = WaterfallAdProvider::new(mode)
+                .with_source(
+                    "direct",
+                    10,
+                    Arc::new(VastAdProvider::new(format!("{uri}/unused"), Client::new())),
+                )
+                .with_source("backfill", 1, static_source(10.0));
+
+            let filled = waterfall
+                .get_ad_segments_for_source(
+                    &VmapAdSource::AdTagUri(format!("{uri}/fill")),
+                    10.0,
+                    "session-vmap",
+                    &AdRequestContext::default(),
+                )
+                .await;
+            assert_eq!(filled.len(), 1, "{mode:?}");
+            assert!(filled[0].uri.starts_with("w0-"), "{mode:?}");
+
+            let fallen_through = waterfall
+                .get_ad_segments_for_source(
+                    &VmapAdSource::AdTagUri(format!("{uri}/no-fill")),
+                    10.0,
+                    "session-vmap",
+                    &AdRequestContext::default(),
+                )
+                .await;
+            assert!(!fallen_through.is_empty(), "{mode:?}");
+            assert!(
+                fallen_through.iter().all(|s| s.uri.starts_with("w1-")),
+                "{mode:?}: {fallen_through:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn pads_unfilled_break_with_slate() {
+        let waterfall = WaterfallAdProvider::new(WaterfallMode::Parallel).with_slate(
+            SlateProvider::new("http://slate.example.com".to_string(), 1.0),
+        );
+        let segments = waterfall
+            .get_ad_segments(3.0, "s", &AdRequestContext::default())
+            .await;
+        assert_eq!(segments.len(), 3);
+        assert!(segments.iter().all(|s| is_slate_segment(&s.uri)));
+        assert!(
+            waterfall
+                .resolve_segment_url(&segments[0].uri, "s")
+                .is_some()
+        );
+    }
+}
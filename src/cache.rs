@@ -5,10 +5,16 @@
 //! while eliminating thundering-herd requests to the origin CDN.
 //! The TTL is configurable via [`ManifestCache::with_ttl`] or the
 //! `MANIFEST_CACHE_TTL_MS` environment variable.
+//!
+//! [`SegmentCache`] holds ad segments pre-warmed ahead of their break
+//! (`AD_PREFETCH_WARM`), so the first viewers of a break are not served
+//! from a cold ad CDN.
 
+use axum::body::Bytes;
 use dashmap::DashMap;
 use metrics::{counter, gauge};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::{Duration, Instant};
 use tracing::debug;
 
@@ -107,6 +113,107 @@ impl Default for ManifestCache {
     }
 }
 
+/// How long pre-warmed ad segments are kept (5 minutes).
+const SEGMENT_TTL: Duration = Duration::from_secs(300);
+/// Largest ad segment kept in the segment cache (8 MiB).
+pub const MAX_SEGMENT_BYTES: usize = 8 * 1024 * 1024;
+/// Total size of the segment cache (256 MiB).
+const MAX_SEGMENT_CACHE_BYTES: usize = 256 * 1024 * 1024;
+
+/// Prometheus metric: ad segments served from the segment cache.
+const SEGMENT_CACHE_HIT: &str = "ritcher_segment_cache_hit";
+
+/// A cached ad segment with its content type.
+#[derive(Clone, Debug)]
+struct CachedSegment {
+    body: Bytes,
+    content_type: String,
+    fetched_at: Instant,
+}
+
+/// Thread-safe cache of pre-warmed ad segments, keyed by upstream URL.
+///
+/// Bounded by [`MAX_SEGMENT_BYTES`] per segment and a total byte budget;
+/// inserts over budget are refused. Expired entries are evicted by
+/// [`SegmentCache::cleanup`].
+#[derive(Clone, Debug, Default)]
+pub struct SegmentCache {
+    entries: Arc<DashMap<String, CachedSegment>>,
+    bytes: Arc<AtomicUsize>,
+}
+
+impl SegmentCache {
+    /// Create an empty segment cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Body and content type of a fresh cached segment.
+    pub fn get(&self, url: &str) -> Option<(Bytes, String)> {
+        let entry = self.entries.get(url)?;
+        if entry.fetched_at.elapsed() >= SEGMENT_TTL {
+            return None;
+        }
+        debug!("Segment cache HIT for {}", url);
+        counter!(SEGMENT_CACHE_HIT).increment(1);
+        Some((entry.body.clone(), entry.content_type.clone()))
+    }
+
+    /// Return `true` if a fresh segment is cached for `url`.
+    pub fn contains(&self, url: &str) -> bool {
+        self.entries
+            .get(url)
+            .is_some_and(|entry| entry.fetched_at.elapsed() < SEGMENT_TTL)
+    }
+
+    /// Cache a segment body.
+    ///
+    /// Returns `false` when the segment is larger than
+    /// [`MAX_SEGMENT_BYTES`] or would exceed the cache's byte budget.
+    pub fn insert(&self, url: &str, body: Bytes, content_type: String) -> bool {
+        let size = body.len();
+        if size > MAX_SEGMENT_BYTES
+            || self.bytes.load(Ordering::Relaxed) + size > MAX_SEGMENT_CACHE_BYTES
+        {
+            return false;
+        }
+        let previous = self.entries.insert(
+            url.to_string(),
+            CachedSegment {
+                body,
+                content_type,
+                fetched_at: Instant::now(),
+            },
+        );
+        self.bytes.fetch_add(size, Ordering::Relaxed);
+        if let Some(previous) = previous {
+            self.bytes.fetch_sub(previous.body.len(), Ordering::Relaxed);
+        }
+        true
+    }
+
+    /// Evict expired segments.
+    pub fn cleanup(&self) {
+        self.entries.retain(|_, entry| {
+            let keep = entry.fetched_at.elapsed() < SEGMENT_TTL;
+            if !keep {
+                self.bytes.fetch_sub(entry.body.len(), Ordering::Relaxed);
+            }
+            keep
+        });
+    }
+
+    /// Return the current number of cached segments.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Return `true` if the cache contains no segments.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -179,4 +286,30 @@ mod tests {
         cache.insert("https://b.example.com/live.m3u8", "b".to_string());
         assert_eq!(cache.len(), 2);
     }
+
+    #[test]
+    fn segment_cache_enforces_size_limit() {
+        let cache = SegmentCache::new();
+        assert!(cache.insert(
+            "https://ads.example.com/seg0.ts",
+            Bytes::from_static(b"ts"),
+            "video/MP2T".to_string()
+        ));
+        assert_eq!(
+            cache.get("https://ads.example.com/seg0.ts"),
+            Some((Bytes::from_static(b"ts"), "video/MP2T".to_string()))
+        );
+        assert!(cache.contains("https://ads.example.com/seg0.ts"));
+
+        let oversized = Bytes::from(vec![0u8; MAX_SEGMENT_BYTES + 1]);
+        assert!(!cache.insert(
+            "https://ads.example.com/big.mp4",
+            oversized,
+            "video/mp4".to_string()
+        ));
+        assert_eq!(cache.len(), 1);
+
+        cache.cleanup();
+        assert_eq!(cache.len(), 1);
+    }
 }
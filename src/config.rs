@@ -1,3 +1,9 @@
+use crate::ad::conditioning::{CreativePolicy, DeliveryPreference, parse_resolution};
+use crate::ad::probe::CodecProfile;
+use crate::ad::shared_decision::DecisionScope;
+use crate::ad::waterfall::{AdSourceConfig, WaterfallMode, parse_sources};
+use crate::dash::sgai::DashSgaiScheme;
+use crate::scte35::SegmentationTypes;
 use std::env;
 use tracing::warn;
 
@@ -27,6 +33,35 @@ pub enum AdProviderType {
     Vast,
     /// Demo ad provider with 5 visually distinct creatives per break
     Demo,
+    /// Waterfall over the VAST demand sources in `AD_SOURCES`
+    Waterfall,
+    /// OpenRTB 2.6 auction among the bidders in `OPENRTB_ENDPOINTS`
+    OpenRtb,
+    /// JSON ad-decision service at `AD_DECISION_URL`
+    JsonDecision,
+}
+
+/// Where the viewer's IP, user agent and referer are forwarded
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ViewerHeaderForwarding {
+    /// VAST requests and server-side tracking beacons (default)
+    All,
+    /// VAST requests only
+    Vast,
+    /// Never
+    Off,
+}
+
+impl ViewerHeaderForwarding {
+    /// Whether VAST requests carry the viewer headers
+    pub fn on_vast(self) -> bool {
+        self != Self::Off
+    }
+
+    /// Whether tracking beacons carry the viewer headers
+    pub fn on_beacons(self) -> bool {
+        self == Self::All
+    }
 }
 
 /// Application configuration loaded from environment variables.
@@ -45,18 +80,85 @@ pub struct Config {
     pub is_dev: bool,
     /// HLS stitching mode: ssai (default) or sgai (`STITCHING_MODE`)
     pub stitching_mode: StitchingMode,
-    /// Ad provider type selection (`AD_PROVIDER_TYPE`: auto, vast, static, demo)
+    /// DASH SGAI signalling: callback (default), replace or insert (`DASH_SGAI_SCHEME`)
+    pub dash_sgai_scheme: DashSgaiScheme,
+    /// Ad provider type selection (`AD_PROVIDER_TYPE`: auto, vast, static,
+    /// demo, waterfall, openrtb, json)
     pub ad_provider_type: AdProviderType,
-    /// Static ad source URL (`AD_SOURCE_URL`, used when ad_provider_type = Static)
+    /// Static ad source URL or comma-separated HLS creative playlist URLs
+    /// (`AD_SOURCE_URL`, used when ad_provider_type = Static)
     pub ad_source_url: String,
     /// Static ad segment duration in seconds (`AD_SEGMENT_DURATION`, default: 1.0)
     pub ad_segment_duration: f32,
     /// VAST ad server endpoint URL (`VAST_ENDPOINT`)
     pub vast_endpoint: Option<String>,
+    /// Query parameters forwarded to the ad server for targeting; a
+    /// trailing `*` matches any suffix (`AD_PARAM_ALLOWLIST`, default: `ad.*`)
+    pub ad_param_allowlist: Vec<String>,
+    /// Forward `X-Device-IP`, `X-Device-User-Agent`, `X-Forwarded-For` and
+    /// `X-Device-Referer` on ad requests (`FORWARD_VIEWER_HEADERS`: all,
+    /// vast or none, default: all)
+    pub forward_viewer_headers: ViewerHeaderForwarding,
+    /// Overall time budget in milliseconds for resolving an ad break,
+    /// wrapper chains included (`AD_DECISION_TIMEOUT_MS`, default: 3000)
+    pub ad_decision_timeout_ms: u64,
+    /// Demand sources of the ad waterfall, as a JSON array (`AD_SOURCES`)
+    pub ad_sources: Vec<AdSourceConfig>,
+    /// How the waterfall asks its sources: sequential or parallel
+    /// (`AD_WATERFALL_MODE`, default: sequential)
+    pub ad_waterfall_mode: WaterfallMode,
+    /// OpenRTB 2.6 bidder endpoints, comma-separated (`OPENRTB_ENDPOINTS`)
+    pub openrtb_endpoints: Vec<String>,
+    /// JSON ad-decision service URL (`AD_DECISION_URL`)
+    pub ad_decision_url: Option<String>,
+    /// Which sessions share the ad decision of an SSAI break: session,
+    /// channel or cohort (`AD_DECISION_SCOPE`, default: session)
+    pub ad_decision_scope: DecisionScope,
+    /// Decide breaks signalled up to this many seconds ahead in the
+    /// background, 0 = at stitch time only (`AD_PREFETCH_SECS`, default: 0)
+    pub ad_prefetch_secs: u64,
+    /// Fetch the ad segments of prefetched decisions into the segment cache
+    /// (`AD_PREFETCH_WARM`, default: false)
+    pub ad_prefetch_warm: bool,
+    /// Times a creative may play per session, 0 = uncapped
+    /// (`AD_FREQUENCY_CAP`, default: 0)
+    pub ad_frequency_cap: u32,
+    /// Keep ads of the same advertiser or category out of the same pod and
+    /// this many breaks before it; unset = no separation
+    /// (`AD_SEPARATION_BREAKS`)
+    pub ad_separation_breaks: Option<u32>,
+    /// Rules VAST creatives must meet to be stitched (`AD_ALLOWED_MIME_TYPES`,
+    /// `AD_MIN_BITRATE`/`AD_MAX_BITRATE`, `AD_MIN_RESOLUTION`/
+    /// `AD_MAX_RESOLUTION`, `AD_MIN_DURATION`/`AD_MAX_DURATION`,
+    /// `AD_DENIED_DOMAINS`, `AD_DENIED_AD_IDS`, `AD_ALLOW_VPAID`,
+    /// `AD_CREATIVE_DELIVERY`)
+    pub creative_policy: CreativePolicy,
+    /// Probe creative media against these codecs and size before stitching;
+    /// `None` = no probing (`AD_PROBE`, `AD_PROBE_CODECS`,
+    /// `AD_PROBE_MAX_RESOLUTION`)
+    pub ad_probe: Option<CodecProfile>,
+    /// Transcode service progressive creatives are submitted to; unset =
+    /// stitch them as they are (`AD_NORMALIZER_URL`)
+    pub ad_normalizer_url: Option<String>,
+    /// Time between transcode job polls in milliseconds
+    /// (`AD_NORMALIZER_POLL_MS`, default: 2000)
+    pub ad_normalizer_poll_ms: u64,
+    /// VMAP document scheduling pre/mid/post-roll breaks for VOD content
+    /// without CUE markers (`VMAP_URL`)
+    pub vmap_url: Option<String>,
+    /// JSON or CSV file of per-channel ad breaks loaded at startup
+    /// (`SCHEDULE_FILE`)
+    pub schedule_file: Option<String>,
+    /// Bearer token required by the schedule API (`SCHEDULE_API_TOKEN`);
+    /// without one the API is only open in dev mode
+    pub schedule_api_token: Option<String>,
     /// Slate URL for fallback content when no ads are available (`SLATE_URL`)
     pub slate_url: Option<String>,
     /// Slate segment duration in seconds (`SLATE_SEGMENT_DURATION`, default: 1.0)
     pub slate_segment_duration: f32,
+    /// Interval between slate playlist reloads in seconds, 0 = load once
+    /// (`SLATE_REFRESH_SECS`, default: 300)
+    pub slate_refresh_secs: u64,
     /// Session store backend (`SESSION_STORE`: memory or valkey)
     pub session_store: SessionStoreType,
     /// Valkey/Redis connection URL (`VALKEY_URL`)
@@ -71,6 +173,11 @@ pub struct Config {
     pub origin_timeout_secs: u64,
     /// Manifest cache TTL in milliseconds (`MANIFEST_CACHE_TTL_MS`, default: 2000)
     pub manifest_cache_ttl_ms: u64,
+    /// SCTE-35 segmentation type IDs that open/close an ad break
+    /// (`SCTE35_BREAK_START_TYPES`, `SCTE35_BREAK_END_TYPES`)
+    pub scte35_segmentation_types: SegmentationTypes,
+    /// Scan proxied fMP4 segments for `emsg` SCTE-35 (`INBAND_SCTE35`, default: false)
+    pub inband_scte35: bool,
 }
 
 impl Config {
@@ -118,13 +225,114 @@ impl Config {
             _ => StitchingMode::Ssai,
         };
 
+        // DASH SGAI signalling: callback (default), or alternative MPD replace/insert
+        let dash_sgai_scheme = match env::var("DASH_SGAI_SCHEME")
+            .unwrap_or_else(|_| "callback".to_string())
+            .to_lowercase()
+            .as_str()
+        {
+            "replace" => DashSgaiScheme::Replace,
+            "insert" => DashSgaiScheme::Insert,
+            "callback" => DashSgaiScheme::Callback,
+            other => {
+                warn!("Invalid DASH_SGAI_SCHEME '{}', using callback", other);
+                DashSgaiScheme::Callback
+            }
+        };
+
         // VAST endpoint URL (optional)
         let vast_endpoint = env::var("VAST_ENDPOINT").ok();
 
+        // Ad waterfall demand sources (optional JSON array)
+        let ad_sources = match env::var("AD_SOURCES") {
+            Ok(json) if !json.trim().is_empty() => parse_sources(&json)?,
+            _ => Vec::new(),
+        };
+        let ad_waterfall_mode = match env::var("AD_WATERFALL_MODE")
+            .unwrap_or_else(|_| "sequential".to_string())
+            .to_lowercase()
+            .as_str()
+        {
+            "sequential" => WaterfallMode::Sequential,
+            "parallel" => WaterfallMode::Parallel,
+            other => {
+                warn!("Invalid AD_WATERFALL_MODE '{}', using sequential", other);
+                WaterfallMode::Sequential
+            }
+        };
+
+        // OpenRTB bidder endpoints (optional, comma-separated)
+        let openrtb_endpoints: Vec<String> = env::var("OPENRTB_ENDPOINTS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|url| !url.is_empty())
+            .map(str::to_string)
+            .collect();
+
+        // JSON ad-decision service URL (optional)
+        let ad_decision_url = env::var("AD_DECISION_URL")
+            .ok()
+            .filter(|url| !url.trim().is_empty());
+
+        // Ad decisions shared per channel or targeting cohort
+        let ad_decision_scope = match env::var("AD_DECISION_SCOPE")
+            .unwrap_or_else(|_| "session".to_string())
+            .to_lowercase()
+            .as_str()
+        {
+            "session" => DecisionScope::Session,
+            "channel" => DecisionScope::Channel,
+            "cohort" => DecisionScope::Cohort,
+            other => {
+                warn!("Invalid AD_DECISION_SCOPE '{}', using session", other);
+                DecisionScope::Session
+            }
+        };
+
+        // Background decisioning of breaks signalled ahead of time
+        let ad_prefetch_secs: u64 = env::var("AD_PREFETCH_SECS")
+            .unwrap_or_else(|_| "0".to_string())
+            .parse()
+            .unwrap_or(0);
+        let ad_prefetch_warm = env::var("AD_PREFETCH_WARM")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .unwrap_or(false);
+
+        // Per-session frequency capping and competitive separation
+        let ad_frequency_cap: u32 = env::var("AD_FREQUENCY_CAP")
+            .unwrap_or_else(|_| "0".to_string())
+            .parse()
+            .unwrap_or(0);
+        let ad_separation_breaks: Option<u32> = env::var("AD_SEPARATION_BREAKS")
+            .ok()
+            .and_then(|v| v.parse().ok());
+
+        // Creative policy: which VAST creatives and media files are stitched
+        let creative_policy = creative_policy_from_env();
+
+        // Creative probing: check the media's real codecs, size and duration
+        let ad_probe = env::var("AD_PROBE")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .unwrap_or(false)
+            .then(codec_profile_from_env);
+
+        // Creative normalization: transcode service for non-HLS creatives
+        let ad_normalizer_url = env::var("AD_NORMALIZER_URL")
+            .ok()
+            .filter(|v| !v.trim().is_empty());
+        let ad_normalizer_poll_ms: u64 = env::var("AD_NORMALIZER_POLL_MS")
+            .unwrap_or_else(|_| "2000".to_string())
+            .parse()
+            .unwrap_or(2000);
+
         // Demo ad base URL (for DemoAdProvider creative sources)
         let demo_ad_base_url = env::var("DEMO_AD_BASE_URL").ok();
 
-        // Ad provider type: auto-detect from VAST_ENDPOINT or explicit AD_PROVIDER_TYPE
+        // Ad provider type: auto-detect from AD_SOURCES / OPENRTB_ENDPOINTS /
+        // AD_DECISION_URL / VAST_ENDPOINT or explicit AD_PROVIDER_TYPE
         let ad_provider_type_raw = env::var("AD_PROVIDER_TYPE")
             .unwrap_or_else(|_| "auto".to_string())
             .to_lowercase();
@@ -132,9 +340,20 @@ impl Config {
             "vast" => AdProviderType::Vast,
             "static" => AdProviderType::Static,
             "demo" => AdProviderType::Demo,
+            "waterfall" => AdProviderType::Waterfall,
+            "openrtb" => AdProviderType::OpenRtb,
+            "json" => AdProviderType::JsonDecision,
             _ => {
-                // Auto-detect: use VAST if endpoint is configured, otherwise static
-                if vast_endpoint.is_some() {
+                // Auto-detect: waterfall if sources are configured, then
+                // OpenRTB if bidders are, then a JSON decision service, then
+                // VAST if an endpoint is configured, otherwise static
+                if !ad_sources.is_empty() {
+                    AdProviderType::Waterfall
+                } else if !openrtb_endpoints.is_empty() {
+                    AdProviderType::OpenRtb
+                } else if ad_decision_url.is_some() {
+                    AdProviderType::JsonDecision
+                } else if vast_endpoint.is_some() {
                     AdProviderType::Vast
                 } else {
                     AdProviderType::Static
@@ -152,6 +371,45 @@ impl Config {
             .parse()
             .unwrap_or(1.0);
 
+        // Targeting parameters: comma-separated names or `prefix*` patterns
+        let ad_param_allowlist = env::var("AD_PARAM_ALLOWLIST")
+            .unwrap_or_else(|_| "ad.*".to_string())
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .collect();
+
+        // Viewer header forwarding on VAST requests and beacons
+        let forward_viewer_headers = match env::var("FORWARD_VIEWER_HEADERS")
+            .unwrap_or_else(|_| "all".to_string())
+            .to_lowercase()
+            .as_str()
+        {
+            "all" => ViewerHeaderForwarding::All,
+            "vast" => ViewerHeaderForwarding::Vast,
+            "none" | "off" => ViewerHeaderForwarding::Off,
+            other => {
+                warn!("Invalid FORWARD_VIEWER_HEADERS '{}', using all", other);
+                ViewerHeaderForwarding::All
+            }
+        };
+
+        // Ad decision deadline per break, shared by all wrapper hops
+        let ad_decision_timeout_ms: u64 = env::var("AD_DECISION_TIMEOUT_MS")
+            .unwrap_or_else(|_| "3000".to_string())
+            .parse()
+            .unwrap_or(3000);
+
+        // VMAP URL: optional ad schedule for VOD content without CUE markers
+        let vmap_url = env::var("VMAP_URL").ok();
+
+        // Channel break schedule: optional file and API token
+        let schedule_file = env::var("SCHEDULE_FILE").ok();
+        let schedule_api_token = env::var("SCHEDULE_API_TOKEN")
+            .ok()
+            .filter(|token| !token.is_empty());
+
         // Slate URL: optional fallback content for empty ad breaks
         let slate_url = env::var("SLATE_URL").ok();
 
@@ -161,6 +419,12 @@ impl Config {
             .parse()
             .unwrap_or(1.0);
 
+        // Slate playlist refresh interval: defaults to 5 minutes
+        let slate_refresh_secs = env::var("SLATE_REFRESH_SECS")
+            .unwrap_or_else(|_| "300".to_string())
+            .parse()
+            .unwrap_or(300);
+
         let session_ttl_secs: u64 = env::var("SESSION_TTL_SECS")
             .unwrap_or_else(|_| "300".to_string())
             .parse()
@@ -190,13 +454,45 @@ impl Config {
             .parse()
             .unwrap_or(2000);
 
+        // SCTE-35 segmentation types: comma-separated hex or decimal IDs
+        let mut scte35_segmentation_types = SegmentationTypes::default();
+        if let Ok(list) = env::var("SCTE35_BREAK_START_TYPES") {
+            match SegmentationTypes::parse_list(&list) {
+                Some(types) => scte35_segmentation_types.break_start = types,
+                None => warn!(
+                    "Invalid SCTE35_BREAK_START_TYPES '{}', using defaults",
+                    list
+                ),
+            }
+        }
+        if let Ok(list) = env::var("SCTE35_BREAK_END_TYPES") {
+            match SegmentationTypes::parse_list(&list) {
+                Some(types) => scte35_segmentation_types.break_end = types,
+                None => warn!("Invalid SCTE35_BREAK_END_TYPES '{}', using defaults", list),
+            }
+        }
+
+        let inband_scte35 = env::var("INBAND_SCTE35")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .unwrap_or(false);
+
         // Emit warnings for important silent fallbacks in production mode
         if !is_dev {
             if rate_limit_rpm == 0 {
                 warn!("Rate limiting is disabled (RATE_LIMIT_RPM is 0 or unset)");
             }
 
-            if vast_endpoint.is_none() && matches!(ad_provider_type_raw.as_str(), "auto" | "vast") {
+            if schedule_api_token.is_none() {
+                warn!("Schedule API is disabled (SCHEDULE_API_TOKEN is unset)");
+            }
+
+            if vast_endpoint.is_none()
+                && ad_sources.is_empty()
+                && openrtb_endpoints.is_empty()
+                && ad_decision_url.is_none()
+                && matches!(ad_provider_type_raw.as_str(), "auto" | "vast")
+            {
                 warn!("No VAST endpoint configured, falling back to static ads");
             }
         }
@@ -207,12 +503,33 @@ impl Config {
             origin_url,
             is_dev,
             stitching_mode,
+            dash_sgai_scheme,
             ad_provider_type,
             ad_source_url,
             ad_segment_duration,
             vast_endpoint,
+            ad_param_allowlist,
+            forward_viewer_headers,
+            ad_decision_timeout_ms,
+            ad_sources,
+            ad_waterfall_mode,
+            openrtb_endpoints,
+            ad_decision_url,
+            ad_decision_scope,
+            ad_prefetch_secs,
+            ad_prefetch_warm,
+            ad_frequency_cap,
+            ad_separation_breaks,
+            creative_policy,
+            ad_probe,
+            ad_normalizer_url,
+            ad_normalizer_poll_ms,
+            vmap_url,
+            schedule_file,
+            schedule_api_token,
             slate_url,
             slate_segment_duration,
+            slate_refresh_secs,
             session_store,
             valkey_url,
             session_ttl_secs,
@@ -220,8 +537,90 @@ impl Config {
             demo_ad_base_url,
             origin_timeout_secs,
             manifest_cache_ttl_ms,
+            scte35_segmentation_types,
+            inband_scte35,
+        })
+    }
+}
+
+/// Read the creative policy from the environment, defaults for unset or
+/// invalid values
+fn creative_policy_from_env() -> CreativePolicy {
+    let mut policy = CreativePolicy::default();
+    let list = |name: &str| -> Option<Vec<String>> {
+        env::var(name).ok().map(|v| {
+            v.split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(str::to_string)
+                .collect()
         })
+    };
+    fn number<T: std::str::FromStr>(name: &str) -> Option<T> {
+        env::var(name).ok().and_then(|v| v.trim().parse().ok())
+    }
+    let resolution = |name: &str| {
+        let value = env::var(name).ok()?;
+        let parsed = parse_resolution(&value);
+        if parsed.is_none() {
+            warn!("Invalid {} '{}', expected WIDTHxHEIGHT", name, value);
+        }
+        parsed
+    };
+
+    if let Some(types) = list("AD_ALLOWED_MIME_TYPES").filter(|t| !t.is_empty()) {
+        policy.allowed_mime_types = types;
+    }
+    policy.min_bitrate = number("AD_MIN_BITRATE");
+    policy.max_bitrate = number("AD_MAX_BITRATE");
+    policy.min_resolution = resolution("AD_MIN_RESOLUTION");
+    policy.max_resolution = resolution("AD_MAX_RESOLUTION");
+    policy.min_duration = number("AD_MIN_DURATION");
+    policy.max_duration = number("AD_MAX_DURATION");
+    policy.denied_domains = list("AD_DENIED_DOMAINS").unwrap_or_default();
+    policy.denied_ad_ids = list("AD_DENIED_AD_IDS").unwrap_or_default();
+    policy.reject_vpaid = !env::var("AD_ALLOW_VPAID")
+        .unwrap_or_else(|_| "false".to_string())
+        .parse()
+        .unwrap_or(false);
+    policy.delivery = match env::var("AD_CREATIVE_DELIVERY")
+        .unwrap_or_else(|_| "prefer-hls".to_string())
+        .to_lowercase()
+        .as_str()
+    {
+        "prefer-hls" => DeliveryPreference::PreferHls,
+        "hls-only" => DeliveryPreference::HlsOnly,
+        "prefer-progressive" => DeliveryPreference::PreferProgressive,
+        "progressive-only" => DeliveryPreference::ProgressiveOnly,
+        other => {
+            warn!("Invalid AD_CREATIVE_DELIVERY '{}', using prefer-hls", other);
+            DeliveryPreference::PreferHls
+        }
+    };
+    policy
+}
+
+/// Read the codec profile creatives are probed against
+fn codec_profile_from_env() -> CodecProfile {
+    let mut profile = CodecProfile::default();
+    if let Ok(codecs) = env::var("AD_PROBE_CODECS") {
+        profile.codecs = codecs
+            .split(',')
+            .map(str::trim)
+            .filter(|c| !c.is_empty())
+            .map(str::to_string)
+            .collect();
+    }
+    if let Ok(value) = env::var("AD_PROBE_MAX_RESOLUTION") {
+        profile.max_resolution = parse_resolution(&value);
+        if profile.max_resolution.is_none() {
+            warn!(
+                "Invalid AD_PROBE_MAX_RESOLUTION '{}', expected WIDTHxHEIGHT",
+                value
+            );
+        }
     }
+    profile
 }
 
 #[cfg(test)]
@@ -439,6 +838,393 @@ mod tests {
         );
     }
 
+    #[test]
+    fn vmap_url_is_optional() {
+        with_env(&[("DEV_MODE", "true")], &["VMAP_URL"], || {
+            assert_eq!(Config::from_env().unwrap().vmap_url, None);
+        });
+        with_env(
+            &[
+                ("DEV_MODE", "true"),
+                ("VMAP_URL", "https://ads.example.com/vmap"),
+            ],
+            &[],
+            || {
+                let config = Config::from_env().unwrap();
+                assert_eq!(
+                    config.vmap_url.as_deref(),
+                    Some("https://ads.example.com/vmap")
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn ad_param_allowlist_defaults_and_parses() {
+        with_env(&[("DEV_MODE", "true")], &["AD_PARAM_ALLOWLIST"], || {
+            let config = Config::from_env().unwrap();
+            assert_eq!(config.ad_param_allowlist, vec!["ad.*"]);
+        });
+        with_env(
+            &[
+                ("DEV_MODE", "true"),
+                ("AD_PARAM_ALLOWLIST", "ad.*, ppid,,kw"),
+            ],
+            &[],
+            || {
+                let config = Config::from_env().unwrap();
+                assert_eq!(config.ad_param_allowlist, vec!["ad.*", "ppid", "kw"]);
+            },
+        );
+        with_env(
+            &[("DEV_MODE", "true"), ("AD_PARAM_ALLOWLIST", "")],
+            &[],
+            || {
+                let config = Config::from_env().unwrap();
+                assert!(config.ad_param_allowlist.is_empty());
+            },
+        );
+    }
+
+    #[test]
+    fn forward_viewer_headers_parses() {
+        with_env(&[("DEV_MODE", "true")], &["FORWARD_VIEWER_HEADERS"], || {
+            let config = Config::from_env().unwrap();
+            assert_eq!(config.forward_viewer_headers, ViewerHeaderForwarding::All);
+        });
+        for (raw, expected) in [
+            ("vast", ViewerHeaderForwarding::Vast),
+            ("NONE", ViewerHeaderForwarding::Off),
+            ("bogus", ViewerHeaderForwarding::All),
+        ] {
+            with_env(
+                &[("DEV_MODE", "true"), ("FORWARD_VIEWER_HEADERS", raw)],
+                &[],
+                || {
+                    let config = Config::from_env().unwrap();
+                    assert_eq!(config.forward_viewer_headers, expected);
+                },
+            );
+        }
+        assert!(ViewerHeaderForwarding::Vast.on_vast());
+        assert!(!ViewerHeaderForwarding::Vast.on_beacons());
+        assert!(!ViewerHeaderForwarding::Off.on_vast());
+    }
+
+    #[test]
+    fn ad_decision_timeout_defaults_and_parses() {
+        with_env(&[("DEV_MODE", "true")], &["AD_DECISION_TIMEOUT_MS"], || {
+            let config = Config::from_env().unwrap();
+            assert_eq!(config.ad_decision_timeout_ms, 3000);
+        });
+        with_env(
+            &[("DEV_MODE", "true"), ("AD_DECISION_TIMEOUT_MS", "1500")],
+            &[],
+            || {
+                let config = Config::from_env().unwrap();
+                assert_eq!(config.ad_decision_timeout_ms, 1500);
+            },
+        );
+    }
+
+    #[test]
+    fn ad_sources_select_waterfall() {
+        with_env(
+            &[
+                ("DEV_MODE", "true"),
+                ("VAST_ENDPOINT", "https://ads.example.com/vast"),
+                (
+                    "AD_SOURCES",
+                    r#"[{"name": "direct", "endpoint": "https://direct.example.com/vast", "weight": 5}]"#,
+                ),
+                ("AD_WATERFALL_MODE", "parallel"),
+            ],
+            &["AD_PROVIDER_TYPE"],
+            || {
+                let config = Config::from_env().unwrap();
+                assert_eq!(config.ad_provider_type, AdProviderType::Waterfall);
+                assert_eq!(config.ad_sources.len(), 1);
+                assert_eq!(config.ad_sources[0].weight, 5);
+                assert_eq!(config.ad_waterfall_mode, WaterfallMode::Parallel);
+            },
+        );
+        with_env(
+            &[("DEV_MODE", "true"), ("AD_SOURCES", "[{\"name\": \"x\"}]")],
+            &[],
+            || assert!(Config::from_env().is_err()),
+        );
+    }
+
+    #[test]
+    fn openrtb_endpoints_select_openrtb() {
+        with_env(
+            &[
+                ("DEV_MODE", "true"),
+                ("VAST_ENDPOINT", "https://ads.example.com/vast"),
+                (
+                    "OPENRTB_ENDPOINTS",
+                    "https://ssp.example.com/bid, ,https://dsp.example.com/rtb",
+                ),
+            ],
+            &["AD_PROVIDER_TYPE", "AD_SOURCES"],
+            || {
+                let config = Config::from_env().unwrap();
+                assert_eq!(config.ad_provider_type, AdProviderType::OpenRtb);
+                assert_eq!(
+                    config.openrtb_endpoints,
+                    vec!["https://ssp.example.com/bid", "https://dsp.example.com/rtb"]
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn ad_decision_url_selects_json_decision() {
+        with_env(
+            &[
+                ("DEV_MODE", "true"),
+                ("VAST_ENDPOINT", "https://ads.example.com/vast"),
+                ("AD_DECISION_URL", "https://decide.example.com/v1/breaks"),
+            ],
+            &["AD_PROVIDER_TYPE", "AD_SOURCES", "OPENRTB_ENDPOINTS"],
+            || {
+                let config = Config::from_env().unwrap();
+                assert_eq!(config.ad_provider_type, AdProviderType::JsonDecision);
+                assert_eq!(
+                    config.ad_decision_url.as_deref(),
+                    Some("https://decide.example.com/v1/breaks")
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn ad_decision_scope_defaults_to_session() {
+        with_env(&[("DEV_MODE", "true")], &["AD_DECISION_SCOPE"], || {
+            let config = Config::from_env().unwrap();
+            assert_eq!(config.ad_decision_scope, DecisionScope::Session);
+        });
+        with_env(
+            &[("DEV_MODE", "true"), ("AD_DECISION_SCOPE", "Cohort")],
+            &[],
+            || {
+                let config = Config::from_env().unwrap();
+                assert_eq!(config.ad_decision_scope, DecisionScope::Cohort);
+            },
+        );
+    }
+
+    #[test]
+    fn ad_prefetch_is_opt_in() {
+        with_env(
+            &[("DEV_MODE", "true")],
+            &["AD_PREFETCH_SECS", "AD_PREFETCH_WARM"],
+            || {
+                let config = Config::from_env().unwrap();
+                assert_eq!(config.ad_prefetch_secs, 0);
+                assert!(!config.ad_prefetch_warm);
+            },
+        );
+        with_env(
+            &[
+                ("DEV_MODE", "true"),
+                ("AD_PREFETCH_SECS", "20"),
+                ("AD_PREFETCH_WARM", "true"),
+            ],
+            &[],
+            || {
+                let config = Config::from_env().unwrap();
+                assert_eq!(config.ad_prefetch_secs, 20);
+                assert!(config.ad_prefetch_warm);
+            },
+        );
+    }
+
+    #[test]
+    fn frequency_policy_is_opt_in() {
+        with_env(
+            &[("DEV_MODE", "true")],
+            &["AD_FREQUENCY_CAP", "AD_SEPARATION_BREAKS"],
+            || {
+                let config = Config::from_env().unwrap();
+                assert_eq!(config.ad_frequency_cap, 0);
+                assert_eq!(config.ad_separation_breaks, None);
+            },
+        );
+        with_env(
+            &[
+                ("DEV_MODE", "true"),
+                ("AD_FREQUENCY_CAP", "2"),
+                ("AD_SEPARATION_BREAKS", "0"),
+            ],
+            &[],
+            || {
+                let config = Config::from_env().unwrap();
+                assert_eq!(config.ad_frequency_cap, 2);
+                assert_eq!(config.ad_separation_breaks, Some(0));
+            },
+        );
+    }
+
+    #[test]
+    fn creative_policy_from_env_overrides_defaults() {
+        const VARS: [&str; 11] = [
+            "AD_ALLOWED_MIME_TYPES",
+            "AD_MIN_BITRATE",
+            "AD_MAX_BITRATE",
+            "AD_MIN_RESOLUTION",
+            "AD_MAX_RESOLUTION",
+            "AD_MIN_DURATION",
+            "AD_MAX_DURATION",
+            "AD_DENIED_DOMAINS",
+            "AD_DENIED_AD_IDS",
+            "AD_ALLOW_VPAID",
+            "AD_CREATIVE_DELIVERY",
+        ];
+        with_env(&[("DEV_MODE", "true")], &VARS, || {
+            let config = Config::from_env().unwrap();
+            assert_eq!(config.creative_policy, CreativePolicy::default());
+            assert!(config.creative_policy.reject_vpaid);
+        });
+        with_env(
+            &[
+                ("DEV_MODE", "true"),
+                ("AD_ALLOWED_MIME_TYPES", "video/mp4, application/x-mpegURL"),
+                ("AD_MAX_BITRATE", "8000"),
+                ("AD_MIN_RESOLUTION", "640x360"),
+                ("AD_MAX_RESOLUTION", "huge"),
+                ("AD_MAX_DURATION", "30"),
+                ("AD_DENIED_DOMAINS", "bad.example,worse.example"),
+                ("AD_ALLOW_VPAID", "true"),
+                ("AD_CREATIVE_DELIVERY", "hls-only"),
+            ],
+            &["AD_MIN_BITRATE", "AD_MIN_DURATION", "AD_DENIED_AD_IDS"],
+            || {
+                let policy = Config::from_env().unwrap().creative_policy;
+                assert_eq!(
+                    policy.allowed_mime_types,
+                    vec!["video/mp4", "application/x-mpegURL"]
+                );
+                assert_eq!(policy.max_bitrate, Some(8000));
+                assert_eq!(policy.min_resolution, Some((640, 360)));
+                assert_eq!(policy.max_resolution, None);
+                assert_eq!(policy.max_duration, Some(30.0));
+                assert_eq!(policy.denied_domains, vec!["bad.example", "worse.example"]);
+                assert!(!policy.reject_vpaid);
+                assert_eq!(policy.delivery, DeliveryPreference::HlsOnly);
+            },
+        );
+    }
+
+    #[test]
+    fn creative_probing_is_opt_in() {
+        with_env(
+            &[("DEV_MODE", "true")],
+            &["AD_PROBE", "AD_PROBE_CODECS", "AD_PROBE_MAX_RESOLUTION"],
+            || {
+                assert_eq!(Config::from_env().unwrap().ad_probe, None);
+            },
+        );
+        with_env(
+            &[("DEV_MODE", "true"), ("AD_PROBE", "true")],
+            &["AD_PROBE_CODECS", "AD_PROBE_MAX_RESOLUTION"],
+            || {
+                assert_eq!(
+                    Config::from_env().unwrap().ad_probe,
+                    Some(CodecProfile::default())
+                );
+            },
+        );
+        with_env(
+            &[
+                ("DEV_MODE", "true"),
+                ("AD_PROBE", "true"),
+                ("AD_PROBE_CODECS", "avc1, hvc1,mp4a,ec-3"),
+                ("AD_PROBE_MAX_RESOLUTION", "1920x1080"),
+            ],
+            &[],
+            || {
+                let profile = Config::from_env().unwrap().ad_probe.unwrap();
+                assert_eq!(profile.codecs, vec!["avc1", "hvc1", "mp4a", "ec-3"]);
+                assert_eq!(profile.max_resolution, Some((1920, 1080)));
+            },
+        );
+    }
+
+    #[test]
+    fn normalizer_is_opt_in() {
+        with_env(
+            &[("DEV_MODE", "true"), ("AD_NORMALIZER_URL", " ")],
+            &["AD_NORMALIZER_POLL_MS"],
+            || {
+                let config = Config::from_env().unwrap();
+                assert_eq!(config.ad_normalizer_url, None);
+                assert_eq!(config.ad_normalizer_poll_ms, 2000);
+            },
+        );
+        with_env(
+            &[
+                ("DEV_MODE", "true"),
+                ("AD_NORMALIZER_URL", "http://normalizer.example.com/jobs"),
+                ("AD_NORMALIZER_POLL_MS", "500"),
+            ],
+            &[],
+            || {
+                let config = Config::from_env().unwrap();
+                assert_eq!(
+                    config.ad_normalizer_url.as_deref(),
+                    Some("http://normalizer.example.com/jobs")
+                );
+                assert_eq!(config.ad_normalizer_poll_ms, 500);
+            },
+        );
+    }
+
+    #[test]
+    fn schedule_settings_are_optional() {
+        with_env(
+            &[("DEV_MODE", "true")],
+            &["SCHEDULE_FILE", "SCHEDULE_API_TOKEN"],
+            || {
+                let config = Config::from_env().unwrap();
+                assert_eq!(config.schedule_file, None);
+                assert_eq!(config.schedule_api_token, None);
+            },
+        );
+        with_env(
+            &[
+                ("DEV_MODE", "true"),
+                ("SCHEDULE_FILE", "/etc/ritcher/breaks.csv"),
+                ("SCHEDULE_API_TOKEN", ""),
+            ],
+            &[],
+            || {
+                let config = Config::from_env().unwrap();
+                assert_eq!(
+                    config.schedule_file.as_deref(),
+                    Some("/etc/ritcher/breaks.csv")
+                );
+                assert_eq!(config.schedule_api_token, None);
+            },
+        );
+    }
+
+    #[test]
+    fn slate_refresh_secs_defaults_and_parses() {
+        with_env(&[("DEV_MODE", "true")], &["SLATE_REFRESH_SECS"], || {
+            let config = Config::from_env().unwrap();
+            assert_eq!(config.slate_refresh_secs, 300);
+        });
+        with_env(
+            &[("DEV_MODE", "true"), ("SLATE_REFRESH_SECS", "60")],
+            &[],
+            || {
+                let config = Config::from_env().unwrap();
+                assert_eq!(config.slate_refresh_secs, 60);
+            },
+        );
+    }
+
     #[test]
     fn rate_limit_disabled_defaults_to_zero() {
         // When RATE_LIMIT_RPM is unset, rate_limit_rpm == 0 (disabled).
@@ -516,4 +1302,105 @@ mod tests {
             },
         );
     }
+
+    #[test]
+    fn scte35_segmentation_types_default() {
+        with_env(
+            &[("DEV_MODE", "true")],
+            &["SCTE35_BREAK_START_TYPES", "SCTE35_BREAK_END_TYPES"],
+            || {
+                let config = Config::from_env().unwrap();
+                assert_eq!(
+                    config.scte35_segmentation_types,
+                    SegmentationTypes::default()
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn scte35_segmentation_types_parsed() {
+        with_env(
+            &[
+                ("DEV_MODE", "true"),
+                ("SCTE35_BREAK_START_TYPES", "0x34,0x36"),
+                ("SCTE35_BREAK_END_TYPES", "53, 55"),
+            ],
+            &[],
+            || {
+                let config = Config::from_env().unwrap();
+                assert_eq!(
+                    config.scte35_segmentation_types.break_start,
+                    vec![0x34, 0x36]
+                );
+                assert_eq!(config.scte35_segmentation_types.break_end, vec![0x35, 0x37]);
+            },
+        );
+    }
+
+    #[test]
+    fn scte35_segmentation_types_invalid_falls_back() {
+        with_env(
+            &[
+                ("DEV_MODE", "true"),
+                ("SCTE35_BREAK_START_TYPES", "0x34,bogus"),
+            ],
+            &["SCTE35_BREAK_END_TYPES"],
+            || {
+                let config = Config::from_env().unwrap();
+                assert_eq!(
+                    config.scte35_segmentation_types,
+                    SegmentationTypes::default()
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn inband_scte35_defaults_to_disabled() {
+        with_env(&[("DEV_MODE", "true")], &["INBAND_SCTE35"], || {
+            let config = Config::from_env().unwrap();
+            assert!(!config.inband_scte35);
+        });
+    }
+
+    #[test]
+    fn inband_scte35_enabled() {
+        with_env(
+            &[("DEV_MODE", "true"), ("INBAND_SCTE35", "true")],
+            &[],
+            || {
+                let config = Config::from_env().unwrap();
+                assert!(config.inband_scte35);
+            },
+        );
+    }
+
+    #[test]
+    fn dash_sgai_scheme_defaults_to_callback() {
+        with_env(&[("DEV_MODE", "true")], &["DASH_SGAI_SCHEME"], || {
+            let config = Config::from_env().unwrap();
+            assert_eq!(config.dash_sgai_scheme, DashSgaiScheme::Callback);
+        });
+    }
+
+    #[test]
+    fn dash_sgai_scheme_alternative_mpd() {
+        with_env(
+            &[("DEV_MODE", "true"), ("DASH_SGAI_SCHEME", "Replace")],
+            &[],
+            || {
+                let config = Config::from_env().unwrap();
+                assert_eq!(config.dash_sgai_scheme, DashSgaiScheme::Replace);
+            },
+        );
+        with_env(
+            &[("DEV_MODE", "true"), ("DASH_SGAI_SCHEME", "insert")],
+            &[],
+            || {
+                let config = Config::from_env().unwrap();
+                assert_eq!(config.dash_sgai_scheme, DashSgaiScheme::Insert);
+            },
+        );
+    }
 }
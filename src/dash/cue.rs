@@ -1,4 +1,8 @@
-use dash_mpd::MPD;
+use crate::scte35::{
+    self, SegmentationDescriptor, SegmentationTypes, SpliceAction, SpliceCommandType, SpliceInfo,
+};
+use dash_mpd::scte35::SpliceInfoSection;
+use dash_mpd::{Event, EventStream, MPD, Period};
 use tracing::{debug, info, warn};
 
 /// Represents an ad break detected from DASH EventStream/SCTE-35 signaling
@@ -14,6 +18,8 @@ pub struct DashAdBreak {
     pub presentation_time: f64,
     /// The type of SCTE-35 signal detected
     pub signal_type: DashSignalType,
+    /// Segmentation UPID from the signal, kept for ad-request targeting
+    pub upid: Option<String>,
 }
 
 /// Type of SCTE-35 signal detected in EventStream
@@ -21,22 +27,41 @@ pub struct DashAdBreak {
 pub enum DashSignalType {
     /// SpliceInsert with outOfNetworkIndicator=true
     SpliceInsert,
-    /// TimeSignal with segmentation descriptor (deferred to Fas 3)
-    #[allow(dead_code)]
+    /// TimeSignal with an ad-start segmentation descriptor
     TimeSignal,
+    /// Break scheduled by VMAP rather than signalled in the MPD; holds the
+    /// break's index in the VMAP document
+    Vmap(usize),
+    /// Break from the channel schedule, matched against the MPD's
+    /// `availabilityStartTime`
+    Scheduled,
 }
 
 /// Detect ad breaks from DASH EventStream elements with SCTE-35 signaling
 ///
-/// Scans each Period's EventStreams for SCTE-35 scheme identifiers and extracts
-/// SpliceInsert signals with outOfNetworkIndicator=true.
+/// Uses the default SCTE-35 segmentation types; see
+/// [`detect_dash_ad_breaks_with_types`].
+pub fn detect_dash_ad_breaks(mpd: &MPD) -> Vec<DashAdBreak> {
+    detect_dash_ad_breaks_with_types(mpd, &SegmentationTypes::default())
+}
+
+/// Detect ad breaks from DASH EventStream elements with SCTE-35 signaling
 ///
-/// Supported schemeIdUri values:
-/// - `urn:scte:scte35:2013:xml` — SCTE-35 in clear XML format (MVP target)
-/// - `urn:scte:scte35:2014:xml+bin` — base64-encoded binary (deferred)
+/// Scans each Period's EventStreams for SCTE-35 scheme identifiers and parses
+/// the SpliceInfoSection of every Event, either as nested XML
+/// (`<scte35:SpliceInfoSection>`) or base64 `<scte35:Binary>`
+/// (`urn:scte:scte35:2014:xml+bin`):
+///
+/// - `SpliceInsert` with `outOfNetworkIndicator="true"` opens a break
+/// - `TimeSignal` opens a break when a `SegmentationDescriptor` type is in
+///   `types.break_start`
+/// - cue-ins, cancels and other segmentation types are skipped
+///
+/// Events without a parseable SpliceInfoSection are skipped: nothing says
+/// they are out-of-network splices rather than cue-ins or cancels.
 ///
 /// Returns a vector of DashAdBreak structs with period index, duration, and timing.
-pub fn detect_dash_ad_breaks(mpd: &MPD) -> Vec<DashAdBreak> {
+pub fn detect_dash_ad_breaks_with_types(mpd: &MPD, types: &SegmentationTypes) -> Vec<DashAdBreak> {
     let mut ad_breaks = Vec::new();
 
     for (period_idx, period) in mpd.periods.iter().enumerate() {
@@ -59,15 +84,9 @@ pub fn detect_dash_ad_breaks(mpd: &MPD) -> Vec<DashAdBreak> {
                 period_idx, scheme_id
             );
 
-            // Get timescale (default to 1 if not specified per DASH spec)
-            // NOTE: MVP does not implement timescale inheritance from Period/MPD level.
-            // If this becomes an issue with production MPDs, we'll need to track parent timescales.
-            let timescale = event_stream.timescale.unwrap_or(1) as f64;
-
             for event in &event_stream.event {
-                // Try to detect SpliceInsert from event content
                 if let Some(ad_break) =
-                    detect_splice_insert(event, period_idx, &period.id, timescale)
+                    detect_scte35_event(event, event_stream, period_idx, period, types)
                 {
                     info!(
                         "Detected ad break at Period #{}, presentation_time: {}s, duration: {}s",
@@ -87,31 +106,180 @@ pub(crate) fn is_scte35_scheme(scheme_id: &str) -> bool {
     scheme_id.starts_with("urn:scte:scte35:")
 }
 
-/// Detect SpliceInsert from an Event element
+/// Resolve the timescale that applies to an Event's `presentationTime` and `duration`
+///
+/// DASH defines no Period- or MPD-level timescale for events: an Event
+/// inherits `EventStream@timescale`, which defaults to 1 (ISO/IEC 23009-1
+/// §5.10.2). Some packagers also put `@timescale` on the Event itself, which
+/// takes precedence. A zero timescale is invalid and treated as absent.
+fn event_timescale(event: &Event, event_stream: &EventStream) -> u64 {
+    event
+        .timescale
+        .filter(|&t| t > 0)
+        .or(event_stream.timescale.filter(|&t| t > 0))
+        .unwrap_or(1)
+}
+
+/// Media time at the start of a Period, in seconds
 ///
-/// Looks for SCTE-35 SpliceInsert with outOfNetworkIndicator=true and extracts
-/// duration from BreakDuration.
-fn detect_splice_insert(
-    event: &dash_mpd::Event,
+/// Taken from the first SegmentTemplate `presentationTimeOffset` found at
+/// AdaptationSet or Representation level; 0 when none is set.
+pub(crate) fn period_time_offset(period: &Period) -> f64 {
+    period
+        .adaptations
+        .iter()
+        .flat_map(|a| {
+            std::iter::once(a.SegmentTemplate.as_ref())
+                .chain(a.representations.iter().map(|r| r.SegmentTemplate.as_ref()))
+        })
+        .flatten()
+        .find_map(|t| {
+            let pto = t.presentationTimeOffset?;
+            let timescale = t.timescale.filter(|&ts| ts > 0).unwrap_or(1);
+            Some(pto as f64 / timescale as f64)
+        })
+        .unwrap_or(0.0)
+}
+
+/// Convert a dash-mpd `SpliceInfoSection` into the shared SCTE-35 model
+fn splice_info_from_xml(section: &SpliceInfoSection) -> SpliceInfo {
+    let segmentation = section
+        .segmentation_descriptor
+        .iter()
+        .map(|d| SegmentationDescriptor {
+            event_id: d.segmentation_event_id,
+            cancelled: d.segmentation_event_cancel_indicator.unwrap_or(false),
+            type_id: d.segmentation_type_id,
+            duration_ticks: d.segmentation_duration,
+            upid: d
+                .segmentation_upids
+                .iter()
+                .filter_map(|u| u.content.as_deref().map(str::trim))
+                .find(|c| !c.is_empty())
+                .map(str::to_string),
+        })
+        .collect();
+
+    if let Some(insert) = &section.splice_insert {
+        return SpliceInfo {
+            command: SpliceCommandType::SpliceInsert,
+            splice_event_cancelled: insert.splice_event_cancel_indicator.unwrap_or(false),
+            out_of_network: insert.out_of_network_indicator.unwrap_or(false),
+            break_duration_ticks: insert.break_duration.as_ref().map(|b| b.duration),
+            // dash-mpd does not model the splice_insert's Program/SpliceTime
+            splice_time_ticks: None,
+            segmentation,
+        };
+    }
+
+    let command = if section.time_signal.is_some() {
+        SpliceCommandType::TimeSignal
+    } else if section.splice_null.is_some() {
+        SpliceCommandType::SpliceNull
+    } else {
+        SpliceCommandType::Other
+    };
+
+    SpliceInfo {
+        command,
+        splice_event_cancelled: false,
+        out_of_network: false,
+        break_duration_ticks: None,
+        splice_time_ticks: section
+            .time_signal
+            .as_ref()
+            .and_then(|signal| signal.splice_time.first()?.pts_time)
+            .map(|pts| scte35::adjust_pts(pts, section.pts_adjustment.unwrap_or(0))),
+        segmentation,
+    }
+}
+
+/// Find the SpliceInfoSection carried by an Event: XML directly or wrapped
+/// in a `<scte35:Signal>` element, or base64 `<scte35:Binary>` in a Signal
+fn event_splice_info(event: &Event) -> Option<SpliceInfo> {
+    if let Some(section) = event.splice_info_section.first().or_else(|| {
+        event
+            .signal
+            .iter()
+            .find_map(|s| s.splice_info_section.as_ref())
+    }) {
+        return Some(splice_info_from_xml(section));
+    }
+
+    event
+        .signal
+        .iter()
+        .filter_map(|s| s.content.as_ref())
+        .find_map(|binary| {
+            let bytes = scte35::decode_base64(&binary.content)?;
+            scte35::decode_splice_info_section(&bytes)
+        })
+}
+
+/// Detect an ad break from a single SCTE-35 Event element
+///
+/// Classifies the Event's SpliceInfoSection and, for break starts, resolves
+/// timing: `presentationTime` is offset by `EventStream@presentationTimeOffset`
+/// and scaled by the Event's timescale. An Event without `presentationTime`
+/// is placed at the SCTE-35 splice time, mapped onto the Period through its
+/// SegmentTemplate `presentationTimeOffset`, when the cue carries one. The
+/// DASH `Event@duration` is used when present; otherwise the SCTE-35
+/// BreakDuration/segmentationDuration (90 kHz ticks) is used.
+fn detect_scte35_event(
+    event: &Event,
+    event_stream: &EventStream,
     period_idx: usize,
-    period_id: &Option<String>,
-    timescale: f64,
+    period: &Period,
+    types: &SegmentationTypes,
 ) -> Option<DashAdBreak> {
-    // Calculate presentation time in seconds
-    let presentation_time = event.presentationTime.unwrap_or(0) as f64 / timescale;
+    let (signal_type, scte35_duration, upid, splice_time) = match event_splice_info(event) {
+        Some(info) => match info.action(types) {
+            SpliceAction::BreakStart { duration, upid } => {
+                let signal_type = match info.command {
+                    SpliceCommandType::TimeSignal => DashSignalType::TimeSignal,
+                    _ => DashSignalType::SpliceInsert,
+                };
+                let splice_time = info.splice_time_ticks.map(scte35::ticks_to_seconds);
+                (signal_type, duration, upid, splice_time)
+            }
+            action => {
+                debug!(
+                    "Skipping SCTE-35 Event {:?} at Period #{}: {:?}",
+                    event.id, period_idx, action
+                );
+                return None;
+            }
+        },
+        None => {
+            warn!(
+                "SCTE-35 Event {:?} at Period #{} has no parseable SpliceInfoSection, skipping",
+                event.id, period_idx
+            );
+            return None;
+        }
+    };
 
-    // Calculate duration in seconds
-    // Event.duration is in timescale units
-    let duration_seconds = if let Some(duration_ticks) = event.duration {
-        duration_ticks as f64 / timescale
-    } else {
-        // If no duration in Event, try to parse from content
-        // This is a simplified MVP - full SCTE-35 parsing would need more work
-        warn!(
-            "Event at Period #{} has no duration attribute, skipping",
-            period_idx
-        );
-        return None;
+    let timescale = event_timescale(event, event_stream) as f64;
+
+    // Event@presentationTime is relative to the Period start minus the
+    // EventStream@presentationTimeOffset (ISO/IEC 23009-1 §5.10.2.1)
+    let pto = event_stream.presentationTimeOffset.unwrap_or(0) as f64;
+    let presentation_time = match (event.presentationTime, splice_time) {
+        (Some(time), _) => (time as f64 - pto) / timescale,
+        (None, Some(splice_time)) => splice_time - period_time_offset(period),
+        (None, None) => -pto / timescale,
+    };
+
+    let duration_seconds = match (event.duration, scte35_duration) {
+        (Some(duration_ticks), _) => duration_ticks as f64 / timescale,
+        (None, Some(duration)) => duration,
+        (None, None) => {
+            warn!(
+                "Event at Period #{} has no duration attribute, skipping",
+                period_idx
+            );
+            return None;
+        }
     };
 
     // Validate duration bounds to prevent DoS via malicious MPD
@@ -132,25 +300,18 @@ fn detect_splice_insert(
         return None;
     }
 
-    // MVP approach: For SCTE-35 EventStreams, the presence of an Event with duration
-    // indicates an ad break. The dash-mpd crate doesn't parse the inner SCTE-35 XML,
-    // so we rely on schemeIdUri filtering (done by caller) and Event attributes.
-    //
-    // In production, you would parse the nested scte35:SpliceInsert XML to verify
-    // outOfNetworkIndicator=true, but for MVP this simplified approach works with
-    // test-adservers and most packaging systems that use urn:scte:scte35:2013:xml
-    // correctly (they only emit Events for actual ad breaks).
     debug!(
-        "Detected SCTE-35 Event at Period #{}: presentationTime={}s, duration={}s",
-        period_idx, presentation_time, duration_seconds
+        "Detected SCTE-35 {:?} at Period #{}: presentationTime={}s, duration={}s",
+        signal_type, period_idx, presentation_time, duration_seconds
     );
 
     Some(DashAdBreak {
         period_index: period_idx,
-        period_id: period_id.clone(),
+        period_id: period.id.clone(),
         duration: duration_seconds,
         presentation_time,
-        signal_type: DashSignalType::SpliceInsert,
+        signal_type,
+        upid,
     })
 }
 
@@ -257,7 +418,11 @@ mod tests {
 <MPD xmlns="urn:mpeg:dash:schema:mpd:2011" type="static">
   <Period id="1">
     <EventStream schemeIdUri="urn:scte:scte35:2013:xml" timescale="1">
-      <Event presentationTime="10" duration="0" id="1"/>
+      <Event presentationTime="10" duration="0" id="1">
+        <scte35:SpliceInfoSection xmlns:scte35="http://www.scte.org/schemas/35/2016">
+          <scte35:SpliceInsert spliceEventId="1" outOfNetworkIndicator="true"/>
+        </scte35:SpliceInfoSection>
+      </Event>
     </EventStream>
     <AdaptationSet>
       <Representation id="1" bandwidth="1000000">
@@ -280,7 +445,11 @@ mod tests {
 <MPD xmlns="urn:mpeg:dash:schema:mpd:2011" type="static">
   <Period id="1">
     <EventStream schemeIdUri="urn:scte:scte35:2013:xml" timescale="1">
-      <Event presentationTime="10" duration="9999999" id="1"/>
+      <Event presentationTime="10" duration="9999999" id="1">
+        <scte35:SpliceInfoSection xmlns:scte35="http://www.scte.org/schemas/35/2016">
+          <scte35:SpliceInsert spliceEventId="1" outOfNetworkIndicator="true"/>
+        </scte35:SpliceInfoSection>
+      </Event>
     </EventStream>
     <AdaptationSet>
       <Representation id="1" bandwidth="1000000">
@@ -307,7 +476,11 @@ mod tests {
 <MPD xmlns="urn:mpeg:dash:schema:mpd:2011" type="static">
   <Period id="1">
     <EventStream schemeIdUri="urn:scte:scte35:2013:xml" timescale="1">
-      <Event presentationTime="10" duration="601" id="1"/>
+      <Event presentationTime="10" duration="601" id="1">
+        <scte35:SpliceInfoSection xmlns:scte35="http://www.scte.org/schemas/35/2016">
+          <scte35:SpliceInsert spliceEventId="1" outOfNetworkIndicator="true"/>
+        </scte35:SpliceInfoSection>
+      </Event>
     </EventStream>
     <AdaptationSet>
       <Representation id="1" bandwidth="1000000">
@@ -331,7 +504,11 @@ mod tests {
 <MPD xmlns="urn:mpeg:dash:schema:mpd:2011" type="static">
   <Period id="1">
     <EventStream schemeIdUri="urn:scte:scte35:2013:xml" timescale="1">
-      <Event presentationTime="10" duration="600" id="1"/>
+      <Event presentationTime="10" duration="600" id="1">
+        <scte35:SpliceInfoSection xmlns:scte35="http://www.scte.org/schemas/35/2016">
+          <scte35:SpliceInsert spliceEventId="1" outOfNetworkIndicator="true"/>
+        </scte35:SpliceInfoSection>
+      </Event>
     </EventStream>
     <AdaptationSet>
       <Representation id="1" bandwidth="1000000">
@@ -348,4 +525,315 @@ mod tests {
         assert_eq!(ad_breaks.len(), 1);
         assert_eq!(ad_breaks[0].duration, 600.0);
     }
+
+    /// Wrap SCTE-35 Event XML in a single-Period MPD
+    fn mpd_with_events(event_stream_attrs: &str, events: &str) -> String {
+        format!(
+            r#"<?xml version="1.0"?>
+<MPD xmlns="urn:mpeg:dash:schema:mpd:2011" xmlns:scte35="http://www.scte.org/schemas/35/2016" type="static">
+  <Period id="1">
+    <EventStream schemeIdUri="urn:scte:scte35:2013:xml" {}>
+      {}
+    </EventStream>
+    <AdaptationSet>
+      <Representation id="1" bandwidth="1000000">
+        <SegmentTemplate media="$Number$.m4s"/>
+      </Representation>
+    </AdaptationSet>
+  </Period>
+</MPD>"#,
+            event_stream_attrs, events
+        )
+    }
+
+    #[test]
+    fn test_skip_cue_in_splice_insert() {
+        let xml = mpd_with_events(
+            r#"timescale="1""#,
+            r#"<Event presentationTime="80" duration="0" id="2">
+        <scte35:SpliceInfoSection>
+          <scte35:SpliceInsert spliceEventId="100" outOfNetworkIndicator="false"/>
+        </scte35:SpliceInfoSection>
+      </Event>"#,
+        );
+
+        let mpd = parse_mpd(&xml).expect("Failed to parse MPD");
+        assert!(detect_dash_ad_breaks(&mpd).is_empty());
+    }
+
+    #[test]
+    fn test_skip_cancelled_splice_insert() {
+        let xml = mpd_with_events(
+            r#"timescale="1""#,
+            r#"<Event presentationTime="10" duration="30" id="1">
+        <scte35:SpliceInfoSection>
+          <scte35:SpliceInsert spliceEventId="100" spliceEventCancelIndicator="true" outOfNetworkIndicator="true"/>
+        </scte35:SpliceInfoSection>
+      </Event>"#,
+        );
+
+        let mpd = parse_mpd(&xml).expect("Failed to parse MPD");
+        assert!(detect_dash_ad_breaks(&mpd).is_empty());
+    }
+
+    #[test]
+    fn test_splice_insert_break_duration_in_90khz_ticks() {
+        // No Event@duration: BreakDuration (90 kHz) is used instead
+        let xml = mpd_with_events(
+            r#"timescale="1""#,
+            r#"<Event presentationTime="10" id="1">
+        <scte35:SpliceInfoSection>
+          <scte35:SpliceInsert spliceEventId="100" outOfNetworkIndicator="true">
+            <scte35:BreakDuration autoReturn="true" duration="2700000"/>
+          </scte35:SpliceInsert>
+        </scte35:SpliceInfoSection>
+      </Event>"#,
+        );
+
+        let mpd = parse_mpd(&xml).expect("Failed to parse MPD");
+        let ad_breaks = detect_dash_ad_breaks(&mpd);
+
+        assert_eq!(ad_breaks.len(), 1);
+        assert_eq!(ad_breaks[0].duration, 30.0);
+        assert_eq!(ad_breaks[0].signal_type, DashSignalType::SpliceInsert);
+    }
+
+    #[test]
+    fn test_time_signal_placement_opportunity() {
+        // segmentationTypeId 0x34 (52) = Provider Placement Opportunity Start
+        let xml = mpd_with_events(
+            r#"timescale="90000""#,
+            r#"<Event presentationTime="900000" id="1">
+        <scte35:SpliceInfoSection>
+          <scte35:TimeSignal>
+            <scte35:SpliceTime ptsTime="900000"/>
+          </scte35:TimeSignal>
+          <scte35:SegmentationDescriptor segmentationEventId="7" segmentationTypeId="52" segmentationDuration="1350000"/>
+        </scte35:SpliceInfoSection>
+      </Event>"#,
+        );
+
+        let mpd = parse_mpd(&xml).expect("Failed to parse MPD");
+        let ad_breaks = detect_dash_ad_breaks(&mpd);
+
+        assert_eq!(ad_breaks.len(), 1);
+        assert_eq!(ad_breaks[0].presentation_time, 10.0);
+        assert_eq!(ad_breaks[0].duration, 15.0);
+        assert_eq!(ad_breaks[0].signal_type, DashSignalType::TimeSignal);
+    }
+
+    #[test]
+    fn test_event_without_presentation_time_placed_at_splice_time() {
+        let xml = r#"<?xml version="1.0"?>
+<MPD xmlns="urn:mpeg:dash:schema:mpd:2011" xmlns:scte35="http://www.scte.org/schemas/35/2016" type="static">
+  <Period id="1">
+    <EventStream schemeIdUri="urn:scte:scte35:2013:xml" timescale="90000">
+      <Event id="1">
+        <scte35:SpliceInfoSection ptsAdjustment="90000">
+          <scte35:TimeSignal>
+            <scte35:SpliceTime ptsTime="1800000"/>
+          </scte35:TimeSignal>
+          <scte35:SegmentationDescriptor segmentationEventId="7" segmentationTypeId="52" segmentationDuration="1350000"/>
+        </scte35:SpliceInfoSection>
+      </Event>
+    </EventStream>
+    <AdaptationSet>
+      <SegmentTemplate media="$Number$.m4s" timescale="90000" presentationTimeOffset="900000"/>
+      <Representation id="1" bandwidth="1000000"/>
+    </AdaptationSet>
+  </Period>
+</MPD>"#;
+
+        let mpd = parse_mpd(xml).expect("Failed to parse MPD");
+        let ad_breaks = detect_dash_ad_breaks(&mpd);
+
+        // Splice at 21s of media time, 11s into a Period starting at 10s
+        assert_eq!(ad_breaks.len(), 1);
+        assert_eq!(ad_breaks[0].presentation_time, 11.0);
+        assert_eq!(ad_breaks[0].duration, 15.0);
+    }
+
+    #[test]
+    fn test_time_signal_non_ad_and_end_types_skipped() {
+        // 0x10 Program Start and 0x35 Provider Placement Opportunity End
+        let xml = mpd_with_events(
+            r#"timescale="1""#,
+            r#"<Event presentationTime="0" duration="3600" id="1">
+        <scte35:SpliceInfoSection>
+          <scte35:TimeSignal><scte35:SpliceTime ptsTime="0"/></scte35:TimeSignal>
+          <scte35:SegmentationDescriptor segmentationEventId="1" segmentationTypeId="16"/>
+        </scte35:SpliceInfoSection>
+      </Event>
+      <Event presentationTime="40" duration="1" id="2">
+        <scte35:SpliceInfoSection>
+          <scte35:TimeSignal><scte35:SpliceTime ptsTime="0"/></scte35:TimeSignal>
+          <scte35:SegmentationDescriptor segmentationEventId="7" segmentationTypeId="53"/>
+        </scte35:SpliceInfoSection>
+      </Event>"#,
+        );
+
+        let mpd = parse_mpd(&xml).expect("Failed to parse MPD");
+        assert!(detect_dash_ad_breaks(&mpd).is_empty());
+    }
+
+    #[test]
+    fn test_event_timescale_overrides_event_stream() {
+        let xml = mpd_with_events(
+            r#"timescale="1""#,
+            r#"<Event presentationTime="20000" duration="30000" timescale="1000" id="1">
+              <scte35:SpliceInfoSection xmlns:scte35="http://www.scte.org/schemas/35/2016">
+                <scte35:SpliceInsert spliceEventId="1" outOfNetworkIndicator="true"/>
+              </scte35:SpliceInfoSection>
+            </Event>"#,
+        );
+
+        let mpd = parse_mpd(&xml).expect("Failed to parse MPD");
+        let ad_breaks = detect_dash_ad_breaks(&mpd);
+
+        assert_eq!(ad_breaks.len(), 1);
+        assert_eq!(ad_breaks[0].presentation_time, 20.0);
+        assert_eq!(ad_breaks[0].duration, 30.0);
+    }
+
+    #[test]
+    fn test_presentation_time_offset_applied() {
+        let xml = mpd_with_events(
+            r#"timescale="90000" presentationTimeOffset="900000""#,
+            r#"<Event presentationTime="1800000" duration="2700000" id="1">
+              <scte35:SpliceInfoSection xmlns:scte35="http://www.scte.org/schemas/35/2016">
+                <scte35:SpliceInsert spliceEventId="1" outOfNetworkIndicator="true"/>
+              </scte35:SpliceInfoSection>
+            </Event>"#,
+        );
+
+        let mpd = parse_mpd(&xml).expect("Failed to parse MPD");
+        let ad_breaks = detect_dash_ad_breaks(&mpd);
+
+        assert_eq!(ad_breaks.len(), 1);
+        assert_eq!(ad_breaks[0].presentation_time, 10.0);
+        assert_eq!(ad_breaks[0].duration, 30.0);
+    }
+
+    #[test]
+    fn test_event_before_presentation_time_offset_skipped() {
+        let xml = mpd_with_events(
+            r#"timescale="90000" presentationTimeOffset="900000""#,
+            r#"<Event presentationTime="450000" duration="2700000" id="1">
+              <scte35:SpliceInfoSection>
+                <scte35:SpliceInsert spliceEventId="1" outOfNetworkIndicator="true"/>
+              </scte35:SpliceInfoSection>
+            </Event>"#,
+        );
+
+        let mpd = parse_mpd(&xml).expect("Failed to parse MPD");
+        assert!(detect_dash_ad_breaks(&mpd).is_empty());
+    }
+
+    #[test]
+    fn test_event_without_splice_info_skipped() {
+        let xml = mpd_with_events(
+            r#"timescale="1""#,
+            r#"<Event presentationTime="10" duration="30" id="1"/>
+            <Event presentationTime="60" duration="30" id="2">
+              <scte35:Signal><scte35:Binary>bm90IHNjdGUzNQ==</scte35:Binary></scte35:Signal>
+            </Event>"#,
+        );
+
+        let mpd = parse_mpd(&xml).expect("Failed to parse MPD");
+        assert!(detect_dash_ad_breaks(&mpd).is_empty());
+    }
+
+    #[test]
+    fn test_zero_timescale_falls_back_to_one() {
+        let xml = mpd_with_events(
+            r#"timescale="0""#,
+            r#"<Event presentationTime="10" duration="30" id="1">
+              <scte35:SpliceInfoSection xmlns:scte35="http://www.scte.org/schemas/35/2016">
+                <scte35:SpliceInsert spliceEventId="1" outOfNetworkIndicator="true"/>
+              </scte35:SpliceInfoSection>
+            </Event>"#,
+        );
+
+        let mpd = parse_mpd(&xml).expect("Failed to parse MPD");
+        let ad_breaks = detect_dash_ad_breaks(&mpd);
+
+        assert_eq!(ad_breaks.len(), 1);
+        assert_eq!(ad_breaks[0].duration, 30.0);
+    }
+
+    #[test]
+    fn test_time_signal_keeps_upid() {
+        let xml = mpd_with_events(
+            r#"timescale="1""#,
+            r#"<Event presentationTime="10" id="1">
+        <scte35:SpliceInfoSection>
+          <scte35:TimeSignal><scte35:SpliceTime ptsTime="900000"/></scte35:TimeSignal>
+          <scte35:SegmentationDescriptor segmentationEventId="7" segmentationTypeId="54" segmentationDuration="2700000">
+            <scte35:SegmentationUpid segmentationUpidType="14">ADS-BREAK-42</scte35:SegmentationUpid>
+          </scte35:SegmentationDescriptor>
+        </scte35:SpliceInfoSection>
+      </Event>"#,
+        );
+
+        let mpd = parse_mpd(&xml).expect("Failed to parse MPD");
+        let ad_breaks = detect_dash_ad_breaks(&mpd);
+
+        assert_eq!(ad_breaks.len(), 1);
+        assert_eq!(ad_breaks[0].duration, 30.0);
+        assert_eq!(ad_breaks[0].upid.as_deref(), Some("ADS-BREAK-42"));
+    }
+
+    #[test]
+    fn test_time_signal_configured_types() {
+        // 0x30 Provider Advertisement Start, 0x34 Provider Placement Opportunity Start
+        let xml = mpd_with_events(
+            r#"timescale="1""#,
+            r#"<Event presentationTime="10" duration="30" id="1">
+        <scte35:SpliceInfoSection>
+          <scte35:TimeSignal><scte35:SpliceTime ptsTime="0"/></scte35:TimeSignal>
+          <scte35:SegmentationDescriptor segmentationEventId="1" segmentationTypeId="48"/>
+        </scte35:SpliceInfoSection>
+      </Event>
+      <Event presentationTime="100" duration="30" id="2">
+        <scte35:SpliceInfoSection>
+          <scte35:TimeSignal><scte35:SpliceTime ptsTime="0"/></scte35:TimeSignal>
+          <scte35:SegmentationDescriptor segmentationEventId="2" segmentationTypeId="52"/>
+        </scte35:SpliceInfoSection>
+      </Event>"#,
+        );
+        let mpd = parse_mpd(&xml).expect("Failed to parse MPD");
+
+        assert_eq!(detect_dash_ad_breaks(&mpd).len(), 2);
+
+        let types = SegmentationTypes {
+            break_start: vec![0x34],
+            break_end: vec![0x35],
+        };
+        let ad_breaks = detect_dash_ad_breaks_with_types(&mpd, &types);
+        assert_eq!(ad_breaks.len(), 1);
+        assert_eq!(ad_breaks[0].presentation_time, 100.0);
+    }
+
+    #[test]
+    fn test_time_signal_xml_bin_payload() {
+        // SCTE-35 2022 §14.1 sample: time_signal, Provider Placement
+        // Opportunity Start (0x34) with a 307s segmentation duration
+        let xml = mpd_with_events(
+            r#"timescale="1""#,
+            r#"<Event presentationTime="20" id="1">
+        <scte35:Signal>
+          <scte35:Binary>/DA0AAAAAAAA///wBQb+cr0AUAAeAhxDVUVJSAAAjn/PAAGlmbAICAAAAAAsoKGKNAIAmsnRfg==</scte35:Binary>
+        </scte35:Signal>
+      </Event>"#,
+        );
+
+        let mpd = parse_mpd(&xml).expect("Failed to parse MPD");
+        let ad_breaks = detect_dash_ad_breaks(&mpd);
+
+        assert_eq!(ad_breaks.len(), 1);
+        assert_eq!(ad_breaks[0].signal_type, DashSignalType::TimeSignal);
+        assert_eq!(ad_breaks[0].presentation_time, 20.0);
+        assert!((ad_breaks[0].duration - 307.0).abs() < 0.001);
+        assert_eq!(ad_breaks[0].upid.as_deref(), Some("0x000000002CA0A18A"));
+    }
 }
@@ -0,0 +1,513 @@
+//! In-band SCTE-35 detection from ISO-BMFF `emsg` boxes.
+//!
+//! CMAF origins may carry SCTE-35 only as `emsg` boxes with scheme
+//! `urn:scte:scte35:2013:bin` inside media segments, with no MPD EventStream.
+//! This module scans the top-level boxes of a segment prefix (`emsg` must
+//! precede the first `moof`, ISO/IEC 23009-1 §5.10.3.3), decodes the binary
+//! `splice_info_section` in `message_data`, and reports ad break starts on
+//! the media timeline.
+
+use crate::scte35::{self, SegmentationTypes, SpliceAction};
+use tracing::{debug, warn};
+
+/// `emsg` `event_duration` value meaning "unknown"
+const UNKNOWN_DURATION: u32 = 0xFFFF_FFFF;
+
+/// A decoded `emsg` box (version 0 or 1)
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmsgBox {
+    /// `scheme_id_uri`
+    pub scheme_id_uri: String,
+    /// `value`
+    pub value: String,
+    /// `timescale` for the time and duration fields
+    pub timescale: u32,
+    /// Version 1: absolute `presentation_time` on the media timeline
+    pub presentation_time: Option<u64>,
+    /// Version 0: `presentation_time_delta` from the segment's earliest presentation time
+    pub presentation_time_delta: Option<u32>,
+    /// `event_duration`
+    pub event_duration: u32,
+    /// `id`
+    pub id: u32,
+    /// `message_data`
+    pub message_data: Vec<u8>,
+}
+
+/// An SCTE-35 ad break start signalled in-band
+#[derive(Debug, Clone, PartialEq)]
+pub struct InbandBreak {
+    /// `emsg` id, used to de-duplicate repeated signals
+    pub event_id: u32,
+    /// Break start on the media timeline, in seconds
+    pub media_time: f64,
+    /// Break duration in seconds
+    pub duration: f64,
+    /// Segmentation UPID, kept for ad-request targeting
+    pub upid: Option<String>,
+}
+
+/// Result of scanning a segment prefix
+#[derive(Debug, Default, PartialEq)]
+pub struct EmsgScan {
+    /// `emsg` boxes in segment order
+    pub boxes: Vec<EmsgBox>,
+    /// `sidx` earliest presentation time in seconds, used to place version 0 boxes
+    pub earliest_presentation_time: Option<f64>,
+    /// Whether a `moof` or `mdat` was reached, i.e. no further `emsg` can follow
+    pub complete: bool,
+}
+
+/// Scan the top-level boxes of a (possibly truncated) segment prefix
+///
+/// Stops at the first `moof`/`mdat` or at a box that extends past the end
+/// of `data`.
+pub fn scan_boxes(data: &[u8]) -> EmsgScan {
+    let mut scan = EmsgScan::default();
+    let mut offset = 0;
+
+    while let Some((box_type, header_len, box_len)) = box_header(&data[offset..]) {
+        if box_type == *b"moof" || box_type == *b"mdat" {
+            scan.complete = true;
+            break;
+        }
+        let Some(body) = data.get(offset + header_len..offset + box_len) else {
+            break;
+        };
+        match &box_type {
+            b"emsg" => match parse_emsg(body) {
+                Some(emsg) => scan.boxes.push(emsg),
+                None => warn!("Malformed emsg box at offset {}", offset),
+            },
+            b"sidx" => {
+                if scan.earliest_presentation_time.is_none() {
+                    scan.earliest_presentation_time = parse_sidx_earliest(body);
+                }
+            }
+            _ => {}
+        }
+        offset += box_len;
+    }
+
+    scan
+}
+
+/// Detect SCTE-35 ad break starts in a scanned segment prefix
+///
+/// Only `urn:scte:scte35:*:bin` schemes are considered. The `emsg`
+/// `event_duration` is used when known, otherwise the SCTE-35 duration;
+/// breaks without either, or outside 0–600s, are skipped like their MPD
+/// EventStream counterparts. Version 0 boxes are placed with a `sidx`, or
+/// else at the SCTE-35 splice time.
+pub fn detect_emsg_ad_breaks(scan: &EmsgScan, types: &SegmentationTypes) -> Vec<InbandBreak> {
+    let mut breaks = Vec::new();
+
+    for emsg in &scan.boxes {
+        if !(emsg.scheme_id_uri.starts_with("urn:scte:scte35:")
+            && emsg.scheme_id_uri.ends_with(":bin"))
+        {
+            debug!("Skipping non-SCTE-35 emsg: {}", emsg.scheme_id_uri);
+            continue;
+        }
+        if emsg.timescale == 0 {
+            warn!("emsg {} has zero timescale, skipping", emsg.id);
+            continue;
+        }
+        let timescale = f64::from(emsg.timescale);
+
+        let Some(info) = scte35::decode_splice_info_section(&emsg.message_data) else {
+            warn!(
+                "emsg {} carries an undecodable splice_info_section",
+                emsg.id
+            );
+            continue;
+        };
+        let SpliceAction::BreakStart { duration, upid } = info.action(types) else {
+            continue;
+        };
+
+        let media_time = match (
+            emsg.presentation_time,
+            emsg.presentation_time_delta,
+            scan.earliest_presentation_time,
+        ) {
+            (Some(time), _, _) => time as f64 / timescale,
+            (None, Some(delta), Some(earliest)) => earliest + f64::from(delta) / timescale,
+            // Without a sidx, fall back to the cue's splice time: media
+            // timelines packaged from transport streams keep the PTS clock
+            _ => match info.splice_time_ticks {
+                Some(ticks) => scte35::ticks_to_seconds(ticks),
+                None => {
+                    debug!(
+                        "emsg v0 {} without sidx or splice time cannot be placed, skipping",
+                        emsg.id
+                    );
+                    continue;
+                }
+            },
+        };
+
+        let duration = if emsg.event_duration != UNKNOWN_DURATION && emsg.event_duration > 0 {
+            Some(f64::from(emsg.event_duration) / timescale)
+        } else {
+            duration
+        };
+        let Some(duration) = duration.filter(|d| *d > 0.0 && *d <= 600.0) else {
+            warn!("emsg {} has no valid break duration, skipping", emsg.id);
+            continue;
+        };
+
+        breaks.push(InbandBreak {
+            event_id: emsg.id,
+            media_time,
+            duration,
+            upid,
+        });
+    }
+
+    breaks
+}
+
+/// Read a box header: `(type, header length, total length)`
+///
+/// Handles 64-bit `largesize`; a size of 0 ("to end of file") is rejected
+/// because the end of a streamed segment is not known yet.
+fn box_header(data: &[u8]) -> Option<([u8; 4], usize, usize)> {
+    let size = u32::from_be_bytes(data.get(0..4)?.try_into().ok()?);
+    let box_type: [u8; 4] = data.get(4..8)?.try_into().ok()?;
+    let (header_len, box_len) = match size {
+        0 => return None,
+        1 => {
+            let large = u64::from_be_bytes(data.get(8..16)?.try_into().ok()?);
+            (16, usize::try_from(large).ok()?)
+        }
+        n => (8, usize::try_from(n).ok()?),
+    };
+    (box_len >= header_len).then_some((box_type, header_len, box_len))
+}
+
+/// Parse an `emsg` body (after the box header)
+fn parse_emsg(body: &[u8]) -> Option<EmsgBox> {
+    let mut r = ByteReader::new(body);
+    let version = r.u8()?;
+    r.skip(3); // flags
+
+    let mut emsg = match version {
+        0 => {
+            let scheme_id_uri = r.cstring()?;
+            let value = r.cstring()?;
+            EmsgBox {
+                scheme_id_uri,
+                value,
+                timescale: r.u32()?,
+                presentation_time: None,
+                presentation_time_delta: Some(r.u32()?),
+                event_duration: r.u32()?,
+                id: r.u32()?,
+                message_data: Vec::new(),
+            }
+        }
+        1 => {
+            let timescale = r.u32()?;
+            let presentation_time = r.u64()?;
+            let event_duration = r.u32()?;
+            let id = r.u32()?;
+            EmsgBox {
+                scheme_id_uri: r.cstring()?,
+                value: r.cstring()?,
+                timescale,
+                presentation_time: Some(presentation_time),
+                presentation_time_delta: None,
+                event_duration,
+                id,
+                message_data: Vec::new(),
+            }
+        }
+        _ => return None,
+    };
+    emsg.message_data = r.rest().to_vec();
+    Some(emsg)
+}
+
+/// Parse the `earliest_presentation_time` of a `sidx` body, in seconds
+fn parse_sidx_earliest(body: &[u8]) -> Option<f64> {
+    let mut r = ByteReader::new(body);
+    let version = r.u8()?;
+    r.skip(3); // flags
+    r.skip(4); // reference_ID
+    let timescale = r.u32()?;
+    if timescale == 0 {
+        return None;
+    }
+    let earliest = if version == 0 {
+        u64::from(r.u32()?)
+    } else {
+        r.u64()?
+    };
+    Some(earliest as f64 / f64::from(timescale))
+}
+
+/// Big-endian byte reader over a box body
+struct ByteReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.pos..self.pos + len)?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    fn skip(&mut self, len: usize) {
+        self.pos += len;
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_be_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_be_bytes(self.take(8)?.try_into().ok()?))
+    }
+
+    /// Null-terminated UTF-8 string
+    fn cstring(&mut self) -> Option<String> {
+        let rest = self.data.get(self.pos..)?;
+        let len = rest.iter().position(|&b| b == 0)?;
+        let s = String::from_utf8_lossy(&rest[..len]).into_owned();
+        self.pos += len + 1;
+        Some(s)
+    }
+
+    fn rest(&self) -> &'a [u8] {
+        self.data.get(self.pos..).unwrap_or(&[])
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// SCTE-35 2022 §14.1: time_signal, Provider Placement Opportunity Start (0x34, 307s)
+    const TIME_SIGNAL_PO_START: &str =
+        "/DA0AAAAAAAA///wBQb+cr0AUAAeAhxDVUVJSAAAjn/PAAGlmbAICAAAAAAsoKGKNAIAmsnRfg==";
+
+    fn full_box(box_type: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut out = (u32::try_from(body.len() + 8).unwrap())
+            .to_be_bytes()
+            .to_vec();
+        out.extend_from_slice(box_type);
+        out.extend_from_slice(body);
+        out
+    }
+
+    /// Build a version 1 `emsg` box
+    pub(crate) fn emsg_v1(
+        scheme: &str,
+        timescale: u32,
+        presentation_time: u64,
+        duration: u32,
+        id: u32,
+        message: &[u8],
+    ) -> Vec<u8> {
+        let mut body = vec![1, 0, 0, 0];
+        body.extend_from_slice(&timescale.to_be_bytes());
+        body.extend_from_slice(&presentation_time.to_be_bytes());
+        body.extend_from_slice(&duration.to_be_bytes());
+        body.extend_from_slice(&id.to_be_bytes());
+        body.extend_from_slice(scheme.as_bytes());
+        body.push(0);
+        body.push(0); // empty value
+        body.extend_from_slice(message);
+        full_box(b"emsg", &body)
+    }
+
+    fn emsg_v0(scheme: &str, timescale: u32, delta: u32, duration: u32, message: &[u8]) -> Vec<u8> {
+        let mut body = vec![0, 0, 0, 0];
+        body.extend_from_slice(scheme.as_bytes());
+        body.push(0);
+        body.push(0);
+        body.extend_from_slice(&timescale.to_be_bytes());
+        body.extend_from_slice(&delta.to_be_bytes());
+        body.extend_from_slice(&duration.to_be_bytes());
+        body.extend_from_slice(&7u32.to_be_bytes());
+        body.extend_from_slice(message);
+        full_box(b"emsg", &body)
+    }
+
+    fn sidx_v0(timescale: u32, earliest: u32) -> Vec<u8> {
+        let mut body = vec![0, 0, 0, 0];
+        body.extend_from_slice(&1u32.to_be_bytes());
+        body.extend_from_slice(&timescale.to_be_bytes());
+        body.extend_from_slice(&earliest.to_be_bytes());
+        body.extend_from_slice(&[0; 8]); // first_offset, reserved, reference_count
+        full_box(b"sidx", &body)
+    }
+
+    pub(crate) fn po_start() -> Vec<u8> {
+        scte35::decode_base64(TIME_SIGNAL_PO_START).unwrap()
+    }
+
+    fn segment(boxes: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = full_box(b"styp", b"msdhcmfs");
+        for b in boxes {
+            data.extend_from_slice(b);
+        }
+        data.extend_from_slice(&full_box(b"moof", &[0; 16]));
+        data.extend_from_slice(&full_box(b"mdat", &[0; 32]));
+        data
+    }
+
+    #[test]
+    fn scan_finds_emsg_before_moof() {
+        let data = segment(&[emsg_v1(
+            "urn:scte:scte35:2013:bin",
+            90_000,
+            900_000,
+            2_700_000,
+            42,
+            &po_start(),
+        )]);
+
+        let scan = scan_boxes(&data);
+
+        assert!(scan.complete);
+        assert_eq!(scan.boxes.len(), 1);
+        assert_eq!(scan.boxes[0].scheme_id_uri, "urn:scte:scte35:2013:bin");
+        assert_eq!(scan.boxes[0].presentation_time, Some(900_000));
+        assert_eq!(scan.boxes[0].id, 42);
+    }
+
+    #[test]
+    fn detect_v1_break_uses_event_duration() {
+        let data = segment(&[emsg_v1(
+            "urn:scte:scte35:2013:bin",
+            90_000,
+            900_000,
+            2_700_000,
+            42,
+            &po_start(),
+        )]);
+
+        let breaks = detect_emsg_ad_breaks(&scan_boxes(&data), &SegmentationTypes::default());
+
+        assert_eq!(
+            breaks,
+            vec![InbandBreak {
+                event_id: 42,
+                media_time: 10.0,
+                duration: 30.0,
+                upid: Some("0x000000002CA0A18A".to_string()),
+            }]
+        );
+    }
+
+    #[test]
+    fn detect_v1_unknown_duration_falls_back_to_scte35() {
+        let data = segment(&[emsg_v1(
+            "urn:scte:scte35:2013:bin",
+            1000,
+            5000,
+            UNKNOWN_DURATION,
+            1,
+            &po_start(),
+        )]);
+
+        let breaks = detect_emsg_ad_breaks(&scan_boxes(&data), &SegmentationTypes::default());
+
+        assert_eq!(breaks.len(), 1);
+        assert_eq!(breaks[0].media_time, 5.0);
+        assert!((breaks[0].duration - 307.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn detect_v0_placed_with_sidx() {
+        let data = segment(&[
+            sidx_v0(1000, 100_000),
+            emsg_v0("urn:scte:scte35:2013:bin", 1000, 2000, 30_000, &po_start()),
+        ]);
+
+        let breaks = detect_emsg_ad_breaks(&scan_boxes(&data), &SegmentationTypes::default());
+
+        assert_eq!(breaks.len(), 1);
+        assert_eq!(breaks[0].media_time, 102.0);
+        assert_eq!(breaks[0].duration, 30.0);
+    }
+
+    #[test]
+    fn detect_v0_without_sidx_placed_at_splice_time() {
+        let data = segment(&[emsg_v0(
+            "urn:scte:scte35:2013:bin",
+            1000,
+            2000,
+            30_000,
+            &po_start(),
+        )]);
+
+        let breaks = detect_emsg_ad_breaks(&scan_boxes(&data), &SegmentationTypes::default());
+        assert_eq!(breaks.len(), 1);
+        // pts_time 0x072BD0050 of the SCTE-35 sample
+        assert!((breaks[0].media_time - 21_388.766_756).abs() < 0.001);
+    }
+
+    #[test]
+    fn detect_skips_other_schemes_and_types() {
+        let data = segment(&[emsg_v1(
+            "urn:mpeg:dash:event:2012",
+            1000,
+            0,
+            1000,
+            1,
+            &po_start(),
+        )]);
+        let breaks = detect_emsg_ad_breaks(&scan_boxes(&data), &SegmentationTypes::default());
+        assert!(breaks.is_empty());
+
+        let data = segment(&[emsg_v1(
+            "urn:scte:scte35:2013:bin",
+            1000,
+            0,
+            1000,
+            1,
+            &po_start(),
+        )]);
+        let types = SegmentationTypes {
+            break_start: vec![0x30],
+            break_end: vec![0x31],
+        };
+        assert!(detect_emsg_ad_breaks(&scan_boxes(&data), &types).is_empty());
+    }
+
+    #[test]
+    fn scan_truncated_prefix_is_incomplete() {
+        let emsg = emsg_v1("urn:scte:scte35:2013:bin", 1000, 0, 1000, 1, &po_start());
+        let data = segment(std::slice::from_ref(&emsg));
+        let cut = data.len() - emsg.len() / 2 - 64;
+
+        let scan = scan_boxes(&data[..cut]);
+
+        assert!(!scan.complete);
+        assert!(scan.boxes.is_empty());
+    }
+
+    #[test]
+    fn scan_rejects_garbage() {
+        let scan = scan_boxes(&[0x47, 0x40, 0x00, 0x10, 0x00]);
+        assert!(!scan.complete);
+        assert!(scan.boxes.is_empty());
+
+        // Declared size smaller than the header
+        let scan = scan_boxes(&[0, 0, 0, 4, b'e', b'm', b's', b'g']);
+        assert!(scan.boxes.is_empty());
+    }
+}
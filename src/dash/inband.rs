@@ -0,0 +1,257 @@
+//! Per-session store of ad breaks found in-band by the segment proxy.
+//!
+//! When `INBAND_SCTE35=true`, [`serve_segment`](crate::server::handlers::segment::serve_segment)
+//! scans fMP4 segments for `emsg` SCTE-35 and records the breaks here. The
+//! next MPD generation for the same session merges them with the breaks
+//! signalled in the MPD itself, placing each one in the Period whose
+//! timeline contains it.
+
+use crate::dash::cue::{DashAdBreak, DashSignalType, period_time_offset};
+use crate::dash::emsg::InbandBreak;
+use dash_mpd::MPD;
+use dashmap::DashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
+
+/// How long a break is kept after its scheduled end
+const RETENTION: Duration = Duration::from_secs(120);
+
+/// Breaks closer than this to an MPD-signalled break in the same Period are
+/// treated as the same signal
+const DUPLICATE_TOLERANCE_SECS: f64 = 0.5;
+
+#[derive(Clone, Debug)]
+struct RecordedBreak {
+    ad_break: InbandBreak,
+    recorded_at: Instant,
+}
+
+impl RecordedBreak {
+    fn is_expired(&self) -> bool {
+        self.recorded_at.elapsed() > Duration::from_secs_f64(self.ad_break.duration) + RETENTION
+    }
+}
+
+/// Thread-safe store of in-band breaks keyed by session ID
+#[derive(Clone, Debug, Default)]
+pub struct InbandBreakStore {
+    sessions: Arc<DashMap<String, Vec<RecordedBreak>>>,
+}
+
+impl InbandBreakStore {
+    /// Create an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record breaks seen in a segment, ignoring `emsg` ids already stored
+    ///
+    /// Packagers repeat the same `emsg` in every segment until the splice
+    /// point, so de-duplication by id keeps one entry per break. Returns the
+    /// number of newly recorded breaks.
+    pub fn record(&self, session_id: &str, breaks: Vec<InbandBreak>) -> usize {
+        if breaks.is_empty() {
+            return 0;
+        }
+        let mut recorded = 0;
+        let mut entry = self.sessions.entry(session_id.to_string()).or_default();
+        for ad_break in breaks {
+            if entry
+                .iter()
+                .any(|r| r.ad_break.event_id == ad_break.event_id)
+            {
+                continue;
+            }
+            info!(
+                "Recorded in-band SCTE-35 break {} for session {}: media time {}s, duration {}s",
+                ad_break.event_id, session_id, ad_break.media_time, ad_break.duration
+            );
+            entry.push(RecordedBreak {
+                ad_break,
+                recorded_at: Instant::now(),
+            });
+            recorded += 1;
+        }
+        drop(entry);
+        recorded
+    }
+
+    /// Unexpired breaks recorded for a session
+    pub fn breaks(&self, session_id: &str) -> Vec<InbandBreak> {
+        self.sessions
+            .get(session_id)
+            .map(|entry| {
+                entry
+                    .iter()
+                    .filter(|r| !r.is_expired())
+                    .map(|r| r.ad_break.clone())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Drop expired breaks and sessions left without any
+    pub fn cleanup(&self) {
+        self.sessions.retain(|_, breaks| {
+            breaks.retain(|r| !r.is_expired());
+            !breaks.is_empty()
+        });
+    }
+
+    /// Number of sessions with recorded breaks
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    /// Return `true` if no session has recorded breaks
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+}
+
+/// Merge in-band breaks into the breaks detected from the MPD
+///
+/// Each in-band break is placed in the last Period whose timeline starts at
+/// or before its media time, using the Period's SegmentTemplate
+/// `presentationTimeOffset`. Breaks that duplicate an MPD-signalled break,
+/// or that fall before every Period, are dropped.
+pub fn merge_inband_breaks(mpd: &MPD, ad_breaks: &mut Vec<DashAdBreak>, inband: &[InbandBreak]) {
+    for ad_break in inband {
+        let placement = mpd
+            .periods
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(idx, period)| (idx, ad_break.media_time - period_time_offset(period)))
+            .find(|(_, time)| *time >= 0.0);
+
+        let Some((period_index, presentation_time)) = placement else {
+            debug!(
+                "In-band break {} at media time {}s precedes all Periods, skipping",
+                ad_break.event_id, ad_break.media_time
+            );
+            continue;
+        };
+
+        let duplicate = ad_breaks.iter().any(|b| {
+            b.period_index == period_index
+                && (b.presentation_time - presentation_time).abs() < DUPLICATE_TOLERANCE_SECS
+        });
+        if duplicate {
+            debug!(
+                "In-band break {} duplicates an MPD-signalled break, skipping",
+                ad_break.event_id
+            );
+            continue;
+        }
+
+        ad_breaks.push(DashAdBreak {
+            period_index,
+            period_id: mpd.periods[period_index].id.clone(),
+            duration: ad_break.duration,
+            presentation_time,
+            signal_type: DashSignalType::TimeSignal,
+            upid: ad_break.upid.clone(),
+        });
+    }
+
+    ad_breaks.sort_by(|a, b| {
+        a.period_index
+            .cmp(&b.period_index)
+            .then(a.presentation_time.total_cmp(&b.presentation_time))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dash::parser::parse_mpd;
+
+    fn inband(event_id: u32, media_time: f64) -> InbandBreak {
+        InbandBreak {
+            event_id,
+            media_time,
+            duration: 30.0,
+            upid: Some("UPID".to_string()),
+        }
+    }
+
+    fn two_period_mpd() -> MPD {
+        parse_mpd(
+            r#"<?xml version="1.0"?>
+<MPD xmlns="urn:mpeg:dash:schema:mpd:2011" type="dynamic">
+  <Period id="p0" start="PT0S">
+    <AdaptationSet>
+      <SegmentTemplate media="$Number$.m4s" timescale="90000" presentationTimeOffset="0"/>
+      <Representation id="1" bandwidth="1000000"/>
+    </AdaptationSet>
+  </Period>
+  <Period id="p1" start="PT100S">
+    <AdaptationSet>
+      <Representation id="1" bandwidth="1000000">
+        <SegmentTemplate media="$Number$.m4s" timescale="90000" presentationTimeOffset="9000000"/>
+      </Representation>
+    </AdaptationSet>
+  </Period>
+</MPD>"#,
+        )
+        .expect("Failed to parse MPD")
+    }
+
+    #[test]
+    fn record_deduplicates_by_event_id() {
+        let store = InbandBreakStore::new();
+        assert_eq!(store.record("s1", vec![inband(1, 10.0)]), 1);
+        assert_eq!(
+            store.record("s1", vec![inband(1, 10.0), inband(2, 50.0)]),
+            1
+        );
+
+        assert_eq!(store.breaks("s1").len(), 2);
+        assert!(store.breaks("s2").is_empty());
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    fn cleanup_keeps_fresh_breaks() {
+        let store = InbandBreakStore::new();
+        store.record("s1", vec![inband(1, 10.0)]);
+        store.cleanup();
+        assert_eq!(store.breaks("s1").len(), 1);
+    }
+
+    #[test]
+    fn merge_places_break_in_containing_period() {
+        let mpd = two_period_mpd();
+        let mut ad_breaks = Vec::new();
+
+        merge_inband_breaks(&mpd, &mut ad_breaks, &[inband(1, 40.0), inband(2, 130.0)]);
+
+        assert_eq!(ad_breaks.len(), 2);
+        assert_eq!(ad_breaks[0].period_index, 0);
+        assert_eq!(ad_breaks[0].presentation_time, 40.0);
+        assert_eq!(ad_breaks[1].period_index, 1);
+        assert_eq!(ad_breaks[1].period_id.as_deref(), Some("p1"));
+        assert_eq!(ad_breaks[1].presentation_time, 30.0);
+        assert_eq!(ad_breaks[1].upid.as_deref(), Some("UPID"));
+    }
+
+    #[test]
+    fn merge_skips_duplicates_of_mpd_breaks() {
+        let mpd = two_period_mpd();
+        let mut ad_breaks = vec![DashAdBreak {
+            period_index: 1,
+            period_id: Some("p1".to_string()),
+            duration: 30.0,
+            presentation_time: 30.0,
+            signal_type: DashSignalType::SpliceInsert,
+            upid: None,
+        }];
+
+        merge_inband_breaks(&mpd, &mut ad_breaks, &[inband(1, 130.2)]);
+
+        assert_eq!(ad_breaks.len(), 1);
+        assert_eq!(ad_breaks[0].signal_type, DashSignalType::SpliceInsert);
+    }
+}
@@ -1,11 +1,28 @@
 use crate::ad::provider::AdSegment;
-use crate::dash::cue::DashAdBreak;
+use crate::dash::cue::{DashAdBreak, DashSignalType};
 use dash_mpd::{
-    AdaptationSet, Initialization, MPD, Period, Representation, SegmentList, SegmentURL,
+    AdaptationSet, AssetIdentifier, Initialization, MPD, Period, Representation, SegmentList,
+    SegmentURL, SupplementalProperty,
 };
 use std::time::Duration;
 use tracing::{info, warn};
 
+/// DASH-IF asset identifier scheme, set on ad Periods so players can tell
+/// them apart from content
+const ASSET_ID_SCHEME: &str = "urn:org:dashif:asset-id:2013";
+
+/// AssetIdentifier value prefix of inserted ad Periods
+const AD_ASSET_PREFIX: &str = "ritcher-ad-";
+
+/// Period continuity scheme (ISO 23009-1 §5.3.2.4)
+const PERIOD_CONTINUITY_SCHEME: &str = "urn:mpeg:dash:period-continuity:2015";
+
+/// Period connectivity scheme (ISO 23009-1 §5.3.2.4)
+const PERIOD_CONNECTIVITY_SCHEME: &str = "urn:mpeg:dash:period-connectivity:2015";
+
+/// Codec prefixes of text (subtitle) tracks in fMP4
+const TEXT_CODECS: &[&str] = &["stpp", "wvtt", "tx3g"];
+
 /// Interleave ad segments into DASH MPD by inserting ad Periods
 ///
 /// Creates new Period elements with SegmentList-based ad content and inserts them
@@ -16,6 +33,10 @@ use tracing::{info, warn};
 /// muxed (containing both audio and video), the same SegmentList URLs are used for
 /// all AdaptationSets — the player demuxes the correct track.
 ///
+/// Content Periods on either side of an ad break are linked with
+/// `period-continuity` so players resume the content tracks cleanly, and a
+/// static MPD's `mediaPresentationDuration` grows by the inserted ads.
+///
 /// # Arguments
 /// * `mpd` - The original MPD to modify
 /// * `ad_breaks` - Detected ad breaks from EventStream/SCTE-35
@@ -47,6 +68,7 @@ pub fn interleave_ads_mpd(
     }
 
     // Iterate ad breaks in reverse order to preserve period indices when inserting
+    let mut inserted_duration = 0.0;
     for (break_idx, ad_break) in ad_breaks.iter().enumerate().rev() {
         let ad_segments = &ad_segments_per_break[break_idx];
 
@@ -80,8 +102,12 @@ pub fn interleave_ads_mpd(
             content_adaptations,
         );
 
-        // Insert ad Period after the signal period
-        let insert_position = ad_break.period_index + 1;
+        // Insert ad Period after the signal period. VMAP breaks sit on a
+        // Period boundary: a break at the start of a Period goes before it.
+        let insert_position = match ad_break.signal_type {
+            DashSignalType::Vmap(_) if ad_break.presentation_time <= 0.0 => ad_break.period_index,
+            _ => ad_break.period_index + 1,
+        };
         if insert_position <= mpd.periods.len() {
             mpd.periods.insert(insert_position, ad_period);
         } else {
@@ -91,8 +117,21 @@ pub fn interleave_ads_mpd(
             );
             mpd.periods.push(ad_period);
         }
+        inserted_duration += ad_segments
+            .iter()
+            .map(|s| f64::from(s.duration))
+            .sum::<f64>();
     }
 
+    // Ads lengthen a static presentation
+    if mpd.mpdtype.as_deref() != Some("dynamic")
+        && let Some(duration) = mpd.mediaPresentationDuration
+    {
+        mpd.mediaPresentationDuration = Some(duration + Duration::from_secs_f64(inserted_duration));
+    }
+
+    signal_period_continuity(&mut mpd);
+
     info!(
         "Interleaving complete: MPD now has {} periods ({} ad breaks inserted)",
         mpd.periods.len(),
@@ -102,6 +141,66 @@ pub fn interleave_ads_mpd(
     mpd
 }
 
+/// Build a standalone static MPD for one ad break
+///
+/// Served as the alternative MPD for DASH SGAI: the player switches to it
+/// when the break's `ReplacePresentation` / `InsertPresentation` event
+/// fires. The single ad Period uses the same segment URLs as an SSAI ad
+/// Period, with a video and an audio AdaptationSet since the content MPD
+/// is not available to mirror.
+pub fn build_ad_mpd(
+    ad_segments: &[AdSegment],
+    break_idx: usize,
+    session_id: &str,
+    base_url: &str,
+) -> MPD {
+    let period = create_ad_period(
+        ad_segments,
+        break_idx,
+        session_id,
+        base_url,
+        &default_ad_adaptations(),
+    );
+    let duration = period.duration;
+
+    MPD {
+        mpdtype: Some("static".to_string()),
+        xmlns: Some("urn:mpeg:dash:schema:mpd:2011".to_string()),
+        profiles: Some("urn:mpeg:dash:profile:isoff-live:2011".to_string()),
+        minBufferTime: Some(Duration::from_secs(2)),
+        mediaPresentationDuration: duration,
+        periods: vec![period],
+        ..Default::default()
+    }
+}
+
+/// Video + audio track layout used when no content AdaptationSets are known
+fn default_ad_adaptations() -> [AdaptationSet; 2] {
+    [
+        AdaptationSet {
+            contentType: Some("video".to_string()),
+            mimeType: Some("video/mp4".to_string()),
+            representations: vec![Representation {
+                bandwidth: Some(500_000),
+                codecs: Some("avc1.64001e".to_string()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        },
+        AdaptationSet {
+            contentType: Some("audio".to_string()),
+            mimeType: Some("audio/mp4".to_string()),
+            representations: vec![Representation {
+                bandwidth: Some(128_000),
+                codecs: Some("mp4a.40.2".to_string()),
+                audioSamplingRate: Some("48000".to_string()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        },
+    ]
+}
+
 /// Create a DASH Period containing ad content with SegmentList
 ///
 /// Mirrors the content Period's AdaptationSet structure so that all tracks
@@ -117,6 +216,11 @@ pub fn interleave_ads_mpd(
 /// the content Representations so the player can set up MediaSource buffers with
 /// matching parameters.
 ///
+/// Ads are played clear, so `ContentProtection` is never carried over. Text
+/// (subtitle) AdaptationSets keep their language and role but get an empty
+/// SegmentList. The Period carries a DASH-IF `AssetIdentifier` marking it
+/// as an ad asset.
+///
 /// Falls back to a single video-only AdaptationSet when no content AdaptationSets
 /// are available (backward compatibility).
 fn create_ad_period(
@@ -153,26 +257,41 @@ fn create_ad_period(
             .iter()
             .enumerate()
             .map(|(as_idx, content_as)| {
-                // Determine track type prefix from contentType (v=video, a=audio)
-                let track_prefix = match content_as.contentType.as_deref() {
-                    Some("audio") => "a",
-                    _ => "v", // default to video for unknown types
-                };
-
-                // Track-specific init segment URL
-                let init_url = format!(
-                    "{}/stitch/{}/ad/break-{}-{}init.m4s",
-                    base_url, session_id, break_idx, track_prefix
-                );
-
-                // Track-specific data segment URLs
-                let segment_urls =
-                    build_segment_urls(ad_segments, base_url, session_id, break_idx, track_prefix);
-
-                // Copy codec info from content Representation
+                let kind = TrackKind::of(content_as);
                 let content_rep = content_as.representations.first();
                 let bw = content_rep.and_then(|r| r.bandwidth).unwrap_or(500_000);
 
+                // Text tracks stay in the ad Period so subtitle selection
+                // survives the break, but carry no segments: ads have no
+                // subtitles of their own.
+                let segment_list = match kind.prefix() {
+                    Some(track_prefix) => SegmentList {
+                        timescale: Some(1),
+                        duration: Some(seg_duration),
+                        Initialization: Some(Initialization {
+                            sourceURL: Some(format!(
+                                "{}/stitch/{}/ad/break-{}-{}init.m4s",
+                                base_url, session_id, break_idx, track_prefix
+                            )),
+                            ..Default::default()
+                        }),
+                        segment_urls: build_segment_urls(
+                            ad_segments,
+                            base_url,
+                            session_id,
+                            break_idx,
+                            track_prefix,
+                        ),
+                        ..Default::default()
+                    },
+                    None => SegmentList {
+                        timescale: Some(1),
+                        duration: Some(seg_duration),
+                        ..Default::default()
+                    },
+                };
+
+                // Copy codec info from content Representation
                 let representation = Representation {
                     id: Some(format!("ad-rep-{}-{}", break_idx, as_idx)),
                     bandwidth: Some(bw),
@@ -180,23 +299,19 @@ fn create_ad_period(
                     width: content_rep.and_then(|r| r.width),
                     height: content_rep.and_then(|r| r.height),
                     audioSamplingRate: content_rep.and_then(|r| r.audioSamplingRate.clone()),
-                    SegmentList: Some(SegmentList {
-                        timescale: Some(1),
-                        duration: Some(seg_duration),
-                        Initialization: Some(Initialization {
-                            sourceURL: Some(init_url),
-                            ..Default::default()
-                        }),
-                        segment_urls,
-                        ..Default::default()
-                    }),
+                    SegmentList: Some(segment_list),
                     ..Default::default()
                 };
 
+                // Ads are clear: ContentProtection is deliberately not copied
                 AdaptationSet {
+                    id: content_as.id.clone(),
                     contentType: content_as.contentType.clone(),
                     mimeType: content_as.mimeType.clone(),
+                    codecs: content_as.codecs.clone(),
                     lang: content_as.lang.clone(),
+                    Role: content_as.Role.clone(),
+                    Accessibility: content_as.Accessibility.clone(),
                     representations: vec![representation],
                     ..Default::default()
                 }
@@ -208,11 +323,154 @@ fn create_ad_period(
     Period {
         id: Some(format!("ad-{}", break_idx)),
         duration: Some(Duration::from_secs_f64(total_duration)),
+        asset_identifier: Some(AssetIdentifier {
+            schemeIdUri: Some(ASSET_ID_SCHEME.to_string()),
+            value: Some(format!("{}{}", AD_ASSET_PREFIX, break_idx)),
+            ..Default::default()
+        }),
         adaptations,
         ..Default::default()
     }
 }
 
+/// Track type of a content AdaptationSet
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TrackKind {
+    Video,
+    Audio,
+    Text,
+}
+
+impl TrackKind {
+    /// Classify from `contentType`, then `mimeType`, then codecs
+    ///
+    /// Unknown tracks are treated as video, matching the ad provider's
+    /// default rendition.
+    fn of(adaptation: &AdaptationSet) -> Self {
+        match adaptation.contentType.as_deref() {
+            Some("video") => return Self::Video,
+            Some("audio") => return Self::Audio,
+            Some("text") => return Self::Text,
+            _ => {}
+        }
+
+        let mime = adaptation
+            .mimeType
+            .as_deref()
+            .or_else(|| {
+                adaptation
+                    .representations
+                    .first()
+                    .and_then(|r| r.mimeType.as_deref())
+            })
+            .unwrap_or("");
+        if mime.starts_with("audio/") {
+            return Self::Audio;
+        }
+        if mime.starts_with("text/") || mime == "application/ttml+xml" {
+            return Self::Text;
+        }
+
+        let codecs = adaptation
+            .codecs
+            .as_deref()
+            .or_else(|| {
+                adaptation
+                    .representations
+                    .first()
+                    .and_then(|r| r.codecs.as_deref())
+            })
+            .unwrap_or("");
+        if TEXT_CODECS.iter().any(|c| codecs.starts_with(c)) {
+            return Self::Text;
+        }
+
+        Self::Video
+    }
+
+    /// Ad segment track prefix, or `None` for tracks without ad media
+    fn prefix(self) -> Option<&'static str> {
+        match self {
+            Self::Video => Some("v"),
+            Self::Audio => Some("a"),
+            Self::Text => None,
+        }
+    }
+}
+
+/// Link content Periods separated by ad Periods with period continuity
+///
+/// For each content Period that directly follows one or more inserted ad
+/// Periods, every AdaptationSet whose `id` also exists in the content Period
+/// before the break gets a `urn:mpeg:dash:period-continuity:2015`
+/// SupplementalProperty naming that Period, so the player can resume the
+/// same tracks without re-initialising. Origin `period-connectivity`
+/// properties pointing at that Period are removed, since the two are no
+/// longer adjacent.
+fn signal_period_continuity(mpd: &mut MPD) {
+    let mut last_content: Option<usize> = None;
+    let mut after_ad = false;
+
+    for idx in 0..mpd.periods.len() {
+        if is_ad_period(&mpd.periods[idx]) {
+            after_ad = true;
+            continue;
+        }
+
+        if after_ad && let Some(prev_idx) = last_content {
+            let (before, after) = mpd.periods.split_at_mut(idx);
+            link_periods(&before[prev_idx], &mut after[0]);
+        }
+
+        last_content = Some(idx);
+        after_ad = false;
+    }
+}
+
+/// Add continuity properties from `next` back to `previous`
+fn link_periods(previous: &Period, next: &mut Period) {
+    let Some(previous_id) = previous.id.as_deref() else {
+        return;
+    };
+
+    for adaptation in &mut next.adaptations {
+        let continues = adaptation.id.as_ref().is_some_and(|id| {
+            previous
+                .adaptations
+                .iter()
+                .any(|a| a.id.as_ref() == Some(id))
+        });
+        if !continues {
+            continue;
+        }
+
+        adaptation.supplemental_property.retain(|p| {
+            !(p.schemeIdUri == PERIOD_CONNECTIVITY_SCHEME
+                && p.value.as_deref() == Some(previous_id))
+        });
+        let already_linked = adaptation.supplemental_property.iter().any(|p| {
+            p.schemeIdUri == PERIOD_CONTINUITY_SCHEME && p.value.as_deref() == Some(previous_id)
+        });
+        if !already_linked {
+            adaptation.supplemental_property.push(SupplementalProperty {
+                schemeIdUri: PERIOD_CONTINUITY_SCHEME.to_string(),
+                value: Some(previous_id.to_string()),
+                ..Default::default()
+            });
+        }
+    }
+}
+
+/// Return `true` for ad Periods created by [`create_ad_period`]
+fn is_ad_period(period: &Period) -> bool {
+    period.asset_identifier.as_ref().is_some_and(|a| {
+        a.schemeIdUri.as_deref() == Some(ASSET_ID_SCHEME)
+            && a.value
+                .as_deref()
+                .is_some_and(|v| v.starts_with(AD_ASSET_PREFIX))
+    })
+}
+
 /// Build track-specific SegmentURL entries for an ad break
 fn build_segment_urls(
     ad_segments: &[AdSegment],
@@ -270,6 +528,7 @@ fn create_fallback_video_adaptation_set(
 mod tests {
     use super::*;
     use crate::dash::cue::{DashAdBreak, DashSignalType};
+    use dash_mpd::{ContentProtection, Role};
 
     fn create_test_mpd_with_periods(count: usize) -> MPD {
         let mut mpd = MPD::default();
@@ -332,6 +591,7 @@ mod tests {
             duration,
             presentation_time: 0.0,
             signal_type: DashSignalType::SpliceInsert,
+            upid: None,
         }
     }
 
@@ -426,6 +686,42 @@ mod tests {
         assert_eq!(result.periods[5].id, Some("content-3".to_string()));
     }
 
+    #[test]
+    fn test_interleave_vmap_breaks_on_period_boundaries() {
+        let mut mpd = create_test_mpd_with_periods(2);
+        mpd.mediaPresentationDuration = Some(Duration::from_secs(120));
+        let vmap_break = |period_index, presentation_time, vmap_index| DashAdBreak {
+            presentation_time,
+            signal_type: DashSignalType::Vmap(vmap_index),
+            ..create_test_ad_break(period_index, 30.0)
+        };
+        // Pre-roll, mid-roll at the start of content-1, post-roll
+        let ad_breaks = vec![
+            vmap_break(0, 0.0, 0),
+            vmap_break(1, 0.0, 1),
+            vmap_break(1, 60.0, 2),
+        ];
+        let ad = |uri: &str| AdSegment {
+            uri: uri.to_string(),
+            duration: 10.0,
+            tracking: None,
+        };
+        let ad_segments = vec![vec![ad("pre.ts")], vec![ad("mid.ts")], vec![ad("post.ts")]];
+
+        let result = interleave_ads_mpd(mpd, &ad_breaks, &ad_segments, "test", "http://test");
+
+        let ids: Vec<&str> = result
+            .periods
+            .iter()
+            .map(|p| p.id.as_deref().unwrap())
+            .collect();
+        assert_eq!(ids, vec!["ad-0", "content-0", "ad-1", "content-1", "ad-2"]);
+        assert_eq!(
+            result.mediaPresentationDuration,
+            Some(Duration::from_secs(150))
+        );
+    }
+
     #[test]
     fn test_interleave_no_ad_breaks() {
         let mpd = create_test_mpd_with_periods(2);
@@ -769,4 +1065,184 @@ mod tests {
         assert_eq!(audio_rep.codecs, Some("mp4a.40.5".to_string()));
         assert_eq!(audio_rep.audioSamplingRate, Some("48000".to_string()));
     }
+
+    #[test]
+    fn test_build_ad_mpd_is_static_with_video_and_audio() {
+        let ad_segments = vec![
+            AdSegment {
+                uri: "ad1.ts".to_string(),
+                duration: 10.0,
+                tracking: None,
+            },
+            AdSegment {
+                uri: "ad2.ts".to_string(),
+                duration: 5.0,
+                tracking: None,
+            },
+        ];
+
+        let mpd = build_ad_mpd(&ad_segments, 3, "sess", "http://stitcher");
+
+        assert_eq!(mpd.mpdtype.as_deref(), Some("static"));
+        assert_eq!(mpd.mediaPresentationDuration, Some(Duration::from_secs(15)));
+        assert_eq!(mpd.periods.len(), 1);
+        assert_eq!(mpd.periods[0].id.as_deref(), Some("ad-3"));
+
+        let adaptations = &mpd.periods[0].adaptations;
+        assert_eq!(adaptations.len(), 2);
+        assert_eq!(adaptations[1].contentType.as_deref(), Some("audio"));
+        let audio_list = adaptations[1].representations[0]
+            .SegmentList
+            .as_ref()
+            .unwrap();
+        assert_eq!(
+            audio_list.segment_urls[1].media.as_deref(),
+            Some("http://stitcher/stitch/sess/ad/break-3-aseg-1.m4s")
+        );
+    }
+
+    fn one_segment() -> Vec<Vec<AdSegment>> {
+        vec![vec![AdSegment {
+            uri: "ad.ts".to_string(),
+            duration: 10.0,
+            tracking: None,
+        }]]
+    }
+
+    /// Protected content Period with video, audio and subtitle tracks
+    fn create_test_mpd_protected_with_subtitles(count: usize) -> MPD {
+        let protection = ContentProtection {
+            schemeIdUri: Some("urn:mpeg:dash:mp4protection:2011".to_string()),
+            value: Some("cenc".to_string()),
+            ..Default::default()
+        };
+        let mut mpd = MPD::default();
+        for i in 0..count {
+            mpd.periods.push(Period {
+                id: Some(format!("content-{}", i)),
+                duration: Some(Duration::from_secs(60)),
+                adaptations: vec![
+                    AdaptationSet {
+                        id: Some("1".to_string()),
+                        mimeType: Some("video/mp4".to_string()),
+                        ContentProtection: vec![protection.clone()],
+                        representations: vec![Representation {
+                            codecs: Some("avc1.64001f".to_string()),
+                            ..Default::default()
+                        }],
+                        ..Default::default()
+                    },
+                    AdaptationSet {
+                        id: Some("2".to_string()),
+                        mimeType: Some("audio/mp4".to_string()),
+                        lang: Some("en".to_string()),
+                        ContentProtection: vec![protection.clone()],
+                        ..Default::default()
+                    },
+                    AdaptationSet {
+                        id: Some("3".to_string()),
+                        mimeType: Some("application/mp4".to_string()),
+                        lang: Some("fr".to_string()),
+                        Role: vec![Role {
+                            schemeIdUri: Some("urn:mpeg:dash:role:2011".to_string()),
+                            value: Some("subtitle".to_string()),
+                            ..Default::default()
+                        }],
+                        representations: vec![Representation {
+                            codecs: Some("stpp.ttml.im1t".to_string()),
+                            ..Default::default()
+                        }],
+                        ..Default::default()
+                    },
+                ],
+                ..Default::default()
+            });
+        }
+        mpd
+    }
+
+    #[test]
+    fn test_ad_period_is_clear_with_track_kinds_from_mime_and_codecs() {
+        let mpd = create_test_mpd_protected_with_subtitles(1);
+        let ad_breaks = vec![create_test_ad_break(0, 10.0)];
+
+        let result = interleave_ads_mpd(mpd, &ad_breaks, &one_segment(), "s", "http://t");
+
+        let ad_period = &result.periods[1];
+        assert_eq!(ad_period.adaptations.len(), 3);
+        assert!(
+            ad_period
+                .adaptations
+                .iter()
+                .all(|a| a.ContentProtection.is_empty()),
+            "Ad AdaptationSets must be clear"
+        );
+
+        let list = |i: usize| {
+            ad_period.adaptations[i].representations[0]
+                .SegmentList
+                .as_ref()
+                .unwrap()
+        };
+        assert_eq!(
+            list(1).segment_urls[0].media.as_deref(),
+            Some("http://t/stitch/s/ad/break-0-aseg-0.m4s")
+        );
+
+        // Subtitles keep language and role but carry no ad media
+        let text = &ad_period.adaptations[2];
+        assert_eq!(text.lang.as_deref(), Some("fr"));
+        assert_eq!(text.Role[0].value.as_deref(), Some("subtitle"));
+        assert!(list(2).segment_urls.is_empty());
+        assert!(list(2).Initialization.is_none());
+    }
+
+    #[test]
+    fn test_ad_period_has_ad_asset_identifier() {
+        let mpd = create_test_mpd_with_periods(2);
+        let ad_breaks = vec![create_test_ad_break(0, 10.0)];
+
+        let result = interleave_ads_mpd(mpd, &ad_breaks, &one_segment(), "s", "http://t");
+
+        let asset = result.periods[1].asset_identifier.as_ref().unwrap();
+        assert_eq!(asset.schemeIdUri.as_deref(), Some(ASSET_ID_SCHEME));
+        assert_eq!(asset.value.as_deref(), Some("ritcher-ad-0"));
+        assert!(result.periods[0].asset_identifier.is_none());
+    }
+
+    #[test]
+    fn test_period_continuity_links_content_around_ads() {
+        let mut mpd = create_test_mpd_protected_with_subtitles(2);
+        // Origin signalled the two content Periods as adjacent
+        mpd.periods[1].adaptations[0]
+            .supplemental_property
+            .push(SupplementalProperty {
+                schemeIdUri: PERIOD_CONNECTIVITY_SCHEME.to_string(),
+                value: Some("content-0".to_string()),
+                ..Default::default()
+            });
+        mpd.periods[1].adaptations[2].id = Some("9".to_string());
+        let ad_breaks = vec![create_test_ad_break(0, 10.0), create_test_ad_break(0, 10.0)];
+        let ad_segments = [one_segment(), one_segment()].concat();
+
+        let result = interleave_ads_mpd(mpd, &ad_breaks, &ad_segments, "s", "http://t");
+
+        // content-0, ad-0, ad-1, content-1
+        assert_eq!(result.periods.len(), 4);
+        let content = &result.periods[3];
+        let video_props = &content.adaptations[0].supplemental_property;
+        assert_eq!(video_props.len(), 1);
+        assert_eq!(video_props[0].schemeIdUri, PERIOD_CONTINUITY_SCHEME);
+        assert_eq!(video_props[0].value.as_deref(), Some("content-0"));
+        assert_eq!(content.adaptations[1].supplemental_property.len(), 1);
+        // No AdaptationSet with id 9 before the break
+        assert!(content.adaptations[2].supplemental_property.is_empty());
+        // Ad Periods themselves are not linked
+        assert!(
+            result.periods[2]
+                .adaptations
+                .iter()
+                .all(|a| a.supplemental_property.is_empty())
+        );
+    }
 }
@@ -1,4 +1,7 @@
 pub mod cue;
+pub mod emsg;
+pub mod inband;
 pub mod interleaver;
 pub mod parser;
+pub mod schedule;
 pub mod sgai;
@@ -0,0 +1,605 @@
+//! Place scheduled ad breaks into DASH MPDs
+//!
+//! VMAP breaks go into static MPDs. For SSAI a mid-roll needs a Period
+//! boundary to insert the ad Period at, so the content Period under the
+//! offset is split in two: the tail is a copy whose SegmentTemplates start
+//! `presentationTimeOffset` later, and the interleaver links both halves
+//! with `period-continuity`. For SGAI the Periods stay as they are and the
+//! break is signalled at its offset.
+//!
+//! Channel schedule breaks go into dynamic MPDs: their wall-clock start is
+//! mapped through `availabilityStartTime` onto the Period timeline and they
+//! are merged with the SCTE-35 breaks as if signalled in the MPD.
+//!
+//! Breaks of a dynamic MPD signalled well ahead of the live edge can be
+//! held back and decided ahead of time (see [`take_upcoming_breaks`]).
+
+use crate::ad::break_schedule::ChannelBreak;
+use crate::ad::prefetch::UpcomingBreak;
+use crate::ad::schedule::{self, BreakPosition, ScheduledBreak};
+use crate::ad::vast::VmapResponse;
+use crate::dash::cue::{DashAdBreak, DashSignalType};
+use chrono::{DateTime, Utc};
+use dash_mpd::{MPD, Period, SegmentTemplate};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+/// Offsets within this many seconds of a Period edge snap to the edge
+const SNAP_SECS: f64 = 0.5;
+
+/// Breaks are stitched once they start within the MPD's
+/// `minimumUpdatePeriod` plus this many seconds of the live edge
+const STITCH_AHEAD_SECS: f64 = 10.0;
+
+/// Resolve VMAP breaks against a static MPD
+///
+/// Ad opportunities for `#n` offsets are the start of the presentation,
+/// every later Period start and the end. With `split_periods` (SSAI) each
+/// break lands on a Period boundary: `presentation_time` 0 means "before
+/// `period_index`", anything else "after it". Without it (SGAI) the break
+/// keeps its offset within the containing Period. Breaks that land on the
+/// same spot as an earlier one are dropped.
+pub fn schedule_ad_breaks(
+    mpd: &mut MPD,
+    vmap: &VmapResponse,
+    split_periods: bool,
+) -> Vec<(DashAdBreak, ScheduledBreak)> {
+    let mut timeline = period_timeline(mpd);
+    let Some(&(last_start, last_duration)) = timeline.last() else {
+        return Vec::new();
+    };
+    let total = last_start + last_duration;
+
+    let mut opportunities = vec![0.0];
+    opportunities.extend(timeline.iter().skip(1).map(|&(start, _)| start));
+    opportunities.push(total);
+
+    let mut placed: Vec<(DashAdBreak, ScheduledBreak)> = Vec::new();
+    for scheduled in schedule::resolve_breaks(vmap, total, &opportunities) {
+        let last = timeline.len() - 1;
+        let (period_index, presentation_time) = match scheduled.position {
+            BreakPosition::Pre => (0, 0.0),
+            BreakPosition::Post => (last, timeline[last].1),
+            BreakPosition::Mid => {
+                let index = timeline
+                    .iter()
+                    .rposition(|&(start, _)| start <= scheduled.offset)
+                    .unwrap_or(0);
+                let (start, duration) = timeline[index];
+                let into = scheduled.offset - start;
+                if !split_periods {
+                    (index, into)
+                } else if into <= SNAP_SECS {
+                    (index, 0.0)
+                } else if duration - into <= SNAP_SECS
+                    || !split_period(mpd, index, into, duration, scheduled.vmap_index)
+                {
+                    // Next Period boundary, or after the last Period
+                    if index < last {
+                        (index + 1, 0.0)
+                    } else {
+                        (last, duration)
+                    }
+                } else {
+                    timeline[index].1 = into;
+                    timeline.insert(index + 1, (scheduled.offset, duration - into));
+                    (index + 1, 0.0)
+                }
+            }
+        };
+
+        if placed.iter().any(|(b, _)| {
+            b.period_index == period_index && b.presentation_time == presentation_time
+        }) {
+            warn!(
+                "VMAP: break {} shares Period {} position {}s with an earlier break, skipping",
+                scheduled.vmap_index, period_index, presentation_time
+            );
+            continue;
+        }
+
+        placed.push((
+            DashAdBreak {
+                period_index,
+                period_id: mpd.periods[period_index].id.clone(),
+                duration: f64::from(schedule::DEFAULT_BREAK_DURATION),
+                presentation_time,
+                signal_type: DashSignalType::Vmap(scheduled.vmap_index),
+                upid: None,
+            },
+            scheduled,
+        ));
+    }
+
+    info!(
+        "VMAP: placed {} break(s) in a {:.1}s MPD ({} Period(s))",
+        placed.len(),
+        total,
+        mpd.periods.len()
+    );
+    placed
+}
+
+/// How far ahead of the wall clock a channel break may be signalled,
+/// mirroring how early encoders insert SCTE-35 before the splice point
+const LOOKAHEAD_SECS: f64 = 60.0;
+
+/// Merge channel schedule breaks into the breaks detected from the MPD
+///
+/// Each break's wall-clock start, relative to `availabilityStartTime`, is
+/// placed in the last Period starting at or before it. Breaks more than
+/// [`LOOKAHEAD_SECS`] in the future, before the first Period or
+/// overlapping a detected break in the same Period are dropped. MPDs
+/// without `availabilityStartTime` cannot be matched and are left as is.
+pub fn merge_channel_breaks(
+    mpd: &MPD,
+    ad_breaks: &mut Vec<DashAdBreak>,
+    scheduled: &[ChannelBreak],
+) {
+    if scheduled.is_empty() {
+        return;
+    }
+    let Some(availability_start) = mpd.availabilityStartTime else {
+        debug!("MPD has no availabilityStartTime, cannot place scheduled breaks");
+        return;
+    };
+    let timeline = period_timeline(mpd);
+    let now = Utc::now();
+
+    for ad_break in scheduled {
+        let ahead = (ad_break.start - now).num_milliseconds() as f64 / 1000.0;
+        if ahead > LOOKAHEAD_SECS {
+            continue;
+        }
+        let offset = (ad_break.start - availability_start).num_milliseconds() as f64 / 1000.0;
+        let Some(period_index) = timeline.iter().rposition(|&(start, _)| start <= offset) else {
+            debug!(
+                "Scheduled break {} precedes all Periods, skipping",
+                ad_break.id
+            );
+            continue;
+        };
+        let presentation_time = offset - timeline[period_index].0;
+
+        let overlaps = ad_breaks.iter().any(|b| {
+            b.period_index == period_index
+                && b.presentation_time < presentation_time + ad_break.duration
+                && presentation_time < b.presentation_time + b.duration
+        });
+        if overlaps {
+            debug!(
+                "Scheduled break {} overlaps a signalled break, skipping",
+                ad_break.id
+            );
+            continue;
+        }
+
+        info!(
+            "Scheduled break {} placed in Period {} at {:.3}s",
+            ad_break.id, period_index, presentation_time
+        );
+        ad_breaks.push(DashAdBreak {
+            period_index,
+            period_id: mpd.periods[period_index].id.clone(),
+            duration: ad_break.duration,
+            presentation_time,
+            signal_type: DashSignalType::Scheduled,
+            upid: None,
+        });
+    }
+
+    ad_breaks.sort_by(|a, b| {
+        a.period_index
+            .cmp(&b.period_index)
+            .then(a.presentation_time.total_cmp(&b.presentation_time))
+    });
+}
+
+/// Start of `ad_break` on the MPD's presentation timeline, in seconds
+pub fn break_start(mpd: &MPD, ad_break: &DashAdBreak) -> f64 {
+    let period_start = period_timeline(mpd)
+        .get(ad_break.period_index)
+        .map_or(0.0, |&(start, _)| start);
+    period_start + ad_break.presentation_time
+}
+
+/// How many seconds past `start` on the presentation timeline the live
+/// edge of a dynamic MPD is
+///
+/// Static MPDs and dynamic ones without `availabilityStartTime` are
+/// available in full: infinite.
+pub fn live_edge_elapsed(mpd: &MPD, start: f64) -> f64 {
+    match mpd.availabilityStartTime {
+        Some(availability_start) if mpd.mpdtype.as_deref() == Some("dynamic") => {
+            (Utc::now() - availability_start).num_milliseconds() as f64 / 1000.0 - start
+        }
+        _ => f64::INFINITY,
+    }
+}
+
+/// Wall-clock time of `start` seconds into a dynamic MPD's presentation
+/// timeline
+///
+/// `None` for static MPDs and dynamic ones without `availabilityStartTime`.
+pub fn wall_clock(mpd: &MPD, start: f64) -> Option<DateTime<Utc>> {
+    let availability_start = mpd.availabilityStartTime?;
+    #[allow(clippy::cast_possible_truncation)] // milliseconds of a timeline
+    let offset = chrono::Duration::milliseconds((start * 1000.0).round() as i64);
+    (mpd.mpdtype.as_deref() == Some("dynamic")).then(|| availability_start + offset)
+}
+
+/// Take the breaks of a dynamic MPD starting too far ahead of its live
+/// edge to be stitched yet
+///
+/// A break is stitched once it starts within the MPD's
+/// `minimumUpdatePeriod` plus [`STITCH_AHEAD_SECS`] of the live edge, so
+/// that players refresh the MPD and see its ad Period before they can fetch
+/// content past its start. Breaks further ahead are removed from
+/// `ad_breaks` and returned, to be decided ahead of time.
+pub fn take_upcoming_breaks(mpd: &MPD, ad_breaks: &mut Vec<DashAdBreak>) -> Vec<UpcomingBreak> {
+    let horizon = mpd.minimumUpdatePeriod.map_or(0.0, |d| d.as_secs_f64()) + STITCH_AHEAD_SECS;
+    let mut upcoming = Vec::new();
+    ad_breaks.retain(|ad_break| {
+        let start = break_start(mpd, ad_break);
+        let Some(wall_start) = wall_clock(mpd, start) else {
+            return true;
+        };
+        if -live_edge_elapsed(mpd, start) <= horizon {
+            return true;
+        }
+        debug!(
+            "Break at {:.3}s is {:.1}s ahead of the live edge, holding it back",
+            start,
+            -live_edge_elapsed(mpd, start)
+        );
+        #[allow(clippy::cast_possible_truncation)] // ad break durations fit f32
+        upcoming.push(UpcomingBreak {
+            start: wall_start,
+            duration: ad_break.duration as f32,
+            upid: ad_break.upid.clone(),
+        });
+        false
+    });
+    upcoming
+}
+
+/// Start and duration of each Period, in seconds
+///
+/// Missing `@start` follows the previous Period; missing `@duration` runs
+/// to the next Period's start or, for the last one, to the end of the
+/// presentation.
+fn period_timeline(mpd: &MPD) -> Vec<(f64, f64)> {
+    let total = mpd.mediaPresentationDuration.map(|d| d.as_secs_f64());
+    let mut timeline: Vec<(f64, f64)> = Vec::with_capacity(mpd.periods.len());
+    let mut next_start = 0.0;
+    for (index, period) in mpd.periods.iter().enumerate() {
+        let start = period.start.map_or(next_start, |s| s.as_secs_f64());
+        let end = period
+            .duration
+            .map(|d| start + d.as_secs_f64())
+            .or_else(|| {
+                mpd.periods
+                    .get(index + 1)
+                    .and_then(|p| p.start)
+                    .map(|s| s.as_secs_f64())
+            })
+            .or(total)
+            .unwrap_or(start);
+        timeline.push((start, (end - start).max(0.0)));
+        next_start = end;
+    }
+    timeline
+}
+
+/// Split Period `index` at `at` seconds into it
+///
+/// Only SegmentTemplate-addressed Periods can be split, since shifting
+/// `presentationTimeOffset` is enough to start the tail mid-way. Returns
+/// false and leaves the MPD untouched otherwise.
+fn split_period(mpd: &mut MPD, index: usize, at: f64, duration: f64, vmap_index: usize) -> bool {
+    let head = &mut mpd.periods[index];
+    if !is_template_addressed(head) {
+        warn!(
+            "VMAP: Period {} is not SegmentTemplate-addressed and cannot be split",
+            index
+        );
+        return false;
+    }
+
+    let head_id = head
+        .id
+        .get_or_insert_with(|| format!("content-{}", index))
+        .clone();
+    head.duration = Some(Duration::from_secs_f64(at));
+
+    let mut tail = head.clone();
+    tail.id = Some(format!("{}-vmap-{}", head_id, vmap_index));
+    tail.start = None;
+    tail.duration = Some(Duration::from_secs_f64(duration - at));
+    // Events belong to the part of the Period they were signalled in
+    tail.event_streams.clear();
+    shift_templates(&mut tail, at);
+
+    info!(
+        "VMAP: split Period {} ({}) at {:.3}s for break {}",
+        index, head_id, at, vmap_index
+    );
+    mpd.periods.insert(index + 1, tail);
+    true
+}
+
+/// Whether every Representation of the Period resolves to a SegmentTemplate
+fn is_template_addressed(period: &Period) -> bool {
+    period.adaptations.iter().all(|adaptation| {
+        period.SegmentTemplate.is_some()
+            || adaptation.SegmentTemplate.is_some()
+            || (!adaptation.representations.is_empty()
+                && adaptation
+                    .representations
+                    .iter()
+                    .all(|r| r.SegmentTemplate.is_some()))
+    })
+}
+
+/// Move every SegmentTemplate of the Period `secs` later into the media
+///
+/// `presentationTimeOffset` moves by `secs`. Number-based templates
+/// without a SegmentTimeline also skip the segments that lie wholly before
+/// the new start, since their numbering counts from the Period start.
+fn shift_templates(period: &mut Period, secs: f64) {
+    fn shift(template: &mut SegmentTemplate, secs: f64) {
+        // Offsets are non-negative seconds times a u64 timescale; rounding
+        // to whole ticks is intentional
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let ticks = (secs * template.timescale.unwrap_or(1) as f64).round() as u64;
+        template.presentationTimeOffset =
+            Some(template.presentationTimeOffset.unwrap_or(0) + ticks);
+        if template.SegmentTimeline.is_none()
+            && let Some(duration) = template.duration.filter(|d| *d > 0.0)
+        {
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            let skipped = (ticks as f64 / duration).floor() as u64;
+            template.startNumber = Some(template.startNumber.unwrap_or(1) + skipped);
+        }
+    }
+
+    // Each level that carries its own template overrides the one above it,
+    // so every copy is shifted
+    if let Some(template) = period.SegmentTemplate.as_mut() {
+        shift(template, secs);
+    }
+    for adaptation in &mut period.adaptations {
+        if let Some(template) = adaptation.SegmentTemplate.as_mut() {
+            shift(template, secs);
+        }
+        for representation in &mut adaptation.representations {
+            if let Some(template) = representation.SegmentTemplate.as_mut() {
+                shift(template, secs);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ad::vast::{TimeOffset, VmapAdBreak, VmapAdSource};
+    use crate::dash::parser::parse_mpd;
+
+    const VOD_MPD: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<MPD xmlns="urn:mpeg:dash:schema:mpd:2011" type="static" mediaPresentationDuration="PT60S" profiles="urn:mpeg:dash:profile:isoff-on-demand:2011">
+  <Period id="main">
+    <AdaptationSet id="1" contentType="video" mimeType="video/mp4">
+      <SegmentTemplate timescale="1000" duration="2000" media="v-$Number$.m4s" initialization="v-init.mp4" startNumber="1"/>
+      <Representation id="v1" bandwidth="1000000" codecs="avc1.64001f"/>
+    </AdaptationSet>
+  </Period>
+</MPD>"#;
+
+    fn vmap(offsets: &[TimeOffset]) -> VmapResponse {
+        VmapResponse {
+            version: "1.0".into(),
+            ad_breaks: offsets
+                .iter()
+                .map(|&time_offset| VmapAdBreak {
+                    break_id: None,
+                    break_type: "linear".into(),
+                    time_offset,
+                    ad_source: Some(VmapAdSource::AdTagUri("https://ads.example.com".into())),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn ssai_splits_the_period_at_mid_rolls() {
+        let mut mpd = parse_mpd(VOD_MPD).unwrap();
+        let vmap = vmap(&[
+            TimeOffset::Start,
+            TimeOffset::Seconds(20.0),
+            TimeOffset::End,
+        ]);
+
+        let placed = schedule_ad_breaks(&mut mpd, &vmap, true);
+
+        assert_eq!(mpd.periods.len(), 2);
+        assert_eq!(mpd.periods[0].duration, Some(Duration::from_secs(20)));
+        let tail = &mpd.periods[1];
+        assert_eq!(tail.id.as_deref(), Some("main-vmap-1"));
+        assert_eq!(tail.start, None);
+        assert_eq!(tail.duration, Some(Duration::from_secs(40)));
+        let template = tail.adaptations[0].SegmentTemplate.as_ref().unwrap();
+        assert_eq!(template.presentationTimeOffset, Some(20_000));
+        assert_eq!(template.startNumber, Some(11));
+
+        let spots: Vec<(usize, f64, DashSignalType)> = placed
+            .iter()
+            .map(|(b, _)| (b.period_index, b.presentation_time, b.signal_type))
+            .collect();
+        assert_eq!(
+            spots,
+            vec![
+                (0, 0.0, DashSignalType::Vmap(0)),
+                (1, 0.0, DashSignalType::Vmap(1)),
+                (1, 40.0, DashSignalType::Vmap(2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn sgai_keeps_periods_and_offsets() {
+        let mut mpd = parse_mpd(VOD_MPD).unwrap();
+        let vmap = vmap(&[TimeOffset::Percent(50.0)]);
+
+        let placed = schedule_ad_breaks(&mut mpd, &vmap, false);
+
+        assert_eq!(mpd.periods.len(), 1);
+        assert_eq!(placed.len(), 1);
+        assert_eq!(placed[0].0.period_index, 0);
+        assert_eq!(placed[0].0.presentation_time, 30.0);
+    }
+
+    fn channel_break(id: &str, start: chrono::DateTime<Utc>, duration: f64) -> ChannelBreak {
+        ChannelBreak {
+            id: id.to_string(),
+            start,
+            duration,
+        }
+    }
+
+    #[test]
+    fn channel_breaks_map_through_availability_start_time() {
+        let mut mpd = parse_mpd(
+            r#"<MPD xmlns="urn:mpeg:dash:schema:mpd:2011" type="dynamic" availabilityStartTime="2026-10-18T12:00:00Z">
+  <Period id="p0" start="PT0S"/>
+  <Period id="p1" start="PT600S"/>
+</MPD>"#,
+        )
+        .unwrap();
+        let ast = mpd.availabilityStartTime.unwrap();
+        let mut ad_breaks = vec![DashAdBreak {
+            period_index: 1,
+            period_id: Some("p1".into()),
+            duration: 30.0,
+            presentation_time: 100.0,
+            signal_type: DashSignalType::SpliceInsert,
+            upid: None,
+        }];
+
+        merge_channel_breaks(
+            &mpd,
+            &mut ad_breaks,
+            &[
+                channel_break("in-p0", ast + chrono::Duration::seconds(120), 30.0),
+                channel_break("overlap", ast + chrono::Duration::seconds(710), 30.0),
+                channel_break("in-p1", ast + chrono::Duration::seconds(900), 60.0),
+                channel_break("early", ast - chrono::Duration::seconds(10), 30.0),
+                channel_break("future", Utc::now() + chrono::Duration::hours(1), 30.0),
+            ],
+        );
+
+        let spots: Vec<(usize, f64, DashSignalType)> = ad_breaks
+            .iter()
+            .map(|b| (b.period_index, b.presentation_time, b.signal_type))
+            .collect();
+        assert_eq!(
+            spots,
+            vec![
+                (0, 120.0, DashSignalType::Scheduled),
+                (1, 100.0, DashSignalType::SpliceInsert),
+                (1, 300.0, DashSignalType::Scheduled),
+            ]
+        );
+
+        // Without availabilityStartTime nothing can be placed
+        mpd.availabilityStartTime = None;
+        let mut none = Vec::new();
+        merge_channel_breaks(&mpd, &mut none, &[channel_break("a", ast, 30.0)]);
+        assert!(none.is_empty());
+    }
+
+    #[test]
+    fn break_starts_follow_the_period_timeline() {
+        let mut mpd = parse_mpd(
+            r#"<MPD xmlns="urn:mpeg:dash:schema:mpd:2011" type="dynamic" availabilityStartTime="2026-10-18T12:00:00Z">
+  <Period id="p0" start="PT0S"/>
+  <Period id="p1" start="PT600S"/>
+</MPD>"#,
+        )
+        .unwrap();
+        let ad_break = DashAdBreak {
+            period_index: 1,
+            period_id: Some("p1".into()),
+            duration: 30.0,
+            presentation_time: 100.0,
+            signal_type: DashSignalType::SpliceInsert,
+            upid: None,
+        };
+        assert_eq!(break_start(&mpd, &ad_break), 700.0);
+
+        let ast = mpd.availabilityStartTime.unwrap();
+        let elapsed = live_edge_elapsed(&mpd, 700.0);
+        let expected = (Utc::now() - ast).num_seconds() as f64 - 700.0;
+        assert!((elapsed - expected).abs() < 5.0, "got {elapsed}");
+
+        mpd.mpdtype = Some("static".into());
+        assert_eq!(live_edge_elapsed(&mpd, 700.0), f64::INFINITY);
+    }
+
+    #[test]
+    fn breaks_far_ahead_of_the_live_edge_are_held_back() {
+        let ast = Utc::now() - chrono::Duration::seconds(1000);
+        let mut mpd = parse_mpd(&format!(
+            r#"<MPD xmlns="urn:mpeg:dash:schema:mpd:2011" type="dynamic" availabilityStartTime="{}" minimumUpdatePeriod="PT2S">
+  <Period id="p0" start="PT0S"/>
+</MPD>"#,
+            ast.format("%Y-%m-%dT%H:%M:%SZ")
+        ))
+        .unwrap();
+        let ad_break = |presentation_time: f64, upid: &str| DashAdBreak {
+            period_index: 0,
+            period_id: Some("p0".into()),
+            duration: 30.0,
+            presentation_time,
+            signal_type: DashSignalType::SpliceInsert,
+            upid: Some(upid.into()),
+        };
+        let mut ad_breaks = vec![ad_break(1005.0, "near"), ad_break(1100.0, "far")];
+
+        let upcoming = take_upcoming_breaks(&mpd, &mut ad_breaks);
+
+        assert_eq!(ad_breaks.len(), 1);
+        assert_eq!(ad_breaks[0].upid.as_deref(), Some("near"));
+        assert_eq!(upcoming.len(), 1);
+        assert_eq!(upcoming[0].upid.as_deref(), Some("far"));
+        assert_eq!(upcoming[0].duration, 30.0);
+        assert_eq!(
+            upcoming[0].start,
+            mpd.availabilityStartTime.unwrap() + chrono::Duration::seconds(1100)
+        );
+
+        // Static MPDs stitch every break
+        mpd.mpdtype = Some("static".into());
+        let mut ad_breaks = vec![ad_break(1100.0, "far")];
+        assert!(take_upcoming_breaks(&mpd, &mut ad_breaks).is_empty());
+        assert_eq!(ad_breaks.len(), 1);
+    }
+
+    #[test]
+    fn positions_count_period_starts() {
+        let mut mpd = parse_mpd(
+            r#"<MPD xmlns="urn:mpeg:dash:schema:mpd:2011" type="static" mediaPresentationDuration="PT30S">
+  <Period id="a" duration="PT10S"/>
+  <Period id="b" duration="PT20S"/>
+</MPD>"#,
+        )
+        .unwrap();
+        let vmap = vmap(&[TimeOffset::Position(2)]);
+
+        let placed = schedule_ad_breaks(&mut mpd, &vmap, true);
+
+        assert_eq!(mpd.periods.len(), 2);
+        assert_eq!(placed[0].0.period_index, 1);
+        assert_eq!(placed[0].0.presentation_time, 0.0);
+    }
+}
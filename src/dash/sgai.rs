@@ -1,26 +1,78 @@
-//! DASH SGAI (Server-Guided Ad Insertion) via MPD callback EventStream
+//! DASH SGAI (Server-Guided Ad Insertion) via MPD EventStreams
 //!
-//! Implements the DASH event callback mechanism (ISO 23009-1, scheme
-//! `urn:mpeg:dash:event:callback:2015`). When a DASH player encounters
-//! this EventStream, it GETs the URL in the Event's text content.
+//! Two signalling schemes are supported (`DASH_SGAI_SCHEME`):
+//!
+//! - **Callback** (`urn:mpeg:dash:event:callback:2015`, ISO 23009-1): the
+//!   player GETs the URL in the Event's text content, which points to the
+//!   asset-list endpoint returning
+//!   JSON: `{"ASSETS": [{"URI": "...", "DURATION": 15.0}]}`
+//! - **Alternative MPD** (`urn:mpeg:dash:event:alternativeMPD:replace:2025`
+//!   or `...:insert:2025`): each Event carries a `ReplacePresentation` or
+//!   `InsertPresentation` element whose `url` points to a generated ad MPD.
+//!   dash.js and Shaka switch to that MPD for the break and play the ad
+//!   natively. Replace mode skips the content under the break; insert mode
+//!   pauses it.
 //!
 //! In SGAI mode the stitcher does NOT insert ad Periods. Instead it:
 //! 1. Detects SCTE-35 ad breaks (reuses `detect_dash_ad_breaks`)
-//! 2. Injects a callback EventStream in each Period containing an ad break
+//! 2. Injects a signalling EventStream in each Period containing an ad break
 //! 3. Strips original SCTE-35 EventStreams to avoid double-signaling
 //! 4. Rewrites content URLs (same as always)
-//!
-//! The callback URL points to the existing asset-list endpoint which returns
-//! JSON: `{"ASSETS": [{"URI": "...", "DURATION": 15.0}]}`
 
-use crate::dash::cue::{self, DashAdBreak};
+use crate::dash::cue::{self, DashAdBreak, DashSignalType};
+use crate::error::{Result, RitcherError};
 use dash_mpd::{Event, EventStream, MPD};
+use quick_xml::events::{BytesEnd, BytesStart, Event as XmlEvent};
+use quick_xml::{Reader, Writer};
 use std::collections::HashMap;
 use tracing::info;
 
 /// DASH MPD Event callback scheme URI (ISO 23009-1)
 const CALLBACK_SCHEME: &str = "urn:mpeg:dash:event:callback:2015";
 
+/// Alternative MPD scheme URI: the ad replaces the content under the break
+pub const ALTERNATIVE_MPD_REPLACE_SCHEME: &str = "urn:mpeg:dash:event:alternativeMPD:replace:2025";
+
+/// Alternative MPD scheme URI: the ad is inserted and content resumes after it
+pub const ALTERNATIVE_MPD_INSERT_SCHEME: &str = "urn:mpeg:dash:event:alternativeMPD:insert:2025";
+
+/// Timescale of alternative MPD EventStreams (milliseconds)
+///
+/// `maxDuration` and `returnOffset` are expressed in the same unit as the
+/// Event timing, so milliseconds keep sub-second breaks exact.
+const ALTERNATIVE_MPD_TIMESCALE: u64 = 1000;
+
+/// DASH SGAI signalling scheme
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum DashSgaiScheme {
+    /// Callback EventStream pointing to the asset-list JSON
+    #[default]
+    Callback,
+    /// Alternative MPD that replaces the content under the break
+    Replace,
+    /// Alternative MPD inserted before content resumes
+    Insert,
+}
+
+/// Alternative presentation element to attach to a serialized Event
+///
+/// `dash_mpd::Event` cannot hold child elements, so
+/// [`inject_dash_alternative_mpd`] returns these and
+/// [`render_alternative_presentations`] writes them into the serialized MPD.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AlternativePresentation {
+    /// `id` of the Event that carries the element
+    pub event_id: String,
+    /// Element name: `ReplacePresentation` or `InsertPresentation`
+    pub element: &'static str,
+    /// Ad MPD URL
+    pub url: String,
+    /// Maximum duration of the alternative MPD, in milliseconds
+    pub max_duration: u64,
+    /// Offset into the content to return to (replace mode), in milliseconds
+    pub return_offset: Option<u64>,
+}
+
 /// Inject SGAI callback EventStreams for detected ad breaks.
 ///
 /// For each ad break, adds an EventStream with the callback scheme to the
@@ -62,9 +114,10 @@ pub fn inject_dash_callbacks(
         let events: Vec<Event> = breaks
             .iter()
             .map(|(break_idx, ad_break)| {
+                let (break_ref, query) = break_ref(*break_idx, ad_break);
                 let callback_url = format!(
-                    "{}/stitch/{}/asset-list/{}?dur={}",
-                    base_url, session_id, break_idx, ad_break.duration as u64,
+                    "{}/stitch/{}/asset-list/{}?dur={}{}",
+                    base_url, session_id, break_ref, ad_break.duration as u64, query,
                 );
 
                 info!(
@@ -99,6 +152,194 @@ pub fn inject_dash_callbacks(
     );
 }
 
+/// Inject alternative MPD EventStreams for detected ad breaks.
+///
+/// Like [`inject_dash_callbacks`], breaks in the same Period share one
+/// EventStream. Each Event points to the per-break ad MPD endpoint
+/// (`/stitch/{session}/ad-mpd/{break}?dur=`). In replace mode the player
+/// returns to the content `duration` after the break start; in insert mode
+/// it resumes where it left off.
+///
+/// Returns the presentation elements to write with
+/// [`render_alternative_presentations`] after serialization. `Callback`
+/// is not an alternative MPD scheme and injects nothing.
+pub fn inject_dash_alternative_mpd(
+    mpd: &mut MPD,
+    ad_breaks: &[DashAdBreak],
+    session_id: &str,
+    base_url: &str,
+    scheme: DashSgaiScheme,
+) -> Vec<AlternativePresentation> {
+    let (scheme_uri, element) = match scheme {
+        DashSgaiScheme::Replace => (ALTERNATIVE_MPD_REPLACE_SCHEME, "ReplacePresentation"),
+        DashSgaiScheme::Insert => (ALTERNATIVE_MPD_INSERT_SCHEME, "InsertPresentation"),
+        DashSgaiScheme::Callback => return Vec::new(),
+    };
+
+    if ad_breaks.is_empty() {
+        info!("No ad breaks detected, skipping DASH alternative MPD injection");
+        return Vec::new();
+    }
+
+    let mut presentations = Vec::with_capacity(ad_breaks.len());
+    let mut streams: HashMap<usize, EventStream> = HashMap::new();
+
+    for (break_idx, ad_break) in ad_breaks.iter().enumerate() {
+        if ad_break.period_index >= mpd.periods.len() {
+            continue;
+        }
+
+        // Ad break times are non-negative seconds; rounding to whole
+        // milliseconds is intentional.
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let (presentation_ms, duration_ms) = (
+            (ad_break.presentation_time * 1000.0).round() as u64,
+            (ad_break.duration * 1000.0).round() as u64,
+        );
+        let (break_ref, query) = break_ref(break_idx, ad_break);
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let url = format!(
+            "{}/stitch/{}/ad-mpd/{}?dur={}{}",
+            base_url, session_id, break_ref, ad_break.duration as u64, query,
+        );
+        let event_id = format!("ad-break-{}", break_idx);
+
+        info!(
+            "DASH SGAI: injecting {} at Period #{}: duration={}s",
+            element, ad_break.period_index, ad_break.duration
+        );
+
+        streams
+            .entry(ad_break.period_index)
+            .or_insert_with(|| EventStream {
+                schemeIdUri: Some(scheme_uri.to_string()),
+                timescale: Some(ALTERNATIVE_MPD_TIMESCALE),
+                ..Default::default()
+            })
+            .event
+            .push(Event {
+                id: Some(event_id.clone()),
+                presentationTime: Some(presentation_ms),
+                duration: Some(duration_ms),
+                ..Default::default()
+            });
+
+        presentations.push(AlternativePresentation {
+            event_id,
+            element,
+            url,
+            max_duration: duration_ms,
+            return_offset: (scheme == DashSgaiScheme::Replace).then_some(duration_ms),
+        });
+    }
+
+    let period_count = streams.len();
+    for (period_idx, stream) in streams {
+        mpd.periods[period_idx].event_streams.push(stream);
+    }
+
+    info!(
+        "DASH SGAI: injected {} alternative MPD event(s) across {} period(s)",
+        presentations.len(),
+        period_count
+    );
+
+    presentations
+}
+
+/// Break id and extra query parameters of a break's SGAI endpoint URLs
+///
+/// VMAP breaks are addressed by their VMAP index with `vmap=1`, so the
+/// endpoint fills them from the break's own ad source.
+fn break_ref(break_idx: usize, ad_break: &DashAdBreak) -> (usize, &'static str) {
+    match ad_break.signal_type {
+        DashSignalType::Vmap(vmap_index) => (vmap_index, "&vmap=1"),
+        _ => (break_idx, ""),
+    }
+}
+
+/// Write alternative presentation elements into their Events.
+///
+/// Streams the serialized MPD through quick-xml and, for each Event whose
+/// `id` matches a presentation, emits the `ReplacePresentation` or
+/// `InsertPresentation` child element. Everything else is copied verbatim.
+pub fn render_alternative_presentations(
+    xml: &str,
+    presentations: &[AlternativePresentation],
+) -> Result<String> {
+    if presentations.is_empty() {
+        return Ok(xml.to_string());
+    }
+
+    let by_id: HashMap<&str, &AlternativePresentation> = presentations
+        .iter()
+        .map(|p| (p.event_id.as_str(), p))
+        .collect();
+
+    let mut reader = Reader::from_str(xml);
+    let mut writer = Writer::new(Vec::with_capacity(xml.len() + presentations.len() * 200));
+    let write_err =
+        |e: std::io::Error| RitcherError::ConversionError(format!("Failed to write MPD: {}", e));
+
+    loop {
+        let event = reader
+            .read_event()
+            .map_err(|e| RitcherError::MpdParseError(format!("Invalid serialized MPD: {}", e)))?;
+        match event {
+            XmlEvent::Eof => break,
+            XmlEvent::Start(ref e) | XmlEvent::Empty(ref e)
+                if e.local_name().as_ref() == b"Event" =>
+            {
+                let presentation = event_id(e).and_then(|id| by_id.get(id.as_str()).copied());
+                let Some(presentation) = presentation else {
+                    writer.write_event(event.borrow()).map_err(write_err)?;
+                    continue;
+                };
+                let self_closing = matches!(event, XmlEvent::Empty(_));
+                writer
+                    .write_event(XmlEvent::Start(e.borrow()))
+                    .map_err(write_err)?;
+                writer
+                    .write_event(XmlEvent::Empty(presentation_element(presentation)))
+                    .map_err(write_err)?;
+                if self_closing {
+                    writer
+                        .write_event(XmlEvent::End(BytesEnd::new("Event")))
+                        .map_err(write_err)?;
+                }
+            }
+            other => writer.write_event(other).map_err(write_err)?,
+        }
+    }
+
+    String::from_utf8(writer.into_inner()).map_err(|e| {
+        RitcherError::ConversionError(format!("Serialized MPD is not valid UTF-8: {}", e))
+    })
+}
+
+/// `id` attribute of an Event start tag
+fn event_id(e: &BytesStart) -> Option<String> {
+    e.try_get_attribute("id")
+        .ok()
+        .flatten()
+        .and_then(|a| a.unescape_value().ok())
+        .map(|v| v.into_owned())
+}
+
+/// Build the `ReplacePresentation` / `InsertPresentation` element
+fn presentation_element(presentation: &AlternativePresentation) -> BytesStart<'static> {
+    let mut element = BytesStart::new(presentation.element);
+    element.push_attribute(("url", presentation.url.as_str()));
+    element.push_attribute((
+        "maxDuration",
+        presentation.max_duration.to_string().as_str(),
+    ));
+    if let Some(offset) = presentation.return_offset {
+        element.push_attribute(("returnOffset", offset.to_string().as_str()));
+    }
+    element
+}
+
 /// Remove SCTE-35 EventStreams from all Periods to avoid double-signaling.
 ///
 /// Retains any non-SCTE-35 EventStreams (including the callback EventStream
@@ -150,6 +391,7 @@ mod tests {
             duration,
             presentation_time,
             signal_type: DashSignalType::SpliceInsert,
+            upid: None,
         }
     }
 
@@ -302,4 +544,116 @@ mod tests {
         // Only the original SCTE-35 EventStream
         assert_eq!(mpd.periods[0].event_streams.len(), 1);
     }
+
+    #[test]
+    fn test_inject_alternative_mpd_replace() {
+        let mut mpd = make_mpd_with_periods(2);
+        let ad_breaks = vec![make_ad_break(0, 15.5, 30.0), make_ad_break(0, 90.0, 15.0)];
+
+        let presentations = inject_dash_alternative_mpd(
+            &mut mpd,
+            &ad_breaks,
+            "sess",
+            "http://s",
+            DashSgaiScheme::Replace,
+        );
+
+        let stream = &mpd.periods[0].event_streams[1];
+        assert_eq!(
+            stream.schemeIdUri.as_deref(),
+            Some(ALTERNATIVE_MPD_REPLACE_SCHEME)
+        );
+        assert_eq!(stream.timescale, Some(1000));
+        assert_eq!(stream.event.len(), 2);
+        assert_eq!(stream.event[0].presentationTime, Some(15_500));
+        assert_eq!(stream.event[0].duration, Some(30_000));
+        assert_eq!(mpd.periods[1].event_streams.len(), 1);
+
+        assert_eq!(presentations.len(), 2);
+        assert_eq!(presentations[0].element, "ReplacePresentation");
+        assert_eq!(presentations[0].url, "http://s/stitch/sess/ad-mpd/0?dur=30");
+        assert_eq!(presentations[0].return_offset, Some(30_000));
+        assert_eq!(presentations[1].event_id, "ad-break-1");
+    }
+
+    #[test]
+    fn test_inject_alternative_mpd_insert_has_no_return_offset() {
+        let mut mpd = make_mpd_with_periods(1);
+        let ad_breaks = vec![make_ad_break(0, 10.0, 20.0)];
+
+        let presentations = inject_dash_alternative_mpd(
+            &mut mpd,
+            &ad_breaks,
+            "sess",
+            "http://s",
+            DashSgaiScheme::Insert,
+        );
+
+        assert_eq!(
+            mpd.periods[0].event_streams[1].schemeIdUri.as_deref(),
+            Some(ALTERNATIVE_MPD_INSERT_SCHEME)
+        );
+        assert_eq!(presentations[0].element, "InsertPresentation");
+        assert_eq!(presentations[0].max_duration, 20_000);
+        assert!(presentations[0].return_offset.is_none());
+    }
+
+    #[test]
+    fn test_inject_alternative_mpd_callback_is_noop() {
+        let mut mpd = make_mpd_with_periods(1);
+        let ad_breaks = vec![make_ad_break(0, 10.0, 20.0)];
+
+        let presentations = inject_dash_alternative_mpd(
+            &mut mpd,
+            &ad_breaks,
+            "sess",
+            "http://s",
+            DashSgaiScheme::Callback,
+        );
+
+        assert!(presentations.is_empty());
+        assert_eq!(mpd.periods[0].event_streams.len(), 1);
+    }
+
+    #[test]
+    fn test_render_alternative_presentations() {
+        let mut mpd = make_mpd_with_periods(1);
+        let ad_breaks = vec![make_ad_break(0, 10.0, 20.0)];
+        let presentations = inject_dash_alternative_mpd(
+            &mut mpd,
+            &ad_breaks,
+            "sess",
+            "http://s",
+            DashSgaiScheme::Replace,
+        );
+        strip_scte35_event_streams(&mut mpd);
+
+        let xml = render_alternative_presentations(&mpd.to_string(), &presentations).unwrap();
+
+        assert!(xml.contains(ALTERNATIVE_MPD_REPLACE_SCHEME));
+        assert!(xml.contains(
+            r#"<ReplacePresentation url="http://s/stitch/sess/ad-mpd/0?dur=20" maxDuration="20000" returnOffset="20000"/>"#
+        ));
+        // The output must still parse, with the Event kept intact
+        let reparsed = dash_mpd::parse(&xml).expect("Rendered MPD should parse");
+        let event = &reparsed.periods[0].event_streams[0].event[0];
+        assert_eq!(event.id.as_deref(), Some("ad-break-0"));
+        assert_eq!(event.presentationTime, Some(10_000));
+    }
+
+    #[test]
+    fn test_render_alternative_presentations_leaves_other_events() {
+        let xml = r#"<MPD><Period><EventStream schemeIdUri="urn:x"><Event id="other">data</Event></EventStream></Period></MPD>"#;
+        let presentations = vec![AlternativePresentation {
+            event_id: "ad-break-0".to_string(),
+            element: "InsertPresentation",
+            url: "http://s/ad.mpd".to_string(),
+            max_duration: 1000,
+            return_offset: None,
+        }];
+
+        let rendered = render_alternative_presentations(xml, &presentations).unwrap();
+
+        assert_eq!(rendered, xml);
+    }
 }
@@ -49,6 +49,18 @@ pub enum RitcherError {
     #[error("Invalid origin URL: {0}")]
     InvalidOrigin(String),
 
+    /// Request body or parameters failed validation (HTTP 400).
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+
+    /// Requested resource is not held by this instance (HTTP 404).
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    /// Missing or wrong API credentials (HTTP 401).
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+
     /// Origin response body exceeds the size limit (HTTP 502).
     #[error("Origin response too large: {0}")]
     ResponseTooLarge(String),
@@ -115,6 +127,18 @@ impl IntoResponse for RitcherError {
                 tracing::error!("Invalid origin URL: {}", e);
                 (StatusCode::BAD_REQUEST, self.to_string())
             }
+            RitcherError::InvalidRequest(ref e) => {
+                tracing::warn!("Invalid request: {}", e);
+                (StatusCode::BAD_REQUEST, self.to_string())
+            }
+            RitcherError::NotFound(ref e) => {
+                tracing::warn!("Not found: {}", e);
+                (StatusCode::NOT_FOUND, "Not found".to_string())
+            }
+            RitcherError::Unauthorized(ref e) => {
+                tracing::warn!("Unauthorized: {}", e);
+                (StatusCode::UNAUTHORIZED, "Unauthorized".to_string())
+            }
             RitcherError::ResponseTooLarge(ref e) => {
                 tracing::error!("Response too large: {}", e);
                 (
@@ -229,6 +253,27 @@ mod tests {
         );
     }
 
+    #[test]
+    fn invalid_request_returns_400() {
+        let err = RitcherError::InvalidRequest("duration must be positive".to_string());
+        let (status, _) = response_parts(err);
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn not_found_returns_404() {
+        let err = RitcherError::NotFound("shared-120-seg-0.ts".to_string());
+        let (status, _) = response_parts(err);
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn unauthorized_returns_401() {
+        let err = RitcherError::Unauthorized("missing bearer token".to_string());
+        let (status, _) = response_parts(err);
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
     #[test]
     fn playlist_parse_error_returns_422() {
         let err = RitcherError::PlaylistParseError("bad m3u8".to_string());
@@ -1,5 +1,9 @@
-use m3u8_rs::MediaPlaylist;
-use tracing::{debug, info};
+use crate::ad::prefetch::UpcomingBreak;
+use crate::hls::schedule::live_edge;
+use crate::scte35::{self, SegmentationTypes, SpliceAction, SpliceInfo};
+use chrono::{DateTime, Utc};
+use m3u8_rs::{DateRange, MediaPlaylist, MediaSegment};
+use tracing::{debug, info, warn};
 
 /// Represents an ad break detected from CUE tags in the playlist
 #[derive(Debug, Clone, PartialEq)]
@@ -10,6 +14,36 @@ pub struct AdBreak {
     pub end_index: usize,
     /// Duration of the ad break in seconds
     pub duration: f32,
+    /// Segmentation UPID from the SCTE-35 signal, kept for ad-request targeting
+    pub upid: Option<String>,
+}
+
+/// A cue found on a single segment, normalized across tag formats
+#[derive(Debug, PartialEq)]
+enum CueSignal {
+    /// Break start; `duration` is `None` when the cue does not carry one
+    Out {
+        duration: Option<f32>,
+        upid: Option<String>,
+    },
+    /// Break end
+    In,
+    /// Cancels the break in progress
+    Cancel,
+}
+
+/// Ad break being accumulated while scanning segments
+struct OpenBreak {
+    start_index: usize,
+    duration: Option<f32>,
+    upid: Option<String>,
+}
+
+/// Detect ad breaks using the default SCTE-35 segmentation types
+///
+/// See [`detect_ad_breaks_with_types`].
+pub fn detect_ad_breaks(playlist: &MediaPlaylist) -> Vec<AdBreak> {
+    detect_ad_breaks_with_types(playlist, &SegmentationTypes::default())
 }
 
 /// Detect ad breaks from SCTE-35 CUE tags in HLS playlists
@@ -20,62 +54,346 @@ pub struct AdBreak {
 /// - `#EXT-X-CUE-OUT-CONT:{elapsed}/{duration}` — mid-break continuation
 /// - `#EXT-X-CUE-IN` — ad break end
 ///
+/// Binary SCTE-35 carried in `#EXT-X-DATERANGE` (`SCTE35-OUT`, `SCTE35-CMD`,
+/// `SCTE35-IN`), `#EXT-X-SCTE35:CUE=` and `#EXT-OATCLS-SCTE35` is decoded
+/// and classified with `types`, so `time_signal` cues with segmentation
+/// descriptors open and close breaks too. A cue without a duration is
+/// closed by the matching end signal and sized from the segments it spans.
+///
 /// Note: m3u8-rs strips the `#EXT-` prefix from unknown tags, so the tag
 /// field contains e.g. `X-CUE-OUT` (not `EXT-X-CUE-OUT`).
 ///
 /// Returns a vector of AdBreak structs with start/end indices and duration.
-pub fn detect_ad_breaks(playlist: &MediaPlaylist) -> Vec<AdBreak> {
+pub fn detect_ad_breaks_with_types(
+    playlist: &MediaPlaylist,
+    types: &SegmentationTypes,
+) -> Vec<AdBreak> {
+    detect(playlist, types, None)
+}
+
+/// Detect the ad breaks that have started
+///
+/// Like [`detect_ad_breaks_with_types`], except that a `DATERANGE` starting
+/// at or after the playlist's live edge is skipped: it announces a break
+/// that has not begun yet, which [`detect_upcoming_breaks`] hands to the
+/// prefetcher and which is stitched once the playlist reaches its
+/// `START-DATE`. Without prefetching such a break opens at the segment
+/// carrying the tag instead.
+pub fn detect_started_ad_breaks(
+    playlist: &MediaPlaylist,
+    types: &SegmentationTypes,
+) -> Vec<AdBreak> {
+    detect(playlist, types, live_edge(playlist))
+}
+
+/// Detect ad breaks, skipping `DATERANGE`s starting at or after `edge`
+fn detect(
+    playlist: &MediaPlaylist,
+    types: &SegmentationTypes,
+    edge: Option<DateTime<Utc>>,
+) -> Vec<AdBreak> {
     let mut ad_breaks = Vec::new();
-    let mut current_break: Option<(usize, f32)> = None; // (start_index, duration)
+    let mut current_break: Option<OpenBreak> = None;
 
     for (index, segment) in playlist.segments.iter().enumerate() {
-        // Check unknown_tags for CUE markers
-        for tag in &segment.unknown_tags {
-            // Match against tag name directly (m3u8-rs strips #EXT- prefix)
-            // CUE-IN: tag.tag is "X-CUE-IN", rest is None
-            if is_cue_in(&tag.tag) {
-                if let Some((start_idx, duration)) = current_break.take() {
-                    info!("Detected CUE-IN at segment #{}", index);
-                    ad_breaks.push(AdBreak {
-                        start_index: start_idx,
-                        end_index: index,
-                        duration,
-                    });
+        for signal in segment_signals(segment, types, edge) {
+            match signal {
+                CueSignal::In => {
+                    if let Some(open) = current_break.take() {
+                        info!("Detected CUE-IN at segment #{}", index);
+                        ad_breaks.push(close_break(playlist, open, index));
+                    }
                 }
-            }
-            // CUE-OUT-CONT: tag.tag is "X-CUE-OUT-CONT", rest is e.g. "10/30"
-            else if is_cue_out_cont(&tag.tag) {
-                debug!("Detected CUE-OUT-CONT at segment #{}", index);
-            }
-            // CUE-OUT: tag.tag is "X-CUE-OUT", rest is e.g. "30" or "DURATION=30"
-            else if let Some(duration) = parse_cue_out(&tag.tag, tag.rest.as_deref()) {
-                info!(
-                    "Detected CUE-OUT at segment #{}: duration {}s",
-                    index, duration
-                );
-                if current_break.is_none() {
-                    current_break = Some((index, duration));
+                CueSignal::Cancel => {
+                    if let Some(open) = current_break.take() {
+                        info!(
+                            "SCTE-35 cancel at segment #{} drops break started at segment #{}",
+                            index, open.start_index
+                        );
+                    }
+                }
+                CueSignal::Out { duration, upid } => {
+                    info!(
+                        "Detected CUE-OUT at segment #{}: duration {:?}s, upid {:?}",
+                        index, duration, upid
+                    );
+                    match current_break.as_mut() {
+                        None => {
+                            current_break = Some(OpenBreak {
+                                start_index: index,
+                                duration,
+                                upid,
+                            });
+                        }
+                        // Packagers often pair EXT-X-CUE-OUT with the SCTE-35
+                        // payload on the same segment: merge what each carries
+                        Some(open) if open.start_index == index => {
+                            open.duration = open.duration.or(duration);
+                            open.upid = open.upid.take().or(upid);
+                        }
+                        Some(_) => {}
+                    }
                 }
             }
         }
     }
 
     // If we reached the end with an open ad break, close it
-    if let Some((start_idx, duration)) = current_break {
+    if let Some(open) = current_break {
         info!(
             "Ad break started at segment #{} not closed, ending at playlist end",
-            start_idx
+            open.start_index
         );
-        ad_breaks.push(AdBreak {
-            start_index: start_idx,
-            end_index: playlist.segments.len(),
-            duration,
-        });
+        ad_breaks.push(close_break(playlist, open, playlist.segments.len()));
     }
 
     ad_breaks
 }
 
+/// Finish an open break at `end_index`, sizing it from its segments when
+/// the cue carried no duration
+fn close_break(playlist: &MediaPlaylist, open: OpenBreak, end_index: usize) -> AdBreak {
+    let duration = open.duration.unwrap_or_else(|| {
+        playlist.segments[open.start_index..end_index]
+            .iter()
+            .map(|s| s.duration)
+            .sum()
+    });
+    AdBreak {
+        start_index: open.start_index,
+        end_index,
+        duration,
+        upid: open.upid,
+    }
+}
+
+/// Detect breaks announced ahead of their start
+///
+/// An `EXT-X-DATERANGE` opening a break with a `START-DATE` past the
+/// playlist's live edge (the end of its last segment) is a break that has
+/// not begun yet. So is a break opened by the `trailing` cue tags written
+/// after the last segment (see [`trailing_cues`]): it starts at the live
+/// edge. Only breaks with a known duration are returned. Playlists without
+/// `EXT-X-PROGRAM-DATE-TIME` have none.
+///
+/// The splice time of SCTE-35 cues carried in tags is not used: a playlist
+/// gives no PTS to map it onto, so these cues apply where they are placed.
+pub fn detect_upcoming_breaks(
+    playlist: &MediaPlaylist,
+    trailing: Option<&MediaSegment>,
+    types: &SegmentationTypes,
+) -> Vec<UpcomingBreak> {
+    let Some(edge) = live_edge(playlist) else {
+        return Vec::new();
+    };
+    let mut upcoming: Vec<UpcomingBreak> = playlist
+        .segments
+        .iter()
+        .chain(trailing)
+        .filter_map(|segment| segment.daterange.as_ref())
+        .filter(|daterange| is_upcoming(daterange, Some(edge)))
+        .filter_map(|daterange| match daterange_signal(daterange, types)? {
+            CueSignal::Out {
+                duration: Some(duration),
+                upid,
+            } => Some(UpcomingBreak {
+                start: daterange.start_date.with_timezone(&Utc),
+                duration,
+                upid,
+            }),
+            _ => None,
+        })
+        .collect();
+
+    let edge_break = trailing.and_then(|segment| {
+        segment_signals(segment, types, Some(edge))
+            .into_iter()
+            .find_map(|signal| match signal {
+                CueSignal::Out {
+                    duration: Some(duration),
+                    upid,
+                } => Some(UpcomingBreak {
+                    start: edge,
+                    duration,
+                    upid,
+                }),
+                _ => None,
+            })
+    });
+    if let Some(edge_break) = edge_break {
+        info!(
+            "Cue after the last segment opens a {}s break at the live edge",
+            edge_break.duration
+        );
+        upcoming.push(edge_break);
+    }
+    upcoming
+}
+
+/// Cue tags written after a playlist's last segment, as a segment
+///
+/// Packagers write a break's cue (`EXT-X-CUE-OUT`, `EXT-X-SCTE35`, ...) as
+/// soon as the splice is signalled, ahead of the segment it applies to.
+/// m3u8-rs drops tags that no segment follows, so they are read from the
+/// raw playlist. `None` when no tag follows the last segment.
+pub fn trailing_cues(content: &str) -> Option<MediaSegment> {
+    let lines: Vec<&str> = content.lines().map(str::trim).collect();
+    let last_uri = lines
+        .iter()
+        .rposition(|line| !line.is_empty() && !line.starts_with('#'))?;
+    let tags: Vec<&str> = lines[last_uri + 1..]
+        .iter()
+        .copied()
+        .filter(|line| line.starts_with("#EXT") && *line != "#EXT-X-ENDLIST")
+        .collect();
+    if tags.is_empty() {
+        return None;
+    }
+
+    // Re-parse the tags as those of a placeholder segment
+    let synthetic = format!(
+        "#EXTM3U\n#EXT-X-TARGETDURATION:1\n{}\n#EXTINF:1,\nedge.ts\n",
+        tags.join("\n")
+    );
+    match m3u8_rs::parse_media_playlist_res(synthetic.as_bytes()) {
+        Ok(mut playlist) => playlist.segments.pop(),
+        Err(e) => {
+            warn!("Unparseable cue tags after the last segment: {:?}", e);
+            None
+        }
+    }
+}
+
+/// Whether a DATERANGE starts at or after the live edge
+fn is_upcoming(daterange: &DateRange, edge: Option<DateTime<Utc>>) -> bool {
+    edge.is_some_and(|edge| daterange.start_date.with_timezone(&Utc) >= edge)
+}
+
+/// Collect the cues carried by a segment, DATERANGE first, then unknown tags
+/// in playlist order
+///
+/// A DATERANGE starting at or after `edge` is skipped.
+fn segment_signals(
+    segment: &MediaSegment,
+    types: &SegmentationTypes,
+    edge: Option<DateTime<Utc>>,
+) -> Vec<CueSignal> {
+    let mut signals = Vec::new();
+
+    if let Some(daterange) = &segment.daterange
+        && !is_upcoming(daterange, edge)
+    {
+        signals.extend(daterange_signal(daterange, types));
+    }
+
+    for tag in &segment.unknown_tags {
+        // Match against tag name directly (m3u8-rs strips #EXT- prefix)
+        // CUE-IN: tag.tag is "X-CUE-IN", rest is None
+        if is_cue_in(&tag.tag) {
+            signals.push(CueSignal::In);
+        }
+        // CUE-OUT-CONT: tag.tag is "X-CUE-OUT-CONT", rest is e.g. "10/30"
+        else if is_cue_out_cont(&tag.tag) {
+            debug!("Detected CUE-OUT-CONT");
+        }
+        // CUE-OUT: tag.tag is "X-CUE-OUT", rest is e.g. "30" or "DURATION=30"
+        else if let Some(duration) = parse_cue_out(&tag.tag, tag.rest.as_deref()) {
+            signals.push(CueSignal::Out {
+                duration: Some(duration),
+                upid: None,
+            });
+        }
+        // EXT-X-SCTE35: rest is e.g. `CUE="/DA0...",ID="1"`
+        else if tag.tag == "X-SCTE35" {
+            let cue = tag.rest.as_deref().and_then(|r| attribute_value(r, "CUE"));
+            if let Some(info) = cue.and_then(decode_base64_cue) {
+                signals.extend(splice_signal(&info, None, types));
+            }
+        }
+        // EXT-OATCLS-SCTE35: rest is the base64 section
+        else if tag.tag == "OATCLS-SCTE35"
+            && let Some(info) = tag.rest.as_deref().and_then(decode_base64_cue)
+        {
+            signals.extend(splice_signal(&info, None, types));
+        }
+    }
+
+    signals
+}
+
+/// Classify an `EXT-X-DATERANGE` carrying SCTE-35 attributes
+///
+/// `DURATION`/`PLANNED-DURATION` take precedence over the SCTE-35 duration.
+/// An undecodable `SCTE35-OUT` still opens a break, matching how DASH Events
+/// without a parseable payload are treated.
+fn daterange_signal(daterange: &DateRange, types: &SegmentationTypes) -> Option<CueSignal> {
+    let attrs = daterange.other_attributes.as_ref()?;
+    #[allow(clippy::cast_possible_truncation)] // ad break durations fit f32
+    let declared = daterange
+        .duration
+        .or(daterange.planned_duration)
+        .map(|d| d as f32);
+    let decode = |name: &str| {
+        let value = attrs.get(name)?.as_str();
+        let info = scte35::decode_hex(value).and_then(|b| scte35::decode_splice_info_section(&b));
+        if info.is_none() {
+            warn!("Undecodable {} on DATERANGE {}", name, daterange.id);
+        }
+        Some(info)
+    };
+
+    if let Some(info) = decode("SCTE35-OUT") {
+        return match info {
+            Some(info) => splice_signal(&info, declared, types),
+            None => Some(CueSignal::Out {
+                duration: declared,
+                upid: None,
+            }),
+        };
+    }
+    if let Some(Some(info)) = decode("SCTE35-CMD") {
+        return splice_signal(&info, declared, types);
+    }
+    if attrs.contains_key("SCTE35-IN") {
+        return Some(CueSignal::In);
+    }
+    None
+}
+
+/// Map a decoded splice to a cue; `declared` overrides the SCTE-35 duration
+fn splice_signal(
+    info: &SpliceInfo,
+    declared: Option<f32>,
+    types: &SegmentationTypes,
+) -> Option<CueSignal> {
+    match info.action(types) {
+        SpliceAction::BreakStart { duration, upid } => {
+            #[allow(clippy::cast_possible_truncation)] // ad break durations fit f32
+            let duration = declared.or(duration.map(|d| d as f32));
+            Some(CueSignal::Out { duration, upid })
+        }
+        SpliceAction::BreakEnd => Some(CueSignal::In),
+        SpliceAction::Cancel => Some(CueSignal::Cancel),
+        SpliceAction::Ignore => None,
+    }
+}
+
+fn decode_base64_cue(value: &str) -> Option<SpliceInfo> {
+    let info = scte35::decode_base64(value).and_then(|b| scte35::decode_splice_info_section(&b));
+    if info.is_none() {
+        warn!("Undecodable base64 SCTE-35 cue");
+    }
+    info
+}
+
+/// Extract an attribute from an `ATTR=value,ATTR="value"` list
+fn attribute_value<'a>(list: &'a str, name: &str) -> Option<&'a str> {
+    list.split(',').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        (key.trim() == name).then(|| value.trim().trim_matches('"'))
+    })
+}
+
 /// Check if a tag name represents CUE-IN
 ///
 /// m3u8-rs strips `#EXT-` so we check for `X-CUE-IN` and `CUE-IN`
@@ -130,7 +448,7 @@ pub fn is_in_ad_break(segment_index: usize, ad_breaks: &[AdBreak]) -> bool {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use m3u8_rs::{ExtTag, MediaSegment};
+    use m3u8_rs::{ExtTag, QuotedOrUnquoted};
 
     fn create_segment(uri: &str) -> MediaSegment {
         MediaSegment {
@@ -224,7 +542,8 @@ mod tests {
             AdBreak {
                 start_index: 1,
                 end_index: 4,
-                duration: 30.0
+                duration: 30.0,
+                upid: None,
             }
         );
     }
@@ -303,6 +622,7 @@ mod tests {
             start_index: 2,
             end_index: 5,
             duration: 30.0,
+            upid: None,
         }];
 
         assert!(!is_in_ad_break(0, &ad_breaks));
@@ -312,4 +632,258 @@ mod tests {
         assert!(is_in_ad_break(4, &ad_breaks));
         assert!(!is_in_ad_break(5, &ad_breaks));
     }
+
+    /// SCTE-35 2022 §14.1: time_signal, Provider Placement Opportunity Start (0x34, 307s)
+    const TIME_SIGNAL_PO_START: &str =
+        "/DA0AAAAAAAA///wBQb+cr0AUAAeAhxDVUVJSAAAjn/PAAGlmbAICAAAAAAsoKGKNAIAmsnRfg==";
+
+    fn hex_cue(base64: &str) -> String {
+        let bytes = scte35::decode_base64(base64).unwrap();
+        let hex: String = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        format!("0x{}", hex)
+    }
+
+    /// Build a time_signal section with one segmentation descriptor
+    /// (no duration, ASCII UPID type 0x0E) for the given type ID
+    #[allow(clippy::cast_possible_truncation)] // fixture lengths are well under 255
+    fn time_signal_cue(type_id: u8, upid: &[u8]) -> String {
+        let mut descriptor = vec![b'C', b'U', b'E', b'I', 0, 0, 0, 1, 0x7F, 0xBF];
+        descriptor.push(0x0E);
+        descriptor.push(upid.len() as u8);
+        descriptor.extend_from_slice(upid);
+        descriptor.extend_from_slice(&[type_id, 0, 0]);
+
+        let mut section = vec![
+            0xFC, 0x30, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xF0, 0x05, 0x06,
+            0xFE, 0x00, 0x00, 0x00, 0x00,
+        ];
+        section.extend_from_slice(&[0x00, descriptor.len() as u8 + 2, 0x02]);
+        section.push(descriptor.len() as u8);
+        section.extend_from_slice(&descriptor);
+        section.extend_from_slice(&[0, 0, 0, 0]); // CRC (not verified)
+
+        let hex: String = section.iter().map(|b| format!("{:02X}", b)).collect();
+        format!("0x{}", hex)
+    }
+
+    fn create_segment_with_daterange(
+        attrs: &[(&str, &str)],
+        duration: Option<f64>,
+    ) -> MediaSegment {
+        let mut segment = create_segment("segment.ts");
+        segment.daterange = Some(DateRange {
+            id: "splice-1".to_string(),
+            class: None,
+            start_date: chrono::DateTime::parse_from_rfc3339("2026-01-01T00:00:00Z").unwrap(),
+            end_date: None,
+            duration,
+            planned_duration: None,
+            x_prefixed: None,
+            end_on_next: false,
+            other_attributes: Some(
+                attrs
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), QuotedOrUnquoted::Unquoted(v.to_string())))
+                    .collect(),
+            ),
+        });
+        segment
+    }
+
+    #[test]
+    fn test_daterange_time_signal_open_and_close() {
+        let playlist = MediaPlaylist {
+            segments: vec![
+                create_segment("seg0.ts"),
+                create_segment_with_daterange(
+                    &[("SCTE35-OUT", &time_signal_cue(0x34, b"BREAK-7"))],
+                    None,
+                ),
+                create_segment("seg2.ts"),
+                create_segment_with_daterange(&[("SCTE35-IN", &time_signal_cue(0x35, b""))], None),
+                create_segment("seg4.ts"),
+            ],
+            ..Default::default()
+        };
+
+        let ad_breaks = detect_ad_breaks(&playlist);
+
+        // No duration in the cue: sized from the two segments it spans
+        assert_eq!(
+            ad_breaks,
+            vec![AdBreak {
+                start_index: 1,
+                end_index: 3,
+                duration: 20.0,
+                upid: Some("BREAK-7".to_string()),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_daterange_duration_overrides_scte35() {
+        let playlist = MediaPlaylist {
+            segments: vec![
+                create_segment_with_daterange(
+                    &[("SCTE35-OUT", &hex_cue(TIME_SIGNAL_PO_START))],
+                    Some(60.0),
+                ),
+                create_segment("seg1.ts"),
+            ],
+            ..Default::default()
+        };
+
+        let ad_breaks = detect_ad_breaks(&playlist);
+
+        assert_eq!(ad_breaks.len(), 1);
+        assert_eq!(ad_breaks[0].duration, 60.0);
+        assert_eq!(ad_breaks[0].upid.as_deref(), Some("0x000000002CA0A18A"));
+    }
+
+    #[test]
+    fn test_ext_x_scte35_time_signal() {
+        let cue = format!("CUE=\"{}\",ID=\"1\"", TIME_SIGNAL_PO_START);
+        let playlist = MediaPlaylist {
+            segments: vec![
+                create_segment("seg0.ts"),
+                create_segment_with_tag("X-SCTE35", Some(&cue)),
+                create_segment("seg2.ts"),
+            ],
+            ..Default::default()
+        };
+
+        let ad_breaks = detect_ad_breaks(&playlist);
+
+        assert_eq!(ad_breaks.len(), 1);
+        assert_eq!(ad_breaks[0].start_index, 1);
+        assert!((ad_breaks[0].duration - 307.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_oatcls_merges_upid_into_cue_out() {
+        let mut segment = create_segment_with_tag("X-CUE-OUT", Some("30"));
+        segment.unknown_tags.push(ExtTag {
+            tag: "OATCLS-SCTE35".to_string(),
+            rest: Some(TIME_SIGNAL_PO_START.to_string()),
+        });
+        let playlist = MediaPlaylist {
+            segments: vec![
+                segment,
+                create_segment("seg1.ts"),
+                create_segment_with_tag("X-CUE-IN", None),
+            ],
+            ..Default::default()
+        };
+
+        let ad_breaks = detect_ad_breaks(&playlist);
+
+        assert_eq!(ad_breaks.len(), 1);
+        assert_eq!(ad_breaks[0].duration, 30.0);
+        assert_eq!(ad_breaks[0].end_index, 2);
+        assert_eq!(ad_breaks[0].upid.as_deref(), Some("0x000000002CA0A18A"));
+    }
+
+    #[test]
+    fn test_time_signal_configured_types() {
+        let playlist = MediaPlaylist {
+            segments: vec![
+                create_segment_with_daterange(&[("SCTE35-OUT", &time_signal_cue(0x30, b""))], None),
+                create_segment("seg1.ts"),
+            ],
+            ..Default::default()
+        };
+
+        assert_eq!(detect_ad_breaks(&playlist).len(), 1);
+
+        let types = SegmentationTypes {
+            break_start: vec![0x34, 0x36],
+            break_end: vec![0x35, 0x37],
+        };
+        assert!(detect_ad_breaks_with_types(&playlist, &types).is_empty());
+    }
+
+    #[test]
+    fn test_non_ad_time_signal_ignored() {
+        // 0x10 Program Start must not open a break
+        let playlist = MediaPlaylist {
+            segments: vec![
+                create_segment_with_daterange(&[("SCTE35-CMD", &time_signal_cue(0x10, b""))], None),
+                create_segment("seg1.ts"),
+            ],
+            ..Default::default()
+        };
+
+        assert!(detect_ad_breaks(&playlist).is_empty());
+    }
+
+    #[test]
+    fn test_upcoming_daterange_is_not_a_break_yet() {
+        // Three 10s segments ending at 2026-01-01T00:00:00Z, where the
+        // DATERANGE starts
+        let mut first = create_segment("seg0.ts");
+        first.program_date_time =
+            Some(chrono::DateTime::parse_from_rfc3339("2025-12-31T23:59:30Z").unwrap());
+        let playlist = MediaPlaylist {
+            segments: vec![
+                first,
+                create_segment("seg1.ts"),
+                create_segment_with_daterange(
+                    &[("SCTE35-OUT", &time_signal_cue(0x34, b""))],
+                    Some(30.0),
+                ),
+            ],
+            ..Default::default()
+        };
+
+        let types = SegmentationTypes::default();
+        assert!(detect_started_ad_breaks(&playlist, &types).is_empty());
+        let upcoming = detect_upcoming_breaks(&playlist, None, &types);
+        assert_eq!(upcoming.len(), 1);
+        assert_eq!(upcoming[0].duration, 30.0);
+        assert_eq!(upcoming[0].start.to_rfc3339(), "2026-01-01T00:00:00+00:00");
+
+        // Without prefetching the break opens at the segment carrying it
+        let ad_breaks = detect_ad_breaks(&playlist);
+        assert_eq!(ad_breaks.len(), 1);
+        assert_eq!(ad_breaks[0].start_index, 2);
+    }
+
+    #[test]
+    fn test_cue_out_after_the_last_segment_is_upcoming() {
+        let content = "#EXTM3U
+#EXT-X-TARGETDURATION:10
+#EXT-X-PROGRAM-DATE-TIME:2025-12-31T23:59:40Z
+#EXTINF:10.0,
+seg0.ts
+#EXTINF:10.0,
+seg1.ts
+#EXT-X-CUE-OUT:30
+";
+        let playlist = m3u8_rs::parse_media_playlist_res(content.as_bytes()).unwrap();
+        let trailing = trailing_cues(content).expect("cue after the last segment");
+
+        // The parser drops the cue: no break has started yet
+        assert!(detect_ad_breaks(&playlist).is_empty());
+        let upcoming =
+            detect_upcoming_breaks(&playlist, Some(&trailing), &SegmentationTypes::default());
+        assert_eq!(upcoming.len(), 1);
+        assert_eq!(upcoming[0].duration, 30.0);
+        assert_eq!(upcoming[0].start.to_rfc3339(), "2026-01-01T00:00:00+00:00");
+    }
+
+    #[test]
+    fn test_trailing_cues_need_tags_after_the_last_segment() {
+        assert!(trailing_cues("#EXTM3U\n#EXTINF:10.0,\nseg0.ts\n").is_none());
+        assert!(trailing_cues("#EXTM3U\n#EXTINF:10.0,\nseg0.ts\n#EXT-X-ENDLIST\n").is_none());
+
+        let segment = trailing_cues("#EXTM3U\n#EXTINF:10.0,\nseg0.ts\n#EXT-X-CUE-IN\n").unwrap();
+        assert_eq!(segment.unknown_tags[0].tag, "X-CUE-IN");
+    }
+
+    #[test]
+    fn test_attribute_value() {
+        assert_eq!(attribute_value(r#"CUE="abc",ID="1""#, "CUE"), Some("abc"));
+        assert_eq!(attribute_value(r#"ID=2,TYPE=0x34"#, "TYPE"), Some("0x34"));
+        assert_eq!(attribute_value(r#"ID=2"#, "CUE"), None);
+    }
 }
@@ -9,6 +9,7 @@
 //! AVPlayer) fetches ad content directly from the ad CDN via the X-ASSET-LIST
 //! URL and handles playback client-side.
 
+use crate::ad::schedule::{BreakPosition, ScheduledBreak};
 use crate::hls::cue::AdBreak;
 use chrono::{DateTime, FixedOffset, TimeZone};
 use m3u8_rs::{DateRange, MediaPlaylist, QuotedOrUnquoted};
@@ -105,33 +106,13 @@ pub fn inject_interstitials(
             start_index, ad_break.duration, asset_list_url
         );
 
-        let mut x_prefixed = HashMap::new();
-        x_prefixed.insert(
-            "X-ASSET-LIST".to_string(),
-            QuotedOrUnquoted::Quoted(asset_list_url),
-        );
-        // X-RESUME-OFFSET=0 — resume content at the break point (no gap)
-        x_prefixed.insert(
-            "X-RESUME-OFFSET".to_string(),
-            QuotedOrUnquoted::Unquoted("0".to_string()),
-        );
-        // X-RESTRICT — prevent the player from allowing skip/seek past the ad
-        x_prefixed.insert(
-            "X-RESTRICT".to_string(),
-            QuotedOrUnquoted::Quoted("SKIP,JUMP".to_string()),
-        );
-
-        let daterange = DateRange {
-            id: format!("ad-break-{}", break_idx),
-            class: Some("com.apple.hls.interstitial".to_string()),
+        let daterange = interstitial_daterange(
+            format!("ad-break-{}", break_idx),
             start_date,
-            end_date: None,
-            duration: Some(ad_break.duration as f64),
-            planned_duration: None,
-            x_prefixed: Some(x_prefixed),
-            end_on_next: false,
-            other_attributes: None,
-        };
+            ad_break.duration,
+            asset_list_url,
+            None,
+        );
 
         playlist.segments[start_index].daterange = Some(daterange);
     }
@@ -140,6 +121,120 @@ pub fn inject_interstitials(
     remove_cue_tags(playlist);
 }
 
+/// Inject EXT-X-DATERANGE interstitial markers for scheduled (VMAP) breaks.
+///
+/// Like [`inject_interstitials`], but each break sits on a segment
+/// boundary instead of replacing content. Pre-rolls carry `CUE="PRE"` and
+/// post-rolls `CUE="POST"`; a post-roll hangs off the last segment with a
+/// START-DATE at the end of the playlist. The asset-list URL names the
+/// VMAP break (`vmap=1`, break id = VMAP index) so the asset-list handler
+/// fills it from the break's own ad source.
+///
+/// Call `ensure_program_date_time` before this function.
+pub fn inject_scheduled_interstitials(
+    playlist: &mut MediaPlaylist,
+    breaks: &[(AdBreak, ScheduledBreak)],
+    session_id: &str,
+    base_url: &str,
+) {
+    let Some(last_index) = playlist.segments.len().checked_sub(1) else {
+        return;
+    };
+
+    for (ad_break, scheduled) in breaks {
+        let (segment_index, start_date, position) = if ad_break.start_index > last_index {
+            let end = compute_pdt_at(playlist, last_index).map(|pdt| {
+                // Segment durations are positive f32 seconds; ms fit in i64
+                #[allow(clippy::cast_possible_truncation)]
+                let duration_ms = (playlist.segments[last_index].duration * 1000.0) as i64;
+                pdt + chrono::Duration::milliseconds(duration_ms)
+            });
+            (last_index, end, BreakPosition::Post)
+        } else {
+            (
+                ad_break.start_index,
+                compute_pdt_at(playlist, ad_break.start_index),
+                scheduled.position,
+            )
+        };
+
+        let Some(start_date) = start_date else {
+            info!(
+                "SGAI: No PDT available for segment {} — skipping scheduled interstitial",
+                segment_index
+            );
+            continue;
+        };
+
+        if playlist.segments[segment_index].daterange.is_some() {
+            info!(
+                "SGAI: segment {} already carries an interstitial — skipping VMAP break {}",
+                segment_index, scheduled.vmap_index
+            );
+            continue;
+        }
+
+        let asset_list_url = format!(
+            "{}/stitch/{}/asset-list/{}?dur={}&vmap=1",
+            base_url, session_id, scheduled.vmap_index, ad_break.duration
+        );
+
+        info!(
+            "SGAI: Injecting scheduled interstitial at segment #{} ({:?}): asset-list={}",
+            segment_index, position, asset_list_url
+        );
+
+        playlist.segments[segment_index].daterange = Some(interstitial_daterange(
+            format!("vmap-break-{}", scheduled.vmap_index),
+            start_date,
+            ad_break.duration,
+            asset_list_url,
+            position.cue(),
+        ));
+    }
+}
+
+/// Build a `com.apple.hls.interstitial` DateRange for one break
+fn interstitial_daterange(
+    id: String,
+    start_date: DateTime<FixedOffset>,
+    duration: f32,
+    asset_list_url: String,
+    cue: Option<&str>,
+) -> DateRange {
+    let mut x_prefixed = HashMap::new();
+    x_prefixed.insert(
+        "X-ASSET-LIST".to_string(),
+        QuotedOrUnquoted::Quoted(asset_list_url),
+    );
+    // X-RESUME-OFFSET=0 — resume content at the break point (no gap)
+    x_prefixed.insert(
+        "X-RESUME-OFFSET".to_string(),
+        QuotedOrUnquoted::Unquoted("0".to_string()),
+    );
+    // X-RESTRICT — prevent the player from allowing skip/seek past the ad
+    x_prefixed.insert(
+        "X-RESTRICT".to_string(),
+        QuotedOrUnquoted::Quoted("SKIP,JUMP".to_string()),
+    );
+
+    // CUE="PRE"/"POST" — play before the content starts or after it ends
+    let other_attributes = cue
+        .map(|cue| HashMap::from([("CUE".to_string(), QuotedOrUnquoted::Quoted(cue.to_string()))]));
+
+    DateRange {
+        id,
+        class: Some("com.apple.hls.interstitial".to_string()),
+        start_date,
+        end_date: None,
+        duration: Some(duration as f64),
+        planned_duration: None,
+        x_prefixed: Some(x_prefixed),
+        end_on_next: false,
+        other_attributes,
+    }
+}
+
 /// Remove SCTE-35 CUE tags from all segment unknown_tags.
 fn remove_cue_tags(playlist: &mut MediaPlaylist) {
     for seg in playlist.segments.iter_mut() {
@@ -278,6 +373,7 @@ mod tests {
             start_index: 1,
             end_index: 3,
             duration: 30.0,
+            upid: None,
         }];
 
         inject_interstitials(&mut playlist, &ad_breaks, "sess-1", "http://localhost:3000");
@@ -309,11 +405,13 @@ mod tests {
                 start_index: 1,
                 end_index: 2,
                 duration: 30.0,
+                upid: None,
             },
             AdBreak {
                 start_index: 4,
                 end_index: 5,
                 duration: 60.0,
+                upid: None,
             },
         ];
 
@@ -353,6 +451,7 @@ mod tests {
             start_index: 1,
             end_index: 2,
             duration: 30.0,
+            upid: None,
         }];
 
         inject_interstitials(&mut playlist, &ad_breaks, "sess-3", "http://localhost:3000");
@@ -382,6 +481,7 @@ mod tests {
             start_index: 1,
             end_index: 2,
             duration: 30.0,
+            upid: None,
         }];
 
         inject_interstitials(
@@ -425,6 +525,7 @@ mod tests {
             start_index: 1,
             end_index: 2,
             duration: 30.0,
+            upid: None,
         }];
 
         inject_interstitials(
@@ -464,4 +565,71 @@ mod tests {
         let pdt2 = compute_pdt_at(&playlist, 2).unwrap();
         assert_eq!((pdt2 - base).num_seconds(), 20);
     }
+
+    #[test]
+    fn inject_scheduled_interstitials_sets_pre_and_post_cues() {
+        use crate::ad::schedule::{BreakPosition, ScheduledBreak};
+        use crate::ad::vast::VmapAdSource;
+
+        let mut playlist = make_playlist(vec![make_segment(10.0), make_segment(10.0)]);
+        ensure_program_date_time(&mut playlist);
+
+        let scheduled = |vmap_index, index, position| {
+            (
+                AdBreak {
+                    start_index: index,
+                    end_index: index,
+                    duration: 30.0,
+                    upid: None,
+                },
+                ScheduledBreak {
+                    vmap_index,
+                    offset: 0.0,
+                    position,
+                    source: VmapAdSource::AdTagUri("https://ads.example.com".into()),
+                },
+            )
+        };
+        let breaks = vec![
+            scheduled(0, 0, BreakPosition::Pre),
+            scheduled(2, 2, BreakPosition::Post),
+        ];
+
+        inject_scheduled_interstitials(&mut playlist, &breaks, "s1", "http://stitcher");
+
+        let pre = playlist.segments[0].daterange.as_ref().unwrap();
+        assert_eq!(pre.id, "vmap-break-0");
+        assert_eq!(
+            pre.other_attributes
+                .as_ref()
+                .unwrap()
+                .get("CUE")
+                .unwrap()
+                .as_str(),
+            "PRE"
+        );
+        assert_eq!(
+            pre.x_prefixed
+                .as_ref()
+                .unwrap()
+                .get("X-ASSET-LIST")
+                .unwrap()
+                .as_str(),
+            "http://stitcher/stitch/s1/asset-list/0?dur=30&vmap=1"
+        );
+
+        // The post-roll hangs off the last segment, dated at the playlist end
+        let post = playlist.segments[1].daterange.as_ref().unwrap();
+        assert_eq!(post.id, "vmap-break-2");
+        assert_eq!(
+            post.other_attributes
+                .as_ref()
+                .unwrap()
+                .get("CUE")
+                .unwrap()
+                .as_str(),
+            "POST"
+        );
+        assert_eq!((post.start_date - pre.start_date).num_seconds(), 20);
+    }
 }
@@ -2,3 +2,4 @@ pub mod cue;
 pub mod interstitial;
 pub mod ll_hls;
 pub mod parser;
+pub mod schedule;
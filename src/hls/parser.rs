@@ -77,7 +77,7 @@ pub fn rewrite_content_urls(
 ///
 /// Example transformation:
 /// - Input:  `720p/playlist.m3u8`
-/// - Output: `{base_url}/stitch/{session_id}/playlist.m3u8?origin={origin_base}/720p/playlist.m3u8`
+/// - Output: `{base_url}/stitch/{session_id}/playlist.m3u8?origin={origin_base}/720p/playlist.m3u8&bw={bandwidth}`
 pub fn rewrite_master_urls(
     mut playlist: Playlist,
     session_id: &str,
@@ -97,10 +97,11 @@ pub fn rewrite_master_urls(
                 format!("{}/{}", origin_base, variant.uri)
             };
 
-            // Rewrite to route through stitcher
+            // Rewrite to route through stitcher; the bandwidth lets the
+            // playlist handler pick the matching slate rendition
             variant.uri = format!(
-                "{}/stitch/{}/playlist.m3u8?origin={}",
-                base_url, session_id, absolute_url
+                "{}/stitch/{}/playlist.m3u8?origin={}&bw={}",
+                base_url, session_id, absolute_url, variant.bandwidth
             );
 
             info!("Rewrote variant: {} → {}", original_uri, variant.uri);
@@ -148,6 +149,27 @@ pub fn rewrite_master_urls(
     Ok(playlist)
 }
 
+/// Append a `name=value` query parameter to the stitcher URLs of a
+/// rewritten master playlist
+///
+/// Carries request-level parameters (such as the schedule `channel`) from
+/// the master playlist request to every variant and rendition request.
+pub fn append_master_query_param(playlist: &mut Playlist, name: &str, value: &str) {
+    if let Playlist::MasterPlaylist(master) = playlist {
+        let param = format!("&{}={}", name, value);
+        for variant in master.variants.iter_mut() {
+            variant.uri.push_str(&param);
+        }
+        for uri in master
+            .alternatives
+            .iter_mut()
+            .filter_map(|a| a.uri.as_mut())
+        {
+            uri.push_str(&param);
+        }
+    }
+}
+
 /// Serialize playlist to string
 pub fn serialize_playlist(playlist: Playlist) -> Result<String> {
     let mut output = Vec::new();
@@ -195,11 +217,11 @@ mod tests {
             assert_eq!(master.variants.len(), 2);
             assert_eq!(
                 master.variants[0].uri,
-                "http://stitcher.example.com/stitch/session-1/playlist.m3u8?origin=http://cdn.example.com/stream/720p/playlist.m3u8"
+                "http://stitcher.example.com/stitch/session-1/playlist.m3u8?origin=http://cdn.example.com/stream/720p/playlist.m3u8&bw=2000000"
             );
             assert_eq!(
                 master.variants[1].uri,
-                "http://stitcher.example.com/stitch/session-1/playlist.m3u8?origin=http://cdn.example.com/stream/1080p/playlist.m3u8"
+                "http://stitcher.example.com/stitch/session-1/playlist.m3u8?origin=http://cdn.example.com/stream/1080p/playlist.m3u8&bw=5000000"
             );
         } else {
             panic!("Expected MasterPlaylist");
@@ -228,7 +250,7 @@ mod tests {
         if let Playlist::MasterPlaylist(master) = result {
             assert_eq!(
                 master.variants[0].uri,
-                "http://stitcher.example.com/stitch/session-1/playlist.m3u8?origin=http://other-cdn.example.com/720p/playlist.m3u8"
+                "http://stitcher.example.com/stitch/session-1/playlist.m3u8?origin=http://other-cdn.example.com/720p/playlist.m3u8&bw=2000000"
             );
         } else {
             panic!("Expected MasterPlaylist");
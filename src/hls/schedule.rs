@@ -0,0 +1,411 @@
+//! Place scheduled ad breaks into HLS playlists
+//!
+//! VMAP breaks in VOD playlists are inserted between content segments
+//! rather than replacing them, so each becomes a zero-length [`AdBreak`]
+//! (`start_index == end_index`) at the segment boundary closest to its
+//! offset. Channel schedule breaks in live playlists are matched against
+//! `EXT-X-PROGRAM-DATE-TIME` and replace the content segments they cover,
+//! exactly like a CUE-OUT/CUE-IN pair. The interleaver and interstitial
+//! injector then handle both like any other break.
+
+use crate::ad::break_schedule::ChannelBreak;
+use crate::ad::prefetch::UpcomingBreak;
+use crate::ad::schedule::{self, DEFAULT_BREAK_DURATION, ScheduledBreak};
+use crate::ad::vast::VmapResponse;
+use crate::hls::cue::AdBreak;
+use chrono::{DateTime, FixedOffset, Utc};
+use m3u8_rs::MediaPlaylist;
+use tracing::{debug, info, warn};
+
+/// Break edges within this many seconds of a segment boundary snap to it
+const SNAP_SECS: f64 = 0.5;
+
+/// Resolve VMAP breaks against a VOD playlist
+///
+/// Ad opportunities for `#n` offsets are the start of the playlist, every
+/// `EXT-X-DISCONTINUITY` and the end. Mid-rolls snap forward to the next
+/// segment boundary; breaks that land on the same boundary as an earlier
+/// one are dropped. Returns the breaks alongside their scheduled source.
+pub fn schedule_ad_breaks(
+    playlist: &MediaPlaylist,
+    vmap: &VmapResponse,
+) -> Vec<(AdBreak, ScheduledBreak)> {
+    let mut starts = Vec::with_capacity(playlist.segments.len());
+    let mut elapsed = 0.0_f64;
+    let mut opportunities = vec![0.0];
+    for (index, segment) in playlist.segments.iter().enumerate() {
+        if index > 0 && segment.discontinuity {
+            opportunities.push(elapsed);
+        }
+        starts.push(elapsed);
+        elapsed += f64::from(segment.duration);
+    }
+    opportunities.push(elapsed);
+
+    let mut placed: Vec<(AdBreak, ScheduledBreak)> = Vec::new();
+    for scheduled in schedule::resolve_breaks(vmap, elapsed, &opportunities) {
+        // Boundary at or after the offset; a millisecond of slack absorbs
+        // rounding in the segment durations
+        let index = starts
+            .iter()
+            .position(|&start| start >= scheduled.offset - 0.001)
+            .unwrap_or(starts.len());
+        let index = match scheduled.position {
+            schedule::BreakPosition::Pre => 0,
+            schedule::BreakPosition::Post => starts.len(),
+            schedule::BreakPosition::Mid => index,
+        };
+
+        if placed.iter().any(|(b, _)| b.start_index == index) {
+            warn!(
+                "VMAP: break {} shares segment boundary {} with an earlier break, skipping",
+                scheduled.vmap_index, index
+            );
+            continue;
+        }
+
+        placed.push((
+            AdBreak {
+                start_index: index,
+                end_index: index,
+                duration: DEFAULT_BREAK_DURATION,
+                upid: None,
+            },
+            scheduled,
+        ));
+    }
+
+    info!(
+        "VMAP: placed {} break(s) in a {:.1}s playlist",
+        placed.len(),
+        elapsed
+    );
+    placed
+}
+
+/// Merge channel schedule breaks into the breaks detected from CUE tags
+///
+/// Each break starts on the first segment whose program date-time is at or
+/// after the break start (snapping within [`SNAP_SECS`]) and ends on the
+/// first segment at or after its end, or at the end of the playlist when
+/// the break runs past it. Breaks that started before the playlist window,
+/// overlap a detected break or cover no segment are dropped. Playlists
+/// without `EXT-X-PROGRAM-DATE-TIME` cannot be matched and are left as is.
+pub fn merge_channel_breaks(
+    playlist: &MediaPlaylist,
+    ad_breaks: &mut Vec<AdBreak>,
+    scheduled: &[ChannelBreak],
+) {
+    if scheduled.is_empty() {
+        return;
+    }
+    let Some(starts) = segment_start_times(playlist) else {
+        debug!("Playlist has no EXT-X-PROGRAM-DATE-TIME, cannot place scheduled breaks");
+        return;
+    };
+
+    let boundary_at_or_after = |time: DateTime<Utc>| {
+        starts
+            .iter()
+            .position(|&start| seconds_between(time, start) >= -SNAP_SECS)
+    };
+
+    for ad_break in scheduled {
+        let Some(start_index) = boundary_at_or_after(ad_break.start) else {
+            continue;
+        };
+        if start_index == 0 && seconds_between(ad_break.start, starts[0]) > SNAP_SECS {
+            debug!(
+                "Scheduled break {} started before the playlist window, skipping",
+                ad_break.id
+            );
+            continue;
+        }
+        let end_index = boundary_at_or_after(ad_break.end()).unwrap_or(starts.len());
+        if end_index <= start_index {
+            continue;
+        }
+        if ad_breaks
+            .iter()
+            .any(|b| b.start_index < end_index && start_index < b.end_index)
+        {
+            debug!(
+                "Scheduled break {} overlaps a signalled break, skipping",
+                ad_break.id
+            );
+            continue;
+        }
+
+        info!(
+            "Scheduled break {} placed on segments {}..{}",
+            ad_break.id, start_index, end_index
+        );
+        #[allow(clippy::cast_possible_truncation)] // ad break durations fit f32
+        ad_breaks.push(AdBreak {
+            start_index,
+            end_index,
+            duration: ad_break.duration as f32,
+            upid: None,
+        });
+    }
+
+    ad_breaks.sort_by_key(|b| b.start_index);
+}
+
+/// Channel schedule breaks that start past the playlist's live edge
+///
+/// These are not in the playlist yet; see [`crate::ad::prefetch`].
+/// Playlists without `EXT-X-PROGRAM-DATE-TIME` have none.
+pub fn upcoming_channel_breaks(
+    playlist: &MediaPlaylist,
+    scheduled: &[ChannelBreak],
+) -> Vec<UpcomingBreak> {
+    let Some(edge) = live_edge(playlist) else {
+        return Vec::new();
+    };
+    #[allow(clippy::cast_possible_truncation)] // ad break durations fit f32
+    scheduled
+        .iter()
+        .filter(|ad_break| seconds_between(edge, ad_break.start) > SNAP_SECS)
+        .map(|ad_break| UpcomingBreak {
+            start: ad_break.start,
+            duration: ad_break.duration as f32,
+            upid: None,
+        })
+        .collect()
+}
+
+/// Wall-clock end of the playlist's last segment
+pub fn live_edge(playlist: &MediaPlaylist) -> Option<DateTime<Utc>> {
+    let starts = segment_start_times(playlist)?;
+    let last = playlist.segments.last()?;
+    Some(*starts.last()? + millis(f64::from(last.duration)))
+}
+
+/// Wall-clock start of every segment, from the playlist's program date-times
+///
+/// Segments before the first `EXT-X-PROGRAM-DATE-TIME` are timed backwards
+/// from it; later ones run forward from the most recent tag.
+pub fn segment_start_times(playlist: &MediaPlaylist) -> Option<Vec<DateTime<Utc>>> {
+    let (anchor_index, anchor) = playlist
+        .segments
+        .iter()
+        .enumerate()
+        .find_map(|(i, s)| s.program_date_time.map(|pdt| (i, pdt)))?;
+
+    let lead: f64 = playlist.segments[..anchor_index]
+        .iter()
+        .map(|s| f64::from(s.duration))
+        .sum();
+    let mut current: DateTime<FixedOffset> = anchor - millis(lead);
+    let mut starts = Vec::with_capacity(playlist.segments.len());
+    for segment in &playlist.segments {
+        if let Some(pdt) = segment.program_date_time {
+            current = pdt;
+        }
+        starts.push(current.with_timezone(&Utc));
+        current += millis(f64::from(segment.duration));
+    }
+    Some(starts)
+}
+
+/// Seconds from `from` to `to` (negative when `to` is earlier)
+fn seconds_between(from: DateTime<Utc>, to: DateTime<Utc>) -> f64 {
+    (to - from).num_milliseconds() as f64 / 1000.0
+}
+
+fn millis(secs: f64) -> chrono::Duration {
+    // Segment durations are small positive seconds; ms values fit in i64
+    #[allow(clippy::cast_possible_truncation)]
+    chrono::Duration::milliseconds((secs * 1000.0).round() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ad::schedule::BreakPosition;
+    use crate::ad::vast::{TimeOffset, VmapAdBreak, VmapAdSource};
+    use m3u8_rs::MediaSegment;
+
+    fn playlist(durations: &[f32], discontinuity_at: &[usize]) -> MediaPlaylist {
+        MediaPlaylist {
+            segments: durations
+                .iter()
+                .enumerate()
+                .map(|(i, &duration)| MediaSegment {
+                    uri: format!("seg{}.ts", i),
+                    duration,
+                    discontinuity: discontinuity_at.contains(&i),
+                    ..Default::default()
+                })
+                .collect(),
+            end_list: true,
+            ..Default::default()
+        }
+    }
+
+    fn vmap(offsets: &[TimeOffset]) -> VmapResponse {
+        VmapResponse {
+            version: "1.0".into(),
+            ad_breaks: offsets
+                .iter()
+                .map(|&time_offset| VmapAdBreak {
+                    break_id: None,
+                    break_type: "linear".into(),
+                    time_offset,
+                    ad_source: Some(VmapAdSource::AdTagUri("https://ads.example.com".into())),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn places_pre_mid_and_post_rolls_on_segment_boundaries() {
+        let playlist = playlist(&[10.0; 6], &[]);
+        let vmap = vmap(&[
+            TimeOffset::Start,
+            TimeOffset::Seconds(25.0),
+            TimeOffset::End,
+        ]);
+
+        let placed = schedule_ad_breaks(&playlist, &vmap);
+
+        let summary: Vec<(usize, usize, BreakPosition)> = placed
+            .iter()
+            .map(|(b, s)| (b.start_index, b.end_index, s.position))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (0, 0, BreakPosition::Pre),
+                (3, 3, BreakPosition::Mid),
+                (6, 6, BreakPosition::Post),
+            ]
+        );
+    }
+
+    #[test]
+    fn positions_count_discontinuities() {
+        let playlist = playlist(&[10.0; 6], &[2, 4]);
+        let vmap = vmap(&[TimeOffset::Position(3), TimeOffset::Position(4)]);
+
+        let placed = schedule_ad_breaks(&playlist, &vmap);
+
+        assert_eq!(placed.len(), 2);
+        assert_eq!(placed[0].0.start_index, 4);
+        assert_eq!(placed[0].1.position, BreakPosition::Mid);
+        assert_eq!(placed[1].0.start_index, 6);
+        assert_eq!(placed[1].1.position, BreakPosition::Post);
+    }
+
+    fn live_playlist(count: usize, pdt: &str) -> MediaPlaylist {
+        let mut playlist = playlist(&vec![6.0; count], &[]);
+        playlist.end_list = false;
+        playlist.segments[1].program_date_time = Some(DateTime::parse_from_rfc3339(pdt).unwrap());
+        playlist
+    }
+
+    fn channel_break(id: &str, start: &str, duration: f64) -> ChannelBreak {
+        ChannelBreak {
+            id: id.to_string(),
+            start: DateTime::parse_from_rfc3339(start)
+                .unwrap()
+                .with_timezone(&Utc),
+            duration,
+        }
+    }
+
+    #[test]
+    fn channel_breaks_match_program_date_time() {
+        // Segment 0 starts at 11:59:54, segment 1 at 12:00:00, 6s each
+        let playlist = live_playlist(10, "2026-10-18T12:00:00Z");
+        let mut ad_breaks = Vec::new();
+
+        merge_channel_breaks(
+            &playlist,
+            &mut ad_breaks,
+            &[
+                // Mid-segment start snaps forward to 12:00:12 (segment 3)
+                channel_break("a", "2026-10-18T12:00:10Z", 12.0),
+                // Runs past the end of the window
+                channel_break("b", "2026-10-18T12:00:42Z", 30.0),
+                // Before the window / after it
+                channel_break("old", "2026-10-18T11:50:00Z", 30.0),
+                channel_break("later", "2026-10-18T13:00:00Z", 30.0),
+            ],
+        );
+
+        let spans: Vec<(usize, usize, f32)> = ad_breaks
+            .iter()
+            .map(|b| (b.start_index, b.end_index, b.duration))
+            .collect();
+        assert_eq!(spans, vec![(3, 5, 12.0), (8, 10, 30.0)]);
+    }
+
+    #[test]
+    fn upcoming_channel_breaks_start_past_the_live_edge() {
+        // Live edge at 12:00:54
+        let playlist = live_playlist(10, "2026-10-18T12:00:00Z");
+
+        let upcoming = upcoming_channel_breaks(
+            &playlist,
+            &[
+                channel_break("in-window", "2026-10-18T12:00:30Z", 30.0),
+                channel_break("next", "2026-10-18T12:01:30Z", 60.0),
+            ],
+        );
+
+        assert_eq!(upcoming.len(), 1);
+        assert_eq!(upcoming[0].duration, 60.0);
+        assert_eq!(upcoming[0].start.to_rfc3339(), "2026-10-18T12:01:30+00:00");
+    }
+
+    #[test]
+    fn channel_breaks_yield_to_signalled_breaks() {
+        let playlist = live_playlist(10, "2026-10-18T12:00:00+02:00");
+        let mut ad_breaks = vec![AdBreak {
+            start_index: 2,
+            end_index: 4,
+            duration: 12.0,
+            upid: None,
+        }];
+
+        merge_channel_breaks(
+            &playlist,
+            &mut ad_breaks,
+            &[
+                channel_break("overlap", "2026-10-18T10:00:12Z", 12.0),
+                channel_break("after", "2026-10-18T10:00:24Z", 6.0),
+            ],
+        );
+
+        let spans: Vec<(usize, usize)> = ad_breaks
+            .iter()
+            .map(|b| (b.start_index, b.end_index))
+            .collect();
+        assert_eq!(spans, vec![(2, 4), (5, 6)]);
+    }
+
+    #[test]
+    fn channel_breaks_need_program_date_time() {
+        let playlist = playlist(&[6.0; 4], &[]);
+        let mut ad_breaks = Vec::new();
+        merge_channel_breaks(
+            &playlist,
+            &mut ad_breaks,
+            &[channel_break("a", "2026-10-18T12:00:00Z", 6.0)],
+        );
+        assert!(ad_breaks.is_empty());
+    }
+
+    #[test]
+    fn drops_breaks_sharing_a_boundary() {
+        let playlist = playlist(&[10.0; 3], &[]);
+        let vmap = vmap(&[TimeOffset::Seconds(11.0), TimeOffset::Seconds(15.0)]);
+
+        let placed = schedule_ad_breaks(&playlist, &vmap);
+
+        assert_eq!(placed.len(), 1);
+        assert_eq!(placed[0].1.vmap_index, 0);
+    }
+}
@@ -4,7 +4,7 @@
 //! previously copy-pasted in `handlers/ad.rs`, `handlers/segment.rs`, and
 //! `ad/vast_provider.rs`.
 
-use reqwest::{Client, Response};
+use reqwest::{Client, Response, header::HeaderMap};
 use std::time::Duration;
 use tracing::warn;
 
@@ -25,6 +25,8 @@ pub struct RetryConfig {
     ///
     /// When `None`, the client's own timeout applies.
     pub timeout: Option<Duration>,
+    /// Extra headers sent with every attempt
+    pub headers: HeaderMap,
 }
 
 impl Default for RetryConfig {
@@ -33,6 +35,7 @@ impl Default for RetryConfig {
             max_attempts: DEFAULT_MAX_ATTEMPTS,
             backoff: Duration::from_millis(DEFAULT_BACKOFF_MS),
             timeout: None,
+            headers: HeaderMap::new(),
         }
     }
 }
@@ -59,7 +62,7 @@ pub async fn fetch_with_retry(
     // The final attempt is handled separately below to guarantee a
     // return without `unreachable!()` or other panic paths.
     for attempt in 1..max_attempts {
-        let mut request = client.get(url);
+        let mut request = client.get(url).headers(config.headers.clone());
         if let Some(timeout) = config.timeout {
             request = request.timeout(timeout);
         }
@@ -90,7 +93,7 @@ pub async fn fetch_with_retry(
     }
 
     // Final attempt — returns directly, no further retry
-    let mut request = client.get(url);
+    let mut request = client.get(url).headers(config.headers.clone());
     if let Some(timeout) = config.timeout {
         request = request.timeout(timeout);
     }
@@ -136,6 +139,7 @@ mod tests {
             max_attempts: 5,
             backoff: Duration::from_millis(100),
             timeout: Some(Duration::from_secs(10)),
+            headers: HeaderMap::new(),
         };
         assert_eq!(cfg.max_attempts, 5);
         assert_eq!(cfg.backoff, Duration::from_millis(100));
@@ -166,6 +170,7 @@ mod tests {
             max_attempts: 3,
             backoff: Duration::from_millis(200),
             timeout: Some(Duration::from_secs(5)),
+            headers: HeaderMap::new(),
         };
         let cloned = cfg.clone();
         assert_eq!(cloned.max_attempts, 3);
@@ -195,6 +200,28 @@ mod tests {
         assert_eq!(result.unwrap().text().await.unwrap(), "ok");
     }
 
+    #[tokio::test]
+    async fn sends_configured_headers() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(wiremock::matchers::header("x-device-ip", "198.51.100.4"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-device-ip", "198.51.100.4".parse().unwrap());
+        let config = RetryConfig {
+            max_attempts: 1,
+            headers,
+            ..Default::default()
+        };
+
+        let result = fetch_with_retry(&Client::new(), &server.uri(), &config).await;
+        assert!(result.is_ok());
+    }
+
     #[tokio::test]
     async fn retries_on_server_error_then_succeeds() {
         let server = MockServer::start().await;
@@ -217,6 +244,7 @@ mod tests {
             max_attempts: 2,
             backoff: Duration::from_millis(1),
             timeout: None,
+            headers: HeaderMap::new(),
         };
 
         let result = fetch_with_retry(&client, &server.uri(), &config).await;
@@ -238,6 +266,7 @@ mod tests {
             max_attempts: 2,
             backoff: Duration::from_millis(1),
             timeout: None,
+            headers: HeaderMap::new(),
         };
 
         let result = fetch_with_retry(&client, &server.uri(), &config).await;
@@ -258,6 +287,7 @@ mod tests {
             max_attempts: 1,
             backoff: Duration::from_millis(1),
             timeout: None,
+            headers: HeaderMap::new(),
         };
 
         let result = fetch_with_retry(&client, &server.uri(), &config).await;
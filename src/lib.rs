@@ -20,6 +20,7 @@
 //! - [`hls`] -- HLS playlist parsing, CUE detection, SGAI interstitials
 //! - [`http_retry`] -- HTTP fetch with exponential backoff
 //! - [`metrics`] -- Prometheus metric definitions and recording helpers
+//! - [`scte35`] -- SCTE-35 splice signal model shared by HLS and DASH
 //! - [`server`] -- Axum routes, handlers, middleware, state
 //! - [`session`] -- Per-viewer session management (memory or Valkey)
 //!
@@ -38,5 +39,6 @@ pub mod error;
 pub mod hls;
 pub mod http_retry;
 pub mod metrics;
+pub mod scte35;
 pub mod server;
 pub mod session;
@@ -13,12 +13,39 @@ pub const ACTIVE_SESSIONS: &str = "ritcher_active_sessions";
 pub const AD_BREAKS_DETECTED: &str = "ritcher_ad_breaks_detected";
 /// VAST requests by result (success, error, timeout, empty)
 pub const VAST_REQUESTS: &str = "ritcher_vast_requests_total";
+/// OpenRTB bid requests by result (bid, nobid, error, timeout)
+pub const OPENRTB_REQUESTS: &str = "ritcher_openrtb_requests_total";
+/// JSON ad-decision requests by result (success, empty, error, timeout)
+pub const DECISION_REQUESTS: &str = "ritcher_decision_requests_total";
+/// VAST failures by spec error code, reported to the `<Error>` URLs
+pub const VAST_ERRORS: &str = "ritcher_vast_errors_total";
 /// Slate fallback activations
 pub const SLATE_FALLBACKS: &str = "ritcher_slate_fallbacks_total";
 /// Origin fetch errors
 pub const ORIGIN_FETCH_ERRORS: &str = "ritcher_origin_fetch_errors_total";
 /// Tracking beacons fired by event type and result
 pub const TRACKING_BEACONS: &str = "ritcher_tracking_beacons_total";
+/// Ad breaks recorded from in-band `emsg` SCTE-35 in proxied segments
+pub const INBAND_BREAKS: &str = "ritcher_inband_breaks_total";
+/// Fit-to-break pod adjustments by action (dropped/padded)
+pub const POD_ADJUSTMENTS: &str = "ritcher_pod_adjustments_total";
+/// Waterfall fill results by demand source (filled/partial/empty/skipped)
+pub const AD_SOURCE_FILLS: &str = "ritcher_ad_source_fills_total";
+/// Seconds of a break filled by each waterfall demand source
+pub const AD_SOURCE_FILL_SECONDS: &str = "ritcher_ad_source_fill_seconds";
+/// Shared ad decisions by result (decided/reused)
+pub const SHARED_DECISIONS: &str = "ritcher_shared_decisions_total";
+/// Prefetched ad decisions by result (started/hit/waited)
+pub const AD_PREFETCHES: &str = "ritcher_ad_prefetches_total";
+/// Ads left out of a break by reason (cap/separation)
+pub const FREQUENCY_REJECTIONS: &str = "ritcher_frequency_rejections_total";
+/// Creatives and media files rejected by the creative policy, by reason
+pub const CREATIVE_REJECTIONS: &str = "ritcher_creative_rejections_total";
+/// Creative media probes by verdict (match/mismatch/unknown)
+pub const CREATIVE_PROBES: &str = "ritcher_creative_probes_total";
+/// Creative normalization jobs and lookups by result
+/// (submitted/ready/failed/pending)
+pub const AD_NORMALIZATIONS: &str = "ritcher_ad_normalizations_total";
 
 // ── Recording helpers ───────────────────────────────────────────────────
 
@@ -46,16 +73,81 @@ pub fn record_ad_breaks(count: usize) {
     counter!(AD_BREAKS_DETECTED).increment(count as u64);
 }
 
+/// Record ad breaks found in-band by the segment proxy
+pub fn record_inband_breaks(count: usize) {
+    // usize-to-u64: safe on all supported platforms (usize <= u64).
+    #[allow(clippy::cast_possible_truncation)]
+    counter!(INBAND_BREAKS).increment(count as u64);
+}
+
 /// Record a VAST request result
 pub fn record_vast_request(result: &str) {
     counter!(VAST_REQUESTS, "result" => result.to_string()).increment(1);
 }
 
+/// Record an OpenRTB bid request result
+pub fn record_openrtb_request(result: &str) {
+    counter!(OPENRTB_REQUESTS, "result" => result.to_string()).increment(1);
+}
+
+/// Record a JSON ad-decision request result
+pub fn record_decision_request(result: &str) {
+    counter!(DECISION_REQUESTS, "result" => result.to_string()).increment(1);
+}
+
+/// Record a VAST failure by its error code
+pub fn record_vast_error(code: u16) {
+    counter!(VAST_ERRORS, "code" => code.to_string()).increment(1);
+}
+
 /// Record a slate fallback activation
 pub fn record_slate_fallback() {
     counter!(SLATE_FALLBACKS).increment(1);
 }
 
+/// Record a fit-to-break pod adjustment
+pub fn record_pod_adjustment(action: &str) {
+    counter!(POD_ADJUSTMENTS, "action" => action.to_string()).increment(1);
+}
+
+/// Record the fill result of a waterfall demand source for one break
+pub fn record_ad_source_fill(source: &str, result: &str, seconds: f32) {
+    counter!(AD_SOURCE_FILLS, "source" => source.to_string(), "result" => result.to_string())
+        .increment(1);
+    histogram!(AD_SOURCE_FILL_SECONDS, "source" => source.to_string()).record(f64::from(seconds));
+}
+
+/// Record a break served from a shared ad decision
+pub fn record_shared_decision(result: &str) {
+    counter!(SHARED_DECISIONS, "result" => result.to_string()).increment(1);
+}
+
+/// Record a prefetched ad decision being started or used
+pub fn record_ad_prefetch(result: &str) {
+    counter!(AD_PREFETCHES, "result" => result.to_string()).increment(1);
+}
+
+/// Record an ad left out by frequency capping or competitive separation
+pub fn record_frequency_rejection(reason: &str) {
+    counter!(FREQUENCY_REJECTIONS, "reason" => reason.to_string()).increment(1);
+}
+
+/// Record a creative or media file rejected by the creative policy
+pub fn record_creative_rejection(reason: &str) {
+    counter!(CREATIVE_REJECTIONS, "reason" => reason.to_string()).increment(1);
+}
+
+/// Record the verdict of a creative media probe
+pub fn record_creative_probe(verdict: &str) {
+    counter!(CREATIVE_PROBES, "verdict" => verdict.to_string()).increment(1);
+}
+
+/// Record a normalization job outcome, or a creative left out while its
+/// package is pending
+pub fn record_normalization(result: &str) {
+    counter!(AD_NORMALIZATIONS, "result" => result.to_string()).increment(1);
+}
+
 /// Record an origin fetch error
 pub fn record_origin_error() {
     counter!(ORIGIN_FETCH_ERRORS).increment(1);
@@ -0,0 +1,704 @@
+//! SCTE-35 splice signal model shared by HLS and DASH cue detection.
+//!
+//! Cue detectors translate their container-specific representation
+//! (DASH `<scte35:SpliceInfoSection>` XML, binary sections carried in HLS
+//! tags or `xml+bin` payloads) into a [`SpliceInfo`] and then call
+//! [`SpliceInfo::action`] to decide whether the cue opens an ad break, closes
+//! one, or should be ignored. Keeping the decision in one place means
+//! cue-ins, cancels and non-ad segmentation types are handled the same way
+//! for every format.
+
+/// SCTE-35 time values (`pts_time`, `break_duration`, `segmentation_duration`)
+/// are expressed in 90 kHz clock ticks.
+pub const TICKS_PER_SECOND: f64 = 90_000.0;
+
+/// `segmentation_type_id` values (SCTE-35 2022, Table 23) relevant to ad insertion.
+pub mod segmentation_type {
+    /// Break Start
+    pub const BREAK_START: u8 = 0x22;
+    /// Break End
+    pub const BREAK_END: u8 = 0x23;
+    /// Provider Advertisement Start
+    pub const PROVIDER_AD_START: u8 = 0x30;
+    /// Provider Advertisement End
+    pub const PROVIDER_AD_END: u8 = 0x31;
+    /// Distributor Advertisement Start
+    pub const DISTRIBUTOR_AD_START: u8 = 0x32;
+    /// Distributor Advertisement End
+    pub const DISTRIBUTOR_AD_END: u8 = 0x33;
+    /// Provider Placement Opportunity Start
+    pub const PROVIDER_PO_START: u8 = 0x34;
+    /// Provider Placement Opportunity End
+    pub const PROVIDER_PO_END: u8 = 0x35;
+    /// Distributor Placement Opportunity Start
+    pub const DISTRIBUTOR_PO_START: u8 = 0x36;
+    /// Distributor Placement Opportunity End
+    pub const DISTRIBUTOR_PO_END: u8 = 0x37;
+}
+
+/// Default segmentation types that open an ad break
+const DEFAULT_START_TYPES: &[u8] = &[
+    segmentation_type::BREAK_START,
+    segmentation_type::PROVIDER_AD_START,
+    segmentation_type::DISTRIBUTOR_AD_START,
+    segmentation_type::PROVIDER_PO_START,
+    segmentation_type::DISTRIBUTOR_PO_START,
+];
+
+/// Default segmentation types that close an ad break
+const DEFAULT_END_TYPES: &[u8] = &[
+    segmentation_type::BREAK_END,
+    segmentation_type::PROVIDER_AD_END,
+    segmentation_type::DISTRIBUTOR_AD_END,
+    segmentation_type::PROVIDER_PO_END,
+    segmentation_type::DISTRIBUTOR_PO_END,
+];
+
+/// Which `segmentation_type_id` values open and close an ad break
+///
+/// Configured from `SCTE35_BREAK_START_TYPES` / `SCTE35_BREAK_END_TYPES`.
+/// Defaults to Break, Provider/Distributor Advertisement and Provider/
+/// Distributor Placement Opportunity start and end types.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SegmentationTypes {
+    /// Types that open a break
+    pub break_start: Vec<u8>,
+    /// Types that close a break
+    pub break_end: Vec<u8>,
+}
+
+impl Default for SegmentationTypes {
+    fn default() -> Self {
+        Self {
+            break_start: DEFAULT_START_TYPES.to_vec(),
+            break_end: DEFAULT_END_TYPES.to_vec(),
+        }
+    }
+}
+
+impl SegmentationTypes {
+    /// Parse a comma-separated list of type IDs, hex (`0x34`) or decimal (`52`)
+    ///
+    /// Returns `None` if any entry is not a valid `u8`.
+    pub fn parse_list(list: &str) -> Option<Vec<u8>> {
+        list.split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(
+                |s| match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+                    Some(hex) => u8::from_str_radix(hex, 16).ok(),
+                    None => s.parse().ok(),
+                },
+            )
+            .collect()
+    }
+}
+
+/// Splice command carried in a `splice_info_section`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpliceCommandType {
+    /// `splice_null` (heartbeat, carries descriptors only)
+    SpliceNull,
+    /// `splice_insert`
+    SpliceInsert,
+    /// `time_signal` (meaning is carried by segmentation descriptors)
+    TimeSignal,
+    /// Any other command (schedule, bandwidth reservation, private)
+    Other,
+}
+
+/// A single `segmentation_descriptor`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SegmentationDescriptor {
+    /// `segmentation_event_id`
+    pub event_id: Option<u32>,
+    /// `segmentation_event_cancel_indicator`
+    pub cancelled: bool,
+    /// `segmentation_type_id` (absent on cancellations, which omit it)
+    pub type_id: Option<u8>,
+    /// `segmentation_duration` in 90 kHz ticks
+    pub duration_ticks: Option<u64>,
+    /// `segmentation_upid`: text when printable ASCII, otherwise `0x`-prefixed hex
+    pub upid: Option<String>,
+}
+
+/// Decoded `splice_info_section`, independent of its transport
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpliceInfo {
+    /// Splice command type
+    pub command: SpliceCommandType,
+    /// `splice_event_cancel_indicator` of a `splice_insert`
+    pub splice_event_cancelled: bool,
+    /// `out_of_network_indicator` of a `splice_insert`
+    pub out_of_network: bool,
+    /// `break_duration` of a `splice_insert`, in 90 kHz ticks
+    pub break_duration_ticks: Option<u64>,
+    /// When the splice happens: `pts_time` plus the section's
+    /// `pts_adjustment` (modulo 2^33), in 90 kHz ticks; `None` for
+    /// immediate splices
+    pub splice_time_ticks: Option<u64>,
+    /// Segmentation descriptors attached to the section
+    pub segmentation: Vec<SegmentationDescriptor>,
+}
+
+/// What a cue means for ad insertion
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpliceAction {
+    /// Opens an ad break; `duration` in seconds when the cue carries one,
+    /// `upid` from the segmentation descriptor for ad-request targeting
+    BreakStart {
+        duration: Option<f64>,
+        upid: Option<String>,
+    },
+    /// Returns to the network (cue-in / ad end)
+    BreakEnd,
+    /// Cancels a previously signalled event
+    Cancel,
+    /// Not relevant to ad insertion (heartbeat, non-ad segmentation type)
+    Ignore,
+}
+
+impl SpliceInfo {
+    /// Classify this cue for ad insertion.
+    ///
+    /// - `splice_insert`: cancel indicator wins, then `out_of_network_indicator`
+    ///   decides between break start and cue-in.
+    /// - `time_signal`: the first segmentation descriptor whose
+    ///   `segmentation_type_id` is in `types` decides; all other types are
+    ///   ignored.
+    pub fn action(&self, types: &SegmentationTypes) -> SpliceAction {
+        match self.command {
+            SpliceCommandType::SpliceInsert => {
+                if self.splice_event_cancelled {
+                    SpliceAction::Cancel
+                } else if self.out_of_network {
+                    SpliceAction::BreakStart {
+                        duration: self.break_duration_ticks.map(ticks_to_seconds),
+                        upid: self.segmentation.iter().find_map(|d| d.upid.clone()),
+                    }
+                } else {
+                    SpliceAction::BreakEnd
+                }
+            }
+            SpliceCommandType::TimeSignal => self
+                .segmentation
+                .iter()
+                .find_map(|d| segmentation_action(d, types))
+                .unwrap_or(SpliceAction::Ignore),
+            SpliceCommandType::SpliceNull | SpliceCommandType::Other => SpliceAction::Ignore,
+        }
+    }
+}
+
+/// Classify a single segmentation descriptor, `None` for non-ad types
+///
+/// A cancelled descriptor without a type ID (the binary encoding omits it)
+/// is treated as a cancel, since it may refer to a break in progress.
+fn segmentation_action(
+    descriptor: &SegmentationDescriptor,
+    types: &SegmentationTypes,
+) -> Option<SpliceAction> {
+    let Some(type_id) = descriptor.type_id else {
+        return descriptor.cancelled.then_some(SpliceAction::Cancel);
+    };
+    let is_start = types.break_start.contains(&type_id);
+    if !is_start && !types.break_end.contains(&type_id) {
+        return None;
+    }
+    if descriptor.cancelled {
+        return Some(SpliceAction::Cancel);
+    }
+    if is_start {
+        return Some(SpliceAction::BreakStart {
+            duration: descriptor.duration_ticks.map(ticks_to_seconds),
+            upid: descriptor.upid.clone(),
+        });
+    }
+    Some(SpliceAction::BreakEnd)
+}
+
+/// Decode a binary `splice_info_section` (SCTE-35 2022, §9.6)
+///
+/// Only the fields needed for ad insertion are extracted: the splice command,
+/// `splice_insert` flags and break duration, and any `segmentation_descriptor`
+/// (tag 0x02, identifier `CUEI`). Encrypted sections and truncated input
+/// return `None`. The CRC is not verified; HLS and DASH carriage already
+/// protect the payload.
+pub fn decode_splice_info_section(data: &[u8]) -> Option<SpliceInfo> {
+    let mut r = BitReader::new(data);
+
+    if r.bits(8)? != 0xFC {
+        return None;
+    }
+    r.skip(4); // section_syntax_indicator, private_indicator, sap_type
+    r.skip(12); // section_length
+    r.skip(8); // protocol_version
+    if r.bits(1)? == 1 {
+        // encrypted_packet
+        return None;
+    }
+    r.skip(6); // encryption_algorithm
+    let pts_adjustment = r.bits(33)?;
+    r.skip(8 + 12); // cw_index, tier
+    let command_length = usize::try_from(r.bits(12)?).ok()?;
+    let command_type = r.bits(8)?;
+    let command_start = r.byte_pos();
+
+    let mut info = SpliceInfo {
+        command: SpliceCommandType::Other,
+        splice_event_cancelled: false,
+        out_of_network: false,
+        break_duration_ticks: None,
+        splice_time_ticks: None,
+        segmentation: Vec::new(),
+    };
+
+    match command_type {
+        0x00 => info.command = SpliceCommandType::SpliceNull,
+        0x05 => {
+            info.command = SpliceCommandType::SpliceInsert;
+            r.skip(32); // splice_event_id
+            info.splice_event_cancelled = r.bits(1)? == 1;
+            r.skip(7);
+            if !info.splice_event_cancelled {
+                info.out_of_network = r.bits(1)? == 1;
+                let program_splice = r.bits(1)? == 1;
+                let duration_flag = r.bits(1)? == 1;
+                let splice_immediate = r.bits(1)? == 1;
+                r.skip(4);
+                if program_splice && !splice_immediate {
+                    info.splice_time_ticks = r.splice_time()?;
+                }
+                if !program_splice {
+                    let component_count = r.bits(8)?;
+                    for _ in 0..component_count {
+                        r.skip(8); // component_tag
+                        if !splice_immediate {
+                            r.splice_time()?;
+                        }
+                    }
+                }
+                if duration_flag {
+                    r.skip(7); // auto_return, reserved
+                    info.break_duration_ticks = Some(r.bits(33)?);
+                }
+                r.skip(32); // unique_program_id, avail_num, avails_expected
+            }
+        }
+        0x06 => {
+            info.command = SpliceCommandType::TimeSignal;
+            info.splice_time_ticks = r.splice_time()?;
+        }
+        _ => {}
+    }
+
+    info.splice_time_ticks = info
+        .splice_time_ticks
+        .map(|pts| adjust_pts(pts, pts_adjustment));
+
+    // splice_command_length of 0xFFF means "unknown" (legacy encoders);
+    // fall back to where the parsed command ended.
+    let descriptors_start = if command_length == 0xFFF {
+        if info.command == SpliceCommandType::Other {
+            return Some(info);
+        }
+        r.byte_pos()
+    } else {
+        command_start + command_length
+    };
+
+    let loop_length = u16::from_be_bytes([
+        *data.get(descriptors_start)?,
+        *data.get(descriptors_start + 1)?,
+    ]) as usize;
+    let loop_start = descriptors_start + 2;
+    let descriptors = data.get(loop_start..loop_start + loop_length)?;
+
+    let mut offset = 0;
+    while offset + 2 <= descriptors.len() {
+        let tag = descriptors[offset];
+        let length = descriptors[offset + 1] as usize;
+        let body = descriptors.get(offset + 2..offset + 2 + length)?;
+        if tag == 0x02
+            && body.starts_with(b"CUEI")
+            && let Some(descriptor) = decode_segmentation_descriptor(&body[4..])
+        {
+            info.segmentation.push(descriptor);
+        }
+        offset += 2 + length;
+    }
+
+    Some(info)
+}
+
+/// Decode a `segmentation_descriptor` body following the `CUEI` identifier
+fn decode_segmentation_descriptor(data: &[u8]) -> Option<SegmentationDescriptor> {
+    let mut r = BitReader::new(data);
+    #[allow(clippy::cast_possible_truncation)] // 32-bit field
+    let event_id = r.bits(32)? as u32;
+    let cancelled = r.bits(1)? == 1;
+    r.skip(7);
+
+    let mut descriptor = SegmentationDescriptor {
+        event_id: Some(event_id),
+        cancelled,
+        ..Default::default()
+    };
+    if cancelled {
+        return Some(descriptor);
+    }
+
+    let program_segmentation = r.bits(1)? == 1;
+    let duration_flag = r.bits(1)? == 1;
+    r.skip(6); // delivery_not_restricted and restriction flags
+    if !program_segmentation {
+        let component_count = r.bits(8)?;
+        r.skip(48 * u32::try_from(component_count).ok()?);
+    }
+    if duration_flag {
+        descriptor.duration_ticks = Some(r.bits(40)?);
+    }
+    r.skip(8); // segmentation_upid_type
+    let upid_length = usize::try_from(r.bits(8)?).ok()?;
+    let upid_start = r.byte_pos();
+    let upid = data.get(upid_start..upid_start + upid_length)?;
+    if !upid.is_empty() {
+        descriptor.upid = Some(format_upid(upid));
+    }
+    r.skip(8 * u32::try_from(upid_length).ok()?);
+    #[allow(clippy::cast_possible_truncation)] // 8-bit field
+    let type_id = r.bits(8)? as u8;
+    descriptor.type_id = Some(type_id);
+
+    Some(descriptor)
+}
+
+/// Render a UPID as text when it is printable ASCII (ADI, Ad-ID, URI, ...),
+/// otherwise as `0x`-prefixed uppercase hex
+fn format_upid(upid: &[u8]) -> String {
+    if upid.iter().all(|b| b.is_ascii_graphic() || *b == b' ') {
+        String::from_utf8_lossy(upid).into_owned()
+    } else {
+        let hex: String = upid.iter().map(|b| format!("{:02X}", b)).collect();
+        format!("0x{}", hex)
+    }
+}
+
+/// Decode a base64 (standard alphabet, optional padding) SCTE-35 payload,
+/// as carried by `#EXT-X-SCTE35:CUE=`, `#EXT-OATCLS-SCTE35` and
+/// `<scte35:Binary>`
+pub fn decode_base64(input: &str) -> Option<Vec<u8>> {
+    fn value(c: u8) -> Option<u32> {
+        match c {
+            b'A'..=b'Z' => Some(u32::from(c - b'A')),
+            b'a'..=b'z' => Some(u32::from(c - b'a') + 26),
+            b'0'..=b'9' => Some(u32::from(c - b'0') + 52),
+            b'+' => Some(62),
+            b'/' => Some(63),
+            _ => None,
+        }
+    }
+
+    let input = input.trim().trim_end_matches('=');
+    let mut out = Vec::with_capacity(input.len() * 3 / 4);
+    let mut acc = 0u32;
+    let mut bits = 0;
+    for c in input.bytes().filter(|c| !c.is_ascii_whitespace()) {
+        acc = (acc << 6) | value(c)?;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            #[allow(clippy::cast_possible_truncation)] // masked to one byte
+            out.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    }
+    Some(out)
+}
+
+/// Decode a `0x`-prefixed hex SCTE-35 payload, as carried by the
+/// `SCTE35-OUT`/`SCTE35-IN`/`SCTE35-CMD` attributes of `#EXT-X-DATERANGE`
+pub fn decode_hex(input: &str) -> Option<Vec<u8>> {
+    let input = input.trim();
+    let hex = input
+        .strip_prefix("0x")
+        .or_else(|| input.strip_prefix("0X"))
+        .unwrap_or(input);
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// MSB-first bit reader over a byte slice
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    /// Read up to 64 bits, `None` past the end of the input
+    fn bits(&mut self, count: u32) -> Option<u64> {
+        let mut value = 0u64;
+        for _ in 0..count {
+            let byte = *self.data.get(self.pos / 8)?;
+            let bit = (byte >> (7 - self.pos % 8)) & 1;
+            value = (value << 1) | u64::from(bit);
+            self.pos += 1;
+        }
+        Some(value)
+    }
+
+    /// Skip bits; reading past the end is detected by the next `bits` call
+    fn skip(&mut self, count: u32) {
+        self.pos += count as usize;
+    }
+
+    fn byte_pos(&self) -> usize {
+        self.pos.div_ceil(8)
+    }
+
+    /// Read a `splice_time()` structure: its `pts_time`, if specified
+    fn splice_time(&mut self) -> Option<Option<u64>> {
+        if self.bits(1)? == 1 {
+            self.skip(6);
+            Some(Some(self.bits(33)?))
+        } else {
+            self.skip(7);
+            Some(None)
+        }
+    }
+}
+
+/// Apply a section's `pts_adjustment` to a `pts_time`, wrapping at 33 bits
+pub fn adjust_pts(pts_time: u64, pts_adjustment: u64) -> u64 {
+    (pts_time + pts_adjustment) & ((1 << 33) - 1)
+}
+
+/// Convert 90 kHz ticks to seconds
+pub fn ticks_to_seconds(ticks: u64) -> f64 {
+    ticks as f64 / TICKS_PER_SECOND
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// SCTE-35 2022 §14.1: time_signal, Provider Placement Opportunity Start
+    const TIME_SIGNAL_PO_START: &str =
+        "/DA0AAAAAAAA///wBQb+cr0AUAAeAhxDVUVJSAAAjn/PAAGlmbAICAAAAAAsoKGKNAIAmsnRfg==";
+    /// SCTE-35 2022 §14.2: splice_insert out of network with break_duration
+    const SPLICE_INSERT_OUT: &str =
+        "/DAvAAAAAAAA///wFAVIAACPf+/+c2nALv4AUsz1AAAAAAAKAAhDVUVJAAABNWLbowo=";
+
+    fn splice_insert(out_of_network: bool, cancelled: bool) -> SpliceInfo {
+        SpliceInfo {
+            command: SpliceCommandType::SpliceInsert,
+            splice_event_cancelled: cancelled,
+            out_of_network,
+            break_duration_ticks: Some(2_700_000),
+            splice_time_ticks: None,
+            segmentation: Vec::new(),
+        }
+    }
+
+    fn time_signal(type_id: u8, cancelled: bool) -> SpliceInfo {
+        SpliceInfo {
+            command: SpliceCommandType::TimeSignal,
+            splice_event_cancelled: false,
+            out_of_network: false,
+            break_duration_ticks: None,
+            splice_time_ticks: None,
+            segmentation: vec![SegmentationDescriptor {
+                event_id: Some(1),
+                cancelled,
+                type_id: Some(type_id),
+                duration_ticks: Some(1_350_000),
+                upid: Some("urn:uuid:abc".to_string()),
+            }],
+        }
+    }
+
+    fn defaults() -> SegmentationTypes {
+        SegmentationTypes::default()
+    }
+
+    #[test]
+    fn splice_insert_out_of_network_starts_break() {
+        assert_eq!(
+            splice_insert(true, false).action(&defaults()),
+            SpliceAction::BreakStart {
+                duration: Some(30.0),
+                upid: None,
+            }
+        );
+    }
+
+    #[test]
+    fn splice_insert_cue_in_and_cancel() {
+        assert_eq!(
+            splice_insert(false, false).action(&defaults()),
+            SpliceAction::BreakEnd
+        );
+        assert_eq!(
+            splice_insert(true, true).action(&defaults()),
+            SpliceAction::Cancel
+        );
+    }
+
+    #[test]
+    fn time_signal_ad_start_types() {
+        for type_id in [0x22, 0x30, 0x32, 0x34, 0x36] {
+            assert_eq!(
+                time_signal(type_id, false).action(&defaults()),
+                SpliceAction::BreakStart {
+                    duration: Some(15.0),
+                    upid: Some("urn:uuid:abc".to_string()),
+                },
+                "type 0x{:02x} should open a break",
+                type_id
+            );
+        }
+    }
+
+    #[test]
+    fn time_signal_ad_end_and_cancel() {
+        assert_eq!(
+            time_signal(0x35, false).action(&defaults()),
+            SpliceAction::BreakEnd
+        );
+        assert_eq!(
+            time_signal(0x34, true).action(&defaults()),
+            SpliceAction::Cancel
+        );
+    }
+
+    #[test]
+    fn time_signal_non_ad_types_ignored() {
+        // 0x10 Program Start, 0x11 Program End, 0x20 Chapter Start
+        for type_id in [0x10, 0x11, 0x20] {
+            assert_eq!(
+                time_signal(type_id, false).action(&defaults()),
+                SpliceAction::Ignore
+            );
+        }
+    }
+
+    #[test]
+    fn time_signal_respects_configured_types() {
+        let types = SegmentationTypes {
+            break_start: vec![0x34],
+            break_end: vec![0x35],
+        };
+        assert!(matches!(
+            time_signal(0x34, false).action(&types),
+            SpliceAction::BreakStart { .. }
+        ));
+        assert_eq!(
+            time_signal(0x30, false).action(&types),
+            SpliceAction::Ignore
+        );
+        assert_eq!(
+            time_signal(0x31, false).action(&types),
+            SpliceAction::Ignore
+        );
+    }
+
+    #[test]
+    fn splice_null_ignored() {
+        let info = SpliceInfo {
+            command: SpliceCommandType::SpliceNull,
+            splice_event_cancelled: false,
+            out_of_network: false,
+            break_duration_ticks: None,
+            splice_time_ticks: None,
+            segmentation: Vec::new(),
+        };
+        assert_eq!(info.action(&defaults()), SpliceAction::Ignore);
+    }
+
+    #[test]
+    fn parse_type_list_hex_and_decimal() {
+        assert_eq!(
+            SegmentationTypes::parse_list("0x34, 0x36,48"),
+            Some(vec![0x34, 0x36, 0x30])
+        );
+        assert_eq!(SegmentationTypes::parse_list(""), Some(vec![]));
+        assert_eq!(SegmentationTypes::parse_list("0x34,zz"), None);
+        assert_eq!(SegmentationTypes::parse_list("256"), None);
+    }
+
+    #[test]
+    fn decode_time_signal_placement_opportunity() {
+        let bytes = decode_base64(TIME_SIGNAL_PO_START).unwrap();
+        let info = decode_splice_info_section(&bytes).unwrap();
+
+        assert_eq!(info.command, SpliceCommandType::TimeSignal);
+        assert_eq!(info.splice_time_ticks, Some(0x0_72BD_0050));
+        assert_eq!(info.segmentation.len(), 1);
+        let descriptor = &info.segmentation[0];
+        assert_eq!(descriptor.event_id, Some(0x4800_008E));
+        assert_eq!(descriptor.type_id, Some(0x34));
+        assert_eq!(descriptor.duration_ticks, Some(0x01A5_99B0));
+        assert_eq!(descriptor.upid.as_deref(), Some("0x000000002CA0A18A"));
+
+        match info.action(&defaults()) {
+            SpliceAction::BreakStart { duration, upid } => {
+                assert!((duration.unwrap() - 307.0).abs() < 0.001);
+                assert_eq!(upid.as_deref(), Some("0x000000002CA0A18A"));
+            }
+            other => panic!("expected BreakStart, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn decode_splice_insert_with_break_duration() {
+        let bytes = decode_base64(SPLICE_INSERT_OUT).unwrap();
+        let info = decode_splice_info_section(&bytes).unwrap();
+
+        assert_eq!(info.command, SpliceCommandType::SpliceInsert);
+        assert!(info.out_of_network);
+        assert!(!info.splice_event_cancelled);
+        assert_eq!(info.break_duration_ticks, Some(0x0052_CCF5));
+        assert_eq!(info.splice_time_ticks, Some(0x0_7369_C02E));
+        // avail_descriptor only, no segmentation descriptors
+        assert!(info.segmentation.is_empty());
+    }
+
+    #[test]
+    fn pts_adjustment_wraps_at_33_bits() {
+        assert_eq!(adjust_pts(100, 50), 150);
+        assert_eq!(adjust_pts((1 << 33) - 10, 25), 15);
+    }
+
+    #[test]
+    fn decode_hex_matches_base64() {
+        let bytes = decode_base64(TIME_SIGNAL_PO_START).unwrap();
+        let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        assert_eq!(decode_hex(&format!("0x{}", hex)), Some(bytes));
+        assert_eq!(decode_hex("0xF"), None);
+        assert_eq!(decode_hex("0xZZ"), None);
+    }
+
+    #[test]
+    fn decode_rejects_invalid_sections() {
+        assert_eq!(decode_splice_info_section(&[]), None);
+        assert_eq!(decode_splice_info_section(&[0x00, 0x01, 0x02]), None);
+        let bytes = decode_base64(TIME_SIGNAL_PO_START).unwrap();
+        assert_eq!(decode_splice_info_section(&bytes[..20]), None);
+        assert_eq!(decode_base64("not base64!"), None);
+    }
+
+    #[test]
+    fn format_upid_text_and_binary() {
+        assert_eq!(format_upid(b"SIGNAL:abc123"), "SIGNAL:abc123");
+        assert_eq!(format_upid(&[0x00, 0xAB]), "0x00AB");
+    }
+}
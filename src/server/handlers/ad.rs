@@ -1,16 +1,24 @@
 use crate::{
-    ad::tracking,
+    ad::{
+        provider::ResolvedSegment,
+        shared_decision::{is_shared_decision, is_shared_segment},
+        tracking,
+    },
     error::Result,
     http_retry::{RetryConfig, fetch_with_retry},
     metrics,
-    server::{state::AppState, url_validation::validate_session_id},
+    server::{
+        handlers::ad_context::request_ad_context, state::AppState,
+        url_validation::validate_session_id,
+    },
 };
 use axum::{
     body::Body,
     extract::{Path, State},
-    http::{StatusCode, header},
+    http::{HeaderMap, StatusCode, header},
     response::{IntoResponse, Response},
 };
+use std::collections::HashMap;
 use std::time::Instant;
 use tracing::info;
 
@@ -19,51 +27,96 @@ use tracing::info;
 /// The `ad_name` path parameter encodes the break and segment index
 /// (e.g. `break-0-seg-3.ts`). URL resolution is delegated to the
 /// `AdProvider` trait, keeping this handler decoupled from ad source details.
-/// Fires VAST tracking beacons (impressions, quartiles) as a side effect.
+/// Fires VAST tracking beacons (impressions, quartiles) as a side effect,
+/// expanding their VAST macros with the session's viewer context. Segments
+/// of shared ad decisions (see [`crate::ad::shared_decision`]) resolve
+/// through their break's decision and are served without beacons; those
+/// of breaks this instance has not decided return 404.
 ///
-/// Uses [`fetch_with_retry`] for fault-tolerant HTTP fetching.
+/// Segments pre-warmed by the ad prefetcher are served from the segment
+/// cache; others are fetched with [`fetch_with_retry`] for fault-tolerant
+/// HTTP fetching.
 pub async fn serve_ad(
     Path((session_id, ad_name)): Path<(String, String)>,
+    headers: HeaderMap,
     State(state): State<AppState>,
 ) -> Result<Response> {
     validate_session_id(&session_id)?;
     let start = Instant::now();
     info!("Serving ad: {} for session: {}", ad_name, session_id);
 
-    // Resolve ad segment with tracking context
-    let resolved = state
-        .ad_provider
-        .resolve_segment_with_tracking(&ad_name, &session_id)
-        .ok_or_else(|| {
-            crate::error::RitcherError::InternalError(format!(
-                "Failed to resolve ad segment URL for: {}",
-                ad_name
-            ))
-        })?;
+    // Resolve ad segment with tracking context. Segments of shared
+    // decisions are the same for every session: their beacons fire when
+    // the sessions' playlists are served instead.
+    let resolved = if is_shared_decision(&session_id) {
+        let name = if is_shared_segment(&ad_name) {
+            state
+                .shared_decisions
+                .route(&session_id, &ad_name)
+                .ok_or_else(|| {
+                    crate::error::RitcherError::NotFound(format!(
+                        "No shared decision holds ad segment {} of {}",
+                        ad_name, session_id
+                    ))
+                })?
+        } else {
+            ad_name.clone()
+        };
+        state
+            .ad_provider
+            .resolve_segment_url(&name, &session_id)
+            .map(|url| ResolvedSegment {
+                url,
+                tracking: None,
+            })
+    } else {
+        state
+            .ad_provider
+            .resolve_segment_with_tracking(&ad_name, &session_id)
+    }
+    .ok_or_else(|| {
+        crate::error::RitcherError::InternalError(format!(
+            "Failed to resolve ad segment URL for: {}",
+            ad_name
+        ))
+    })?;
 
-    // Fire tracking beacons (non-blocking) if present
-    if let Some(tracking) = &resolved.tracking {
-        // Fire impressions on first segment
-        if tracking.segment_index == 0 {
-            tracking::fire_impressions(state.http_client.clone(), &tracking.impression_urls);
+    // Beacon macro values and the viewer headers forwarded with beacons
+    let beacon_ctx = match &resolved.tracking {
+        Some(tracking) => {
+            let viewer = request_ad_context(&state, &session_id, &headers, &HashMap::new()).await;
+            let beacon_headers = if state.config.forward_viewer_headers.on_beacons() {
+                viewer.viewer_headers()
+            } else {
+                HeaderMap::new()
+            };
+            Some((
+                tracking::macro_context(tracking, viewer.macro_context()),
+                beacon_headers,
+            ))
         }
+        None => None,
+    };
 
-        // Fire quartile events
-        let events = tracking::events_for_segment(
-            tracking.segment_index,
-            tracking.total_segments,
-            &tracking.tracking_events,
-        );
-        for event in events {
-            tracking::fire_beacon(
-                state.http_client.clone(),
-                event.url.clone(),
-                event.event.clone(),
-            );
-        }
+    // Fire tracking beacons (non-blocking) if present
+    if let (Some(tracking), Some((ctx, beacon_headers))) = (&resolved.tracking, &beacon_ctx) {
+        tracking::fire_segment_beacons(state.http_client.clone(), tracking, ctx, beacon_headers);
     }
 
     let ad_url = &resolved.url;
+
+    // Segments pre-warmed for a prefetched break are served from memory
+    if let Some((body, content_type)) = state.segment_cache.get(ad_url) {
+        metrics::record_request("ad", 200);
+        metrics::record_duration("ad", start);
+        return Ok((
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, content_type.as_str())],
+            Body::from(body),
+        )
+            .into_response());
+    }
+
     info!("Fetching ad segment from: {}", ad_url);
 
     match fetch_with_retry(&state.http_client, ad_url, &RetryConfig::default()).await {
@@ -88,11 +141,17 @@ pub async fn serve_ad(
                 .into_response())
         }
         Err(e) => {
-            // Fire error beacon if tracking metadata is present
+            // Fire error beacons if tracking metadata is present
             if let Some(tracking) = &resolved.tracking
-                && let Some(error_url) = &tracking.error_url
+                && let Some((ctx, beacon_headers)) = beacon_ctx
             {
-                tracking::fire_error(state.http_client.clone(), error_url);
+                let ctx = ctx.with_error_code(tracking::media_error_code(&e));
+                tracking::fire_error(
+                    state.http_client.clone(),
+                    &tracking.error_urls,
+                    &ctx,
+                    &beacon_headers,
+                );
             }
 
             metrics::record_request("ad", 502);
@@ -0,0 +1,57 @@
+//! Viewer ad context of stitch requests
+//!
+//! The playlist and manifest handlers start sessions: the first request of
+//! a session captures the viewer's [`AdRequestContext`] and stores it with
+//! the session. Later requests (variant playlists, asset lists, ad
+//! segments) reuse the stored context, so every ad request of the session
+//! carries the same targeting. Viewer headers missing from the stored
+//! context (IP, user agent, referer) are taken from the current request.
+
+use crate::{ad::AdRequestContext, server::state::AppState};
+use axum::http::HeaderMap;
+use std::collections::HashMap;
+
+/// Start or resume the session and return its ad context
+pub(crate) async fn session_ad_context(
+    state: &AppState,
+    session_id: &str,
+    origin_url: &str,
+    headers: &HeaderMap,
+    params: &HashMap<String, String>,
+) -> AdRequestContext {
+    let captured =
+        AdRequestContext::from_request(headers, params, &state.config.ad_param_allowlist);
+    let session = state
+        .sessions
+        .get_or_create(
+            session_id.to_string(),
+            origin_url.to_string(),
+            captured.clone(),
+        )
+        .await;
+    let mut context = session.ad_context;
+    context.fill_viewer(&captured);
+    context
+}
+
+/// Ad context of an existing session
+///
+/// Falls back to the context of this request when the session is unknown
+/// (expired, or started on another instance without a shared store).
+pub(crate) async fn request_ad_context(
+    state: &AppState,
+    session_id: &str,
+    headers: &HeaderMap,
+    params: &HashMap<String, String>,
+) -> AdRequestContext {
+    let captured =
+        AdRequestContext::from_request(headers, params, &state.config.ad_param_allowlist);
+    match state.sessions.get(session_id).await {
+        Some(session) => {
+            let mut context = session.ad_context;
+            context.fill_viewer(&captured);
+            context
+        }
+        None => captured,
+    }
+}
@@ -0,0 +1,109 @@
+//! DASH SGAI alternative MPD endpoint
+//!
+//! Called by DASH players (dash.js, Shaka) when a
+//! `urn:mpeg:dash:event:alternativeMPD:replace:2025` / `insert:2025` Event
+//! fires: the `url` of its `ReplacePresentation` / `InsertPresentation`
+//! element points here. Returns a static MPD holding one ad Period built
+//! from the ad provider's decision for the break, so the player plays the
+//! ad natively before returning to the content MPD.
+
+use crate::{
+    dash::{interleaver, parser},
+    error::{Result, RitcherError},
+    metrics,
+    server::{
+        handlers::{
+            ad_context::request_ad_context,
+            asset_list::{scheduled_source, validate_dur_param},
+        },
+        state::AppState,
+        url_validation::validate_session_id,
+    },
+};
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use std::collections::HashMap;
+use std::time::Instant;
+use tracing::info;
+
+/// Serve the alternative ad MPD for one DASH SGAI break.
+///
+/// Query params:
+/// - `dur` -- requested ad break duration in seconds (default: 30.0, max: 600.0)
+/// - `vmap` -- `1` when the break comes from the VMAP schedule (see the
+///   asset-list endpoint)
+///
+/// Returns 404 when the ad provider has nothing to fill the break, so the
+/// player stays on the content MPD.
+pub async fn serve_ad_mpd(
+    Path((session_id, break_id)): Path<(String, String)>,
+    Query(params): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    State(state): State<AppState>,
+) -> Result<Response> {
+    validate_session_id(&session_id)?;
+    let start = Instant::now();
+    info!(
+        "Serving ad MPD for session: {} break: {}",
+        session_id, break_id
+    );
+
+    let break_idx: usize = break_id.parse().map_err(|_| {
+        RitcherError::InvalidOrigin("Invalid break id: must be a number".to_string())
+    })?;
+
+    let duration: f32 = match params.get("dur") {
+        Some(d) => validate_dur_param(d)?,
+        None => 30.0,
+    };
+
+    let ad_context = request_ad_context(&state, &session_id, &headers, &params).await;
+    let ad_segments = match scheduled_source(&state, &params, &break_id).await? {
+        Some(source) => {
+            state
+                .ad_provider
+                .get_ad_segments_for_source(&source, duration, &session_id, &ad_context)
+                .await
+        }
+        None => {
+            state
+                .ad_provider
+                .get_ad_segments(duration, &session_id, &ad_context)
+                .await
+        }
+    };
+
+    if ad_segments.is_empty() {
+        info!(
+            "No ads for break {} of session {}, returning 404",
+            break_idx, session_id
+        );
+        metrics::record_request("ad_mpd", 404);
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+
+    let mpd =
+        interleaver::build_ad_mpd(&ad_segments, break_idx, &session_id, &state.config.base_url);
+    let mpd_xml = parser::serialize_mpd(&mpd)?;
+
+    info!(
+        "Ad MPD: {} segment(s) for session {} break {} (duration {}s)",
+        ad_segments.len(),
+        session_id,
+        break_idx,
+        duration
+    );
+
+    metrics::record_request("ad_mpd", 200);
+    metrics::record_duration("ad_mpd", start);
+
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/dash+xml")],
+        mpd_xml,
+    )
+        .into_response())
+}
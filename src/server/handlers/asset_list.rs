@@ -16,15 +16,19 @@
 //! {"ASSETS": [...], "X-VERIFICATIONS": [{"vendor": "...", "resource": "...", ...}]}
 //! ```
 
-use crate::ad::vast::Verification;
+use crate::ad::vast::{Verification, VmapAdSource};
 use crate::{
     error::Result,
     metrics,
-    server::{state::AppState, url_validation::validate_session_id},
+    server::{
+        handlers::ad_context::request_ad_context, state::AppState,
+        url_validation::validate_session_id,
+    },
 };
 use axum::{
     Json,
     extract::{Path, Query, State},
+    http::HeaderMap,
     response::{IntoResponse, Response},
 };
 use serde::Serialize;
@@ -82,7 +86,7 @@ const MAX_DUR: f32 = 600.0;
 ///
 /// Must be parseable as `f32`, finite (not NaN/Infinity), and in the
 /// range `0.0..=600.0`. Returns HTTP 400 on invalid input.
-fn validate_dur_param(value: &str) -> crate::error::Result<f32> {
+pub(crate) fn validate_dur_param(value: &str) -> crate::error::Result<f32> {
     let dur: f32 = value.parse().map_err(|_| {
         crate::error::RitcherError::InvalidOrigin(
             "Invalid dur parameter: must be a number".to_string(),
@@ -102,6 +106,32 @@ fn validate_dur_param(value: &str) -> crate::error::Result<f32> {
     Ok(dur)
 }
 
+/// Look up the VMAP ad source of a scheduled break
+///
+/// Scheduled (VMAP) breaks are signalled with `vmap=1` and the break's VMAP
+/// index as break id. Returns None for breaks detected from CUE markers.
+pub(crate) async fn scheduled_source(
+    state: &AppState,
+    params: &HashMap<String, String>,
+    break_id: &str,
+) -> crate::error::Result<Option<VmapAdSource>> {
+    if params.get("vmap").map(String::as_str) != Some("1") {
+        return Ok(None);
+    }
+    let Some(schedule) = &state.vmap else {
+        return Err(crate::error::RitcherError::InvalidOrigin(
+            "VMAP break requested but no VMAP_URL is configured".to_string(),
+        ));
+    };
+    let vmap_index: usize = break_id.parse().map_err(|_| {
+        crate::error::RitcherError::InvalidOrigin("Invalid break id: must be a number".to_string())
+    })?;
+    schedule
+        .ad_source(&state.http_client, &state.manifest_cache, vmap_index)
+        .await
+        .map(Some)
+}
+
 /// Serve HLS Interstitials asset-list JSON.
 ///
 /// Called by the player for each ad break it encounters. Returns the list of
@@ -109,9 +139,12 @@ fn validate_dur_param(value: &str) -> crate::error::Result<f32> {
 ///
 /// Query params:
 /// - `dur` -- requested ad break duration in seconds (default: 30.0, max: 600.0)
+/// - `vmap` -- `1` when the break comes from the VMAP schedule; the break
+///   id is then its VMAP index and the break's own ad source fills it
 pub async fn serve_asset_list(
     Path((session_id, break_id)): Path<(String, String)>,
     Query(params): Query<HashMap<String, String>>,
+    headers: HeaderMap,
     State(state): State<AppState>,
 ) -> Result<Response> {
     validate_session_id(&session_id)?;
@@ -126,10 +159,21 @@ pub async fn serve_asset_list(
         None => 30.0,
     };
 
-    let creatives = state
-        .ad_provider
-        .get_ad_creatives(duration, &session_id)
-        .await;
+    let ad_context = request_ad_context(&state, &session_id, &headers, &params).await;
+    let creatives = match scheduled_source(&state, &params, &break_id).await? {
+        Some(source) => {
+            state
+                .ad_provider
+                .get_ad_creatives_for_source(&source, duration, &session_id, &ad_context)
+                .await
+        }
+        None => {
+            state
+                .ad_provider
+                .get_ad_creatives(duration, &session_id, &ad_context)
+                .await
+        }
+    };
 
     // Collect all unique verifications across all creatives.
     // In a typical VAST response all creatives from the same InLine share the
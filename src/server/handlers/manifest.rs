@@ -1,6 +1,16 @@
+use super::{ad_context::session_ad_context, schedule::requested_channel};
 use crate::{
+    ad::{
+        context::AdRequestContext,
+        provider::AdSegment,
+        shared_decision::{SharedDecisions, timeline_break_key},
+    },
     config::StitchingMode,
-    dash::{cue, interleaver, parser, sgai},
+    dash::{
+        cue::{self, DashAdBreak},
+        inband, interleaver, parser, schedule,
+        sgai::{self, DashSgaiScheme},
+    },
     error::Result,
     metrics,
     server::{
@@ -11,23 +21,38 @@ use crate::{
 };
 use axum::{
     extract::{Path, Query, State},
-    http::{StatusCode, header},
+    http::{HeaderMap, StatusCode, header},
     response::{IntoResponse, Response},
 };
+use dash_mpd::MPD;
 use futures_util::StreamExt;
 use std::collections::HashMap;
+use std::future::Future;
 use std::time::Instant;
-use tracing::info;
+use tracing::{info, warn};
+
+/// Prefetched decisions match a break starting within this many seconds
+const PREFETCH_TOLERANCE_SECS: f64 = 0.5;
 
 /// Serve a modified DASH MPD with stitched ad Periods.
 ///
-/// Fetches the origin MPD, detects SCTE-35 EventStream ad breaks, and
-/// either inserts ad Periods (SSAI) or injects callback EventStreams (SGAI).
+/// Fetches the origin MPD, detects SCTE-35 EventStream ad breaks plus the
+/// breaks scheduled for the `channel` query parameter's channel (or, for
+/// static MPDs without any, places the VMAP schedule's breaks), and either
+/// inserts ad Periods (SSAI) or injects callback EventStreams (SGAI). Ad
+/// requests carry the session's viewer context, except for shared ad
+/// decisions, whose ad Periods are stitched under the decision ID instead
+/// of the session ID (see [`crate::ad::shared_decision`]); other sessions
+/// decide each break once for all MPD refreshes (see
+/// [`crate::ad::break_decision`]). With ad prefetching enabled, breaks of dynamic MPDs signalled well ahead of the
+/// live edge are decided in the background and stitched once they come
+/// close.
 ///
 /// Returns `application/dash+xml` with HTTP 200 on success.
 pub async fn serve_manifest(
     Path(session_id): Path<String>,
     Query(params): Query<HashMap<String, String>>,
+    headers: HeaderMap,
     State(state): State<AppState>,
 ) -> Result<Response> {
     validate_session_id(&session_id)?;
@@ -43,6 +68,11 @@ pub async fn serve_manifest(
         &state.config.origin_url
     };
 
+    // Channel whose break schedule applies to this stream
+    let channel = requested_channel(&params)?;
+
+    let ad_context = session_ad_context(&state, &session_id, origin_url, &headers, &params).await;
+
     info!("Fetching MPD from origin: {}", origin_url);
 
     // Try manifest cache first, then fetch from origin
@@ -118,8 +148,71 @@ pub async fn serve_manifest(
         .unwrap_or(origin_url);
 
     // Step 1: Detect ad breaks from EventStream/SCTE-35
-    let ad_breaks = cue::detect_dash_ad_breaks(&mpd);
+    let mut ad_breaks =
+        cue::detect_dash_ad_breaks_with_types(&mpd, &state.config.scte35_segmentation_types);
+
+    // Merge breaks the segment proxy found in-band (emsg) for this session
+    if state.config.inband_scte35 {
+        let inband_breaks = state.inband_breaks.breaks(&session_id);
+        inband::merge_inband_breaks(&mpd, &mut ad_breaks, &inband_breaks);
+    }
+
+    // Merge the channel's scheduled breaks, placed via availabilityStartTime
+    let channel_breaks = state.break_schedule.breaks(channel);
+    schedule::merge_channel_breaks(&mpd, &mut ad_breaks, &channel_breaks);
 
+    // Static MPDs without SCTE-35 signals take their breaks from the VMAP
+    // schedule, when one is configured. SSAI splits content Periods so
+    // every break sits on a Period boundary.
+    let mut scheduled_sources = Vec::new();
+    if ad_breaks.is_empty()
+        && mpd.mpdtype.as_deref() != Some("dynamic")
+        && let Some(schedule) = &state.vmap
+    {
+        match schedule
+            .fetch(&state.http_client, &state.manifest_cache)
+            .await
+        {
+            Ok(vmap) => {
+                let split_periods = state.config.stitching_mode == StitchingMode::Ssai;
+                for (ad_break, scheduled) in
+                    schedule::schedule_ad_breaks(&mut mpd, &vmap, split_periods)
+                {
+                    ad_breaks.push(ad_break);
+                    scheduled_sources.push(scheduled.source);
+                }
+            }
+            Err(e) => warn!("VMAP fetch failed, serving without scheduled breaks: {}", e),
+        }
+    }
+
+    // Ad Periods of shared ad decisions are stitched under the decision ID,
+    // making them the same for every session
+    let ssai = state.config.stitching_mode == StitchingMode::Ssai;
+    let decision_id = if ssai {
+        state
+            .shared_decisions
+            .decision_id(origin_url, channel, &ad_context)
+    } else {
+        None
+    };
+    let stitch_id = decision_id.as_deref().unwrap_or(&session_id);
+
+    // Breaks signalled well ahead of the live edge are decided in the
+    // background and stitched once they come close
+    let prefetching = ssai && state.prefetcher.is_enabled();
+    if prefetching {
+        let upcoming = schedule::take_upcoming_breaks(&mpd, &mut ad_breaks);
+        if !upcoming.is_empty() {
+            let ctx = match decision_id {
+                Some(_) => state.shared_decisions.decision_context(&ad_context),
+                None => ad_context.clone(),
+            };
+            state.prefetcher.prefetch(stitch_id, &upcoming, &ctx);
+        }
+    }
+
+    let mut alternative_presentations = Vec::new();
     if !ad_breaks.is_empty() {
         info!("Detected {} ad break(s)", ad_breaks.len());
         metrics::record_ad_breaks(ad_breaks.len());
@@ -128,12 +221,59 @@ pub async fn serve_manifest(
             StitchingMode::Ssai => {
                 // Step 2: Get ad segments for each break
                 let mut ad_segments_per_break = Vec::with_capacity(ad_breaks.len());
-                for ad_break in &ad_breaks {
+                for (break_idx, ad_break) in ad_breaks.iter().enumerate() {
                     // DASH ad break durations (f64) are typically < 300s; f32
                     // precision loss at that magnitude is negligible for ad fetching.
                     #[allow(clippy::cast_possible_truncation)]
                     let dur = ad_break.duration as f32;
-                    let segs = state.ad_provider.get_ad_segments(dur, &session_id).await;
+                    let break_start = schedule::break_start(&mpd, ad_break);
+                    let break_key = timeline_break_key(break_start);
+                    let break_ctx = ad_context.clone().with_upid(ad_break.upid.clone());
+                    let request_ctx = match decision_id {
+                        Some(_) => state.shared_decisions.decision_context(&break_ctx),
+                        None => break_ctx.clone().with_break_key(break_key),
+                    };
+                    let request = match scheduled_sources.get(break_idx) {
+                        Some(source) => state.ad_provider.get_ad_segments_for_source(
+                            source,
+                            dur,
+                            stitch_id,
+                            &request_ctx,
+                        ),
+                        None => state
+                            .ad_provider
+                            .get_ad_segments(dur, stitch_id, &request_ctx),
+                    };
+                    // Breaks decided ahead of time are matched by wall-clock
+                    // start, computed the same way on every refresh
+                    let start = schedule::wall_clock(&mpd, break_start);
+                    let request = state.prefetcher.segments(
+                        stitch_id,
+                        start,
+                        dur,
+                        PREFETCH_TOLERANCE_SECS,
+                        request,
+                    );
+                    let segs = match &decision_id {
+                        Some(decision_id) => {
+                            shared_segments(
+                                &state.shared_decisions,
+                                &mpd,
+                                ad_break,
+                                decision_id,
+                                &session_id,
+                                &break_ctx,
+                                request,
+                            )
+                            .await
+                        }
+                        None => {
+                            state
+                                .break_decisions
+                                .segments(&session_id, break_key, request)
+                                .await
+                        }
+                    };
                     ad_segments_per_break.push(segs);
                 }
 
@@ -142,18 +282,29 @@ pub async fn serve_manifest(
                     mpd,
                     &ad_breaks,
                     &ad_segments_per_break,
-                    &session_id,
+                    stitch_id,
                     &state.config.base_url,
                 );
             }
             StitchingMode::Sgai => {
-                // SGAI: inject callback EventStreams instead of ad Periods
-                sgai::inject_dash_callbacks(
-                    &mut mpd,
-                    &ad_breaks,
-                    &session_id,
-                    &state.config.base_url,
-                );
+                // SGAI: inject signalling EventStreams instead of ad Periods
+                match state.config.dash_sgai_scheme {
+                    DashSgaiScheme::Callback => sgai::inject_dash_callbacks(
+                        &mut mpd,
+                        &ad_breaks,
+                        &session_id,
+                        &state.config.base_url,
+                    ),
+                    scheme => {
+                        alternative_presentations = sgai::inject_dash_alternative_mpd(
+                            &mut mpd,
+                            &ad_breaks,
+                            &session_id,
+                            &state.config.base_url,
+                            scheme,
+                        );
+                    }
+                }
                 sgai::strip_scte35_event_streams(&mut mpd);
                 metrics::record_interstitials(ad_breaks.len());
             }
@@ -163,10 +314,15 @@ pub async fn serve_manifest(
     }
 
     // Step 4: Rewrite URLs to proxy through stitcher
-    parser::rewrite_dash_urls(&mut mpd, &session_id, &state.config.base_url, origin_base)?;
+    parser::rewrite_dash_urls(&mut mpd, stitch_id, &state.config.base_url, origin_base)?;
 
     // Step 5: Serialize MPD to XML
-    let mpd_xml = parser::serialize_mpd(&mpd)?;
+    let mut mpd_xml = parser::serialize_mpd(&mpd)?;
+
+    // Alternative MPD Events carry child elements dash_mpd cannot model
+    if !alternative_presentations.is_empty() {
+        mpd_xml = sgai::render_alternative_presentations(&mpd_xml, &alternative_presentations)?;
+    }
 
     metrics::record_request("manifest", 200);
     metrics::record_duration("manifest", start);
@@ -179,3 +335,40 @@ pub async fn serve_manifest(
     )
         .into_response())
 }
+
+/// Shared ad segments of a DASH break, deciding them with `decide` when the
+/// break has no decision yet
+///
+/// Breaks are keyed by their start on the presentation timeline, which
+/// stays put as a live MPD's Periods come and go. The session's beacons
+/// fire for the ad segments the live edge has reached; the whole break in
+/// static MPDs.
+async fn shared_segments<F>(
+    decisions: &SharedDecisions,
+    mpd: &MPD,
+    ad_break: &DashAdBreak,
+    decision_id: &str,
+    session_id: &str,
+    ad_context: &AdRequestContext,
+    decide: F,
+) -> Vec<AdSegment>
+where
+    F: Future<Output = Vec<AdSegment>>,
+{
+    let start = schedule::break_start(mpd, ad_break);
+    let break_key = timeline_break_key(start);
+    // Seconds into a break are small; f32 precision is plenty
+    #[allow(clippy::cast_possible_truncation)]
+    let elapsed = schedule::live_edge_elapsed(mpd, start) as f32;
+
+    let segments = decisions.segments(decision_id, break_key, decide).await;
+    decisions.track(
+        session_id,
+        decision_id,
+        break_key,
+        &segments,
+        elapsed,
+        ad_context,
+    );
+    segments
+}
@@ -1,8 +1,11 @@
 pub mod ad;
+pub mod ad_context;
+pub mod ad_mpd;
 pub mod asset_list;
 pub mod demo;
 pub mod health;
 pub mod manifest;
 pub mod metrics;
 pub mod playlist;
+pub mod schedule;
 pub mod segment;
@@ -1,8 +1,13 @@
+use super::{ad_context::session_ad_context, schedule::requested_channel};
 use crate::{
-    ad::{AdProvider, interleaver},
-    config::StitchingMode,
+    ad::{
+        AdProvider, AdRequestContext, break_decision::BreakDecisions, break_schedule::ChannelBreak,
+        interleaver, prefetch::AdPrefetcher, provider::AdSegment, shared_decision::SharedDecisions,
+        vast::VmapResponse,
+    },
+    config::{Config, StitchingMode},
     error::Result,
-    hls::{cue, interstitial, ll_hls, parser},
+    hls::{cue, interstitial, ll_hls, parser, schedule},
     metrics,
     server::{
         MAX_MANIFEST_SIZE,
@@ -12,25 +17,34 @@ use crate::{
 };
 use axum::{
     extract::{Path, Query, State},
-    http::{StatusCode, header},
+    http::{HeaderMap, StatusCode, header},
     response::{IntoResponse, Response},
 };
 use futures_util::StreamExt;
-use m3u8_rs::Playlist;
+use m3u8_rs::{MediaPlaylist, MediaSegment, Playlist};
 use std::collections::HashMap;
+use std::future::Future;
 use std::time::Instant;
-use tracing::info;
+use tracing::{info, warn};
 
 /// Serve a modified HLS playlist with stitched ad markers.
 ///
-/// Fetches the origin playlist, detects SCTE-35 CUE ad breaks, and either
-/// interleaves ad segments (SSAI) or injects `EXT-X-DATERANGE` interstitial
-/// markers (SGAI). LL-HLS query parameters are forwarded to the origin.
+/// Fetches the origin playlist, detects SCTE-35 CUE ad breaks (plus the
+/// breaks scheduled for the `channel` query parameter's channel), and
+/// either interleaves ad segments (SSAI) or injects `EXT-X-DATERANGE`
+/// interstitial markers (SGAI). LL-HLS query parameters are forwarded to
+/// the origin. The session's viewer context (see [`AdRequestContext`]) is
+/// captured on its first request and sent with every ad request.
+///
+/// With `AD_DECISION_SCOPE` set to `channel` or `cohort`, SSAI media
+/// playlists are stitched under the shared decision ID instead of the
+/// session ID (see [`crate::ad::shared_decision`]).
 ///
 /// Returns `application/vnd.apple.mpegurl` with HTTP 200 on success.
 pub async fn serve_playlist(
     Path(session_id): Path<String>,
     Query(params): Query<HashMap<String, String>>,
+    headers: HeaderMap,
     State(state): State<AppState>,
 ) -> Result<Response> {
     validate_session_id(&session_id)?;
@@ -49,6 +63,11 @@ pub async fn serve_playlist(
     // Validate LL-HLS numeric params before forwarding.
     validate_ll_hls_params(&params)?;
 
+    // Channel whose break schedule applies to this stream
+    let channel = requested_channel(&params)?;
+
+    let ad_context = session_ad_context(&state, &session_id, origin_url, &headers, &params).await;
+
     // Forward LL-HLS query params (_HLS_msn, _HLS_part, etc.) to origin
     // so the origin can block until the requested MSN/part is available.
     let fetch_url = append_ll_hls_params(origin_url, &params);
@@ -142,6 +161,14 @@ pub async fn serve_playlist(
     // Parse HLS playlist
     let playlist = parser::parse_hls_playlist(&content)?;
 
+    // Cues written after the last segment announce a break at the live
+    // edge; the parser drops them
+    let trailing = if state.prefetcher.is_enabled() {
+        cue::trailing_cues(&content)
+    } else {
+        None
+    };
+
     // Extract base URL from origin
     let origin_base = origin_url
         .rsplit_once('/')
@@ -155,18 +182,80 @@ pub async fn serve_playlist(
         _ => "video",
     };
 
+    // VOD playlists without CUE markers take their breaks from the VMAP
+    // schedule, when one is configured
+    let vmap = match (&playlist, &state.vmap) {
+        (Playlist::MediaPlaylist(media_playlist), Some(schedule))
+            if media_playlist.end_list && track_type != "subtitles" =>
+        {
+            match schedule
+                .fetch(&state.http_client, &state.manifest_cache)
+                .await
+            {
+                Ok(vmap) => Some(vmap),
+                Err(e) => {
+                    warn!("VMAP fetch failed, serving without scheduled breaks: {}", e);
+                    None
+                }
+            }
+        }
+        _ => None,
+    };
+
+    let channel_breaks = state.break_schedule.breaks(channel);
+
+    // Media playlists of shared ad decisions are stitched under the
+    // decision ID, making them the same for every session sharing it
+    let decision_id = state
+        .shared_decisions
+        .decision_id(origin_url, channel, &ad_context);
+    let shared = match (&decision_id, &playlist) {
+        (Some(decision_id), Playlist::MediaPlaylist(_))
+            if state.config.stitching_mode == StitchingMode::Ssai =>
+        {
+            Some(SharedBreaks {
+                decisions: &state.shared_decisions,
+                decision_id,
+                session_id: &session_id,
+            })
+        }
+        _ => None,
+    };
+    let stitch_id = shared.map_or(session_id.as_str(), |shared| shared.decision_id);
+
     // Process playlist through the ad insertion pipeline
-    let modified_playlist = process_playlist(
+    let mut modified_playlist = process_playlist(
         playlist,
-        &session_id,
+        stitch_id,
         &state.config.base_url,
         origin_base,
         state.ad_provider.as_ref(),
         track_type,
-        &state.config.stitching_mode,
+        &state.config,
+        vmap.as_ref(),
+        &channel_breaks,
+        &ad_context,
+        shared,
+        &state.break_decisions,
+        &state.prefetcher,
+        trailing.as_ref(),
     )
     .await?;
 
+    // Variant playlists are requested separately: keep them on this channel
+    if let Some(channel) = params.get("channel") {
+        parser::append_master_query_param(&mut modified_playlist, "channel", channel);
+    }
+
+    // Point slate padding at the slate rendition matching this variant
+    // (`bw` is set by the master playlist rewrite)
+    if let Playlist::MediaPlaylist(ref mut media_playlist) = modified_playlist
+        && let Some(slate) = state.ad_provider.slate()
+    {
+        let bandwidth = params.get("bw").and_then(|bw| bw.parse().ok());
+        slate.annotate_playlist(media_playlist, bandwidth, stitch_id, &state.config.base_url);
+    }
+
     // Serialize to string
     let mut playlist_str = parser::serialize_playlist(modified_playlist)?;
 
@@ -176,7 +265,7 @@ pub async fn serve_playlist(
         playlist_str = ll_hls::inject_ll_hls_tags(&playlist_str, tags);
         playlist_str = ll_hls::rewrite_ll_hls_uris(
             &playlist_str,
-            &session_id,
+            stitch_id,
             &state.config.base_url,
             origin_base,
         );
@@ -203,9 +292,23 @@ pub async fn serve_playlist(
 ///   otherwise pass through unchanged
 /// - `"subtitles"` — skip ad insertion entirely, only rewrite URLs
 ///
-/// The `stitching_mode` selects the insertion strategy:
+/// `config.scte35_segmentation_types` selects which SCTE-35 segmentation
+/// types open and close a break, and `config.stitching_mode` selects the
+/// insertion strategy:
 /// - `StitchingMode::Ssai` — replace content segments with ad segments (traditional SSAI)
 /// - `StitchingMode::Sgai` — inject EXT-X-DATERANGE interstitial markers (HLS Interstitials)
+///
+/// `channel_breaks` from the channel schedule are matched against
+/// `EXT-X-PROGRAM-DATE-TIME` and handled like CUE-signalled breaks. When an
+/// ENDLIST playlist has no breaks, breaks from `vmap` are inserted between
+/// content segments instead (pre-, mid- and post-rolls). Every ad request
+/// carries the viewer's `ad_context`, except for `shared` decisions, which
+/// are made once for all sessions sharing them; `session_id` is then the
+/// decision ID. Other sessions decide each break once in `break_decisions`,
+/// so refreshes and renditions stitch the same pod. Breaks signalled ahead of their start, including by the
+/// `trailing` cues after the last segment, are handed to the `prefetcher`,
+/// whose decisions are used once the breaks arrive.
+#[allow(clippy::too_many_arguments)]
 async fn process_playlist(
     playlist: Playlist,
     session_id: &str,
@@ -213,7 +316,14 @@ async fn process_playlist(
     origin_base: &str,
     ad_provider: &dyn AdProvider,
     track_type: &str,
-    stitching_mode: &StitchingMode,
+    config: &Config,
+    vmap: Option<&VmapResponse>,
+    channel_breaks: &[ChannelBreak],
+    ad_context: &AdRequestContext,
+    shared: Option<SharedBreaks<'_>>,
+    break_decisions: &BreakDecisions,
+    prefetcher: &AdPrefetcher,
+    trailing: Option<&MediaSegment>,
 ) -> Result<Playlist> {
     // Handle MasterPlaylist: rewrite variant-stream URLs through stitcher
     if matches!(&playlist, Playlist::MasterPlaylist(_)) {
@@ -232,8 +342,52 @@ async fn process_playlist(
         return Ok(playlist);
     };
 
-    // Step 1: Detect ad breaks from CUE tags
-    let ad_breaks = cue::detect_ad_breaks(&media_playlist);
+    // Stitcher URLs of ad segments
+    let segment_url = |name: &str, break_idx, segment_idx| match shared {
+        Some(shared) => shared.decisions.segment_url(
+            ad_provider,
+            name,
+            shared.decision_id,
+            base_url,
+            break_idx,
+            segment_idx,
+        ),
+        None => ad_provider.segment_url(name, session_id, base_url, break_idx, segment_idx),
+    };
+
+    // Step 1: Detect ad breaks from CUE tags and SCTE-35 signals, then add
+    // the channel's scheduled breaks. Breaks signalled ahead of their start
+    // are decided in the background and stitched once they begin.
+    let prefetching = prefetcher.is_enabled() && config.stitching_mode == StitchingMode::Ssai;
+    let types = &config.scte35_segmentation_types;
+    let mut ad_breaks = if prefetching {
+        cue::detect_started_ad_breaks(&media_playlist, types)
+    } else {
+        cue::detect_ad_breaks_with_types(&media_playlist, types)
+    };
+    schedule::merge_channel_breaks(&media_playlist, &mut ad_breaks, channel_breaks);
+
+    if prefetching {
+        let mut upcoming = cue::detect_upcoming_breaks(&media_playlist, trailing, types);
+        upcoming.extend(schedule::upcoming_channel_breaks(
+            &media_playlist,
+            channel_breaks,
+        ));
+        if !upcoming.is_empty() {
+            let ctx = match shared {
+                Some(shared) => shared.decisions.decision_context(ad_context),
+                None => ad_context.clone(),
+            };
+            prefetcher.prefetch(session_id, &upcoming, &ctx);
+        }
+    }
+
+    let scheduled = match vmap {
+        Some(vmap) if ad_breaks.is_empty() && media_playlist.end_list => {
+            schedule::schedule_ad_breaks(&media_playlist, vmap)
+        }
+        _ => Vec::new(),
+    };
 
     if !ad_breaks.is_empty() {
         info!(
@@ -243,16 +397,58 @@ async fn process_playlist(
         );
         metrics::record_ad_breaks(ad_breaks.len());
 
-        match stitching_mode {
+        match config.stitching_mode {
             StitchingMode::Ssai => {
                 // Step 2: Get ad segments for each break
                 // For audio tracks, the same muxed ad segments are used — the player
                 // demuxes the audio track from the muxed container
                 let mut ad_segments_per_break = Vec::with_capacity(ad_breaks.len());
+                // Breaks decided ahead of time are matched by wall-clock start
+                let starts = schedule::segment_start_times(&media_playlist);
+                #[allow(clippy::cast_precision_loss)] // target durations are small
+                let tolerance = media_playlist.target_duration as f64;
                 for ad_break in &ad_breaks {
-                    let segs = ad_provider
-                        .get_ad_segments(ad_break.duration, session_id)
-                        .await;
+                    let start = starts
+                        .as_ref()
+                        .and_then(|starts| starts.get(ad_break.start_index).copied());
+                    let break_ctx = ad_context.clone().with_upid(ad_break.upid.clone());
+                    let segs = match shared {
+                        Some(shared) => {
+                            let decision_ctx = shared.decisions.decision_context(&break_ctx);
+                            let decide = prefetcher.segments(
+                                shared.decision_id,
+                                start,
+                                ad_break.duration,
+                                tolerance,
+                                ad_provider.get_ad_segments(
+                                    ad_break.duration,
+                                    shared.decision_id,
+                                    &decision_ctx,
+                                ),
+                            );
+                            shared
+                                .segments(&media_playlist, ad_break, &break_ctx, decide)
+                                .await
+                        }
+                        None => {
+                            let break_key = hls_break_key(&media_playlist, ad_break);
+                            let break_ctx = break_ctx.with_break_key(break_key);
+                            let decide = prefetcher.segments(
+                                session_id,
+                                start,
+                                ad_break.duration,
+                                tolerance,
+                                ad_provider.get_ad_segments(
+                                    ad_break.duration,
+                                    session_id,
+                                    &break_ctx,
+                                ),
+                            );
+                            break_decisions
+                                .segments(session_id, break_key, decide)
+                                .await
+                        }
+                    };
                     ad_segments_per_break.push(segs);
                 }
 
@@ -261,8 +457,7 @@ async fn process_playlist(
                     media_playlist,
                     &ad_breaks,
                     &ad_segments_per_break,
-                    session_id,
-                    base_url,
+                    segment_url,
                 );
             }
             StitchingMode::Sgai => {
@@ -279,6 +474,68 @@ async fn process_playlist(
                 metrics::record_interstitials(ad_breaks.len());
             }
         }
+    } else if !scheduled.is_empty() {
+        info!(
+            "Scheduled {} VMAP ad break(s) for {} track",
+            scheduled.len(),
+            track_type
+        );
+        metrics::record_ad_breaks(scheduled.len());
+
+        match config.stitching_mode {
+            StitchingMode::Ssai => {
+                let mut ad_breaks = Vec::with_capacity(scheduled.len());
+                let mut ad_segments_per_break = Vec::with_capacity(scheduled.len());
+                for (ad_break, scheduled_break) in &scheduled {
+                    let segs = match shared {
+                        Some(shared) => {
+                            let decision_ctx = shared.decisions.decision_context(ad_context);
+                            let decide = ad_provider.get_ad_segments_for_source(
+                                &scheduled_break.source,
+                                ad_break.duration,
+                                shared.decision_id,
+                                &decision_ctx,
+                            );
+                            shared
+                                .segments(&media_playlist, ad_break, ad_context, decide)
+                                .await
+                        }
+                        None => {
+                            let break_key = hls_break_key(&media_playlist, ad_break);
+                            let break_ctx = ad_context.clone().with_break_key(break_key);
+                            let decide = ad_provider.get_ad_segments_for_source(
+                                &scheduled_break.source,
+                                ad_break.duration,
+                                session_id,
+                                &break_ctx,
+                            );
+                            break_decisions
+                                .segments(session_id, break_key, decide)
+                                .await
+                        }
+                    };
+                    ad_breaks.push(ad_break.clone());
+                    ad_segments_per_break.push(segs);
+                }
+
+                media_playlist = interleaver::interleave_ads(
+                    media_playlist,
+                    &ad_breaks,
+                    &ad_segments_per_break,
+                    segment_url,
+                );
+            }
+            StitchingMode::Sgai => {
+                interstitial::ensure_program_date_time(&mut media_playlist);
+                interstitial::inject_scheduled_interstitials(
+                    &mut media_playlist,
+                    &scheduled,
+                    session_id,
+                    base_url,
+                );
+                metrics::record_interstitials(scheduled.len());
+            }
+        }
     } else if track_type == "audio" {
         // Audio rendition without CUE markers: pass through without ad insertion.
         // The muxed video ad segments already contain audio, but without CUE markers
@@ -295,6 +552,65 @@ async fn process_playlist(
     parser::rewrite_content_urls(playlist, session_id, base_url, origin_base)
 }
 
+/// Shared ad decisions applied to a session's media playlist
+#[derive(Clone, Copy)]
+struct SharedBreaks<'a> {
+    decisions: &'a SharedDecisions,
+    /// Decision ID the playlist is stitched under
+    decision_id: &'a str,
+    /// Session whose beacons fire
+    session_id: &'a str,
+}
+
+impl SharedBreaks<'_> {
+    /// Shared ad segments of `ad_break`, deciding them with `decide` when
+    /// the break has no decision yet
+    ///
+    /// Breaks are keyed by [`hls_break_key`]. The session's beacons fire
+    /// for the ad segments the break's content in the playlist has reached;
+    /// the whole break when the playlist is complete.
+    async fn segments<F>(
+        &self,
+        playlist: &MediaPlaylist,
+        ad_break: &cue::AdBreak,
+        ad_context: &AdRequestContext,
+        decide: F,
+    ) -> Vec<AdSegment>
+    where
+        F: Future<Output = Vec<AdSegment>>,
+    {
+        let break_key = hls_break_key(playlist, ad_break);
+        let elapsed = if playlist.end_list {
+            f32::INFINITY
+        } else {
+            playlist
+                .segments
+                .get(ad_break.start_index..ad_break.end_index.min(playlist.segments.len()))
+                .map_or(0.0, |segments| segments.iter().map(|s| s.duration).sum())
+        };
+
+        let segments = self
+            .decisions
+            .segments(self.decision_id, break_key, decide)
+            .await;
+        self.decisions.track(
+            self.session_id,
+            self.decision_id,
+            break_key,
+            &segments,
+            elapsed,
+            ad_context,
+        );
+        segments
+    }
+}
+
+/// Key of a break within a media playlist: the media sequence number of
+/// its first segment, the same in every refresh and rendition
+fn hls_break_key(playlist: &MediaPlaylist, ad_break: &cue::AdBreak) -> u64 {
+    playlist.media_sequence + ad_break.start_index as u64
+}
+
 /// Maximum allowed value for `_HLS_msn` and `_HLS_part` query parameters.
 ///
 /// Acts as a sanity check -- no real playlist should have a media sequence
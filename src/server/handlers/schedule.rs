@@ -0,0 +1,196 @@
+//! Channel break schedule API
+//!
+//! Operators push ad breaks for channels without SCTE-35 here; stitch
+//! requests pick them up through their `channel` query parameter (or the
+//! [`DEFAULT_CHANNEL`]).
+//!
+//! - `GET /schedule/{channel}/breaks` — list upcoming breaks
+//! - `POST /schedule/{channel}/breaks` — add one break or an array of them:
+//!
+//! ```json
+//! [{"id": "news-1", "start": "2026-10-18T12:00:00Z", "duration": 60},
+//!  {"start_in": 300, "duration": 30}]
+//! ```
+//!
+//! - `DELETE /schedule/{channel}/breaks/{break_id}` — remove a break
+//!
+//! Every call needs `Authorization: Bearer $SCHEDULE_API_TOKEN`. Without a
+//! configured token the API only answers in dev mode.
+
+use crate::{
+    ad::break_schedule::{BreakDefinition, ChannelBreak, DEFAULT_CHANNEL, validate_channel},
+    error::{Result, RitcherError},
+    server::state::AppState,
+};
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// A scheduled break as returned by the API
+#[derive(Debug, Serialize)]
+pub struct BreakView {
+    pub id: String,
+    /// RFC 3339 start
+    pub start: String,
+    /// RFC 3339 end
+    pub end: String,
+    /// Duration in seconds
+    pub duration: f64,
+}
+
+impl From<&ChannelBreak> for BreakView {
+    fn from(ad_break: &ChannelBreak) -> Self {
+        Self {
+            id: ad_break.id.clone(),
+            start: ad_break.start.to_rfc3339(),
+            end: ad_break.end().to_rfc3339(),
+            duration: ad_break.duration,
+        }
+    }
+}
+
+/// POST body: a single break or an array of breaks
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum BreakPayload {
+    One(BreakDefinition),
+    Many(Vec<BreakDefinition>),
+}
+
+/// List the unexpired breaks of a channel
+pub async fn list_breaks(
+    Path(channel): Path<String>,
+    headers: HeaderMap,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<BreakView>>> {
+    authorize(&state, &headers)?;
+    validate_channel(&channel)?;
+    let breaks = state.break_schedule.breaks(&channel);
+    Ok(Json(breaks.iter().map(BreakView::from).collect()))
+}
+
+/// Add breaks to a channel, replacing breaks with the same id
+///
+/// Returns HTTP 201 with the stored breaks. The whole request is rejected
+/// when any break is invalid.
+pub async fn add_breaks(
+    Path(channel): Path<String>,
+    headers: HeaderMap,
+    State(state): State<AppState>,
+    Json(payload): Json<BreakPayload>,
+) -> Result<Response> {
+    authorize(&state, &headers)?;
+    validate_channel(&channel)?;
+
+    let definitions = match payload {
+        BreakPayload::One(definition) => vec![definition],
+        BreakPayload::Many(definitions) => definitions,
+    };
+    let now = Utc::now();
+    let breaks = definitions
+        .into_iter()
+        .map(|d| d.resolve(now))
+        .collect::<Result<Vec<_>>>()?;
+    let views: Vec<BreakView> = breaks.iter().map(BreakView::from).collect();
+    state.break_schedule.add(&channel, breaks)?;
+
+    Ok((StatusCode::CREATED, Json(views)).into_response())
+}
+
+/// Remove a break; HTTP 204 on success, 404 when the channel has no such break
+pub async fn remove_break(
+    Path((channel, break_id)): Path<(String, String)>,
+    headers: HeaderMap,
+    State(state): State<AppState>,
+) -> Result<StatusCode> {
+    authorize(&state, &headers)?;
+    validate_channel(&channel)?;
+    if state.break_schedule.remove(&channel, &break_id) {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Ok(StatusCode::NOT_FOUND)
+    }
+}
+
+/// Channel named by a stitch request's `channel` query parameter
+pub(crate) fn requested_channel(params: &HashMap<String, String>) -> Result<&str> {
+    match params.get("channel") {
+        Some(channel) => {
+            validate_channel(channel)?;
+            Ok(channel)
+        }
+        None => Ok(DEFAULT_CHANNEL),
+    }
+}
+
+/// Check the request's bearer token against `SCHEDULE_API_TOKEN`
+fn authorize(state: &AppState, headers: &HeaderMap) -> Result<()> {
+    let Some(expected) = state.config.schedule_api_token.as_deref() else {
+        return if state.config.is_dev {
+            Ok(())
+        } else {
+            Err(RitcherError::Unauthorized(
+                "schedule API is disabled without SCHEDULE_API_TOKEN".to_string(),
+            ))
+        };
+    };
+
+    let provided = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    if constant_time_eq(provided.as_bytes(), expected.as_bytes()) {
+        Ok(())
+    } else {
+        Err(RitcherError::Unauthorized(
+            "missing or wrong schedule API token".to_string(),
+        ))
+    }
+}
+
+/// Compare two byte strings without short-circuiting on the first mismatch
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payload_accepts_one_or_many() {
+        let one: BreakPayload =
+            serde_json::from_str(r#"{"start_in": 10, "duration": 30}"#).unwrap();
+        assert!(matches!(one, BreakPayload::One(_)));
+
+        let many: BreakPayload = serde_json::from_str(
+            r#"[{"start": "2026-10-18T12:00:00Z", "duration": 30}, {"start_in": 5, "duration": 15}]"#,
+        )
+        .unwrap();
+        assert!(matches!(many, BreakPayload::Many(ref v) if v.len() == 2));
+    }
+
+    #[test]
+    fn requested_channel_defaults_and_validates() {
+        let mut params = HashMap::new();
+        assert_eq!(requested_channel(&params).unwrap(), DEFAULT_CHANNEL);
+        params.insert("channel".to_string(), "news".to_string());
+        assert_eq!(requested_channel(&params).unwrap(), "news");
+        params.insert("channel".to_string(), "../news".to_string());
+        assert!(requested_channel(&params).is_err());
+    }
+
+    #[test]
+    fn constant_time_eq_compares_bytes() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret2"));
+    }
+}
@@ -1,4 +1,5 @@
 use crate::{
+    dash::{emsg, inband::InbandBreakStore},
     error::Result,
     http_retry::{RetryConfig, fetch_with_retry},
     metrics,
@@ -8,15 +9,25 @@ use crate::{
     },
 };
 use axum::{
-    body::Body,
+    body::{Body, Bytes},
     extract::{Path, Query, State},
     http::{StatusCode, header},
     response::{IntoResponse, Response},
 };
+use futures_util::{Stream, StreamExt};
 use std::collections::HashMap;
 use std::time::Instant;
 use tracing::{info, warn};
 
+/// Upper bound on the segment prefix buffered for `emsg` scanning (64 KiB).
+///
+/// `emsg` boxes precede the first `moof`, so the prefix is normally a few
+/// hundred bytes; the cap bounds memory for segments without a `moof`.
+const MAX_EMSG_SCAN_BYTES: usize = 64 * 1024;
+
+/// fMP4/CMAF segment extensions that may carry `emsg` boxes
+const FMP4_EXTENSIONS: &[&str] = &[".m4s", ".mp4", ".m4v", ".m4a", ".cmfv", ".cmfa"];
+
 /// Reject segment paths containing path traversal sequences.
 ///
 /// Checks for `..` components that could escape the intended directory,
@@ -74,11 +85,60 @@ fn hex_val(b: u8) -> Option<u8> {
     }
 }
 
+/// Whether a segment path names an fMP4/CMAF media segment
+fn is_fmp4_segment(segment_path: &str) -> bool {
+    let path = segment_path.split('?').next().unwrap_or(segment_path);
+    FMP4_EXTENSIONS.iter().any(|ext| path.ends_with(ext))
+}
+
+/// Pass a segment stream through unchanged while scanning its prefix for
+/// in-band SCTE-35 `emsg` boxes.
+///
+/// Chunks are forwarded as they arrive; only the first bytes are copied
+/// aside until the first `moof`/`mdat` (or [`MAX_EMSG_SCAN_BYTES`]) is
+/// reached, then the prefix is scanned once and any breaks are recorded
+/// for the session.
+fn scan_emsg_stream<S, E>(
+    stream: S,
+    store: InbandBreakStore,
+    session_id: String,
+    types: crate::scte35::SegmentationTypes,
+) -> impl Stream<Item = std::result::Result<Bytes, E>>
+where
+    S: Stream<Item = std::result::Result<Bytes, E>>,
+{
+    let mut prefix: Vec<u8> = Vec::new();
+    let mut done = false;
+
+    stream.map(move |chunk| {
+        if !done && let Ok(bytes) = &chunk {
+            let take = bytes.len().min(MAX_EMSG_SCAN_BYTES - prefix.len());
+            prefix.extend_from_slice(&bytes[..take]);
+
+            let scan = emsg::scan_boxes(&prefix);
+            if scan.complete || prefix.len() >= MAX_EMSG_SCAN_BYTES {
+                done = true;
+                let breaks = emsg::detect_emsg_ad_breaks(&scan, &types);
+                let recorded = store.record(&session_id, breaks);
+                if recorded > 0 {
+                    metrics::record_inband_breaks(recorded);
+                }
+                prefix = Vec::new();
+            }
+        }
+        chunk
+    })
+}
+
 /// Proxy content segments from origin to the player.
 ///
 /// Validates the segment path against path-traversal attacks, then streams
 /// the segment from the origin CDN to the client without buffering.
 /// Uses [`fetch_with_retry`] for fault-tolerant HTTP fetching.
+///
+/// With `INBAND_SCTE35=true`, fMP4 segments are scanned for `emsg` SCTE-35
+/// on the way through (see [`scan_emsg_stream`]) and the breaks found are
+/// used by the session's next MPD.
 pub async fn serve_segment(
     Path((session_id, segment_path)): Path<(String, String)>,
     Query(params): Query<HashMap<String, String>>,
@@ -119,10 +179,21 @@ pub async fn serve_segment(
             metrics::record_request("segment", 200);
             metrics::record_duration("segment", start);
 
+            let body = if state.config.inband_scte35 && is_fmp4_segment(&segment_path) {
+                Body::from_stream(scan_emsg_stream(
+                    response.bytes_stream(),
+                    state.inband_breaks.clone(),
+                    session_id,
+                    state.config.scte35_segmentation_types.clone(),
+                ))
+            } else {
+                Body::from_stream(response.bytes_stream())
+            };
+
             Ok((
                 StatusCode::OK,
                 [(header::CONTENT_TYPE, content_type.as_str())],
-                Body::from_stream(response.bytes_stream()),
+                body,
             )
                 .into_response())
         }
@@ -225,6 +296,57 @@ mod tests {
         assert_eq!(percent_decode("%2"), "%2");
     }
 
+    // --- is_fmp4_segment ---
+
+    #[test]
+    fn fmp4_segment_extensions() {
+        assert!(is_fmp4_segment("video/1080p/seg-42.m4s"));
+        assert!(is_fmp4_segment("audio/chunk.cmfa"));
+        assert!(!is_fmp4_segment("hls/seg-42.ts"));
+        assert!(!is_fmp4_segment("chunklist.m3u8"));
+    }
+
+    // --- scan_emsg_stream ---
+
+    #[tokio::test]
+    async fn scan_emsg_stream_records_breaks_and_passes_bytes() {
+        use crate::dash::emsg::tests::{emsg_v1, po_start};
+
+        let mut segment = emsg_v1(
+            "urn:scte:scte35:2013:bin",
+            90_000,
+            900_000,
+            2_700_000,
+            9,
+            &po_start(),
+        );
+        segment.extend_from_slice(&[0, 0, 0, 16, b'm', b'o', b'o', b'f']);
+        segment.extend_from_slice(&[0; 8]);
+
+        // Split mid-box to exercise prefix accumulation across chunks
+        let (a, b) = segment.split_at(20);
+        let chunks: Vec<std::result::Result<Bytes, std::io::Error>> =
+            vec![Ok(Bytes::copy_from_slice(a)), Ok(Bytes::copy_from_slice(b))];
+        let store = InbandBreakStore::new();
+
+        let out: Vec<u8> = scan_emsg_stream(
+            futures_util::stream::iter(chunks),
+            store.clone(),
+            "session-1".to_string(),
+            crate::scte35::SegmentationTypes::default(),
+        )
+        .map(|c| c.unwrap())
+        .collect::<Vec<_>>()
+        .await
+        .concat();
+
+        assert_eq!(out, segment);
+        let breaks = store.breaks("session-1");
+        assert_eq!(breaks.len(), 1);
+        assert_eq!(breaks[0].media_time, 10.0);
+        assert_eq!(breaks[0].duration, 30.0);
+    }
+
     // --- hex_val ---
 
     #[test]
@@ -17,14 +17,14 @@ use axum::{
     http::{Method, header, header::HeaderValue},
     middleware::{self, Next},
     response::Response,
-    routing::get,
+    routing::{delete, get},
 };
 use metrics_exporter_prometheus::PrometheusBuilder;
 use state::AppState;
 use std::time::Duration;
 use tokio_util::sync::CancellationToken;
 use tower_http::cors::{Any, CorsLayer};
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 /// Version header value — resolved at compile time from Cargo.toml.
 static VERSION: HeaderValue = HeaderValue::from_static(env!("CARGO_PKG_VERSION"));
@@ -73,6 +73,9 @@ pub async fn build_router_with_token(config: Config, cancel: CancellationToken)
 
     // Spawn background task for ad cache eviction (TTL + size bound)
     let cleanup_ad_provider = state.ad_provider.clone();
+    let cleanup_breaks = state.break_decisions.clone();
+    let cleanup_shared = state.shared_decisions.clone();
+    let cleanup_prefetch = state.prefetcher.clone();
     let cancel_ad = cancel.clone();
     tokio::spawn(async move {
         let mut interval = tokio::time::interval(Duration::from_secs(60));
@@ -80,6 +83,9 @@ pub async fn build_router_with_token(config: Config, cancel: CancellationToken)
             tokio::select! {
                 _ = interval.tick() => {
                     cleanup_ad_provider.cleanup_cache();
+                    cleanup_breaks.cleanup();
+                    cleanup_shared.cleanup();
+                    cleanup_prefetch.cleanup();
                 }
                 _ = cancel_ad.cancelled() => {
                     info!("Ad cache cleanup task shutting down");
@@ -89,6 +95,77 @@ pub async fn build_router_with_token(config: Config, cancel: CancellationToken)
         }
     });
 
+    // Spawn background task to reload a playlist-based slate (initial load
+    // happens in AppState::new)
+    let slate_refresh = state
+        .ad_provider
+        .slate()
+        .is_some_and(|slate| slate.is_playlist());
+    if slate_refresh && state.config.slate_refresh_secs > 0 {
+        let refresh_provider = state.ad_provider.clone();
+        let refresh_client = state.http_client.clone();
+        let cancel_slate = cancel.clone();
+        let period = Duration::from_secs(state.config.slate_refresh_secs);
+        tokio::spawn(async move {
+            let mut interval =
+                tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {
+                        if let Some(slate) = refresh_provider.slate()
+                            && let Err(e) = slate.refresh(&refresh_client).await
+                        {
+                            warn!("Slate playlist refresh failed, keeping previous: {}", e);
+                        }
+                    }
+                    _ = cancel_slate.cancelled() => {
+                        info!("Slate refresh task shutting down");
+                        break;
+                    }
+                }
+            }
+        });
+    }
+
+    // Spawn background task for in-band break eviction (only populated when
+    // INBAND_SCTE35=true)
+    if state.config.inband_scte35 {
+        let cleanup_inband = state.inband_breaks.clone();
+        let cancel_inband = cancel.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(60));
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {
+                        cleanup_inband.cleanup();
+                    }
+                    _ = cancel_inband.cancelled() => {
+                        info!("In-band break cleanup task shutting down");
+                        break;
+                    }
+                }
+            }
+        });
+    }
+
+    // Spawn background task for scheduled break eviction
+    let cleanup_schedule = state.break_schedule.clone();
+    let cancel_schedule = cancel.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(60));
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    cleanup_schedule.cleanup();
+                }
+                _ = cancel_schedule.cancelled() => {
+                    info!("Break schedule cleanup task shutting down");
+                    break;
+                }
+            }
+        }
+    });
+
     // Spawn background task for rate limiter cleanup (prevents stale IP entries)
     if let Some(ref limiter) = state.rate_limiter {
         let cleanup_limiter = limiter.clone();
@@ -161,6 +238,19 @@ pub async fn build_router_with_token(config: Config, cancel: CancellationToken)
             "/stitch/{session_id}/asset-list/{break_id}",
             get(handlers::asset_list::serve_asset_list),
         )
+        .route(
+            "/stitch/{session_id}/ad-mpd/{break_id}",
+            get(handlers::ad_mpd::serve_ad_mpd),
+        )
+        // Channel break schedule API
+        .route(
+            "/schedule/{channel}/breaks",
+            get(handlers::schedule::list_breaks).post(handlers::schedule::add_breaks),
+        )
+        .route(
+            "/schedule/{channel}/breaks/{break_id}",
+            delete(handlers::schedule::remove_break),
+        )
         .layer(middleware::from_fn(version_header))
         .layer(middleware::from_fn_with_state(
             state.clone(),
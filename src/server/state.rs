@@ -1,7 +1,19 @@
 use crate::{
-    ad::{AdProvider, DemoAdProvider, SlateProvider, StaticAdProvider, VastAdProvider},
-    cache::ManifestCache,
+    ad::{
+        AdProvider, DemoAdProvider, JsonDecisionAdProvider, OpenRtbAdProvider, SlateProvider,
+        StaticAdProvider, VastAdProvider, WaterfallAdProvider,
+        break_decision::BreakDecisions,
+        break_schedule::BreakSchedule,
+        frequency::FrequencyPolicy,
+        normalizer::AdNormalizer,
+        prefetch::AdPrefetcher,
+        probe::CreativeProber,
+        schedule::VmapSchedule,
+        shared_decision::{DecisionScope, SharedDecisions},
+    },
+    cache::{ManifestCache, SegmentCache},
     config::{AdProviderType, Config, SessionStoreType},
+    dash::inband::InbandBreakStore,
     server::{
         dns_resolver::SsrfSafeResolver, rate_limit::RateLimiter,
         url_validation::validate_origin_url,
@@ -24,8 +36,25 @@ pub struct AppState {
     pub sessions: SessionManager,
     /// Ad provider for serving ad content (trait object for runtime flexibility)
     pub ad_provider: Arc<dyn AdProvider>,
+    /// Ad decisions of each session's breaks, reused by playlist refreshes
+    /// and renditions
+    pub break_decisions: BreakDecisions,
+    /// Ad decisions shared per channel or cohort (`AD_DECISION_SCOPE`)
+    pub shared_decisions: SharedDecisions,
+    /// Background decisioning of upcoming breaks (`AD_PREFETCH_SECS`)
+    pub prefetcher: AdPrefetcher,
+    /// Ad segments pre-warmed for prefetched breaks (`AD_PREFETCH_WARM`)
+    pub segment_cache: SegmentCache,
     /// Short-TTL cache for origin manifests (deduplicates concurrent fetches)
     pub manifest_cache: ManifestCache,
+    /// Ad breaks found in-band (`emsg`) by the segment proxy, per session
+    pub inband_breaks: InbandBreakStore,
+    /// VMAP ad schedule for VOD content without CUE markers (None when
+    /// VMAP_URL is unset)
+    pub vmap: Option<VmapSchedule>,
+    /// Per-channel ad breaks for streams without SCTE-35, pushed through
+    /// the schedule API or loaded from SCHEDULE_FILE
+    pub break_schedule: BreakSchedule,
     /// Optional per-IP rate limiter (None when RATE_LIMIT_RPM=0)
     pub rate_limiter: Option<RateLimiter>,
     /// Server start time for uptime tracking
@@ -101,6 +130,38 @@ impl AppState {
             }
         };
 
+        // One prober, and probe cache, for every VAST-based provider
+        let prober = config.ad_probe.clone().map(|profile| {
+            info!(
+                "Creative probing: enabled (codecs: {}, max resolution: {:?})",
+                profile.codecs.join(","),
+                profile.max_resolution
+            );
+            CreativeProber::new(http_client.clone(), profile)
+        });
+
+        // One normalizer, and package cache, for every VAST-based provider
+        let normalizer = config.ad_normalizer_url.as_deref().map(|url| {
+            info!(
+                "Creative normalization: enabled (url: {}, poll: {}ms)",
+                url, config.ad_normalizer_poll_ms
+            );
+            AdNormalizer::new(url.to_string(), http_client.clone())
+                .with_poll_interval(Duration::from_millis(config.ad_normalizer_poll_ms))
+        });
+
+        // Frequency capping and separation for every VAST-based provider
+        let frequency_policy = FrequencyPolicy {
+            cap: config.ad_frequency_cap,
+            separation_breaks: config.ad_separation_breaks,
+        };
+        if frequency_policy.is_enabled() {
+            info!(
+                "Frequency policy: cap {} per session, separation {:?} break(s)",
+                frequency_policy.cap, frequency_policy.separation_breaks
+            );
+        }
+
         // Create ad provider based on config
         let ad_provider: Arc<dyn AdProvider> = match config.ad_provider_type {
             AdProviderType::Vast => {
@@ -110,7 +171,21 @@ impl AppState {
                     .expect("VAST_ENDPOINT is required when AD_PROVIDER_TYPE=vast");
                 info!("Ad provider: VAST (endpoint: {})", endpoint);
 
-                let mut provider = VastAdProvider::new(endpoint.to_string(), http_client.clone());
+                let mut provider = VastAdProvider::new(endpoint.to_string(), http_client.clone())
+                    .with_viewer_headers(config.forward_viewer_headers.on_vast())
+                    .with_beacon_headers(config.forward_viewer_headers.on_beacons())
+                    .with_decision_timeout(Duration::from_millis(config.ad_decision_timeout_ms))
+                    .with_creative_policy(config.creative_policy.clone());
+
+                if frequency_policy.is_enabled() {
+                    provider = provider.with_frequency_policy(frequency_policy, sessions.clone());
+                }
+                if let Some(prober) = &prober {
+                    provider = provider.with_prober(prober.clone());
+                }
+                if let Some(normalizer) = &normalizer {
+                    provider = provider.with_normalizer(normalizer.clone());
+                }
 
                 // Configure slate fallback if SLATE_URL is set
                 if let Some(slate_url) = &config.slate_url {
@@ -128,15 +203,150 @@ impl AppState {
 
                 Arc::new(provider)
             }
+            AdProviderType::Waterfall => {
+                assert!(
+                    !config.ad_sources.is_empty(),
+                    "AD_SOURCES is required when AD_PROVIDER_TYPE=waterfall"
+                );
+                info!(
+                    "Ad provider: Waterfall ({:?}, {} sources)",
+                    config.ad_waterfall_mode,
+                    config.ad_sources.len()
+                );
+
+                let mut provider = WaterfallAdProvider::new(config.ad_waterfall_mode);
+                for source in &config.ad_sources {
+                    let timeout_ms = source.timeout_ms.unwrap_or(config.ad_decision_timeout_ms);
+                    info!(
+                        "Ad source: {} (endpoint: {}, weight: {}, timeout: {}ms)",
+                        source.name, source.endpoint, source.weight, timeout_ms
+                    );
+                    let mut child =
+                        VastAdProvider::new(source.endpoint.clone(), http_client.clone())
+                            .with_viewer_headers(config.forward_viewer_headers.on_vast())
+                            .with_beacon_headers(config.forward_viewer_headers.on_beacons())
+                            .with_decision_timeout(Duration::from_millis(timeout_ms))
+                            .with_creative_policy(config.creative_policy.clone());
+                    if frequency_policy.is_enabled() {
+                        child = child.with_frequency_policy(frequency_policy, sessions.clone());
+                    }
+                    if let Some(prober) = &prober {
+                        child = child.with_prober(prober.clone());
+                    }
+                    if let Some(normalizer) = &normalizer {
+                        child = child.with_normalizer(normalizer.clone());
+                    }
+                    provider = provider.with_source(&source.name, source.weight, Arc::new(child));
+                }
+
+                if let Some(slate_url) = &config.slate_url {
+                    info!(
+                        "Slate fallback: enabled (url: {}, segment duration: {}s)",
+                        slate_url, config.slate_segment_duration
+                    );
+                    provider = provider.with_slate(SlateProvider::new(
+                        slate_url.clone(),
+                        config.slate_segment_duration,
+                    ));
+                } else {
+                    info!("Slate fallback: disabled (no SLATE_URL configured)");
+                }
+
+                Arc::new(provider)
+            }
+            AdProviderType::OpenRtb => {
+                assert!(
+                    !config.openrtb_endpoints.is_empty(),
+                    "OPENRTB_ENDPOINTS is required when AD_PROVIDER_TYPE=openrtb"
+                );
+                info!(
+                    "Ad provider: OpenRTB 2.6 (bidders: {})",
+                    config.openrtb_endpoints.join(", ")
+                );
+
+                let mut provider =
+                    OpenRtbAdProvider::new(config.openrtb_endpoints.clone(), http_client.clone())
+                        .with_viewer_headers(config.forward_viewer_headers.on_vast())
+                        .with_beacon_headers(config.forward_viewer_headers.on_beacons())
+                        .with_decision_timeout(Duration::from_millis(config.ad_decision_timeout_ms))
+                        .with_creative_policy(config.creative_policy.clone());
+                if frequency_policy.is_enabled() {
+                    provider = provider.with_frequency_policy(frequency_policy, sessions.clone());
+                }
+                if let Some(prober) = &prober {
+                    provider = provider.with_prober(prober.clone());
+                }
+                if let Some(normalizer) = &normalizer {
+                    provider = provider.with_normalizer(normalizer.clone());
+                }
+
+                if let Some(slate_url) = &config.slate_url {
+                    info!(
+                        "Slate fallback: enabled (url: {}, segment duration: {}s)",
+                        slate_url, config.slate_segment_duration
+                    );
+                    provider = provider.with_slate(SlateProvider::new(
+                        slate_url.clone(),
+                        config.slate_segment_duration,
+                    ));
+                } else {
+                    info!("Slate fallback: disabled (no SLATE_URL configured)");
+                }
+
+                Arc::new(provider)
+            }
+            AdProviderType::JsonDecision => {
+                let url = config
+                    .ad_decision_url
+                    .as_deref()
+                    .expect("AD_DECISION_URL is required when AD_PROVIDER_TYPE=json");
+                info!("Ad provider: JSON decision service (url: {})", url);
+
+                let mut provider =
+                    JsonDecisionAdProvider::new(url.to_string(), http_client.clone())
+                        .with_viewer_headers(config.forward_viewer_headers.on_vast())
+                        .with_beacon_headers(config.forward_viewer_headers.on_beacons())
+                        .with_decision_timeout(Duration::from_millis(config.ad_decision_timeout_ms))
+                        .with_creative_policy(config.creative_policy.clone());
+                if frequency_policy.is_enabled() {
+                    provider = provider.with_frequency_policy(frequency_policy, sessions.clone());
+                }
+                if let Some(prober) = &prober {
+                    provider = provider.with_prober(prober.clone());
+                }
+                if let Some(normalizer) = &normalizer {
+                    provider = provider.with_normalizer(normalizer.clone());
+                }
+
+                if let Some(slate_url) = &config.slate_url {
+                    info!(
+                        "Slate fallback: enabled (url: {}, segment duration: {}s)",
+                        slate_url, config.slate_segment_duration
+                    );
+                    provider = provider.with_slate(SlateProvider::new(
+                        slate_url.clone(),
+                        config.slate_segment_duration,
+                    ));
+                } else {
+                    info!("Slate fallback: disabled (no SLATE_URL configured)");
+                }
+
+                Arc::new(provider)
+            }
             AdProviderType::Static => {
                 info!(
                     "Ad provider: Static (source: {}, segment duration: {}s)",
                     config.ad_source_url, config.ad_segment_duration
                 );
-                Arc::new(StaticAdProvider::new(
-                    config.ad_source_url.clone(),
-                    config.ad_segment_duration,
-                ))
+                let provider =
+                    StaticAdProvider::new(config.ad_source_url.clone(), config.ad_segment_duration);
+                if provider.has_creatives() {
+                    match provider.load_creatives(&http_client).await {
+                        Ok(count) => info!("Static ad creatives: loaded {}", count),
+                        Err(e) => warn!("Static ad creatives: load failed: {}", e),
+                    }
+                }
+                Arc::new(provider)
             }
             AdProviderType::Demo => {
                 let base_url = config
@@ -152,6 +362,42 @@ impl AppState {
             }
         };
 
+        // Load a playlist-based slate up front; the server refreshes it in
+        // the background
+        if let Some(slate) = ad_provider.slate()
+            && slate.is_playlist()
+        {
+            match slate.refresh(&http_client).await {
+                Ok(variants) => info!("Slate playlist: loaded {} rendition(s)", variants),
+                Err(e) => warn!("Slate playlist: initial load failed: {}", e),
+            }
+        }
+
+        if config.ad_decision_scope != DecisionScope::Session {
+            info!(
+                "Shared ad decisions: enabled ({:?} scope)",
+                config.ad_decision_scope
+            );
+        }
+        let shared_decisions = SharedDecisions::new(config.ad_decision_scope, http_client.clone())
+            .with_beacon_headers(config.forward_viewer_headers.on_beacons());
+
+        let segment_cache = SegmentCache::new();
+        let mut prefetcher = AdPrefetcher::new(
+            ad_provider.clone(),
+            http_client.clone(),
+            Duration::from_secs(config.ad_prefetch_secs),
+        );
+        if prefetcher.is_enabled() {
+            info!(
+                "Ad prefetch: deciding breaks up to {}s ahead (segment pre-warm: {})",
+                config.ad_prefetch_secs, config.ad_prefetch_warm
+            );
+            if config.ad_prefetch_warm {
+                prefetcher = prefetcher.with_segment_cache(segment_cache.clone());
+            }
+        }
+
         let rate_limiter = if config.rate_limit_rpm > 0 {
             info!(
                 "Rate limiter: {} requests/min per IP",
@@ -163,6 +409,19 @@ impl AppState {
             None
         };
 
+        let vmap = config.vmap_url.clone().map(|url| {
+            info!("VMAP schedule: enabled (url: {})", url);
+            VmapSchedule::new(url)
+        });
+
+        let break_schedule = BreakSchedule::new();
+        if let Some(path) = &config.schedule_file {
+            match break_schedule.load_file(path) {
+                Ok(count) => info!("Break schedule: loaded {} break(s) from {}", count, path),
+                Err(e) => warn!("Break schedule: load failed: {}", e),
+            }
+        }
+
         let manifest_cache =
             ManifestCache::with_ttl(Duration::from_millis(config.manifest_cache_ttl_ms));
 
@@ -171,7 +430,14 @@ impl AppState {
             http_client,
             sessions,
             ad_provider,
+            break_decisions: BreakDecisions::new(),
+            shared_decisions,
+            prefetcher,
+            segment_cache,
             manifest_cache,
+            inband_breaks: InbandBreakStore::new(),
+            vmap,
+            break_schedule,
             rate_limiter,
             started_at: Instant::now(),
         }
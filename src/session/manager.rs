@@ -1,3 +1,7 @@
+use crate::ad::AdRequestContext;
+#[cfg(feature = "valkey")]
+use crate::ad::frequency::MAX_HISTORY;
+use crate::ad::frequency::{AdHistory, AdIdentity};
 use dashmap::DashMap;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
@@ -9,6 +13,25 @@ use tracing::{error, info};
 #[cfg(feature = "valkey")]
 use redis::aio::ConnectionManager;
 
+/// Appends a break to a session's ad history, if the session exists
+///
+/// KEYS[1] is the session, KEYS[2] its ad history list; ARGV[1] is the
+/// break's ads and ARGV[2] the number of breaks kept. The list expires
+/// with the session.
+#[cfg(feature = "valkey")]
+const RECORD_ADS_SCRIPT: &str = r"
+local ttl = redis.call('PTTL', KEYS[1])
+if ttl == -2 then
+    return 0
+end
+redis.call('RPUSH', KEYS[2], ARGV[1])
+redis.call('LTRIM', KEYS[2], -tonumber(ARGV[2]), -1)
+if ttl > 0 then
+    redis.call('PEXPIRE', KEYS[2], ttl)
+end
+return 1
+";
+
 /// Session data stored for each active session
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Session {
@@ -18,6 +41,15 @@ pub struct Session {
     pub created_at: SystemTime,
     #[serde(with = "epoch_secs")]
     pub last_accessed: SystemTime,
+    /// Viewer context captured when the session started
+    #[serde(default)]
+    pub ad_context: AdRequestContext,
+    /// Ads placed in the session's breaks, for frequency capping
+    ///
+    /// Held here by the memory store only; Valkey keeps the history in a
+    /// list next to the session (see [`SessionManager::ad_history`]).
+    #[serde(skip)]
+    pub ad_history: AdHistory,
 }
 
 /// Serde helper: SystemTime ↔ u64 epoch seconds
@@ -91,34 +123,57 @@ impl SessionManager {
         })
     }
 
-    /// Get or create a session
-    pub async fn get_or_create(&self, session_id: String, origin_url: String) -> Session {
+    /// Get or create a session, marking it accessed
+    ///
+    /// `ad_context` is only stored when the session is created; an existing
+    /// session keeps the context it started with.
+    pub async fn get_or_create(
+        &self,
+        session_id: String,
+        origin_url: String,
+        ad_context: AdRequestContext,
+    ) -> Session {
         match &self.backend {
-            Backend::Memory { sessions } => sessions
-                .entry(session_id.clone())
-                .or_insert_with(|| {
-                    let now = SystemTime::now();
-                    Session {
+            Backend::Memory { sessions } => {
+                let now = SystemTime::now();
+                sessions
+                    .entry(session_id.clone())
+                    .and_modify(|session| session.last_accessed = now)
+                    .or_insert_with(|| Session {
                         session_id: session_id.clone(),
                         origin_url,
                         created_at: now,
                         last_accessed: now,
-                    }
-                })
-                .clone(),
+                        ad_context,
+                        ad_history: AdHistory::default(),
+                    })
+                    .clone()
+            }
             #[cfg(feature = "valkey")]
             Backend::Valkey { conn, key_prefix } => {
                 let key = format!("{}:{}", key_prefix, session_id);
                 let mut conn = conn.clone();
-                // Try to get existing session
-                if let Ok(Some(json)) = redis::cmd("GET")
+                let ttl_secs = self.ttl.as_secs();
+                // Read the session and refresh its TTL in a single round trip
+                let existing = redis::pipe()
+                    .cmd("GETEX")
                     .arg(&key)
-                    .query_async::<Option<String>>(&mut conn)
-                    .await
-                {
-                    if let Ok(session) = serde_json::from_str::<Session>(&json) {
-                        return session;
+                    .arg("EX")
+                    .arg(ttl_secs)
+                    .cmd("EXPIRE")
+                    .arg(ads_key(key_prefix, &session_id))
+                    .arg(ttl_secs)
+                    .ignore()
+                    .query_async::<(Option<String>,)>(&mut conn)
+                    .await;
+                match existing {
+                    Ok((Some(json),)) => {
+                        if let Ok(session) = serde_json::from_str::<Session>(&json) {
+                            return session;
+                        }
                     }
+                    Ok((None,)) => {}
+                    Err(e) => error!("Valkey GETEX failed in get_or_create: {}", e),
                 }
                 // Create new session
                 let now = SystemTime::now();
@@ -127,15 +182,19 @@ impl SessionManager {
                     origin_url,
                     created_at: now,
                     last_accessed: now,
+                    ad_context,
+                    ad_history: AdHistory::default(),
                 };
                 if let Ok(json) = serde_json::to_string(&session) {
-                    let ttl_secs = self.ttl.as_secs();
+                    // NX: a session created concurrently by another request
+                    // is kept
                     if let Err(e) = redis::cmd("SET")
                         .arg(&key)
                         .arg(&json)
+                        .arg("NX")
                         .arg("EX")
                         .arg(ttl_secs)
-                        .query_async::<()>(&mut conn)
+                        .query_async::<Option<String>>(&mut conn)
                         .await
                     {
                         error!("Failed to store session in Valkey: {}", e);
@@ -167,10 +226,14 @@ impl SessionManager {
                 // Trade-off: last_accessed is not updated in the stored JSON, but
                 // the key's TTL accurately reflects session liveness. The field is
                 // only used for diagnostics, not for eviction logic.
-                if let Err(e) = redis::cmd("EXPIRE")
+                if let Err(e) = redis::pipe()
+                    .cmd("EXPIRE")
                     .arg(&key)
                     .arg(ttl_secs)
-                    .query_async::<i32>(&mut conn)
+                    .cmd("EXPIRE")
+                    .arg(ads_key(key_prefix, session_id))
+                    .arg(ttl_secs)
+                    .query_async::<(i32, i32)>(&mut conn)
                     .await
                 {
                     error!("Valkey EXPIRE failed in touch: {}", e);
@@ -203,6 +266,82 @@ impl SessionManager {
         }
     }
 
+    /// Ads placed in a session's breaks so far (empty for unknown sessions)
+    pub async fn ad_history(&self, session_id: &str) -> AdHistory {
+        match &self.backend {
+            Backend::Memory { sessions } => sessions
+                .get(session_id)
+                .map(|session| session.ad_history.clone())
+                .unwrap_or_default(),
+            #[cfg(feature = "valkey")]
+            Backend::Valkey { conn, key_prefix } => {
+                let mut conn = conn.clone();
+                let breaks = match redis::cmd("LRANGE")
+                    .arg(ads_key(key_prefix, session_id))
+                    .arg(0)
+                    .arg(-1)
+                    .query_async::<Vec<String>>(&mut conn)
+                    .await
+                {
+                    Ok(breaks) => breaks,
+                    Err(e) => {
+                        error!("Valkey LRANGE failed in ad_history: {}", e);
+                        return AdHistory::default();
+                    }
+                };
+                let mut history = AdHistory::default();
+                for json in breaks {
+                    if let Ok(recorded) = serde_json::from_str::<RecordedBreak>(&json) {
+                        history.record(recorded.break_key, &recorded.ads);
+                    }
+                }
+                history
+            }
+        }
+    }
+
+    /// Record the ads placed in a session's next break
+    ///
+    /// A break keyed `break_key` counts once, however often it is recorded
+    /// (see [`AdHistory::record`]). Unknown sessions are ignored. With
+    /// Valkey, each break is appended to a list next to the session by a
+    /// script, so instances recording breaks of the same session
+    /// concurrently do not overwrite each other; repeated breaks are
+    /// skipped when the list is read.
+    pub async fn record_ads(&self, session_id: &str, break_key: Option<u64>, ads: &[AdIdentity]) {
+        match &self.backend {
+            Backend::Memory { sessions } => {
+                if let Some(mut session) = sessions.get_mut(session_id) {
+                    session.ad_history.record(break_key, ads);
+                }
+            }
+            #[cfg(feature = "valkey")]
+            Backend::Valkey { conn, key_prefix } => {
+                let key = format!("{}:{}", key_prefix, session_id);
+                let mut conn = conn.clone();
+                let recorded = RecordedBreak {
+                    break_key,
+                    ads: ads.to_vec(),
+                };
+                let Ok(json) = serde_json::to_string(&recorded) else {
+                    return;
+                };
+                // Every break holds at least one ad, so the last MAX_HISTORY
+                // breaks hold all the ads the history keeps
+                if let Err(e) = redis::Script::new(RECORD_ADS_SCRIPT)
+                    .key(&key)
+                    .key(ads_key(key_prefix, session_id))
+                    .arg(&json)
+                    .arg(MAX_HISTORY)
+                    .invoke_async::<i32>(&mut conn)
+                    .await
+                {
+                    error!("Valkey script failed in record_ads: {}", e);
+                }
+            }
+        }
+    }
+
     /// Remove expired sessions (no-op for Valkey — TTL is native)
     pub async fn cleanup_expired(&self) {
         match &self.backend {
@@ -279,14 +418,14 @@ impl SessionManager {
                             return None;
                         }
                     };
-                if json.is_some() {
-                    if let Err(e) = redis::cmd("DEL")
+                if json.is_some()
+                    && let Err(e) = redis::cmd("DEL")
                         .arg(&key)
+                        .arg(ads_key(key_prefix, session_id))
                         .query_async::<()>(&mut conn)
                         .await
-                    {
-                        error!("Valkey DEL failed in remove: {}", e);
-                    }
+                {
+                    error!("Valkey DEL failed in remove: {}", e);
                 }
                 json.and_then(|j| serde_json::from_str(&j).ok())
             }
@@ -294,6 +433,23 @@ impl SessionManager {
     }
 }
 
+/// A break of a session's ad history, as stored in its Valkey list
+#[cfg(feature = "valkey")]
+#[derive(Serialize, Deserialize)]
+struct RecordedBreak {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    break_key: Option<u64>,
+    ads: Vec<AdIdentity>,
+}
+
+/// Valkey key of a session's ad history list
+///
+/// Kept outside the session key space so it is not counted as a session.
+#[cfg(feature = "valkey")]
+fn ads_key(key_prefix: &str, session_id: &str) -> String {
+    format!("{}-ads:{}", key_prefix, session_id)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -302,7 +458,11 @@ mod tests {
     async fn test_session_creation() {
         let manager = SessionManager::new_memory(Duration::from_secs(300));
         let session = manager
-            .get_or_create("test123".to_string(), "https://example.com".to_string())
+            .get_or_create(
+                "test123".to_string(),
+                "https://example.com".to_string(),
+                AdRequestContext::default(),
+            )
             .await;
 
         assert_eq!(session.session_id, "test123");
@@ -314,7 +474,11 @@ mod tests {
     async fn test_session_touch() {
         let manager = SessionManager::new_memory(Duration::from_secs(300));
         let session = manager
-            .get_or_create("test456".to_string(), "https://example.com".to_string())
+            .get_or_create(
+                "test456".to_string(),
+                "https://example.com".to_string(),
+                AdRequestContext::default(),
+            )
             .await;
 
         let initial_time = session.last_accessed;
@@ -325,11 +489,38 @@ mod tests {
         assert!(updated_session.last_accessed > initial_time);
     }
 
+    #[tokio::test]
+    async fn get_or_create_marks_existing_session_accessed() {
+        let manager = SessionManager::new_memory(Duration::from_secs(300));
+        let session = manager
+            .get_or_create(
+                "resumed".to_string(),
+                "https://example.com".to_string(),
+                AdRequestContext::default(),
+            )
+            .await;
+
+        std::thread::sleep(Duration::from_millis(10));
+        let resumed = manager
+            .get_or_create(
+                "resumed".to_string(),
+                "https://example.com".to_string(),
+                AdRequestContext::default(),
+            )
+            .await;
+        assert!(resumed.last_accessed > session.last_accessed);
+        assert_eq!(resumed.created_at, session.created_at);
+    }
+
     #[tokio::test]
     async fn test_session_removal() {
         let manager = SessionManager::new_memory(Duration::from_secs(300));
         manager
-            .get_or_create("test789".to_string(), "https://example.com".to_string())
+            .get_or_create(
+                "test789".to_string(),
+                "https://example.com".to_string(),
+                AdRequestContext::default(),
+            )
             .await;
 
         assert_eq!(manager.session_count().await, 1);
@@ -337,6 +528,58 @@ mod tests {
         assert_eq!(manager.session_count().await, 0);
     }
 
+    #[tokio::test]
+    async fn session_keeps_initial_ad_context() {
+        let manager = SessionManager::new_memory(Duration::from_secs(300));
+        let first = AdRequestContext {
+            client_ip: Some("198.51.100.4".to_string()),
+            ..Default::default()
+        };
+        manager
+            .get_or_create("ctx".to_string(), "https://example.com".to_string(), first)
+            .await;
+        let session = manager
+            .get_or_create(
+                "ctx".to_string(),
+                "https://example.com".to_string(),
+                AdRequestContext::default(),
+            )
+            .await;
+        assert_eq!(
+            session.ad_context.client_ip.as_deref(),
+            Some("198.51.100.4")
+        );
+    }
+
+    #[tokio::test]
+    async fn ad_history_is_kept_with_the_session() {
+        let manager = SessionManager::new_memory(Duration::from_secs(300));
+        manager
+            .get_or_create(
+                "viewer".to_string(),
+                "https://example.com".to_string(),
+                AdRequestContext::default(),
+            )
+            .await;
+        let ad = AdIdentity {
+            ad_id: "ad-id.org:ABCD0001000H".to_string(),
+            ..Default::default()
+        };
+
+        manager
+            .record_ads("viewer", Some(3), std::slice::from_ref(&ad))
+            .await;
+        manager
+            .record_ads("viewer", Some(3), std::slice::from_ref(&ad))
+            .await;
+        manager.record_ads("no-such-session", None, &[ad]).await;
+
+        let history = manager.ad_history("viewer").await;
+        assert_eq!(history.breaks, 1);
+        assert_eq!(history.impressions("ad-id.org:ABCD0001000H"), 1);
+        assert_eq!(manager.ad_history("no-such-session").await.breaks, 0);
+    }
+
     #[tokio::test]
     async fn session_count_empty() {
         let manager = SessionManager::new_memory(Duration::from_secs(300));
@@ -359,11 +602,19 @@ mod tests {
     async fn get_or_create_returns_existing_session() {
         let manager = SessionManager::new_memory(Duration::from_secs(300));
         manager
-            .get_or_create("idempotent".to_string(), "https://first.com".to_string())
+            .get_or_create(
+                "idempotent".to_string(),
+                "https://first.com".to_string(),
+                AdRequestContext::default(),
+            )
             .await;
         // Second call with a different origin_url — existing session should be returned
         let session = manager
-            .get_or_create("idempotent".to_string(), "https://second.com".to_string())
+            .get_or_create(
+                "idempotent".to_string(),
+                "https://second.com".to_string(),
+                AdRequestContext::default(),
+            )
             .await;
         assert_eq!(
             session.origin_url, "https://first.com",
@@ -377,7 +628,11 @@ mod tests {
         // Very short TTL so sessions expire almost immediately.
         let manager = SessionManager::new_memory(Duration::from_millis(1));
         manager
-            .get_or_create("stale".to_string(), "https://example.com".to_string())
+            .get_or_create(
+                "stale".to_string(),
+                "https://example.com".to_string(),
+                AdRequestContext::default(),
+            )
             .await;
         assert_eq!(manager.session_count().await, 1);
 
@@ -10,7 +10,14 @@
 //! and not subject to user-supplied origin validation.
 
 use m3u8_rs::Playlist;
-use ritcher::config::{AdProviderType, Config, SessionStoreType, StitchingMode};
+use ritcher::ad::conditioning::CreativePolicy;
+use ritcher::ad::shared_decision::DecisionScope;
+use ritcher::ad::waterfall::WaterfallMode;
+use ritcher::config::{
+    AdProviderType, Config, SessionStoreType, StitchingMode, ViewerHeaderForwarding,
+};
+use ritcher::dash::sgai::DashSgaiScheme;
+use ritcher::scte35::SegmentationTypes;
 use ritcher::server::build_router;
 use std::net::SocketAddr;
 use std::time::Duration;
@@ -36,12 +43,33 @@ async fn start_server(mode: StitchingMode, origin_path: &str) -> SocketAddr {
         origin_url: format!("http://{}{}", addr, origin_path),
         is_dev: true,
         stitching_mode: mode,
+        dash_sgai_scheme: DashSgaiScheme::Callback,
         ad_provider_type: AdProviderType::Static,
         ad_source_url: "https://hls.src.tedm.io/content/ts_h264_480p_1s".to_string(),
         ad_segment_duration: 1.0,
         vast_endpoint: None,
+        ad_param_allowlist: vec!["ad.*".to_string()],
+        forward_viewer_headers: ViewerHeaderForwarding::All,
+        ad_decision_timeout_ms: 3000,
+        ad_sources: vec![],
+        ad_waterfall_mode: WaterfallMode::Sequential,
+        openrtb_endpoints: vec![],
+        ad_decision_url: None,
+        ad_decision_scope: DecisionScope::Session,
+        ad_prefetch_secs: 0,
+        ad_prefetch_warm: false,
+        ad_frequency_cap: 0,
+        ad_separation_breaks: None,
+        creative_policy: CreativePolicy::default(),
+        ad_probe: None,
+        ad_normalizer_url: None,
+        ad_normalizer_poll_ms: 2000,
+        vmap_url: None,
+        schedule_file: None,
+        schedule_api_token: None,
         slate_url: None,
         slate_segment_duration: 1.0,
+        slate_refresh_secs: 300,
         session_store: SessionStoreType::Memory,
         valkey_url: None,
         session_ttl_secs: 300,
@@ -49,6 +77,8 @@ async fn start_server(mode: StitchingMode, origin_path: &str) -> SocketAddr {
         demo_ad_base_url: None,
         origin_timeout_secs: 30,
         manifest_cache_ttl_ms: 2000,
+        scte35_segmentation_types: SegmentationTypes::default(),
+        inband_scte35: false,
     };
 
     let app = build_router(config).await;
@@ -584,12 +614,33 @@ fn test_config_with_origin(origin_url: &str) -> Config {
         origin_url: origin_url.to_string(),
         is_dev: true,
         stitching_mode: StitchingMode::Ssai,
+        dash_sgai_scheme: DashSgaiScheme::Callback,
         ad_provider_type: AdProviderType::Static,
         ad_source_url: "https://hls.src.tedm.io/content/ts_h264_480p_1s".to_string(),
         ad_segment_duration: 1.0,
         vast_endpoint: None,
+        ad_param_allowlist: vec!["ad.*".to_string()],
+        forward_viewer_headers: ViewerHeaderForwarding::All,
+        ad_decision_timeout_ms: 3000,
+        ad_sources: vec![],
+        ad_waterfall_mode: WaterfallMode::Sequential,
+        openrtb_endpoints: vec![],
+        ad_decision_url: None,
+        ad_decision_scope: DecisionScope::Session,
+        ad_prefetch_secs: 0,
+        ad_prefetch_warm: false,
+        ad_frequency_cap: 0,
+        ad_separation_breaks: None,
+        creative_policy: CreativePolicy::default(),
+        ad_probe: None,
+        ad_normalizer_url: None,
+        ad_normalizer_poll_ms: 2000,
+        vmap_url: None,
+        schedule_file: None,
+        schedule_api_token: None,
         slate_url: None,
         slate_segment_duration: 1.0,
+        slate_refresh_secs: 300,
         session_store: SessionStoreType::Memory,
         valkey_url: None,
         session_ttl_secs: 300,
@@ -597,6 +648,8 @@ fn test_config_with_origin(origin_url: &str) -> Config {
         demo_ad_base_url: None,
         origin_timeout_secs: 30,
         manifest_cache_ttl_ms: 2000,
+        scte35_segmentation_types: SegmentationTypes::default(),
+        inband_scte35: false,
     }
 }
 
@@ -689,12 +742,33 @@ async fn rate_limiting_blocks_excessive_requests() {
             origin_url: format!("http://{}/demo/playlist.m3u8", addr),
             is_dev: true,
             stitching_mode: StitchingMode::Ssai,
+            dash_sgai_scheme: DashSgaiScheme::Callback,
             ad_provider_type: AdProviderType::Static,
             ad_source_url: "https://hls.src.tedm.io/content/ts_h264_480p_1s".to_string(),
             ad_segment_duration: 1.0,
             vast_endpoint: None,
+            ad_param_allowlist: vec!["ad.*".to_string()],
+            forward_viewer_headers: ViewerHeaderForwarding::All,
+            ad_decision_timeout_ms: 3000,
+            ad_sources: vec![],
+            ad_waterfall_mode: WaterfallMode::Sequential,
+            openrtb_endpoints: vec![],
+            ad_decision_url: None,
+            ad_decision_scope: DecisionScope::Session,
+            ad_prefetch_secs: 0,
+            ad_prefetch_warm: false,
+            ad_frequency_cap: 0,
+            ad_separation_breaks: None,
+            creative_policy: CreativePolicy::default(),
+            ad_probe: None,
+            ad_normalizer_url: None,
+            ad_normalizer_poll_ms: 2000,
+            vmap_url: None,
+            schedule_file: None,
+            schedule_api_token: None,
             slate_url: None,
             slate_segment_duration: 1.0,
+            slate_refresh_secs: 300,
             session_store: SessionStoreType::Memory,
             valkey_url: None,
             session_ttl_secs: 300,
@@ -702,6 +776,8 @@ async fn rate_limiting_blocks_excessive_requests() {
             demo_ad_base_url: None,
             origin_timeout_secs: 30,
             manifest_cache_ttl_ms: 2000,
+            scte35_segmentation_types: SegmentationTypes::default(),
+            inband_scte35: false,
         };
 
         let app = build_router(config).await;
@@ -18,7 +18,14 @@ use axum::{
     http::{Request, StatusCode},
 };
 use http_body_util::BodyExt;
-use ritcher::config::{AdProviderType, Config, SessionStoreType, StitchingMode};
+use ritcher::ad::conditioning::CreativePolicy;
+use ritcher::ad::shared_decision::DecisionScope;
+use ritcher::ad::waterfall::WaterfallMode;
+use ritcher::config::{
+    AdProviderType, Config, SessionStoreType, StitchingMode, ViewerHeaderForwarding,
+};
+use ritcher::dash::sgai::DashSgaiScheme;
+use ritcher::scte35::SegmentationTypes;
 use ritcher::server::build_router;
 use std::net::SocketAddr;
 use tower::ServiceExt;
@@ -38,12 +45,33 @@ fn test_config() -> Config {
         origin_url: "https://example.com".to_string(),
         is_dev: true,
         stitching_mode: StitchingMode::Ssai,
+        dash_sgai_scheme: DashSgaiScheme::Callback,
         ad_provider_type: AdProviderType::Static,
         ad_source_url: "https://hls.src.tedm.io/content/ts_h264_480p_1s".to_string(),
         ad_segment_duration: 1.0,
         vast_endpoint: None,
+        ad_param_allowlist: vec!["ad.*".to_string()],
+        forward_viewer_headers: ViewerHeaderForwarding::All,
+        ad_decision_timeout_ms: 3000,
+        ad_sources: vec![],
+        ad_waterfall_mode: WaterfallMode::Sequential,
+        openrtb_endpoints: vec![],
+        ad_decision_url: None,
+        ad_decision_scope: DecisionScope::Session,
+        ad_prefetch_secs: 0,
+        ad_prefetch_warm: false,
+        ad_frequency_cap: 0,
+        ad_separation_breaks: None,
+        creative_policy: CreativePolicy::default(),
+        ad_probe: None,
+        ad_normalizer_url: None,
+        ad_normalizer_poll_ms: 2000,
+        vmap_url: None,
+        schedule_file: None,
+        schedule_api_token: None,
         slate_url: None,
         slate_segment_duration: 1.0,
+        slate_refresh_secs: 300,
         session_store: SessionStoreType::Memory,
         valkey_url: None,
         session_ttl_secs: 300,
@@ -51,6 +79,8 @@ fn test_config() -> Config {
         demo_ad_base_url: None,
         origin_timeout_secs: 30,
         manifest_cache_ttl_ms: 2000,
+        scte35_segmentation_types: SegmentationTypes::default(),
+        inband_scte35: false,
     }
 }
 
@@ -557,260 +587,1261 @@ async fn playlist_ssai_mode_interleaves_ad_segments() {
     );
 }
 
-/// SGAI mode: origin playlist with CUE-OUT break → stitched playlist has
-/// EXT-X-DATERANGE interstitial tags (no segment replacement).
+/// VAST mode with an empty VAST response and a playlist-based slate: the
+/// break is filled from the slate rendition matching `bw`, with its init
+/// section signalled, and slate segments resolve to the real slate URIs.
 #[tokio::test]
-async fn playlist_sgai_mode_injects_daterange_tags() {
+async fn playlist_slate_fallback_uses_slate_playlist_rendition() {
     let mock_server = MockServer::start().await;
 
     Mock::given(method("GET"))
         .and(path("/playlist.m3u8"))
-        .respond_with(
-            ResponseTemplate::new(200)
-                .set_body_string(HLS_WITH_CUE)
-                .insert_header("content-type", "application/vnd.apple.mpegurl"),
-        )
+        .respond_with(ResponseTemplate::new(200).set_body_string(HLS_WITH_CUE))
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/vast"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(r#"<VAST version="3.0"></VAST>"#))
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/slate/master.m3u8"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(
+            "#EXTM3U\n#EXT-X-STREAM-INF:BANDWIDTH=500000\nlow.m3u8\n\
+             #EXT-X-STREAM-INF:BANDWIDTH=2000000\nhigh.m3u8\n",
+        ))
+        .mount(&mock_server)
+        .await;
+    for name in ["low", "high"] {
+        Mock::given(method("GET"))
+            .and(path(format!("/slate/{}.m3u8", name)))
+            .respond_with(ResponseTemplate::new(200).set_body_string(format!(
+                "#EXTM3U\n#EXT-X-TARGETDURATION:4\n#EXT-X-MAP:URI=\"{name}-init.mp4\"\n\
+                 #EXTINF:4.0,\n{name}-0.m4s\n#EXTINF:4.0,\n{name}-1.m4s\n#EXT-X-ENDLIST\n"
+            )))
+            .mount(&mock_server)
+            .await;
+    }
+    Mock::given(method("GET"))
+        .and(path("/slate/high-0.m4s"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(b"slate-high".to_vec()))
         .mount(&mock_server)
         .await;
 
-    let addr = start_server(config_with_origin_and_mode(
-        &mock_server,
-        "/playlist.m3u8",
-        StitchingMode::Sgai,
-    ))
+    let addr = start_server(Config {
+        ad_provider_type: AdProviderType::Vast,
+        vast_endpoint: Some(format!("{}/vast", mock_server.uri())),
+        slate_url: Some(format!("{}/slate/master.m3u8", mock_server.uri())),
+        ..config_with_origin(&mock_server, "/playlist.m3u8")
+    })
     .await;
     let client = reqwest::Client::new();
 
-    let resp = client
-        .get(format!("http://{}/stitch/sgai-test/playlist.m3u8", addr))
+    let body = client
+        .get(format!(
+            "http://{}/stitch/slate-test/playlist.m3u8?bw=3000000",
+            addr
+        ))
         .send()
         .await
+        .unwrap()
+        .text()
+        .await
         .unwrap();
 
-    assert_eq!(resp.status(), 200);
-    let body = resp.text().await.unwrap();
-
-    assert!(
-        body.contains("EXT-X-DATERANGE"),
-        "SGAI must inject EXT-X-DATERANGE tags, got:\n{}",
-        body
-    );
     assert!(
-        body.contains("com.apple.hls.interstitial"),
-        "SGAI must include interstitial CLASS, got:\n{}",
+        body.contains("/stitch/slate-test/ad/slate-v1-seg-0.m4s"),
+        "Slate must use the rendition matching bw, got:\n{}",
         body
     );
-    // SGAI must NOT inject DISCONTINUITY (segments are not replaced)
     assert!(
-        !body.contains("#EXT-X-DISCONTINUITY"),
-        "SGAI must not inject DISCONTINUITY tags, got:\n{}",
+        body.contains(
+            "#EXT-X-MAP:URI=\"http://localhost:3000/stitch/slate-test/ad/slate-v1-init-0.mp4\""
+        ),
+        "Slate init section must be signalled, got:\n{}",
         body
     );
+
+    let resp = client
+        .get(format!(
+            "http://{}/stitch/slate-test/ad/slate-v1-seg-0.m4s",
+            addr
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.bytes().await.unwrap().as_ref(), b"slate-high");
 }
 
-/// Origin returns a body that is not valid UTF-8 → handler returns 422.
+/// VAST macros: the ad request carries the break duration and position,
+/// and beacons fired while serving the ad carry the viewer's details
+/// (captured when the session started) and the ad playhead,
+/// percent-encoded.
 #[tokio::test]
-async fn playlist_non_utf8_body_returns_422() {
+async fn vast_macros_expand_in_ad_request_and_beacons() {
     let mock_server = MockServer::start().await;
-
-    // Raw bytes that are not valid UTF-8
-    let invalid_utf8 = vec![0xFF, 0xFE, 0x00, 0x01];
+    let uri = mock_server.uri();
 
     Mock::given(method("GET"))
         .and(path("/playlist.m3u8"))
-        .respond_with(
-            ResponseTemplate::new(200)
-                .set_body_bytes(invalid_utf8)
-                .insert_header("content-type", "application/vnd.apple.mpegurl"),
-        )
+        .respond_with(ResponseTemplate::new(200).set_body_string(HLS_WITH_CUE))
         .mount(&mock_server)
         .await;
+    let vast = format!(
+        r#"<VAST version="4.0"><Ad id="a"><InLine><AdSystem>T</AdSystem><AdTitle>A</AdTitle>
+<Impression><![CDATA[{uri}/imp?ip=[DEVICEIP]&ua=[DEVICEUA]&lat=[LIMITADTRACKING]&a=[ASSETURI]]]></Impression>
+<Creatives><Creative><Linear><Duration>00:00:10</Duration>
+<TrackingEvents><Tracking event="start"><![CDATA[{uri}/start?ph=[ADPLAYHEAD]&ps=[PODSEQUENCE]&bp=[BREAKPOSITION]&c=[GDPRCONSENT]]]></Tracking></TrackingEvents>
+<MediaFiles><MediaFile delivery="progressive" type="video/mp4">{uri}/ad.mp4</MediaFile></MediaFiles>
+</Linear></Creative></Creatives></InLine></Ad></VAST>"#
+    );
+    Mock::given(method("GET"))
+        .and(path("/vast"))
+        .and(wiremock::matchers::query_param("dur", "10"))
+        .and(wiremock::matchers::query_param("bp", "2"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(vast))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/ad.mp4"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(b"ad".to_vec()))
+        .mount(&mock_server)
+        .await;
+    for beacon in ["/imp", "/start"] {
+        Mock::given(method("GET"))
+            .and(path(beacon))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+    }
 
-    let addr = start_server(config_with_origin(&mock_server, "/playlist.m3u8")).await;
+    let addr = start_server(Config {
+        ad_provider_type: AdProviderType::Vast,
+        vast_endpoint: Some(format!(
+            "{uri}/vast?dur=[DURATION]&bp=[BREAKPOSITION]&cb=[CACHEBUSTING]"
+        )),
+        ..config_with_origin_and_mode(&mock_server, "/playlist.m3u8", StitchingMode::Ssai)
+    })
+    .await;
     let client = reqwest::Client::new();
 
+    // The session's first request carries the viewer's details
+    let body = client
+        .get(format!("http://{}/stitch/macro-test/playlist.m3u8", addr))
+        .header("x-forwarded-for", "203.0.113.7")
+        .header("user-agent", "Player/1.0 (TV)")
+        .header("dnt", "1")
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(
+        body.contains("/stitch/macro-test/ad/break-0-seg-0.ts"),
+        "got:\n{}",
+        body
+    );
+
     let resp = client
-        .get(format!("http://{}/stitch/test-session/playlist.m3u8", addr))
+        .get(format!(
+            "http://{}/stitch/macro-test/ad/break-0-seg-0.ts",
+            addr
+        ))
         .send()
         .await
         .unwrap();
+    assert_eq!(resp.status(), 200);
 
+    // Beacons are fire-and-forget; wait for both to arrive
+    let mut beacons = Vec::new();
+    for _ in 0..50 {
+        beacons = mock_server
+            .received_requests()
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|r| matches!(r.url.path(), "/imp" | "/start"))
+            .map(|r| format!("{}?{}", r.url.path(), r.url.query().unwrap_or_default()))
+            .collect::<Vec<_>>();
+        if beacons.len() == 2 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    beacons.sort();
+
+    let asset = format!("{uri}/ad.mp4")
+        .replace(':', "%3A")
+        .replace('/', "%2F");
     assert_eq!(
-        resp.status(),
-        422,
-        "Non-UTF-8 body should return 422 Unprocessable Entity"
+        beacons,
+        vec![
+            format!("/imp?ip=203.0.113.7&ua=Player%2F1.0%20%28TV%29&lat=1&a={asset}"),
+            "/start?ph=00%3A00%3A00.000&ps=1&bp=2&c=-2".to_string(),
+        ]
     );
 }
 
-/// Origin returns a body that is valid UTF-8 but not parseable as M3U8
-/// → handler returns 422.
+/// `[UPID]`: the ad request for a break carries the segmentation UPID of
+/// its SCTE-35 cue.
 #[tokio::test]
-async fn playlist_invalid_m3u8_body_returns_422() {
+async fn vast_request_carries_break_upid() {
     let mock_server = MockServer::start().await;
+    let uri = mock_server.uri();
 
+    let playlist = HLS_WITH_CUE.replace(
+        "#EXT-X-CUE-OUT:10\n",
+        "#EXT-X-CUE-OUT:10\n#EXT-OATCLS-SCTE35:/DA0AAAAAAAA///wBQb+cr0AUAAeAhxDVUVJSAAAjn/PAAGlmbAICAAAAAAsoKGKNAIAmsnRfg==\n",
+    );
     Mock::given(method("GET"))
         .and(path("/playlist.m3u8"))
-        .respond_with(
-            ResponseTemplate::new(200)
-                .set_body_string("this is not a valid m3u8 playlist at all")
-                .insert_header("content-type", "application/vnd.apple.mpegurl"),
-        )
+        .respond_with(ResponseTemplate::new(200).set_body_string(playlist))
+        .mount(&mock_server)
+        .await;
+    let vast = format!(
+        r#"<VAST version="4.0"><Ad id="a"><InLine><AdSystem>T</AdSystem><AdTitle>A</AdTitle>
+<Creatives><Creative><Linear><Duration>00:00:10</Duration>
+<MediaFiles><MediaFile delivery="progressive" type="video/mp4">{uri}/ad.mp4</MediaFile></MediaFiles>
+</Linear></Creative></Creatives></InLine></Ad></VAST>"#
+    );
+    Mock::given(method("GET"))
+        .and(path("/vast"))
+        .and(wiremock::matchers::query_param(
+            "upid",
+            "0x000000002CA0A18A",
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_string(vast))
+        .expect(1)
         .mount(&mock_server)
         .await;
 
-    let addr = start_server(config_with_origin(&mock_server, "/playlist.m3u8")).await;
-    let client = reqwest::Client::new();
+    let addr = start_server(Config {
+        ad_provider_type: AdProviderType::Vast,
+        vast_endpoint: Some(format!("{uri}/vast?upid=[UPID]")),
+        ..config_with_origin_and_mode(&mock_server, "/playlist.m3u8", StitchingMode::Ssai)
+    })
+    .await;
 
-    let resp = client
-        .get(format!("http://{}/stitch/test-session/playlist.m3u8", addr))
-        .send()
+    let body = reqwest::get(format!("http://{}/stitch/upid-test/playlist.m3u8", addr))
+        .await
+        .unwrap()
+        .text()
         .await
         .unwrap();
-
-    assert_eq!(
-        resp.status(),
-        422,
-        "Invalid M3U8 body should return 422 Unprocessable Entity"
+    assert!(
+        body.contains("/stitch/upid-test/ad/break-0-seg-0.ts"),
+        "got:\n{}",
+        body
     );
 }
 
-// ── Manifest handler — session ID validation ──────────────────────────────────
-
+/// Viewer headers: VAST requests carry the session viewer's `X-Device-*`
+/// and `X-Forwarded-For` headers; tracking beacons carry them only with
+/// `FORWARD_VIEWER_HEADERS=all`.
 #[tokio::test]
-async fn manifest_invalid_session_id_returns_400() {
-    let app = build_router(test_config()).await;
-
-    let req = Request::builder()
-        .uri("/stitch/bad.session/manifest.mpd")
-        .body(Body::empty())
-        .unwrap();
-
-    let resp = app.oneshot(req).await.unwrap();
-    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+async fn vast_request_and_beacons_forward_viewer_headers() {
+    use wiremock::matchers::header;
+
+    for (forwarding, beacon_headers) in [
+        (ViewerHeaderForwarding::All, true),
+        (ViewerHeaderForwarding::Vast, false),
+    ] {
+        let mock_server = MockServer::start().await;
+        let uri = mock_server.uri();
+
+        Mock::given(method("GET"))
+            .and(path("/playlist.m3u8"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(HLS_WITH_CUE))
+            .mount(&mock_server)
+            .await;
+        let vast = format!(
+            r#"<VAST version="4.0"><Ad id="a"><InLine><AdSystem>T</AdSystem><AdTitle>A</AdTitle>
+<Impression><![CDATA[{uri}/imp]]></Impression>
+<Creatives><Creative><Linear><Duration>00:00:10</Duration>
+<MediaFiles><MediaFile delivery="progressive" type="video/mp4">{uri}/ad.mp4</MediaFile></MediaFiles>
+</Linear></Creative></Creatives></InLine></Ad></VAST>"#
+        );
+        Mock::given(method("GET"))
+            .and(path("/vast"))
+            .and(header("x-device-ip", "203.0.113.7"))
+            .and(header("x-forwarded-for", "203.0.113.7"))
+            .and(header("x-device-user-agent", "Player/1.0 (TV)"))
+            .and(header("x-device-referer", "https://tv.example.com/live"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(vast))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        for route in ["/ad.mp4", "/imp"] {
+            Mock::given(method("GET"))
+                .and(path(route))
+                .respond_with(ResponseTemplate::new(200).set_body_bytes(b"ad".to_vec()))
+                .mount(&mock_server)
+                .await;
+        }
+
+        let addr = start_server(Config {
+            ad_provider_type: AdProviderType::Vast,
+            vast_endpoint: Some(format!("{uri}/vast")),
+            forward_viewer_headers: forwarding,
+            ..config_with_origin_and_mode(&mock_server, "/playlist.m3u8", StitchingMode::Ssai)
+        })
+        .await;
+        let client = reqwest::Client::new();
+
+        let body = client
+            .get(format!("http://{addr}/stitch/headers-test/playlist.m3u8"))
+            .header("x-forwarded-for", "203.0.113.7, 10.0.0.1")
+            .header("user-agent", "Player/1.0 (TV)")
+            .header("referer", "https://tv.example.com/live")
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert!(
+            body.contains("/stitch/headers-test/ad/break-0-seg-0.ts"),
+            "VAST request without viewer headers, got:\n{body}"
+        );
+
+        let resp = client
+            .get(format!(
+                "http://{addr}/stitch/headers-test/ad/break-0-seg-0.ts"
+            ))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 200);
+
+        // Beacons are fire-and-forget; wait for the impression to arrive
+        let mut impression = None;
+        for _ in 0..50 {
+            impression = mock_server
+                .received_requests()
+                .await
+                .unwrap()
+                .into_iter()
+                .find(|r| r.url.path() == "/imp");
+            if impression.is_some() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        let impression = impression.expect("impression beacon not fired");
+        assert_eq!(
+            impression.headers.contains_key("x-device-ip"),
+            beacon_headers,
+            "{forwarding:?}"
+        );
+    }
 }
 
+/// Viewer targeting: each session's VAST requests carry the allowlisted
+/// `ad.*` parameters and IP captured on the session's first request, also
+/// on later requests of the session that omit them.
 #[tokio::test]
-async fn manifest_session_id_too_long_returns_400() {
-    let long_id = "b".repeat(65);
-    let app = build_router(test_config()).await;
-
-    let req = Request::builder()
-        .uri(format!("/stitch/{}/manifest.mpd", long_id))
-        .body(Body::empty())
-        .unwrap();
-
-    let resp = app.oneshot(req).await.unwrap();
-    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
-}
-
-// ── Manifest handler — origin error paths (wiremock) ─────────────────────────
+async fn vast_request_carries_session_targeting() {
+    use wiremock::matchers::query_param;
 
-/// Origin returns 500 → manifest handler returns 502.
-#[tokio::test]
-async fn manifest_origin_500_returns_502() {
     let mock_server = MockServer::start().await;
+    let uri = mock_server.uri();
 
+    // Each break is decided once per session: later requests reach a new
+    // break further down the stream
     Mock::given(method("GET"))
-        .and(path("/manifest.mpd"))
-        .respond_with(ResponseTemplate::new(500))
+        .and(path("/playlist.m3u8"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(HLS_WITH_CUE))
+        .up_to_n_times(1)
+        .mount(&mock_server)
+        .await;
+    let later = HLS_WITH_CUE.replace(
+        "#EXT-X-TARGETDURATION:6\n",
+        "#EXT-X-TARGETDURATION:6\n#EXT-X-MEDIA-SEQUENCE:10\n",
+    );
+    Mock::given(method("GET"))
+        .and(path("/playlist.m3u8"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(later))
         .mount(&mock_server)
         .await;
+    let empty_vast = r#"<VAST version="4.0"></VAST>"#;
+    for (genre, ip, hits) in [("sports", "198.51.100.4", 2), ("news", "198.51.100.9", 1)] {
+        Mock::given(method("GET"))
+            .and(path("/vast"))
+            .and(query_param("genre", genre))
+            .and(query_param("ip", ip))
+            .respond_with(ResponseTemplate::new(200).set_body_string(empty_vast))
+            .expect(hits)
+            .mount(&mock_server)
+            .await;
+    }
 
-    let addr = start_server(config_with_origin(&mock_server, "/manifest.mpd")).await;
+    let addr = start_server(Config {
+        ad_provider_type: AdProviderType::Vast,
+        vast_endpoint: Some(format!("{uri}/vast?ip=[DEVICEIP]")),
+        manifest_cache_ttl_ms: 0,
+        ..config_with_origin(&mock_server, "/playlist.m3u8")
+    })
+    .await;
     let client = reqwest::Client::new();
-
-    let resp = client
-        .get(format!("http://{}/stitch/test-session/manifest.mpd", addr))
-        .send()
+    let get = |session: &str, query: &str, ip: &str| {
+        client
+            .get(format!(
+                "http://{addr}/stitch/{session}/playlist.m3u8{query}"
+            ))
+            .header("x-forwarded-for", ip.to_string())
+            .send()
+    };
+
+    let first = get("viewer-a", "?ad.genre=sports&secret=1", "198.51.100.4")
+        .await
+        .unwrap();
+    assert_eq!(first.status(), 200);
+    // Later request of the same session, from another hop and without params
+    let again = get("viewer-a", "", "10.0.0.1").await.unwrap();
+    assert_eq!(again.status(), 200);
+    let other = get("viewer-b", "?ad.genre=news", "198.51.100.9")
         .await
         .unwrap();
+    assert_eq!(other.status(), 200);
 
-    assert_eq!(resp.status(), 502);
+    let forwarded_secret = mock_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .any(|r| r.url.query().is_some_and(|q| q.contains("secret")));
+    assert!(
+        !forwarded_secret,
+        "Non-allowlisted params must not be forwarded"
+    );
 }
 
-/// Origin returns 404 → manifest handler returns 502.
+/// Channel-wide decisions: one VAST request serves every session of the
+/// channel, the stitched playlists are identical, and each session's
+/// impression still fires with its own viewer context.
 #[tokio::test]
-async fn manifest_origin_404_returns_502() {
+async fn shared_decision_serves_channel_from_one_vast_request() {
     let mock_server = MockServer::start().await;
+    let uri = mock_server.uri();
 
     Mock::given(method("GET"))
-        .and(path("/manifest.mpd"))
-        .respond_with(ResponseTemplate::new(404))
+        .and(path("/playlist.m3u8"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(HLS_WITH_CUE))
+        .mount(&mock_server)
+        .await;
+    let vast = format!(
+        r#"<VAST version="4.0"><Ad id="a"><InLine><AdSystem>T</AdSystem><AdTitle>A</AdTitle>
+<Impression><![CDATA[{uri}/imp?ip=[DEVICEIP]]]></Impression>
+<Creatives><Creative><Linear><Duration>00:00:10</Duration>
+<MediaFiles><MediaFile delivery="progressive" type="video/mp4">{uri}/ad.mp4</MediaFile></MediaFiles>
+</Linear></Creative></Creatives></InLine></Ad></VAST>"#
+    );
+    Mock::given(method("GET"))
+        .and(path("/vast"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(vast))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/ad.mp4"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(b"ad".to_vec()))
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/imp"))
+        .respond_with(ResponseTemplate::new(200))
         .mount(&mock_server)
         .await;
 
-    let addr = start_server(config_with_origin(&mock_server, "/manifest.mpd")).await;
+    let addr = start_server(Config {
+        ad_provider_type: AdProviderType::Vast,
+        vast_endpoint: Some(format!("{uri}/vast")),
+        ad_decision_scope: DecisionScope::Channel,
+        ..config_with_origin_and_mode(&mock_server, "/playlist.m3u8", StitchingMode::Ssai)
+    })
+    .await;
     let client = reqwest::Client::new();
 
+    let mut bodies = Vec::new();
+    for (session, ip) in [("viewer-a", "203.0.113.7"), ("viewer-b", "203.0.113.8")] {
+        let body = client
+            .get(format!(
+                "http://{}/stitch/{}/playlist.m3u8?channel=news",
+                addr, session
+            ))
+            .header("x-forwarded-for", ip)
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        bodies.push(body);
+    }
+    assert_eq!(bodies[0], bodies[1]);
+
+    let ad_uri = bodies[0]
+        .lines()
+        .find(|line| line.contains("/ad/"))
+        .expect("stitched playlist has an ad segment");
+    assert!(
+        ad_uri.contains("/stitch/_shared-") && ad_uri.ends_with("/ad/shared-1-seg-0.ts"),
+        "got: {}",
+        ad_uri
+    );
+    let ad_path = &ad_uri[ad_uri.find("/stitch/").unwrap()..];
     let resp = client
-        .get(format!("http://{}/stitch/test-session/manifest.mpd", addr))
+        .get(format!("http://{}{}", addr, ad_path))
         .send()
         .await
         .unwrap();
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.bytes().await.unwrap().as_ref(), b"ad");
 
-    assert_eq!(resp.status(), 502);
+    // A break this instance has not decided is not served
+    let undecided = ad_path.replace("/shared-1-seg-0.ts", "/shared-99-seg-0.ts");
+    let resp = client
+        .get(format!("http://{}{}", addr, undecided))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 404);
+
+    // Beacons are fire-and-forget; wait for both sessions' impressions
+    let mut impressions = Vec::new();
+    for _ in 0..50 {
+        impressions = mock_server
+            .received_requests()
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|r| r.url.path() == "/imp")
+            .map(|r| r.url.query().unwrap_or_default().to_string())
+            .collect::<Vec<_>>();
+        if impressions.len() == 2 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    impressions.sort();
+    assert_eq!(impressions, vec!["ip=203.0.113.7", "ip=203.0.113.8"]);
 }
 
-/// Origin returns a valid DASH MPD → handler returns 200 with correct
-/// Content-Type and valid XML body.
+/// A live break is decided once per session: refreshes and renditions get
+/// the same pod, and a frequency cap of 1 does not trip mid-break.
 #[tokio::test]
-async fn manifest_valid_origin_returns_200_with_dash_content_type() {
+async fn live_break_pod_is_the_same_across_refreshes_and_renditions() {
+    const LIVE_WITH_CUE: &str = "#EXTM3U
+#EXT-X-VERSION:3
+#EXT-X-TARGETDURATION:10
+#EXT-X-MEDIA-SEQUENCE:100
+#EXTINF:10.0,
+seg-100.ts
+#EXT-X-CUE-OUT:20
+#EXTINF:10.0,
+seg-101.ts
+#EXTINF:10.0,
+seg-102.ts
+#EXT-X-CUE-IN
+#EXTINF:10.0,
+seg-103.ts
+";
     let mock_server = MockServer::start().await;
+    let uri = mock_server.uri();
 
     Mock::given(method("GET"))
-        .and(path("/manifest.mpd"))
-        .respond_with(
-            ResponseTemplate::new(200)
-                .set_body_string(MINIMAL_MPD)
-                .insert_header("content-type", "application/dash+xml"),
+        .and(path("/live.m3u8"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(LIVE_WITH_CUE))
+        .mount(&mock_server)
+        .await;
+    let ad = |id: &str| {
+        format!(
+            r#"<Ad id="{id}"><InLine><AdSystem>T</AdSystem><AdTitle>{id}</AdTitle>
+<Creatives><Creative><UniversalAdId idRegistry="ad-id.org">{id}</UniversalAdId>
+<Linear><Duration>00:00:10</Duration>
+<MediaFiles><MediaFile delivery="progressive" type="video/mp4">{uri}/{id}.mp4</MediaFile></MediaFiles>
+</Linear></Creative></Creatives></InLine></Ad>"#
         )
+    };
+    Mock::given(method("GET"))
+        .and(path("/vast"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(format!(
+            r#"<VAST version="4.0">{}{}</VAST>"#,
+            ad("A"),
+            ad("B")
+        )))
+        .expect(1)
         .mount(&mock_server)
         .await;
 
-    let addr = start_server(config_with_origin(&mock_server, "/manifest.mpd")).await;
+    let addr = start_server(Config {
+        ad_provider_type: AdProviderType::Vast,
+        vast_endpoint: Some(format!("{uri}/vast")),
+        ad_frequency_cap: 1,
+        ..config_with_origin_and_mode(&mock_server, "/live.m3u8", StitchingMode::Ssai)
+    })
+    .await;
     let client = reqwest::Client::new();
 
-    let resp = client
-        .get(format!("http://{}/stitch/test-session/manifest.mpd", addr))
-        .send()
-        .await
-        .unwrap();
-
-    assert_eq!(resp.status(), 200);
-    let ct = resp
-        .headers()
-        .get("content-type")
-        .unwrap()
-        .to_str()
-        .unwrap();
-    assert!(
-        ct.contains("dash+xml"),
-        "Expected DASH content-type, got: {}",
-        ct
-    );
+    let mut pods = Vec::new();
+    for query in ["", "", "?track=audio"] {
+        let body = client
+            .get(format!(
+                "http://{}/stitch/viewer/playlist.m3u8{}",
+                addr, query
+            ))
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        let pod: Vec<String> = body
+            .lines()
+            .filter(|line| line.contains("/ad/"))
+            .map(str::to_string)
+            .collect();
+        pods.push(pod);
+    }
 
-    let body = resp.text().await.unwrap();
-    assert!(body.contains("<MPD"), "Body should contain MPD element");
+    assert_eq!(pods[0].len(), 2, "both ads fill the break: {:?}", pods[0]);
+    assert_eq!(pods[1], pods[0]);
+    assert_eq!(pods[2], pods[0]);
 }
 
-/// SSAI mode: origin MPD with SCTE-35 EventStream → stitched MPD has ad Periods.
+/// SGAI mode: origin playlist with CUE-OUT break → stitched playlist has
+/// EXT-X-DATERANGE interstitial tags (no segment replacement).
 #[tokio::test]
-async fn manifest_ssai_mode_inserts_ad_periods() {
+async fn playlist_sgai_mode_injects_daterange_tags() {
     let mock_server = MockServer::start().await;
 
     Mock::given(method("GET"))
-        .and(path("/manifest.mpd"))
+        .and(path("/playlist.m3u8"))
         .respond_with(
             ResponseTemplate::new(200)
-                .set_body_string(MINIMAL_MPD)
-                .insert_header("content-type", "application/dash+xml"),
+                .set_body_string(HLS_WITH_CUE)
+                .insert_header("content-type", "application/vnd.apple.mpegurl"),
         )
         .mount(&mock_server)
         .await;
 
     let addr = start_server(config_with_origin_and_mode(
         &mock_server,
-        "/manifest.mpd",
+        "/playlist.m3u8",
+        StitchingMode::Sgai,
+    ))
+    .await;
+    let client = reqwest::Client::new();
+
+    let resp = client
+        .get(format!("http://{}/stitch/sgai-test/playlist.m3u8", addr))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(resp.status(), 200);
+    let body = resp.text().await.unwrap();
+
+    assert!(
+        body.contains("EXT-X-DATERANGE"),
+        "SGAI must inject EXT-X-DATERANGE tags, got:\n{}",
+        body
+    );
+    assert!(
+        body.contains("com.apple.hls.interstitial"),
+        "SGAI must include interstitial CLASS, got:\n{}",
+        body
+    );
+    // SGAI must NOT inject DISCONTINUITY (segments are not replaced)
+    assert!(
+        !body.contains("#EXT-X-DISCONTINUITY"),
+        "SGAI must not inject DISCONTINUITY tags, got:\n{}",
+        body
+    );
+}
+
+/// VOD playlist (ENDLIST, no CUE markers) of four 10s segments.
+const HLS_VOD: &str = "#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-TARGETDURATION:10\n\
+#EXTINF:10.0,\nvod-0.ts\n#EXTINF:10.0,\nvod-1.ts\n#EXTINF:10.0,\nvod-2.ts\n\
+#EXTINF:10.0,\nvod-3.ts\n#EXT-X-ENDLIST\n";
+
+/// Inline VAST with a single 15s HLS creative.
+const VAST_15S: &str = r#"<VAST version="3.0"><Ad id="a"><InLine><AdSystem>T</AdSystem><AdTitle>A</AdTitle>
+<Creatives><Creative><Linear><Duration>00:00:15</Duration><MediaFiles>
+<MediaFile delivery="streaming" type="application/x-mpegURL">https://ads.example.com/a.m3u8</MediaFile>
+</MediaFiles></Linear></Creative></Creatives></InLine></Ad></VAST>"#;
+
+/// Mount a VMAP with a pre-roll (tag), a mid-roll at 00:00:15 (inline
+/// VAST) and a post-roll (tag) at `/vmap`.
+async fn mount_vmap(mock_server: &MockServer) {
+    let vmap = format!(
+        r#"<vmap:VMAP xmlns:vmap="http://www.iab.net/videosuite/vmap" version="1.0">
+  <vmap:AdBreak timeOffset="start" breakType="linear"><vmap:AdSource>
+    <vmap:AdTagURI><![CDATA[{uri}/vast]]></vmap:AdTagURI></vmap:AdSource></vmap:AdBreak>
+  <vmap:AdBreak timeOffset="00:00:15" breakType="linear"><vmap:AdSource>
+    <vmap:VASTAdData>{vast}</vmap:VASTAdData></vmap:AdSource></vmap:AdBreak>
+  <vmap:AdBreak timeOffset="end" breakType="linear"><vmap:AdSource>
+    <vmap:AdTagURI><![CDATA[{uri}/vast]]></vmap:AdTagURI></vmap:AdSource></vmap:AdBreak>
+</vmap:VMAP>"#,
+        uri = mock_server.uri(),
+        vast = VAST_15S
+    );
+    Mock::given(method("GET"))
+        .and(path("/vmap"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(vmap))
+        .mount(mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/vast"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(VAST_15S))
+        .mount(mock_server)
+        .await;
+}
+
+/// VMAP + VOD playlist in SSAI mode → ads are inserted before, inside
+/// (at the next segment boundary) and after the content.
+#[tokio::test]
+async fn playlist_vmap_inserts_pre_mid_and_post_rolls() {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/vod.m3u8"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(HLS_VOD))
+        .mount(&mock_server)
+        .await;
+    mount_vmap(&mock_server).await;
+
+    let addr = start_server(Config {
+        ad_provider_type: AdProviderType::Vast,
+        vast_endpoint: Some(format!("{}/unused", mock_server.uri())),
+        vmap_url: Some(format!("{}/vmap", mock_server.uri())),
+        ..config_with_origin(&mock_server, "/vod.m3u8")
+    })
+    .await;
+
+    let body = reqwest::get(format!("http://{}/stitch/vmap-ssai/playlist.m3u8", addr))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    let uris: Vec<&str> = body
+        .lines()
+        .filter(|l| !l.starts_with('#') && !l.is_empty())
+        .map(|l| l.split('?').next().unwrap())
+        .map(|l| {
+            if l.contains("/ad/") {
+                "ad"
+            } else {
+                l.rsplit('/').next().unwrap()
+            }
+        })
+        .collect();
+    assert_eq!(
+        uris,
+        vec![
+            "ad", "vod-0.ts", "vod-1.ts", "ad", "vod-2.ts", "vod-3.ts", "ad"
+        ],
+        "got:\n{}",
+        body
+    );
+    assert_eq!(body.matches("#EXT-X-DISCONTINUITY").count(), 5);
+    assert!(body.contains("#EXT-X-ENDLIST"));
+}
+
+/// VMAP + VOD playlist in SGAI mode → pre/post-roll interstitials carry
+/// CUE attributes and the asset-list fills the break from its ad source.
+#[tokio::test]
+async fn playlist_vmap_sgai_signals_cue_and_fills_asset_list() {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/vod.m3u8"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(HLS_VOD))
+        .mount(&mock_server)
+        .await;
+    mount_vmap(&mock_server).await;
+
+    let addr = start_server(Config {
+        ad_provider_type: AdProviderType::Vast,
+        vast_endpoint: Some(format!("{}/unused", mock_server.uri())),
+        vmap_url: Some(format!("{}/vmap", mock_server.uri())),
+        ..config_with_origin_and_mode(&mock_server, "/vod.m3u8", StitchingMode::Sgai)
+    })
+    .await;
+
+    let body = reqwest::get(format!("http://{}/stitch/vmap-sgai/playlist.m3u8", addr))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    assert_eq!(body.matches("com.apple.hls.interstitial").count(), 3);
+    assert!(body.contains("CUE=\"PRE\""), "got:\n{}", body);
+    assert!(body.contains("CUE=\"POST\""), "got:\n{}", body);
+    assert!(
+        body.contains("/stitch/vmap-sgai/asset-list/1?dur=30&vmap=1"),
+        "got:\n{}",
+        body
+    );
+
+    let json: serde_json::Value = reqwest::get(format!(
+        "http://{}/stitch/vmap-sgai/asset-list/1?dur=30&vmap=1",
+        addr
+    ))
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+    assert_eq!(json["ASSETS"][0]["URI"], "https://ads.example.com/a.m3u8");
+    assert_eq!(json["ASSETS"][0]["DURATION"], 15.0);
+}
+
+/// Live playlist of six 6s segments whose first segment starts at `pdt`.
+fn live_playlist_at(pdt: chrono::DateTime<chrono::Utc>) -> String {
+    let mut playlist = format!(
+        "#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-TARGETDURATION:6\n#EXT-X-MEDIA-SEQUENCE:100\n\
+         #EXT-X-PROGRAM-DATE-TIME:{}\n",
+        pdt.to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+    );
+    for i in 0..6 {
+        playlist.push_str(&format!("#EXTINF:6.0,\nlive-{}.ts\n", i));
+    }
+    playlist
+}
+
+/// Push breaks for `channel` through the schedule API (dev mode, no token).
+async fn push_breaks(addr: SocketAddr, channel: &str, body: serde_json::Value) {
+    let response = reqwest::Client::new()
+        .post(format!("http://{}/schedule/{}/breaks", addr, channel))
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+}
+
+/// Schedule API: bearer token required, breaks can be added, listed and
+/// removed, and invalid payloads are rejected as a whole.
+#[tokio::test]
+async fn schedule_api_manages_channel_breaks() {
+    let addr = start_server(Config {
+        schedule_api_token: Some("s3cret".to_string()),
+        ..test_config()
+    })
+    .await;
+    let client = reqwest::Client::new();
+    let url = format!("http://{}/schedule/news/breaks", addr);
+    let valid = serde_json::json!([
+        {"id": "b1", "start_in": 60, "duration": 30},
+        {"start": "2099-01-01T00:00:00Z", "duration": 45}
+    ]);
+
+    let unauthorized = client.post(&url).json(&valid).send().await.unwrap();
+    assert_eq!(unauthorized.status(), 401);
+    let wrong_token = client.get(&url).bearer_auth("guess").send().await.unwrap();
+    assert_eq!(wrong_token.status(), 401);
+
+    let invalid = client
+        .post(&url)
+        .bearer_auth("s3cret")
+        .json(&serde_json::json!([
+            {"id": "ok", "start_in": 60, "duration": 30},
+            {"start": "tomorrow", "duration": 30}
+        ]))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(invalid.status(), 400);
+
+    let created = client
+        .post(&url)
+        .bearer_auth("s3cret")
+        .json(&valid)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(created.status(), 201);
+
+    let listed: serde_json::Value = client
+        .get(&url)
+        .bearer_auth("s3cret")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let ids: Vec<&str> = listed
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|b| b["id"].as_str().unwrap())
+        .collect();
+    assert_eq!(ids, vec!["b1", "sched-4070908800000"]);
+    assert_eq!(listed[1]["end"], "2099-01-01T00:00:45+00:00");
+
+    let delete = |id: &str| {
+        client
+            .delete(format!("{}/{}", url, id))
+            .bearer_auth("s3cret")
+            .send()
+    };
+    assert_eq!(delete("b1").await.unwrap().status(), 204);
+    assert_eq!(delete("b1").await.unwrap().status(), 404);
+}
+
+/// Schedule API without a token is closed outside dev mode.
+#[tokio::test]
+async fn schedule_api_disabled_without_token_in_production() {
+    let addr = start_server(Config {
+        is_dev: false,
+        ..test_config()
+    })
+    .await;
+    let response = reqwest::get(format!("http://{}/schedule/news/breaks", addr))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 401);
+}
+
+/// Live playlist without SCTE-35 + a scheduled break on its channel → the
+/// segments under the break are replaced by ads, as for a CUE-OUT/CUE-IN.
+#[tokio::test]
+async fn playlist_channel_schedule_replaces_segments_with_ads() {
+    let mock_server = MockServer::start().await;
+    let pdt = chrono::Utc::now();
+    Mock::given(method("GET"))
+        .and(path("/live.m3u8"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(live_playlist_at(pdt)))
+        .mount(&mock_server)
+        .await;
+
+    let addr = start_server(config_with_origin(&mock_server, "/live.m3u8")).await;
+    let start = pdt + chrono::Duration::seconds(12);
+    push_breaks(
+        addr,
+        "news",
+        serde_json::json!({"id": "top", "start": start.to_rfc3339(), "duration": 12}),
+    )
+    .await;
+
+    let body = reqwest::get(format!(
+        "http://{}/stitch/sched/playlist.m3u8?channel=news",
+        addr
+    ))
+    .await
+    .unwrap()
+    .text()
+    .await
+    .unwrap();
+    assert!(!body.contains("live-2.ts"), "got:\n{}", body);
+    assert!(!body.contains("live-3.ts"), "got:\n{}", body);
+    assert!(body.contains("live-4.ts"));
+    assert!(body.contains("/stitch/sched/ad/"), "got:\n{}", body);
+
+    // Other channels are unaffected
+    let other = reqwest::get(format!("http://{}/stitch/sched/playlist.m3u8", addr))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(other.contains("live-2.ts"));
+    assert!(!other.contains("/ad/"));
+}
+
+/// SGAI: a scheduled break becomes an interstitial DATERANGE.
+#[tokio::test]
+async fn playlist_channel_schedule_injects_interstitial() {
+    let mock_server = MockServer::start().await;
+    let pdt = chrono::Utc::now();
+    Mock::given(method("GET"))
+        .and(path("/live.m3u8"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(live_playlist_at(pdt)))
+        .mount(&mock_server)
+        .await;
+
+    let addr = start_server(config_with_origin_and_mode(
+        &mock_server,
+        "/live.m3u8",
+        StitchingMode::Sgai,
+    ))
+    .await;
+    push_breaks(
+        addr,
+        "default",
+        serde_json::json!([{"start": (pdt + chrono::Duration::seconds(18)).to_rfc3339(), "duration": 30}]),
+    )
+    .await;
+
+    let body = reqwest::get(format!("http://{}/stitch/sgai-sched/playlist.m3u8", addr))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert_eq!(
+        body.matches("com.apple.hls.interstitial").count(),
+        1,
+        "got:\n{}",
+        body
+    );
+    assert!(body.contains("DURATION=30"), "got:\n{}", body);
+}
+
+/// The `channel` parameter of a master playlist request is carried to its
+/// variant and rendition playlists.
+#[tokio::test]
+async fn playlist_master_keeps_channel_param() {
+    let mock_server = MockServer::start().await;
+    let master = "#EXTM3U\n\
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aud\",NAME=\"en\",URI=\"audio.m3u8\"\n\
+#EXT-X-STREAM-INF:BANDWIDTH=800000,AUDIO=\"aud\"\nvideo.m3u8\n";
+    Mock::given(method("GET"))
+        .and(path("/master.m3u8"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(master))
+        .mount(&mock_server)
+        .await;
+
+    let addr = start_server(config_with_origin(&mock_server, "/master.m3u8")).await;
+    let body = reqwest::get(format!(
+        "http://{}/stitch/m/playlist.m3u8?channel=news",
+        addr
+    ))
+    .await
+    .unwrap()
+    .text()
+    .await
+    .unwrap();
+
+    assert!(
+        body.contains("video.m3u8&bw=800000&channel=news"),
+        "got:\n{}",
+        body
+    );
+    assert!(
+        body.contains("audio.m3u8&track=audio&channel=news"),
+        "got:\n{}",
+        body
+    );
+
+    let invalid = reqwest::get(format!(
+        "http://{}/stitch/m/playlist.m3u8?channel=a%2Fb",
+        addr
+    ))
+    .await
+    .unwrap();
+    assert_eq!(invalid.status(), 400);
+}
+
+/// Origin returns a body that is not valid UTF-8 → handler returns 422.
+#[tokio::test]
+async fn playlist_non_utf8_body_returns_422() {
+    let mock_server = MockServer::start().await;
+
+    // Raw bytes that are not valid UTF-8
+    let invalid_utf8 = vec![0xFF, 0xFE, 0x00, 0x01];
+
+    Mock::given(method("GET"))
+        .and(path("/playlist.m3u8"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(invalid_utf8)
+                .insert_header("content-type", "application/vnd.apple.mpegurl"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let addr = start_server(config_with_origin(&mock_server, "/playlist.m3u8")).await;
+    let client = reqwest::Client::new();
+
+    let resp = client
+        .get(format!("http://{}/stitch/test-session/playlist.m3u8", addr))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(
+        resp.status(),
+        422,
+        "Non-UTF-8 body should return 422 Unprocessable Entity"
+    );
+}
+
+/// Origin returns a body that is valid UTF-8 but not parseable as M3U8
+/// → handler returns 422.
+#[tokio::test]
+async fn playlist_invalid_m3u8_body_returns_422() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/playlist.m3u8"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_string("this is not a valid m3u8 playlist at all")
+                .insert_header("content-type", "application/vnd.apple.mpegurl"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let addr = start_server(config_with_origin(&mock_server, "/playlist.m3u8")).await;
+    let client = reqwest::Client::new();
+
+    let resp = client
+        .get(format!("http://{}/stitch/test-session/playlist.m3u8", addr))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(
+        resp.status(),
+        422,
+        "Invalid M3U8 body should return 422 Unprocessable Entity"
+    );
+}
+
+// ── Manifest handler — session ID validation ──────────────────────────────────
+
+#[tokio::test]
+async fn manifest_invalid_session_id_returns_400() {
+    let app = build_router(test_config()).await;
+
+    let req = Request::builder()
+        .uri("/stitch/bad.session/manifest.mpd")
+        .body(Body::empty())
+        .unwrap();
+
+    let resp = app.oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn manifest_session_id_too_long_returns_400() {
+    let long_id = "b".repeat(65);
+    let app = build_router(test_config()).await;
+
+    let req = Request::builder()
+        .uri(format!("/stitch/{}/manifest.mpd", long_id))
+        .body(Body::empty())
+        .unwrap();
+
+    let resp = app.oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
+
+// ── Manifest handler — origin error paths (wiremock) ─────────────────────────
+
+/// Origin returns 500 → manifest handler returns 502.
+#[tokio::test]
+async fn manifest_origin_500_returns_502() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/manifest.mpd"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&mock_server)
+        .await;
+
+    let addr = start_server(config_with_origin(&mock_server, "/manifest.mpd")).await;
+    let client = reqwest::Client::new();
+
+    let resp = client
+        .get(format!("http://{}/stitch/test-session/manifest.mpd", addr))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(resp.status(), 502);
+}
+
+/// Origin returns 404 → manifest handler returns 502.
+#[tokio::test]
+async fn manifest_origin_404_returns_502() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/manifest.mpd"))
+        .respond_with(ResponseTemplate::new(404))
+        .mount(&mock_server)
+        .await;
+
+    let addr = start_server(config_with_origin(&mock_server, "/manifest.mpd")).await;
+    let client = reqwest::Client::new();
+
+    let resp = client
+        .get(format!("http://{}/stitch/test-session/manifest.mpd", addr))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(resp.status(), 502);
+}
+
+/// Origin returns a valid DASH MPD → handler returns 200 with correct
+/// Content-Type and valid XML body.
+#[tokio::test]
+async fn manifest_valid_origin_returns_200_with_dash_content_type() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/manifest.mpd"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_string(MINIMAL_MPD)
+                .insert_header("content-type", "application/dash+xml"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let addr = start_server(config_with_origin(&mock_server, "/manifest.mpd")).await;
+    let client = reqwest::Client::new();
+
+    let resp = client
+        .get(format!("http://{}/stitch/test-session/manifest.mpd", addr))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(resp.status(), 200);
+    let ct = resp
+        .headers()
+        .get("content-type")
+        .unwrap()
+        .to_str()
+        .unwrap();
+    assert!(
+        ct.contains("dash+xml"),
+        "Expected DASH content-type, got: {}",
+        ct
+    );
+
+    let body = resp.text().await.unwrap();
+    assert!(body.contains("<MPD"), "Body should contain MPD element");
+}
+
+/// SSAI mode: origin MPD with SCTE-35 EventStream → stitched MPD has ad Periods.
+#[tokio::test]
+async fn manifest_ssai_mode_inserts_ad_periods() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/manifest.mpd"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_string(MINIMAL_MPD)
+                .insert_header("content-type", "application/dash+xml"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let addr = start_server(config_with_origin_and_mode(
+        &mock_server,
+        "/manifest.mpd",
         StitchingMode::Ssai,
     ))
     .await;
@@ -832,6 +1863,352 @@ async fn manifest_ssai_mode_inserts_ad_periods() {
     );
 }
 
+/// Like [`MINIMAL_MPD`], with a SegmentTemplate whose content URLs the
+/// stitcher rewrites through a session path.
+const TEMPLATE_MPD: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<MPD xmlns="urn:mpeg:dash:schema:mpd:2011"
+     xmlns:scte35="urn:scte:scte35:2013:xml"
+     type="static"
+     mediaPresentationDuration="PT60S"
+     minBufferTime="PT2S">
+  <Period id="content" start="PT0S" duration="PT10S">
+    <EventStream schemeIdUri="urn:scte:scte35:2013:xml" timescale="90000">
+      <Event id="1" duration="900000">
+        <scte35:SpliceInfoSection>
+          <scte35:SpliceInsert spliceEventId="1" outOfNetworkIndicator="1"/>
+        </scte35:SpliceInfoSection>
+      </Event>
+    </EventStream>
+    <AdaptationSet mimeType="video/mp4" segmentAlignment="true">
+      <SegmentTemplate media="seg-$Number$.m4s" initialization="init.mp4" timescale="90000" duration="360000"/>
+      <Representation id="1" bandwidth="800000" codecs="avc1.42c01e" width="640" height="360"/>
+    </AdaptationSet>
+  </Period>
+</MPD>
+"#;
+
+/// Channel-wide decisions apply to DASH: one ad request serves every
+/// session, and the sessions get byte-identical MPDs whose ad Periods and
+/// content segments share the decision ID's URLs.
+#[tokio::test]
+async fn manifest_shared_decision_serves_channel_from_one_ad_request() {
+    let mock_server = MockServer::start().await;
+    let uri = mock_server.uri();
+
+    Mock::given(method("GET"))
+        .and(path("/manifest.mpd"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(TEMPLATE_MPD))
+        .mount(&mock_server)
+        .await;
+    let vast = format!(
+        r#"<VAST version="4.0"><Ad id="a"><InLine><AdSystem>T</AdSystem><AdTitle>A</AdTitle>
+<Impression><![CDATA[{uri}/imp?ip=[DEVICEIP]]]></Impression>
+<Creatives><Creative><Linear><Duration>00:00:10</Duration>
+<MediaFiles><MediaFile delivery="progressive" type="video/mp4">{uri}/ad.mp4</MediaFile></MediaFiles>
+</Linear></Creative></Creatives></InLine></Ad></VAST>"#
+    );
+    Mock::given(method("GET"))
+        .and(path("/vast"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(vast))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/imp"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&mock_server)
+        .await;
+
+    let addr = start_server(Config {
+        ad_provider_type: AdProviderType::Vast,
+        vast_endpoint: Some(format!("{uri}/vast")),
+        ad_decision_scope: DecisionScope::Channel,
+        ..config_with_origin_and_mode(&mock_server, "/manifest.mpd", StitchingMode::Ssai)
+    })
+    .await;
+    let client = reqwest::Client::new();
+
+    let mut bodies = Vec::new();
+    let mut ad_urls = Vec::new();
+    for (session, ip) in [("viewer-a", "203.0.113.7"), ("viewer-b", "203.0.113.8")] {
+        let body = client
+            .get(format!(
+                "http://{}/stitch/{}/manifest.mpd?channel=news",
+                addr, session
+            ))
+            .header("x-forwarded-for", ip)
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        let urls: Vec<String> = body
+            .lines()
+            .filter(|line| line.contains("/ad/"))
+            .map(str::to_string)
+            .collect();
+        assert!(
+            !urls.is_empty() && urls.iter().all(|url| url.contains("/stitch/_shared-")),
+            "got:\n{}",
+            body
+        );
+        assert!(
+            body.contains("/stitch/_shared-") && body.contains("/segment/seg-$Number$.m4s"),
+            "got:\n{}",
+            body
+        );
+        ad_urls.push(urls);
+        bodies.push(body);
+    }
+    assert_eq!(ad_urls[0], ad_urls[1]);
+    assert_eq!(bodies[0], bodies[1], "Cohort sessions must share one MPD");
+
+    // Each session's impression fires with its own viewer context
+    let mut impressions = Vec::new();
+    for _ in 0..50 {
+        impressions = mock_server
+            .received_requests()
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|r| r.url.path() == "/imp")
+            .map(|r| r.url.query().unwrap_or_default().to_string())
+            .collect::<Vec<_>>();
+        if impressions.len() == 2 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    impressions.sort();
+    assert_eq!(impressions, vec!["ip=203.0.113.7", "ip=203.0.113.8"]);
+}
+
+/// CMAF MPD without an EventStream; SCTE-35 is carried in-band as `emsg`.
+const INBAND_MPD: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<MPD xmlns="urn:mpeg:dash:schema:mpd:2011" type="static" mediaPresentationDuration="PT60S" minBufferTime="PT2S">
+  <Period id="content" start="PT0S">
+    <AdaptationSet mimeType="video/mp4">
+      <SegmentTemplate media="seg-$Number$.m4s" timescale="90000" duration="360000"/>
+      <Representation id="1" bandwidth="800000" codecs="avc1.42c01e" width="640" height="360"/>
+    </AdaptationSet>
+  </Period>
+</MPD>
+"#;
+
+/// fMP4 segment prefix: a version 1 `emsg` carrying a SCTE-35 `time_signal`
+/// (Provider Placement Opportunity Start) at 10s for 30s, then `moof`.
+fn inband_scte35_segment() -> Vec<u8> {
+    let section = ritcher::scte35::decode_base64(
+        "/DA0AAAAAAAA///wBQb+cr0AUAAeAhxDVUVJSAAAjn/PAAGlmbAICAAAAAAsoKGKNAIAmsnRfg==",
+    )
+    .unwrap();
+    let mut body = vec![1, 0, 0, 0];
+    body.extend_from_slice(&90_000u32.to_be_bytes());
+    body.extend_from_slice(&900_000u64.to_be_bytes());
+    body.extend_from_slice(&2_700_000u32.to_be_bytes());
+    body.extend_from_slice(&1u32.to_be_bytes());
+    body.extend_from_slice(b"urn:scte:scte35:2013:bin\0\0");
+    body.extend_from_slice(&section);
+
+    let mut segment = u32::try_from(body.len() + 8)
+        .unwrap()
+        .to_be_bytes()
+        .to_vec();
+    segment.extend_from_slice(b"emsg");
+    segment.extend_from_slice(&body);
+    segment.extend_from_slice(&[0, 0, 0, 8, b'm', b'o', b'o', b'f']);
+    segment.extend_from_slice(&[0, 0, 0, 16, b'm', b'd', b'a', b't']);
+    segment.extend_from_slice(&[0; 8]);
+    segment
+}
+
+/// INBAND_SCTE35: a proxied segment carrying `emsg` SCTE-35 makes the next
+/// MPD for the session insert an ad Period; the segment bytes are unchanged.
+#[tokio::test]
+async fn inband_emsg_break_used_by_next_manifest() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/manifest.mpd"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_string(INBAND_MPD)
+                .insert_header("content-type", "application/dash+xml"),
+        )
+        .mount(&mock_server)
+        .await;
+    // Without `?origin=` (127.x is rejected by the SSRF validator) the proxy
+    // resolves segments against the configured origin URL
+    Mock::given(method("GET"))
+        .and(path("/manifest.mpd/seg-1.m4s"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(inband_scte35_segment())
+                .insert_header("content-type", "video/mp4"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let config = Config {
+        inband_scte35: true,
+        ..config_with_origin(&mock_server, "/manifest.mpd")
+    };
+    let addr = start_server(config).await;
+    let client = reqwest::Client::new();
+    let manifest_url = format!("http://{}/stitch/inband-session/manifest.mpd", addr);
+
+    let before = client
+        .get(&manifest_url)
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(
+        !before.contains("ad-0"),
+        "No break before the segment is seen"
+    );
+
+    let segment = client
+        .get(format!(
+            "http://{}/stitch/inband-session/segment/seg-1.m4s",
+            addr
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(segment.status(), 200);
+    assert_eq!(
+        segment.bytes().await.unwrap().as_ref(),
+        inband_scte35_segment().as_slice()
+    );
+
+    let after = client
+        .get(&manifest_url)
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(
+        after.contains("ad-0"),
+        "In-band break must insert an ad Period, got:\n{}",
+        after
+    );
+
+    // Another session has not seen the segment
+    let other = client
+        .get(format!("http://{}/stitch/other-session/manifest.mpd", addr))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(!other.contains("ad-0"));
+}
+
+/// VMAP + static MPD in SSAI mode → the content Period is split at the
+/// mid-roll and ad Periods are inserted before, between and after it.
+#[tokio::test]
+async fn manifest_vmap_splits_period_for_mid_roll() {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/manifest.mpd"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_string(INBAND_MPD)
+                .insert_header("content-type", "application/dash+xml"),
+        )
+        .mount(&mock_server)
+        .await;
+    mount_vmap(&mock_server).await;
+
+    let addr = start_server(Config {
+        vmap_url: Some(format!("{}/vmap", mock_server.uri())),
+        ..config_with_origin(&mock_server, "/manifest.mpd")
+    })
+    .await;
+
+    let body = reqwest::get(format!("http://{}/stitch/vmap-dash/manifest.mpd", addr))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    let mpd = dash_mpd::parse(&body).unwrap();
+    let ids: Vec<&str> = mpd
+        .periods
+        .iter()
+        .map(|p| p.id.as_deref().unwrap_or(""))
+        .collect();
+    assert_eq!(ids.len(), 5, "got:\n{}", body);
+    assert!(ids[0].starts_with("ad-"), "got {:?}", ids);
+    assert_eq!(ids[1], "content");
+    assert!(ids[2].starts_with("ad-"), "got {:?}", ids);
+    assert_eq!(ids[3], "content-vmap-1");
+    assert!(ids[4].starts_with("ad-"), "got {:?}", ids);
+
+    let tail = mpd.periods[3].adaptations[0]
+        .SegmentTemplate
+        .as_ref()
+        .unwrap();
+    assert_eq!(tail.presentationTimeOffset, Some(1_350_000));
+    assert_eq!(tail.startNumber, Some(4));
+}
+
+/// Dynamic MPD without SCTE-35 + a scheduled break on the default channel
+/// → an ad Period is inserted, placed via `availabilityStartTime`.
+#[tokio::test]
+async fn manifest_channel_schedule_inserts_ad_period() {
+    let mock_server = MockServer::start().await;
+    let ast = chrono::Utc::now() - chrono::Duration::seconds(600);
+    let mpd = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<MPD xmlns="urn:mpeg:dash:schema:mpd:2011" type="dynamic" availabilityStartTime="{}" minimumUpdatePeriod="PT2S" minBufferTime="PT2S">
+  <Period id="live" start="PT0S">
+    <AdaptationSet mimeType="video/mp4">
+      <SegmentTemplate media="seg-$Number$.m4s" timescale="90000" duration="360000"/>
+      <Representation id="1" bandwidth="800000" codecs="avc1.42c01e" width="640" height="360"/>
+    </AdaptationSet>
+  </Period>
+</MPD>
+"#,
+        ast.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+    );
+    Mock::given(method("GET"))
+        .and(path("/live.mpd"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_string(mpd)
+                .insert_header("content-type", "application/dash+xml"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let addr = start_server(config_with_origin(&mock_server, "/live.mpd")).await;
+    let url = format!("http://{}/stitch/dash-sched/manifest.mpd", addr);
+
+    let before = reqwest::get(&url).await.unwrap().text().await.unwrap();
+    assert!(!before.contains("ad-0"));
+
+    push_breaks(
+        addr,
+        "default",
+        serde_json::json!({"start_in": 10, "duration": 30}),
+    )
+    .await;
+
+    let after = reqwest::get(&url).await.unwrap().text().await.unwrap();
+    let stitched = dash_mpd::parse(&after).unwrap();
+    assert_eq!(stitched.periods.len(), 2, "got:\n{}", after);
+    assert!(after.contains("ad-0"), "got:\n{}", after);
+}
+
 /// SGAI mode: origin MPD with SCTE-35 EventStream → stitched MPD has callback
 /// EventStreams instead of ad Periods.
 #[tokio::test]
@@ -877,6 +2254,50 @@ async fn manifest_sgai_mode_injects_callback_eventstream() {
     );
 }
 
+/// SGAI with `DASH_SGAI_SCHEME=replace`: Events carry a ReplacePresentation
+/// pointing at the per-break ad MPD endpoint.
+#[tokio::test]
+async fn manifest_sgai_replace_scheme_injects_alternative_mpd() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/manifest.mpd"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_string(MINIMAL_MPD)
+                .insert_header("content-type", "application/dash+xml"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let addr = start_server(Config {
+        dash_sgai_scheme: DashSgaiScheme::Replace,
+        ..config_with_origin_and_mode(&mock_server, "/manifest.mpd", StitchingMode::Sgai)
+    })
+    .await;
+
+    let body = reqwest::get(format!("http://{}/stitch/alt-dash/manifest.mpd", addr))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    assert!(
+        body.contains("urn:mpeg:dash:event:alternativeMPD:replace:2025"),
+        "Expected alternative MPD EventStream, got:\n{}",
+        body
+    );
+    assert!(
+        body.contains(
+            r#"<ReplacePresentation url="http://localhost:3000/stitch/alt-dash/ad-mpd/0?dur="#
+        ),
+        "Expected ReplacePresentation element, got:\n{}",
+        body
+    );
+    assert!(!body.contains("urn:mpeg:dash:event:callback:2015"));
+}
+
 /// Origin returns a body that is not valid XML/MPD → handler returns 422.
 #[tokio::test]
 async fn manifest_invalid_mpd_body_returns_422() {
@@ -1082,6 +2503,45 @@ async fn asset_list_valid_session_returns_200_with_json() {
     );
 }
 
+// ── Ad MPD handler ──────────────────────────────────────────────────────────
+
+/// Static provider → ad MPD endpoint returns a static MPD with one ad Period.
+#[tokio::test]
+async fn ad_mpd_returns_static_mpd_with_ad_period() {
+    let app = build_router(test_config()).await;
+
+    let req = Request::builder()
+        .uri("/stitch/test-session/ad-mpd/2?dur=10")
+        .body(Body::empty())
+        .unwrap();
+
+    let resp = app.oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        resp.headers().get("content-type").unwrap(),
+        "application/dash+xml"
+    );
+
+    let body = resp.into_body().collect().await.unwrap().to_bytes();
+    let mpd = dash_mpd::parse(std::str::from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(mpd.mpdtype.as_deref(), Some("static"));
+    assert_eq!(mpd.periods.len(), 1);
+    assert_eq!(mpd.periods[0].id.as_deref(), Some("ad-2"));
+}
+
+#[tokio::test]
+async fn ad_mpd_invalid_break_id_returns_400() {
+    let app = build_router(test_config()).await;
+
+    let req = Request::builder()
+        .uri("/stitch/test-session/ad-mpd/abc?dur=10")
+        .body(Body::empty())
+        .unwrap();
+
+    let resp = app.oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
+
 // ── Playlist handler — response size limit ────────────────────────────────
 
 /// Origin advertises Content-Length above MAX_MANIFEST_SIZE → handler rejects